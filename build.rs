@@ -0,0 +1,38 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+fn main() {
+    #[cfg(feature = "flightsql")]
+    compile_flight_descriptor();
+}
+
+/// Compile `proto/Flight.proto`, and the well-known types it imports, into the encoded
+/// `FileDescriptorSet` that the FlightSQL server's gRPC reflection serves
+#[cfg(feature = "flightsql")]
+fn compile_flight_descriptor() {
+    println!("cargo:rerun-if-changed=proto/Flight.proto");
+    let descriptor_set = protox::Compiler::new(["proto"])
+        .and_then(|mut compiler| {
+            compiler.include_imports(true);
+            compiler.open_file("Flight.proto")?;
+            Ok(compiler.encode_file_descriptor_set())
+        })
+        .unwrap_or_else(|e| panic!("failed to compile proto/Flight.proto: {e}"));
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR is set"));
+    std::fs::write(out_dir.join("flight_descriptor.bin"), descriptor_set)
+        .expect("failed to write the Flight descriptor");
+}
@@ -0,0 +1,189 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cancelling statements while they are planned or their results are streamed, for example when
+//! a client gives up on a statement or it runs past its timeout
+
+use std::future::{Future, IntoFuture};
+use std::time::Duration;
+
+use datafusion::common::Result;
+use datafusion::error::DataFusionError;
+use datafusion::execution::SendableRecordBatchStream;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use futures::future::BoxFuture;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+/// Message of the error statements fail with once they are cancelled
+pub const CANCELLED_MESSAGE: &str = "Query was cancelled";
+
+/// The error statements fail with once they are cancelled
+pub fn cancelled_error() -> DataFusionError {
+    DataFusionError::Execution(CANCELLED_MESSAGE.to_string())
+}
+
+/// Cancels a statement. Clones cancel the same statement, so the handle can be kept by whatever
+/// decides to cancel it, such as a timeout or a request from the client.
+#[derive(Clone, Debug, Default)]
+pub struct QueryHandle {
+    token: CancellationToken,
+}
+
+impl QueryHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the statement. Its plan and the stream of its results are dropped the next time
+    /// they would be polled, which stops any tasks still executing it.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Completes once the statement is cancelled
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Like [`Self::cancelled`] but not borrowing the handle, for streams that outlive it
+    pub async fn cancelled_owned(self) {
+        self.token.cancelled_owned().await
+    }
+
+    /// Run `future` until the statement is cancelled, returning `None` if it was cancelled first.
+    /// The future is dropped once the statement is cancelled.
+    pub async fn until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.token.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+
+    /// Ends `stream` with [`cancelled_error`] once the statement is cancelled. The stream is
+    /// dropped at that point, which stops its execution.
+    pub fn wrap_stream(&self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        let schema = stream.schema();
+        let batches = futures::stream::unfold(Some((stream, self.clone())), |state| async move {
+            let (mut stream, handle) = state?;
+            match handle.until_cancelled(stream.next()).await {
+                None => Some((Err(cancelled_error()), None)),
+                Some(batch) => batch.map(|batch| (batch, Some((stream, handle)))),
+            }
+        });
+        Box::pin(RecordBatchStreamAdapter::new(schema, batches))
+    }
+
+    /// Cancel the statement once `timeout` elapses, unless the returned timer is dropped first
+    pub fn cancel_after(&self, timeout: Duration) -> CancelTimer {
+        let handle = self.clone();
+        CancelTimer(tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            handle.cancel();
+        }))
+    }
+}
+
+/// Cancels a statement after a timeout, started by [`QueryHandle::cancel_after`]. Dropping the
+/// timer stops it.
+#[derive(Debug)]
+pub struct CancelTimer(JoinHandle<()>);
+
+impl Drop for CancelTimer {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// A statement that is planned and executed once awaited, resolving to the stream of its results.
+/// It can be cancelled with its [`QueryHandle`] from when it's created until its results have
+/// been consumed.
+pub struct CancellableQuery {
+    handle: QueryHandle,
+    future: BoxFuture<'static, Result<SendableRecordBatchStream>>,
+}
+
+impl CancellableQuery {
+    /// Make the statement executed by `future` cancellable with `handle`
+    pub fn new(
+        handle: QueryHandle,
+        future: impl Future<Output = Result<SendableRecordBatchStream>> + Send + 'static,
+    ) -> Self {
+        let cancellable = handle.clone();
+        let future = Box::pin(async move {
+            match cancellable.until_cancelled(future).await {
+                Some(stream) => Ok(cancellable.wrap_stream(stream?)),
+                None => Err(cancelled_error()),
+            }
+        });
+        Self { handle, future }
+    }
+
+    pub fn handle(&self) -> QueryHandle {
+        self.handle.clone()
+    }
+}
+
+impl IntoFuture for CancellableQuery {
+    type Output = Result<SendableRecordBatchStream>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.future
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_stops_stream() {
+        let ctx = SessionContext::new();
+        let df = ctx
+            .sql("SELECT * FROM generate_series(1, 100000000)")
+            .await
+            .unwrap();
+        let handle = QueryHandle::new();
+        let mut stream = handle.wrap_stream(df.execute_stream().await.unwrap());
+        assert!(stream.next().await.unwrap().is_ok());
+        handle.cancel();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains(CANCELLED_MESSAGE));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn cancel_before_execution() {
+        let handle = QueryHandle::new();
+        handle.cancel();
+        let query = CancellableQuery::new(handle, async {
+            let ctx = SessionContext::new();
+            ctx.sql("SELECT 1").await?.execute_stream().await
+        });
+        let err = query.await.unwrap_err();
+        assert!(err.to_string().contains(CANCELLED_MESSAGE));
+    }
+}
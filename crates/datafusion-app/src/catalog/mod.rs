@@ -19,6 +19,8 @@
 pub mod clickhouse;
 #[cfg(feature = "mongodb")]
 pub mod mongodb;
+pub mod refresh;
+pub mod session;
 
 use std::sync::Arc;
 
@@ -0,0 +1,122 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Refreshing the files of listing tables, such as those created with `CREATE EXTERNAL TABLE`,
+//! so files and partitions added to their locations show up in long running sessions
+
+use std::{sync::Arc, time::Duration};
+
+use datafusion::execution::{cache::CacheAccessor, runtime_env::RuntimeEnv};
+use log::{debug, info};
+use tokio::task::JoinHandle;
+
+/// Drop the files and statistics cached for listing tables, so every listing table lists its
+/// locations again the next time it's scanned. Sessions forked from the same context share the
+/// cache, so they're all refreshed.
+pub fn refresh_listing_tables(runtime_env: &RuntimeEnv) {
+    let cache_manager = &runtime_env.cache_manager;
+    if let Some(cache) = cache_manager.get_list_files_cache() {
+        cache.clear();
+    }
+    if let Some(cache) = cache_manager.get_file_statistic_cache() {
+        cache.clear();
+    }
+}
+
+/// Refreshes listing tables on an interval, see [`refresh_listing_tables`]. Dropping the
+/// refresher stops it.
+#[derive(Debug)]
+pub struct CatalogRefresher(JoinHandle<()>);
+
+impl CatalogRefresher {
+    pub fn start(runtime_env: Arc<RuntimeEnv>, interval: Duration) -> Self {
+        info!("Refreshing listing tables every {interval:?}");
+        CatalogRefresher(tokio::spawn(async move {
+            let mut timer = tokio::time::interval(interval);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            // The first tick completes immediately, when nothing can be stale yet
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                debug!("Refreshing listing tables");
+                refresh_listing_tables(&runtime_env);
+            }
+        }))
+    }
+}
+
+impl Drop for CatalogRefresher {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{arrow::array::Int64Array, prelude::SessionContext};
+
+    use super::*;
+
+    async fn count(ctx: &SessionContext) -> i64 {
+        let batches = ctx
+            .sql("SELECT count(*) FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap()
+            .value(0)
+    }
+
+    #[tokio::test]
+    async fn test_new_files_show_up_after_refresh() {
+        let dir = std::env::temp_dir().join(format!("dft-catalog-refresh-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("1.csv"), "a\n1\n2\n").unwrap();
+
+        let ctx = SessionContext::new();
+        // Without a cache of the listed files new files show up without a refresh
+        assert!(ctx
+            .runtime_env()
+            .cache_manager
+            .get_list_files_cache()
+            .is_some());
+        ctx.sql(&format!(
+            "CREATE EXTERNAL TABLE t STORED AS CSV LOCATION '{}/' OPTIONS ('format.has_header' 'true')",
+            dir.display()
+        ))
+        .await
+        .unwrap();
+        let before = count(&ctx).await;
+
+        std::fs::write(dir.join("2.csv"), "a\n3\n").unwrap();
+        let cached = count(&ctx).await;
+        refresh_listing_tables(&ctx.runtime_env());
+        let after = count(&ctx).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before, 2);
+        // The new file isn't listed until the tables are refreshed
+        assert_eq!(cached, 2);
+        assert_eq!(after, 3);
+    }
+}
@@ -0,0 +1,203 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Catalogs of a forked session that keep the views it creates to itself, while tables are
+//! still registered in, and read from, the catalogs it was forked from
+
+use std::{
+    any::Any,
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use datafusion::{
+    catalog::{
+        CatalogProvider, CatalogProviderList, MemorySchemaProvider, SchemaProvider, TableProvider,
+    },
+    common::Result,
+    logical_expr::TableType,
+};
+
+/// Views created in the session, by their catalog and schema
+type SessionViews = Arc<Mutex<HashMap<(String, String), Arc<MemorySchemaProvider>>>>;
+
+/// Wraps the catalogs of the session that was forked, see the module docs
+#[derive(Debug)]
+pub struct SessionCatalogList {
+    shared: Arc<dyn CatalogProviderList>,
+    views: SessionViews,
+}
+
+impl SessionCatalogList {
+    pub fn new(shared: Arc<dyn CatalogProviderList>) -> Self {
+        Self {
+            shared,
+            views: SessionViews::default(),
+        }
+    }
+}
+
+impl CatalogProviderList for SessionCatalogList {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn register_catalog(
+        &self,
+        name: String,
+        catalog: Arc<dyn CatalogProvider>,
+    ) -> Option<Arc<dyn CatalogProvider>> {
+        self.shared.register_catalog(name, catalog)
+    }
+
+    fn catalog_names(&self) -> Vec<String> {
+        self.shared.catalog_names()
+    }
+
+    fn catalog(&self, name: &str) -> Option<Arc<dyn CatalogProvider>> {
+        let shared = self.shared.catalog(name)?;
+        Some(Arc::new(SessionCatalog {
+            name: name.to_string(),
+            shared,
+            views: Arc::clone(&self.views),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct SessionCatalog {
+    name: String,
+    shared: Arc<dyn CatalogProvider>,
+    views: SessionViews,
+}
+
+impl CatalogProvider for SessionCatalog {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema_names(&self) -> Vec<String> {
+        self.shared.schema_names()
+    }
+
+    fn schema(&self, name: &str) -> Option<Arc<dyn SchemaProvider>> {
+        let shared = self.shared.schema(name)?;
+        let mut views = self.views.lock().unwrap_or_else(PoisonError::into_inner);
+        let views = Arc::clone(
+            views
+                .entry((self.name.clone(), name.to_string()))
+                .or_default(),
+        );
+        Some(Arc::new(SessionSchema { shared, views }))
+    }
+
+    fn register_schema(
+        &self,
+        name: &str,
+        schema: Arc<dyn SchemaProvider>,
+    ) -> Result<Option<Arc<dyn SchemaProvider>>> {
+        self.shared.register_schema(name, schema)
+    }
+
+    fn deregister_schema(
+        &self,
+        name: &str,
+        cascade: bool,
+    ) -> Result<Option<Arc<dyn SchemaProvider>>> {
+        self.shared.deregister_schema(name, cascade)
+    }
+}
+
+/// A schema whose views are looked up in the session before the shared schema
+#[derive(Debug)]
+struct SessionSchema {
+    shared: Arc<dyn SchemaProvider>,
+    views: Arc<MemorySchemaProvider>,
+}
+
+#[async_trait::async_trait]
+impl SchemaProvider for SessionSchema {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        let mut names: BTreeSet<String> = self.shared.table_names().into_iter().collect();
+        names.extend(self.views.table_names());
+        names.into_iter().collect()
+    }
+
+    async fn table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>> {
+        match self.views.table(name).await? {
+            Some(view) => Ok(Some(view)),
+            None => self.shared.table(name).await,
+        }
+    }
+
+    fn register_table(
+        &self,
+        name: String,
+        table: Arc<dyn TableProvider>,
+    ) -> Result<Option<Arc<dyn TableProvider>>> {
+        if table.table_type() == TableType::View {
+            self.views.register_table(name, table)
+        } else {
+            self.shared.register_table(name, table)
+        }
+    }
+
+    fn deregister_table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>> {
+        if self.views.table_exist(name) {
+            self.views.deregister_table(name)
+        } else {
+            self.shared.deregister_table(name)
+        }
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        self.views.table_exist(name) || self.shared.table_exist(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{execution::SessionStateBuilder, prelude::SessionContext};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_views_are_kept_to_the_session() {
+        let ctx = SessionContext::new();
+        let state = ctx.state();
+        let catalog_list = Arc::new(SessionCatalogList::new(Arc::clone(state.catalog_list())));
+        let session = SessionContext::new_with_state(
+            SessionStateBuilder::new_from_existing(state)
+                .with_catalog_list(catalog_list)
+                .build(),
+        );
+
+        session.sql("CREATE VIEW v AS VALUES (1)").await.unwrap();
+        session.sql("CREATE TABLE t AS VALUES (1)").await.unwrap();
+        assert!(session.table_exist("v").unwrap());
+        assert!(!ctx.table_exist("v").unwrap());
+        // Tables are still shared
+        assert!(ctx.table_exist("t").unwrap());
+
+        session.sql("DROP VIEW v").await.unwrap();
+        assert!(!session.table_exist("v").unwrap());
+    }
+}
@@ -17,18 +17,30 @@
 
 //! Configuration management handling
 
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use datafusion::execution::memory_pool::{
+    FairSpillPool, GreedyMemoryPool, MemoryPool, TrackConsumersPool, UnboundedMemoryPool,
+};
 #[cfg(feature = "udfs-wasm")]
 use datafusion_udfs_wasm::WasmInputDataType;
 use serde::Deserialize;
 use std::collections::HashMap;
+use sysinfo::System;
+
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+use color_eyre::Result;
 
+#[cfg(any(feature = "s3", feature = "azure"))]
+use crate::secrets::Secrets;
 #[cfg(feature = "s3")]
-use {
-    color_eyre::Result,
-    object_store::aws::{AmazonS3, AmazonS3Builder},
-};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+#[cfg(feature = "azure")]
+use object_store::azure::{MicrosoftAzure, MicrosoftAzureBuilder};
+#[cfg(feature = "gcs")]
+use object_store::gcp::{GoogleCloudStorage, GoogleCloudStorageBuilder};
 
 // Merges a shared config with a priority config. If a field is present in the priority config that
 // it replaces the entire field from the shared config.
@@ -82,9 +94,317 @@ pub fn merge_configs(shared: ExecutionConfig, priority: ExecutionConfig) -> Exec
         merged.net.geoip_db_path = Some(geoip_db_path)
     }
 
+    if priority.memory != MemoryConfig::default() {
+        merged.memory = priority.memory
+    }
+    if priority.spill != SpillConfig::default() {
+        merged.spill = priority.spill
+    }
+    if priority.result_cache != ResultCacheConfig::default() {
+        merged.result_cache = priority.result_cache
+    }
+    if priority.system_tables != SystemTablesConfig::default() {
+        merged.system_tables = priority.system_tables
+    }
+    if priority.dialect != SqlDialect::default() {
+        merged.dialect = priority.dialect
+    }
+    if let Some(refresh_interval_secs) = priority.catalog.refresh_interval_secs {
+        merged.catalog.refresh_interval_secs = Some(refresh_interval_secs)
+    }
+    if priority.guardrails != GuardrailsConfig::default() {
+        merged.guardrails = priority.guardrails
+    }
+    // Secrets are looked up by name so the app's secrets are added to the shared ones
+    merged.secrets.extend(priority.secrets);
+    if merged.query_secrets != priority.query_secrets {
+        merged.query_secrets = priority.query_secrets
+    }
+    if let Some(object_store_ddl) = priority.object_store_ddl {
+        merged.object_store_ddl = Some(object_store_ddl)
+    }
+    if !priority.udf.is_empty() {
+        merged.udf = priority.udf
+    }
+
     merged
 }
 
+/// SQL dialect that queries are parsed with, so that SQL written for other engines can be run
+/// without edits
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SqlDialect {
+    #[default]
+    Generic,
+    Postgres,
+    MySql,
+    Ansi,
+    DuckDb,
+}
+
+impl SqlDialect {
+    /// Name of the dialect for the `datafusion.sql_parser.dialect` setting
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Generic => "generic",
+            Self::Postgres => "postgresql",
+            Self::MySql => "mysql",
+            Self::Ansi => "ansi",
+            Self::DuckDb => "duckdb",
+        }
+    }
+}
+
+/// A scalar function, declared with `[[shared.udf]]`, that is registered with the session at
+/// startup
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct UdfConfig {
+    /// Name the function is called with in SQL
+    pub name: String,
+    pub kind: UdfKind,
+    /// Path of the WASM module or shared library that implements the function
+    pub path: PathBuf,
+    pub signature: UdfSignature,
+    /// Name the function is exported with, if it's different from `name`
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// How the arguments of WASM functions are passed, `Row` (the default) or `ArrowIpc`
+    #[cfg(feature = "udfs-wasm")]
+    #[serde(default)]
+    pub input_data_type: Option<WasmInputDataType>,
+}
+
+impl UdfConfig {
+    /// Name of the function in the module or library that implements it
+    pub fn symbol(&self) -> &str {
+        self.symbol.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// What implements a [`UdfConfig`] function
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UdfKind {
+    /// A function exported by a WASM module, requires the `udfs-wasm` feature
+    Wasm,
+    /// A function exported by a shared library, requires the `udfs-native` feature
+    Native,
+}
+
+/// Argument and return types of a [`UdfConfig`] function, written as Arrow data types such as
+/// `Int64` or `Utf8`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct UdfSignature {
+    #[serde(default)]
+    pub input_types: Vec<String>,
+    pub return_type: String,
+}
+
+/// Which DataFusion [`MemoryPool`] the queries of an app share
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryPoolType {
+    /// [`FairSpillPool`], which splits the memory evenly between the operators that can spill so
+    /// that one large sort can't starve the others
+    #[default]
+    Fair,
+    /// [`GreedyMemoryPool`], which gives memory to whichever operator asks first
+    Greedy,
+    /// [`UnboundedMemoryPool`], which doesn't limit the memory queries use
+    Unbounded,
+}
+
+/// Limits on the memory used by the queries of an app
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct MemoryConfig {
+    #[serde(default)]
+    pub pool: MemoryPoolType,
+    /// Bytes of memory queries can use, across all of them
+    #[serde(default)]
+    pub limit_bytes: Option<usize>,
+    /// Fraction of the system's memory, or of the cgroup's memory limit when the app runs in a
+    /// container that sets a lower one, queries can use when `limit_bytes` is unset. Must be
+    /// greater than 0 and at most 1.
+    #[serde(default = "default_memory_limit_fraction")]
+    pub limit_fraction: f64,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            pool: MemoryPoolType::default(),
+            limit_bytes: None,
+            limit_fraction: default_memory_limit_fraction(),
+        }
+    }
+}
+
+impl MemoryConfig {
+    /// Check that `limit_fraction` is a fraction, so the pool can't be empty or larger than the
+    /// memory there is
+    pub fn validate(&self) -> Result<(), String> {
+        if self.limit_fraction > 0.0 && self.limit_fraction <= 1.0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "memory.limit_fraction must be greater than 0 and at most 1, got {}",
+                self.limit_fraction
+            ))
+        }
+    }
+
+    /// The limit in bytes, from `limit_bytes` or the memory available to the app
+    pub fn limit(&self) -> usize {
+        self.limit_bytes.unwrap_or_else(|| {
+            let mut system = System::new();
+            system.refresh_memory();
+            // Containers are killed once they exceed their cgroup's limit, however much memory
+            // the host has
+            let total = match system.cgroup_limits() {
+                Some(cgroup) => cgroup.total_memory.min(system.total_memory()),
+                None => system.total_memory(),
+            };
+            (total as f64 * self.limit_fraction) as usize
+        })
+    }
+
+    /// Create the pool queries allocate memory from. Queries that need more memory than the pool
+    /// has left, and can't spill, fail with a resources exhausted error naming the largest
+    /// consumers.
+    pub fn memory_pool(&self) -> Arc<dyn MemoryPool> {
+        let top_consumers = NonZeroUsize::new(5).expect("non-zero");
+        match self.pool {
+            MemoryPoolType::Fair => Arc::new(TrackConsumersPool::new(
+                FairSpillPool::new(self.limit()),
+                top_consumers,
+            )),
+            MemoryPoolType::Greedy => Arc::new(TrackConsumersPool::new(
+                GreedyMemoryPool::new(self.limit()),
+                top_consumers,
+            )),
+            MemoryPoolType::Unbounded => Arc::new(UnboundedMemoryPool::default()),
+        }
+    }
+}
+
+fn default_memory_limit_fraction() -> f64 {
+    0.8
+}
+
+/// Where operators spill to disk once the memory pool is exhausted
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct SpillConfig {
+    /// Whether operators can spill. Queries that need more memory than the pool has left fail
+    /// when they can't.
+    #[serde(default = "default_spill_enabled")]
+    pub enabled: bool,
+    /// Directories spill files are written to, which are created if they don't exist. The OS temp
+    /// directory is used when empty.
+    #[serde(default)]
+    pub directories: Vec<PathBuf>,
+    /// Bytes of spill files that can be on disk at once, across all queries. DataFusion's default
+    /// is used when unset.
+    #[serde(default)]
+    pub max_disk_bytes: Option<u64>,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_spill_enabled(),
+            directories: Vec::new(),
+            max_disk_bytes: None,
+        }
+    }
+}
+
+fn default_spill_enabled() -> bool {
+    true
+}
+
+/// Caching of query results so that repeated queries, such as dashboards refreshed on an
+/// interval, aren't executed again while their results are fresh
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ResultCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seconds a cached result is returned for after the query was executed
+    #[serde(default = "default_result_cache_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Bytes of record batches that can be cached at once. Results larger than this are never
+    /// cached and the oldest results are evicted to make room for new ones.
+    #[serde(default = "default_result_cache_max_bytes")]
+    pub max_bytes: usize,
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_result_cache_ttl_secs(),
+            max_bytes: default_result_cache_max_bytes(),
+        }
+    }
+}
+
+fn default_result_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_result_cache_max_bytes() -> usize {
+    256 * 1024 * 1024
+}
+
+/// Limits on the results of each query, checked as they're consumed so a query fails instead of
+/// returning more than the app can hold
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct GuardrailsConfig {
+    /// Rows a query can return
+    #[serde(default)]
+    pub max_rows: Option<u64>,
+    /// Bytes of record batches a query can return
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// Seconds a query can run for once it has been planned, including the time its results
+    /// spend waiting to be read
+    #[serde(default)]
+    pub max_duration_secs: Option<u64>,
+}
+
+/// Where the value of a secret is read from, each time it's used
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretSource {
+    /// The environment variable with this name
+    Env(String),
+    /// The contents of this file without trailing whitespace, such as a secret mounted by Docker
+    /// or Kubernetes. It's read as plaintext, so it's only as protected as its permissions.
+    File(PathBuf),
+    /// The password of an entry in the OS keyring, requires the `secrets-keyring` feature
+    Keyring { service: String, user: String },
+}
+
+/// Settings of the tables in the `system` schema
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct SystemTablesConfig {
+    /// Number of finished queries kept in `system.queries`, the oldest are dropped first
+    #[serde(default = "default_query_history_size")]
+    pub query_history_size: usize,
+}
+
+impl Default for SystemTablesConfig {
+    fn default() -> Self {
+        Self {
+            query_history_size: default_query_history_size(),
+        }
+    }
+}
+
+fn default_query_history_size() -> usize {
+    1000
+}
+
 /// Configuration for the `net` feature
 #[cfg(feature = "net")]
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -129,6 +449,34 @@ pub struct ExecutionConfig {
     #[cfg(feature = "observability")]
     #[serde(default)]
     pub observability: ObservabilityConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    #[serde(default)]
+    pub spill: SpillConfig,
+    #[serde(default)]
+    pub result_cache: ResultCacheConfig,
+    #[serde(default)]
+    pub system_tables: SystemTablesConfig,
+    #[serde(default)]
+    pub dialect: SqlDialect,
+    /// Functions registered with the session at startup
+    #[serde(default)]
+    pub udf: Vec<UdfConfig>,
+    #[serde(default)]
+    pub guardrails: GuardrailsConfig,
+    /// Secrets that credentials in the config and DDL refer to as `secret://<name>`
+    #[serde(default)]
+    pub secrets: HashMap<String, SecretSource>,
+    /// Resolve the secrets referred to by statements run as queries, not only by the DDL file and
+    /// config. Anyone who can run a query can then use the secrets, so it shouldn't be set for
+    /// servers whose clients aren't trusted with them.
+    #[serde(default)]
+    pub query_secrets: bool,
+    /// Allow `CREATE EXTERNAL OBJECT STORE` statements to be run as queries, not only from the
+    /// DDL file. Unset, it's allowed by the CLI and TUI but not by the servers, whose clients
+    /// could otherwise register stores that are used with the server's credentials.
+    #[serde(default)]
+    pub object_store_ddl: Option<bool>,
 }
 
 impl Default for ExecutionConfig {
@@ -152,6 +500,16 @@ impl Default for ExecutionConfig {
             catalog: default_catalog(),
             #[cfg(feature = "observability")]
             observability: default_observability(),
+            memory: MemoryConfig::default(),
+            spill: SpillConfig::default(),
+            result_cache: ResultCacheConfig::default(),
+            system_tables: SystemTablesConfig::default(),
+            dialect: SqlDialect::default(),
+            udf: Vec::new(),
+            guardrails: GuardrailsConfig::default(),
+            secrets: HashMap::new(),
+            query_secrets: false,
+            object_store_ddl: None,
         }
     }
 }
@@ -214,10 +572,34 @@ pub struct S3Config {
     aws_endpoint: Option<String>,
     aws_session_token: Option<String>,
     aws_allow_http: Option<bool>,
+    /// Named profile from the shared AWS config files (`~/.aws/credentials` and
+    /// `~/.aws/config`) to read credentials and the region from
+    aws_profile: Option<String>,
 }
 
 #[cfg(feature = "s3")]
 impl S3Config {
+    /// Config for the bucket at `url` (for example `s3://my_bucket`) that resolves credentials
+    /// with the AWS credential chain, optionally reading them from the named `aws_profile`
+    pub fn from_url(url: &str, aws_profile: Option<String>) -> Result<Self> {
+        let parsed = url::Url::parse(url)?;
+        let bucket_name = parsed
+            .host_str()
+            .ok_or_else(|| color_eyre::eyre::eyre!("No bucket name in S3 url: {url}"))?;
+        Ok(Self {
+            bucket_name: bucket_name.to_string(),
+            object_store_url: Some(url.to_string()),
+            use_credential_chain: true,
+            aws_access_key_id: None,
+            aws_secret_access_key: None,
+            _aws_default_region: None,
+            aws_endpoint: None,
+            aws_session_token: None,
+            aws_allow_http: None,
+            aws_profile,
+        })
+    }
+
     pub fn object_store_url(&self) -> &Option<String> {
         &self.object_store_url
     }
@@ -225,6 +607,17 @@ impl S3Config {
 
 #[cfg(feature = "s3")]
 impl S3Config {
+    /// This config with the credentials that refer to secrets replaced by the secrets
+    pub fn with_secrets(&self, secrets: &Secrets) -> Result<Self> {
+        Ok(Self {
+            aws_access_key_id: secrets.resolve_option(self.aws_access_key_id.as_ref())?,
+            aws_secret_access_key: secrets.resolve_option(self.aws_secret_access_key.as_ref())?,
+            aws_endpoint: secrets.resolve_option(self.aws_endpoint.as_ref())?,
+            aws_session_token: secrets.resolve_option(self.aws_session_token.as_ref())?,
+            ..self.clone()
+        })
+    }
+
     pub fn to_object_store(&self) -> Result<AmazonS3> {
         // Choose builder based on credential chain preference
         let mut builder = if self.use_credential_chain {
@@ -243,6 +636,23 @@ impl S3Config {
         // Always set bucket name (required)
         builder = builder.with_bucket_name(&self.bucket_name);
 
+        // Profile credentials take precedence over the environment but not the TOML
+        if let Some(profile) = &self.aws_profile {
+            let profile = AwsProfile::load(profile)?;
+            if let Some(access_key) = profile.access_key_id {
+                builder = builder.with_access_key_id(access_key)
+            }
+            if let Some(secret) = profile.secret_access_key {
+                builder = builder.with_secret_access_key(secret)
+            }
+            if let Some(token) = profile.session_token {
+                builder = builder.with_token(token)
+            }
+            if let Some(region) = profile.region {
+                builder = builder.with_region(region)
+            }
+        }
+
         // Apply TOML-specified credentials if provided
         // These will override environment-based credentials due to precedence
         if let Some(access_key) = &self.aws_access_key_id {
@@ -265,6 +675,168 @@ impl S3Config {
     }
 }
 
+/// Credentials and region of a named profile in the shared AWS config files
+#[cfg(feature = "s3")]
+#[derive(Debug, Default, PartialEq)]
+struct AwsProfile {
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+    session_token: Option<String>,
+    region: Option<String>,
+}
+
+#[cfg(feature = "s3")]
+impl AwsProfile {
+    /// Load the profile from the files at `AWS_SHARED_CREDENTIALS_FILE` and `AWS_CONFIG_FILE`,
+    /// defaulting to `~/.aws/credentials` and `~/.aws/config`
+    fn load(name: &str) -> Result<Self> {
+        let aws_dir = directories::BaseDirs::new().map(|dirs| dirs.home_dir().join(".aws"));
+        let read = |env_var: &str, default: &str| {
+            std::env::var_os(env_var)
+                .map(PathBuf::from)
+                .or_else(|| aws_dir.as_ref().map(|dir| dir.join(default)))
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .unwrap_or_default()
+        };
+        let credentials = read("AWS_SHARED_CREDENTIALS_FILE", "credentials");
+        let config = read("AWS_CONFIG_FILE", "config");
+        Self::from_files(name, &credentials, &config)
+            .ok_or_else(|| color_eyre::eyre::eyre!("AWS profile '{name}' not found"))
+    }
+
+    /// Parse the profile from the contents of the credentials and config files. Profiles in the
+    /// config file are named `[profile <name>]`, except for the default profile.
+    fn from_files(name: &str, credentials: &str, config: &str) -> Option<Self> {
+        let credentials = ini_section(credentials, name);
+        let config_section = if name == "default" {
+            name.to_string()
+        } else {
+            format!("profile {name}")
+        };
+        let config = ini_section(config, &config_section);
+        if credentials.is_none() && config.is_none() {
+            return None;
+        }
+        let credentials = credentials.unwrap_or_default();
+        let config = config.unwrap_or_default();
+        let get = |key: &str| {
+            credentials
+                .get(key)
+                .or_else(|| config.get(key))
+                .map(|v| v.to_string())
+        };
+        Some(Self {
+            access_key_id: get("aws_access_key_id"),
+            secret_access_key: get("aws_secret_access_key"),
+            session_token: get("aws_session_token"),
+            region: get("region"),
+        })
+    }
+}
+
+/// Key value pairs of a `[section]` in an INI file, if the section exists
+#[cfg(feature = "s3")]
+fn ini_section(contents: &str, section: &str) -> Option<HashMap<String, String>> {
+    let mut values = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if values.is_some() {
+                break;
+            }
+            if header.trim() == section {
+                values = Some(HashMap::new());
+            }
+        } else if let (Some(values), Some((key, value))) = (values.as_mut(), line.split_once('=')) {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    values
+}
+
+/// A Google Cloud Storage bucket, with credentials read from the `GOOGLE_*` environment
+/// variables unless a service account is provided
+#[cfg(feature = "gcs")]
+#[derive(Clone, Debug, Deserialize)]
+pub struct GcsConfig {
+    /// Url of the bucket, for example `gs://my_bucket`
+    object_store_url: String,
+    /// Path to a service account JSON key file
+    service_account_path: Option<String>,
+}
+
+#[cfg(feature = "gcs")]
+impl GcsConfig {
+    pub fn new(object_store_url: String, service_account_path: Option<String>) -> Self {
+        Self {
+            object_store_url,
+            service_account_path,
+        }
+    }
+
+    pub fn object_store_url(&self) -> &str {
+        &self.object_store_url
+    }
+
+    pub fn to_object_store(&self) -> Result<GoogleCloudStorage> {
+        let mut builder = GoogleCloudStorageBuilder::from_env().with_url(&self.object_store_url);
+        if let Some(path) = &self.service_account_path {
+            builder = builder.with_service_account_path(path);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// An Azure Blob Storage container, with credentials read from the `AZURE_*` environment
+/// variables unless an account is provided
+#[cfg(feature = "azure")]
+#[derive(Clone, Debug, Deserialize)]
+pub struct AzureConfig {
+    /// Url of the container, for example `az://my_container` or
+    /// `abfss://my_container@my_account.dfs.core.windows.net`
+    object_store_url: String,
+    /// Storage account name, required if it is not part of the url or `AZURE_STORAGE_ACCOUNT_NAME`
+    account: Option<String>,
+    access_key: Option<String>,
+}
+
+#[cfg(feature = "azure")]
+impl AzureConfig {
+    pub fn new(object_store_url: String, account: Option<String>) -> Self {
+        Self {
+            object_store_url,
+            account,
+            access_key: None,
+        }
+    }
+
+    pub fn object_store_url(&self) -> &str {
+        &self.object_store_url
+    }
+
+    /// This config with the credentials that refer to secrets replaced by the secrets
+    pub fn with_secrets(&self, secrets: &Secrets) -> Result<Self> {
+        Ok(Self {
+            object_store_url: self.object_store_url.clone(),
+            account: secrets.resolve_option(self.account.as_ref())?,
+            access_key: secrets.resolve_option(self.access_key.as_ref())?,
+        })
+    }
+
+    pub fn to_object_store(&self) -> Result<MicrosoftAzure> {
+        let mut builder = MicrosoftAzureBuilder::from_env().with_url(&self.object_store_url);
+        if let Some(account) = &self.account {
+            builder = builder.with_account(account);
+        }
+        if let Some(access_key) = &self.access_key {
+            builder = builder.with_access_key(access_key);
+        }
+        Ok(builder.build()?)
+    }
+}
+
 #[cfg(feature = "clickhouse")]
 fn default_clickhouse_catalog_name() -> String {
     "clickhouse".to_string()
@@ -393,14 +965,51 @@ pub struct HuggingFaceConfig {
     pub token: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct ObjectStoreConfig {
     #[cfg(feature = "s3")]
     pub s3: Option<Vec<S3Config>>,
+    #[cfg(feature = "gcs")]
+    pub gcs: Option<Vec<GcsConfig>>,
+    #[cfg(feature = "azure")]
+    pub azure: Option<Vec<AzureConfig>>,
     #[cfg(feature = "huggingface")]
     pub huggingface: Option<Vec<HuggingFaceConfig>>,
 }
 
+impl ObjectStoreConfig {
+    /// Add the object stores from `other` to the stores of this config
+    #[cfg_attr(
+        not(any(
+            feature = "s3",
+            feature = "gcs",
+            feature = "azure",
+            feature = "huggingface"
+        )),
+        allow(unused_variables)
+    )]
+    pub fn extend(&mut self, other: ObjectStoreConfig) {
+        #[cfg(feature = "s3")]
+        if let Some(s3) = other.s3 {
+            self.s3.get_or_insert_with(Vec::new).extend(s3);
+        }
+        #[cfg(feature = "gcs")]
+        if let Some(gcs) = other.gcs {
+            self.gcs.get_or_insert_with(Vec::new).extend(gcs);
+        }
+        #[cfg(feature = "azure")]
+        if let Some(azure) = other.azure {
+            self.azure.get_or_insert_with(Vec::new).extend(azure);
+        }
+        #[cfg(feature = "huggingface")]
+        if let Some(huggingface) = other.huggingface {
+            self.huggingface
+                .get_or_insert_with(Vec::new)
+                .extend(huggingface);
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct RestCatalogConfig {
     pub name: String,
@@ -438,6 +1047,8 @@ pub struct FlightSQLConfig {
     pub max_decoding_message_size: Option<usize>,
     /// Maximum size (in bytes) of an encoded gRPC message. `None` uses tonic's default (4MB).
     pub max_encoding_message_size: Option<usize>,
+    /// TLS settings used when the connection URL is `https://`
+    pub tls: FlightSQLClientTlsConfig,
 }
 
 #[cfg(feature = "flightsql")]
@@ -450,6 +1061,7 @@ impl Default for FlightSQLConfig {
             headers: HashMap::new(),
             max_decoding_message_size: None,
             max_encoding_message_size: None,
+            tls: FlightSQLClientTlsConfig::default(),
         }
     }
 }
@@ -463,6 +1075,7 @@ impl FlightSQLConfig {
         headers: HashMap<String, String>,
         max_decoding_message_size: Option<usize>,
         max_encoding_message_size: Option<usize>,
+        tls: FlightSQLClientTlsConfig,
     ) -> Self {
         Self {
             connection_url,
@@ -471,14 +1084,36 @@ impl FlightSQLConfig {
             headers,
             max_decoding_message_size,
             max_encoding_message_size,
+            tls,
         }
     }
 }
 
+/// TLS settings of the FlightSQL client
+#[cfg(feature = "flightsql")]
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FlightSQLClientTlsConfig {
+    /// PEM bundle of the CAs trusted to sign the server certificate. The system's trusted roots
+    /// are used when unset.
+    #[serde(default)]
+    pub ca_cert_path: Option<PathBuf>,
+    /// Name the server certificate is verified against, instead of the host of the connection URL
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Accept any server certificate. Only meant for testing against servers with self-signed
+    /// certificates.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct AuthConfig {
     pub basic_auth: Option<BasicAuth>,
     pub bearer_token: Option<String>,
+    /// Exchange the basic auth credentials for a session token with the FlightSQL `Handshake`
+    /// RPC when connecting, and send the token instead of the credentials on later requests
+    #[serde(default)]
+    pub handshake: bool,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -491,12 +1126,17 @@ pub struct BasicAuth {
 pub struct CatalogConfig {
     #[serde(default = "default_catalog_name")]
     pub name: String,
+    /// Seconds between refreshes of the files of listing tables in the TUI and servers, so files
+    /// added to their locations show up without running their DDL again. Disabled when unset.
+    #[serde(default)]
+    pub refresh_interval_secs: Option<u64>,
 }
 
 impl Default for CatalogConfig {
     fn default() -> Self {
         Self {
             name: default_catalog_name(),
+            refresh_interval_secs: None,
         }
     }
 }
@@ -550,3 +1190,48 @@ fn default_tokio_metrics_enabled() -> bool {
 fn default_tokio_metrics_interval_secs() -> u64 {
     10
 }
+
+#[cfg(all(test, feature = "s3"))]
+mod tests {
+    use super::AwsProfile;
+
+    const CREDENTIALS: &str = r#"
+[default]
+aws_access_key_id = DEFAULT_KEY
+aws_secret_access_key = DEFAULT_SECRET
+
+[dev]
+aws_access_key_id = DEV_KEY
+aws_secret_access_key = DEV_SECRET
+aws_session_token = DEV_TOKEN
+"#;
+
+    const CONFIG: &str = r#"
+[default]
+region = us-east-1
+
+# Named profiles are prefixed with `profile` in the config file
+[profile dev]
+region = eu-west-1
+"#;
+
+    #[test]
+    fn test_aws_profile_from_files() {
+        let profile = AwsProfile::from_files("dev", CREDENTIALS, CONFIG).unwrap();
+        assert_eq!(
+            profile,
+            AwsProfile {
+                access_key_id: Some("DEV_KEY".to_string()),
+                secret_access_key: Some("DEV_SECRET".to_string()),
+                session_token: Some("DEV_TOKEN".to_string()),
+                region: Some("eu-west-1".to_string()),
+            }
+        );
+
+        let profile = AwsProfile::from_files("default", CREDENTIALS, CONFIG).unwrap();
+        assert_eq!(profile.access_key_id, Some("DEFAULT_KEY".to_string()));
+        assert_eq!(profile.region, Some("us-east-1".to_string()));
+
+        assert!(AwsProfile::from_files("missing", CREDENTIALS, CONFIG).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Hooks notified of the DDL statements a session executed, for example to persist the tables
+//! and views they create
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::LogicalPlan;
+use log::error;
+
+/// Notified of the DDL statements executed by a session once they succeeded. Registered with
+/// [`crate::extensions::DftSessionStateBuilder::add_ddl_listener`].
+#[async_trait::async_trait]
+pub trait DdlListener: Debug + Send + Sync {
+    /// Handle `plan`, a [`LogicalPlan::Ddl`] executed by `state`. Errors are logged rather than
+    /// failing the statement, which has already been executed.
+    async fn on_ddl(&self, state: &SessionState, plan: &LogicalPlan) -> color_eyre::Result<()>;
+}
+
+/// The DDL listeners of a session, kept as an extension of its config
+#[derive(Debug, Default)]
+pub struct DdlListeners(pub Vec<Arc<dyn DdlListener>>);
+
+/// Notify the listeners registered with `state`, if any, that `plan` was executed
+pub async fn notify_ddl_listeners(state: &SessionState, plan: &LogicalPlan) {
+    if !matches!(plan, LogicalPlan::Ddl(_)) {
+        return;
+    }
+    let Some(listeners) = state.config().get_extension::<DdlListeners>() else {
+        return;
+    };
+    for listener in &listeners.0 {
+        if let Err(e) = listener.on_ddl(state, plan).await {
+            error!("Error handling DDL statement with {listener:?}: {e}");
+        }
+    }
+}
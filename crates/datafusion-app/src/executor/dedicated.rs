@@ -21,11 +21,15 @@ use std::{
     time::Duration,
 };
 
+use datafusion::{
+    error::DataFusionError, execution::SendableRecordBatchStream,
+    physical_plan::stream::RecordBatchStreamAdapter,
+};
 use futures::{
     future::{BoxFuture, Shared},
-    Future, FutureExt, TryFutureExt,
+    Future, FutureExt, StreamExt, TryFutureExt,
 };
-use log::{info, warn};
+use log::{debug, info, warn};
 use parking_lot::RwLock;
 use tokio::{
     runtime::Handle,
@@ -58,6 +62,8 @@ impl Display for JobError {
     }
 }
 
+impl std::error::Error for JobError {}
+
 /// Manages a separate tokio runtime (thread pool) for executing tasks.
 ///
 /// A `DedicatedExecutor` runs futures (and any `tasks` that are
@@ -156,6 +162,7 @@ impl DedicatedExecutor {
         let (tx_handle, rx_handle) = std::sync::mpsc::channel();
 
         let io_handle = tokio::runtime::Handle::try_current().ok();
+        let worker_name = name.clone();
         let thread = std::thread::Builder::new()
             .name(format!("{name} driver"))
             .spawn(move || {
@@ -171,6 +178,7 @@ impl DedicatedExecutor {
                 let mut runtime_builder = runtime_builder;
                 let runtime = runtime_builder
                     .worker_threads(config.dedicated_executor_threads)
+                    .thread_name(worker_name)
                     .on_thread_start(move || register_io_runtime(io_handle.clone()))
                     .build()
                     .expect("Creating tokio runtime");
@@ -300,6 +308,48 @@ impl DedicatedExecutor {
         .boxed()
     }
 
+    /// Polls `stream` on the thread pool managed by this `DedicatedExecutor`, returning a stream
+    /// of its batches that can be consumed from any runtime.
+    ///
+    /// Most of the work of a DataFusion plan happens when its stream is polled rather than when
+    /// it is created, so streams created with [`DedicatedExecutor::spawn`] need to be driven
+    /// here as well to keep that work off of the IO runtime. Dropping the returned stream
+    /// cancels the work.
+    pub fn run_cpu_sendable_record_batch_stream(
+        &self,
+        stream: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        let schema = stream.schema();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(2);
+        let job = self.spawn(async move {
+            debug!(
+                "polling stream on thread {}",
+                std::thread::current().name().unwrap_or_default()
+            );
+            let mut stream = stream;
+            while let Some(batch) = stream.next().await {
+                // The receiver is only gone once the returned stream was dropped
+                if tx.send(batch).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let batches = futures::stream::poll_fn(move |cx| rx.poll_recv(cx));
+        // The job can only fail once all of its batches were sent, for example if the plan
+        // panicked, so its error comes last
+        let job_error = job
+            .map(|res| {
+                res.err()
+                    .map(|e| Err(DataFusionError::External(Box::new(e))))
+            })
+            .into_stream()
+            .filter_map(futures::future::ready);
+        Box::pin(RecordBatchStreamAdapter::new(
+            schema,
+            batches.chain(job_error),
+        ))
+    }
+
     /// signals shutdown of this executor and any Clones
     pub fn shutdown(&self) {
         if self.testing {
@@ -759,6 +809,45 @@ mod tests {
         exec.join().await;
     }
 
+    #[tokio::test]
+    async fn run_cpu_stream() {
+        use datafusion::arrow::{
+            array::Int32Array,
+            datatypes::{DataType, Field, Schema},
+            record_batch::RecordBatch,
+        };
+
+        let exec = exec();
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+        )
+        .unwrap();
+
+        // The batches are only produced when the stream is polled, which should happen on the
+        // executor rather than the thread consuming the stream
+        let caller = std::thread::current().id();
+        let produced = batch.clone();
+        let stream = futures::stream::repeat_with(move || {
+            assert_ne!(std::thread::current().id(), caller);
+            Ok(produced.clone())
+        })
+        .take(2);
+        let stream = Box::pin(RecordBatchStreamAdapter::new(schema, stream));
+
+        let batches: Vec<_> = exec
+            .run_cpu_sendable_record_batch_stream(stream)
+            .collect()
+            .await;
+        assert_eq!(batches.len(), 2);
+        for result in batches {
+            assert_eq!(result.unwrap(), batch);
+        }
+
+        exec.join().await;
+    }
+
     #[tokio::test]
     async fn test_io_runtime_multi_thread() {
         let mut runtime_builder = tokio::runtime::Builder::new_multi_thread();
@@ -21,7 +21,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use futures::{Future, FutureExt};
+use futures::{stream::BoxStream, Future, FutureExt, StreamExt};
 use tokio::{runtime::Handle, task::JoinHandle};
 
 thread_local! {
@@ -62,6 +62,47 @@ where
     DropGuard(h.spawn(fut)).await
 }
 
+/// Runs `fut` on the IO runtime registered for this thread, like [`spawn_io`], or awaits it on
+/// the current thread when there is none, such as on the threads of the IO runtime itself
+pub async fn spawn_io_or_current<Fut>(fut: Fut) -> Fut::Output
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send,
+{
+    match io_runtime_handle() {
+        Some(h) => DropGuard(h.spawn(fut)).await,
+        None => fut.await,
+    }
+}
+
+/// Polls `stream` on the IO runtime registered for the thread that first polls the returned
+/// stream, or on that thread when there is none, see [`spawn_io_or_current`]. The stream is
+/// dropped once the returned stream is.
+pub fn stream_io_or_current<T: Send + 'static>(
+    mut stream: BoxStream<'static, T>,
+) -> BoxStream<'static, T> {
+    futures::stream::once(async move {
+        let Some(h) = io_runtime_handle() else {
+            return stream;
+        };
+        let (tx, rx) = tokio::sync::mpsc::channel(2);
+        let task = h.spawn(async move {
+            while let Some(item) = stream.next().await {
+                if tx.send(item).await.is_err() {
+                    break;
+                }
+            }
+        });
+        futures::stream::unfold((rx, DropGuard(task)), |(mut rx, task)| async move {
+            let item = rx.recv().await?;
+            Some((item, (rx, task)))
+        })
+        .boxed()
+    })
+    .flatten()
+    .boxed()
+}
+
 struct DropGuard<T>(JoinHandle<T>);
 impl<T> Drop for DropGuard<T> {
     fn drop(&mut self) {
@@ -110,6 +151,42 @@ mod tests {
         rt_io.shutdown_background();
     }
 
+    #[tokio::test]
+    async fn test_stream_io_or_current() {
+        // Without an IO runtime the stream is polled on the current thread
+        let stream =
+            futures::stream::poll_fn(|_| std::task::Poll::Ready(Some(std::thread::current().id())))
+                .take(1)
+                .boxed();
+        let ids: Vec<_> = stream_io_or_current(stream).collect().await;
+        assert_eq!(ids, vec![std::thread::current().id()]);
+
+        let rt_io = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let io_thread_id = rt_io
+            .spawn(async move { std::thread::current().id() })
+            .await
+            .unwrap();
+        register_io_runtime(Some(rt_io.handle().clone()));
+
+        let stream =
+            futures::stream::poll_fn(|_| std::task::Poll::Ready(Some(std::thread::current().id())))
+                .take(2)
+                .boxed();
+        let ids: Vec<_> = stream_io_or_current(stream).collect().await;
+        assert_eq!(ids, vec![io_thread_id, io_thread_id]);
+        assert_eq!(
+            spawn_io_or_current(async move { std::thread::current().id() }).await,
+            io_thread_id
+        );
+
+        register_io_runtime(None);
+        rt_io.shutdown_background();
+    }
+
     #[tokio::test]
     #[should_panic(expected = "IO runtime registered")]
     async fn test_panic_if_no_runtime_registered() {
@@ -20,3 +20,4 @@
 /// Ref: https://github.com/influxdata/influxdb3_core/tree/6fcbb004232738d55655f32f4ad2385523d10696/executor
 pub mod dedicated;
 pub mod io;
+pub mod object_store;
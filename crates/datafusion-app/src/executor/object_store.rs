@@ -0,0 +1,209 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Object stores whose requests run on the IO runtime, so that plans reading from them can run
+//! on the [`DedicatedExecutor`](super::dedicated::DedicatedExecutor), which has no IO driver
+
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    common::Result as DFResult,
+    execution::object_store::{DefaultObjectStoreRegistry, ObjectStoreRegistry},
+    object_store::{
+        path::Path, CopyOptions, Error, GetOptions, GetResult, GetResultPayload, ListResult,
+        MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOptions, PutOptions, PutPayload,
+        PutResult, RenameOptions, Result, UploadPart,
+    },
+};
+use futures::{stream::BoxStream, FutureExt};
+use url::Url;
+
+use super::io::{spawn_io_or_current, stream_io_or_current};
+
+/// Registry that wraps the stores registered with it in an [`IoObjectStore`]
+#[derive(Debug, Default)]
+pub struct IoObjectStoreRegistry {
+    inner: DefaultObjectStoreRegistry,
+}
+
+impl ObjectStoreRegistry for IoObjectStoreRegistry {
+    fn register_store(
+        &self,
+        url: &Url,
+        store: Arc<dyn ObjectStore>,
+    ) -> Option<Arc<dyn ObjectStore>> {
+        self.inner
+            .register_store(url, Arc::new(IoObjectStore::new(store)))
+    }
+
+    fn deregister_store(&self, url: &Url) -> DFResult<Arc<dyn ObjectStore>> {
+        self.inner.deregister_store(url)
+    }
+
+    fn get_store(&self, url: &Url) -> DFResult<Arc<dyn ObjectStore>> {
+        self.inner.get_store(url)
+    }
+}
+
+/// Runs the requests of `inner`, and polls the streams it returns, on the IO runtime registered
+/// for the calling thread, if there is one
+#[derive(Debug)]
+pub struct IoObjectStore {
+    inner: Arc<dyn ObjectStore>,
+}
+
+impl IoObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>) -> Self {
+        Self { inner }
+    }
+}
+
+impl fmt::Display for IoObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IoObjectStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for IoObjectStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        let inner = Arc::clone(&self.inner);
+        let location = location.clone();
+        spawn_io_or_current(async move { inner.put_opts(&location, payload, opts).await }).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOptions,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let inner = Arc::clone(&self.inner);
+        let location = location.clone();
+        let upload =
+            spawn_io_or_current(async move { inner.put_multipart_opts(&location, opts).await })
+                .await?;
+        Ok(Box::new(IoMultipartUpload(Some(upload))))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let inner = Arc::clone(&self.inner);
+        let location = location.clone();
+        let mut result =
+            spawn_io_or_current(async move { inner.get_opts(&location, options).await }).await?;
+        // The body is only read as the payload is polled
+        result.payload = match result.payload {
+            GetResultPayload::Stream(stream) => {
+                GetResultPayload::Stream(stream_io_or_current(stream))
+            }
+            payload => payload,
+        };
+        Ok(result)
+    }
+
+    fn delete_stream(
+        &self,
+        locations: BoxStream<'static, Result<Path>>,
+    ) -> BoxStream<'static, Result<Path>> {
+        stream_io_or_current(self.inner.delete_stream(locations))
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, Result<ObjectMeta>> {
+        stream_io_or_current(self.inner.list(prefix))
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'static, Result<ObjectMeta>> {
+        stream_io_or_current(self.inner.list_with_offset(prefix, offset))
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let inner = Arc::clone(&self.inner);
+        let prefix = prefix.cloned();
+        spawn_io_or_current(async move { inner.list_with_delimiter(prefix.as_ref()).await }).await
+    }
+
+    async fn copy_opts(&self, from: &Path, to: &Path, options: CopyOptions) -> Result<()> {
+        let inner = Arc::clone(&self.inner);
+        let (from, to) = (from.clone(), to.clone());
+        spawn_io_or_current(async move { inner.copy_opts(&from, &to, options).await }).await
+    }
+
+    async fn rename_opts(&self, from: &Path, to: &Path, options: RenameOptions) -> Result<()> {
+        let inner = Arc::clone(&self.inner);
+        let (from, to) = (from.clone(), to.clone());
+        spawn_io_or_current(async move { inner.rename_opts(&from, &to, options).await }).await
+    }
+}
+
+/// Runs the requests of a multipart upload on the IO runtime, see [`IoObjectStore`]. The upload
+/// is moved to the IO runtime while it completes or aborts, and is gone if that's cancelled.
+#[derive(Debug)]
+struct IoMultipartUpload(Option<Box<dyn MultipartUpload>>);
+
+impl IoMultipartUpload {
+    fn take(&mut self) -> Result<Box<dyn MultipartUpload>> {
+        self.0.take().ok_or_else(cancelled)
+    }
+}
+
+fn cancelled() -> Error {
+    Error::Generic {
+        store: "IoObjectStore",
+        source: "The multipart upload was cancelled".into(),
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for IoMultipartUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        match &mut self.0 {
+            Some(upload) => spawn_io_or_current(upload.put_part(data)).boxed(),
+            None => futures::future::ready(Err(cancelled())).boxed(),
+        }
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        let mut upload = self.take()?;
+        let (upload, result) = spawn_io_or_current(async move {
+            let result = upload.complete().await;
+            (upload, result)
+        })
+        .await;
+        self.0 = Some(upload);
+        result
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        let mut upload = self.take()?;
+        let (upload, result) = spawn_io_or_current(async move {
+            let result = upload.abort().await;
+            (upload, result)
+        })
+        .await;
+        self.0 = Some(upload);
+        result
+    }
+}
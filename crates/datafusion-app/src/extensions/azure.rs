@@ -0,0 +1,76 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Azure Blob Storage Integration: [AzureExtension]
+
+use crate::config::ExecutionConfig;
+use crate::extensions::{DftSessionStateBuilder, Extension};
+use crate::secrets::Secrets;
+use log::{debug, info};
+use std::sync::Arc;
+
+use url::Url;
+
+#[derive(Debug, Default)]
+pub struct AzureExtension {}
+
+impl AzureExtension {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for AzureExtension {
+    async fn register(
+        &self,
+        config: ExecutionConfig,
+        builder: &mut DftSessionStateBuilder,
+    ) -> datafusion::common::Result<()> {
+        let Some(object_store_config) = &config.object_store else {
+            return Ok(());
+        };
+
+        let Some(azure_configs) = &object_store_config.azure else {
+            return Ok(());
+        };
+
+        let secrets = Secrets::from_config(&config);
+        for azure_config in azure_configs {
+            match azure_config
+                .with_secrets(&secrets)
+                .and_then(|azure_config| azure_config.to_object_store())
+            {
+                Ok(object_store) => {
+                    debug!("created object store: {}", object_store);
+                    let object_store_url = azure_config.object_store_url();
+                    if let Ok(parsed_url) = Url::parse(object_store_url) {
+                        builder
+                            .runtime_env()
+                            .register_object_store(&parsed_url, Arc::new(object_store));
+                        info!("registered azure object store at {object_store_url}");
+                    }
+                }
+                Err(e) => {
+                    log::error!("error creating object store: {:?}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
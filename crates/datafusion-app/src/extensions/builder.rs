@@ -25,14 +25,19 @@ use datafusion::datasource::file_format::{
     FileFormatFactory,
 };
 use datafusion::execution::context::SessionState;
-use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::execution::disk_manager::{DiskManagerBuilder, DiskManagerMode};
+use datafusion::execution::runtime_env::{RuntimeEnv, RuntimeEnvBuilder};
 use datafusion::execution::session_state::SessionStateBuilder;
 use datafusion::prelude::SessionConfig;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use crate::config::ExecutionConfig;
+use crate::config::{ExecutionConfig, SqlDialect};
+use crate::ddl::{DdlListener, DdlListeners};
+use crate::executor::object_store::IoObjectStoreRegistry;
+use crate::rewrite::{PlanRewriter, PlanRewriters};
+use crate::secrets::{with_table_factory_secrets, Secrets};
 
 use super::{enabled_extensions, Extension};
 
@@ -61,6 +66,8 @@ pub struct DftSessionStateBuilder {
     file_format_factories: Vec<Arc<dyn FileFormatFactory>>,
     catalog_providers: Option<HashMap<String, Arc<dyn CatalogProvider>>>,
     runtime_env: Option<Arc<RuntimeEnv>>,
+    plan_rewriters: Vec<Arc<dyn PlanRewriter>>,
+    ddl_listeners: Vec<Arc<dyn DdlListener>>,
 }
 
 impl Debug for DftSessionStateBuilder {
@@ -72,6 +79,8 @@ impl Debug for DftSessionStateBuilder {
                 &"TODO TableFactory does not implement Debug",
             )
             .field("runtime_env", &self.runtime_env)
+            .field("plan_rewriters", &self.plan_rewriters)
+            .field("ddl_listeners", &self.ddl_listeners)
             .finish()
     }
 }
@@ -89,6 +98,8 @@ impl Default for DftSessionStateBuilder {
             ],
             catalog_providers: None,
             runtime_env: None,
+            plan_rewriters: Vec::new(),
+            ddl_listeners: Vec::new(),
         }
     }
 }
@@ -96,11 +107,20 @@ impl Default for DftSessionStateBuilder {
 impl DftSessionStateBuilder {
     /// Create a new builder
     pub fn try_new(config: Option<ExecutionConfig>) -> Result<Self> {
-        let session_config = if let Some(cfg) = config.clone().unwrap_or_default().datafusion {
-            SessionConfig::from_string_hash_map(&cfg)?.with_information_schema(true)
+        let execution_config = config.clone().unwrap_or_default();
+        let mut session_config = if let Some(cfg) = &execution_config.datafusion {
+            SessionConfig::from_string_hash_map(cfg)?.with_information_schema(true)
         } else {
             SessionConfig::default().with_information_schema(true)
         };
+        if execution_config.dialect != SqlDialect::default() {
+            session_config.options_mut().set(
+                "datafusion.sql_parser.dialect",
+                execution_config.dialect.name(),
+            )?;
+        }
+        // Created up front so that extensions register their object stores with it
+        let runtime_env = create_runtime_env(&execution_config)?;
 
         let builder = Self {
             session_config,
@@ -113,7 +133,9 @@ impl DftSessionStateBuilder {
             ],
 
             catalog_providers: None,
-            runtime_env: None,
+            runtime_env: Some(runtime_env),
+            plan_rewriters: Vec::new(),
+            ddl_listeners: Vec::new(),
         };
         Ok(builder)
     }
@@ -147,6 +169,17 @@ impl DftSessionStateBuilder {
         }
     }
 
+    /// Add a rewriter applied to the plans of the statements the servers execute, after the
+    /// rewriters added before it
+    pub fn add_plan_rewriter(&mut self, rewriter: Arc<dyn PlanRewriter>) {
+        self.plan_rewriters.push(rewriter);
+    }
+
+    /// Add a listener notified of the DDL statements the session executes
+    pub fn add_ddl_listener(&mut self, listener: Arc<dyn DdlListener>) {
+        self.ddl_listeners.push(listener);
+    }
+
     /// Return the current [`RuntimeEnv`], creating a default if it doesn't exist
     pub fn runtime_env(&mut self) -> &RuntimeEnv {
         if self.runtime_env.is_none() {
@@ -181,14 +214,28 @@ impl DftSessionStateBuilder {
     /// Build the [`SessionState`] from the specified configuration
     pub fn build(self) -> datafusion::common::Result<SessionState> {
         let Self {
+            execution_config,
             session_config,
             table_factories,
             file_format_factories,
             catalog_providers,
             runtime_env,
+            plan_rewriters,
+            ddl_listeners,
             ..
         } = self;
 
+        let session_config = if plan_rewriters.is_empty() {
+            session_config
+        } else {
+            session_config.with_extension(Arc::new(PlanRewriters(plan_rewriters)))
+        };
+        let session_config = if ddl_listeners.is_empty() {
+            session_config
+        } else {
+            session_config.with_extension(Arc::new(DdlListeners(ddl_listeners)))
+        };
+
         let mut builder = SessionStateBuilder::new()
             .with_default_features()
             .with_config(session_config);
@@ -209,6 +256,45 @@ impl DftSessionStateBuilder {
             builder = builder.with_catalog_list(Arc::new(catalogs_list));
         }
 
-        Ok(builder.build())
+        let mut state = builder.build();
+        // Secrets are resolved when tables are created so the DDL keeps the `secret://` references.
+        // Unless `query_secrets` is set, only the DDL, which is executed with a session of its
+        // own, can refer to them.
+        let execution_config = execution_config.unwrap_or_default();
+        let secrets = Secrets::from_config(&execution_config);
+        let secrets = execution_config.query_secrets.then_some(&secrets);
+        with_table_factory_secrets(&mut state, secrets);
+        Ok(state)
+    }
+}
+
+/// Create the [`RuntimeEnv`] with the memory pool and the spill directories of `config`
+fn create_runtime_env(config: &ExecutionConfig) -> Result<Arc<RuntimeEnv>> {
+    let spill = &config.spill;
+    let mode = if !spill.enabled {
+        DiskManagerMode::Disabled
+    } else if spill.directories.is_empty() {
+        DiskManagerMode::OsTmpDirectory
+    } else {
+        for dir in &spill.directories {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                eyre::eyre!("Error creating spill directory {}: {e}", dir.display())
+            })?;
+        }
+        DiskManagerMode::Directories(spill.directories.clone())
+    };
+    let mut disk_manager = DiskManagerBuilder::default().with_mode(mode);
+    if let Some(max_disk_bytes) = spill.max_disk_bytes {
+        disk_manager = disk_manager.with_max_temp_directory_size(max_disk_bytes);
+    }
+    let mut runtime_env = RuntimeEnvBuilder::new()
+        .with_memory_pool(config.memory.memory_pool())
+        .with_disk_manager_builder(disk_manager);
+    if config.dedicated_executor_enabled {
+        // The dedicated executor can't do IO itself
+        runtime_env =
+            runtime_env.with_object_store_registry(Arc::new(IoObjectStoreRegistry::default()));
     }
+    let runtime_env = runtime_env.build_arc()?;
+    Ok(runtime_env)
 }
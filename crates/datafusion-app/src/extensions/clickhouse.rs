@@ -20,6 +20,7 @@
 use crate::catalog::clickhouse::ClickHouseCatalogProvider;
 use crate::config::ExecutionConfig;
 use crate::extensions::{DftSessionStateBuilder, Extension};
+use crate::secrets::Secrets;
 use datafusion::common::{DataFusionError, Result};
 use datafusion_table_providers::sql::db_connection_pool::clickhousepool::ClickHouseConnectionPool;
 use datafusion_table_providers::util::secrets::to_secret_map;
@@ -42,8 +43,9 @@ impl Extension for ClickHouseExtension {
         config: ExecutionConfig,
         builder: &mut DftSessionStateBuilder,
     ) -> Result<()> {
+        let secrets = Secrets::from_config(&config);
         for clickhouse_config in config.clickhouse.iter().flatten() {
-            let params = to_secret_map(clickhouse_config.to_params());
+            let params = to_secret_map(secrets.resolve_map(&clickhouse_config.to_params())?);
             let pool = ClickHouseConnectionPool::new(params)
                 .await
                 .map_err(|e| DataFusionError::External(Box::new(e)))?;
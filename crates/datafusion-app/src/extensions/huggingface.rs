@@ -19,6 +19,7 @@
 
 use crate::config::ExecutionConfig;
 use crate::extensions::{DftSessionStateBuilder, Extension};
+use crate::secrets::Secrets;
 use datafusion::common::DataFusionError;
 use log::info;
 use std::sync::Arc;
@@ -50,6 +51,7 @@ impl Extension for HuggingFaceExtension {
             return Ok(());
         };
 
+        let secrets = Secrets::from_config(&config);
         for huggingface_config in huggingface_configs {
             // I'm not that famliar with Huggingface so I'm not sure what permutations of config
             // values are supposed to work.
@@ -65,7 +67,7 @@ impl Extension for HuggingFaceExtension {
                 hf_builder = hf_builder.root(root);
             };
             if let Some(token) = &huggingface_config.token {
-                hf_builder = hf_builder.token(token);
+                hf_builder = hf_builder.token(&secrets.resolve(token)?);
             };
             if let Some(repo_id) = &huggingface_config.repo_id {
                 hf_builder = hf_builder.repo_id(repo_id);
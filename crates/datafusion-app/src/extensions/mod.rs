@@ -22,11 +22,15 @@ use datafusion::common::Result;
 use datafusion::prelude::SessionContext;
 use std::{fmt::Debug, sync::Arc};
 
+#[cfg(feature = "azure")]
+mod azure;
 mod builder;
 #[cfg(feature = "clickhouse")]
 mod clickhouse;
 #[cfg(feature = "deltalake")]
 mod deltalake;
+#[cfg(feature = "gcs")]
+mod gcs;
 #[cfg(feature = "huggingface")]
 mod huggingface;
 #[cfg(feature = "mongodb")]
@@ -60,6 +64,10 @@ pub fn enabled_extensions() -> Vec<Arc<dyn Extension>> {
     vec![
         #[cfg(feature = "s3")]
         Arc::new(s3::AwsS3Extension::new()),
+        #[cfg(feature = "gcs")]
+        Arc::new(gcs::GoogleCloudStorageExtension::new()),
+        #[cfg(feature = "azure")]
+        Arc::new(azure::AzureExtension::new()),
         #[cfg(feature = "deltalake")]
         Arc::new(deltalake::DeltaLakeExtension::new()),
         #[cfg(feature = "huggingface")]
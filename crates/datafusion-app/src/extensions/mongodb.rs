@@ -20,6 +20,7 @@
 use crate::catalog::mongodb::MongoDbCatalogProvider;
 use crate::config::ExecutionConfig;
 use crate::extensions::{DftSessionStateBuilder, Extension};
+use crate::secrets::Secrets;
 use datafusion::common::Result;
 use log::info;
 use std::sync::Arc;
@@ -40,8 +41,10 @@ impl Extension for MongoDbExtension {
         config: ExecutionConfig,
         builder: &mut DftSessionStateBuilder,
     ) -> Result<()> {
+        let secrets = Secrets::from_config(&config);
         for mongodb_config in config.mongodb.iter().flatten() {
-            let catalog = MongoDbCatalogProvider::try_new(mongodb_config.to_params()).await?;
+            let params = secrets.resolve_map(&mongodb_config.to_params())?;
+            let catalog = MongoDbCatalogProvider::try_new(params).await?;
             builder.add_catalog_provider(&mongodb_config.name, Arc::new(catalog));
             info!("Registered MongoDB catalog '{}'", mongodb_config.name);
         }
@@ -19,6 +19,7 @@
 
 use crate::config::ExecutionConfig;
 use crate::extensions::{DftSessionStateBuilder, Extension};
+use crate::secrets::Secrets;
 use log::{debug, info};
 use std::sync::Arc;
 
@@ -49,8 +50,12 @@ impl Extension for AwsS3Extension {
         };
 
         info!("S3 configs exists");
+        let secrets = Secrets::from_config(&config);
         for s3_config in s3_configs {
-            match s3_config.to_object_store() {
+            match s3_config
+                .with_secrets(&secrets)
+                .and_then(|s3_config| s3_config.to_object_store())
+            {
                 Ok(object_store) => {
                     debug!("created object store: {}", object_store);
                     if let Some(object_store_url) = s3_config.object_store_url() {
@@ -19,32 +19,58 @@ use std::sync::Arc;
 
 use arrow_flight::{
     decode::FlightRecordBatchStream,
+    encode::FlightDataEncoderBuilder,
+    error::FlightError,
     flight_service_client::FlightServiceClient,
     sql::{
-        client::FlightSqlServiceClient, CommandGetDbSchemas, CommandGetTables,
-        CommandGetXdbcTypeInfo,
+        client::{FlightSqlServiceClient, PreparedStatement},
+        CommandGetCrossReference, CommandGetDbSchemas, CommandGetExportedKeys,
+        CommandGetImportedKeys, CommandGetPrimaryKeys, CommandGetTables, CommandGetXdbcTypeInfo,
+        DoPutUpdateResult,
     },
-    FlightInfo,
+    FlightDescriptor, FlightInfo,
 };
 #[cfg(feature = "flightsql")]
 use base64::engine::{general_purpose::STANDARD, Engine as _};
 use datafusion::{
+    arrow::{
+        array::{ArrayRef, RecordBatch, StringArray},
+        compute::cast,
+        datatypes::{DataType, Field, Schema},
+        error::ArrowError,
+    },
     error::{DataFusionError, Result as DFResult},
-    physical_plan::stream::RecordBatchStreamAdapter,
-    sql::parser::DFParser,
+    execution::SendableRecordBatchStream,
+    physical_plan::{
+        limit::LimitStream,
+        metrics::{BaselineMetrics, ExecutionPlanMetricsSet},
+        stream::RecordBatchStreamAdapter,
+    },
+    prelude::{SessionConfig, SessionContext},
+    sql::{
+        parser::{DFParser, Statement},
+        sqlparser::{ast::Statement as SQLStatement, dialect::dialect_from_str},
+    },
 };
 use log::{debug, error, info, warn};
 
 #[cfg(feature = "flightsql")]
 use crate::config::BasicAuth;
+use crate::flightsql_tls::configure_tls;
 use color_eyre::eyre::{self, Result};
-use std::collections::HashMap;
+use futures::{future::BoxFuture, TryStreamExt};
+use prost::Message;
+use std::collections::{HashMap, VecDeque};
 use tokio::sync::Mutex;
 use tokio_stream::StreamExt;
-use tonic::{transport::Channel, IntoRequest};
+use tonic::{
+    metadata::{AsciiMetadataKey, AsciiMetadataValue},
+    transport::Channel,
+    IntoRequest, IntoStreamingRequest,
+};
 
 use crate::{
-    config::FlightSQLConfig,
+    config::{FlightSQLConfig, SqlDialect},
     flightsql_benchmarks::FlightSQLBenchmarkStats,
     local_benchmarks::{BenchmarkMode, BenchmarkProgressReporter},
     ExecOptions, ExecResult,
@@ -56,6 +82,39 @@ pub type FlightSQLClient = Arc<Mutex<Option<FlightSqlServiceClient<Channel>>>>;
 pub struct FlightSQLContext {
     config: FlightSQLConfig,
     client: FlightSQLClient,
+    /// Headers set on the client, kept for the requests made with its inner Flight client
+    headers: Arc<Mutex<HashMap<String, String>>>,
+    /// Session that the `SET` statements sent to the server are also executed in, as the server
+    /// executes them in the client's session, so that queries are parsed with the dialect the
+    /// server parses them with before they're sent, for example to check that only one is
+    /// benchmarked
+    settings: Settings,
+}
+
+/// The session of [`FlightSQLContext::settings`]
+#[derive(Clone, Default)]
+struct Settings(SessionContext);
+
+impl std::fmt::Debug for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Settings").finish_non_exhaustive()
+    }
+}
+
+/// Whether the server rejected a request as unauthenticated. The client converts statuses to
+/// `ArrowError::IpcError`s holding the status' debug representation, so the code is read from
+/// there.
+fn is_unauthenticated(e: &ArrowError) -> bool {
+    matches!(e, ArrowError::IpcError(message) if message.starts_with("Status { code: Unauthenticated"))
+}
+
+/// The FlightSQL commands that return the key columns of tables
+#[derive(Clone, Debug)]
+pub enum KeysCommand {
+    Primary(CommandGetPrimaryKeys),
+    Exported(CommandGetExportedKeys),
+    Imported(CommandGetImportedKeys),
+    CrossReference(CommandGetCrossReference),
 }
 
 impl FlightSQLContext {
@@ -63,6 +122,49 @@ impl FlightSQLContext {
         Self {
             config,
             client: Arc::new(Mutex::new(None)),
+            headers: Arc::new(Mutex::new(HashMap::new())),
+            settings: Settings::default(),
+        }
+    }
+
+    pub fn with_dialect(mut self, dialect: SqlDialect) -> Self {
+        let config = SessionConfig::new().set_str("datafusion.sql_parser.dialect", dialect.name());
+        self.settings = Settings(SessionContext::new_with_config(config));
+        self
+    }
+
+    /// Parse `sql` with the dialect the server parses it with, which starts as the configured
+    /// dialect and is changed by `SET datafusion.sql_parser.dialect`
+    fn parse_sql(&self, sql: &str) -> DFResult<VecDeque<Statement>> {
+        let name = self
+            .settings
+            .0
+            .state()
+            .config_options()
+            .sql_parser
+            .dialect
+            .to_string();
+        let dialect = dialect_from_str(&name)
+            .ok_or_else(|| DataFusionError::Plan(format!("Unsupported SQL dialect: {name}")))?;
+        DFParser::parse_sql_with_dialect(sql, dialect.as_ref())
+    }
+
+    /// Execute `statement` in [`Self::settings`] if it's a `SET` statement the server executed
+    async fn track_settings(&self, statement: Statement) {
+        let Statement::Statement(sql_statement) = &statement else {
+            return;
+        };
+        if !matches!(sql_statement.as_ref(), SQLStatement::Set(_)) {
+            return;
+        }
+        let ctx = &self.settings.0;
+        let result = match ctx.state().statement_to_plan(statement).await {
+            Ok(plan) => ctx.execute_logical_plan(plan).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        // Settings the client doesn't know of don't change how it parses queries
+        if let Err(e) = result {
+            debug!("Error tracking setting: {e}");
         }
     }
 
@@ -78,9 +180,14 @@ impl FlightSQLContext {
         cli_headers: Option<HashMap<String, String>>,
     ) -> Result<()> {
         let final_url = cli_host.unwrap_or(self.config.connection_url.clone());
+        let is_https = final_url.starts_with("https://");
         let url = Box::leak(final_url.into_boxed_str());
         info!("Connecting to FlightSQL host: {}", url);
-        let channel = Channel::from_static(url).connect().await;
+        let mut endpoint = Channel::from_static(url);
+        if is_https {
+            endpoint = configure_tls(endpoint, &self.config.tls)?;
+        }
+        let channel = endpoint.connect().await;
         match channel {
             Ok(c) => {
                 let mut inner = FlightServiceClient::new(c);
@@ -99,22 +206,39 @@ impl FlightSQLContext {
                 // TODO - Do we need this feature block?
                 #[cfg(feature = "flightsql")]
                 {
-                    if let Some(token) = &self.config.auth.bearer_token {
-                        client.set_token(token.to_string());
-                    } else if let Some(BasicAuth { username, password }) =
-                        &self.config.auth.basic_auth
-                    {
-                        let encoded_basic = STANDARD.encode(format!("{username}:{password}"));
-                        client.set_header("Authorization", format!("Basic {encoded_basic}"))
-                    }
-
                     let mut headers = self.config.headers.clone();
                     if let Some(cli) = cli_headers {
                         headers.extend(cli);
                     }
-                    for (name, value) in headers {
+                    for (name, value) in &headers {
                         client.set_header(name, value);
                     }
+
+                    // Credentials are attached to every request the client makes, after any
+                    // custom headers so that they aren't replaced by them
+                    let auth = &self.config.auth;
+                    if let Some(token) = &auth.bearer_token {
+                        client.set_token(token.to_string());
+                    } else if let Some(BasicAuth { username, password }) = &auth.basic_auth {
+                        if auth.handshake {
+                            // The server returns a session token that is sent instead of the
+                            // credentials from then on
+                            client.handshake(username, password).await.map_err(|e| {
+                                eyre::eyre!("Error authenticating with FlightSQL handshake: {e}")
+                            })?;
+                            if client.token().is_none() {
+                                return Err(eyre::eyre!(
+                                    "FlightSQL server did not return a token from the handshake"
+                                ));
+                            }
+                        } else {
+                            let encoded_basic = STANDARD.encode(format!("{username}:{password}"));
+                            let value = format!("Basic {encoded_basic}");
+                            client.set_header("authorization", &value);
+                            headers.insert("authorization".to_string(), value);
+                        }
+                    }
+                    *self.headers.lock().await = headers;
                 }
                 let mut guard = self.client.lock().await;
                 *guard = Some(client);
@@ -127,28 +251,79 @@ impl FlightSQLContext {
         }
     }
 
+    /// Make `request` with `client`. If the server rejects it as unauthenticated, for example
+    /// because the session token from the handshake expired, the credentials are exchanged for a
+    /// new token and the request is made once more.
+    async fn call<T>(
+        &self,
+        client: &mut FlightSqlServiceClient<Channel>,
+        request: impl for<'a> Fn(
+            &'a mut FlightSqlServiceClient<Channel>,
+        ) -> BoxFuture<'a, std::result::Result<T, ArrowError>>,
+    ) -> DFResult<T> {
+        match request(client).await {
+            Err(e) if is_unauthenticated(&e) && self.renew_session_token(client).await => {
+                request(client).await
+            }
+            result => result,
+        }
+        .map_err(|e| DataFusionError::External(Box::new(e)))
+    }
+
+    /// Handshake again with the configured basic credentials, returning whether the server
+    /// issued a new session token. Clients that don't authenticate with a handshake have nothing
+    /// to renew.
+    async fn renew_session_token(&self, client: &mut FlightSqlServiceClient<Channel>) -> bool {
+        let auth = &self.config.auth;
+        let Some(basic) = auth.basic_auth.as_ref() else {
+            return false;
+        };
+        if !auth.handshake || auth.bearer_token.is_some() {
+            return false;
+        }
+        info!("FlightSQL session token was rejected, handshaking again");
+        match client.handshake(&basic.username, &basic.password).await {
+            Ok(_) => client.token().is_some(),
+            Err(e) => {
+                warn!("Error renewing FlightSQL session token: {e}");
+                false
+            }
+        }
+    }
+
+    /// Benchmark a query by running it `cli_iterations` times (or the configured number of
+    /// benchmark iterations). If `prepared` is true the query is prepared on the server once
+    /// and each iteration executes the prepared statement, so the cost of planning on the
+    /// server is reported separately from the cost of executing the query.
     pub async fn benchmark_query(
         &self,
         query: &str,
         cli_iterations: Option<usize>,
         concurrent: bool,
+        prepared: bool,
         progress_reporter: Option<Arc<dyn BenchmarkProgressReporter>>,
     ) -> Result<FlightSQLBenchmarkStats> {
         let iterations = cli_iterations.unwrap_or(self.config.benchmark_iterations);
-        let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
-        let statements = DFParser::parse_sql_with_dialect(query, &dialect)?;
+        let mut statements = self.parse_sql(query)?;
 
         if statements.len() != 1 {
             return Err(eyre::eyre!("Only a single statement can be benchmarked"));
         }
 
-        // Check that client exists
-        {
-            let guard = self.client.lock().await;
-            if guard.is_none() {
+        // Check that client exists and prepare the statement if requested
+        let (prepared_statement, prepare_duration) = {
+            let mut guard = self.client.lock().await;
+            let Some(client) = guard.as_mut() else {
                 return Err(eyre::eyre!("No FlightSQL client configured"));
+            };
+            if prepared {
+                let start = std::time::Instant::now();
+                let prepared_statement = client.prepare(query.to_string(), None).await?;
+                (Some(prepared_statement), Some(start.elapsed()))
+            } else {
+                (None, None)
             }
-        }
+        };
 
         let concurrency = if concurrent {
             std::cmp::min(iterations, num_cpus::get())
@@ -172,67 +347,95 @@ impl FlightSQLContext {
         let mut do_get_durations = Vec::with_capacity(iterations);
         let mut total_durations = Vec::with_capacity(iterations);
 
-        if !concurrent {
-            // Serial execution
-            let mut guard = self.client.lock().await;
-            if let Some(ref mut client) = *guard {
-                for i in 0..iterations {
-                    let (rows, gfi_dur, ttfb_dur, dg_dur, total_dur) =
-                        Self::benchmark_single_iteration(client, query).await?;
-                    rows_returned.push(rows);
-                    get_flight_info_durations.push(gfi_dur);
-                    ttfb_durations.push(ttfb_dur);
-                    do_get_durations.push(dg_dur);
-                    total_durations.push(total_dur);
-
-                    if let Some(ref reporter) = progress_reporter {
-                        reporter.on_iteration_complete(i + 1, iterations, total_dur);
+        // The iterations run in their own block so the prepared statement is closed below even
+        // when one of them fails
+        let res: Result<()> = async {
+            if !concurrent {
+                // Serial execution
+                let mut guard = self.client.lock().await;
+                if let Some(ref mut client) = *guard {
+                    let mut prepared_statement = prepared_statement.clone();
+                    for i in 0..iterations {
+                        let (rows, gfi_dur, ttfb_dur, dg_dur, total_dur) =
+                            Self::benchmark_single_iteration(
+                                client,
+                                query,
+                                prepared_statement.as_mut(),
+                            )
+                            .await?;
+                        rows_returned.push(rows);
+                        get_flight_info_durations.push(gfi_dur);
+                        ttfb_durations.push(ttfb_dur);
+                        do_get_durations.push(dg_dur);
+                        total_durations.push(total_dur);
+
+                        if let Some(ref reporter) = progress_reporter {
+                            reporter.on_iteration_complete(i + 1, iterations, total_dur);
+                        }
                     }
                 }
-            }
-        } else {
-            // Concurrent execution - spawn tasks that share the client
-            let mut completed = 0;
-
-            while completed < iterations {
-                let batch_size = std::cmp::min(concurrency, iterations - completed);
-                let mut join_set = tokio::task::JoinSet::new();
-
-                for _ in 0..batch_size {
-                    let client = Arc::clone(&self.client);
-                    let query_str = query.to_string();
+            } else {
+                // Concurrent execution - spawn tasks that share the client
+                let mut completed = 0;
+
+                while completed < iterations {
+                    let batch_size = std::cmp::min(concurrency, iterations - completed);
+                    let mut join_set = tokio::task::JoinSet::new();
+
+                    for _ in 0..batch_size {
+                        let client = Arc::clone(&self.client);
+                        let query_str = query.to_string();
+                        let mut prepared_statement = prepared_statement.clone();
+
+                        join_set.spawn(async move {
+                            let mut guard = client.lock().await;
+                            if let Some(ref mut c) = *guard {
+                                Self::benchmark_single_iteration(
+                                    c,
+                                    &query_str,
+                                    prepared_statement.as_mut(),
+                                )
+                                .await
+                            } else {
+                                Err(eyre::eyre!("No FlightSQL client configured"))
+                            }
+                        });
+                    }
 
-                    join_set.spawn(async move {
-                        let mut guard = client.lock().await;
-                        if let Some(ref mut c) = *guard {
-                            Self::benchmark_single_iteration(c, &query_str).await
-                        } else {
-                            Err(eyre::eyre!("No FlightSQL client configured"))
+                    while let Some(result) = join_set.join_next().await {
+                        let (rows, gfi_dur, ttfb_dur, dg_dur, total_dur) = result??;
+                        rows_returned.push(rows);
+                        get_flight_info_durations.push(gfi_dur);
+                        ttfb_durations.push(ttfb_dur);
+                        do_get_durations.push(dg_dur);
+                        total_durations.push(total_dur);
+
+                        completed += 1;
+                        if let Some(ref reporter) = progress_reporter {
+                            reporter.on_iteration_complete(completed, iterations, total_dur);
                         }
-                    });
-                }
-
-                while let Some(result) = join_set.join_next().await {
-                    let (rows, gfi_dur, ttfb_dur, dg_dur, total_dur) = result??;
-                    rows_returned.push(rows);
-                    get_flight_info_durations.push(gfi_dur);
-                    ttfb_durations.push(ttfb_dur);
-                    do_get_durations.push(dg_dur);
-                    total_durations.push(total_dur);
-
-                    completed += 1;
-                    if let Some(ref reporter) = progress_reporter {
-                        reporter.on_iteration_complete(completed, iterations, total_dur);
                     }
                 }
             }
+            Ok(())
+        }
+        .await;
+
+        if let Some(prepared_statement) = prepared_statement {
+            if let Err(e) = prepared_statement.close().await {
+                warn!("Error closing prepared statement: {:?}", e);
+            }
         }
+        res?;
 
         if let Some(ref reporter) = progress_reporter {
             reporter.finish();
         }
+        if let Some(statement) = statements.pop_front() {
+            self.track_settings(statement).await;
+        }
 
-        Ok(FlightSQLBenchmarkStats::new(
+        let stats = FlightSQLBenchmarkStats::new(
             query.to_string(),
             rows_returned,
             mode,
@@ -240,12 +443,19 @@ impl FlightSQLContext {
             ttfb_durations,
             do_get_durations,
             total_durations,
-        ))
+        );
+        Ok(match prepare_duration {
+            Some(duration) => stats.with_prepare_duration(duration),
+            None => stats,
+        })
     }
 
+    /// Run a single benchmark iteration, executing `prepared_statement` instead of `query` if
+    /// one is provided
     async fn benchmark_single_iteration(
         client: &mut FlightSqlServiceClient<Channel>,
         query: &str,
+        prepared_statement: Option<&mut PreparedStatement<Channel>>,
     ) -> Result<(
         usize,
         std::time::Duration,
@@ -255,7 +465,10 @@ impl FlightSQLContext {
     )> {
         let mut rows = 0;
         let start = std::time::Instant::now();
-        let flight_info = client.execute(query.to_string(), None).await?;
+        let flight_info = match prepared_statement {
+            Some(prepared_statement) => prepared_statement.execute().await?,
+            None => client.execute(query.to_string(), None).await?,
+        };
 
         if flight_info.endpoint.len() > 1 {
             warn!("More than one endpoint: Benchmark results will not be reliable");
@@ -300,27 +513,51 @@ impl FlightSQLContext {
     pub async fn execute_sql_with_opts(
         &self,
         sql: &str,
-        _opts: ExecOptions,
+        opts: ExecOptions,
     ) -> DFResult<ExecResult> {
+        if opts.params.is_some() {
+            return Err(DataFusionError::NotImplemented(
+                "Parameters are not supported for FlightSQL queries".to_string(),
+            ));
+        }
         if let Some(ref mut client) = *self.client.lock().await {
-            let flight_info = client
-                .execute(sql.to_string(), None)
-                .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            let flight_info = self
+                .call(client, |c| Box::pin(c.execute(sql.to_string(), None)))
+                .await?;
             if flight_info.endpoint.len() != 1 {
                 return Err(DataFusionError::External("More than one endpoint".into()));
             }
             let endpoint = &flight_info.endpoint[0];
             if let Some(ticket) = &endpoint.ticket {
-                match client.do_get(ticket.clone().into_request()).await {
+                match self
+                    .call(client, |c| {
+                        Box::pin(c.do_get(ticket.clone().into_request()))
+                    })
+                    .await
+                {
                     Ok(stream) => {
+                        if let Ok(mut statements) = self.parse_sql(sql) {
+                            if let (Some(statement), true) =
+                                (statements.pop_front(), statements.is_empty())
+                            {
+                                self.track_settings(statement).await;
+                            }
+                        }
                         let mut peekable = stream.peekable();
                         if let Some(Ok(first)) = peekable.peek().await {
                             let schema = first.schema();
                             let mapped = peekable
                                 .map(|r| r.map_err(|e| DataFusionError::External(e.into())));
                             let adapter = RecordBatchStreamAdapter::new(schema, mapped);
-                            Ok(ExecResult::RecordBatchStream(Box::pin(adapter)))
+                            // The server returns the full result so the page is taken as it
+                            // streams in
+                            let limited = LimitStream::new(
+                                Box::pin(adapter),
+                                opts.offset,
+                                opts.limit,
+                                BaselineMetrics::new(&ExecutionPlanMetricsSet::new(), 0),
+                            );
+                            Ok(ExecResult::RecordBatchStream(Box::pin(limited)))
                         } else {
                             Err(DataFusionError::External("No first result".into()))
                         }
@@ -341,10 +578,7 @@ impl FlightSQLContext {
         let client = Arc::clone(&self.client);
         let mut guard = client.lock().await;
         if let Some(client) = guard.as_mut() {
-            client
-                .get_catalogs()
-                .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))
+            self.call(client, |c| Box::pin(c.get_catalogs())).await
         } else {
             Err(DataFusionError::External(
                 "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
@@ -364,10 +598,8 @@ impl FlightSQLContext {
                 catalog,
                 db_schema_filter_pattern: schema_filter_pattern,
             };
-            client
-                .get_db_schemas(cmd)
+            self.call(client, |c| Box::pin(c.get_db_schemas(cmd.clone())))
                 .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))
         } else {
             Err(DataFusionError::External(
                 "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
@@ -393,10 +625,8 @@ impl FlightSQLContext {
                 table_types,
                 include_schema,
             };
-            client
-                .get_tables(cmd)
+            self.call(client, |c| Box::pin(c.get_tables(cmd.clone())))
                 .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))
         } else {
             Err(DataFusionError::External(
                 "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
@@ -408,10 +638,7 @@ impl FlightSQLContext {
         let client = Arc::clone(&self.client);
         let mut guard = client.lock().await;
         if let Some(client) = guard.as_mut() {
-            client
-                .get_table_types()
-                .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))
+            self.call(client, |c| Box::pin(c.get_table_types())).await
         } else {
             Err(DataFusionError::External(
                 "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
@@ -430,10 +657,8 @@ impl FlightSQLContext {
                 .into_iter()
                 .filter_map(|id| SqlInfo::try_from(id as i32).ok())
                 .collect();
-            client
-                .get_sql_info(sql_info_list)
+            self.call(client, |c| Box::pin(c.get_sql_info(sql_info_list.clone())))
                 .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))
         } else {
             Err(DataFusionError::External(
                 "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
@@ -441,6 +666,141 @@ impl FlightSQLContext {
         }
     }
 
+    /// `GetFlightInfo` for one of the commands that return the key columns of tables
+    pub async fn get_keys_flight_info(&self, command: KeysCommand) -> DFResult<FlightInfo> {
+        let client = Arc::clone(&self.client);
+        let mut guard = client.lock().await;
+        let Some(client) = guard.as_mut() else {
+            return Err(DataFusionError::External(
+                "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
+            ));
+        };
+        self.call(client, |c| match command.clone() {
+            KeysCommand::Primary(cmd) => Box::pin(c.get_primary_keys(cmd)),
+            KeysCommand::Exported(cmd) => Box::pin(c.get_exported_keys(cmd)),
+            KeysCommand::Imported(cmd) => Box::pin(c.get_imported_keys(cmd)),
+            KeysCommand::CrossReference(cmd) => Box::pin(c.get_cross_reference(cmd)),
+        })
+        .await
+    }
+
+    /// Stream `batches` into `table` on the server with `DoExchange`, returning the number of rows
+    /// the server acknowledged writing
+    pub async fn ingest(&self, table: &str, batches: SendableRecordBatchStream) -> DFResult<i64> {
+        let client = Arc::clone(&self.client);
+        let mut guard = client.lock().await;
+        let Some(client) = guard.as_mut() else {
+            return Err(DataFusionError::External(
+                "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
+            ));
+        };
+
+        // The request stream can't fail, so it ends at the first error reading the batches and
+        // the error is reported once the server has acknowledged the batches before it
+        let (error_tx, mut error_rx) = tokio::sync::oneshot::channel();
+        let mut error_tx = Some(error_tx);
+        let flight_data = FlightDataEncoderBuilder::new()
+            .with_flight_descriptor(Some(FlightDescriptor::new_path(vec![table.to_string()])))
+            .build(batches.map_err(|e| FlightError::ExternalError(Box::new(e))))
+            .map_while(move |data| match data {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    if let Some(tx) = error_tx.take() {
+                        let _ = tx.send(e);
+                    }
+                    None
+                }
+            });
+
+        // The batches are consumed as they're sent, so unlike other requests this one can't be
+        // made again with a renewed session token if the server rejects the current one
+        let mut request = flight_data.into_streaming_request();
+        let mut headers = self.headers.lock().await.clone();
+        if let Some(token) = client.token() {
+            headers.insert("authorization".to_string(), format!("Bearer {token}"));
+        }
+        for (name, value) in headers {
+            let key = AsciiMetadataKey::from_bytes(name.as_bytes())
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            let value = AsciiMetadataValue::try_from(value)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            request.metadata_mut().insert(key, value);
+        }
+
+        let mut acks = client
+            .inner_mut()
+            .do_exchange(request)
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?
+            .into_inner();
+        let mut rows = 0;
+        while let Some(ack) = acks
+            .message()
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?
+        {
+            let result = DoPutUpdateResult::decode(ack.app_metadata)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            rows += result.record_count;
+        }
+        if let Ok(e) = error_rx.try_recv() {
+            return Err(DataFusionError::External(Box::new(e)));
+        }
+        Ok(rows)
+    }
+
+    /// Create a prepared statement for `sql` on the server and bind `params` to its parameters.
+    /// Each parameter is cast from its string form to the type the server inferred for it.
+    pub async fn prepare_statement(
+        &self,
+        sql: String,
+        params: Vec<String>,
+    ) -> DFResult<PreparedStatement<Channel>> {
+        let client = Arc::clone(&self.client);
+        let mut guard = client.lock().await;
+        let Some(client) = guard.as_mut() else {
+            return Err(DataFusionError::External(
+                "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
+            ));
+        };
+        let mut prepared_statement = self
+            .call(client, |c| Box::pin(c.prepare(sql.clone(), None)))
+            .await?;
+        let parameter_schema = prepared_statement.parameter_schema()?.clone();
+        if parameter_schema.fields().len() != params.len() {
+            // Don't leave the statement open on the server
+            if let Err(e) = prepared_statement.close().await {
+                warn!("Error closing prepared statement: {:?}", e);
+            }
+            return Err(DataFusionError::Plan(format!(
+                "Prepared statement has {} parameters but {} were provided",
+                parameter_schema.fields().len(),
+                params.len()
+            )));
+        }
+        if !params.is_empty() {
+            let mut fields = Vec::with_capacity(params.len());
+            let mut columns = Vec::with_capacity(params.len());
+            for (field, value) in parameter_schema.fields().iter().zip(params) {
+                let value: ArrayRef = Arc::new(StringArray::from(vec![value]));
+                // Parameters the server couldn't infer a type for are sent as strings
+                let column = match field.data_type() {
+                    DataType::Null => value,
+                    data_type => cast(&value, data_type)?,
+                };
+                fields.push(Field::new(
+                    field.name(),
+                    column.data_type().clone(),
+                    field.is_nullable(),
+                ));
+                columns.push(column);
+            }
+            let parameters = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+            prepared_statement.set_parameters(parameters)?;
+        }
+        Ok(prepared_statement)
+    }
+
     pub async fn get_xdbc_type_info_flight_info(
         &self,
         data_type: Option<i32>,
@@ -449,10 +809,8 @@ impl FlightSQLContext {
         let mut guard = client.lock().await;
         if let Some(client) = guard.as_mut() {
             let cmd = CommandGetXdbcTypeInfo { data_type };
-            client
-                .get_xdbc_type_info(cmd)
+            self.call(client, |c| Box::pin(c.get_xdbc_type_info(cmd.clone())))
                 .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))
         } else {
             Err(DataFusionError::External(
                 "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`".into(),
@@ -467,10 +825,11 @@ impl FlightSQLContext {
             let mut streams = Vec::new();
             for endpoint in flight_info.endpoint {
                 if let Some(ticket) = endpoint.ticket {
-                    let stream = client
-                        .do_get(ticket.into_request())
-                        .await
-                        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                    let stream = self
+                        .call(client, |c| {
+                            Box::pin(c.do_get(ticket.clone().into_request()))
+                        })
+                        .await?;
                     streams.push(stream);
                 } else {
                     debug!("No ticket for endpoint: {endpoint}");
@@ -484,3 +843,42 @@ impl FlightSQLContext {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_set_dialect_changes_how_queries_are_parsed() {
+        let ctx = FlightSQLContext::default().with_dialect(SqlDialect::Postgres);
+        let dialect = |ctx: &FlightSQLContext| {
+            let state = ctx.settings.0.state();
+            state.config_options().sql_parser.dialect.to_string()
+        };
+        assert!(dialect(&ctx).eq_ignore_ascii_case("postgresql"));
+
+        let mut statements = ctx
+            .parse_sql("SET datafusion.sql_parser.dialect = 'mysql'")
+            .unwrap();
+        ctx.track_settings(statements.pop_front().unwrap()).await;
+        assert!(dialect(&ctx).eq_ignore_ascii_case("mysql"));
+        // Other statements aren't executed
+        let mut statements = ctx.parse_sql("CREATE TABLE t AS VALUES (1)").unwrap();
+        ctx.track_settings(statements.pop_front().unwrap()).await;
+        assert!(!ctx.settings.0.table_exist("t").unwrap());
+    }
+
+    #[test]
+    fn test_is_unauthenticated() {
+        let status = |status: tonic::Status| ArrowError::IpcError(format!("{status:?}"));
+        assert!(is_unauthenticated(&status(tonic::Status::unauthenticated(
+            "Session token expired"
+        ))));
+        assert!(!is_unauthenticated(&status(tonic::Status::unavailable(
+            "Server is shutting down"
+        ))));
+        assert!(!is_unauthenticated(&ArrowError::ExternalError(
+            "Unauthenticated".into()
+        )));
+    }
+}
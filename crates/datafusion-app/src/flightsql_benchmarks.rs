@@ -20,7 +20,7 @@ use std::time::Duration;
 use crate::local_benchmarks::is_all_same;
 
 use crate::local_benchmarks::BenchmarkMode;
-use crate::local_benchmarks::DurationsSummary;
+use crate::local_benchmarks::{csv_field, duration_ms, DurationsSummary};
 
 pub struct FlightSQLBenchmarkStats {
     query: String,
@@ -31,6 +31,8 @@ pub struct FlightSQLBenchmarkStats {
     ttfb_durations: Vec<Duration>,
     do_get_durations: Vec<Duration>,
     total_durations: Vec<Duration>,
+    /// How long it took to create the prepared statement, when benchmarking one
+    prepare_duration: Option<Duration>,
 }
 
 impl FlightSQLBenchmarkStats {
@@ -53,9 +55,16 @@ impl FlightSQLBenchmarkStats {
             ttfb_durations,
             do_get_durations,
             total_durations,
+            prepare_duration: None,
         }
     }
 
+    /// Record that the benchmark executed a prepared statement that took `duration` to create
+    pub fn with_prepare_duration(mut self, duration: Duration) -> Self {
+        self.prepare_duration = Some(duration);
+        self
+    }
+
     fn summarize(&self, durations: &[Duration]) -> DurationsSummary {
         if durations.is_empty() {
             return DurationsSummary {
@@ -96,7 +105,7 @@ impl FlightSQLBenchmarkStats {
         let execution_summary = self.summarize(&self.do_get_durations);
         let total_summary = self.summarize(&self.total_durations);
 
-        csv.push_str(&self.query);
+        csv.push_str(&csv_field(&self.query));
         csv.push(',');
         csv.push_str(&self.runs.to_string());
         csv.push(',');
@@ -111,13 +120,36 @@ impl FlightSQLBenchmarkStats {
         csv.push_str(&self.mode.to_string());
         csv
     }
+
+    /// Summary of the benchmark as a JSON object, with durations in milliseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "query": self.query,
+            "runs": self.runs,
+            "mode": self.mode.to_string(),
+            "rows": self.rows,
+            "prepare_ms": self.prepare_duration.map(duration_ms),
+            "get_flight_info": self.summarize(&self.get_flight_info_durations).to_json(),
+            "ttfb": self.summarize(&self.ttfb_durations).to_json(),
+            "do_get": self.summarize(&self.do_get_durations).to_json(),
+            "total": self.summarize(&self.total_durations).to_json(),
+        })
+    }
 }
 
 impl std::fmt::Display for FlightSQLBenchmarkStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f)?;
         writeln!(f, "----------------------------")?;
-        writeln!(f, "Benchmark Stats ({} runs, {})", self.runs, self.mode)?;
+        if self.prepare_duration.is_some() {
+            writeln!(
+                f,
+                "Benchmark Stats ({} runs, {}, prepared)",
+                self.runs, self.mode
+            )?;
+        } else {
+            writeln!(f, "Benchmark Stats ({} runs, {})", self.runs, self.mode)?;
+        }
         writeln!(f, "----------------------------")?;
         writeln!(f, "{}", self.query)?;
         writeln!(f, "----------------------------")?;
@@ -129,6 +161,12 @@ impl std::fmt::Display for FlightSQLBenchmarkStats {
         writeln!(f, "----------------------------")?;
         writeln!(f)?;
 
+        if let Some(prepare_duration) = self.prepare_duration {
+            writeln!(f, "Create Prepared Statement")?;
+            writeln!(f, "{:?}", prepare_duration)?;
+            writeln!(f)?;
+        }
+
         let logical_planning_summary = self.summarize(&self.get_flight_info_durations);
         writeln!(f, "Get Flight Info")?;
         writeln!(f, "{}", logical_planning_summary)?;
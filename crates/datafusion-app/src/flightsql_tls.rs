@@ -0,0 +1,130 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! TLS for connections from the FlightSQL client to `https://` hosts
+
+use std::sync::Arc;
+
+use color_eyre::eyre::{self, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint};
+
+use crate::config::FlightSQLClientTlsConfig;
+
+/// Configure `endpoint` to connect over TLS. The server certificate is verified against the
+/// configured CA, or the system's trusted roots, unless verification is disabled.
+pub fn configure_tls(endpoint: Endpoint, config: &FlightSQLClientTlsConfig) -> Result<Endpoint> {
+    let mut tls = ClientTlsConfig::new();
+    if let Some(domain) = &config.domain {
+        tls = tls.domain_name(domain);
+    }
+    if config.insecure_skip_verify {
+        return Ok(endpoint.tls_config_with_verifier(tls, Arc::new(NoServerVerification))?);
+    }
+    tls = match &config.ca_cert_path {
+        Some(path) => {
+            let pem = std::fs::read(path).map_err(|e| {
+                eyre::eyre!("Error reading CA certificate '{}': {e}", path.display())
+            })?;
+            tls.ca_certificate(Certificate::from_pem(pem))
+        }
+        None => tls.with_native_roots(),
+    };
+    Ok(endpoint.tls_config(tls)?)
+}
+
+/// Accepts every server certificate, for `insecure_skip_verify`
+#[derive(Debug)]
+struct NoServerVerification;
+
+impl ServerCertVerifier for NoServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![
+            SignatureScheme::RSA_PKCS1_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA384,
+            SignatureScheme::RSA_PKCS1_SHA512,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::ECDSA_NISTP521_SHA512,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PSS_SHA384,
+            SignatureScheme::RSA_PSS_SHA512,
+            SignatureScheme::ED25519,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::transport::Channel;
+
+    #[test]
+    fn missing_ca_certificate_is_an_error() {
+        let config = FlightSQLClientTlsConfig {
+            ca_cert_path: Some("/does/not/exist.pem".into()),
+            ..Default::default()
+        };
+        let endpoint = Channel::from_static("https://localhost:50051");
+        let Err(e) = configure_tls(endpoint, &config) else {
+            panic!("Expected missing CA certificate to fail");
+        };
+        assert!(e.to_string().contains("/does/not/exist.pem"));
+    }
+
+    #[test]
+    fn insecure_skip_verify_ignores_ca() {
+        let config = FlightSQLClientTlsConfig {
+            ca_cert_path: Some("/does/not/exist.pem".into()),
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        let endpoint = Channel::from_static("https://localhost:50051");
+        assert!(configure_tls(endpoint, &config).is_ok());
+    }
+}
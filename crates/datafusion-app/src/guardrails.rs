@@ -0,0 +1,327 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Limits on the rows, bytes and duration of each query's results, see [`GuardrailsConfig`]
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use datafusion::{
+    arrow::{array::Array, datatypes::SchemaRef, record_batch::RecordBatch},
+    common::Result,
+    error::DataFusionError,
+    execution::{RecordBatchStream, SendableRecordBatchStream},
+};
+use futures::{Stream, StreamExt};
+use tokio::time::{Instant, Sleep};
+
+use crate::config::GuardrailsConfig;
+
+/// Fails result streams that exceed the limits of a [`GuardrailsConfig`]. The query is stopped,
+/// by dropping its stream, as soon as a limit is exceeded.
+#[derive(Clone, Debug, Default)]
+pub struct Guardrails {
+    max_rows: Option<u64>,
+    max_bytes: Option<u64>,
+    max_duration: Option<Duration>,
+}
+
+impl Guardrails {
+    pub fn new(config: &GuardrailsConfig) -> Self {
+        Self {
+            max_rows: config.max_rows,
+            max_bytes: config.max_bytes,
+            max_duration: config.max_duration_secs.map(Duration::from_secs),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_rows.is_some() || self.max_bytes.is_some() || self.max_duration.is_some()
+    }
+
+    /// Start counting the results of a query against the limits, for queries whose results are
+    /// returned by more than one stream
+    pub fn start_query(&self) -> QueryGuardrails {
+        QueryGuardrails {
+            guardrails: self.clone(),
+            usage: Arc::new(Usage::default()),
+        }
+    }
+
+    /// Return `stream`, all the results of a query, with the limits applied. The duration is
+    /// measured from when this is called.
+    pub fn guard(&self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        self.start_query().guard(stream)
+    }
+
+    /// The error for results of `rows` rows and `bytes` bytes, if they exceed a limit
+    fn check(&self, rows: u64, bytes: u64) -> Option<DataFusionError> {
+        if let Some(max_rows) = self.max_rows.filter(|max| rows > *max) {
+            return Some(DataFusionError::ResourcesExhausted(format!(
+                "Query returned more than {max_rows} rows, the limit set by `guardrails.max_rows`"
+            )));
+        }
+        if let Some(max_bytes) = self.max_bytes.filter(|max| bytes > *max) {
+            return Some(DataFusionError::ResourcesExhausted(format!(
+                "Query returned more than {max_bytes} bytes, the limit set by `guardrails.max_bytes`"
+            )));
+        }
+        None
+    }
+
+    fn timeout_error(&self) -> DataFusionError {
+        let secs = self.max_duration.unwrap_or_default().as_secs();
+        DataFusionError::Execution(format!(
+            "Query ran for longer than {secs} seconds, the limit set by `guardrails.max_duration_secs`"
+        ))
+    }
+}
+
+/// The results of one query counted against the limits of [`Guardrails`]. Clones share the
+/// counts, so the streams of the partitions of a query, which can be read in parallel, are
+/// limited together rather than each being allowed the limits of the whole query.
+#[derive(Clone, Debug)]
+pub struct QueryGuardrails {
+    guardrails: Guardrails,
+    usage: Arc<Usage>,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    rows: AtomicU64,
+    bytes: AtomicU64,
+    /// When the first of the query's streams was guarded, which its duration is measured from
+    started: OnceLock<Instant>,
+}
+
+impl QueryGuardrails {
+    /// Return `stream`, some or all of the results of the query, with the limits applied. The
+    /// duration is measured from when the first stream of the query was guarded.
+    pub fn guard(&self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        if !self.guardrails.is_enabled() {
+            return stream;
+        }
+        let started = *self.usage.started.get_or_init(Instant::now);
+        Box::pin(GuardedStream {
+            schema: stream.schema(),
+            inner: Some(stream),
+            query: self.clone(),
+            deadline: self
+                .guardrails
+                .max_duration
+                .map(|duration| Box::pin(tokio::time::sleep_until(started + duration))),
+        })
+    }
+
+    /// Count `batch` against the limits, returning an error if the query has exceeded one
+    fn add(&self, batch: &RecordBatch) -> Option<DataFusionError> {
+        let num_rows = batch.num_rows() as u64;
+        let num_bytes = batch_size(batch);
+        let rows = self.usage.rows.fetch_add(num_rows, Ordering::Relaxed) + num_rows;
+        let bytes = self.usage.bytes.fetch_add(num_bytes, Ordering::Relaxed) + num_bytes;
+        self.guardrails.check(rows, bytes)
+    }
+}
+
+/// Bytes of the rows of `batch`. Unlike [`RecordBatch::get_array_memory_size`], the parts of
+/// buffers outside of the batch's slice of them, such as the rest of a batch cut short by a
+/// `LIMIT`, aren't counted.
+fn batch_size(batch: &RecordBatch) -> u64 {
+    batch
+        .columns()
+        .iter()
+        .map(|column| {
+            column
+                .to_data()
+                .get_slice_memory_size()
+                .unwrap_or_else(|_| column.get_array_memory_size())
+        })
+        .sum::<usize>() as u64
+}
+
+struct GuardedStream {
+    schema: SchemaRef,
+    /// Dropped, which stops the query, once the stream ends or a limit is exceeded
+    inner: Option<SendableRecordBatchStream>,
+    query: QueryGuardrails,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl GuardedStream {
+    fn fail(&mut self, error: DataFusionError) -> Poll<Option<Result<RecordBatch>>> {
+        self.inner = None;
+        Poll::Ready(Some(Err(error)))
+    }
+}
+
+impl Stream for GuardedStream {
+    type Item = Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(inner) = this.inner.as_mut() else {
+            return Poll::Ready(None);
+        };
+        // Checked before the results so queries that never return a batch still time out
+        if let Some(deadline) = this.deadline.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                let error = this.query.guardrails.timeout_error();
+                return this.fail(error);
+            }
+        }
+        match inner.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(batch))) => match this.query.add(&batch) {
+                Some(error) => this.fail(error),
+                None => Poll::Ready(Some(Ok(batch))),
+            },
+            Poll::Ready(None) => {
+                this.inner = None;
+                Poll::Ready(None)
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl RecordBatchStream for GuardedStream {
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{
+        arrow::{
+            array::Int64Array,
+            datatypes::{DataType, Field, Schema},
+        },
+        physical_plan::stream::RecordBatchStreamAdapter,
+    };
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    fn batches(n: usize) -> SendableRecordBatchStream {
+        let batch = batch(vec![1, 2, 3]);
+        stream(batch, n)
+    }
+
+    fn batch(values: Vec<i64>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(values))]).unwrap()
+    }
+
+    /// A stream that returns `batch` `n` times
+    fn stream(batch: RecordBatch, n: usize) -> SendableRecordBatchStream {
+        let schema = batch.schema();
+        let stream = futures::stream::iter((0..n).map(move |_| Ok(batch.clone())));
+        Box::pin(RecordBatchStreamAdapter::new(schema, stream))
+    }
+
+    async fn collect(
+        guardrails: GuardrailsConfig,
+        stream: SendableRecordBatchStream,
+    ) -> Result<u64> {
+        let batches: Vec<_> = Guardrails::new(&guardrails)
+            .guard(stream)
+            .try_collect()
+            .await?;
+        Ok(batches.iter().map(|b| b.num_rows() as u64).sum())
+    }
+
+    #[tokio::test]
+    async fn test_results_within_limits() {
+        let config = GuardrailsConfig {
+            max_rows: Some(6),
+            max_bytes: Some(1024 * 1024),
+            max_duration_secs: Some(60),
+        };
+        assert_eq!(collect(config, batches(2)).await.unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_max_rows() {
+        let config = GuardrailsConfig {
+            max_rows: Some(5),
+            ..Default::default()
+        };
+        let err = collect(config, batches(2)).await.unwrap_err();
+        assert!(err.to_string().contains("guardrails.max_rows"));
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes() {
+        let config = GuardrailsConfig {
+            max_bytes: Some(1),
+            ..Default::default()
+        };
+        let err = collect(config, batches(1)).await.unwrap_err();
+        assert!(err.to_string().contains("guardrails.max_bytes"));
+    }
+
+    #[tokio::test]
+    async fn test_max_duration() {
+        let config = GuardrailsConfig {
+            max_duration_secs: Some(0),
+            ..Default::default()
+        };
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        // A query that never returns a batch
+        let pending = futures::stream::pending::<Result<RecordBatch>>();
+        let stream = Box::pin(RecordBatchStreamAdapter::new(schema, pending));
+        let err = collect(config, stream).await.unwrap_err();
+        assert!(err.to_string().contains("guardrails.max_duration_secs"));
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes_counts_slices() {
+        // 8 bytes of the 8000 of the batch's buffer
+        let sliced = batch((0..1000).collect()).slice(0, 1);
+        let config = GuardrailsConfig {
+            max_bytes: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(collect(config, stream(sliced, 1)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_partitions_share_limits() {
+        let config = GuardrailsConfig {
+            max_rows: Some(5),
+            ..Default::default()
+        };
+        let query = Guardrails::new(&config).start_query();
+        // Each partition is within the limit but together they aren't
+        let first: Vec<_> = query.guard(batches(1)).try_collect().await.unwrap();
+        assert_eq!(first.len(), 1);
+        let err = query
+            .guard(batches(1))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("guardrails.max_rows"));
+    }
+}
@@ -15,36 +15,76 @@
 // specific language governing permissions and limitations
 // under the License.
 
+pub mod cancel;
 pub mod catalog;
 pub mod config;
+pub mod ddl;
 pub mod executor;
 pub mod extensions;
 #[cfg(feature = "flightsql")]
 pub mod flightsql;
 #[cfg(feature = "flightsql")]
 pub mod flightsql_benchmarks;
+#[cfg(feature = "flightsql")]
+pub mod flightsql_tls;
+pub mod guardrails;
 pub mod local;
 pub mod local_benchmarks;
+pub mod object_store_ddl;
 #[cfg(feature = "observability")]
 pub mod observability;
+pub mod plans;
+pub mod result_cache;
+pub mod rewrite;
+pub mod secrets;
 pub mod sql_utils;
 pub mod stats;
+pub mod system;
 pub mod tables;
+pub mod udf;
 #[cfg(feature = "udfs-wasm")]
 pub mod wasm;
 
 pub use stats::{collect_plan_io_stats, ExecutionStats};
 
-use datafusion::execution::SendableRecordBatchStream;
+use datafusion::{common::ParamValues, execution::SendableRecordBatchStream};
+use rewrite::QueryIdentity;
 
 pub struct ExecOptions {
     pub limit: Option<usize>,
+    /// Number of rows to skip before returning results
+    pub offset: usize,
     pub flightsql: bool,
+    /// Values substituted for the statement's `$1` or `$name` placeholders
+    pub params: Option<ParamValues>,
+    /// Who sent the statement, passed to the session's plan rewriters
+    pub identity: QueryIdentity,
 }
 
 impl ExecOptions {
     pub fn new(limit: Option<usize>, flightsql: bool) -> Self {
-        Self { limit, flightsql }
+        Self {
+            limit,
+            offset: 0,
+            flightsql,
+            params: None,
+            identity: QueryIdentity::default(),
+        }
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_params(mut self, params: ParamValues) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    pub fn with_identity(mut self, identity: QueryIdentity) -> Self {
+        self.identity = identity;
+        self
     }
 }
 
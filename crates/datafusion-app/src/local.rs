@@ -17,29 +17,62 @@
 
 //! [`ExecutionContext`]: DataFusion based execution context for running SQL queries
 
+use std::collections::VecDeque;
+use std::future::Future;
 use std::io::Write;
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use color_eyre::eyre::eyre;
-use datafusion::logical_expr::LogicalPlan;
+use datafusion::logical_expr::{DdlStatement, LogicalPlan};
 use futures::TryFutureExt;
 use log::{debug, error, info};
 
+use crate::cancel::{CancellableQuery, QueryHandle};
 use crate::catalog::create_app_catalog;
+use crate::catalog::refresh::CatalogRefresher;
+use crate::catalog::session::SessionCatalogList;
 use crate::config::ExecutionConfig;
+use crate::ddl::notify_ddl_listeners;
+use crate::guardrails::{Guardrails, QueryGuardrails};
+use crate::object_store_ddl::{parse_create_object_store, CreateObjectStore};
+use crate::plans::{
+    decode_logical_plan, decode_physical_plan, encode_logical_plan, encode_physical_plan,
+    plan_file_name, PlanFormat, PlanKind,
+};
+use crate::result_cache::{changes_state, is_cacheable, ResultCache};
+use crate::rewrite::{rewrite_plan, QueryIdentity};
+use crate::secrets::{refuse_secret_refs, with_table_factory_secrets, Secrets};
+use crate::system::{QueryRecord, SystemTables};
+use crate::udf::create_config_udfs;
 use crate::{ExecOptions, ExecResult};
 use color_eyre::eyre::{self, Result};
+use datafusion::arrow::datatypes::Schema;
 use datafusion::common::Result as DFResult;
+use datafusion::config::ConfigOptions;
+use datafusion::error::DataFusionError;
+use datafusion::execution::memory_pool::{GreedyMemoryPool, TrackConsumersPool};
+use datafusion::execution::runtime_env::RuntimeEnvBuilder;
+use datafusion::execution::session_state::SessionStateBuilder;
 use datafusion::execution::{SendableRecordBatchStream, SessionState};
-use datafusion::physical_plan::{execute_stream, ExecutionPlan};
+use datafusion::physical_plan::{execute_stream, EmptyRecordBatchStream, ExecutionPlan};
 use datafusion::prelude::*;
 use datafusion::sql::parser::{DFParser, Statement};
+use datafusion::sql::planner::object_name_to_table_reference;
+use datafusion::sql::sqlparser::ast::Statement as SQLStatement;
+use datafusion::sql::sqlparser::dialect::{dialect_from_str, Dialect};
+use datafusion::sql::sqlparser::tokenizer::{Location, Token, Tokenizer};
+use datafusion::sql::TableReference;
 use tokio_stream::StreamExt;
+use tracing::{info_span, Instrument};
 
-use super::executor::dedicated::DedicatedExecutor;
-use super::local_benchmarks::{BenchmarkMode, BenchmarkProgressReporter, LocalBenchmarkStats};
-use super::stats::{ExecutionDurationStats, ExecutionStats};
+use super::executor::dedicated::{DedicatedExecutor, JobError};
+use super::local_benchmarks::{
+    BenchmarkMode, BenchmarkProgressReporter, LocalBenchmarkStats, PeakMemoryPool,
+};
+use super::stats::{collect_plan_spill_stats, ExecutionDurationStats, ExecutionStats};
 #[cfg(feature = "udfs-wasm")]
 use super::wasm::create_wasm_udfs;
 #[cfg(feature = "observability")]
@@ -67,11 +100,34 @@ pub struct ExecutionContext {
     ddl_path: Option<PathBuf>,
     /// Dedicated executor for running CPU intensive work
     executor: Option<DedicatedExecutor>,
+    /// Results of recent queries, shared by forked sessions
+    result_cache: Option<Arc<ResultCache>>,
+    /// Tables of the `system` schema, shared by forked sessions
+    system_tables: SystemTables,
+    /// Limits on the results of each query
+    guardrails: Guardrails,
     /// Observability handlers
     #[cfg(feature = "observability")]
     observability: ObservabilityContext,
 }
 
+/// Snapshot of the settings of an [`ExecutionContext`]'s session
+#[derive(Clone, Debug)]
+pub struct SessionSettings {
+    options: ConfigOptions,
+}
+
+impl SessionSettings {
+    /// The value of the setting `key`, such as `datafusion.execution.batch_size`
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.options
+            .entries()
+            .into_iter()
+            .find(|entry| entry.key == key)
+            .and_then(|entry| entry.value)
+    }
+}
+
 impl std::fmt::Debug for ExecutionContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ExecutionContext").finish()
@@ -88,15 +144,20 @@ impl ExecutionContext {
     ) -> Result<Self> {
         let mut executor = None;
         if config.dedicated_executor_enabled {
-            // Ideally we would only use `enable_time` but we are still doing
-            // some network requests as part of planning / execution which require network
-            // functionality.
-            let runtime_builder = tokio::runtime::Builder::new_multi_thread();
+            // The executor has no IO driver, network requests run on the IO runtime instead,
+            // see `IoObjectStoreRegistry`
+            let mut runtime_builder = tokio::runtime::Builder::new_multi_thread();
+            runtime_builder.enable_time();
             let dedicated_executor =
                 DedicatedExecutor::new("cpu_runtime", config.clone(), runtime_builder);
             executor = Some(dedicated_executor)
         }
 
+        let result_cache = config
+            .result_cache
+            .enabled
+            .then(|| Arc::new(ResultCache::new(&config.result_cache)));
+
         let mut session_ctx = SessionContext::new_with_state(session_state);
         session_ctx = session_ctx.enable_url_table();
 
@@ -111,6 +172,10 @@ impl ExecutionContext {
             }
         }
 
+        for udf in create_config_udfs(&config.udf)? {
+            session_ctx.register_udf(udf);
+        }
+
         session_ctx.register_udtf(
             "parquet_metadata",
             Arc::new(datafusion_functions_parquet::ParquetMetadataFunc {}),
@@ -166,6 +231,9 @@ impl ExecutionContext {
         let catalog = create_app_catalog(config, app_name, app_version)?;
         session_ctx.register_catalog(&config.catalog.name, catalog);
 
+        let system_tables = SystemTables::from_config(config);
+        system_tables.register(&session_ctx)?;
+
         let ctx = {
             #[cfg(feature = "observability")]
             {
@@ -190,6 +258,9 @@ impl ExecutionContext {
                     session_ctx,
                     ddl_path: config.ddl_path.as_ref().map(PathBuf::from),
                     executor,
+                    result_cache,
+                    system_tables,
+                    guardrails: Guardrails::new(&config.guardrails),
                     observability,
                 }
             }
@@ -200,6 +271,9 @@ impl ExecutionContext {
                     session_ctx,
                     ddl_path: config.ddl_path.as_ref().map(PathBuf::from),
                     executor,
+                    result_cache,
+                    system_tables,
+                    guardrails: Guardrails::new(&config.guardrails),
                 }
             }
         };
@@ -215,6 +289,8 @@ impl ExecutionContext {
         // Okay to `unwrap` in a test
         let app_catalog = create_app_catalog(&exec_cfg, "test", ".0.1.0").unwrap();
         session_ctx.register_catalog("test", app_catalog);
+        let system_tables = SystemTables::from_config(&exec_cfg);
+        system_tables.register(&session_ctx).unwrap();
         #[cfg(feature = "observability")]
         let observability =
             ObservabilityContext::try_new(ObservabilityConfig::default(), "test").unwrap();
@@ -223,6 +299,9 @@ impl ExecutionContext {
             session_ctx,
             ddl_path: None,
             executor: None,
+            result_cache: None,
+            system_tables,
+            guardrails: Guardrails::default(),
             #[cfg(feature = "observability")]
             observability,
         }
@@ -241,6 +320,93 @@ impl ExecutionContext {
         &self.session_ctx
     }
 
+    /// Return a copy of this context with its own copy of the session state, so that
+    /// configuration changed with `SET` only applies to the copy. Catalogs, and so the tables
+    /// registered in them, are shared with this context.
+    pub fn fork_session(&self) -> Self {
+        let mut forked = self.clone();
+        forked.session_ctx = SessionContext::new_with_state(self.session_ctx.state());
+        forked
+    }
+
+    /// Return a copy of this context, like [`Self::fork_session`], that also keeps the views
+    /// created in it to itself. Tables are still registered in the shared catalogs.
+    pub fn fork_session_with_own_views(&self) -> Self {
+        let state = self.session_ctx.state();
+        let catalog_list = Arc::new(SessionCatalogList::new(Arc::clone(state.catalog_list())));
+        let state = SessionStateBuilder::new_from_existing(state)
+            .with_catalog_list(catalog_list)
+            .build();
+        let mut forked = self.clone();
+        forked.session_ctx = SessionContext::new_with_state(state);
+        forked
+    }
+
+    /// Return a copy of this context, like [`Self::fork_session`], whose queries share a memory
+    /// pool of `limit` bytes of their own. Queries that need more memory than the pool has left,
+    /// and can't spill, fail with a resources exhausted error naming the largest consumers.
+    pub fn fork_with_memory_limit(&self, limit: usize) -> Result<Self> {
+        let state = self.session_ctx.state();
+        let pool = TrackConsumersPool::new(
+            GreedyMemoryPool::new(limit),
+            NonZeroUsize::new(5).expect("non-zero"),
+        );
+        let runtime_env = RuntimeEnvBuilder::from_runtime_env(state.runtime_env())
+            .with_memory_pool(Arc::new(pool))
+            .build_arc()?;
+        let state = SessionStateBuilder::new_from_existing(state)
+            .with_runtime_env(runtime_env)
+            .build();
+        let mut forked = self.clone();
+        forked.session_ctx = SessionContext::new_with_state(state);
+        Ok(forked)
+    }
+
+    /// Return a snapshot of the session's settings, including those changed with `SET`, that can
+    /// be restored with [`Self::restore_session_settings`]
+    pub fn session_settings(&self) -> SessionSettings {
+        SessionSettings {
+            options: ConfigOptions::clone(self.session_ctx.state().config_options()),
+        }
+    }
+
+    /// Replace the session's settings with `settings`, undoing the `SET` statements executed since
+    /// they were taken. Runtime settings, such as `datafusion.runtime.memory_limit`, aren't
+    /// included.
+    pub fn restore_session_settings(&self, settings: &SessionSettings) {
+        let state = self.session_ctx.state_ref();
+        let mut state = state.write();
+        *state.config_mut().options_mut() = settings.options.clone();
+    }
+
+    /// Change the setting `key` of the session to `value`, like `SET key = value`
+    pub fn set_session_setting(&self, key: &str, value: &str) -> DFResult<()> {
+        let state = self.session_ctx.state_ref();
+        let mut state = state.write();
+        state.config_mut().options_mut().set(key, value)
+    }
+
+    /// Return the cache of query results, if it's enabled
+    pub fn result_cache(&self) -> Option<&Arc<ResultCache>> {
+        self.result_cache.as_ref()
+    }
+
+    /// Return the tables of the `system` schema
+    pub fn system_tables(&self) -> &SystemTables {
+        &self.system_tables
+    }
+
+    /// Start refreshing the listing tables of this context, and its forked sessions, on the
+    /// configured `catalog.refresh_interval_secs`. Refreshing stops when the returned refresher
+    /// is dropped.
+    pub fn start_catalog_refresh(&self) -> Option<CatalogRefresher> {
+        let secs = self.config.catalog.refresh_interval_secs?;
+        Some(CatalogRefresher::start(
+            self.session_ctx.runtime_env(),
+            Duration::from_secs(secs.max(1)),
+        ))
+    }
+
     /// Return the inner [`DedicatedExecutor`]
     pub fn executor(&self) -> &Option<DedicatedExecutor> {
         &self.executor
@@ -252,36 +418,206 @@ impl ExecutionContext {
         &self.observability
     }
 
+    /// Runs `task` on the [`DedicatedExecutor`] if it is available, otherwise on the current
+    /// runtime.  Planning and executing DataFusion plans is CPU bound so it should be run here to
+    /// keep the IO runtime responsive.
+    pub async fn spawn_cpu<T>(&self, task: T) -> Result<T::Output, JobError>
+    where
+        T: Future + Send + 'static,
+        T::Output: Send + 'static,
+    {
+        if let Some(executor) = &self.executor {
+            executor.spawn(task.in_current_span()).await
+        } else {
+            Ok(task.await)
+        }
+    }
+
+    /// Polls `stream` on the [`DedicatedExecutor`] if it is available.  Streams are lazy, so
+    /// most of the work of a plan happens when it's polled rather than when it's created.
+    pub fn run_cpu_stream(&self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        if let Some(executor) = &self.executor {
+            executor.run_cpu_sendable_record_batch_stream(stream)
+        } else {
+            stream
+        }
+    }
+
+    /// Limits on the results of queries
+    pub fn guardrails(&self) -> &Guardrails {
+        &self.guardrails
+    }
+
+    /// Poll `stream`, the results of a query, with [`Self::run_cpu_stream`] and fail it if it
+    /// exceeds the configured guardrails
+    fn guard_results(&self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        self.guardrails.guard(self.run_cpu_stream(stream))
+    }
+
+    /// Parse `sql` with the session's SQL dialect, the `datafusion.sql_parser.dialect` setting,
+    /// which is set from the `dialect` config and can be changed with `SET`
+    pub fn parse_sql(&self, sql: &str) -> DFResult<VecDeque<Statement>> {
+        DFParser::parse_sql_with_dialect(sql, self.dialect()?.as_ref())
+    }
+
+    /// The session's SQL dialect, see [`Self::parse_sql`]
+    pub fn dialect(&self) -> DFResult<Box<dyn Dialect>> {
+        let name = self
+            .session_ctx
+            .state()
+            .config_options()
+            .sql_parser
+            .dialect
+            .to_string();
+        dialect_from_str(&name)
+            .ok_or_else(|| DataFusionError::Plan(format!("Unsupported SQL dialect: {name}")))
+    }
+
     /// Convert the statement to a `LogicalPlan`.  Uses the [`DedicatedExecutor`] if it is available.
     pub async fn statement_to_logical_plan(&self, statement: Statement) -> Result<LogicalPlan> {
         let ctx = self.session_ctx.clone();
         let task = async move { ctx.state().statement_to_plan(statement).await };
-        if let Some(executor) = &self.executor {
-            let job = executor.spawn(task).map_err(|e| eyre::eyre!(e));
-            let job_res = job.await?;
-            job_res.map_err(|e| eyre!(e))
-        } else {
-            task.await.map_err(|e| eyre!(e))
-        }
+        self.spawn_cpu(task)
+            .await
+            .map_err(|e| eyre!(e))?
+            .map_err(|e| eyre!(e))
+    }
+
+    /// Apply the plan rewriters registered with the session to the plan of a statement sent by
+    /// `identity`
+    pub fn rewrite_plan(
+        &self,
+        logical_plan: LogicalPlan,
+        identity: &QueryIdentity,
+    ) -> DFResult<LogicalPlan> {
+        rewrite_plan(&self.session_ctx.state(), logical_plan, identity)
     }
 
     /// Executes the provided `LogicalPlan` returning a `SendableRecordBatchStream`.  Uses the [`DedicatedExecutor`] if it is available.  Useful on server implementations when planning and execution are done in separate steps and you may be storing the logical plan with something like a request_id.
     pub async fn execute_logical_plan(
         &self,
         logical_plan: LogicalPlan,
+    ) -> Result<SendableRecordBatchStream> {
+        self.execute_logical_plan_as(logical_plan, None, &QueryIdentity::default())
+            .await
+    }
+
+    /// Like [`Self::execute_logical_plan`] for a plan planned from `sql`, sent by `identity`,
+    /// which is what the query is recorded as in the system tables. Plans that weren't planned
+    /// from SQL, such as the metadata queries of servers, are recorded as their plan.
+    pub async fn execute_logical_plan_as(
+        &self,
+        logical_plan: LogicalPlan,
+        sql: Option<&str>,
+        identity: &QueryIdentity,
     ) -> Result<SendableRecordBatchStream> {
         let ctx = self.session_ctx.clone();
+        let changes_state = changes_state(&logical_plan);
+        let sql = match sql {
+            Some(sql) => sql.to_string(),
+            None => logical_plan.display_indent().to_string(),
+        };
+        let mut query = self.system_tables.start_query(sql, identity);
         let task = async move {
-            let df = ctx.execute_logical_plan(logical_plan).await?;
+            let df = execute_plan(&ctx, logical_plan).await?;
             df.execute_stream().await
         };
-        if let Some(executor) = &self.executor {
-            let job = executor.spawn(task).map_err(|e| eyre!(e));
-            let job_res = job.await?;
-            job_res.map_err(|e| eyre!(e))
-        } else {
-            task.await.map_err(|e| eyre!(e))
+        let stream = match self
+            .spawn_cpu(task)
+            .await
+            .map_err(job_error)
+            .and_then(|r| r)
+        {
+            Ok(stream) => stream,
+            Err(e) => {
+                query.fail(&e);
+                return Err(eyre!(e));
+            }
+        };
+        let stream = self.invalidate_cache_after(changes_state, stream);
+        Ok(query.track(self.guard_results(stream)))
+    }
+
+    /// Creates the physical plan for the provided `LogicalPlan`.  Uses the [`DedicatedExecutor`] if it is available.  Useful on server implementations that execute the partitions of a plan separately with [`Self::execute_partition`].
+    pub async fn logical_plan_to_physical_plan(
+        &self,
+        logical_plan: LogicalPlan,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let ctx = self.session_ctx.clone();
+        let task = async move { ctx.state().create_physical_plan(&logical_plan).await };
+        self.spawn_cpu(task)
+            .await
+            .map_err(|e| eyre!(e))?
+            .map_err(|e| eyre!(e))
+    }
+
+    /// Write the logical and physical plans of `statement` to `dir`, named with
+    /// [`plan_file_name`], returning the paths they were written to. Statements that are executed
+    /// while planning, such as DDL, only have their logical plan written.
+    pub async fn save_statement_plans(
+        &self,
+        statement: Statement,
+        dir: &Path,
+        name: &str,
+        format: PlanFormat,
+    ) -> Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir)?;
+        let logical_plan = self.statement_to_logical_plan(statement).await?;
+        let mut paths = Vec::new();
+        let path = dir.join(plan_file_name(name, PlanKind::Logical, format));
+        std::fs::write(&path, encode_logical_plan(&logical_plan, format)?)?;
+        paths.push(path);
+        if !matches!(
+            logical_plan,
+            LogicalPlan::Ddl(_) | LogicalPlan::Statement(_)
+        ) {
+            let physical_plan = self.logical_plan_to_physical_plan(logical_plan).await?;
+            let path = dir.join(plan_file_name(name, PlanKind::Physical, format));
+            std::fs::write(&path, encode_physical_plan(physical_plan, format)?)?;
+            paths.push(path);
         }
+        Ok(paths)
+    }
+
+    /// Execute a plan saved by [`Self::save_statement_plans`]. Its kind and format are taken from
+    /// its file name, see [`PlanKind::from_path`] and [`PlanFormat::from_path`].
+    pub async fn execute_saved_plan(&self, path: &Path) -> Result<SendableRecordBatchStream> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| eyre!("Error reading plan from {}: {e}", path.display()))?;
+        let format = PlanFormat::from_path(path);
+        let task_ctx = self.session_ctx.task_ctx();
+        match PlanKind::from_path(path)? {
+            PlanKind::Logical => {
+                let plan = decode_logical_plan(&bytes, format, &task_ctx)?;
+                self.execute_logical_plan(plan).await
+            }
+            PlanKind::Physical => {
+                let plan = decode_physical_plan(&bytes, format, &task_ctx)?;
+                let task = async move { execute_stream(plan, task_ctx) };
+                let stream = self.spawn_cpu(task).await.map_err(|e| eyre!(e))??;
+                Ok(self.guard_results(stream))
+            }
+        }
+    }
+
+    /// Executes a single output partition of the provided `ExecutionPlan` returning a `SendableRecordBatchStream`.  Uses the [`DedicatedExecutor`] if it is available.
+    ///
+    /// The results are counted against `guardrails`, from [`Self::guardrails`], which should be
+    /// shared by the partitions of the plan so they're limited together.
+    pub async fn execute_partition(
+        &self,
+        plan: Arc<dyn ExecutionPlan>,
+        partition: usize,
+        guardrails: &QueryGuardrails,
+    ) -> Result<SendableRecordBatchStream> {
+        let task_ctx = self.session_ctx.task_ctx();
+        let task = async move { plan.execute(partition, task_ctx) };
+        let stream = self
+            .spawn_cpu(task)
+            .await
+            .map_err(|e| eyre!(e))?
+            .map_err(|e| eyre!(e))?;
+        Ok(guardrails.guard(self.run_cpu_stream(stream)))
     }
 
     /// Executes the specified sql string, driving it to completion but discarding any results
@@ -298,156 +634,411 @@ impl ExecutionContext {
     }
 
     /// Create a physical plan from the specified SQL string.  This is useful if you want to store
-    /// the plan and collect metrics from it.
+    /// the plan and collect metrics from it.  Uses the [`DedicatedExecutor`] if it is available.
     pub async fn create_physical_plan(
         &self,
         sql: &str,
     ) -> datafusion::error::Result<Arc<dyn ExecutionPlan>> {
-        let df = self.session_ctx.sql(sql).await?;
-        df.create_physical_plan().await
+        let ctx = self.session_ctx.clone();
+        let sql = sql.to_string();
+        let task = async move { ctx.sql(&sql).await?.create_physical_plan().await };
+        self.spawn_cpu(task).await.map_err(job_error)?
     }
 
     /// Executes the specified sql string, returning the resulting
-    /// [`SendableRecordBatchStream`] of results
+    /// [`SendableRecordBatchStream`] of results.  Uses the [`DedicatedExecutor`] if it is
+    /// available.
     pub async fn execute_sql(
         &self,
         sql: &str,
     ) -> datafusion::error::Result<SendableRecordBatchStream> {
-        self.session_ctx.sql(sql).await?.execute_stream().await
+        if let Some(result) = self.try_execute_object_store_sql(sql, &QueryIdentity::default()) {
+            return result;
+        }
+        let ctx = self.session_ctx.clone();
+        let sql = sql.to_string();
+        let query = self
+            .system_tables
+            .start_query(sql.clone(), &QueryIdentity::default());
+        let task = async move {
+            let plan = ctx.state().create_logical_plan(&sql).await?;
+            let changes_state = changes_state(&plan);
+            let stream = execute_plan(&ctx, plan).await?.execute_stream().await?;
+            Ok::<_, DataFusionError>((changes_state, stream))
+        };
+        self.track_query(query, self.spawn_cpu(task).await)
     }
 
     /// Executes the a pre-parsed DataFusion [`Statement`], returning the
-    /// resulting [`SendableRecordBatchStream`] of results
+    /// resulting [`SendableRecordBatchStream`] of results.  Uses the [`DedicatedExecutor`] if it
+    /// is available.
     pub async fn execute_statement(
         &self,
         statement: Statement,
     ) -> datafusion::error::Result<SendableRecordBatchStream> {
-        let plan = self
-            .session_ctx
-            .state()
-            .statement_to_plan(statement)
-            .await?;
-        self.session_ctx
-            .execute_logical_plan(plan)
-            .await?
-            .execute_stream()
-            .await
+        let ctx = self.session_ctx.clone();
+        let query = self
+            .system_tables
+            .start_query(statement.to_string(), &QueryIdentity::default());
+        let task = async move {
+            let plan = ctx.state().statement_to_plan(statement).await?;
+            let changes_state = changes_state(&plan);
+            let stream = execute_plan(&ctx, plan).await?.execute_stream().await?;
+            Ok::<_, DataFusionError>((changes_state, stream))
+        };
+        self.track_query(query, self.spawn_cpu(task).await)
+    }
+
+    /// A session sharing the catalogs and `RuntimeEnv` of the app's session whose `CREATE
+    /// EXTERNAL TABLE` statements can refer to secrets, which those of queries can only do when
+    /// `query_secrets` is set. The DDL, and the catalog restored at startup, are executed with it.
+    pub fn ddl_session_ctx(&self) -> SessionContext {
+        let mut state = self.session_ctx.state();
+        with_table_factory_secrets(&mut state, Some(&Secrets::from_config(&self.config)));
+        SessionContext::new_with_state(state)
     }
 
-    /// Load DDL from configured DDL path for execution (so strips out comments and empty lines)
+    /// Register the object store created by a `CREATE EXTERNAL OBJECT STORE` statement of the
+    /// DDL with the session's `RuntimeEnv`, which is shared with forked sessions, and
+    /// `system.object_stores`. A store already registered for its location is replaced.
+    pub fn create_object_store(&self, statement: &CreateObjectStore) -> DFResult<()> {
+        self.register_object_store(statement, &Secrets::from_config(&self.config), true)
+    }
+
+    fn register_object_store(
+        &self,
+        statement: &CreateObjectStore,
+        secrets: &Secrets,
+        replace: bool,
+    ) -> DFResult<()> {
+        statement.register(&self.session_ctx.runtime_env(), secrets, replace)?;
+        self.system_tables
+            .add_object_store(&statement.location, &statement.kind);
+        info!(
+            "Registered {} object store at {}",
+            statement.kind, statement.location
+        );
+        Ok(())
+    }
+
+    /// Execute `sql`, sent by `identity`, if it's a `CREATE EXTERNAL OBJECT STORE` statement,
+    /// which DataFusion can't parse, returning its results, which are empty. Unlike the DDL's,
+    /// these statements are refused unless `object_store_ddl` allows them, and can't replace a
+    /// store that's already registered.
+    fn try_execute_object_store_sql(
+        &self,
+        sql: &str,
+        identity: &QueryIdentity,
+    ) -> Option<DFResult<SendableRecordBatchStream>> {
+        let statement = parse_create_object_store(sql)?;
+        let mut query = self.system_tables.start_query(sql, identity);
+        let created = statement.and_then(|statement| {
+            if !self.config.object_store_ddl.unwrap_or(true) {
+                return Err(DataFusionError::Plan(
+                    "CREATE EXTERNAL OBJECT STORE can only be used in the DDL file unless `object_store_ddl` is set".to_string(),
+                ));
+            }
+            let secrets = if self.config.query_secrets {
+                Secrets::from_config(&self.config)
+            } else {
+                refuse_secret_refs(&statement.options)?;
+                Secrets::default()
+            };
+            self.register_object_store(&statement, &secrets, false)
+        });
+        match created {
+            Ok(()) => {
+                let stream = EmptyRecordBatchStream::new(Arc::new(Schema::empty()));
+                Some(Ok(query.track(Box::pin(stream))))
+            }
+            Err(e) => {
+                query.fail(&e);
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Return the results of `query`, which were planned and executed on the dedicated executor,
+    /// updating its record in the `system` tables as they're consumed
+    fn track_query(
+        &self,
+        mut query: QueryRecord,
+        result: Result<DFResult<(bool, SendableRecordBatchStream)>, JobError>,
+    ) -> DFResult<SendableRecordBatchStream> {
+        let (changes_state, stream) = match result.map_err(job_error).and_then(|r| r) {
+            Ok(result) => result,
+            Err(e) => {
+                query.fail(&e);
+                return Err(e);
+            }
+        };
+        let stream = self.invalidate_cache_after(changes_state, stream);
+        Ok(query.track(self.guard_results(stream)))
+    }
+
+    /// Return `stream`, the results of a plan, invalidating the result cache once it has been
+    /// consumed if the plan changes the catalog or session
+    fn invalidate_cache_after(
+        &self,
+        changes_state: bool,
+        stream: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        match &self.result_cache {
+            Some(cache) if changes_state => cache.invalidate_after(stream),
+            _ => stream,
+        }
+    }
+
+    /// The configured DDL path, which is either a file or a directory of `.sql` files
+    pub fn ddl_path(&self) -> Option<&Path> {
+        self.ddl_path.as_deref()
+    }
+
+    /// Load DDL from configured DDL path for execution (so strips out comments and empty lines).
+    /// If the path is a directory the contents of its `.sql` files are joined in the order they
+    /// are executed in.
     pub fn load_ddl(&self) -> Option<String> {
         info!("Loading DDL from: {:?}", &self.ddl_path);
-        if let Some(ddl_path) = &self.ddl_path {
-            if ddl_path.exists() {
-                let maybe_ddl = std::fs::read_to_string(ddl_path);
-                match maybe_ddl {
-                    Ok(ddl) => Some(ddl),
-                    Err(err) => {
-                        error!("Error reading DDL: {:?}", err);
-                        None
+        let files = self.load_ddl_files()?;
+        let mut ddl = String::new();
+        for (path, contents) in files {
+            match contents {
+                Ok(contents) => {
+                    // Terminate the last statement of the previous file so it isn't joined with
+                    // the first statement of this one
+                    let previous = ddl.trim_end();
+                    if !previous.is_empty() && !previous.ends_with(';') {
+                        ddl.push_str(";\n");
+                    } else if !ddl.is_empty() && !ddl.ends_with('\n') {
+                        ddl.push('\n');
                     }
+                    ddl.push_str(&contents);
+                }
+                Err(err) => {
+                    error!("Error reading DDL from {path:?}: {:?}", err);
                 }
-            } else {
-                info!("DDL path ({:?}) does not exist", ddl_path);
-                None
             }
-        } else {
+        }
+        Some(ddl)
+    }
+
+    /// Paths and contents of the DDL files to execute, or `None` if there is no DDL
+    fn load_ddl_files(&self) -> Option<Vec<(PathBuf, std::io::Result<String>)>> {
+        let Some(ddl_path) = &self.ddl_path else {
             info!("No DDL file configured");
-            None
+            return None;
+        };
+        if !ddl_path.exists() {
+            info!("DDL path ({:?}) does not exist", ddl_path);
+            return None;
+        }
+        match ddl_files(ddl_path) {
+            Ok(files) => Some(
+                files
+                    .into_iter()
+                    .map(|path| {
+                        let contents = std::fs::read_to_string(&path);
+                        (path, contents)
+                    })
+                    .collect(),
+            ),
+            Err(err) => {
+                error!("Error listing DDL files in {:?}: {:?}", ddl_path, err);
+                None
+            }
         }
     }
 
-    /// Save DDL to configured DDL path
-    pub fn save_ddl(&self, ddl: String) {
-        info!("Loading DDL from: {:?}", &self.ddl_path);
-        if let Some(ddl_path) = &self.ddl_path {
-            match std::fs::File::create(ddl_path) {
-                Ok(mut f) => match f.write_all(ddl.as_bytes()) {
+    /// Save DDL to configured DDL path. DDL directories aren't written to, their files are
+    /// edited directly.
+    pub fn save_ddl(&self, ddl: String) -> Result<()> {
+        let Some(ddl_path) = &self.ddl_path else {
+            return Err(eyre!("No DDL file configured"));
+        };
+        if ddl_path.is_dir() {
+            return Err(eyre!(
+                "DDL path ({}) is a directory, edit its files to change the DDL",
+                ddl_path.display()
+            ));
+        }
+        let mut f = std::fs::File::create(ddl_path).map_err(|e| {
+            eyre!(
+                "Error creating or opening DDL file {}: {e}",
+                ddl_path.display()
+            )
+        })?;
+        f.write_all(ddl.as_bytes())
+            .map_err(|e| eyre!("Error writing DDL file {}: {e}", ddl_path.display()))?;
+        info!("Saved DDL file");
+        Ok(())
+    }
+
+    /// Save `statement`, which creates a table, to the configured DDL file. Statements in the
+    /// file that created a table with the same name are replaced, so saving a table again
+    /// doesn't duplicate it.
+    pub fn save_table_ddl(&self, statement: &str) -> Result<()> {
+        let ddl = self.load_ddl().unwrap_or_default();
+        let ddl = replace_table_ddl(&ddl, statement, self.dialect()?.as_ref())?;
+        self.save_ddl(ddl)
+    }
+
+    /// Execute DDL statements sequentially, returning the errors of the statements that failed.
+    /// The statements of a DDL directory are executed file by file, and errors name the file of
+    /// the statement that failed.
+    pub async fn execute_ddl(&self) -> Vec<DataFusionError> {
+        self.execute_ddl_files(false).await
+    }
+
+    /// Execute the DDL again, like [`Self::execute_ddl`], after it changed. Tables and views
+    /// whose definition is unchanged are kept, and those whose definition changed are dropped
+    /// and created again.
+    pub async fn reload_ddl(&self) -> Vec<DataFusionError> {
+        self.execute_ddl_files(true).await
+    }
+
+    async fn execute_ddl_files(&self, reload: bool) -> Vec<DataFusionError> {
+        let mut errors = Vec::new();
+        let Some(files) = self.load_ddl_files() else {
+            info!("No DDL to execute");
+            return errors;
+        };
+        let is_dir = self.ddl_path.as_ref().is_some_and(|path| path.is_dir());
+        for (path, contents) in files {
+            let in_file = |e: DataFusionError| {
+                if is_dir {
+                    e.context(format!("DDL file {}", path.display()))
+                } else {
+                    e
+                }
+            };
+            let ddl = match contents {
+                Ok(ddl) => ddl,
+                Err(e) => {
+                    error!("Error reading DDL from {path:?}: {e}");
+                    errors.push(in_file(DataFusionError::IoError(e)));
+                    continue;
+                }
+            };
+            debug!("Executing DDL from: {:?}", path);
+            let ddl_statements = ddl.split(';').collect::<Vec<&str>>();
+            for statement in ddl_statements {
+                if statement.trim().is_empty() {
+                    continue;
+                }
+                if statement.trim().starts_with("--") {
+                    continue;
+                }
+
+                debug!("Executing DDL statement: {:?}", statement);
+                let result = match parse_create_object_store(statement) {
+                    Some(create) => create.and_then(|create| self.create_object_store(&create)),
+                    None if reload => self.reload_ddl_statement(statement).await,
+                    None => self.execute_ddl_statement(statement).await,
+                };
+                match result {
                     Ok(_) => {
-                        info!("Saved DDL file")
+                        info!("DDL statement executed");
                     }
                     Err(e) => {
-                        error!("Error writing DDL file: {e}")
+                        error!("Error executing DDL statement from {path:?}: {e}");
+                        errors.push(in_file(e));
                     }
-                },
-                Err(e) => {
-                    error!("Error creating or opening DDL file: {e}")
                 }
             }
-        } else {
-            info!("No DDL file configured");
         }
+        errors
     }
 
-    /// Execute DDL statements sequentially
-    pub async fn execute_ddl(&self) {
-        match self.load_ddl() {
-            Some(ddl) => {
-                let ddl_statements = ddl.split(';').collect::<Vec<&str>>();
-                for statement in ddl_statements {
-                    if statement.trim().is_empty() {
-                        continue;
-                    }
-                    if statement.trim().starts_with("--") {
-                        continue;
-                    }
+    /// Execute a statement from the DDL. Statements from the DDL aren't passed to the session's
+    /// DDL listeners as the DDL is already where they are kept.
+    async fn execute_ddl_statement(&self, sql: &str) -> DFResult<()> {
+        let ctx = self.session_ctx.clone();
+        let ddl_ctx = self.ddl_session_ctx();
+        let sql = sql.to_string();
+        let task = async move {
+            let plan = ctx.state().create_logical_plan(&sql).await?;
+            // Only external tables, which can refer to secrets, are created with the DDL's
+            // session, as the functions and settings it creates wouldn't be the app's
+            let ctx = match &plan {
+                LogicalPlan::Ddl(DdlStatement::CreateExternalTable(_)) => ddl_ctx,
+                _ => ctx,
+            };
+            ctx.execute_logical_plan(plan).await?.collect().await
+        };
+        self.spawn_cpu(task)
+            .await
+            .map_err(job_error)
+            .and_then(|r| r)
+            .map(|_| ())
+    }
 
-                    debug!("Executing DDL statement: {:?}", statement);
-                    match self.execute_sql_and_discard_results(statement).await {
-                        Ok(_) => {
-                            info!("DDL statement executed");
-                        }
-                        Err(e) => {
-                            error!("Error executing DDL statement: {e}");
-                        }
-                    }
-                }
+    /// Execute a statement from the DDL that may have been executed before. A table or view it
+    /// creates that is registered with the same definition is kept, and one registered with
+    /// another definition is dropped first so that it's created with the new one.
+    async fn reload_ddl_statement(&self, sql: &str) -> DFResult<()> {
+        let plan = self.session_ctx.state().create_logical_plan(sql).await?;
+        let (name, definition) = match &plan {
+            LogicalPlan::Ddl(DdlStatement::CreateExternalTable(create)) => {
+                (create.name.clone(), create.definition.clone())
+            }
+            LogicalPlan::Ddl(DdlStatement::CreateView(create)) => {
+                (create.name.clone(), create.definition.clone())
             }
-            None => {
-                info!("No DDL to execute");
+            _ => return self.execute_ddl_statement(sql).await,
+        };
+        if let Ok(existing) = self.session_ctx.table_provider(name.clone()).await {
+            if existing.get_table_definition() == definition.as_deref() {
+                debug!("Definition of {name} is unchanged");
+                return Ok(());
             }
+            info!("Definition of {name} changed, registering it again");
+            self.session_ctx.deregister_table(name)?;
         }
+        self.execute_ddl_statement(sql).await
     }
 
     /// Benchmark the provided query.  Currently, only a single statement can be benchmarked
-    async fn benchmark_single_iteration(
-        &self,
-        statement: Statement,
-    ) -> Result<(
-        usize,
-        std::time::Duration,
-        std::time::Duration,
-        std::time::Duration,
-        std::time::Duration,
-    )> {
-        let start = std::time::Instant::now();
-        let logical_plan = self
-            .session_ctx()
-            .state()
-            .statement_to_plan(statement)
-            .await?;
-        let logical_planning_duration = start.elapsed();
-        let physical_plan = self
-            .session_ctx()
-            .state()
-            .create_physical_plan(&logical_plan)
-            .await?;
-        let physical_planning_duration = start.elapsed();
-        let task_ctx = self.session_ctx().task_ctx();
-        let mut stream = execute_stream(physical_plan, task_ctx)?;
-        let mut rows = 0;
-        while let Some(b) = stream.next().await {
-            rows += b?.num_rows();
-        }
-        let execution_duration = start.elapsed();
-        let total_duration = start.elapsed();
-        Ok((
-            rows,
-            logical_planning_duration,
-            physical_planning_duration - logical_planning_duration,
-            execution_duration - physical_planning_duration,
-            total_duration,
-        ))
+    async fn benchmark_single_iteration(&self, statement: Statement) -> Result<BenchmarkIteration> {
+        // Memory is reserved through a pool of the iteration's own so its peak can be measured
+        let state = self.session_ctx.state();
+        let pool = Arc::new(PeakMemoryPool::new(Arc::clone(
+            &state.runtime_env().memory_pool,
+        )));
+        let runtime_env = RuntimeEnvBuilder::from_runtime_env(state.runtime_env())
+            .with_memory_pool(Arc::<PeakMemoryPool>::clone(&pool))
+            .build_arc()?;
+        let ctx = SessionContext::new_with_state(
+            SessionStateBuilder::new_from_existing(state)
+                .with_runtime_env(runtime_env)
+                .build(),
+        );
+        let task = async move {
+            let start = std::time::Instant::now();
+            let logical_plan = ctx.state().statement_to_plan(statement).await?;
+            let logical_planning_duration = start.elapsed();
+            let physical_plan = physical_plan(&ctx, logical_plan).await?;
+            let physical_planning_duration = start.elapsed();
+            let mut stream = execute_stream(Arc::clone(&physical_plan), ctx.task_ctx())?;
+            let mut rows = 0;
+            while let Some(b) = stream.next().await {
+                rows += b?.num_rows();
+            }
+            let execution_duration = start.elapsed();
+            let total_duration = start.elapsed();
+            let spill_count = collect_plan_spill_stats(physical_plan)
+                .map(|stats| stats.spill_count())
+                .unwrap_or_default();
+            Ok::<_, DataFusionError>(BenchmarkIteration {
+                rows,
+                logical_planning: logical_planning_duration,
+                physical_planning: physical_planning_duration - logical_planning_duration,
+                execution: execution_duration - physical_planning_duration,
+                total: total_duration,
+                peak_memory: pool.peak(),
+                spill_count,
+            })
+        };
+        Ok(self.spawn_cpu(task).await.map_err(|e| eyre!(e))??)
     }
 
     pub async fn benchmark_query(
@@ -458,8 +1049,7 @@ impl ExecutionContext {
         progress_reporter: Option<Arc<dyn BenchmarkProgressReporter>>,
     ) -> Result<LocalBenchmarkStats> {
         let iterations = cli_iterations.unwrap_or(self.config.benchmark_iterations);
-        let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
-        let statements = DFParser::parse_sql_with_dialect(query, &dialect)?;
+        let statements = self.parse_sql(query)?;
 
         if statements.len() != 1 {
             return Err(eyre::eyre!("Only a single statement can be benchmarked"));
@@ -482,26 +1072,16 @@ impl ExecutionContext {
             iterations, concurrency
         );
 
-        let mut rows_returned = Vec::with_capacity(iterations);
-        let mut logical_planning_durations = Vec::with_capacity(iterations);
-        let mut physical_planning_durations = Vec::with_capacity(iterations);
-        let mut execution_durations = Vec::with_capacity(iterations);
-        let mut total_durations = Vec::with_capacity(iterations);
+        let mut runs = Vec::with_capacity(iterations);
 
         if !concurrent {
             // Serial execution
             for i in 0..iterations {
-                let (rows, lp_dur, pp_dur, exec_dur, total_dur) =
-                    self.benchmark_single_iteration(statement.clone()).await?;
-                rows_returned.push(rows);
-                logical_planning_durations.push(lp_dur);
-                physical_planning_durations.push(pp_dur);
-                execution_durations.push(exec_dur);
-                total_durations.push(total_dur);
-
+                let run = self.benchmark_single_iteration(statement.clone()).await?;
                 if let Some(ref reporter) = progress_reporter {
-                    reporter.on_iteration_complete(i + 1, iterations, total_dur);
+                    reporter.on_iteration_complete(i + 1, iterations, run.total);
                 }
+                runs.push(run);
             }
         } else {
             // Concurrent execution
@@ -520,17 +1100,12 @@ impl ExecutionContext {
                 }
 
                 while let Some(result) = join_set.join_next().await {
-                    let (rows, lp_dur, pp_dur, exec_dur, total_dur) = result??;
-                    rows_returned.push(rows);
-                    logical_planning_durations.push(lp_dur);
-                    physical_planning_durations.push(pp_dur);
-                    execution_durations.push(exec_dur);
-                    total_durations.push(total_dur);
-
+                    let run = result??;
                     completed += 1;
                     if let Some(ref reporter) = progress_reporter {
-                        reporter.on_iteration_complete(completed, iterations, total_dur);
+                        reporter.on_iteration_complete(completed, iterations, run.total);
                     }
+                    runs.push(run);
                 }
             }
         }
@@ -541,45 +1116,58 @@ impl ExecutionContext {
 
         Ok(LocalBenchmarkStats::new(
             query.to_string(),
-            rows_returned,
+            runs.iter().map(|run| run.rows).collect(),
             mode,
-            logical_planning_durations,
-            physical_planning_durations,
-            execution_durations,
-            total_durations,
+            runs.iter().map(|run| run.logical_planning).collect(),
+            runs.iter().map(|run| run.physical_planning).collect(),
+            runs.iter().map(|run| run.execution).collect(),
+            runs.iter().map(|run| run.total).collect(),
+        )
+        .with_resource_usage(
+            runs.iter().map(|run| run.peak_memory).collect(),
+            runs.iter().map(|run| run.spill_count).collect(),
         ))
     }
 
     pub async fn analyze_query(&self, query: &str) -> Result<ExecutionStats> {
-        let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
         let start = std::time::Instant::now();
-        let statements = DFParser::parse_sql_with_dialect(query, &dialect)?;
+        let statements = self.parse_sql(query)?;
         let parsing_duration = start.elapsed();
         if statements.len() == 1 {
             let statement = statements[0].clone();
-            let logical_plan = self
-                .session_ctx()
-                .state()
-                .statement_to_plan(statement.clone())
-                .await?;
-            let logical_planning_duration = start.elapsed();
-            let physical_plan = self
-                .session_ctx()
-                .state()
-                .create_physical_plan(&logical_plan)
-                .await?;
-            let physical_planning_duration = start.elapsed();
-            let task_ctx = self.session_ctx().task_ctx();
-            let mut stream = execute_stream(Arc::clone(&physical_plan), task_ctx)?;
-            let mut rows = 0;
-            let mut batches = 0;
-            let mut bytes = 0;
-            while let Some(b) = stream.next().await {
-                let batch = b?;
-                rows += batch.num_rows();
-                batches += 1;
-                bytes += batch.get_array_memory_size();
-            }
+            let ctx = self.session_ctx.clone();
+            let task = async move {
+                let logical_plan = ctx.state().statement_to_plan(statement).await?;
+                let logical_planning_duration = start.elapsed();
+                let physical_plan = physical_plan(&ctx, logical_plan).await?;
+                let physical_planning_duration = start.elapsed();
+                let mut stream = execute_stream(Arc::clone(&physical_plan), ctx.task_ctx())?;
+                let mut rows = 0;
+                let mut batches = 0;
+                let mut bytes = 0;
+                while let Some(b) = stream.next().await {
+                    let batch = b?;
+                    rows += batch.num_rows();
+                    batches += 1;
+                    bytes += batch.get_array_memory_size();
+                }
+                Ok::<_, DataFusionError>((
+                    physical_plan,
+                    logical_planning_duration,
+                    physical_planning_duration,
+                    rows,
+                    batches,
+                    bytes,
+                ))
+            };
+            let (
+                physical_plan,
+                logical_planning_duration,
+                physical_planning_duration,
+                rows,
+                batches,
+                bytes,
+            ) = self.spawn_cpu(task).await.map_err(|e| eyre!(e))??;
             let execution_duration = start.elapsed();
             let durations = ExecutionDurationStats::new(
                 parsing_duration,
@@ -601,17 +1189,274 @@ impl ExecutionContext {
         }
     }
 
+    /// Plan and execute `sql` like [`Self::execute_sql_with_opts`] once the returned query is
+    /// awaited. The query's [`QueryHandle`] cancels it while it's
+    /// planned and while its results are streamed.
+    pub fn execute_sql_cancellable(&self, sql: &str, opts: ExecOptions) -> CancellableQuery {
+        let ctx = self.clone();
+        let sql = sql.to_string();
+        CancellableQuery::new(QueryHandle::new(), async move {
+            match ctx.execute_sql_with_opts(&sql, opts).await? {
+                ExecResult::RecordBatchStream(stream) => Ok(stream),
+                ExecResult::RecordBatchStreamWithMetrics(_) => Err(DataFusionError::Execution(
+                    "Execution failed: unknown result type".to_string(),
+                )),
+            }
+        })
+    }
+
     pub async fn execute_sql_with_opts(
         &self,
         sql: &str,
         opts: ExecOptions,
     ) -> DFResult<ExecResult> {
-        let df = self.session_ctx.sql(sql).await?;
-        let df = if let Some(limit) = opts.limit {
-            df.limit(0, Some(limit))?
-        } else {
-            df
+        if let Some(result) = self.try_execute_object_store_sql(sql, &opts.identity) {
+            return result.map(ExecResult::RecordBatchStream);
+        }
+        let ctx = self.session_ctx.clone();
+        let sql = sql.to_string();
+        let cache = self.result_cache.clone();
+        let mut query = self.system_tables.start_query(sql.clone(), &opts.identity);
+        let task = async move {
+            let state = ctx.state();
+            // Each phase has its own span so that slow statements can be narrowed down from the
+            // logs of whatever span the caller, such as a server request, executes them in
+            let statement = info_span!("parse").in_scope(|| {
+                state.sql_to_statement(&sql, &state.config_options().sql_parser.dialect)
+            })?;
+            let plan = async {
+                let plan = state.statement_to_plan(statement).await?;
+                rewrite_plan(&state, plan, &opts.identity)
+            }
+            .instrument(info_span!("plan"))
+            .await?;
+            let key = match &cache {
+                Some(cache) if is_cacheable(&plan) => {
+                    let key = cache.key(&state, &plan, &opts);
+                    if let Some(stream) = cache.get(&key) {
+                        return Ok(stream);
+                    }
+                    Some(key)
+                }
+                _ => None,
+            };
+            let changes_state = changes_state(&plan);
+            let stream = async {
+                let df = execute_plan(&ctx, plan).await?;
+                let df = match opts.params {
+                    Some(params) => df.with_param_values(params)?,
+                    None => df,
+                };
+                let df = if opts.limit.is_some() || opts.offset > 0 {
+                    df.limit(opts.offset, opts.limit)?
+                } else {
+                    df
+                };
+                df.execute_stream().await
+            }
+            .instrument(info_span!("execute"))
+            .await?;
+            Ok::<_, DataFusionError>(match (cache, key) {
+                (Some(cache), Some(key)) => {
+                    cache.cache_stream(key, stream, &state.runtime_env().memory_pool)
+                }
+                (Some(cache), None) if changes_state => cache.invalidate_after(stream),
+                _ => stream,
+            })
         };
-        Ok(ExecResult::RecordBatchStream(df.execute_stream().await?))
+        let stream = match self
+            .spawn_cpu(task)
+            .await
+            .map_err(job_error)
+            .and_then(|r| r)
+        {
+            Ok(stream) => stream,
+            Err(e) => {
+                query.fail(&e);
+                return Err(e);
+            }
+        };
+        Ok(ExecResult::RecordBatchStream(
+            query.track(self.guard_results(stream)),
+        ))
+    }
+}
+
+/// Stats of one run of a benchmarked query
+struct BenchmarkIteration {
+    rows: usize,
+    logical_planning: Duration,
+    physical_planning: Duration,
+    execution: Duration,
+    total: Duration,
+    /// Peak bytes of memory reserved while the query ran
+    peak_memory: usize,
+    /// Number of times the query's operators spilled to disk
+    spill_count: usize,
+}
+
+/// Execute `plan` with `ctx`, notifying the session's DDL listeners once DDL statements succeeded
+async fn execute_plan(ctx: &SessionContext, plan: LogicalPlan) -> DFResult<DataFrame> {
+    // Views of a session with its own views only last as long as the session
+    let session_view = matches!(
+        plan,
+        LogicalPlan::Ddl(DdlStatement::CreateView(_) | DdlStatement::DropView(_))
+    ) && ctx
+        .state()
+        .catalog_list()
+        .as_any()
+        .is::<SessionCatalogList>();
+    let ddl = (matches!(plan, LogicalPlan::Ddl(_)) && !session_view).then(|| plan.clone());
+    let df = ctx.execute_logical_plan(plan).await?;
+    if let Some(ddl) = ddl {
+        notify_ddl_listeners(&ctx.state(), &ddl).await;
+    }
+    Ok(df)
+}
+
+/// The DDL files at `path`: the file itself, or the `.sql` files of a directory in
+/// lexicographic order of their names, so files can be prefixed with numbers to order them
+pub fn ddl_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.is_file() && file.extension().is_some_and(|ext| ext == "sql") {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Create the physical plan of `plan` with `ctx`. Statements that DataFusion executes while
+/// planning, such as `SET` and DDL, are applied to `ctx` and planned as an empty result.
+async fn physical_plan(
+    ctx: &SessionContext,
+    plan: LogicalPlan,
+) -> DFResult<Arc<dyn ExecutionPlan>> {
+    execute_plan(ctx, plan).await?.create_physical_plan().await
+}
+
+/// Report a failure to run a task on the [`DedicatedExecutor`] like any other DataFusion error
+fn job_error(e: JobError) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
+
+/// Replace the statements of `ddl` that create the same table as `statement`, a `CREATE TABLE`
+/// statement, with `statement`, or append it if there aren't any. The other statements, and
+/// the comments and whitespace around them, are kept as they are.
+pub fn replace_table_ddl(ddl: &str, statement: &str, dialect: &dyn Dialect) -> DFResult<String> {
+    let Some(name) = created_table(statement, dialect) else {
+        return Err(DataFusionError::Plan(format!(
+            "Only CREATE TABLE statements can be saved as the DDL of a table: {statement}"
+        )));
+    };
+    let mut replaced = String::new();
+    for sql in split_statements(ddl, dialect)? {
+        if created_table(sql, dialect).as_ref() != Some(&name) {
+            replaced.push_str(sql);
+        }
+    }
+    replaced.truncate(replaced.trim_end().len());
+    if !replaced.is_empty() {
+        replaced.push('\n');
+    }
+    replaced.push_str(statement.trim().trim_end_matches(';'));
+    replaced.push_str(";\n");
+    Ok(replaced)
+}
+
+/// The table created by `sql`, if it's a single statement that creates one
+fn created_table(sql: &str, dialect: &dyn Dialect) -> Option<TableReference> {
+    let mut statements = DFParser::parse_sql_with_dialect(sql, dialect).ok()?;
+    if statements.len() != 1 {
+        return None;
+    }
+    let name = match statements.pop_front()? {
+        Statement::CreateExternalTable(create) => create.name,
+        Statement::Statement(statement) => match *statement {
+            SQLStatement::CreateTable(create) => create.name,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    object_name_to_table_reference(name, true).ok()
+}
+
+/// Split `sql` after each `;` that ends a statement, keeping the comments and whitespace
+/// before a statement with it
+fn split_statements<'a>(sql: &'a str, dialect: &dyn Dialect) -> DFResult<Vec<&'a str>> {
+    let tokens = Tokenizer::new(dialect, sql)
+        .tokenize_with_location()
+        .map_err(|e| DataFusionError::Plan(format!("Error tokenizing DDL: {e}")))?;
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(sql.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    // Locations are 1-based lines and columns in characters
+    let offset = |location: Location| {
+        let line_start = line_starts[location.line as usize - 1];
+        sql[line_start..]
+            .char_indices()
+            .nth(location.column as usize - 1)
+            .map_or(sql.len(), |(i, _)| line_start + i)
+    };
+    let mut statements = Vec::new();
+    let mut start = 0;
+    for token in tokens {
+        if token.token == Token::SemiColon {
+            let end = offset(token.span.start) + 1;
+            statements.push(&sql[start..end]);
+            start = end;
+        }
+    }
+    statements.push(&sql[start..]);
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::sql::sqlparser::dialect::GenericDialect;
+
+    use super::*;
+
+    #[test]
+    fn test_replace_table_ddl() {
+        let dialect = GenericDialect {};
+        let ddl = "-- Source data\nCREATE EXTERNAL TABLE src STORED AS CSV LOCATION 'data/src.csv';\n\nCREATE TABLE staged AS SELECT 1 AS x;\nCREATE VIEW v AS SELECT ';' AS s;\n";
+
+        let replaced =
+            replace_table_ddl(ddl, "CREATE TABLE staged AS SELECT 2 AS x", &dialect).unwrap();
+        assert_eq!(
+            replaced,
+            "-- Source data\nCREATE EXTERNAL TABLE src STORED AS CSV LOCATION 'data/src.csv';\nCREATE VIEW v AS SELECT ';' AS s;\nCREATE TABLE staged AS SELECT 2 AS x;\n"
+        );
+        // Saving the table again replaces it rather than adding it twice
+        let replaced_again =
+            replace_table_ddl(&replaced, "CREATE TABLE staged AS SELECT 2 AS x;", &dialect)
+                .unwrap();
+        assert_eq!(replaced_again, replaced);
+
+        let appended = replace_table_ddl("", "CREATE TABLE t AS SELECT 1", &dialect).unwrap();
+        assert_eq!(appended, "CREATE TABLE t AS SELECT 1;\n");
+
+        assert!(replace_table_ddl(ddl, "SELECT 1", &dialect).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_object_store_ddl_can_be_disabled() {
+        let config = ExecutionConfig {
+            object_store_ddl: Some(false),
+            ..Default::default()
+        };
+        let state = SessionStateBuilder::new().build();
+        let execution =
+            ExecutionContext::try_new(&config, state, "dft", env!("CARGO_PKG_VERSION")).unwrap();
+
+        let sql = "CREATE EXTERNAL OBJECT STORE s3 LOCATION 's3://my_bucket'";
+        let err = execution.execute_sql(sql).await.err().unwrap();
+        assert!(err.to_string().contains("`object_store_ddl`"), "{err}");
     }
 }
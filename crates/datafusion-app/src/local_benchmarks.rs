@@ -17,8 +17,15 @@
 
 //! [`ExecutionContext`]: DataFusion based execution context for running SQL queries
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
+use datafusion::common::Result as DFResult;
+use datafusion::execution::memory_pool::{
+    MemoryConsumer, MemoryLimit, MemoryPool, MemoryReservation,
+};
+
 /// Duration summary statistics
 #[derive(Debug)]
 pub struct DurationsSummary {
@@ -40,6 +47,137 @@ impl DurationsSummary {
             self.percent_of_total,
         )
     }
+
+    /// Summary as a JSON object, with durations in milliseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "min_ms": duration_ms(self.min),
+            "max_ms": duration_ms(self.max),
+            "mean_ms": duration_ms(self.mean),
+            "median_ms": duration_ms(self.median),
+            "percent_of_total": self.percent_of_total,
+        })
+    }
+}
+
+/// Fractional milliseconds of a duration, used for durations in JSON metrics
+pub fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Quote a field of a saved benchmark results row if it contains a delimiter, quote or line
+/// break, so queries spanning multiple lines are read back as a single field
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Summary statistics of a resource used by each run, such as bytes of memory
+#[derive(Debug)]
+pub struct UsageSummary {
+    pub min: usize,
+    pub max: usize,
+    pub mean: usize,
+    pub median: usize,
+}
+
+impl UsageSummary {
+    fn new(values: &[usize]) -> Self {
+        let mut sorted = values.to_vec();
+        sorted.sort();
+        let len = sorted.len();
+        Self {
+            min: sorted.first().copied().unwrap_or_default(),
+            max: sorted.last().copied().unwrap_or_default(),
+            mean: sorted.iter().sum::<usize>() / len.max(1),
+            median: sorted.get(len / 2).copied().unwrap_or_default(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "median": self.median,
+        })
+    }
+}
+
+impl std::fmt::Display for UsageSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Min: {}", self.min)?;
+        writeln!(f, "Max: {}", self.max)?;
+        writeln!(f, "Median: {}", self.median)?;
+        writeln!(f, "Mean: {}", self.mean)
+    }
+}
+
+/// A [`MemoryPool`] that records the peak of the memory reserved through it, which is reserved
+/// from, and limited by, the pool it wraps. Each benchmark run reserves memory through its own
+/// pool so concurrent runs are measured separately.
+#[derive(Debug)]
+pub struct PeakMemoryPool {
+    inner: Arc<dyn MemoryPool>,
+    reserved: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl PeakMemoryPool {
+    pub fn new(inner: Arc<dyn MemoryPool>) -> Self {
+        Self {
+            inner,
+            reserved: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// The most bytes that were reserved through this pool at once
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    fn record_grow(&self, additional: usize) {
+        let reserved = self.reserved.fetch_add(additional, Ordering::Relaxed) + additional;
+        self.peak.fetch_max(reserved, Ordering::Relaxed);
+    }
+}
+
+impl MemoryPool for PeakMemoryPool {
+    fn register(&self, consumer: &MemoryConsumer) {
+        self.inner.register(consumer)
+    }
+
+    fn unregister(&self, consumer: &MemoryConsumer) {
+        self.inner.unregister(consumer)
+    }
+
+    fn grow(&self, reservation: &MemoryReservation, additional: usize) {
+        self.inner.grow(reservation, additional);
+        self.record_grow(additional);
+    }
+
+    fn shrink(&self, reservation: &MemoryReservation, shrink: usize) {
+        self.inner.shrink(reservation, shrink);
+        self.reserved.fetch_sub(shrink, Ordering::Relaxed);
+    }
+
+    fn try_grow(&self, reservation: &MemoryReservation, additional: usize) -> DFResult<()> {
+        self.inner.try_grow(reservation, additional)?;
+        self.record_grow(additional);
+        Ok(())
+    }
+
+    fn reserved(&self) -> usize {
+        self.inner.reserved()
+    }
+
+    fn memory_limit(&self) -> MemoryLimit {
+        self.inner.memory_limit()
+    }
 }
 
 impl std::fmt::Display for DurationsSummary {
@@ -84,6 +222,10 @@ pub struct LocalBenchmarkStats {
     physical_planning_durations: Vec<Duration>,
     execution_durations: Vec<Duration>,
     total_durations: Vec<Duration>,
+    /// Peak bytes of memory reserved by each run
+    peak_memory: Vec<usize>,
+    /// Number of times the operators of each run spilled to disk
+    spill_counts: Vec<usize>,
 }
 
 impl LocalBenchmarkStats {
@@ -106,9 +248,26 @@ impl LocalBenchmarkStats {
             physical_planning_durations,
             execution_durations,
             total_durations,
+            peak_memory: Vec::new(),
+            spill_counts: Vec::new(),
         }
     }
 
+    /// Add the peak memory reserved and the spills of each run
+    pub fn with_resource_usage(
+        mut self,
+        peak_memory: Vec<usize>,
+        spill_counts: Vec<usize>,
+    ) -> Self {
+        self.peak_memory = peak_memory;
+        self.spill_counts = spill_counts;
+        self
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
     fn summarize(&self, durations: &[Duration]) -> DurationsSummary {
         if durations.is_empty() {
             return DurationsSummary {
@@ -149,7 +308,7 @@ impl LocalBenchmarkStats {
         let execution_summary = self.summarize(&self.execution_durations);
         let total_summary = self.summarize(&self.total_durations);
 
-        csv.push_str(&self.query);
+        csv.push_str(&csv_field(&self.query));
         csv.push(',');
         csv.push_str(&self.runs.to_string());
         csv.push(',');
@@ -161,9 +320,321 @@ impl LocalBenchmarkStats {
         csv.push(',');
         csv.push_str(total_summary.to_csv_fields().as_str());
         csv.push(',');
+        csv.push_str(&usage_median_field(&self.peak_memory));
+        csv.push(',');
+        csv.push_str(&usage_median_field(&self.spill_counts));
+        csv.push(',');
         csv.push_str(&self.mode.to_string());
         csv
     }
+
+    /// Summary of the benchmark as a JSON object, with durations in milliseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "query": self.query,
+            "runs": self.runs,
+            "mode": self.mode.to_string(),
+            "rows": self.rows,
+            "logical_planning": self.summarize(&self.logical_planning_durations).to_json(),
+            "physical_planning": self.summarize(&self.physical_planning_durations).to_json(),
+            "execution": self.summarize(&self.execution_durations).to_json(),
+            "total": self.summarize(&self.total_durations).to_json(),
+            "peak_memory_bytes": UsageSummary::new(&self.peak_memory).to_json(),
+            "spill_count": UsageSummary::new(&self.spill_counts).to_json(),
+        })
+    }
+}
+
+impl LocalBenchmarkStats {
+    /// Compare the median duration of each stage, and the median peak memory and spills if both
+    /// were recorded, against a baseline.  Current durations are truncated to milliseconds to
+    /// match the resolution of saved results.
+    pub fn compare(&self, baseline: &BenchmarkBaseline) -> BenchmarkComparison {
+        let current = |durations: &[Duration]| {
+            let median = self.summarize(durations).median;
+            Duration::from_millis(median.as_millis() as u64)
+        };
+        let stages = vec![
+            StageComparison {
+                name: "Logical Planning",
+                baseline: baseline.logical_planning_median,
+                current: current(&self.logical_planning_durations),
+            },
+            StageComparison {
+                name: "Physical Planning",
+                baseline: baseline.physical_planning_median,
+                current: current(&self.physical_planning_durations),
+            },
+            StageComparison {
+                name: "Execution",
+                baseline: baseline.execution_median,
+                current: current(&self.execution_durations),
+            },
+            StageComparison {
+                name: "Total",
+                baseline: baseline.total_median,
+                current: current(&self.total_durations),
+            },
+        ];
+        let usage = [
+            (
+                "Peak Memory (bytes)",
+                baseline.peak_memory_median,
+                &self.peak_memory,
+            ),
+            ("Spills", baseline.spill_count_median, &self.spill_counts),
+        ]
+        .into_iter()
+        .filter_map(|(name, baseline, values)| {
+            let baseline = baseline?;
+            (!values.is_empty()).then(|| UsageComparison {
+                name,
+                baseline,
+                current: UsageSummary::new(values).median,
+            })
+        })
+        .collect();
+        BenchmarkComparison {
+            query: self.query.clone(),
+            stages,
+            usage,
+        }
+    }
+}
+
+/// The median of a resource used by each run as a saved results field, which is empty if the
+/// usage wasn't recorded
+fn usage_median_field(values: &[usize]) -> String {
+    if values.is_empty() {
+        String::new()
+    } else {
+        UsageSummary::new(values).median.to_string()
+    }
+}
+
+/// Number of fields following the query in a row written by
+/// [`LocalBenchmarkStats::to_summary_csv_row`]: runs, 5 fields for each of the 4 stages, the
+/// median peak memory and spills, and the concurrency mode
+const SUMMARY_CSV_TRAILING_FIELDS: usize = 24;
+
+/// Number of fields following the query in rows saved before peak memory and spills were
+/// recorded, which lack their columns
+const LEGACY_SUMMARY_CSV_TRAILING_FIELDS: usize = 22;
+
+/// Median stage durations, and the median peak memory and spills if they were saved, from a
+/// previous benchmark run, used as a baseline for comparison
+#[derive(Debug, Clone)]
+pub struct BenchmarkBaseline {
+    pub query: String,
+    pub logical_planning_median: Duration,
+    pub physical_planning_median: Duration,
+    pub execution_median: Duration,
+    pub total_median: Duration,
+    pub peak_memory_median: Option<usize>,
+    pub spill_count_median: Option<usize>,
+}
+
+impl BenchmarkBaseline {
+    /// Read the rows of a results file written with `--save`, skipping its header rows
+    pub fn read_csv(reader: impl std::io::Read) -> Result<Vec<Self>, String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(reader);
+        let mut baselines = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| format!("Invalid benchmark results file: {e}"))?;
+            let fields: Vec<&str> = record.iter().collect();
+            if fields.first() == Some(&"query") {
+                continue;
+            }
+            baselines.push(Self::try_from_fields(&fields)?);
+        }
+        Ok(baselines)
+    }
+
+    /// Parse the fields of a row written by [`LocalBenchmarkStats::to_summary_csv_row`].
+    /// Queries in files saved before they were quoted may contain commas, so fields are read
+    /// from the end of the row and any leading fields are joined back into the query.  Rows
+    /// saved before peak memory and spills were recorded end with the total's percent of total
+    /// and the mode, rather than the spills and the mode, and are read without them.
+    fn try_from_fields(fields: &[&str]) -> Result<Self, String> {
+        let row = fields.join(",");
+        let has_usage = fields.len() >= 2 && {
+            let spills = fields[fields.len() - 2].trim();
+            spills.is_empty() || spills.parse::<usize>().is_ok()
+        };
+        let trailing_fields = if has_usage {
+            SUMMARY_CSV_TRAILING_FIELDS
+        } else {
+            LEGACY_SUMMARY_CSV_TRAILING_FIELDS
+        };
+        let Some(query_fields) = fields.len().checked_sub(trailing_fields) else {
+            return Err(format!("Invalid benchmark results row: '{row}'"));
+        };
+        if query_fields == 0 {
+            return Err(format!("Invalid benchmark results row: '{row}'"));
+        }
+        let (query, trailing) = fields.split_at(query_fields);
+        let millis = |idx: usize| -> Result<Duration, String> {
+            trailing[idx]
+                .trim()
+                .parse::<u64>()
+                .map(Duration::from_millis)
+                .map_err(|e| format!("Invalid duration '{}' in row '{row}': {e}", trailing[idx]))
+        };
+        let usage = |idx: usize| -> Result<Option<usize>, String> {
+            if !has_usage || trailing[idx].trim().is_empty() {
+                return Ok(None);
+            }
+            trailing[idx]
+                .trim()
+                .parse::<usize>()
+                .map(Some)
+                .map_err(|e| format!("Invalid usage '{}' in row '{row}': {e}", trailing[idx]))
+        };
+        // The number of runs is followed by the min, max, mean, median, and percent of total for
+        // each stage
+        Ok(Self {
+            query: query.join(","),
+            logical_planning_median: millis(4)?,
+            physical_planning_median: millis(9)?,
+            execution_median: millis(14)?,
+            total_median: millis(19)?,
+            peak_memory_median: usage(21)?,
+            spill_count_median: usage(22)?,
+        })
+    }
+}
+
+/// Change in the median duration of a single benchmark stage relative to a baseline
+#[derive(Debug)]
+pub struct StageComparison {
+    pub name: &'static str,
+    pub baseline: Duration,
+    pub current: Duration,
+}
+
+impl StageComparison {
+    /// Percentage change from the baseline, where positive values are regressions.  `None` if
+    /// the baseline was zero.
+    pub fn change_percent(&self) -> Option<f64> {
+        if self.baseline.is_zero() {
+            None
+        } else {
+            let baseline = self.baseline.as_secs_f64();
+            Some(((self.current.as_secs_f64() - baseline) / baseline) * 100.0)
+        }
+    }
+}
+
+/// Change in the median of a resource used by each run, such as peak memory, relative to a
+/// baseline
+#[derive(Debug)]
+pub struct UsageComparison {
+    pub name: &'static str,
+    pub baseline: usize,
+    pub current: usize,
+}
+
+impl UsageComparison {
+    /// Percentage change from the baseline, where positive values are regressions.  `None` if
+    /// the baseline was zero.
+    pub fn change_percent(&self) -> Option<f64> {
+        if self.baseline == 0 {
+            None
+        } else {
+            let baseline = self.baseline as f64;
+            Some(((self.current as f64 - baseline) / baseline) * 100.0)
+        }
+    }
+}
+
+/// A stage or resource that regressed compared to the baseline
+#[derive(Debug)]
+pub struct Regression {
+    pub name: &'static str,
+    pub change_percent: f64,
+}
+
+/// Comparison of a benchmarked query against its baseline
+#[derive(Debug)]
+pub struct BenchmarkComparison {
+    query: String,
+    stages: Vec<StageComparison>,
+    usage: Vec<UsageComparison>,
+}
+
+impl BenchmarkComparison {
+    /// Stages and resources that regressed by more than `threshold` percent
+    pub fn regressions(&self, threshold: f64) -> Vec<Regression> {
+        let stages = self.stages.iter().map(|s| (s.name, s.change_percent()));
+        let usage = self.usage.iter().map(|u| (u.name, u.change_percent()));
+        stages
+            .chain(usage)
+            .filter_map(|(name, change)| {
+                change
+                    .filter(|c| *c > threshold)
+                    .map(|change_percent| Regression {
+                        name,
+                        change_percent,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for BenchmarkComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "----------------------------")?;
+        writeln!(f, "Baseline Comparison (median)")?;
+        writeln!(f, "----------------------------")?;
+        writeln!(f, "{}", self.query)?;
+        writeln!(f, "----------------------------")?;
+        writeln!(
+            f,
+            "{:<20} {:<12} {:<12} {:<12} {:<12}",
+            "Stage", "Baseline", "Current", "Delta", "Change (%)"
+        )?;
+        self.stages.iter().try_for_each(|stage| {
+            let delta = if stage.current >= stage.baseline {
+                format!("+{:?}", stage.current - stage.baseline)
+            } else {
+                format!("-{:?}", stage.baseline - stage.current)
+            };
+            writeln!(
+                f,
+                "{:<20} {:<12} {:<12} {:<12} {:<12}",
+                stage.name,
+                format!("{:?}", stage.baseline),
+                format!("{:?}", stage.current),
+                delta,
+                stage
+                    .change_percent()
+                    .map(|c| format!("{:+.2}", c))
+                    .unwrap_or("N/A".to_string()),
+            )
+        })?;
+        self.usage.iter().try_for_each(|usage| {
+            let delta = if usage.current >= usage.baseline {
+                format!("+{}", usage.current - usage.baseline)
+            } else {
+                format!("-{}", usage.baseline - usage.current)
+            };
+            writeln!(
+                f,
+                "{:<20} {:<12} {:<12} {:<12} {:<12}",
+                usage.name,
+                usage.baseline,
+                usage.current,
+                delta,
+                usage
+                    .change_percent()
+                    .map(|c| format!("{:+.2}", c))
+                    .unwrap_or("N/A".to_string()),
+            )
+        })
+    }
 }
 
 pub fn is_all_same(arr: &[usize]) -> bool {
@@ -200,7 +671,17 @@ impl std::fmt::Display for LocalBenchmarkStats {
 
         let total_summary = self.summarize(&self.total_durations);
         writeln!(f, "Total")?;
-        writeln!(f, "{}", total_summary)
+        writeln!(f, "{}", total_summary)?;
+
+        if !self.peak_memory.is_empty() {
+            writeln!(f, "Peak Memory (bytes)")?;
+            writeln!(f, "{}", UsageSummary::new(&self.peak_memory))?;
+        }
+        if !self.spill_counts.is_empty() {
+            writeln!(f, "Spills")?;
+            writeln!(f, "{}", UsageSummary::new(&self.spill_counts))?;
+        }
+        Ok(())
     }
 }
 
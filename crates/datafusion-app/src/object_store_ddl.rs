@@ -0,0 +1,368 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! `CREATE EXTERNAL OBJECT STORE`, a dft statement that registers an object store with the
+//! session's `RuntimeEnv` so remote locations can be added without editing the config:
+//!
+//! ```sql
+//! CREATE EXTERNAL OBJECT STORE s3 LOCATION 's3://my_bucket'
+//! OPTIONS ('aws.region' 'us-east-1', 'aws.secret_access_key' 'secret://aws_secret');
+//! ```
+//!
+//! DataFusion can't parse the statement, so it's recognized before the SQL is parsed.
+
+use std::collections::HashMap;
+
+use datafusion::{
+    common::{plan_err, Result},
+    execution::runtime_env::RuntimeEnv,
+    sql::sqlparser::{
+        dialect::GenericDialect, keywords::Keyword, parser::Parser, tokenizer::Token,
+    },
+};
+
+use crate::secrets::Secrets;
+
+/// The words statements that create object stores start with
+const CREATE_OBJECT_STORE: [&str; 4] = ["CREATE", "EXTERNAL", "OBJECT", "STORE"];
+
+/// A parsed `CREATE EXTERNAL OBJECT STORE` statement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreateObjectStore {
+    /// The service the store is backed by: `s3`, `gcs` or `azure`
+    pub kind: String,
+    /// Url the store is registered for, such as `s3://my_bucket`
+    pub location: String,
+    /// Settings of the store, with the keys `object_store` uses for the kind of store, optionally
+    /// prefixed like the options of `CREATE EXTERNAL TABLE` (`aws.`, `gcp.` or `azure.`). Values
+    /// can refer to secrets.
+    pub options: HashMap<String, String>,
+}
+
+/// Parse `sql` if it's a `CREATE EXTERNAL OBJECT STORE` statement, or return `None` so it's
+/// parsed by DataFusion
+pub fn parse_create_object_store(sql: &str) -> Option<Result<CreateObjectStore>> {
+    let mut words = sql.split_whitespace();
+    let is_create_object_store = CREATE_OBJECT_STORE.iter().all(|expected| {
+        words
+            .next()
+            .is_some_and(|w| w.eq_ignore_ascii_case(expected))
+    });
+    is_create_object_store.then(|| parse(sql))
+}
+
+fn parse(sql: &str) -> Result<CreateObjectStore> {
+    let dialect = GenericDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    for _ in CREATE_OBJECT_STORE {
+        parser.next_token();
+    }
+    let kind = parser.parse_identifier()?.value.to_lowercase();
+    if !parser.parse_keyword(Keyword::LOCATION) {
+        return plan_err!(
+            "CREATE EXTERNAL OBJECT STORE requires a LOCATION, such as 's3://my_bucket'"
+        );
+    }
+    let location = parser.parse_literal_string()?;
+    let mut options = HashMap::new();
+    if parser.parse_keyword(Keyword::OPTIONS) {
+        parser.expect_token(&Token::LParen)?;
+        let pairs = parser.parse_comma_separated(|p| {
+            Ok((p.parse_literal_string()?, p.parse_literal_string()?))
+        })?;
+        parser.expect_token(&Token::RParen)?;
+        options.extend(pairs);
+    }
+    parser.consume_token(&Token::SemiColon);
+    let next = parser.peek_token().token;
+    if next != Token::EOF {
+        return plan_err!("Unexpected {next} at the end of CREATE EXTERNAL OBJECT STORE");
+    }
+    Ok(CreateObjectStore {
+        kind,
+        location,
+        options,
+    })
+}
+
+impl CreateObjectStore {
+    /// Create the store and register it with `runtime_env` for its location, resolving the
+    /// options that refer to secrets. A store that is already registered for the location is
+    /// only replaced if `replace` is set, so queries can't redirect the locations of the config's
+    /// stores.
+    ///
+    /// Settings that aren't given are read from the environment, unless the statement sets the
+    /// store's endpoint, so the credentials of the environment are only sent to the endpoint
+    /// they're for.
+    #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+    pub fn register(
+        &self,
+        runtime_env: &RuntimeEnv,
+        secrets: &Secrets,
+        replace: bool,
+    ) -> Result<()> {
+        use std::sync::Arc;
+
+        use datafusion::error::DataFusionError;
+        use object_store::ObjectStore;
+
+        let url = url::Url::parse(&self.location).map_err(|e| {
+            DataFusionError::Plan(format!(
+                "Invalid object store location {}: {e}",
+                self.location
+            ))
+        })?;
+        if !replace && runtime_env.object_store(&url).is_ok() {
+            return plan_err!(
+                "An object store is already registered for {}",
+                self.location
+            );
+        }
+        let options = secrets.resolve_map(&self.options)?;
+        let store: Arc<dyn ObjectStore> = match self.kind.as_str() {
+            #[cfg(feature = "s3")]
+            "s3" => Arc::new(s3_builder(&self.location, &options)?.build()?),
+            #[cfg(feature = "gcs")]
+            "gcs" => {
+                use object_store::gcp::{GoogleCloudStorageBuilder, GoogleConfigKey};
+
+                let mut builder = GoogleCloudStorageBuilder::from_env().with_url(&self.location);
+                for (key, value) in options {
+                    builder = builder
+                        .with_config(unprefixed(&key, "gcp.").parse::<GoogleConfigKey>()?, value);
+                }
+                Arc::new(builder.build()?)
+            }
+            #[cfg(feature = "azure")]
+            "azure" => Arc::new(azure_builder(&self.location, &options)?.build()?),
+            kind => return unsupported_kind(kind),
+        };
+        runtime_env.register_object_store(&url, store);
+        Ok(())
+    }
+
+    /// Object stores can only be registered when dft is built with one of their features
+    #[cfg(not(any(feature = "s3", feature = "gcs", feature = "azure")))]
+    pub fn register(
+        &self,
+        _runtime_env: &RuntimeEnv,
+        _secrets: &Secrets,
+        _replace: bool,
+    ) -> Result<()> {
+        unsupported_kind(&self.kind)
+    }
+}
+
+/// `key` without the prefix of the options of `CREATE EXTERNAL TABLE`
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+fn unprefixed<'a>(key: &'a str, prefix: &str) -> &'a str {
+    key.strip_prefix(prefix).unwrap_or(key)
+}
+
+/// The builder of an S3 store for `location` with `options`, seeded from the `AWS_*` environment
+/// variables unless the options set the endpoint
+#[cfg(feature = "s3")]
+fn s3_builder(
+    location: &str,
+    options: &HashMap<String, String>,
+) -> Result<object_store::aws::AmazonS3Builder> {
+    use object_store::aws::{AmazonS3Builder, AmazonS3ConfigKey};
+
+    let options = options
+        .iter()
+        .map(|(key, value)| Ok((unprefixed(key, "aws.").parse::<AmazonS3ConfigKey>()?, value)))
+        .collect::<Result<Vec<_>>>()?;
+    let builder = if options
+        .iter()
+        .any(|(key, _)| *key == AmazonS3ConfigKey::Endpoint)
+    {
+        AmazonS3Builder::new()
+    } else {
+        AmazonS3Builder::from_env()
+    };
+    Ok(options
+        .into_iter()
+        .fold(builder.with_url(location), |builder, (key, value)| {
+            builder.with_config(key, value)
+        }))
+}
+
+/// The builder of an Azure store for `location` with `options`, seeded from the `AZURE_*`
+/// environment variables unless the options set the endpoint
+#[cfg(feature = "azure")]
+fn azure_builder(
+    location: &str,
+    options: &HashMap<String, String>,
+) -> Result<object_store::azure::MicrosoftAzureBuilder> {
+    use object_store::azure::{AzureConfigKey, MicrosoftAzureBuilder};
+
+    let options = options
+        .iter()
+        .map(|(key, value)| Ok((unprefixed(key, "azure.").parse::<AzureConfigKey>()?, value)))
+        .collect::<Result<Vec<_>>>()?;
+    let builder = if options
+        .iter()
+        .any(|(key, _)| *key == AzureConfigKey::Endpoint)
+    {
+        MicrosoftAzureBuilder::new()
+    } else {
+        MicrosoftAzureBuilder::from_env()
+    };
+    Ok(options
+        .into_iter()
+        .fold(builder.with_url(location), |builder, (key, value)| {
+            builder.with_config(key, value)
+        }))
+}
+
+fn unsupported_kind(kind: &str) -> Result<()> {
+    match kind {
+        "s3" | "gcs" | "azure" => {
+            plan_err!("{kind} object stores require dft to be built with the `{kind}` feature")
+        }
+        _ => plan_err!("Unsupported object store kind {kind}, expected s3, gcs or azure"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(
+            parse_create_object_store("CREATE EXTERNAL TABLE t STORED AS CSV LOCATION 'x'")
+                .is_none()
+        );
+        assert!(parse_create_object_store("SELECT 1").is_none());
+
+        let statement = parse_create_object_store(
+            "create external object store S3\n  LOCATION 's3://my_bucket' OPTIONS ('aws.region' 'us-east-1', 'endpoint' 'http://localhost:9000');",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            statement,
+            CreateObjectStore {
+                kind: "s3".to_string(),
+                location: "s3://my_bucket".to_string(),
+                options: HashMap::from([
+                    ("aws.region".to_string(), "us-east-1".to_string()),
+                    ("endpoint".to_string(), "http://localhost:9000".to_string()),
+                ]),
+            }
+        );
+
+        let statement =
+            parse_create_object_store("CREATE EXTERNAL OBJECT STORE gcs LOCATION 'gs://b'")
+                .unwrap()
+                .unwrap();
+        assert!(statement.options.is_empty());
+
+        let err = parse_create_object_store("CREATE EXTERNAL OBJECT STORE s3 OPTIONS ('a' 'b')")
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().contains("requires a LOCATION"));
+        let err =
+            parse_create_object_store("CREATE EXTERNAL OBJECT STORE s3 LOCATION 's3://b' extra")
+                .unwrap()
+                .unwrap_err();
+        assert!(err.to_string().contains("Unexpected"));
+    }
+
+    #[test]
+    fn test_unsupported_kind() {
+        let statement =
+            parse_create_object_store("CREATE EXTERNAL OBJECT STORE ftp LOCATION 'ftp://host'")
+                .unwrap()
+                .unwrap();
+        let err = statement
+            .register(&RuntimeEnv::default(), &Secrets::default(), false)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unsupported object store kind ftp"));
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_register_s3() {
+        use datafusion::execution::object_store::ObjectStoreUrl;
+
+        let runtime_env = RuntimeEnv::default();
+        let statement = parse_create_object_store(
+            "CREATE EXTERNAL OBJECT STORE s3 LOCATION 's3://dft-test-bucket' OPTIONS ('aws.region' 'us-east-1', 'access_key_id' 'key', 'aws.secret_access_key' 'secret')",
+        )
+        .unwrap()
+        .unwrap();
+        statement
+            .register(&runtime_env, &Secrets::default(), false)
+            .unwrap();
+        let url = ObjectStoreUrl::parse("s3://dft-test-bucket").unwrap();
+        let registered = runtime_env.object_store(&url).unwrap();
+
+        // The store can only be replaced when `replace` is set
+        let err = statement
+            .register(&runtime_env, &Secrets::default(), false)
+            .unwrap_err();
+        assert!(err.to_string().contains("already registered"), "{err}");
+        assert!(Arc::ptr_eq(
+            &registered,
+            &runtime_env.object_store(&url).unwrap()
+        ));
+        statement
+            .register(&runtime_env, &Secrets::default(), true)
+            .unwrap();
+        assert!(!Arc::ptr_eq(
+            &registered,
+            &runtime_env.object_store(&url).unwrap()
+        ));
+
+        let statement = parse_create_object_store(
+            "CREATE EXTERNAL OBJECT STORE s3 LOCATION 's3://dft-other-bucket' OPTIONS ('aws.not_a_setting' 'x')",
+        )
+        .unwrap()
+        .unwrap();
+        assert!(statement
+            .register(&runtime_env, &Secrets::default(), false)
+            .is_err());
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_s3_endpoint_ignores_env() {
+        use object_store::aws::AmazonS3ConfigKey;
+
+        std::env::set_var("AWS_ACCESS_KEY_ID", "dft-test-env-key");
+        let options = |endpoint: Option<&str>| {
+            let mut options = HashMap::from([("aws.region".to_string(), "us-east-1".to_string())]);
+            if let Some(endpoint) = endpoint {
+                options.insert("aws.endpoint".to_string(), endpoint.to_string());
+            }
+            options
+        };
+        let key_id = |options: HashMap<String, String>| {
+            s3_builder("s3://b", &options)
+                .unwrap()
+                .get_config_value(&AmazonS3ConfigKey::AccessKeyId)
+        };
+        assert_eq!(key_id(options(None)).as_deref(), Some("dft-test-env-key"));
+        assert_eq!(key_id(options(Some("http://localhost:9000"))), None);
+    }
+}
@@ -0,0 +1,192 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Encoding logical and physical plans with `datafusion-proto` so they can be saved for
+//! debugging and executed again without the SQL, catalog or session they were planned with
+
+use std::{path::Path, sync::Arc};
+
+use datafusion::{
+    common::{plan_err, Result},
+    execution::TaskContext,
+    logical_expr::LogicalPlan,
+    physical_plan::ExecutionPlan,
+};
+use datafusion_proto::bytes::{
+    logical_plan_from_bytes, logical_plan_from_json, logical_plan_to_bytes, logical_plan_to_json,
+    physical_plan_from_bytes, physical_plan_from_json, physical_plan_to_bytes,
+    physical_plan_to_json,
+};
+
+/// How a saved plan is encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlanFormat {
+    /// `datafusion-proto` protobuf bytes
+    #[default]
+    Proto,
+    /// The JSON representation of the `datafusion-proto` messages, which is easier to read and
+    /// edit
+    Json,
+}
+
+impl PlanFormat {
+    /// Extension of the files plans are saved to in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Proto => "pb",
+            Self::Json => "json",
+        }
+    }
+
+    /// The format of the plan saved at `path`, JSON for `.json` files and protobuf otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Proto,
+        }
+    }
+}
+
+/// Whether a saved plan is a logical or a physical plan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanKind {
+    Logical,
+    Physical,
+}
+
+impl PlanKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Logical => "logical",
+            Self::Physical => "physical",
+        }
+    }
+
+    /// The kind of the plan saved at `path`, from the `.logical` or `.physical` before its
+    /// extension, such as `query_0.physical.pb`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let kind = path
+            .file_stem()
+            .map(Path::new)
+            .and_then(|stem| stem.extension())
+            .and_then(|kind| kind.to_str());
+        match kind {
+            Some("logical") => Ok(Self::Logical),
+            Some("physical") => Ok(Self::Physical),
+            _ => plan_err!(
+                "Unable to tell whether {} is a logical or physical plan, its name must end with `.logical.<ext>` or `.physical.<ext>`",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Name of the file the plan of `kind` for `name` is saved to in `format`
+pub fn plan_file_name(name: &str, kind: PlanKind, format: PlanFormat) -> String {
+    format!("{name}.{}.{}", kind.name(), format.extension())
+}
+
+pub fn encode_logical_plan(plan: &LogicalPlan, format: PlanFormat) -> Result<Vec<u8>> {
+    match format {
+        PlanFormat::Proto => Ok(logical_plan_to_bytes(plan)?.to_vec()),
+        PlanFormat::Json => Ok(logical_plan_to_json(plan)?.into_bytes()),
+    }
+}
+
+pub fn encode_physical_plan(plan: Arc<dyn ExecutionPlan>, format: PlanFormat) -> Result<Vec<u8>> {
+    match format {
+        PlanFormat::Proto => Ok(physical_plan_to_bytes(plan)?.to_vec()),
+        PlanFormat::Json => Ok(physical_plan_to_json(plan)?.into_bytes()),
+    }
+}
+
+/// Decode a logical plan, resolving the functions it calls with `ctx`
+pub fn decode_logical_plan(
+    bytes: &[u8],
+    format: PlanFormat,
+    ctx: &TaskContext,
+) -> Result<LogicalPlan> {
+    match format {
+        PlanFormat::Proto => logical_plan_from_bytes(bytes, ctx),
+        PlanFormat::Json => logical_plan_from_json(json_str(bytes)?, ctx),
+    }
+}
+
+/// Decode a physical plan, resolving the functions it calls with `ctx`
+pub fn decode_physical_plan(
+    bytes: &[u8],
+    format: PlanFormat,
+    ctx: &TaskContext,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    match format {
+        PlanFormat::Proto => physical_plan_from_bytes(bytes, ctx),
+        PlanFormat::Json => physical_plan_from_json(json_str(bytes)?, ctx),
+    }
+}
+
+fn json_str(bytes: &[u8]) -> Result<&str> {
+    match std::str::from_utf8(bytes) {
+        Ok(json) => Ok(json),
+        Err(e) => plan_err!("Plan JSON isn't valid UTF-8: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+
+    #[test]
+    fn test_plan_file_names() {
+        let name = plan_file_name("query_0", PlanKind::Physical, PlanFormat::Json);
+        assert_eq!(name, "query_0.physical.json");
+        let path = PathBuf::from(name);
+        assert_eq!(PlanKind::from_path(&path).unwrap(), PlanKind::Physical);
+        assert_eq!(PlanFormat::from_path(&path), PlanFormat::Json);
+
+        let path = PathBuf::from("plans/query_1.logical.pb");
+        assert_eq!(PlanKind::from_path(&path).unwrap(), PlanKind::Logical);
+        assert_eq!(PlanFormat::from_path(&path), PlanFormat::Proto);
+
+        assert!(PlanKind::from_path(&PathBuf::from("plan.pb")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plans_round_trip() {
+        let ctx = SessionContext::new();
+        let df = ctx
+            .sql("SELECT a + 1 AS b FROM (VALUES (1), (2)) AS t(a)")
+            .await
+            .unwrap();
+        let logical_plan = df.logical_plan().clone();
+        let physical_plan = df.create_physical_plan().await.unwrap();
+        let task_ctx = ctx.task_ctx();
+
+        for format in [PlanFormat::Proto, PlanFormat::Json] {
+            let bytes = encode_logical_plan(&logical_plan, format).unwrap();
+            let decoded = decode_logical_plan(&bytes, format, &task_ctx).unwrap();
+            assert_eq!(decoded.schema(), logical_plan.schema());
+
+            let bytes = encode_physical_plan(Arc::clone(&physical_plan), format).unwrap();
+            let decoded = decode_physical_plan(&bytes, format, &task_ctx).unwrap();
+            assert_eq!(decoded.schema(), physical_plan.schema());
+        }
+    }
+}
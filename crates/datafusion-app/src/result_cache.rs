@@ -0,0 +1,469 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cache of query results keyed by the query's logical plan
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use datafusion::{
+    arrow::{datatypes::SchemaRef, record_batch::RecordBatch},
+    common::{
+        tree_node::{TreeNode, TreeNodeRecursion},
+        Result,
+    },
+    execution::{
+        memory_pool::{MemoryConsumer, MemoryPool, MemoryReservation},
+        SendableRecordBatchStream, SessionState,
+    },
+    logical_expr::LogicalPlan,
+    physical_plan::stream::RecordBatchStreamAdapter,
+};
+use futures::StreamExt;
+use log::debug;
+use parking_lot::Mutex;
+
+use crate::{config::ResultCacheConfig, rewrite::QueryIdentity, ExecOptions};
+
+/// Results of queries that were executed recently, returned instead of executing the same query
+/// again while they are fresh.
+///
+/// Results are keyed by the query's logical plan, so queries that only differ in formatting share
+/// results, along with its parameters, the session's configuration, who sent it and a version
+/// that is incremented whenever a statement changes the catalog or session. Queries with volatile
+/// functions, such as `now()` or `random()`, are never cached. Cached results are reserved from
+/// the memory pool of the session that computed them.
+#[derive(Debug)]
+pub struct ResultCache {
+    ttl: Duration,
+    max_bytes: usize,
+    version: AtomicU64,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    results: HashMap<ResultCacheKey, CachedResult>,
+    bytes: usize,
+}
+
+#[derive(Debug)]
+struct CachedResult {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    bytes: usize,
+    created: Instant,
+    /// The memory of `batches`, freed once they're evicted
+    _reservation: MemoryReservation,
+}
+
+/// Everything the results of a query depend on. Lookups compare all of it, not only its hash, so
+/// queries whose keys collide don't share results.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResultCacheKey {
+    hash: u64,
+    plan: LogicalPlan,
+    limit: Option<usize>,
+    offset: usize,
+    params: String,
+    settings: Vec<(String, Option<String>)>,
+    identity: QueryIdentity,
+    version: u64,
+}
+
+impl Hash for ResultCacheKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state)
+    }
+}
+
+/// Point in time counts of a [`ResultCache`]'s usage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResultCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl ResultCache {
+    pub fn new(config: &ResultCacheConfig) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_bytes: config.max_bytes,
+            version: AtomicU64::new(0),
+            entries: Mutex::new(Entries::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Version of the catalog and session that cached results were computed against
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Drop all cached results, for example because a table they were read from changed
+    pub fn invalidate(&self) {
+        self.version.fetch_add(1, Ordering::AcqRel);
+        let mut entries = self.entries.lock();
+        entries.results.clear();
+        entries.bytes = 0;
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        let entries = self.entries.lock();
+        ResultCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: entries.results.len(),
+            bytes: entries.bytes,
+        }
+    }
+
+    /// The key of the results of `plan` executed in `state` with `opts`
+    pub fn key(
+        &self,
+        state: &SessionState,
+        plan: &LogicalPlan,
+        opts: &ExecOptions,
+    ) -> ResultCacheKey {
+        // Settings changed with `SET`, such as the time zone, can change the results
+        let settings = state
+            .config_options()
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect();
+        let mut key = ResultCacheKey {
+            hash: 0,
+            plan: plan.clone(),
+            limit: opts.limit,
+            offset: opts.offset,
+            params: format!("{:?}", opts.params),
+            settings,
+            identity: opts.identity.clone(),
+            version: self.version(),
+        };
+        let mut hasher = DefaultHasher::new();
+        key.plan.hash(&mut hasher);
+        key.limit.hash(&mut hasher);
+        key.offset.hash(&mut hasher);
+        key.params.hash(&mut hasher);
+        key.settings.hash(&mut hasher);
+        key.identity.hash(&mut hasher);
+        key.version.hash(&mut hasher);
+        key.hash = hasher.finish();
+        key
+    }
+
+    /// Stream the cached results of `key` if they are fresh
+    pub fn get(&self, key: &ResultCacheKey) -> Option<SendableRecordBatchStream> {
+        let mut entries = self.entries.lock();
+        let result = match entries.results.get(key) {
+            Some(cached) if cached.created.elapsed() < self.ttl => {
+                let batches = cached.batches.clone();
+                let stream = futures::stream::iter(batches.into_iter().map(Ok));
+                Some(Box::pin(RecordBatchStreamAdapter::new(
+                    Arc::clone(&cached.schema),
+                    stream,
+                )) as SendableRecordBatchStream)
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        if result.is_some() {
+            debug!("Result cache hit for {}", key.hash);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Return a stream of the batches of `stream` that caches them under `key` once it has been
+    /// consumed. The batches are reserved from `pool` as they're collected. Results that fail,
+    /// are larger than the cache, or don't fit in the pool are not cached.
+    pub fn cache_stream(
+        self: &Arc<Self>,
+        key: ResultCacheKey,
+        stream: SendableRecordBatchStream,
+        pool: &Arc<dyn MemoryPool>,
+    ) -> SendableRecordBatchStream {
+        let schema = stream.schema();
+        let reservation = MemoryConsumer::new("ResultCache").register(pool);
+        let collected = Arc::new(Mutex::new(Some((Vec::new(), reservation))));
+        let inspected = Arc::clone(&collected);
+        let max_bytes = self.max_bytes;
+        let stream = stream.inspect(move |batch| {
+            let mut collected = inspected.lock();
+            let fits = match (batch, collected.as_mut()) {
+                (Ok(batch), Some((batches, reservation))) => {
+                    let size = batch.get_array_memory_size();
+                    let fits = reservation.size() + size <= max_bytes
+                        && reservation.try_grow(size).is_ok();
+                    if fits {
+                        batches.push(batch.clone());
+                    }
+                    fits
+                }
+                _ => false,
+            };
+            if !fits {
+                // Dropping the reservation frees what was collected so far
+                *collected = None;
+            }
+        });
+
+        let cache = Arc::clone(self);
+        let cached_schema = Arc::clone(&schema);
+        let done = futures::stream::once(async move {
+            if let Some((batches, reservation)) = collected.lock().take() {
+                cache.insert(key, cached_schema, batches, reservation);
+            }
+            None::<Result<RecordBatch>>
+        })
+        .filter_map(futures::future::ready);
+        Box::pin(RecordBatchStreamAdapter::new(schema, stream.chain(done)))
+    }
+
+    /// Return a stream of the batches of `stream`, the results of a statement that changes the
+    /// catalog or session, that invalidates the cache once it has been consumed. DML statements
+    /// only change their tables while their stream is consumed.
+    pub fn invalidate_after(
+        self: &Arc<Self>,
+        stream: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        self.invalidate();
+        let schema = stream.schema();
+        let cache = Arc::clone(self);
+        let done = futures::stream::once(async move {
+            cache.invalidate();
+            None::<Result<RecordBatch>>
+        })
+        .filter_map(futures::future::ready);
+        Box::pin(RecordBatchStreamAdapter::new(schema, stream.chain(done)))
+    }
+
+    fn insert(
+        &self,
+        key: ResultCacheKey,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        reservation: MemoryReservation,
+    ) {
+        let bytes = reservation.size();
+        let mut entries = self.entries.lock();
+        let ttl = self.ttl;
+        entries
+            .results
+            .retain(|_, cached| cached.created.elapsed() < ttl);
+        entries.remove(&key);
+        while entries.bytes + bytes > self.max_bytes {
+            let oldest = entries
+                .results
+                .iter()
+                .min_by_key(|(_, cached)| cached.created)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            }
+        }
+        debug!("Caching {bytes} bytes of results for {}", key.hash);
+        entries.bytes += bytes;
+        entries.results.insert(
+            key,
+            CachedResult {
+                schema,
+                batches,
+                bytes,
+                created: Instant::now(),
+                _reservation: reservation,
+            },
+        );
+    }
+}
+
+impl Entries {
+    fn remove(&mut self, key: &ResultCacheKey) {
+        if let Some(cached) = self.results.remove(key) {
+            self.bytes -= cached.bytes;
+        }
+    }
+}
+
+/// Whether the results of `plan` can be cached, which they can't if it changes the catalog or
+/// session or has volatile functions
+pub fn is_cacheable(plan: &LogicalPlan) -> bool {
+    if changes_state(plan) {
+        return false;
+    }
+    let mut cacheable = true;
+    let _ = plan.apply_with_subqueries(|node| {
+        if node.expressions().iter().any(|e| e.is_volatile()) {
+            cacheable = false;
+            return Ok(TreeNodeRecursion::Stop);
+        }
+        Ok(TreeNodeRecursion::Continue)
+    });
+    cacheable
+}
+
+/// Whether `plan` changes the catalog, the tables in it, or the session, in which case results
+/// cached before it was executed may be stale
+pub fn changes_state(plan: &LogicalPlan) -> bool {
+    // Statements can be nested, for example in `EXPLAIN ANALYZE INSERT ...`
+    let mut changes = false;
+    let _ = plan.apply_with_subqueries(|node| {
+        if matches!(
+            node,
+            LogicalPlan::Ddl(_)
+                | LogicalPlan::Dml(_)
+                | LogicalPlan::Copy(_)
+                | LogicalPlan::Statement(_)
+        ) {
+            changes = true;
+            return Ok(TreeNodeRecursion::Stop);
+        }
+        Ok(TreeNodeRecursion::Continue)
+    });
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{execution::memory_pool::GreedyMemoryPool, prelude::SessionContext};
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    fn cache(max_bytes: usize) -> Arc<ResultCache> {
+        Arc::new(ResultCache::new(&ResultCacheConfig {
+            enabled: true,
+            ttl_secs: 60,
+            max_bytes,
+        }))
+    }
+
+    async fn plan(ctx: &SessionContext, sql: &str) -> LogicalPlan {
+        ctx.state().create_logical_plan(sql).await.unwrap()
+    }
+
+    async fn execute(ctx: &SessionContext, plan: LogicalPlan) -> SendableRecordBatchStream {
+        ctx.execute_logical_plan(plan)
+            .await
+            .unwrap()
+            .execute_stream()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit() {
+        let ctx = SessionContext::new();
+        let cache = cache(1024 * 1024);
+        let opts = ExecOptions::new(None, false);
+
+        let plan1 = plan(&ctx, "SELECT 1 AS a").await;
+        let key = cache.key(&ctx.state(), &plan1, &opts);
+        assert!(cache.get(&key).is_none());
+        let runtime_env = ctx.runtime_env();
+        let pool = &runtime_env.memory_pool;
+        let stream = cache.cache_stream(key.clone(), execute(&ctx, plan1).await, pool);
+        let expected: Vec<RecordBatch> = stream.try_collect().await.unwrap();
+
+        // The same query written differently has the same plan
+        let plan2 = plan(&ctx, "select   1 as a").await;
+        assert_eq!(cache.key(&ctx.state(), &plan2, &opts), key);
+        let cached: Vec<RecordBatch> = cache.get(&key).unwrap().try_collect().await.unwrap();
+        assert_eq!(cached, expected);
+        // The results are reserved from the pool while they're cached
+        assert_eq!(pool.reserved(), cache.stats().bytes);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        // Keys with the same hash only share results if the rest of the key is equal
+        let mut collided = cache.key(&ctx.state(), &plan(&ctx, "SELECT 2 AS a").await, &opts);
+        collided.hash = key.hash;
+        assert!(cache.get(&collided).is_none());
+        let other_user = ExecOptions::new(None, false).with_identity(QueryIdentity::user("other"));
+        assert_ne!(cache.key(&ctx.state(), &plan2, &other_user), key);
+
+        cache.invalidate();
+        assert_ne!(cache.key(&ctx.state(), &plan2, &opts), key);
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(pool.reserved(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_results_over_budget_not_cached() {
+        let ctx = SessionContext::new();
+        let cache = cache(1);
+        let plan = plan(&ctx, "SELECT * FROM generate_series(1, 1000)").await;
+        let key = cache.key(&ctx.state(), &plan, &ExecOptions::new(None, false));
+        let runtime_env = ctx.runtime_env();
+        let pool = &runtime_env.memory_pool;
+        let stream = cache.cache_stream(key.clone(), execute(&ctx, plan).await, pool);
+        let _: Vec<RecordBatch> = stream.try_collect().await.unwrap();
+        assert!(cache.get(&key).is_none());
+        assert_eq!(pool.reserved(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_results_over_pool_not_cached() {
+        let ctx = SessionContext::new();
+        let cache = cache(1024 * 1024);
+        let plan = plan(&ctx, "SELECT * FROM generate_series(1, 1000)").await;
+        let key = cache.key(&ctx.state(), &plan, &ExecOptions::new(None, false));
+        let pool: Arc<dyn MemoryPool> = Arc::new(GreedyMemoryPool::new(1));
+        let stream = cache.cache_stream(key.clone(), execute(&ctx, plan).await, &pool);
+        let _: Vec<RecordBatch> = stream.try_collect().await.unwrap();
+        assert!(cache.get(&key).is_none());
+        assert_eq!(pool.reserved(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_is_cacheable() {
+        let ctx = SessionContext::new();
+        assert!(is_cacheable(&plan(&ctx, "SELECT 1").await));
+        assert!(!is_cacheable(&plan(&ctx, "SELECT random()").await));
+        assert!(!is_cacheable(
+            &plan(&ctx, "CREATE TABLE t AS VALUES (1)").await
+        ));
+        assert!(!is_cacheable(
+            &plan(&ctx, "SET datafusion.execution.batch_size = 1").await
+        ));
+        ctx.sql("CREATE TABLE t (a INT)").await.unwrap();
+        assert!(changes_state(
+            &plan(&ctx, "EXPLAIN ANALYZE INSERT INTO t VALUES (1)").await
+        ));
+    }
+}
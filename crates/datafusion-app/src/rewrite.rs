@@ -0,0 +1,217 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Hooks that rewrite the logical plans of statements before they are executed, for example to
+//! only let each user read its own rows
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{Result, TableReference};
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::{DdlStatement, Expr, LogicalPlan, LogicalPlanBuilder};
+use datafusion::optimizer::analyzer::inline_table_scan::InlineTableScan;
+use datafusion::optimizer::AnalyzerRule;
+
+use crate::system::filter_own_queries;
+
+/// Who sent a statement
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QueryIdentity {
+    /// The user the client authenticated as, unset for anonymous clients and clients
+    /// authenticated with a bearer token
+    pub user: Option<String>,
+}
+
+impl QueryIdentity {
+    pub fn user(user: impl Into<String>) -> Self {
+        Self {
+            user: Some(user.into()),
+        }
+    }
+}
+
+/// Rewrites the logical plans of the statements executed by a session. Registered with
+/// [`crate::extensions::DftSessionStateBuilder::add_plan_rewriter`].
+pub trait PlanRewriter: Debug + Send + Sync {
+    /// Rewrite `plan`, the unoptimized plan of a statement sent by `identity`. Returning an error
+    /// rejects the statement.
+    fn rewrite(&self, plan: LogicalPlan, identity: &QueryIdentity) -> Result<LogicalPlan>;
+}
+
+/// The rewriters of a session, in the order they are applied, kept as an extension of its
+/// config
+#[derive(Debug, Default)]
+pub struct PlanRewriters(pub Vec<Arc<dyn PlanRewriter>>);
+
+/// Only let `identity` read its own queries from the system tables, see
+/// [`crate::system::filter_own_queries`], then apply the rewriters registered with `state`, if
+/// any, to `plan`.
+///
+/// Views are inlined first, as the analyzer would, so that rewriters see the tables they read
+/// rather than only their names, and a row policy can't be bypassed by selecting from a view over
+/// its table. The plans of `CREATE VIEW` are left as they are, they are rewritten for whoever
+/// selects from the view instead of for whoever created it.
+pub fn rewrite_plan(
+    state: &SessionState,
+    plan: LogicalPlan,
+    identity: &QueryIdentity,
+) -> Result<LogicalPlan> {
+    if matches!(plan, LogicalPlan::Ddl(DdlStatement::CreateView(_))) {
+        return Ok(plan);
+    }
+    let plan = InlineTableScan::new().analyze(plan, state.config_options())?;
+    let plan = filter_own_queries(state, plan, identity)?;
+    let Some(rewriters) = state.config().get_extension::<PlanRewriters>() else {
+        return Ok(plan);
+    };
+    rewriters
+        .0
+        .iter()
+        .try_fold(plan, |plan, rewriter| rewriter.rewrite(plan, identity))
+}
+
+/// Only return the rows of `table` matching `predicate` wherever it's scanned in `plan`,
+/// including in subqueries, which is how most row policies are implemented
+pub fn filter_table(
+    plan: LogicalPlan,
+    table: &TableReference,
+    predicate: Expr,
+) -> Result<LogicalPlan> {
+    filter_scans(plan, |name| name.resolved_eq(table), predicate)
+}
+
+/// Only return the rows matching `predicate` of the tables whose scans in `plan` have a name for
+/// which `is_filtered` returns true
+pub(crate) fn filter_scans(
+    plan: LogicalPlan,
+    is_filtered: impl Fn(&TableReference) -> bool,
+    predicate: Expr,
+) -> Result<LogicalPlan> {
+    plan.transform_up_with_subqueries(|plan| match &plan {
+        LogicalPlan::TableScan(scan) if is_filtered(&scan.table_name) => {
+            // Qualify the columns of the predicate as the scan is, which may be by an alias
+            let qualified = predicate.clone().transform(|expr| match expr {
+                Expr::Column(mut column) if column.relation.is_none() => {
+                    column.relation = Some(scan.table_name.clone());
+                    Ok(Transformed::yes(Expr::Column(column)))
+                }
+                expr => Ok(Transformed::no(expr)),
+            })?;
+            let filtered = LogicalPlanBuilder::from(plan)
+                .filter(qualified.data)?
+                .build()?;
+            Ok(Transformed::yes(filtered))
+        }
+        _ => Ok(Transformed::no(plan)),
+    })
+    .map(|transformed| transformed.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Int32Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::logical_expr::{col, lit};
+    use datafusion::prelude::{SessionConfig, SessionContext};
+
+    #[derive(Debug)]
+    struct TenantFilter;
+
+    impl PlanRewriter for TenantFilter {
+        fn rewrite(&self, plan: LogicalPlan, identity: &QueryIdentity) -> Result<LogicalPlan> {
+            let tenant = identity.user.clone().unwrap_or_default();
+            filter_table(
+                plan,
+                &TableReference::bare("t"),
+                col("tenant").eq(lit(tenant)),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_rows_by_identity() {
+        let config = SessionConfig::new()
+            .with_extension(Arc::new(PlanRewriters(vec![Arc::new(TenantFilter)])));
+        let ctx = SessionContext::new_with_config(config);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("tenant", DataType::Utf8, false),
+            Field::new("v", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "a"])),
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+            ],
+        )
+        .unwrap();
+        ctx.register_batch("t", batch).unwrap();
+
+        let state = ctx.state();
+        let plan = state
+            .create_logical_plan("SELECT sum(x.v) AS v FROM t AS x")
+            .await
+            .unwrap();
+        let plan = rewrite_plan(&state, plan, &QueryIdentity::user("a")).unwrap();
+        let batches = ctx
+            .execute_logical_plan(plan)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let sum = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<datafusion::arrow::array::Int64Array>()
+            .unwrap()
+            .value(0);
+        assert_eq!(sum, 4);
+
+        // Views over the table are filtered for whoever selects from them
+        let plan = state
+            .create_logical_plan("CREATE VIEW all_rows AS SELECT * FROM t")
+            .await
+            .unwrap();
+        let plan = rewrite_plan(&state, plan, &QueryIdentity::user("b")).unwrap();
+        ctx.execute_logical_plan(plan).await.unwrap();
+        let state = ctx.state();
+        let plan = state
+            .create_logical_plan("SELECT sum(v) AS v FROM all_rows")
+            .await
+            .unwrap();
+        let plan = rewrite_plan(&state, plan, &QueryIdentity::user("a")).unwrap();
+        let batches = ctx
+            .execute_logical_plan(plan)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let sum = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<datafusion::arrow::array::Int64Array>()
+            .unwrap()
+            .value(0);
+        assert_eq!(sum, 4);
+    }
+}
@@ -0,0 +1,258 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Secrets that credentials in the config and in `CREATE EXTERNAL TABLE` options refer to as
+//! `secret://<name>`, so they don't have to be written in plaintext. The references in the
+//! statements of the DDL file are always resolved, but those in statements run as queries are
+//! refused unless `query_secrets` is set, as anyone who can run a query could otherwise have the
+//! app use its secrets, for example to connect to an endpoint they choose.
+
+use std::{collections::HashMap, sync::Arc};
+
+use datafusion::{
+    catalog::{Session, TableProvider, TableProviderFactory},
+    common::{DataFusionError, Result},
+    execution::SessionState,
+    logical_expr::CreateExternalTable,
+};
+
+use crate::config::{ExecutionConfig, SecretSource};
+
+/// Prefix of values that refer to a secret
+pub const SECRET_SCHEME: &str = "secret://";
+
+/// Whether `value` refers to a secret
+pub fn is_secret_ref(value: &str) -> bool {
+    value.starts_with(SECRET_SCHEME)
+}
+
+/// The secrets of an [`ExecutionConfig`]. Values are read from their source each time they're
+/// resolved, so rotated secrets are picked up. They aren't logged or included in the errors of
+/// resolving them, but the errors of the table providers and object stores they're passed to
+/// can include them.
+#[derive(Clone, Debug, Default)]
+pub struct Secrets {
+    sources: Arc<HashMap<String, SecretSource>>,
+}
+
+impl Secrets {
+    pub fn new(sources: HashMap<String, SecretSource>) -> Self {
+        Self {
+            sources: Arc::new(sources),
+        }
+    }
+
+    pub fn from_config(config: &ExecutionConfig) -> Self {
+        Self::new(config.secrets.clone())
+    }
+
+    /// The secret `value` refers to, or `value` itself if it isn't a `secret://<name>` reference
+    pub fn resolve(&self, value: &str) -> Result<String> {
+        match value.strip_prefix(SECRET_SCHEME) {
+            Some(name) => self.get(name),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// [`Self::resolve`] for optional values
+    pub fn resolve_option(&self, value: Option<&String>) -> Result<Option<String>> {
+        value.map(|value| self.resolve(value)).transpose()
+    }
+
+    /// `options` with the values that refer to secrets replaced by the secrets
+    pub fn resolve_map(
+        &self,
+        options: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        options
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), self.resolve(value)?)))
+            .collect()
+    }
+
+    /// The value of the secret `name`
+    pub fn get(&self, name: &str) -> Result<String> {
+        let Some(source) = self.sources.get(name) else {
+            return Err(DataFusionError::Configuration(format!(
+                "Secret '{name}' isn't defined in the `secrets` config"
+            )));
+        };
+        match source {
+            SecretSource::Env(var) => std::env::var(var).map_err(|_| {
+                DataFusionError::Configuration(format!(
+                    "Secret '{name}' refers to the environment variable {var}, which isn't set"
+                ))
+            }),
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map(|secret| secret.trim_end().to_string())
+                .map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "Secret '{name}' couldn't be read from {}: {e}",
+                        path.display()
+                    ))
+                }),
+            SecretSource::Keyring { service, user } => keyring_password(name, service, user),
+        }
+    }
+}
+
+#[cfg(feature = "secrets-keyring")]
+fn keyring_password(name: &str, service: &str, user: &str) -> Result<String> {
+    keyring::Entry::new(service, user)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| {
+            DataFusionError::Configuration(format!(
+                "Secret '{name}' couldn't be read from the keyring entry for {user} of {service}: {e}"
+            ))
+        })
+}
+
+#[cfg(not(feature = "secrets-keyring"))]
+fn keyring_password(name: &str, _service: &str, _user: &str) -> Result<String> {
+    Err(DataFusionError::Configuration(format!(
+        "Secret '{name}' is stored in the OS keyring, which requires dft to be built with the `secrets-keyring` feature"
+    )))
+}
+
+/// Refuse `options`, of a statement run as a query, if they refer to secrets and `query_secrets`
+/// isn't set
+pub fn refuse_secret_refs(options: &HashMap<String, String>) -> Result<()> {
+    match options.iter().find(|(_, value)| is_secret_ref(value)) {
+        Some((key, _)) => Err(DataFusionError::Plan(format!(
+            "Option '{key}' refers to a secret, which can only be used in the DDL file and config unless `query_secrets` is set"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Wraps a [`TableProviderFactory`], resolving the secrets that the options of `CREATE EXTERNAL
+/// TABLE` statements refer to before the table is created, or refusing them if there are no
+/// secrets to resolve them with. The statement keeps the references, so DDL that is logged or
+/// persisted doesn't contain the secrets.
+#[derive(Debug)]
+pub struct SecretsTableFactory {
+    inner: Arc<dyn TableProviderFactory>,
+    secrets: Option<Secrets>,
+}
+
+impl SecretsTableFactory {
+    pub fn new(inner: Arc<dyn TableProviderFactory>, secrets: Option<Secrets>) -> Self {
+        Self { inner, secrets }
+    }
+}
+
+#[async_trait::async_trait]
+impl TableProviderFactory for SecretsTableFactory {
+    async fn create(
+        &self,
+        state: &dyn Session,
+        cmd: &CreateExternalTable,
+    ) -> Result<Arc<dyn TableProvider>> {
+        if !cmd.options.values().any(|value| is_secret_ref(value)) {
+            return self.inner.create(state, cmd).await;
+        }
+        let Some(secrets) = &self.secrets else {
+            return Err(refuse_secret_refs(&cmd.options).unwrap_err());
+        };
+        let mut cmd = cmd.clone();
+        cmd.options = secrets.resolve_map(&cmd.options)?;
+        self.inner.create(state, &cmd).await
+    }
+}
+
+/// Resolve the secrets in the options of the tables created by every table factory of `state`,
+/// or refuse them if `secrets` is `None`
+pub fn with_table_factory_secrets(state: &mut SessionState, secrets: Option<&Secrets>) {
+    for factory in state.table_factories_mut().values_mut() {
+        *factory = Arc::new(SecretsTableFactory::new(
+            Arc::clone(factory),
+            secrets.cloned(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+
+    fn secrets() -> Secrets {
+        Secrets::new(HashMap::from([
+            (
+                "from_env".to_string(),
+                SecretSource::Env("DFT_TEST_SECRET".to_string()),
+            ),
+            (
+                "missing_env".to_string(),
+                SecretSource::Env("DFT_TEST_SECRET_MISSING".to_string()),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn test_resolve() {
+        std::env::set_var("DFT_TEST_SECRET", "hunter2");
+        let secrets = secrets();
+        assert_eq!(secrets.resolve("secret://from_env").unwrap(), "hunter2");
+        assert_eq!(secrets.resolve("plain").unwrap(), "plain");
+
+        let err = secrets.resolve("secret://missing_env").unwrap_err();
+        assert!(err.to_string().contains("DFT_TEST_SECRET_MISSING"));
+        let err = secrets.resolve("secret://undefined").unwrap_err();
+        assert!(err.to_string().contains("'undefined' isn't defined"));
+    }
+
+    #[test]
+    fn test_resolve_file() {
+        let path = std::env::temp_dir().join(format!("dft-secret-{}", std::process::id()));
+        std::fs::write(&path, "s3cr3t\n").unwrap();
+        let secrets = Secrets::new(HashMap::from([(
+            "token".to_string(),
+            SecretSource::File(path.clone()),
+        )]));
+        let secret = secrets.resolve("secret://token");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(secret.unwrap(), "s3cr3t");
+    }
+
+    #[tokio::test]
+    async fn test_external_table_options() {
+        let ctx = SessionContext::new();
+        let mut state = ctx.state();
+        with_table_factory_secrets(&mut state, Some(&secrets()));
+        let ctx = SessionContext::new_with_state(state);
+
+        let sql = "CREATE EXTERNAL TABLE t STORED AS CSV LOCATION 'data/aggregate_test_100.csv' OPTIONS ('format.delimiter' 'secret://undefined')";
+        let err = ctx.sql(sql).await.unwrap_err();
+        assert!(err.to_string().contains("'undefined' isn't defined"));
+    }
+
+    #[tokio::test]
+    async fn test_external_table_options_refused() {
+        std::env::set_var("DFT_TEST_SECRET", "hunter2");
+        let ctx = SessionContext::new();
+        let mut state = ctx.state();
+        with_table_factory_secrets(&mut state, None);
+        let ctx = SessionContext::new_with_state(state);
+
+        let sql = "CREATE EXTERNAL TABLE t STORED AS CSV LOCATION 'data/aggregate_test_100.csv' OPTIONS ('format.delimiter' 'secret://from_env')";
+        let err = ctx.sql(sql).await.unwrap_err();
+        assert!(err.to_string().contains("`query_secrets`"), "{err}");
+        assert!(!err.to_string().contains("hunter2"));
+    }
+}
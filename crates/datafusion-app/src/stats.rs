@@ -36,6 +36,8 @@ use datafusion::{
 use itertools::Itertools;
 use std::{sync::Arc, time::Duration};
 
+use crate::local_benchmarks::duration_ms;
+
 #[derive(Clone, Debug)]
 pub struct ExecutionStats {
     query: String,
@@ -45,6 +47,8 @@ pub struct ExecutionStats {
     durations: ExecutionDurationStats,
     io: Option<ExecutionIOStats>,
     compute: Option<ExecutionComputeStats>,
+    spill: Option<ExecutionSpillStats>,
+    operators: Option<ExecutionOperatorStats>,
     plan: Arc<dyn ExecutionPlan>,
 }
 
@@ -66,6 +70,8 @@ impl ExecutionStats {
             plan,
             io: None,
             compute: None,
+            spill: None,
+            operators: None,
         })
     }
 
@@ -76,6 +82,27 @@ impl ExecutionStats {
         if let Some(compute) = collect_plan_compute_stats(Arc::clone(&self.plan)) {
             self.compute = Some(compute)
         }
+        self.spill = collect_plan_spill_stats(Arc::clone(&self.plan));
+        self.operators = collect_plan_operator_stats(Arc::clone(&self.plan));
+    }
+
+    /// The metrics of each operator in the executed plan, collected now if
+    /// [`Self::collect_stats`] hasn't been called
+    pub fn operator_stats(&self) -> Option<ExecutionOperatorStats> {
+        self.operators
+            .clone()
+            .or_else(|| collect_plan_operator_stats(Arc::clone(&self.plan)))
+    }
+
+    /// Bytes read by the file sources of the plan, once [`Self::collect_stats`] has been called
+    pub fn bytes_scanned(&self) -> Option<usize> {
+        self.operators
+            .as_ref()
+            .map(|operators| operators.total_bytes_scanned())
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
     }
 
     pub fn rows_selectivity(&self) -> f64 {
@@ -103,6 +130,25 @@ impl ExecutionStats {
             0.0
         }
     }
+
+    /// The same stats as the `Display` implementation as a JSON object, with durations in
+    /// milliseconds and compute times in nanoseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "query": self.query,
+            "rows": self.rows,
+            "bytes": self.bytes,
+            "batches": self.batches,
+            "rows_selectivity": self.rows_selectivity(),
+            "bytes_selectivity": self.bytes_selectivity(),
+            "selectivity_efficiency": self.selectivity_efficiency(),
+            "durations": self.durations.to_json(),
+            "io": self.io.as_ref().map(|io| io.to_json()),
+            "compute": self.compute.as_ref().map(|compute| compute.to_json()),
+            "spill": self.spill.as_ref().map(|spill| spill.to_json()),
+            "operators": self.operators.as_ref().map(|operators| operators.to_json()),
+        })
+    }
 }
 
 impl std::fmt::Display for ExecutionStats {
@@ -139,6 +185,12 @@ impl std::fmt::Display for ExecutionStats {
         if let Some(compute_stats) = &self.compute {
             writeln!(f, "{}", compute_stats)?;
         };
+        if let Some(spill_stats) = &self.spill {
+            writeln!(f, "{}", spill_stats)?;
+        };
+        if let Some(operator_stats) = &self.operators {
+            writeln!(f, "{}", operator_stats)?;
+        };
         Ok(())
     }
 }
@@ -168,6 +220,16 @@ impl ExecutionDurationStats {
             total,
         }
     }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "parsing_ms": duration_ms(self.parsing),
+            "logical_planning_ms": duration_ms(self.logical_planning),
+            "physical_planning_ms": duration_ms(self.physical_planning),
+            "execution_ms": duration_ms(self.execution),
+            "total_ms": duration_ms(self.total),
+        })
+    }
 }
 
 impl std::fmt::Display for ExecutionDurationStats {
@@ -255,6 +317,19 @@ impl ExecutionIOStats {
             0
         }
     }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "bytes_scanned": self.bytes_scanned.as_ref().map(|m| m.as_usize()),
+            "time_opening_ns": self.time_opening.as_ref().map(|m| m.as_usize()),
+            "time_scanning_ns": self.time_scanning.as_ref().map(|m| m.as_usize()),
+            "parquet_output_rows": self.parquet_output_rows,
+            "row_groups": self.row_group_count(),
+            "matched_rg_stats_ratio": self.parquet_rg_pruned_stats_ratio(),
+            "matched_rg_bloom_filter_ratio": self.parquet_rg_pruned_bloom_filter_ratio(),
+            "matched_page_index_ratio": self.parquet_rg_pruned_page_index_ratio(),
+        })
+    }
 }
 
 impl std::fmt::Display for ExecutionIOStats {
@@ -431,6 +506,19 @@ impl PartitionsComputeStats {
     fn partitions(&self) -> usize {
         self.elapsed_computes.len()
     }
+
+    fn to_json(&self) -> serde_json::Value {
+        let (min, median, mean, max, total) = self.summary_stats();
+        serde_json::json!({
+            "name": self.name,
+            "partitions": self.partitions(),
+            "min_ns": min,
+            "median_ns": median,
+            "mean_ns": mean,
+            "max_ns": max,
+            "total_ns": total,
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -486,6 +574,23 @@ impl ExecutionComputeStats {
             writeln!(f, "No {label} Stats")
         }
     }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let nodes = |compute: &Option<Vec<PartitionsComputeStats>>| {
+            compute
+                .as_ref()
+                .map(|nodes| nodes.iter().map(|n| n.to_json()).collect::<Vec<_>>())
+        };
+        serde_json::json!({
+            "elapsed_compute_ns": self.elapsed_compute,
+            "projection": nodes(&self.projection_compute),
+            "filter": nodes(&self.filter_compute),
+            "sort": nodes(&self.sort_compute),
+            "join": nodes(&self.join_compute),
+            "aggregate": nodes(&self.aggregate_compute),
+            "other": nodes(&self.other_compute),
+        })
+    }
 }
 
 impl std::fmt::Display for ExecutionComputeStats {
@@ -731,6 +836,170 @@ impl ExecutionPlanVisitor for PlanComputeVisitor {
     }
 }
 
+/// Metrics for a single operator of an executed plan, aggregated across its partitions
+#[derive(Clone, Debug)]
+pub struct OperatorStats {
+    name: String,
+    /// Depth of the operator in the plan, with the root at zero
+    depth: usize,
+    partitions: usize,
+    output_rows: Option<usize>,
+    elapsed_compute: Option<usize>,
+    peak_memory: Option<usize>,
+    /// Number of times the operator spilled to disk, if it can spill
+    spill_count: Option<usize>,
+    /// Bytes read from files, for file sources
+    bytes_scanned: Option<usize>,
+}
+
+/// Per operator metrics for an executed plan, in plan order (root first)
+#[derive(Clone, Debug)]
+pub struct ExecutionOperatorStats {
+    operators: Vec<OperatorStats>,
+}
+
+impl ExecutionOperatorStats {
+    pub fn operators(&self) -> &[OperatorStats] {
+        &self.operators
+    }
+
+    fn total_elapsed_compute(&self) -> usize {
+        self.operators
+            .iter()
+            .filter_map(|o| o.elapsed_compute)
+            .sum()
+    }
+
+    /// Bytes read by all of the file sources of the plan
+    pub fn total_bytes_scanned(&self) -> usize {
+        self.operators.iter().filter_map(|o| o.bytes_scanned).sum()
+    }
+
+    /// Number of times any operator of the plan spilled to disk
+    pub fn total_spill_count(&self) -> usize {
+        self.operators.iter().filter_map(|o| o.spill_count).sum()
+    }
+
+    /// Operators as a JSON array in plan order, with compute times in nanoseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        self.operators
+            .iter()
+            .map(|op| {
+                serde_json::json!({
+                    "name": op.name,
+                    "depth": op.depth,
+                    "partitions": op.partitions,
+                    "output_rows": op.output_rows,
+                    "elapsed_compute_ns": op.elapsed_compute,
+                    "peak_memory": op.peak_memory,
+                    "spill_count": op.spill_count,
+                    "bytes_scanned": op.bytes_scanned,
+                })
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for ExecutionOperatorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "============================================ Operator Summary ============================================"
+        )?;
+        writeln!(
+            f,
+            "{:<40} {:<12} {:<14} {:<8} {:<14} {:<20} {:<16}",
+            "Operator(Partitions)",
+            "Output Rows",
+            "Peak Memory",
+            "Spills",
+            "Bytes Scanned",
+            "Elapsed Compute",
+            "Compute (%)"
+        )?;
+        let total_elapsed_compute = self.total_elapsed_compute();
+        self.operators.iter().try_for_each(|op| {
+            let compute = op
+                .elapsed_compute
+                .map(|c| {
+                    let pct = if total_elapsed_compute == 0 {
+                        0.0
+                    } else {
+                        (c as f64 / total_elapsed_compute as f64) * 100.0
+                    };
+                    (
+                        format!("{:?}", Duration::from_nanos(c as u64)),
+                        format!("{:.2}", pct),
+                    )
+                })
+                .unwrap_or(("None".to_string(), "None".to_string()));
+            writeln!(
+                f,
+                "{:<40} {:<12} {:<14} {:<8} {:<14} {:<20} {:<16}",
+                format!("{}{}({})", "  ".repeat(op.depth), op.name, op.partitions),
+                op.output_rows
+                    .map(|r| r.to_string())
+                    .unwrap_or("None".to_string()),
+                op.peak_memory
+                    .map(|m| m.to_string())
+                    .unwrap_or("None".to_string()),
+                op.spill_count
+                    .map(|s| s.to_string())
+                    .unwrap_or("None".to_string()),
+                op.bytes_scanned
+                    .map(|b| b.to_string())
+                    .unwrap_or("None".to_string()),
+                compute.0,
+                compute.1,
+            )
+        })?;
+        writeln!(
+            f,
+            "Total: {} spills, {} bytes scanned",
+            self.total_spill_count(),
+            self.total_bytes_scanned()
+        )
+    }
+}
+
+/// Visitor to collect the metrics of every operator in an execution plan
+#[derive(Default)]
+struct PlanOperatorVisitor {
+    depth: usize,
+    operators: Vec<OperatorStats>,
+}
+
+impl ExecutionPlanVisitor for PlanOperatorVisitor {
+    type Error = datafusion::common::DataFusionError;
+
+    fn pre_visit(&mut self, plan: &dyn ExecutionPlan) -> color_eyre::Result<bool, Self::Error> {
+        let metrics = plan.metrics();
+        self.operators.push(OperatorStats {
+            name: plan.name().to_string(),
+            depth: self.depth,
+            partitions: plan.properties().output_partitioning().partition_count(),
+            output_rows: metrics.as_ref().and_then(|m| m.output_rows()),
+            elapsed_compute: metrics.as_ref().and_then(|m| m.elapsed_compute()),
+            peak_memory: metrics
+                .as_ref()
+                .and_then(|m| m.sum_by_name("peak_mem_used"))
+                .map(|m| m.as_usize()),
+            spill_count: metrics.as_ref().and_then(|m| m.spill_count()),
+            bytes_scanned: metrics
+                .as_ref()
+                .and_then(|m| m.sum_by_name("bytes_scanned"))
+                .map(|m| m.as_usize()),
+        });
+        self.depth += 1;
+        Ok(true)
+    }
+
+    fn post_visit(&mut self, _plan: &dyn ExecutionPlan) -> color_eyre::Result<bool, Self::Error> {
+        self.depth -= 1;
+        Ok(true)
+    }
+}
+
 fn is_io_plan(plan: &dyn ExecutionPlan) -> bool {
     let io_plans = ["CsvExec", "ParquetExec", "ArrowExec"];
     io_plans.contains(&plan.name())
@@ -754,6 +1023,93 @@ pub fn collect_plan_compute_stats(plan: Arc<dyn ExecutionPlan>) -> Option<Execut
     }
 }
 
+/// Data written to disk by operators that ran out of memory, such as sorts, aggregations and
+/// joins, summed over the whole plan
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionSpillStats {
+    spill_count: usize,
+    spilled_bytes: usize,
+    spilled_rows: usize,
+}
+
+impl ExecutionSpillStats {
+    pub fn spill_count(&self) -> usize {
+        self.spill_count
+    }
+
+    pub fn spilled_bytes(&self) -> usize {
+        self.spilled_bytes
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "spill_count": self.spill_count,
+            "spilled_bytes": self.spilled_bytes,
+            "spilled_rows": self.spilled_rows,
+        })
+    }
+}
+
+impl std::fmt::Display for ExecutionSpillStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "====================== Spill Summary ======================"
+        )?;
+        writeln!(
+            f,
+            "{:<20} {:<20} {:<20}",
+            "Spills", "Spilled Bytes", "Spilled Rows"
+        )?;
+        writeln!(
+            f,
+            "{:<20} {:<20} {:<20}",
+            self.spill_count, self.spilled_bytes, self.spilled_rows
+        )?;
+        Ok(())
+    }
+}
+
+/// Visitor summing the spill metrics of the operators that can spill
+#[derive(Default)]
+struct PlanSpillVisitor {
+    stats: Option<ExecutionSpillStats>,
+}
+
+impl ExecutionPlanVisitor for PlanSpillVisitor {
+    type Error = datafusion::common::DataFusionError;
+
+    fn pre_visit(&mut self, plan: &dyn ExecutionPlan) -> color_eyre::Result<bool, Self::Error> {
+        if let Some(metrics) = plan.metrics() {
+            if let Some(spill_count) = metrics.spill_count() {
+                let stats = self.stats.get_or_insert_with(ExecutionSpillStats::default);
+                stats.spill_count += spill_count;
+                stats.spilled_bytes += metrics.spilled_bytes().unwrap_or_default();
+                stats.spilled_rows += metrics.spilled_rows().unwrap_or_default();
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Collect the spill metrics of `plan`, or `None` if none of its operators can spill
+pub fn collect_plan_spill_stats(plan: Arc<dyn ExecutionPlan>) -> Option<ExecutionSpillStats> {
+    let mut visitor = PlanSpillVisitor::default();
+    visit_execution_plan(plan.as_ref(), &mut visitor).ok()?;
+    visitor.stats
+}
+
+pub fn collect_plan_operator_stats(plan: Arc<dyn ExecutionPlan>) -> Option<ExecutionOperatorStats> {
+    let mut visitor = PlanOperatorVisitor::default();
+    if visit_execution_plan(plan.as_ref(), &mut visitor).is_ok() {
+        Some(ExecutionOperatorStats {
+            operators: visitor.operators,
+        })
+    } else {
+        None
+    }
+}
+
 pub fn print_io_summary(plan: Arc<dyn ExecutionPlan>) {
     println!("======================= IO Summary ========================");
     if let Some(stats) = collect_plan_io_stats(plan) {
@@ -0,0 +1,708 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Tables of the `system` schema, for introspecting the app with SQL: the queries it executed,
+//! the queries it's executing and the object stores it registered.
+//!
+//! The queries are recorded with the user that sent them, and the statements of clients, which
+//! are rewritten with [`crate::rewrite::rewrite_plan`], only see the queries of their own user, see
+//! [`filter_own_queries`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::Path,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use datafusion::{
+    arrow::{
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    catalog::{MemorySchemaProvider, SchemaProvider},
+    common::{DataFusionError, Result},
+    execution::{SendableRecordBatchStream, SessionState},
+    logical_expr::{col, lit, LogicalPlan},
+    physical_plan::RecordBatchStream,
+    prelude::SessionContext,
+    scalar::ScalarValue,
+};
+use futures::{Stream, StreamExt};
+use indexmap::IndexMap;
+use log::warn;
+use parking_lot::{Mutex, RwLock};
+
+use crate::{
+    config::{ExecutionConfig, SystemTablesConfig},
+    rewrite::{filter_scans, QueryIdentity},
+    tables::map_table::{
+        load_map_storage, save_map_storage, MapData, MapRow, MapTable, MapTableConfig,
+    },
+};
+
+pub const SYSTEM_SCHEMA_NAME: &str = "system";
+const QUERIES_TABLE_NAME: &str = "queries";
+const RUNNING_QUERIES_TABLE_NAME: &str = "running_queries";
+const OBJECT_STORES_TABLE_NAME: &str = "object_stores";
+/// Name of the file in the directory passed to [`SystemTables::save`] that `system.queries` is
+/// saved to
+const QUERIES_FILE: &str = "queries.arrow";
+/// Name of the file in the same directory that's locked while the history is saved
+const QUERIES_LOCK_FILE: &str = "queries.lock";
+
+/// The catalog whose `system` schema the tables are registered in, kept as an extension of the
+/// session's config so that [`filter_own_queries`] can find them
+#[derive(Debug)]
+struct SystemCatalog(String);
+
+/// Data of the `system.queries`, `system.running_queries` and `system.object_stores` tables.
+/// Clones share their data, so the tables registered with a session reflect the queries of every
+/// session forked from it.
+#[derive(Clone, Debug)]
+pub struct SystemTables {
+    queries: MapData,
+    running_queries: MapData,
+    object_stores: MapData,
+    next_query_id: Arc<AtomicU64>,
+    /// Ids of the queries that are in the history saved by [`Self::save`], as they were loaded
+    /// from it or already saved to it
+    persisted: Arc<Mutex<HashSet<u64>>>,
+    query_history_size: usize,
+}
+
+impl SystemTables {
+    pub fn new(config: &SystemTablesConfig) -> Self {
+        Self {
+            queries: Arc::new(RwLock::new(IndexMap::new())),
+            running_queries: Arc::new(RwLock::new(IndexMap::new())),
+            object_stores: Arc::new(RwLock::new(IndexMap::new())),
+            next_query_id: Arc::new(AtomicU64::new(1)),
+            persisted: Arc::new(Mutex::new(HashSet::new())),
+            query_history_size: config.query_history_size,
+        }
+    }
+
+    /// Tables for the app configured with `config`, listing the object stores it configures
+    pub fn from_config(config: &ExecutionConfig) -> Self {
+        let tables = Self::new(&config.system_tables);
+        #[cfg(any(
+            feature = "s3",
+            feature = "gcs",
+            feature = "azure",
+            feature = "huggingface"
+        ))]
+        if let Some(object_stores) = &config.object_store {
+            #[cfg(feature = "s3")]
+            for s3 in object_stores.s3.iter().flatten() {
+                if let Some(url) = s3.object_store_url() {
+                    tables.add_object_store(url, "s3");
+                }
+            }
+            #[cfg(feature = "gcs")]
+            for gcs in object_stores.gcs.iter().flatten() {
+                tables.add_object_store(gcs.object_store_url(), "gcs");
+            }
+            #[cfg(feature = "azure")]
+            for azure in object_stores.azure.iter().flatten() {
+                tables.add_object_store(azure.object_store_url(), "azure");
+            }
+            #[cfg(feature = "huggingface")]
+            for huggingface in object_stores.huggingface.iter().flatten() {
+                if let Some(repo_id) = &huggingface.repo_id {
+                    let url = format!("hf://{}", repo_id.replace("/", "-"));
+                    tables.add_object_store(&url, "huggingface");
+                }
+            }
+        }
+        tables
+    }
+
+    /// Register the tables in the `system` schema of the default catalog of `ctx`, creating the
+    /// schema if it doesn't exist
+    pub fn register(&self, ctx: &SessionContext) -> Result<()> {
+        let catalog_name = ctx.state().config_options().catalog.default_catalog.clone();
+        let catalog = ctx.catalog(&catalog_name).ok_or_else(|| {
+            DataFusionError::Plan(format!("Missing default catalog {catalog_name}"))
+        })?;
+        let schema = match catalog.schema(SYSTEM_SCHEMA_NAME) {
+            Some(schema) => schema,
+            None => {
+                let schema: Arc<dyn SchemaProvider> = Arc::new(MemorySchemaProvider::new());
+                catalog.register_schema(SYSTEM_SCHEMA_NAME, Arc::clone(&schema))?;
+                schema
+            }
+        };
+        let tables = [
+            (QUERIES_TABLE_NAME, queries_schema(), &self.queries),
+            (
+                RUNNING_QUERIES_TABLE_NAME,
+                running_queries_schema(),
+                &self.running_queries,
+            ),
+            (
+                OBJECT_STORES_TABLE_NAME,
+                object_stores_schema(),
+                &self.object_stores,
+            ),
+        ];
+        for (name, table_schema, data) in tables {
+            let config = MapTableConfig::new(name.to_string(), "query_id".to_string());
+            let table = MapTable::try_new(table_schema, None, config, Some(Arc::clone(data)))?;
+            schema.register_table(name.to_string(), Arc::new(table))?;
+        }
+        ctx.state_ref()
+            .write()
+            .config_mut()
+            .set_extension(Arc::new(SystemCatalog(catalog_name)));
+        Ok(())
+    }
+
+    /// Save the query history, `system.queries`, to `dir` so that it can be loaded by the next run
+    /// of the app with [`Self::load`].
+    ///
+    /// Several apps can share `dir`, so the queries this app executed, and hasn't saved yet, are
+    /// added to the history already saved there rather than replacing it. They are numbered after
+    /// the saved queries, as other apps may have used the same ids, and only the most recent
+    /// `query_history_size` queries are kept. Histories that can't be read, for example because
+    /// they were saved by an older version, are replaced.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        // Held until the history is replaced so that apps saving at once don't lose each other's
+        // queries
+        let lock = File::create(dir.join(QUERIES_LOCK_FILE))?;
+        lock.lock()?;
+        let path = dir.join(QUERIES_FILE);
+        let saved: MapData = Arc::new(RwLock::new(IndexMap::new()));
+        if let Err(e) = load_map_storage(&*saved, &queries_schema(), "query_id", &path) {
+            warn!("Replacing the query history at {}: {e}", path.display());
+            saved.write().clear();
+        }
+
+        let mut persisted = self.persisted.lock();
+        let mut added = Vec::new();
+        {
+            let mut saved = saved.write();
+            let mut next_id = max_query_id(&saved).map_or(1, |id| id + 1);
+            for (key, row) in self.queries.read().iter() {
+                match key {
+                    ScalarValue::UInt64(Some(id)) if !persisted.contains(id) => added.push(*id),
+                    _ => continue,
+                }
+                let mut row = row.clone();
+                row.insert("query_id".to_string(), ScalarValue::UInt64(Some(next_id)));
+                saved.insert(ScalarValue::UInt64(Some(next_id)), row);
+                next_id += 1;
+            }
+            if added.is_empty() {
+                return Ok(());
+            }
+            let excess = saved.len().saturating_sub(self.query_history_size);
+            saved.drain(..excess);
+        }
+        save_map_storage(&*saved, &queries_schema(), &path)?;
+        persisted.extend(added);
+        Ok(())
+    }
+
+    /// Load the query history saved to `dir` with [`Self::save`], if any. It should be loaded
+    /// before any queries are executed, queries that are already in the history are kept over
+    /// saved ones with the same id. Queries executed from then on are numbered after the loaded
+    /// ones. Returns the number of queries loaded.
+    pub fn load(&self, dir: &Path) -> Result<usize> {
+        let saved: MapData = Arc::new(RwLock::new(IndexMap::new()));
+        let loaded = load_map_storage(
+            &*saved,
+            &queries_schema(),
+            "query_id",
+            &dir.join(QUERIES_FILE),
+        )?;
+        let mut saved = std::mem::take(&mut *saved.write());
+        let mut queries = self.queries.write();
+        saved.retain(|key, _| !queries.contains_key(key));
+        if let Some(max_id) = max_query_id(&saved) {
+            self.next_query_id.fetch_max(max_id + 1, Ordering::Relaxed);
+        }
+        // They're already in the saved history, so they aren't added to it again by `save`
+        self.persisted
+            .lock()
+            .extend(saved.keys().filter_map(|key| match key {
+                ScalarValue::UInt64(id) => *id,
+                _ => None,
+            }));
+        saved.extend(queries.drain(..));
+        *queries = saved;
+        let excess = queries.len().saturating_sub(self.query_history_size);
+        queries.drain(..excess);
+        Ok(loaded)
+    }
+
+    /// Add the object store at `url` to `system.object_stores`. `kind` is the service it's
+    /// backed by, such as `s3`.
+    pub fn add_object_store(&self, url: &str, kind: &str) {
+        let row = HashMap::from([
+            ("url".to_string(), ScalarValue::Utf8(Some(url.to_string()))),
+            (
+                "kind".to_string(),
+                ScalarValue::Utf8(Some(kind.to_string())),
+            ),
+        ]);
+        self.object_stores
+            .write()
+            .insert(ScalarValue::Utf8(Some(url.to_string())), row);
+    }
+
+    /// Add a query, `sql`, sent by `identity` to `system.running_queries`. It's moved to
+    /// `system.queries` once the returned record is finished or dropped.
+    pub fn start_query(&self, sql: impl Into<String>, identity: &QueryIdentity) -> QueryRecord {
+        let record = QueryRecord {
+            tables: self.clone(),
+            id: self.next_query_id.fetch_add(1, Ordering::Relaxed),
+            sql: sql.into(),
+            user: identity.user.clone(),
+            start_ms: now_ms(),
+            started: Instant::now(),
+            rows: 0,
+            error: None,
+            finished: false,
+            recorded: false,
+        };
+        let row = HashMap::from([
+            ("query_id".to_string(), ScalarValue::UInt64(Some(record.id))),
+            (
+                "sql".to_string(),
+                ScalarValue::Utf8(Some(record.sql.clone())),
+            ),
+            ("start".to_string(), timestamp(record.start_ms)),
+            ("rows".to_string(), ScalarValue::UInt64(Some(0))),
+            (
+                "user_name".to_string(),
+                ScalarValue::Utf8(record.user.clone()),
+            ),
+        ]);
+        self.running_queries
+            .write()
+            .insert(ScalarValue::UInt64(Some(record.id)), row);
+        record
+    }
+}
+
+/// A query that is listed in `system.running_queries` until it's finished
+#[derive(Debug)]
+pub struct QueryRecord {
+    tables: SystemTables,
+    id: u64,
+    sql: String,
+    user: Option<String>,
+    start_ms: i64,
+    started: Instant,
+    rows: u64,
+    error: Option<String>,
+    finished: bool,
+    recorded: bool,
+}
+
+impl QueryRecord {
+    /// Record that the query failed with `error`
+    pub fn fail(&mut self, error: &impl std::fmt::Display) {
+        self.error = Some(error.to_string());
+        self.record();
+    }
+
+    /// Return a stream of the batches of `stream`, the results of the query, that counts them
+    /// and finishes the query once it's consumed. Queries whose results are dropped before then
+    /// are recorded as cancelled.
+    pub fn track(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        Box::pin(TrackedStream {
+            inner: stream,
+            record: self,
+        })
+    }
+
+    fn add_rows(&mut self, rows: usize) {
+        self.rows += rows as u64;
+        let key = ScalarValue::UInt64(Some(self.id));
+        if let Some(row) = self.tables.running_queries.write().get_mut(&key) {
+            row.insert("rows".to_string(), ScalarValue::UInt64(Some(self.rows)));
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.record();
+    }
+
+    /// Move the query from `system.running_queries` to `system.queries`
+    fn record(&mut self) {
+        if self.recorded {
+            return;
+        }
+        self.recorded = true;
+        let key = ScalarValue::UInt64(Some(self.id));
+        self.tables.running_queries.write().shift_remove(&key);
+
+        let status = match (&self.error, self.finished) {
+            (Some(_), _) => "failed",
+            (None, true) => "succeeded",
+            (None, false) => "cancelled",
+        };
+        let row = HashMap::from([
+            ("query_id".to_string(), ScalarValue::UInt64(Some(self.id))),
+            ("sql".to_string(), ScalarValue::Utf8(Some(self.sql.clone()))),
+            ("start".to_string(), timestamp(self.start_ms)),
+            ("end".to_string(), timestamp(now_ms())),
+            (
+                "duration_ms".to_string(),
+                ScalarValue::UInt64(Some(self.started.elapsed().as_millis() as u64)),
+            ),
+            ("rows".to_string(), ScalarValue::UInt64(Some(self.rows))),
+            (
+                "status".to_string(),
+                ScalarValue::Utf8(Some(status.to_string())),
+            ),
+            ("error".to_string(), ScalarValue::Utf8(self.error.clone())),
+            (
+                "user_name".to_string(),
+                ScalarValue::Utf8(self.user.clone()),
+            ),
+        ]);
+        let mut queries = self.tables.queries.write();
+        queries.insert(key, row);
+        let excess = queries.len().saturating_sub(self.tables.query_history_size);
+        queries.drain(..excess);
+    }
+}
+
+/// Only return the rows of `system.queries` and `system.running_queries` that were sent by
+/// `identity` wherever they're scanned in `plan`, so that clients can't read the SQL of each
+/// other's queries. Anonymous clients, and clients authenticated with a bearer token, only see the
+/// queries sent without a user.
+pub fn filter_own_queries(
+    state: &SessionState,
+    plan: LogicalPlan,
+    identity: &QueryIdentity,
+) -> Result<LogicalPlan> {
+    let Some(system_catalog) = state.config().get_extension::<SystemCatalog>() else {
+        return Ok(plan);
+    };
+    let predicate = match &identity.user {
+        Some(user) => col("user_name").eq(lit(user.clone())),
+        None => col("user_name").is_null(),
+    };
+    let defaults = &state.config_options().catalog;
+    filter_scans(
+        plan,
+        |table| {
+            // Names are resolved as the planner did, so that the tables can't be read through
+            // names it resolves to them, and tables of the same name elsewhere are left alone
+            let table = table
+                .clone()
+                .resolve(&defaults.default_catalog, &defaults.default_schema);
+            *table.catalog == system_catalog.0
+                && &*table.schema == SYSTEM_SCHEMA_NAME
+                && [QUERIES_TABLE_NAME, RUNNING_QUERIES_TABLE_NAME].contains(&&*table.table)
+        },
+        predicate,
+    )
+}
+
+impl Drop for QueryRecord {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+/// Results of a query that update its [`QueryRecord`] as they're consumed
+struct TrackedStream {
+    inner: SendableRecordBatchStream,
+    record: QueryRecord,
+}
+
+impl Stream for TrackedStream {
+    type Item = Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.poll_next_unpin(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(batch))) => self.record.add_rows(batch.num_rows()),
+            Poll::Ready(Some(Err(e))) => self.record.fail(e),
+            Poll::Ready(None) => self.record.finish(),
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl RecordBatchStream for TrackedStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+/// The largest id of `queries`, keyed by their `query_id`
+fn max_query_id(queries: &IndexMap<ScalarValue, MapRow>) -> Option<u64> {
+    queries
+        .keys()
+        .filter_map(|key| match key {
+            ScalarValue::UInt64(id) => *id,
+            _ => None,
+        })
+        .max()
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn timestamp(ms: i64) -> ScalarValue {
+    ScalarValue::TimestampMillisecond(Some(ms), Some("UTC".into()))
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+}
+
+fn queries_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("query_id", DataType::UInt64, false),
+        Field::new("sql", DataType::Utf8, false),
+        Field::new("start", timestamp_type(), false),
+        Field::new("end", timestamp_type(), false),
+        Field::new("duration_ms", DataType::UInt64, false),
+        Field::new("rows", DataType::UInt64, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("error", DataType::Utf8, true),
+        Field::new("user_name", DataType::Utf8, true),
+    ]))
+}
+
+fn running_queries_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("query_id", DataType::UInt64, false),
+        Field::new("sql", DataType::Utf8, false),
+        Field::new("start", timestamp_type(), false),
+        Field::new("rows", DataType::UInt64, false),
+        Field::new("user_name", DataType::Utf8, true),
+    ]))
+}
+
+fn object_stores_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("url", DataType::Utf8, false),
+        Field::new("kind", DataType::Utf8, false),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{assert_batches_eq, prelude::SessionContext};
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    async fn query(ctx: &SessionContext, sql: &str) -> Vec<RecordBatch> {
+        ctx.sql(sql).await.unwrap().collect().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_queries_are_recorded() {
+        let ctx = SessionContext::new();
+        let tables = SystemTables::new(&SystemTablesConfig {
+            query_history_size: 2,
+        });
+        tables.register(&ctx).unwrap();
+
+        let record = tables.start_query("SELECT 1", &QueryIdentity::default());
+        let running = query(
+            &ctx,
+            "SELECT query_id, sql, rows FROM system.running_queries",
+        )
+        .await;
+        let expected = [
+            "+----------+----------+------+",
+            "| query_id | sql      | rows |",
+            "+----------+----------+------+",
+            "| 1        | SELECT 1 | 0    |",
+            "+----------+----------+------+",
+        ];
+        assert_batches_eq!(expected, &running);
+
+        let stream = ctx.sql("SELECT 1").await.unwrap().execute_stream().await;
+        let _: Vec<RecordBatch> = record.track(stream.unwrap()).try_collect().await.unwrap();
+        let mut failed = tables.start_query("SELECT x", &QueryIdentity::default());
+        failed.fail(&"no column x");
+        drop(failed);
+        drop(tables.start_query("SELECT 2", &QueryIdentity::default()));
+
+        // The oldest query was dropped from the history
+        let queries = query(
+            &ctx,
+            "SELECT query_id, sql, rows, status, error FROM system.queries",
+        )
+        .await;
+        let expected = [
+            "+----------+----------+------+-----------+-------------+",
+            "| query_id | sql      | rows | status    | error       |",
+            "+----------+----------+------+-----------+-------------+",
+            "| 2        | SELECT x | 0    | failed    | no column x |",
+            "| 3        | SELECT 2 | 0    | cancelled |             |",
+            "+----------+----------+------+-----------+-------------+",
+        ];
+        assert_batches_eq!(expected, &queries);
+        let running = query(&ctx, "SELECT * FROM system.running_queries").await;
+        assert_eq!(running.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_apps_sharing_a_directory_keep_each_others_queries() {
+        let dir = std::env::temp_dir().join(format!("dft-shared-system-{}", std::process::id()));
+        let first = SystemTables::new(&SystemTablesConfig::default());
+        let second = SystemTables::new(&SystemTablesConfig::default());
+        first.load(&dir).unwrap();
+        second.load(&dir).unwrap();
+        let identity = QueryIdentity::default();
+        first.start_query("SELECT 1", &identity).finish();
+        second.start_query("SELECT 2", &identity).finish();
+        first.save(&dir).unwrap();
+        second.save(&dir).unwrap();
+        // Saving again doesn't add the same queries twice
+        first.save(&dir).unwrap();
+
+        let ctx = SessionContext::new();
+        let tables = SystemTables::new(&SystemTablesConfig::default());
+        tables.register(&ctx).unwrap();
+        assert_eq!(tables.load(&dir).unwrap(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let queries = query(&ctx, "SELECT query_id, sql FROM system.queries").await;
+        let expected = [
+            "+----------+----------+",
+            "| query_id | sql      |",
+            "+----------+----------+",
+            "| 1        | SELECT 1 |",
+            "| 2        | SELECT 2 |",
+            "+----------+----------+",
+        ];
+        assert_batches_eq!(expected, &queries);
+    }
+
+    #[tokio::test]
+    async fn test_clients_only_see_their_own_queries() {
+        let ctx = SessionContext::new();
+        let tables = SystemTables::new(&SystemTablesConfig::default());
+        tables.register(&ctx).unwrap();
+        let user = QueryIdentity::user("user");
+        tables.start_query("SELECT 'user'", &user).finish();
+        tables
+            .start_query("SELECT 'other'", &QueryIdentity::user("other"))
+            .finish();
+        tables
+            .start_query("SELECT 'anonymous'", &QueryIdentity::default())
+            .finish();
+        let _running = tables.start_query("SELECT 'running'", &QueryIdentity::user("other"));
+        ctx.sql("CREATE TABLE queries AS VALUES (1)").await.unwrap();
+
+        async fn sql_as(ctx: &SessionContext, sql: &str, identity: &QueryIdentity) -> usize {
+            let state = ctx.state();
+            let plan = state.create_logical_plan(sql).await.unwrap();
+            let plan = crate::rewrite::rewrite_plan(&state, plan, identity).unwrap();
+            let batches = ctx
+                .execute_logical_plan(plan)
+                .await
+                .unwrap()
+                .collect()
+                .await;
+            batches.unwrap().iter().map(|b| b.num_rows()).sum()
+        }
+        let queries = "SELECT * FROM system.queries";
+        assert_eq!(sql_as(&ctx, queries, &user).await, 1);
+        assert_eq!(sql_as(&ctx, queries, &QueryIdentity::default()).await, 1);
+        let all = "SELECT * FROM datafusion.system.queries UNION ALL SELECT * FROM system.queries";
+        assert_eq!(sql_as(&ctx, all, &user).await, 2);
+        let running = "SELECT * FROM system.running_queries";
+        assert_eq!(sql_as(&ctx, running, &user).await, 0);
+        // Views over the tables are filtered for whoever selects from them
+        ctx.sql("CREATE VIEW history AS SELECT * FROM system.queries")
+            .await
+            .unwrap();
+        assert_eq!(sql_as(&ctx, "SELECT * FROM history", &user).await, 1);
+        // Tables of the same name in other schemas are left alone
+        assert_eq!(sql_as(&ctx, "SELECT * FROM queries", &user).await, 1);
+        // The app itself still sees every query
+        let queries = query(&ctx, "SELECT * FROM system.queries").await;
+        assert_eq!(queries.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_object_stores() {
+        let ctx = SessionContext::new();
+        let tables = SystemTables::new(&SystemTablesConfig::default());
+        tables.register(&ctx).unwrap();
+        tables.add_object_store("s3://bucket", "s3");
+        let stores = query(&ctx, "SELECT * FROM system.object_stores").await;
+        let expected = [
+            "+-------------+------+",
+            "| url         | kind |",
+            "+-------------+------+",
+            "| s3://bucket | s3   |",
+            "+-------------+------+",
+        ];
+        assert_batches_eq!(expected, &stores);
+    }
+
+    #[tokio::test]
+    async fn test_query_history_saved_and_loaded() {
+        let dir = std::env::temp_dir().join(format!("dft-system-tables-{}", std::process::id()));
+        let tables = SystemTables::new(&SystemTablesConfig::default());
+        tables
+            .start_query("SELECT 1", &QueryIdentity::default())
+            .finish();
+        tables
+            .start_query("SELECT 2", &QueryIdentity::default())
+            .finish();
+        tables.save(&dir).unwrap();
+
+        let ctx = SessionContext::new();
+        let tables = SystemTables::new(&SystemTablesConfig::default());
+        tables.register(&ctx).unwrap();
+        assert_eq!(tables.load(&dir).unwrap(), 2);
+        tables
+            .start_query("SELECT 3", &QueryIdentity::default())
+            .finish();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let queries = query(&ctx, "SELECT query_id, sql, status FROM system.queries").await;
+        let expected = [
+            "+----------+----------+-----------+",
+            "| query_id | sql      | status    |",
+            "+----------+----------+-----------+",
+            "| 1        | SELECT 1 | succeeded |",
+            "| 2        | SELECT 2 | succeeded |",
+            "| 3        | SELECT 3 | succeeded |",
+            "+----------+----------+-----------+",
+        ];
+        assert_batches_eq!(expected, &queries);
+    }
+}
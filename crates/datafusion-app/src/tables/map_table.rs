@@ -15,17 +15,26 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fs::File,
+    path::Path,
+    sync::Arc,
+};
 
 use async_trait::async_trait;
+use dashmap::DashMap;
 use datafusion::{
     arrow::{
         array::{
             ArrayBuilder, ArrayRef, Int16Builder, Int32Builder, Int64Builder, Int8Builder,
-            LargeStringBuilder, RecordBatch, StringBuilder, UInt16Builder, UInt32Builder,
-            UInt64Builder, UInt8Builder,
+            LargeStringBuilder, RecordBatch, StringBuilder, TimestampMillisecondBuilder,
+            UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
         },
-        datatypes::{DataType, Schema, SchemaRef},
+        datatypes::{DataType, Schema, SchemaRef, TimeUnit},
+        ipc::{reader::FileReader, writer::FileWriter},
     },
     catalog::{Session, TableProvider},
     common::{internal_err, project_schema, Constraints, DataFusionError, Result},
@@ -42,32 +51,196 @@ use datafusion::{
 };
 use indexmap::IndexMap;
 use parking_lot::RwLock;
+use serde::Deserialize;
 
 type ArrayBuilderRef = Box<dyn ArrayBuilder>;
 
-// The first String key is meant to hold primary key and provide O(1) lookup.  The inner HashMap is
-// for holding arbitrary column and value pairs - the key is the column name and we use DataFusions
-// scalar value to provide dynamic typing for the column values.
-type MapData = Arc<RwLock<IndexMap<ScalarValue, HashMap<String, ScalarValue>>>>;
+/// A row of a [`MapTable`]. The key is the column name and we use DataFusions scalar value to
+/// provide dynamic typing for the column values.
+pub type MapRow = HashMap<String, ScalarValue>;
+
+// The ScalarValue key is meant to hold primary key and provide O(1) lookup.
+pub type MapData = Arc<RwLock<IndexMap<ScalarValue, MapRow>>>;
+
+/// Storage of the rows of a [`MapTable`], keyed by their primary key. Implementations provide
+/// their own synchronization so that rows can be added while the table is being scanned.
+pub trait MapStorage: Debug + Send + Sync {
+    /// Insert `row` under `key`, replacing the row that was there
+    fn insert(&self, key: ScalarValue, row: MapRow);
+
+    /// Remove and return the row under `key`
+    fn remove(&self, key: &ScalarValue) -> Option<MapRow>;
+
+    /// A copy of the row under `key`
+    fn get(&self, key: &ScalarValue) -> Option<MapRow>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Call `f` with every row, in the order the storage keeps them, stopping at the first error
+    fn try_for_each_row(
+        &self,
+        f: &mut dyn FnMut(&ScalarValue, &MapRow) -> Result<()>,
+    ) -> Result<()>;
+}
+
+/// Keeps rows in insertion order
+impl MapStorage for RwLock<IndexMap<ScalarValue, MapRow>> {
+    fn insert(&self, key: ScalarValue, row: MapRow) {
+        self.write().insert(key, row);
+    }
+
+    fn remove(&self, key: &ScalarValue) -> Option<MapRow> {
+        self.write().shift_remove(key)
+    }
+
+    fn get(&self, key: &ScalarValue) -> Option<MapRow> {
+        self.read().get(key).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    fn try_for_each_row(
+        &self,
+        f: &mut dyn FnMut(&ScalarValue, &MapRow) -> Result<()>,
+    ) -> Result<()> {
+        self.read().iter().try_for_each(|(key, row)| f(key, row))
+    }
+}
+
+/// Keeps rows sorted by their primary key
+impl MapStorage for RwLock<BTreeMap<OrderedKey, MapRow>> {
+    fn insert(&self, key: ScalarValue, row: MapRow) {
+        self.write().insert(OrderedKey(key), row);
+    }
+
+    fn remove(&self, key: &ScalarValue) -> Option<MapRow> {
+        self.write().remove(&OrderedKey(key.clone()))
+    }
+
+    fn get(&self, key: &ScalarValue) -> Option<MapRow> {
+        self.read().get(&OrderedKey(key.clone())).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    fn try_for_each_row(
+        &self,
+        f: &mut dyn FnMut(&ScalarValue, &MapRow) -> Result<()>,
+    ) -> Result<()> {
+        self.read().iter().try_for_each(|(key, row)| f(&key.0, row))
+    }
+}
+
+/// Shards rows so that concurrent writers rarely contend, at the cost of scanning them in no
+/// particular order
+impl MapStorage for DashMap<ScalarValue, MapRow> {
+    fn insert(&self, key: ScalarValue, row: MapRow) {
+        DashMap::insert(self, key, row);
+    }
+
+    fn remove(&self, key: &ScalarValue) -> Option<MapRow> {
+        DashMap::remove(self, key).map(|(_, row)| row)
+    }
+
+    fn get(&self, key: &ScalarValue) -> Option<MapRow> {
+        DashMap::get(self, key).map(|row| row.value().clone())
+    }
+
+    fn len(&self) -> usize {
+        DashMap::len(self)
+    }
+
+    fn try_for_each_row(
+        &self,
+        f: &mut dyn FnMut(&ScalarValue, &MapRow) -> Result<()>,
+    ) -> Result<()> {
+        self.iter()
+            .try_for_each(|entry| f(entry.key(), entry.value()))
+    }
+}
+
+/// A primary key ordered by its value. `ScalarValue`s of different types aren't comparable, so
+/// they're ordered by their type instead.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OrderedKey(pub ScalarValue);
+
+impl Ord for OrderedKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or_else(|| {
+            self.0
+                .data_type()
+                .to_string()
+                .cmp(&other.0.data_type().to_string())
+        })
+    }
+}
+
+impl PartialOrd for OrderedKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The map a [`MapTable`] stores its rows in
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MapTableBackend {
+    /// [`IndexMap`], which scans rows in the order they were inserted
+    #[default]
+    IndexMap,
+    /// [`BTreeMap`], which scans rows ordered by their primary key
+    BTreeMap,
+    /// [`DashMap`], which scales better with many concurrent writers but scans rows in no
+    /// particular order
+    DashMap,
+}
+
+impl MapTableBackend {
+    /// Create empty storage of this type
+    pub fn create_storage(&self) -> Arc<dyn MapStorage> {
+        match self {
+            Self::IndexMap => Arc::new(RwLock::new(IndexMap::<ScalarValue, MapRow>::new())),
+            Self::BTreeMap => Arc::new(RwLock::new(BTreeMap::<OrderedKey, MapRow>::new())),
+            Self::DashMap => Arc::new(DashMap::<ScalarValue, MapRow>::new()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct MapTableConfig {
     table_name: String,
     /// Column name of the primary key
-    _primary_key: String,
+    primary_key: String,
+    /// Map used when the table isn't created with existing data
+    backend: MapTableBackend,
 }
 
 impl MapTableConfig {
     pub fn new(table_name: String, primary_key: String) -> Self {
         Self {
             table_name,
-            _primary_key: primary_key,
+            primary_key,
+            backend: MapTableBackend::default(),
         }
     }
+
+    pub fn with_backend(mut self, backend: MapTableBackend) -> Self {
+        self.backend = backend;
+        self
+    }
 }
 
-/// Table for tracking observability information. Data is held in a IndexMap, which maintains
-/// insertion order, while the app is running and is serialized on app shutdown.
+/// Table for tracking observability information. Data is held in a [`MapStorage`], an IndexMap
+/// that maintains insertion order by default, while the app is running and can be saved with
+/// [`MapTable::save`] on app shutdown and reloaded with [`MapTable::load`] on startup.
 ///
 /// TODO: Add filter pushdown on the primary key and use `get` on that for O(1)
 /// TODO: Add filter pushdown on non primary key and use `binary_search_by` / `range` (whatever
@@ -78,18 +251,18 @@ pub struct MapTable {
     schema: Arc<Schema>,
     constraints: Option<Constraints>,
     config: MapTableConfig,
-    // TODO: This will be based on a Trait so you can use IndexMap, DashMap, BTreeMap, etc...
-    inner: MapData,
+    inner: Arc<dyn MapStorage>,
 }
 
 impl MapTable {
+    /// Create a table of the rows in `data`, or in new storage of the config's backend
     pub fn try_new(
         schema: Arc<Schema>,
         constraints: Option<Constraints>,
         config: MapTableConfig,
-        data: Option<MapData>,
+        data: Option<Arc<dyn MapStorage>>,
     ) -> Result<Self> {
-        let inner = data.unwrap_or(Arc::new(RwLock::new(IndexMap::new())));
+        let inner = data.unwrap_or_else(|| config.backend.create_storage());
         Ok(Self {
             schema,
             constraints,
@@ -98,41 +271,126 @@ impl MapTable {
         })
     }
 
+    /// The storage of the table's rows, for adding and removing them
+    pub fn storage(&self) -> &Arc<dyn MapStorage> {
+        &self.inner
+    }
+
+    /// Write the table's rows to an Arrow IPC file at `path`, replacing it
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_map_storage(self.inner.as_ref(), &self.schema, path)
+    }
+
+    /// Insert the rows saved with [`Self::save`] at `path`, if it exists, into the table. Returns
+    /// the number of rows loaded.
+    pub fn load(&self, path: &Path) -> Result<usize> {
+        load_map_storage(
+            self.inner.as_ref(),
+            &self.schema,
+            &self.config.primary_key,
+            path,
+        )
+    }
+
     fn try_create_partitions(&self) -> Result<Vec<Vec<RecordBatch>>> {
-        let guard = self.inner.read();
-        let values = guard.values();
-        // We use IndexMap, which has order defined on insertion order to have our builders align
-        // with the order of the fields in the Schema.
-        let mut builders: IndexMap<String, (ArrayBuilderRef, DataType)> = IndexMap::new();
-        for f in &self.schema.fields {
-            let builder = datatype_to_array_builder(f.data_type())?;
-            builders.insert(f.name().clone(), (builder, f.data_type().clone()));
-        }
+        let batch =
+            map_storage_to_batch(self.inner.as_ref(), &self.schema, &self.config.table_name)?;
+        Ok(vec![vec![batch]])
+    }
+}
 
-        for value in values {
-            for (col, val) in value {
-                // Check that the column is in the tables schema
-                if self.schema.fields.find(col).is_some() {
-                    if let Some((builder, builder_datatype)) = builders.get_mut(col) {
-                        try_append_scalar_to_builder(builder, builder_datatype, val)?;
-                    }
-                } else {
-                    return Err(datafusion::error::DataFusionError::External(
-                        format!(
-                            "Column {} for table {} is not in the provided schema",
-                            col, self.config.table_name
-                        )
-                        .into(),
-                    ));
+/// Convert the rows of `storage` into a batch with `schema`
+fn map_storage_to_batch(
+    storage: &dyn MapStorage,
+    schema: &SchemaRef,
+    table_name: &str,
+) -> Result<RecordBatch> {
+    // We use IndexMap, which has order defined on insertion order to have our builders align
+    // with the order of the fields in the Schema.
+    let mut builders: IndexMap<String, (ArrayBuilderRef, DataType)> = IndexMap::new();
+    for f in &schema.fields {
+        let builder = datatype_to_array_builder(f.data_type())?;
+        builders.insert(f.name().clone(), (builder, f.data_type().clone()));
+    }
+
+    storage.try_for_each_row(&mut |_, row| {
+        for (col, val) in row {
+            // Check that the column is in the tables schema
+            if schema.fields.find(col).is_some() {
+                if let Some((builder, builder_datatype)) = builders.get_mut(col) {
+                    try_append_scalar_to_builder(builder, builder_datatype, val)?;
                 }
+            } else {
+                return Err(DataFusionError::External(
+                    format!("Column {col} for table {table_name} is not in the provided schema")
+                        .into(),
+                ));
             }
         }
+        Ok(())
+    })?;
 
-        let arrays: Vec<ArrayRef> = builders.values_mut().map(|(b, _)| b.finish()).collect();
+    let arrays: Vec<ArrayRef> = builders.values_mut().map(|(b, _)| b.finish()).collect();
 
-        let batch = RecordBatch::try_new(Arc::clone(&self.schema), arrays)?;
-        Ok(vec![vec![batch]])
+    Ok(RecordBatch::try_new(Arc::clone(schema), arrays)?)
+}
+
+/// Write the rows of `storage` to an Arrow IPC file at `path`. The file is written next to
+/// `path` and then renamed so that a crash while saving doesn't lose the previous rows.
+pub fn save_map_storage(storage: &dyn MapStorage, schema: &SchemaRef, path: &Path) -> Result<()> {
+    let batch = map_storage_to_batch(storage, schema, &path.display().to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = FileWriter::try_new(File::create(&tmp_path)?, schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Insert the rows of the Arrow IPC file at `path`, if it exists, into `storage`, keyed by their
+/// `primary_key` column. Returns the number of rows loaded.
+pub fn load_map_storage(
+    storage: &dyn MapStorage,
+    schema: &SchemaRef,
+    primary_key: &str,
+    path: &Path,
+) -> Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let reader = FileReader::try_new(file, None)?;
+    if reader.schema() != *schema {
+        return Err(DataFusionError::External(
+            format!(
+                "Schema of {} doesn't match the table's schema",
+                path.display()
+            )
+            .into(),
+        ));
+    }
+    let key_index = schema.index_of(primary_key)?;
+    let mut loaded = 0;
+    for batch in reader {
+        let batch = batch?;
+        for i in 0..batch.num_rows() {
+            let mut row = MapRow::new();
+            for (field, column) in schema.fields().iter().zip(batch.columns()) {
+                row.insert(
+                    field.name().clone(),
+                    ScalarValue::try_from_array(column, i)?,
+                );
+            }
+            let key = ScalarValue::try_from_array(batch.column(key_index), i)?;
+            storage.insert(key, row);
+            loaded += 1;
+        }
     }
+    Ok(loaded)
 }
 
 #[async_trait]
@@ -291,6 +549,9 @@ fn datatype_to_array_builder(datatype: &DataType) -> Result<Box<dyn ArrayBuilder
         DataType::UInt64 => Ok(Box::new(UInt64Builder::new())),
         DataType::Utf8 => Ok(Box::new(StringBuilder::new())),
         DataType::LargeUtf8 => Ok(Box::new(LargeStringBuilder::new())),
+        DataType::Timestamp(TimeUnit::Millisecond, tz) => Ok(Box::new(
+            TimestampMillisecondBuilder::new().with_timezone_opt(tz.clone()),
+        )),
 
         _ => Err(DataFusionError::External(
             "Unsupported column type when constructing batch from Map".into(),
@@ -367,6 +628,18 @@ fn try_append_scalar_to_builder(
                     }
                 }
             }
+            ScalarValue::TimestampMillisecond(ts, _) => {
+                if let Some(builder) = builder
+                    .as_any_mut()
+                    .downcast_mut::<TimestampMillisecondBuilder>()
+                {
+                    if let Some(ts) = ts {
+                        builder.append_value(*ts)
+                    } else {
+                        builder.append_null()
+                    }
+                }
+            }
 
             _ => {
                 return Err(DataFusionError::External(
@@ -395,7 +668,7 @@ mod test {
     use indexmap::IndexMap;
     use parking_lot::RwLock;
 
-    use crate::tables::map_table::{MapTable, MapTableConfig};
+    use crate::tables::map_table::{MapTable, MapTableBackend, MapTableConfig};
 
     fn setup() -> SessionContext {
         let mut data: IndexMap<ScalarValue, HashMap<String, ScalarValue>> = IndexMap::new();
@@ -673,4 +946,99 @@ mod test {
 
         assert_batches_eq!(expected, &batches);
     }
+
+    fn backend_table(backend: MapTableBackend) -> MapTable {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("val", DataType::Utf8, false),
+        ]);
+        let config =
+            MapTableConfig::new("test".to_string(), "id".to_string()).with_backend(backend);
+        let table = MapTable::try_new(Arc::new(schema), None, config, None).unwrap();
+        for id in [3, 1, 2] {
+            let row = HashMap::from([
+                ("id".to_string(), ScalarValue::Int32(Some(id))),
+                (
+                    "val".to_string(),
+                    ScalarValue::Utf8(Some(format!("val{id}"))),
+                ),
+            ]);
+            table.storage().insert(ScalarValue::Int32(Some(id)), row);
+        }
+        table
+    }
+
+    #[tokio::test]
+    async fn test_map_table_backends() {
+        for backend in [
+            MapTableBackend::IndexMap,
+            MapTableBackend::BTreeMap,
+            MapTableBackend::DashMap,
+        ] {
+            let table = backend_table(backend);
+            assert_eq!(table.storage().len(), 3);
+            assert!(table
+                .storage()
+                .remove(&ScalarValue::Int32(Some(2)))
+                .is_some());
+            assert!(table.storage().get(&ScalarValue::Int32(Some(2))).is_none());
+
+            let ctx = SessionContext::new();
+            ctx.register_table("test", Arc::new(table)).unwrap();
+            let batches = ctx
+                .sql("SELECT * FROM test ORDER BY id")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+            let expected = [
+                "+----+------+",
+                "| id | val  |",
+                "+----+------+",
+                "| 1  | val1 |",
+                "| 3  | val3 |",
+                "+----+------+",
+            ];
+            assert_batches_eq!(expected, &batches);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_btree_map_backend_scans_in_key_order() {
+        let ctx = SessionContext::new();
+        let table = backend_table(MapTableBackend::BTreeMap);
+        ctx.register_table("test", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT id FROM test")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let expected = [
+            "+----+", "| id |", "+----+", "| 1  |", "| 2  |", "| 3  |", "+----+",
+        ];
+        assert_batches_eq!(expected, &batches);
+    }
+
+    #[tokio::test]
+    async fn test_map_table_save_and_load() {
+        let path = std::env::temp_dir().join(format!("dft-map-table-{}.arrow", std::process::id()));
+        let table = backend_table(MapTableBackend::IndexMap);
+        table.save(&path).unwrap();
+
+        let loaded = backend_table(MapTableBackend::DashMap);
+        loaded.storage().remove(&ScalarValue::Int32(Some(1)));
+        assert_eq!(loaded.load(&path).unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.storage().len(), 3);
+        assert_eq!(
+            loaded.storage().get(&ScalarValue::Int32(Some(1))),
+            table.storage().get(&ScalarValue::Int32(Some(1)))
+        );
+
+        // Files that don't exist load no rows
+        assert_eq!(loaded.load(&path).unwrap(), 0);
+    }
 }
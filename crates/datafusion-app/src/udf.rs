@@ -0,0 +1,373 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Functions declared in the config with `[[shared.udf]]`, implemented by WASM modules or
+//! shared libraries, that are registered with every app's session at startup
+
+use color_eyre::{eyre::eyre, Result};
+use datafusion::{arrow::datatypes::DataType, logical_expr::ScalarUDF};
+
+use crate::config::{UdfConfig, UdfKind, UdfSignature};
+
+/// Create the functions declared by `configs`
+pub fn create_config_udfs(configs: &[UdfConfig]) -> Result<Vec<ScalarUDF>> {
+    configs
+        .iter()
+        .map(|config| {
+            create_config_udf(config)
+                .map_err(|e| eyre!("Error creating function {}: {e}", config.name))
+        })
+        .collect()
+}
+
+fn create_config_udf(config: &UdfConfig) -> Result<ScalarUDF> {
+    let (input_types, return_type) = parse_signature(&config.signature)?;
+    match config.kind {
+        #[cfg(feature = "udfs-wasm")]
+        UdfKind::Wasm => {
+            use datafusion_udfs_wasm::{try_create_wasm_udf, WasmInputDataType, WasmUdfDetails};
+
+            let module_bytes = std::fs::read(&config.path)?;
+            let details = WasmUdfDetails::new(
+                config.symbol().to_string(),
+                input_types,
+                return_type,
+                config
+                    .input_data_type
+                    .clone()
+                    .unwrap_or(WasmInputDataType::Row),
+            );
+            let udf = try_create_wasm_udf(&module_bytes, details)?;
+            // The module's function is registered under the name it's exported with
+            if udf.name() == config.name {
+                Ok(udf)
+            } else {
+                Ok(udf.with_aliases([config.name.as_str()]))
+            }
+        }
+        #[cfg(not(feature = "udfs-wasm"))]
+        UdfKind::Wasm => Err(eyre!(
+            "WASM functions require dft to be built with the `udfs-wasm` feature"
+        )),
+        #[cfg(feature = "udfs-native")]
+        UdfKind::Native => Ok(ScalarUDF::new_from_impl(native::NativeUdf::try_new(
+            config,
+            input_types,
+            return_type,
+        )?)),
+        #[cfg(not(feature = "udfs-native"))]
+        UdfKind::Native => Err(eyre!(
+            "Native functions require dft to be built with the `udfs-native` feature"
+        )),
+    }
+}
+
+/// The argument and return types of `signature`
+fn parse_signature(signature: &UdfSignature) -> Result<(Vec<DataType>, DataType)> {
+    let input_types = signature
+        .input_types
+        .iter()
+        .map(|t| DataType::try_from(t.as_str()))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let return_type = DataType::try_from(signature.return_type.as_str())?;
+    Ok((input_types, return_type))
+}
+
+#[cfg(feature = "udfs-native")]
+mod native {
+    use std::{
+        any::Any,
+        hash::{Hash, Hasher},
+        io::Cursor,
+        path::PathBuf,
+        sync::Arc,
+    };
+
+    use color_eyre::Result;
+    use datafusion::{
+        arrow::{
+            array::{RecordBatch, RecordBatchOptions},
+            datatypes::{DataType, Field, Schema},
+            ipc::{reader::StreamReader, writer::StreamWriter},
+        },
+        common::{exec_err, Result as DFResult},
+        logical_expr::{ColumnarValue, ScalarFunctionArgs, ScalarUDFImpl, Signature, Volatility},
+    };
+    use libloading::Library;
+
+    use crate::config::UdfConfig;
+
+    /// `int32_t f(const uint8_t *input, size_t input_len, uint8_t **output, size_t *output_len)`
+    type NativeFn = unsafe extern "C" fn(*const u8, usize, *mut *mut u8, *mut usize) -> i32;
+    /// `void dft_free(uint8_t *ptr, size_t len)`
+    type FreeFn = unsafe extern "C" fn(*mut u8, usize);
+
+    /// Name of the function plugins export to free the output of their functions
+    const FREE_SYMBOL: &[u8] = b"dft_free";
+
+    /// A function exported by a shared library. The arguments are passed to it as an Arrow IPC
+    /// stream of one batch, with a column for each argument, and it returns 0 and sets `output`
+    /// to an Arrow IPC stream of one batch with a column of results, or a non-zero value and sets
+    /// `output` to an error message. `output` is freed with the library's `dft_free`.
+    #[derive(Debug)]
+    pub struct NativeUdf {
+        name: String,
+        path: PathBuf,
+        signature: Signature,
+        return_type: DataType,
+        func: NativeFn,
+        free: FreeFn,
+        /// Kept loaded for as long as `func` and `free` can be called
+        _library: Arc<Library>,
+    }
+
+    impl NativeUdf {
+        pub fn try_new(
+            config: &UdfConfig,
+            input_types: Vec<DataType>,
+            return_type: DataType,
+        ) -> Result<Self> {
+            // SAFETY: Loading a library runs its initializers. Plugins are configured by the
+            // user, like the rest of the code they run.
+            let library = unsafe { Library::new(&config.path)? };
+            // SAFETY: The plugin exports the functions with the signatures documented above
+            let (func, free) = unsafe {
+                let func = *library.get::<NativeFn>(config.symbol().as_bytes())?;
+                let free = *library.get::<FreeFn>(FREE_SYMBOL)?;
+                (func, free)
+            };
+            Ok(Self {
+                name: config.name.clone(),
+                path: config.path.clone(),
+                signature: Signature::exact(input_types, Volatility::Immutable),
+                return_type,
+                func,
+                free,
+                _library: Arc::new(library),
+            })
+        }
+
+        /// Call the function with `input` and return its output and status
+        fn call(&self, input: &[u8]) -> (i32, Vec<u8>) {
+            let mut output: *mut u8 = std::ptr::null_mut();
+            let mut output_len = 0;
+            // SAFETY: The function only reads `input.len()` bytes of `input` and sets `output`
+            // to a buffer of `output_len` bytes that is valid until it's passed to `free`
+            unsafe {
+                let status = (self.func)(input.as_ptr(), input.len(), &mut output, &mut output_len);
+                if output.is_null() {
+                    return (status, Vec::new());
+                }
+                let bytes = std::slice::from_raw_parts(output, output_len).to_vec();
+                (self.free)(output, output_len);
+                (status, bytes)
+            }
+        }
+    }
+
+    impl PartialEq for NativeUdf {
+        fn eq(&self, other: &Self) -> bool {
+            self.name == other.name
+                && self.path == other.path
+                && self.signature == other.signature
+                && self.return_type == other.return_type
+        }
+    }
+
+    impl Eq for NativeUdf {}
+
+    impl Hash for NativeUdf {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.name.hash(state);
+            self.path.hash(state);
+            self.return_type.hash(state);
+        }
+    }
+
+    impl ScalarUDFImpl for NativeUdf {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn signature(&self) -> &Signature {
+            &self.signature
+        }
+
+        fn return_type(&self, _arg_types: &[DataType]) -> DFResult<DataType> {
+            Ok(self.return_type.clone())
+        }
+
+        fn invoke_with_args(&self, args: ScalarFunctionArgs) -> DFResult<ColumnarValue> {
+            let arrays = ColumnarValue::values_to_arrays(&args.args)?;
+            let fields: Vec<Field> = arrays
+                .iter()
+                .enumerate()
+                .map(|(i, array)| Field::new(format!("arg_{i}"), array.data_type().clone(), true))
+                .collect();
+            let options = RecordBatchOptions::new().with_row_count(Some(args.number_rows));
+            let batch =
+                RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)?;
+            let mut input = Vec::new();
+            {
+                let mut writer = StreamWriter::try_new(&mut input, &batch.schema())?;
+                writer.write(&batch)?;
+                writer.finish()?;
+            }
+
+            let (status, output) = self.call(&input);
+            if status != 0 {
+                return exec_err!(
+                    "{} failed with status {status}: {}",
+                    self.name,
+                    String::from_utf8_lossy(&output)
+                );
+            }
+            let mut reader = StreamReader::try_new(Cursor::new(output), None)?;
+            let Some(batch) = reader.next().transpose()? else {
+                return exec_err!("{} returned no results", self.name);
+            };
+            if batch.num_columns() != 1 || batch.num_rows() != args.number_rows {
+                return exec_err!(
+                    "{} returned {} columns of {} rows, expected 1 column of {} rows",
+                    self.name,
+                    batch.num_columns(),
+                    batch.num_rows(),
+                    args.number_rows
+                );
+            }
+            let result = Arc::clone(batch.column(0));
+            if result.data_type() != &self.return_type {
+                return exec_err!(
+                    "{} returned {}, expected {}",
+                    self.name,
+                    result.data_type(),
+                    self.return_type
+                );
+            }
+            Ok(ColumnarValue::Array(result))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn config(kind: UdfKind, input_types: &[&str], return_type: &str) -> UdfConfig {
+        UdfConfig {
+            name: "f".to_string(),
+            kind,
+            path: PathBuf::from("/does/not/exist"),
+            signature: UdfSignature {
+                input_types: input_types.iter().map(|t| t.to_string()).collect(),
+                return_type: return_type.to_string(),
+            },
+            symbol: None,
+            #[cfg(feature = "udfs-wasm")]
+            input_data_type: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        let valid = config(UdfKind::Native, &["Int64", "Utf8"], "Float64");
+        let (input_types, return_type) = parse_signature(&valid.signature).unwrap();
+        assert_eq!(input_types, vec![DataType::Int64, DataType::Utf8]);
+        assert_eq!(return_type, DataType::Float64);
+
+        let invalid = config(UdfKind::Native, &["Integer"], "Float64");
+        assert!(parse_signature(&invalid.signature).is_err());
+    }
+
+    /// Build `test-native/native_examples.rs` as a shared library in `dir`, returning its path
+    #[cfg(feature = "udfs-native")]
+    fn build_native_examples(dir: &std::path::Path) -> PathBuf {
+        use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+        std::fs::create_dir_all(dir).unwrap();
+        let library = dir.join(format!("{DLL_PREFIX}native_examples{DLL_SUFFIX}"));
+        let source = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test-native/native_examples.rs"
+        );
+        let status = std::process::Command::new(
+            std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()),
+        )
+        .args(["--edition", "2021", "--crate-type", "cdylib", "-o"])
+        .arg(&library)
+        .arg(source)
+        .status()
+        .unwrap();
+        assert!(status.success());
+        library
+    }
+
+    #[cfg(feature = "udfs-native")]
+    #[tokio::test]
+    async fn test_native_udf() {
+        use datafusion::{assert_batches_eq, prelude::SessionContext};
+
+        let dir = std::env::temp_dir().join(format!("dft-native-udf-{}", std::process::id()));
+        let library = build_native_examples(&dir);
+        let native = |name: &str| UdfConfig {
+            name: name.to_string(),
+            path: library.clone(),
+            ..config(UdfKind::Native, &["Int64"], "Int64")
+        };
+        let udfs = create_config_udfs(&[native("echo"), native("fail")]).unwrap();
+        let ctx = SessionContext::new();
+        for udf in udfs {
+            ctx.register_udf(udf);
+        }
+
+        let batches = ctx
+            .sql("SELECT echo(x) AS x FROM (VALUES (1), (NULL), (3)) AS t(x)")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let expected = [
+            "+---+", "| x |", "+---+", "| 1 |", "|   |", "| 3 |", "+---+",
+        ];
+        assert_batches_eq!(expected, &batches);
+
+        let err = ctx
+            .sql("SELECT fail(x) FROM (VALUES (1)) AS t(x)")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(err
+            .to_string()
+            .contains("fail failed with status 1: something went wrong"));
+    }
+
+    #[test]
+    fn test_errors_name_the_function() {
+        let err = create_config_udfs(&[config(UdfKind::Wasm, &["Int64"], "Int64")]).unwrap_err();
+        assert!(err.to_string().starts_with("Error creating function f:"));
+    }
+}
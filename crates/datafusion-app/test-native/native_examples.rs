@@ -0,0 +1,61 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Native functions for the tests of `src/udf.rs`, which build this file as a shared library with
+//! `rustc --crate-type cdylib`. It has no dependencies so that it can be built without cargo.
+
+use std::ptr;
+
+/// Returns its arguments, which for a single argument are already a valid result: an Arrow IPC
+/// stream of one batch with one column
+#[no_mangle]
+pub unsafe extern "C" fn echo(
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> i32 {
+    set_output(
+        std::slice::from_raw_parts(input, input_len),
+        output,
+        output_len,
+    );
+    0
+}
+
+/// Fails with an error message
+#[no_mangle]
+pub unsafe extern "C" fn fail(
+    _input: *const u8,
+    _input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> i32 {
+    set_output(b"something went wrong", output, output_len);
+    1
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dft_free(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+unsafe fn set_output(bytes: &[u8], output: *mut *mut u8, output_len: *mut usize) {
+    let bytes = Box::<[u8]>::from(bytes);
+    *output_len = bytes.len();
+    *output = Box::into_raw(bytes).cast::<u8>();
+}
@@ -44,8 +44,8 @@ pub struct DftArgs {
         short,
         long,
         num_args = 0..,
-        help = "Execute commands from file(s), then exit",
-        value_parser(parse_valid_file)
+        help = "Execute commands from file(s), then exit. Glob patterns such as 'queries/*.sql' are expanded to the matching files in path order",
+        value_parser(parse_valid_file_or_glob)
     )]
     pub files: Vec<PathBuf>,
 
@@ -61,6 +61,13 @@ pub struct DftArgs {
     #[clap(long, global = true, help = "Path to the configuration file")]
     pub config: Option<String>,
 
+    #[clap(
+        long,
+        global = true,
+        help = "Use the settings from the `[profile.<name>]` tables of the config, merged over the defaults"
+    )]
+    pub profile: Option<String>,
+
     #[clap(
         long = "set",
         short = 's',
@@ -71,6 +78,34 @@ pub struct DftArgs {
     )]
     pub set: Option<Vec<(String, String)>>,
 
+    #[clap(
+        long = "object-store",
+        global = true,
+        help = "Register an object store for the given url (e.g. s3://my_bucket, gs://my_bucket, or az://my_container) at startup. Credentials are read from the environment (e.g. `AWS_*` variables). Can be repeated."
+    )]
+    pub object_stores: Vec<String>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Read credentials and the region for --object-store S3 buckets from this profile in `~/.aws/credentials` and `~/.aws/config`"
+    )]
+    pub aws_profile: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Path to the service account key file used for --object-store GCS buckets"
+    )]
+    pub gcs_service_account: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Storage account used for --object-store Azure containers"
+    )]
+    pub azure_storage_account: Option<String>,
+
     #[clap(
         long,
         short = 'q',
@@ -78,12 +113,52 @@ pub struct DftArgs {
     )]
     pub flightsql: bool,
 
+    #[clap(
+        long,
+        help = "Record every locally executed statement with its duration and result summary to a line delimited JSON file that can be run again with `dft replay`"
+    )]
+    pub record: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Replace `${ENV_VAR}` references in files provided with -f with the value of the environment variable"
+    )]
+    pub env_substitution: bool,
+
     #[clap(long, help = "Run DDL prior to executing")]
     pub run_ddl: bool,
 
+    #[clap(
+        long,
+        help = "DDL statement(s) to run prior to executing, after the DDL file if --run-ddl is provided. Can be repeated.",
+        value_parser(parse_command),
+        action = clap::ArgAction::Append
+    )]
+    pub ddl_sql: Option<Vec<String>>,
+
+    #[clap(
+        long,
+        help = "Create a table in the local session from the results of the last query with `CREATE TABLE <name> AS ...`"
+    )]
+    pub create_table: Option<String>,
+
+    #[clap(
+        long,
+        requires = "create_table",
+        help = "Append the `CREATE TABLE` statement used by --create-table to your DDL file"
+    )]
+    pub persist_ddl: bool,
+
     #[clap(long, short, help = "Only show how long the query took to run")]
     pub time: bool,
 
+    #[clap(
+        long,
+        requires = "time",
+        help = "With --time, show how long each stage (parsing, logical planning, physical planning, execution, and writing output) of each statement took"
+    )]
+    pub time_breakdown: bool,
+
     #[clap(
         long,
         short = 'j',
@@ -91,6 +166,13 @@ pub struct DftArgs {
     )]
     pub json: bool,
 
+    #[clap(
+        long,
+        conflicts_with = "json",
+        help = "Format to print query results in. `csv` and `ndjson` write rows as they arrive so large results can be piped into other tools with constant memory"
+    )]
+    pub format: Option<OutputFormat>,
+
     #[clap(
         long,
         short = 'C',
@@ -98,6 +180,30 @@ pub struct DftArgs {
     )]
     pub concat: bool,
 
+    #[clap(
+        long,
+        help = "Maximum number of rows to print. The rest of the results are still executed, followed by a '... N more rows' indicator"
+    )]
+    pub max_rows: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Truncate printed values longer than the given number of characters"
+    )]
+    pub max_col_width: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Number of files or commands to execute concurrently. Output is buffered per query and printed in order"
+    )]
+    pub jobs: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Abort any statement (including FlightSQL requests) that takes longer than this many seconds to run and report it as a timeout error"
+    )]
+    pub timeout: Option<u64>,
+
     #[clap(long, short, help = "Benchmark the provided query")]
     pub bench: bool,
 
@@ -107,6 +213,59 @@ pub struct DftArgs {
     )]
     pub analyze: bool,
 
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = ErrorFormat::Text,
+        help = "Format of errors. With `json` errors are written to stderr as a JSON object with the error kind, message, statement index, and the start of the statement's SQL"
+    )]
+    pub error_format: ErrorFormat,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = MetricsFormat::Text,
+        help = "Format of the metrics printed by --time, --bench, and --analyze"
+    )]
+    pub metrics_format: MetricsFormat,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Append --metrics-format json metrics to this file, one JSON object per line, instead of printing them"
+    )]
+    pub metrics_output: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Parse and plan each statement without executing it, reporting any errors"
+    )]
+    pub dry_run: bool,
+
+    #[clap(
+        long,
+        help = "Save the logical and physical plans of each executed statement to this directory as `query_<n>.logical.<ext>` and `query_<n>.physical.<ext>` datafusion-proto files, alongside printing its results"
+    )]
+    pub save_plans: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = PlanFileFormat::Proto,
+        requires = "save_plans",
+        help = "Format of the plans saved with --save-plans"
+    )]
+    pub plan_format: PlanFileFormat,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["files", "commands", "save_plans"],
+        help = "Execute a plan saved with --save-plans instead of files or commands. Whether it's a logical or physical plan, and its format, are taken from its file name"
+    )]
+    pub run_plan: Option<PathBuf>,
+
     #[clap(long, help = "Run the provided query before running the benchmark")]
     pub run_before: Option<String>,
 
@@ -116,15 +275,55 @@ pub struct DftArgs {
     #[clap(long, help = "Append the benchmark results to an existing file")]
     pub append: bool,
 
+    #[clap(
+        long,
+        help = "Compare the benchmark results against a baseline file previously created with --save"
+    )]
+    pub compare: Option<PathBuf>,
+
+    #[clap(
+        long,
+        default_value_t = 10.0,
+        help = "Percentage regression of any stage, compared to the baseline, that causes the benchmark to fail"
+    )]
+    pub regression_threshold: f64,
+
     #[clap(short = 'n', help = "Set the number of benchmark iterations to run")]
     pub benchmark_iterations: Option<usize>,
 
     #[clap(long, help = "Run benchmark iterations concurrently/in parallel")]
     pub concurrent: bool,
 
+    #[clap(
+        long,
+        requires_all = ["bench", "flightsql"],
+        help = "Benchmark FlightSQL queries with a prepared statement that is created once and executed on every iteration, separating the cost of planning on the server from executing the query"
+    )]
+    pub prepared: bool,
+
     #[clap(long, help = "Host address to query. Only used for FlightSQL")]
     pub host: Option<String>,
 
+    #[clap(
+        long,
+        help = "Accept any TLS certificate from an `https://` FlightSQL host. Only use this for testing against servers with self-signed certificates"
+    )]
+    pub insecure_skip_verify: bool,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Number of times to retry FlightSQL requests that fail with a transient error (e.g. connection reset or UNAVAILABLE)"
+    )]
+    pub retries: usize,
+
+    #[clap(
+        long,
+        default_value_t = 100,
+        help = "Initial backoff in milliseconds between FlightSQL retries, doubled after each attempt"
+    )]
+    pub retry_backoff_ms: u64,
+
     #[clap(
         long,
         help = "Header to add to Flight SQL connection. Only used for FlightSQL",
@@ -146,11 +345,68 @@ pub struct DftArgs {
     )]
     pub output: Option<PathBuf>,
 
+    #[clap(
+        long,
+        help = "Compress the file saved with --output. Inferred from a '.gz' or '.zst' suffix if not provided"
+    )]
+    pub compression: Option<OutputCompression>,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Column(s) to hive partition the output by, e.g. --partition-by year,month. The output is written as a directory of Parquet (default), CSV, or JSON files depending on its suffix"
+    )]
+    pub partition_by: Vec<String>,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Maximum number of rows in each row group of Parquet output. Row groups are written as soon as they're full, so only one is held in memory at a time"
+    )]
+    pub row_group_size: Option<u64>,
+
+    #[clap(
+        long,
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u16).range(1..),
+        requires = "output",
+        help = "Number of result batches that are queued while --output is written, so that the query keeps executing while earlier results are written. With --partition-by, the number of Parquet row groups encoded in parallel"
+    )]
+    pub write_concurrency: u16,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
 
 impl DftArgs {
+    /// Replace glob patterns passed to `-f` with the files they match, sorted by path so that
+    /// suites run in the same order every time. Returns each pattern with the files it resolved
+    /// to.
+    pub fn expand_file_globs(&mut self) -> Result<Vec<(String, Vec<PathBuf>)>, String> {
+        let mut files = Vec::with_capacity(self.files.len());
+        let mut manifest = Vec::new();
+        for file in std::mem::take(&mut self.files) {
+            let pattern = file.to_string_lossy().to_string();
+            if file.exists() || !is_glob_pattern(&pattern) {
+                files.push(file);
+                continue;
+            }
+            let mut matches = glob::glob(&pattern)
+                .map_err(|e| format!("Invalid glob pattern '{pattern}': {e}"))?
+                .filter_map(|entry| entry.ok())
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            if matches.is_empty() {
+                return Err(format!("No files match '{pattern}'"));
+            }
+            matches.sort();
+            files.extend(matches.iter().cloned());
+            manifest.push((pattern, matches));
+        }
+        self.files = files;
+        Ok(manifest)
+    }
+
     pub fn config_path(&self) -> PathBuf {
         #[cfg(feature = "flightsql")]
         if let Some(Command::ServeFlightSql {
@@ -159,6 +415,13 @@ impl DftArgs {
         {
             return Path::new(cfg).to_path_buf();
         }
+        #[cfg(all(feature = "flightsql", feature = "http"))]
+        if let Some(Command::Serve {
+            config: Some(cfg), ..
+        }) = &self.command
+        {
+            return Path::new(cfg).to_path_buf();
+        }
         if let Some(config) = self.config.as_ref() {
             Path::new(config).to_path_buf()
         } else {
@@ -206,6 +469,61 @@ pub enum FlightSqlCommand {
     },
     /// Executes `CommandGetTableTypes` and `DoGet` to return supported table types
     GetTableTypes,
+    /// Executes `CommandGetPrimaryKeys` and `DoGet` to return the primary key columns of a table
+    GetPrimaryKeys {
+        #[clap(flatten)]
+        table: TableArgs,
+    },
+    /// Executes `CommandGetExportedKeys` and `DoGet` to return the foreign keys that reference a table
+    GetExportedKeys {
+        #[clap(flatten)]
+        table: TableArgs,
+    },
+    /// Executes `CommandGetImportedKeys` and `DoGet` to return the foreign keys of a table
+    GetImportedKeys {
+        #[clap(flatten)]
+        table: TableArgs,
+    },
+    /// Executes `CommandGetCrossReference` and `DoGet` to return the foreign keys of a table that
+    /// reference another table
+    GetCrossReference {
+        /// The catalog of the table with the primary key
+        #[clap(long)]
+        pk_catalog: Option<String>,
+        /// The schema of the table with the primary key
+        #[clap(long)]
+        pk_db_schema: Option<String>,
+        /// The table with the primary key
+        #[clap(long)]
+        pk_table: String,
+        /// The catalog of the table with the foreign key
+        #[clap(long)]
+        fk_catalog: Option<String>,
+        /// The schema of the table with the foreign key
+        #[clap(long)]
+        fk_db_schema: Option<String>,
+        /// The table with the foreign key
+        #[clap(long)]
+        fk_table: String,
+    },
+    /// Creates a prepared statement, binds its parameters, executes it and closes it
+    PreparedQuery {
+        /// The query to prepare, with parameters written as `$1`, `$2`, ...
+        #[clap(long)]
+        sql: String,
+        /// Value of a parameter, in order. Cast to the parameter's type inferred by the server.
+        #[clap(long = "param")]
+        params: Vec<String>,
+    },
+    /// Runs a query locally and streams its results into a table on the server with `DoExchange`
+    Ingest {
+        /// The table on the server to write to
+        #[clap(long)]
+        table: String,
+        /// The local query whose results are written, for example `SELECT * FROM 'data.csv'`
+        #[clap(long)]
+        sql: String,
+    },
     /// Executes `CommandGetSqlInfo` and `DoGet` to return server SQL capabilities
     GetSqlInfo {
         /// Specific SQL info IDs to retrieve (if not provided, returns all)
@@ -220,6 +538,39 @@ pub enum FlightSqlCommand {
     },
 }
 
+#[derive(Clone, Debug, Subcommand)]
+pub enum CatalogCommand {
+    /// List the schemas of the local catalogs
+    ListSchemas {
+        /// The catalog to list schemas of
+        #[clap(long)]
+        catalog: Option<String>,
+        /// Schema filter pattern to apply
+        #[clap(long)]
+        db_schema_filter_pattern: Option<String>,
+    },
+    /// List the tables registered in the local catalogs
+    ListTables {
+        /// The catalog to list tables of
+        #[clap(long)]
+        catalog: Option<String>,
+        /// Schema filter pattern to apply
+        #[clap(long)]
+        db_schema_filter_pattern: Option<String>,
+        /// Table name filter pattern to apply
+        #[clap(long)]
+        table_name_filter_pattern: Option<String>,
+        /// Specific table types to return
+        #[clap(long)]
+        table_types: Option<Vec<String>>,
+    },
+    /// Describe the columns of a registered table
+    Describe {
+        /// The table to describe, optionally qualified with its catalog and schema
+        table: String,
+    },
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum Command {
     /// Start a HTTP server
@@ -250,12 +601,182 @@ pub enum Command {
         #[clap(long, help = "Set the port to be used for serving metrics")]
         metrics_addr: Option<SocketAddr>,
     },
+    /// Start the FlightSQL and HTTP servers in one process, sharing their catalog and metrics
+    #[cfg(all(feature = "flightsql", feature = "http"))]
+    Serve {
+        #[clap(short, long)]
+        config: Option<String>,
+        #[clap(long, help = "Set the address of the FlightSQL server")]
+        flightsql_addr: Option<SocketAddr>,
+        #[clap(long, help = "Set the address of the HTTP server")]
+        http_addr: Option<SocketAddr>,
+        #[clap(long, help = "Set the port to be used for serving metrics")]
+        metrics_addr: Option<SocketAddr>,
+    },
+    /// Inspect the catalogs, schemas, and tables of the local execution context
+    Catalog {
+        #[clap(subcommand)]
+        command: CatalogCommand,
+    },
+    /// Execute a query and print a per operator summary of its execution plan metrics
+    Analyze {
+        #[clap(
+            short,
+            long,
+            num_args = 0..,
+            help = "Analyze the query in the given file(s)",
+            value_parser(parse_valid_file)
+        )]
+        files: Vec<PathBuf>,
+        #[clap(
+            short = 'c',
+            long,
+            num_args = 0..,
+            help = "Analyze the given SQL string(s)",
+            value_parser(parse_command)
+        )]
+        commands: Vec<String>,
+    },
+    /// Print the Arrow schema of a file (csv, parquet, json) or a registered table
+    Schema {
+        /// Path to a file or the name of a registered table
+        target: String,
+        #[clap(long, short = 'j', help = "Output the schema as line-delimited JSON")]
+        json: bool,
+    },
+    /// Convert a file (or directory of files) between CSV, JSON, Parquet, and Arrow formats
+    Convert {
+        /// Path of the file or directory to convert
+        input: String,
+        /// Path to write the converted output to
+        output: String,
+        #[clap(
+            long,
+            help = "Format of the input. Inferred from the input's extension if not provided"
+        )]
+        input_format: Option<FileFormat>,
+        #[clap(
+            long,
+            help = "Format of the output. Inferred from the output's extension if not provided"
+        )]
+        output_format: Option<FileFormat>,
+        #[clap(
+            long,
+            help = "Compression codec for the output, e.g. 'zstd(3)' or 'snappy' for Parquet and 'gzip' for CSV and JSON"
+        )]
+        compression: Option<String>,
+        #[clap(
+            long,
+            value_delimiter = ',',
+            help = "Column(s) to hive partition the output by, e.g. --partition-by year,month"
+        )]
+        partition_by: Vec<String>,
+        #[clap(
+            long = "cast",
+            help = "Override the type of a column with a SQL type, e.g. --cast id=BIGINT. Can be repeated.",
+            value_parser(parse_cast),
+            action = clap::ArgAction::Append
+        )]
+        casts: Vec<(String, String)>,
+    },
+    /// Copy a file, directory, or glob between any two registered object store or local
+    /// locations, optionally converting its format
+    Cp {
+        /// Location of the file(s) to copy, e.g. s3://bucket/data/ or 'data/*.parquet'
+        src: String,
+        /// Location to copy the file(s) to
+        dst: String,
+        #[clap(
+            long,
+            help = "Convert the files to this format with the execution engine instead of copying them byte for byte"
+        )]
+        format: Option<FileFormat>,
+        #[clap(
+            long,
+            requires = "format",
+            help = "Format of the source when converting. Inferred from the source's extension if not provided"
+        )]
+        input_format: Option<FileFormat>,
+    },
+    /// Format SQL files by parsing them and printing each statement back in a normalized form
+    Fmt {
+        /// The SQL file(s) to format
+        #[clap(required = true, value_parser(parse_valid_file))]
+        files: Vec<PathBuf>,
+        #[clap(
+            long,
+            help = "Don't print or write anything and exit with an error if any file is not already formatted",
+            conflicts_with = "write"
+        )]
+        check: bool,
+        #[clap(
+            long,
+            short = 'w',
+            help = "Rewrite the files in place instead of printing them"
+        )]
+        write: bool,
+        #[clap(
+            long,
+            help = "Keep statements that fit within this many characters on a single line"
+        )]
+        max_width: Option<usize>,
+        #[clap(long, default_value_t = KeywordCase::Upper, value_enum, help = "Case of keywords")]
+        keyword_case: KeywordCase,
+    },
+    /// Generate a shell completion script
+    Completions {
+        /// Shell to generate the completion script for
+        shell: clap_complete::Shell,
+        #[clap(
+            long,
+            help = "Complete table names created by your DDL file for `catalog describe` and `schema`"
+        )]
+        tables: bool,
+    },
+    /// Re-run the statements of a session recorded with --record, in order
+    Replay {
+        /// Path of the session file
+        #[clap(value_parser(parse_valid_file))]
+        session: PathBuf,
+    },
+    /// Run TPC-H queries against the generated TPC-H data
+    Tpch {
+        #[clap(subcommand)]
+        command: TpchCommand,
+    },
     GenerateTpch {
         #[clap(long, default_value = "1.0")]
         scale_factor: f64,
         #[clap(long, default_value = "parquet")]
         format: TpchFormat,
     },
+    /// Generate TPC-DS data and queries with the TPC-DS toolkit
+    GenerateTpcds {
+        #[clap(long, default_value = "1.0")]
+        scale_factor: f64,
+        /// Path to the TPC-DS toolkit, with `dsdgen` and `dsqgen` built in its `tools` directory
+        #[clap(long)]
+        toolkit: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum TpchCommand {
+    /// Run all (or the selected) TPC-H queries and print how long each took. Use with --bench
+    /// to benchmark each query instead
+    Run {
+        /// Generate the TPC-H data at this scale factor before running the queries. If not
+        /// provided the previously generated data is used
+        #[clap(long)]
+        scale_factor: Option<f64>,
+        /// Format to generate the data in
+        #[clap(long, default_value = "parquet")]
+        format: TpchFormat,
+        /// Query number(s) to run, e.g. --query 5 or --query 1,3,5. All 22 are run if not
+        /// provided
+        #[clap(long, value_delimiter = ',')]
+        query: Vec<usize>,
+    },
 }
 
 #[derive(Clone, Debug, clap::ValueEnum)]
@@ -265,6 +786,114 @@ pub enum TpchFormat {
     Vortex,
 }
 
+/// File formats supported by the `convert` subcommand
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum FileFormat {
+    Csv,
+    /// Line delimited JSON
+    Json,
+    Parquet,
+    /// Arrow IPC file
+    Arrow,
+}
+
+impl FileFormat {
+    /// Infer the format from the extension of a path
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Self::Csv),
+            "json" | "ndjson" | "jsonl" => Some(Self::Json),
+            "parquet" => Some(Self::Parquet),
+            "arrow" | "ipc" | "feather" => Some(Self::Arrow),
+            _ => None,
+        }
+    }
+}
+
+/// Formats that query results can be printed to stdout in
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Pretty printed tables
+    Table,
+    Csv,
+    /// Line delimited JSON, the same as --json
+    Ndjson,
+}
+
+/// Formats that plans can be saved in
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum PlanFileFormat {
+    /// datafusion-proto protobuf bytes
+    #[default]
+    Proto,
+    /// The JSON representation of the datafusion-proto messages
+    Json,
+}
+
+/// Formats that errors can be printed in
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// Human readable reports
+    #[default]
+    Text,
+    /// One JSON object per error
+    Json,
+}
+
+/// A table named by FlightSQL metadata commands
+#[derive(Clone, Debug, clap::Args)]
+pub struct TableArgs {
+    /// The catalog of the table
+    #[clap(long)]
+    pub catalog: Option<String>,
+    /// The schema of the table
+    #[clap(long)]
+    pub db_schema: Option<String>,
+    /// The table name
+    #[clap(long)]
+    pub table: String,
+}
+
+/// Case that `fmt` prints keywords in
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum KeywordCase {
+    /// `SELECT a FROM t`
+    #[default]
+    Upper,
+    /// `select a from t`
+    Lower,
+}
+
+/// Formats that timing, benchmark, and analyze metrics can be printed in
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum MetricsFormat {
+    /// Human readable summaries
+    #[default]
+    Text,
+    /// One JSON object per query
+    Json,
+}
+
+/// Compression codecs that output files can be written with
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum OutputCompression {
+    Gzip,
+    Zstd,
+}
+
+impl OutputCompression {
+    /// Infer the compression from the extension of a path
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
 fn parse_valid_file(file: &str) -> std::result::Result<PathBuf, String> {
     let path = PathBuf::from(file);
     if !path.exists() {
@@ -276,6 +905,19 @@ fn parse_valid_file(file: &str) -> std::result::Result<PathBuf, String> {
     }
 }
 
+fn is_glob_pattern(file: &str) -> bool {
+    file.contains(['*', '?', '['])
+}
+
+fn parse_valid_file_or_glob(file: &str) -> std::result::Result<PathBuf, String> {
+    if is_glob_pattern(file) && !Path::new(file).exists() {
+        glob::Pattern::new(file).map_err(|e| format!("Invalid glob pattern '{file}': {e}"))?;
+        Ok(PathBuf::from(file))
+    } else {
+        parse_valid_file(file)
+    }
+}
+
 fn parse_command(command: &str) -> std::result::Result<String, String> {
     if !command.is_empty() {
         Ok(command.to_string())
@@ -298,6 +940,17 @@ fn parse_config_override(pair: &str) -> std::result::Result<(String, String), St
     Ok((key.trim().to_string(), value.trim().to_string()))
 }
 
+fn parse_cast(pair: &str) -> std::result::Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((column, data_type)) if !column.trim().is_empty() && !data_type.trim().is_empty() => {
+            Ok((column.trim().to_string(), data_type.trim().to_string()))
+        }
+        _ => Err(format!(
+            "Invalid cast: '{pair}'\n       Expected format: 'column=TYPE', e.g. 'id=BIGINT'"
+        )),
+    }
+}
+
 fn parse_header_line(line: &str) -> Result<(String, String), String> {
     let (name, value) = line
         .split_once(':')
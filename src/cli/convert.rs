@@ -0,0 +1,165 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Conversion of files between formats for the `convert` subcommand
+
+use crate::args::FileFormat;
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use datafusion::dataframe::DataFrame;
+use datafusion::prelude::{
+    ArrowReadOptions, CsvReadOptions, NdJsonReadOptions, ParquetReadOptions, SessionContext,
+};
+use log::info;
+use std::path::Path;
+
+/// Name the input is temporarily registered under so it can be copied with SQL
+const INPUT_TABLE: &str = "__dft_convert_input";
+
+pub struct ConvertOptions<'a> {
+    pub input: &'a str,
+    pub output: &'a str,
+    pub input_format: Option<FileFormat>,
+    pub output_format: Option<FileFormat>,
+    pub compression: Option<&'a str>,
+    pub partition_by: &'a [String],
+    pub casts: &'a [(String, String)],
+}
+
+/// Read the input with DataFusion's readers and write it out in the requested format with a
+/// `COPY` statement
+pub async fn convert(ctx: &SessionContext, options: ConvertOptions<'_>) -> Result<()> {
+    let input_format = resolve_format(options.input, options.input_format)?;
+    let output_format = resolve_format(options.output, options.output_format)?;
+    if output_format == FileFormat::Arrow && options.compression.is_some() {
+        return Err(eyre!("Compression is not supported for Arrow output"));
+    }
+
+    let df = read_input(ctx, options.input, input_format).await?;
+    let mut select_list = Vec::with_capacity(df.schema().fields().len());
+    for field in df.schema().fields() {
+        let name = quote_identifier(field.name());
+        match options
+            .casts
+            .iter()
+            .find(|(column, _)| column == field.name())
+        {
+            Some((_, data_type)) => {
+                select_list.push(format!("CAST({name} AS {data_type}) AS {name}"))
+            }
+            None => select_list.push(name),
+        }
+    }
+    if let Some((column, _)) = options
+        .casts
+        .iter()
+        .find(|(column, _)| df.schema().field_with_unqualified_name(column).is_err())
+    {
+        return Err(eyre!(
+            "Cannot cast '{column}' as it is not a column of the input"
+        ));
+    }
+
+    let mut sql = format!(
+        "COPY (SELECT {} FROM {INPUT_TABLE}) TO {} STORED AS {}",
+        select_list.join(", "),
+        quote_literal(options.output),
+        stored_as(output_format)
+    );
+    if !options.partition_by.is_empty() {
+        let columns: Vec<String> = options
+            .partition_by
+            .iter()
+            .map(|c| quote_identifier(c))
+            .collect();
+        sql.push_str(&format!(" PARTITIONED BY ({})", columns.join(", ")));
+    }
+    if let Some(compression) = options.compression {
+        sql.push_str(&format!(
+            " OPTIONS ('format.compression' {})",
+            quote_literal(compression)
+        ));
+    }
+
+    ctx.register_table(INPUT_TABLE, df.into_view())?;
+    info!("Converting '{}' with: {sql}", options.input);
+    let result = match ctx.sql(&sql).await {
+        Ok(df) => df.collect().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    ctx.deregister_table(INPUT_TABLE)?;
+    result?;
+    Ok(())
+}
+
+fn resolve_format(path: &str, format: Option<FileFormat>) -> Result<FileFormat> {
+    format.or_else(|| FileFormat::from_path(path)).ok_or(eyre!(
+        "Unable to infer the format of '{path}' from its extension. Provide it with --input-format or --output-format"
+    ))
+}
+
+async fn read_input(ctx: &SessionContext, path: &str, format: FileFormat) -> Result<DataFrame> {
+    // Files are only read if their extension matches the one in the read options so use the
+    // input's own extension when it is a single file
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{e}"));
+    let df = match (format, extension.as_deref()) {
+        (FileFormat::Csv, Some(ext)) => {
+            ctx.read_csv(path, CsvReadOptions::new().file_extension(ext))
+                .await?
+        }
+        (FileFormat::Csv, None) => ctx.read_csv(path, CsvReadOptions::new()).await?,
+        (FileFormat::Json, Some(ext)) => {
+            ctx.read_json(path, NdJsonReadOptions::default().file_extension(ext))
+                .await?
+        }
+        (FileFormat::Json, None) => ctx.read_json(path, NdJsonReadOptions::default()).await?,
+        (FileFormat::Parquet, Some(ext)) => {
+            ctx.read_parquet(path, ParquetReadOptions::default().file_extension(ext))
+                .await?
+        }
+        (FileFormat::Parquet, None) => {
+            ctx.read_parquet(path, ParquetReadOptions::default())
+                .await?
+        }
+        (FileFormat::Arrow, Some(ext)) => {
+            ctx.read_arrow(path, ArrowReadOptions::default().file_extension(ext))
+                .await?
+        }
+        (FileFormat::Arrow, None) => ctx.read_arrow(path, ArrowReadOptions::default()).await?,
+    };
+    Ok(df)
+}
+
+fn stored_as(format: FileFormat) -> &'static str {
+    match format {
+        FileFormat::Csv => "CSV",
+        FileFormat::Json => "JSON",
+        FileFormat::Parquet => "PARQUET",
+        FileFormat::Arrow => "ARROW",
+    }
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
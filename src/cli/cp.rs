@@ -0,0 +1,118 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Copying files between object stores for the `cp` subcommand
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::prelude::SessionContext;
+use futures::{StreamExt, TryStreamExt};
+use log::info;
+use object_store::path::Path;
+use object_store::{ObjectMeta, ObjectStore, ObjectStoreExt, WriteMultipart};
+use std::sync::Arc;
+
+/// Number of parts of a file that can be uploaded at the same time
+const MAX_CONCURRENT_PARTS: usize = 8;
+
+/// The number of files and bytes that were copied
+pub struct CopySummary {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Copy the file, directory, or glob at `src` to `dst` byte for byte. Files are streamed between
+/// the object stores registered for each location, with as many files copied at the same time
+/// as the session's target partitions.
+pub async fn copy(ctx: &SessionContext, src: &str, dst: &str) -> Result<CopySummary> {
+    let src_url = ListingTableUrl::parse(src)?;
+    let dst_url = ListingTableUrl::parse(dst)?;
+    let runtime_env = ctx.runtime_env();
+    let src_store = runtime_env.object_store(src_url.object_store())?;
+    let dst_store = runtime_env.object_store(dst_url.object_store())?;
+
+    let state = ctx.state();
+    let files: Vec<ObjectMeta> = src_url
+        .list_all_files(&state, src_store.as_ref(), "")
+        .await?
+        .try_collect()
+        .await?;
+    if files.is_empty() {
+        return Err(eyre!("No files found at '{src}'"));
+    }
+
+    // A single file is copied to `dst` itself unless `dst` is a directory, otherwise the files
+    // keep their paths relative to `src` under `dst`
+    let single_file = files.len() == 1 && &files[0].location == src_url.prefix();
+    let dst_is_dir = dst.ends_with('/') || std::path::Path::new(dst).is_dir();
+    let copies = files
+        .into_iter()
+        .map(|file| {
+            let dst_path = if single_file && !dst_is_dir {
+                dst_url.prefix().clone()
+            } else if single_file {
+                match file.location.filename() {
+                    Some(name) => dst_url.prefix().child(name),
+                    None => dst_url.prefix().clone(),
+                }
+            } else {
+                let relative = file
+                    .location
+                    .prefix_match(src_url.prefix())
+                    .ok_or_else(|| eyre!("'{}' is not under '{src}'", file.location))?;
+                relative.fold(dst_url.prefix().clone(), |path, part| path.child(part))
+            };
+            Ok((file, dst_path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let concurrency = state.config().target_partitions();
+    let bytes: Vec<u64> = futures::stream::iter(copies)
+        .map(|(file, dst_path)| {
+            copy_file(
+                Arc::clone(&src_store),
+                Arc::clone(&dst_store),
+                file,
+                dst_path,
+            )
+        })
+        .buffer_unordered(concurrency)
+        .try_collect()
+        .await?;
+    Ok(CopySummary {
+        files: bytes.len(),
+        bytes: bytes.iter().sum(),
+    })
+}
+
+async fn copy_file(
+    src_store: Arc<dyn ObjectStore>,
+    dst_store: Arc<dyn ObjectStore>,
+    file: ObjectMeta,
+    dst_path: Path,
+) -> Result<u64> {
+    info!("Copying '{}' to '{dst_path}'", file.location);
+    let mut stream = src_store.get(&file.location).await?.into_stream();
+    let mut writer = WriteMultipart::new(dst_store.put_multipart(&dst_path).await?);
+    while let Some(bytes) = stream.next().await {
+        writer.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+        writer.write(&bytes?);
+    }
+    writer.finish().await?;
+    Ok(file.size)
+}
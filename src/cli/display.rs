@@ -0,0 +1,149 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Helpers for formatting what is printed to stdout
+
+use datafusion::arrow::array::{Array, ArrayRef, BooleanArray, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use std::sync::Arc;
+
+/// Tracks how many rows have been printed across all batches of a result stream so that
+/// printing can stop at `--max-rows` while the remainder of the stream is still drained.
+pub struct RowLimiter {
+    max_rows: Option<usize>,
+    printed: usize,
+    omitted: usize,
+}
+
+impl RowLimiter {
+    pub fn new(max_rows: Option<usize>) -> Self {
+        Self {
+            max_rows,
+            printed: 0,
+            omitted: 0,
+        }
+    }
+
+    /// Returns the part of `batch` that should still be printed, or `None` if the row limit
+    /// has already been reached.
+    pub fn limit(&mut self, batch: RecordBatch) -> Option<RecordBatch> {
+        let Some(max_rows) = self.max_rows else {
+            return Some(batch);
+        };
+        let remaining = max_rows.saturating_sub(self.printed);
+        if remaining == 0 {
+            self.omitted += batch.num_rows();
+            return None;
+        }
+        let num_rows = batch.num_rows();
+        if num_rows <= remaining {
+            self.printed += num_rows;
+            Some(batch)
+        } else {
+            self.printed += remaining;
+            self.omitted += num_rows - remaining;
+            Some(batch.slice(0, remaining))
+        }
+    }
+
+    /// The trailing indicator to print if any rows were not printed
+    pub fn truncation_message(&self) -> Option<String> {
+        if self.omitted > 0 {
+            Some(format!("... {} more rows", self.omitted))
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats every column of `batch` as a string and truncates values longer than `max_width`
+/// characters so that wide values don't blow up the printed table.
+pub fn truncate_columns(batch: &RecordBatch, max_width: usize) -> Result<RecordBatch, ArrowError> {
+    let options = FormatOptions::default();
+    let schema = batch.schema();
+    let mut fields = Vec::with_capacity(batch.num_columns());
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(batch.num_columns());
+    for (field, column) in schema.fields().iter().zip(batch.columns()) {
+        let formatter = ArrayFormatter::try_new(column.as_ref(), &options)?;
+        let values: StringArray = (0..column.len())
+            .map(|i| {
+                if column.is_null(i) {
+                    None
+                } else {
+                    Some(truncate_value(formatter.value(i).to_string(), max_width))
+                }
+            })
+            .collect();
+        fields.push(Field::new(field.name(), DataType::Utf8, true));
+        columns.push(Arc::new(values));
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
+
+fn truncate_value(value: String, max_width: usize) -> String {
+    if value.chars().count() <= max_width {
+        return value;
+    }
+    // There's no room for the ellipsis, which would make the value wider than the limit
+    if max_width <= 3 {
+        return value.chars().take(max_width).collect();
+    }
+    let truncated: String = value.chars().take(max_width.saturating_sub(3)).collect();
+    format!("{truncated}...")
+}
+
+/// Converts a schema to a batch with one row per field so it can be printed like query results
+pub fn schema_to_batch(schema: &Schema) -> Result<RecordBatch, ArrowError> {
+    let names: StringArray = schema.fields().iter().map(|f| Some(f.name())).collect();
+    let data_types: StringArray = schema
+        .fields()
+        .iter()
+        .map(|f| Some(f.data_type().to_string()))
+        .collect();
+    let nullable: BooleanArray = schema
+        .fields()
+        .iter()
+        .map(|f| Some(f.is_nullable()))
+        .collect();
+    let output_schema = Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("data_type", DataType::Utf8, false),
+        Field::new("nullable", DataType::Boolean, false),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(output_schema),
+        vec![Arc::new(names), Arc::new(data_types), Arc::new(nullable)],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::truncate_value;
+
+    #[test]
+    fn test_truncate_value_narrow_widths() {
+        let value = "abcdef".to_string();
+        assert_eq!(truncate_value(value.clone(), 0), "");
+        assert_eq!(truncate_value(value.clone(), 1), "a");
+        assert_eq!(truncate_value(value.clone(), 2), "ab");
+        assert_eq!(truncate_value(value.clone(), 3), "abc");
+        assert_eq!(truncate_value(value.clone(), 4), "a...");
+        assert_eq!(truncate_value("ab".to_string(), 3), "ab");
+    }
+}
@@ -0,0 +1,88 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Structured error output for `--error-format json`
+
+use datafusion::arrow::error::ArrowError;
+use datafusion::error::DataFusionError;
+
+/// Maximum number of characters of a statement included with its error
+const SQL_SNIPPET_LENGTH: usize = 200;
+
+/// An error that has already been written to stderr as JSON, so it should not be printed again
+#[derive(Debug)]
+pub struct ReportedError;
+
+impl std::fmt::Display for ReportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error already reported")
+    }
+}
+
+impl std::error::Error for ReportedError {}
+
+/// Classify an error by the stage of running a statement that it came from
+pub fn error_kind(error: &color_eyre::Report) -> &'static str {
+    if let Some(e) = error.downcast_ref::<DataFusionError>() {
+        match e.find_root() {
+            DataFusionError::SQL(..) => "parse",
+            DataFusionError::Plan(_)
+            | DataFusionError::SchemaError(..)
+            | DataFusionError::NotImplemented(_)
+            | DataFusionError::Configuration(_) => "planning",
+            DataFusionError::ResourcesExhausted(_) => "resources_exhausted",
+            _ => "execution",
+        }
+    } else if error.downcast_ref::<ArrowError>().is_some() {
+        "execution"
+    } else {
+        "other"
+    }
+}
+
+/// The error as a JSON object with its kind, message, and if it came from a statement the
+/// statement's index and the start of its SQL
+pub fn error_to_json(
+    error: &color_eyre::Report,
+    statement: Option<usize>,
+    sql: Option<&str>,
+) -> serde_json::Value {
+    let sql = sql.map(|sql| {
+        let sql = sql.trim();
+        match sql.char_indices().nth(SQL_SNIPPET_LENGTH) {
+            Some((end, _)) => format!("{}...", &sql[..end]),
+            None => sql.to_string(),
+        }
+    });
+    serde_json::json!({
+        "kind": error_kind(error),
+        "message": error.to_string(),
+        "statement": statement,
+        "sql": sql,
+    })
+}
+
+/// An error that happened while writing the results of a statement, such as a batch that failed
+/// while streaming, as a JSON object like [`error_to_json`]
+pub fn output_error_to_json(message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "kind": "execution",
+        "message": message,
+        "statement": null,
+        "sql": null,
+    })
+}
@@ -0,0 +1,188 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Formatting of SQL files for the `fmt` subcommand
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use datafusion::sql::parser::{DFParser, Statement};
+use datafusion::sql::sqlparser::dialect::{dialect_from_str, Dialect};
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::tokenizer::{Location, Token, Tokenizer, Whitespace};
+use std::path::PathBuf;
+
+use datafusion_app::config::SqlDialect;
+
+use crate::args::KeywordCase;
+
+/// How [`format_sql`] lays out statements
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FormatOptions {
+    /// Statements that fit within this many characters are kept on a single line
+    pub max_width: Option<usize>,
+    pub keyword_case: KeywordCase,
+    /// Dialect the SQL is parsed with, the one the CLI is configured with
+    pub dialect: SqlDialect,
+}
+
+/// Normalize `sql` by parsing it and printing each statement back from its AST. Keywords are
+/// upper cased and clauses are indented one per line. Statements are separated by a blank line.
+///
+/// Comments aren't part of the AST, so SQL with comments is refused rather than formatted without
+/// them.
+pub fn format_sql(sql: &str, options: FormatOptions) -> Result<String> {
+    let dialect = dialect_from_str(options.dialect.name())
+        .ok_or_else(|| eyre!("Unsupported SQL dialect: {}", options.dialect.name()))?;
+    let dialect = dialect.as_ref();
+    if has_comments(sql, dialect)? {
+        return Err(eyre!(
+            "SQL with comments can't be formatted because the comments would be dropped"
+        ));
+    }
+    let statements = DFParser::parse_sql_with_dialect(sql, dialect)?;
+    let formatted: Vec<String> = statements
+        .iter()
+        .map(|statement| {
+            let single_line = format!("{statement};");
+            let fits = options
+                .max_width
+                .is_some_and(|width| single_line.chars().count() <= width);
+            match statement {
+                _ if fits => single_line,
+                // The sqlparser AST pretty prints with the alternate flag
+                Statement::Statement(statement) => format!("{statement:#};"),
+                _ => single_line,
+            }
+        })
+        .collect();
+    let formatted = format!("{}\n", formatted.join("\n\n"));
+    match options.keyword_case {
+        KeywordCase::Upper => Ok(formatted),
+        KeywordCase::Lower => lowercase_keywords(&formatted, dialect),
+    }
+}
+
+fn has_comments(sql: &str, dialect: &dyn Dialect) -> Result<bool> {
+    let tokens = Tokenizer::new(dialect, sql).tokenize()?;
+    Ok(tokens.iter().any(|token| {
+        matches!(
+            token,
+            Token::Whitespace(
+                Whitespace::SingleLineComment { .. } | Whitespace::MultiLineComment(_)
+            )
+        )
+    }))
+}
+
+/// Lower case the unquoted keywords of `sql`, leaving everything else as it was printed
+fn lowercase_keywords(sql: &str, dialect: &dyn Dialect) -> Result<String> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(sql.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    // Locations are 1 based lines and characters
+    let offset = |location: Location| {
+        let line_start = line_starts[location.line as usize - 1];
+        sql[line_start..]
+            .char_indices()
+            .nth(location.column as usize - 1)
+            .map_or(sql.len(), |(i, _)| line_start + i)
+    };
+    let mut formatted = sql.to_string();
+    for token in Tokenizer::new(dialect, sql).tokenize_with_location()? {
+        if let Token::Word(word) = &token.token {
+            if word.quote_style.is_none() && word.keyword != Keyword::NoKeyword {
+                let range = offset(token.span.start)..offset(token.span.end);
+                // Keywords are ASCII, so lower casing them doesn't move the other tokens
+                let lower = formatted[range.clone()].to_ascii_lowercase();
+                formatted.replace_range(range, &lower);
+            }
+        }
+    }
+    Ok(formatted)
+}
+
+/// Format each of `files`. The formatted SQL is printed unless `write` is set, in which case the
+/// files are rewritten in place. With `check` nothing is printed or written and an error is
+/// returned if any file is not already formatted.
+pub fn format_files(
+    files: &[PathBuf],
+    check: bool,
+    write: bool,
+    options: FormatOptions,
+) -> Result<()> {
+    let mut unformatted = Vec::new();
+    for file in files {
+        let sql = std::fs::read_to_string(file)?;
+        let formatted = format_sql(&sql, options)
+            .map_err(|e| eyre!("Error formatting '{}': {e}", file.display()))?;
+        if check {
+            if formatted != sql {
+                println!("Would reformat: {}", file.display());
+                unformatted.push(file);
+            }
+        } else if write {
+            if formatted != sql {
+                std::fs::write(file, formatted)?;
+                println!("Reformatted: {}", file.display());
+            }
+        } else {
+            print!("{formatted}");
+        }
+    }
+    if unformatted.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("{} file(s) would be reformatted", unformatted.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_sql() {
+        let sql = "select a, 'it''s' as \"select\" from t where a>1";
+        let formatted = format_sql(sql, FormatOptions::default()).unwrap();
+        assert!(formatted.starts_with("SELECT\n"));
+
+        let options = FormatOptions {
+            max_width: Some(80),
+            keyword_case: KeywordCase::Lower,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_sql(sql, options).unwrap(),
+            "select a, 'it''s' as \"select\" from t where a > 1;\n"
+        );
+
+        let options = FormatOptions {
+            max_width: Some(10),
+            keyword_case: KeywordCase::Upper,
+            ..Default::default()
+        };
+        assert_eq!(format_sql(sql, options).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_sql_with_comments() {
+        for sql in ["SELECT 1 -- one", "/* one */ SELECT 1"] {
+            let err = format_sql(sql, FormatOptions::default()).unwrap_err();
+            assert!(err.to_string().contains("comments"));
+        }
+    }
+}
@@ -16,35 +16,69 @@
 // under the License.
 //! [`CliApp`]: Command Line User Interface
 
+mod convert;
+mod cp;
+mod display;
+mod errors;
+mod fmt;
 mod progress;
+mod record;
 
+use crate::args::{
+    CatalogCommand, Command, DftArgs, ErrorFormat, FileFormat, MetricsFormat, OutputCompression,
+    OutputFormat, PlanFileFormat, TpchCommand,
+};
 use crate::config::AppConfig;
-use crate::db::register_db;
-use crate::{args::DftArgs, execution::AppExecution};
+use crate::db::{
+    add_catalog_persistence, register_db, restore_catalog, restore_system_tables,
+    save_system_tables,
+};
+use crate::execution::AppExecution;
+use crate::tpch;
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use datafusion::arrow::array::{RecordBatch, RecordBatchWriter};
+use datafusion::arrow::array::RecordBatch;
 use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::arrow::util::pretty::pretty_format_batches;
 use datafusion::arrow::{csv, json};
-use datafusion::sql::parser::DFParser;
+use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::prelude::{col, lit, CsvReadOptions, NdJsonReadOptions, ParquetReadOptions};
+use datafusion::sql::parser::{DFParser, Statement};
+use datafusion::sql::sqlparser::ast::Statement as SQLStatement;
+use datafusion::sql::sqlparser::dialect::Dialect;
+use datafusion::sql::TableReference;
+use datafusion_app::cancel::QueryHandle;
 use datafusion_app::config::merge_configs;
 use datafusion_app::extensions::DftSessionStateBuilder;
 use datafusion_app::local::ExecutionContext;
-use datafusion_app::local_benchmarks::LocalBenchmarkStats;
+use datafusion_app::local_benchmarks::{duration_ms, BenchmarkBaseline, LocalBenchmarkStats};
+use datafusion_app::object_store_ddl::parse_create_object_store;
+use datafusion_app::plans::PlanFormat;
+use display::RowLimiter;
+use errors::output_error_to_json;
+pub use errors::{error_to_json, ReportedError};
+pub use fmt::{format_files, format_sql, FormatOptions};
 use futures::{Stream, StreamExt};
 use log::info;
 use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+use record::SessionRecorder;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 #[cfg(feature = "flightsql")]
 use {
-    crate::args::{parse_headers_file, Command, FlightSqlCommand},
+    crate::args::{parse_headers_file, FlightSqlCommand},
+    arrow_flight::sql::{
+        CommandGetCrossReference, CommandGetExportedKeys, CommandGetImportedKeys,
+        CommandGetPrimaryKeys,
+    },
+    datafusion::arrow::error::ArrowError,
     datafusion_app::{
-        config::{AuthConfig, FlightSQLConfig},
-        flightsql::FlightSQLContext,
+        config::FlightSQLConfig,
+        flightsql::{FlightSQLContext, KeysCommand},
         flightsql_benchmarks::FlightSQLBenchmarkStats,
     },
     tonic::IntoRequest,
@@ -57,7 +91,7 @@ use {
 };
 
 const LOCAL_BENCHMARK_HEADER_ROW: &str =
-    "query,runs,logical_planning_min,logical_planning_max,logical_planning_mean,logical_planning_median,logical_planning_percent_of_total,physical_planning_min,physical_planning_max,physical_planning,mean,physical_planning_median,physical_planning_percent_of_total,execution_min,execution_max,execution_execution_mean,execution_median,execution_percent_of_total,total_min,total_max,total_mean,total_median,total_percent_of_total,concurrency_mode";
+    "query,runs,logical_planning_min,logical_planning_max,logical_planning_mean,logical_planning_median,logical_planning_percent_of_total,physical_planning_min,physical_planning_max,physical_planning,mean,physical_planning_median,physical_planning_percent_of_total,execution_min,execution_max,execution_execution_mean,execution_median,execution_percent_of_total,total_min,total_max,total_mean,total_median,total_percent_of_total,peak_memory_median,spill_count_median,concurrency_mode";
 
 #[cfg(feature = "flightsql")]
 const FLIGHTSQL_BENCHMARK_HEADER_ROW: &str =
@@ -68,14 +102,25 @@ pub struct CliApp {
     /// Execution context for running queries
     app_execution: AppExecution,
     args: DftArgs,
+    /// Records executed statements if `--record` was provided
+    recorder: Option<SessionRecorder>,
+    /// Number of statements whose plans were saved with `--save-plans`, used to name their files
+    saved_plans: std::sync::atomic::AtomicUsize,
 }
 
 impl CliApp {
-    pub fn new(app_execution: AppExecution, args: DftArgs) -> Self {
-        Self {
+    pub fn try_new(app_execution: AppExecution, args: DftArgs) -> Result<Self> {
+        let recorder = args
+            .record
+            .as_deref()
+            .map(SessionRecorder::try_new)
+            .transpose()?;
+        Ok(Self {
             app_execution,
             args,
-        }
+            recorder,
+            saved_plans: std::sync::atomic::AtomicUsize::new(0),
+        })
     }
 
     fn validate_args(&self) -> color_eyre::Result<()> {
@@ -88,6 +133,90 @@ impl CliApp {
             ));
         }
 
+        if self.args.time_breakdown && (self.args.flightsql || !self.args.partition_by.is_empty()) {
+            return Err(eyre!(
+                "The `time-breakdown` flag can only be used for local queries without `partition-by`"
+            ));
+        }
+
+        if !self.args.partition_by.is_empty() && (self.args.output.is_none() || self.args.flightsql)
+        {
+            return Err(eyre!(
+                "The `partition-by` flag can only be used with `output` for local queries"
+            ));
+        }
+
+        if self.args.row_group_size.is_some() && self.args.output.is_none() {
+            return Err(eyre!(
+                "The `row-group-size` flag can only be used with `output`"
+            ));
+        }
+
+        if self.args.create_table.is_some()
+            && (self.args.commands.len() + self.args.files.len() != 1
+                || self.args.flightsql
+                || self.args.bench
+                || self.args.analyze
+                || self.args.dry_run
+                || self.args.output.is_some())
+        {
+            return Err(eyre!(
+                "The `create-table` flag can only be used with a single local file or command and without `bench`, `analyze`, `dry-run`, or `output`"
+            ));
+        }
+
+        // Only statements executed locally are recorded
+        if self.args.record.is_some() && self.args.flightsql {
+            return Err(eyre!(
+                "The `record` flag can only be used for local queries"
+            ));
+        }
+
+        if self.args.compare.is_some() && (!self.args.bench || self.args.flightsql) {
+            return Err(eyre!(
+                "The `compare` flag can only be used when benchmarking local queries"
+            ));
+        }
+
+        if self.args.timeout == Some(0) {
+            return Err(eyre!("The timeout must be greater than zero"));
+        }
+
+        if let Some(jobs) = self.args.jobs {
+            if jobs == 0 {
+                return Err(eyre!("The number of jobs must be greater than zero"));
+            }
+            if self.args.bench || self.args.analyze || self.args.dry_run {
+                return Err(eyre!(
+                    "The `jobs` flag can not be used with the `bench`, `analyze`, or `dry-run` flags"
+                ));
+            }
+            // Every query would write its results to, and truncate, the same file
+            if self.args.output.is_some() {
+                return Err(eyre!("The `jobs` flag can not be used with `output`"));
+            }
+        }
+
+        if self.args.dry_run && (self.args.bench || self.args.analyze) {
+            return Err(eyre!(
+                "The `dry-run` flag cannot be used with the `bench` or `analyze` flags"
+            ));
+        }
+
+        if (self.args.save_plans.is_some() || self.args.run_plan.is_some())
+            && (self.args.flightsql || self.args.bench || self.args.analyze || self.args.dry_run)
+        {
+            return Err(eyre!(
+                "The `save-plans` and `run-plan` flags can only be used to execute local queries, without `bench`, `analyze`, or `dry-run`"
+            ));
+        }
+
+        if self.args.metrics_output.is_some() && !self.json_metrics() {
+            return Err(eyre!(
+                "The `metrics-output` flag can only be used with `--metrics-format json`"
+            ));
+        }
+
         Ok(())
     }
 
@@ -96,7 +225,48 @@ impl CliApp {
         use futures::stream;
 
         match command {
-            FlightSqlCommand::StatementQuery { sql } => self.exec_from_flightsql(sql, 0).await,
+            FlightSqlCommand::StatementQuery { sql } => {
+                self.exec_from_flightsql(sql, 0, &mut std::io::stdout())
+                    .await
+            }
+            FlightSqlCommand::PreparedQuery { sql, params } => {
+                let mut prepared_statement = self
+                    .app_execution
+                    .flightsql_ctx()
+                    .prepare_statement(sql, params)
+                    .await?;
+                let res = async {
+                    let flight_info = prepared_statement.execute().await?;
+                    let streams = self
+                        .app_execution
+                        .flightsql_ctx()
+                        .do_get(flight_info)
+                        .await?;
+                    let flight_batch_stream = stream::select_all(streams);
+                    self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                        .await
+                }
+                .await;
+                // Close the statement on the server even if executing it failed
+                prepared_statement.close().await?;
+                res
+            }
+            FlightSqlCommand::Ingest { table, sql } => {
+                let batches = self
+                    .app_execution
+                    .session_ctx()
+                    .sql(&sql)
+                    .await?
+                    .execute_stream()
+                    .await?;
+                let rows = self
+                    .app_execution
+                    .flightsql_ctx()
+                    .ingest(&table, batches)
+                    .await?;
+                println!("Ingested {rows} rows into {table}");
+                Ok(())
+            }
             FlightSqlCommand::GetCatalogs => {
                 let flight_info = self
                     .app_execution
@@ -109,7 +279,8 @@ impl CliApp {
                     .do_get(flight_info)
                     .await?;
                 let flight_batch_stream = stream::select_all(streams);
-                self.print_stream(flight_batch_stream).await;
+                self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                    .await?;
                 Ok(())
             }
             FlightSqlCommand::GetDbSchemas {
@@ -127,7 +298,8 @@ impl CliApp {
                     .do_get(flight_info)
                     .await?;
                 let flight_batch_stream = stream::select_all(streams);
-                self.print_stream(flight_batch_stream).await;
+                self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                    .await?;
                 Ok(())
             }
 
@@ -154,7 +326,8 @@ impl CliApp {
                     .do_get(flight_info)
                     .await?;
                 let flight_batch_stream = stream::select_all(streams);
-                self.print_stream(flight_batch_stream).await;
+                self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                    .await?;
                 Ok(())
             }
             FlightSqlCommand::GetTableTypes => {
@@ -169,9 +342,52 @@ impl CliApp {
                     .do_get(flight_info)
                     .await?;
                 let flight_batch_stream = stream::select_all(streams);
-                self.print_stream(flight_batch_stream).await;
+                self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                    .await?;
                 Ok(())
             }
+            FlightSqlCommand::GetPrimaryKeys { table } => {
+                let cmd = CommandGetPrimaryKeys {
+                    catalog: table.catalog,
+                    db_schema: table.db_schema,
+                    table: table.table,
+                };
+                self.print_keys(KeysCommand::Primary(cmd)).await
+            }
+            FlightSqlCommand::GetExportedKeys { table } => {
+                let cmd = CommandGetExportedKeys {
+                    catalog: table.catalog,
+                    db_schema: table.db_schema,
+                    table: table.table,
+                };
+                self.print_keys(KeysCommand::Exported(cmd)).await
+            }
+            FlightSqlCommand::GetImportedKeys { table } => {
+                let cmd = CommandGetImportedKeys {
+                    catalog: table.catalog,
+                    db_schema: table.db_schema,
+                    table: table.table,
+                };
+                self.print_keys(KeysCommand::Imported(cmd)).await
+            }
+            FlightSqlCommand::GetCrossReference {
+                pk_catalog,
+                pk_db_schema,
+                pk_table,
+                fk_catalog,
+                fk_db_schema,
+                fk_table,
+            } => {
+                let cmd = CommandGetCrossReference {
+                    pk_catalog,
+                    pk_db_schema,
+                    pk_table,
+                    fk_catalog,
+                    fk_db_schema,
+                    fk_table,
+                };
+                self.print_keys(KeysCommand::CrossReference(cmd)).await
+            }
             FlightSqlCommand::GetSqlInfo { info } => {
                 let flight_info = self
                     .app_execution
@@ -184,7 +400,8 @@ impl CliApp {
                     .do_get(flight_info)
                     .await?;
                 let flight_batch_stream = stream::select_all(streams);
-                self.print_stream(flight_batch_stream).await;
+                self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                    .await?;
                 Ok(())
             }
             FlightSqlCommand::GetXdbcTypeInfo { data_type } => {
@@ -199,18 +416,50 @@ impl CliApp {
                     .do_get(flight_info)
                     .await?;
                 let flight_batch_stream = stream::select_all(streams);
-                self.print_stream(flight_batch_stream).await;
+                self.print_stream(flight_batch_stream, &mut std::io::stdout())
+                    .await?;
                 Ok(())
             }
         }
     }
 
+    /// Print the key columns returned by one of the FlightSQL key commands
+    #[cfg(feature = "flightsql")]
+    async fn print_keys(&self, command: KeysCommand) -> color_eyre::Result<()> {
+        let flightsql_ctx = self.app_execution.flightsql_ctx();
+        let flight_info = flightsql_ctx.get_keys_flight_info(command).await?;
+        let streams = flightsql_ctx.do_get(flight_info).await?;
+        let flight_batch_stream = futures::stream::select_all(streams);
+        self.print_stream(flight_batch_stream, &mut std::io::stdout())
+            .await
+    }
+
     /// Execute the provided sql, which was passed as an argument from CLI.
     ///
     /// Optionally, use the FlightSQL client for execution.
     pub async fn execute_files_or_commands(&self) -> color_eyre::Result<()> {
         if self.args.run_ddl {
-            self.app_execution.execution_ctx().execute_ddl().await;
+            for error in self.app_execution.execution_ctx().execute_ddl().await {
+                if self.json_errors() {
+                    eprintln!(
+                        "{}",
+                        error_to_json(&color_eyre::Report::new(error), None, None)
+                    );
+                } else {
+                    eprintln!("Error executing DDL: {error}");
+                }
+            }
+        }
+
+        if let Some(ddl_sql) = &self.args.ddl_sql {
+            for ddl in ddl_sql {
+                info!("Executing DDL: {ddl}");
+                self.app_execution
+                    .execution_ctx()
+                    .execute_sql_and_discard_results(ddl)
+                    .await
+                    .map_err(|e| eyre!("Error executing DDL '{ddl}': {e}"))?;
+            }
         }
 
         self.validate_args()?;
@@ -220,6 +469,81 @@ impl CliApp {
             return self.handle_flightsql_command(command.clone()).await;
         };
 
+        match &self.args.command {
+            Some(Command::Analyze { files, commands }) => {
+                return self.analyze_operators(files, commands).await
+            }
+            Some(Command::Schema { target, json }) => {
+                return self.print_schema(target, *json).await
+            }
+            Some(Command::Catalog { command }) => return self.exec_catalog_command(command).await,
+            Some(Command::Replay { session }) => return self.replay(session).await,
+            Some(Command::Tpch {
+                command: TpchCommand::Run { query, .. },
+            }) => return self.run_tpch(query).await,
+            Some(Command::Convert {
+                input,
+                output,
+                input_format,
+                output_format,
+                compression,
+                partition_by,
+                casts,
+            }) => {
+                let options = convert::ConvertOptions {
+                    input,
+                    output,
+                    input_format: *input_format,
+                    output_format: *output_format,
+                    compression: compression.as_deref(),
+                    partition_by,
+                    casts,
+                };
+                let ctx = self.app_execution.session_ctx();
+                convert::convert(ctx, options).await?;
+                println!("Converted '{input}' to '{output}'");
+                return Ok(());
+            }
+            Some(Command::Cp {
+                src,
+                dst,
+                format: Some(format),
+                input_format,
+            }) => {
+                let options = convert::ConvertOptions {
+                    input: src,
+                    output: dst,
+                    input_format: *input_format,
+                    output_format: Some(*format),
+                    compression: None,
+                    partition_by: &[],
+                    casts: &[],
+                };
+                let ctx = self.app_execution.session_ctx();
+                convert::convert(ctx, options).await?;
+                println!("Copied '{src}' to '{dst}'");
+                return Ok(());
+            }
+            Some(Command::Cp { src, dst, .. }) => {
+                let ctx = self.app_execution.session_ctx();
+                let summary = cp::copy(ctx, src, dst).await?;
+                println!(
+                    "Copied {} file(s) ({} bytes) from '{src}' to '{dst}'",
+                    summary.files, summary.bytes
+                );
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if self.args.dry_run {
+            return self.dry_run().await;
+        }
+
+        if let Some(plan) = &self.args.run_plan {
+            return self.run_saved_plan(plan).await;
+        }
+
         #[cfg(not(feature = "flightsql"))]
         match (
             self.args.files.is_empty(),
@@ -308,6 +632,13 @@ impl CliApp {
 
     async fn execute_files(&self, files: &[PathBuf]) -> Result<()> {
         info!("Executing files: {:?}", files);
+        if self.args.jobs.is_some() {
+            let queries = files
+                .iter()
+                .map(|file| self.read_sql_file(file))
+                .collect::<Result<Vec<String>>>()?;
+            return self.execute_concurrently(queries).await;
+        }
         for file in files {
             self.exec_from_file(file).await?
         }
@@ -315,6 +646,40 @@ impl CliApp {
         Ok(())
     }
 
+    /// Execute the queries with up to `--jobs` of them running at the same time.  The output of
+    /// each query is buffered and printed, in the order the queries were provided, once it
+    /// completes so that output from different queries is never interleaved.
+    async fn execute_concurrently(&self, queries: Vec<String>) -> Result<()> {
+        let jobs = self.args.jobs.unwrap_or(1);
+        info!("Executing {} queries with {} jobs", queries.len(), jobs);
+        let mut results = futures::stream::iter(
+            queries
+                .into_iter()
+                .enumerate()
+                .map(|(i, query)| self.exec_buffered(query, i)),
+        )
+        .buffered(jobs);
+        let mut stdout = std::io::stdout();
+        while let Some((output, result)) = results.next().await {
+            stdout.write_all(&output)?;
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Execute the query, locally or with FlightSQL, and return its buffered output
+    #[cfg_attr(not(feature = "flightsql"), allow(unused_variables))]
+    async fn exec_buffered(&self, query: String, i: usize) -> (Vec<u8>, Result<()>) {
+        let mut output = Vec::new();
+        #[cfg(feature = "flightsql")]
+        if self.args.flightsql {
+            let result = self.exec_from_flightsql(query, i, &mut output).await;
+            return (output, result);
+        }
+        let result = self.exec_from_string(&query, &mut output).await;
+        (output, result)
+    }
+
     async fn benchmark_files(&self, files: &[PathBuf]) -> Result<()> {
         if let Some(run_before_query) = &self.args.run_before {
             self.app_execution
@@ -323,18 +688,27 @@ impl CliApp {
                 .await?;
         }
         info!("Benchmarking files: {:?}", files);
+        let mut results_file = self.open_benchmark_results(LOCAL_BENCHMARK_HEADER_ROW)?;
+        let baselines = self.load_benchmark_baselines()?;
+        let mut regressed = false;
         for file in files {
-            let query = std::fs::read_to_string(file)?;
+            let query = self.read_sql_file(file)?;
             let stats = self.benchmark_from_string(&query).await?;
-            println!("{}", stats);
+            self.print_stats(&stats, stats.to_json())?;
+            if let Some(results_file) = &mut results_file {
+                writeln!(results_file, "{}", stats.to_summary_csv_row())?;
+            }
+            if let Some(baselines) = &baselines {
+                regressed |= self.compare_to_baseline(&stats, baselines);
+            }
         }
-        Ok(())
+        self.check_regressions(regressed)
     }
 
     async fn analyze_files(&self, files: &[PathBuf]) -> Result<()> {
         info!("Analyzing files: {:?}", files);
         for file in files {
-            let query = std::fs::read_to_string(file)?;
+            let query = self.read_sql_file(file)?;
             self.analyze_from_string(&query).await?;
         }
         Ok(())
@@ -343,9 +717,18 @@ impl CliApp {
     #[cfg(feature = "flightsql")]
     async fn flightsql_execute_files(&self, files: &[PathBuf]) -> color_eyre::Result<()> {
         info!("Executing FlightSQL files: {:?}", files);
+        if self.args.jobs.is_some() {
+            let queries = files
+                .iter()
+                .map(|file| self.read_sql_file(file))
+                .collect::<Result<Vec<String>>>()?;
+            return self.execute_concurrently(queries).await;
+        }
         for (i, file) in files.iter().enumerate() {
-            let file = std::fs::read_to_string(file)?;
-            self.exec_from_flightsql(file, i).await?;
+            let sql = self.read_sql_file(file)?;
+            self.exec_from_flightsql(sql.clone(), i, &mut std::io::stdout())
+                .await
+                .map_err(|e| self.report_error(e, Some(i), Some(&sql)))?;
         }
 
         Ok(())
@@ -355,30 +738,12 @@ impl CliApp {
     async fn flightsql_benchmark_files(&self, files: &[PathBuf]) -> Result<()> {
         info!("Benchmarking FlightSQL files: {:?}", files);
 
-        let mut open_opts = std::fs::OpenOptions::new();
-        let mut results_file = if let Some(p) = &self.args.save {
-            if !p.exists() {
-                if let Some(parent) = p.parent() {
-                    std::fs::DirBuilder::new().recursive(true).create(parent)?;
-                }
-            };
-            if self.args.append && p.exists() {
-                open_opts.append(true).create(true);
-                Some(open_opts.open(p)?)
-            } else {
-                open_opts.write(true).create(true).truncate(true);
-                let mut file = open_opts.open(p)?;
-                writeln!(file, "{}", FLIGHTSQL_BENCHMARK_HEADER_ROW)?;
-                Some(file)
-            }
-        } else {
-            None
-        };
+        let mut results_file = self.open_benchmark_results(FLIGHTSQL_BENCHMARK_HEADER_ROW)?;
 
         for file in files {
-            let query = std::fs::read_to_string(file)?;
+            let query = self.read_sql_file(file)?;
             let stats = self.flightsql_benchmark_from_string(&query).await?;
-            println!("{}", stats);
+            self.print_stats(&stats, stats.to_json())?;
             if let Some(ref mut results_file) = &mut results_file {
                 writeln!(results_file, "{}", stats.to_summary_csv_row())?
             }
@@ -387,35 +752,93 @@ impl CliApp {
         Ok(())
     }
 
+    /// Run a FlightSQL request, retrying it with exponential backoff if it fails with a
+    /// transient error and `--retries` was provided
     #[cfg(feature = "flightsql")]
-    async fn exec_from_flightsql(&self, sql: String, i: usize) -> color_eyre::Result<()> {
-        let client = self.app_execution.flightsql_client();
-        let mut guard = client.lock().await;
-        if let Some(client) = guard.as_mut() {
+    async fn with_retries<T, F, Fut>(&self, mut request: F) -> Result<T, ArrowError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, ArrowError>>,
+    {
+        let mut backoff = std::time::Duration::from_millis(self.args.retry_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Err(e) if attempt < self.args.retries && is_transient_flightsql_error(&e) => {
+                    attempt += 1;
+                    info!(
+                        "FlightSQL request failed ({e}), retrying in {:?} (attempt {attempt} of {})",
+                        backoff, self.args.retries
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    #[cfg(feature = "flightsql")]
+    async fn exec_from_flightsql(
+        &self,
+        sql: String,
+        i: usize,
+        out: &mut dyn Write,
+    ) -> color_eyre::Result<()> {
+        // The client is cloned so that queries run with `--jobs` don't wait on each other for it
+        let client = self.app_execution.flightsql_client().lock().await.clone();
+        if let Some(mut client) = client {
             let start = if self.args.time {
                 Some(std::time::Instant::now())
             } else {
                 None
             };
-            let flight_info = client.execute(sql, None).await?;
-            for endpoint in flight_info.endpoint {
-                if let Some(ticket) = endpoint.ticket {
-                    let stream = client.do_get(ticket.into_request()).await?;
-                    if let Some(output_path) = &self.args.output {
-                        self.output_stream(stream, output_path).await?
-                    } else if self.args.json {
-                        self.print_json_stream(stream).await;
-                    } else if let Some(start) = start {
-                        self.exec_stream(stream).await;
-                        let elapsed = start.elapsed();
-                        println!("Query {i} executed in {:?}", elapsed);
-                    } else {
-                        self.print_any_stream(stream).await;
+            self.with_timeout(i, async {
+                let flight_info = self
+                    .with_retries(|| {
+                        let mut client = client.clone();
+                        let sql = sql.clone();
+                        async move { client.execute(sql, None).await }
+                    })
+                    .await?;
+                for endpoint in flight_info.endpoint {
+                    if let Some(ticket) = endpoint.ticket {
+                        let stream = self
+                            .with_retries(|| {
+                                let mut client = client.clone();
+                                let ticket = ticket.clone();
+                                async move { client.do_get(ticket.into_request()).await }
+                            })
+                            .await?;
+                        if let Some(output_path) = &self.args.output {
+                            self.output_stream(stream, output_path).await?
+                        } else if self.args.json {
+                            self.print_json_stream(stream, out).await?;
+                        } else if let Some(start) = start {
+                            self.exec_stream(stream, out).await?;
+                            let elapsed = start.elapsed();
+                            if self.json_metrics() {
+                                let metrics = serde_json::json!({
+                                    "query": i,
+                                    "elapsed_ms": duration_ms(elapsed),
+                                });
+                                self.write_metrics(metrics, out)?;
+                            } else {
+                                writeln!(out, "Query {i} executed in {:?}", elapsed)?;
+                            }
+                        } else {
+                            self.print_any_stream(stream, out).await?;
+                        }
                     }
                 }
-            }
+                Ok(())
+            })
+            .await?;
         } else {
-            println!("No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`");
+            writeln!(
+                out,
+                "No FlightSQL client configured.  Add one in `~/.config/dft/config.toml`"
+            )?;
         }
 
         Ok(())
@@ -430,8 +853,12 @@ impl CliApp {
                 .await?;
         }
 
+        if self.args.jobs.is_some() {
+            return self.execute_concurrently(commands.to_vec()).await;
+        }
         for command in commands {
-            self.exec_from_string(command).await?
+            self.exec_from_string(command, &mut std::io::stdout())
+                .await?
         }
 
         Ok(())
@@ -445,36 +872,176 @@ impl CliApp {
                 .await?;
         }
         info!("Benchmarking commands: {:?}", commands);
-        let mut open_opts = std::fs::OpenOptions::new();
-        let mut file = if let Some(p) = &self.args.save {
-            if !p.exists() {
-                if let Some(parent) = p.parent() {
-                    std::fs::DirBuilder::new().recursive(true).create(parent)?;
-                }
-            };
-            if self.args.append && p.exists() {
-                open_opts.append(true).create(true);
-                Some(open_opts.open(p)?)
-            } else {
-                open_opts.write(true).create(true).truncate(true);
-                let mut file = open_opts.open(p)?;
-                writeln!(file, "{}", LOCAL_BENCHMARK_HEADER_ROW)?;
-                Some(file)
-            }
-        } else {
-            None
-        };
+        let mut file = self.open_benchmark_results(LOCAL_BENCHMARK_HEADER_ROW)?;
 
+        let baselines = self.load_benchmark_baselines()?;
+        let mut regressed = false;
         for command in commands {
             let stats = self.benchmark_from_string(command).await?;
-            println!("{}", stats);
+            self.print_stats(&stats, stats.to_json())?;
             if let Some(ref mut file) = &mut file {
                 writeln!(file, "{}", stats.to_summary_csv_row())?;
             }
+            if let Some(baselines) = &baselines {
+                regressed |= self.compare_to_baseline(&stats, baselines);
+            }
+        }
+        self.check_regressions(regressed)
+    }
+
+    /// Whether errors from statements should be written to stderr as JSON
+    fn json_errors(&self) -> bool {
+        self.args.error_format == ErrorFormat::Json
+    }
+
+    /// With `--error-format json`, write the error from a statement to stderr as JSON and
+    /// return a [`ReportedError`] in its place so that it isn't printed again
+    fn report_error(
+        &self,
+        error: color_eyre::Report,
+        statement: Option<usize>,
+        sql: Option<&str>,
+    ) -> color_eyre::Report {
+        if !self.json_errors() || error.is::<ReportedError>() {
+            return error;
+        }
+        eprintln!("{}", error_to_json(&error, statement, sql));
+        color_eyre::Report::new(ReportedError)
+    }
+
+    /// Write an error that happened while writing the results of a statement. With
+    /// `--error-format json` it's written to stderr as JSON and a [`ReportedError`] is returned,
+    /// otherwise it's written in place of the results.
+    fn write_output_error(&self, message: String, out: &mut dyn Write) -> Result<()> {
+        if !self.json_errors() {
+            writeln!(out, "{message}")?;
+            return Ok(());
+        }
+        eprintln!("{}", output_error_to_json(&message));
+        Err(ReportedError.into())
+    }
+
+    /// Whether metrics should be written as JSON rather than human readable text
+    fn json_metrics(&self) -> bool {
+        self.args.metrics_format == MetricsFormat::Json
+    }
+
+    /// Write a JSON metrics object on its own line, appending it to the `--metrics-output` file
+    /// if one was provided
+    fn write_metrics(&self, metrics: serde_json::Value, out: &mut dyn Write) -> Result<()> {
+        match &self.args.metrics_output {
+            Some(path) => {
+                let mut file = std::fs::OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(path)?;
+                writeln!(file, "{metrics}")?;
+            }
+            None => writeln!(out, "{metrics}")?,
         }
         Ok(())
     }
 
+    /// Print benchmark or analyze stats in the format selected with `--metrics-format`
+    fn print_stats(&self, stats: &impl std::fmt::Display, json: serde_json::Value) -> Result<()> {
+        if self.json_metrics() {
+            self.write_metrics(json, &mut std::io::stdout())
+        } else {
+            println!("{}", stats);
+            Ok(())
+        }
+    }
+
+    /// Run a statement, failing with a timeout error if it doesn't complete within `--timeout`.
+    /// The statement is cancelled when the timeout elapses, which drops its stream and aborts its
+    /// execution.
+    async fn with_timeout<T>(
+        &self,
+        i: usize,
+        statement: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        match self.args.timeout {
+            Some(secs) => {
+                let handle = QueryHandle::new();
+                let _timer = handle.cancel_after(std::time::Duration::from_secs(secs));
+                handle
+                    .until_cancelled(statement)
+                    .await
+                    .ok_or_else(|| eyre!("Query {i} timed out after {secs}s"))?
+            }
+            None => statement.await,
+        }
+    }
+
+    /// Open the file passed with `--save` for benchmark results, writing `header` unless
+    /// results are appended to an existing file
+    fn open_benchmark_results(&self, header: &str) -> Result<Option<std::fs::File>> {
+        let Some(p) = &self.args.save else {
+            return Ok(None);
+        };
+        if !p.exists() {
+            if let Some(parent) = p.parent() {
+                std::fs::DirBuilder::new().recursive(true).create(parent)?;
+            }
+        };
+        let mut open_opts = std::fs::OpenOptions::new();
+        if self.args.append && p.exists() {
+            open_opts.append(true).create(true);
+            Ok(Some(open_opts.open(p)?))
+        } else {
+            open_opts.write(true).create(true).truncate(true);
+            let mut file = open_opts.open(p)?;
+            writeln!(file, "{}", header)?;
+            Ok(Some(file))
+        }
+    }
+
+    /// Load the baselines from the file passed with `--compare`, if any
+    fn load_benchmark_baselines(&self) -> Result<Option<Vec<BenchmarkBaseline>>> {
+        let Some(path) = &self.args.compare else {
+            return Ok(None);
+        };
+        let file = std::fs::File::open(path)
+            .map_err(|e| eyre!("Error reading baseline file '{}': {e}", path.display()))?;
+        let baselines = BenchmarkBaseline::read_csv(file).map_err(|e| eyre!(e))?;
+        Ok(Some(baselines))
+    }
+
+    /// Print the comparison of the benchmark against its baseline, returning whether any stage or
+    /// resource regressed by more than the configured threshold.  If the query was benchmarked
+    /// more than once in the baseline file the latest result is used.
+    fn compare_to_baseline(
+        &self,
+        stats: &LocalBenchmarkStats,
+        baselines: &[BenchmarkBaseline],
+    ) -> bool {
+        let Some(baseline) = baselines.iter().rev().find(|b| b.query == stats.query()) else {
+            println!("No baseline found for query: {}", stats.query());
+            return false;
+        };
+        let comparison = stats.compare(baseline);
+        println!("{}", comparison);
+        let regressions = comparison.regressions(self.args.regression_threshold);
+        for regression in &regressions {
+            println!(
+                "\x1b[31m{} regressed by {:.2}%\x1b[0m",
+                regression.name, regression.change_percent
+            );
+        }
+        !regressions.is_empty()
+    }
+
+    fn check_regressions(&self, regressed: bool) -> Result<()> {
+        if regressed {
+            Err(eyre!(
+                "Benchmark regressed by more than {}% compared to the baseline",
+                self.args.regression_threshold
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     async fn analyze_commands(&self, commands: &[String]) -> color_eyre::Result<()> {
         info!("Analyzing commands: {:?}", commands);
         for command in commands {
@@ -487,8 +1054,13 @@ impl CliApp {
     #[cfg(feature = "flightsql")]
     async fn flightsql_execute_commands(&self, commands: &[String]) -> color_eyre::Result<()> {
         info!("Executing FlightSQL commands: {:?}", commands);
+        if self.args.jobs.is_some() {
+            return self.execute_concurrently(commands.to_vec()).await;
+        }
         for (i, command) in commands.iter().enumerate() {
-            self.exec_from_flightsql(command.to_string(), i).await?
+            self.exec_from_flightsql(command.to_string(), i, &mut std::io::stdout())
+                .await
+                .map_err(|e| self.report_error(e, Some(i), Some(command)))?;
         }
 
         Ok(())
@@ -498,29 +1070,11 @@ impl CliApp {
     async fn flightsql_benchmark_commands(&self, commands: &[String]) -> color_eyre::Result<()> {
         info!("Benchmark FlightSQL commands: {:?}", commands);
 
-        let mut open_opts = std::fs::OpenOptions::new();
-        let mut file = if let Some(p) = &self.args.save {
-            if !p.exists() {
-                if let Some(parent) = p.parent() {
-                    std::fs::DirBuilder::new().recursive(true).create(parent)?;
-                }
-            };
-            if self.args.append && p.exists() {
-                open_opts.append(true).create(true);
-                Some(open_opts.open(p)?)
-            } else {
-                open_opts.write(true).create(true).truncate(true);
-                let mut file = open_opts.open(p)?;
-                writeln!(file, "{}", FLIGHTSQL_BENCHMARK_HEADER_ROW)?;
-                Some(file)
-            }
-        } else {
-            None
-        };
+        let mut file = self.open_benchmark_results(FLIGHTSQL_BENCHMARK_HEADER_ROW)?;
 
         for command in commands {
             let stats = self.flightsql_benchmark_from_string(command).await?;
-            println!("{}", stats);
+            self.print_stats(&stats, stats.to_json())?;
             if let Some(ref mut file) = &mut file {
                 writeln!(file, "{}", stats.to_summary_csv_row())?
             }
@@ -529,35 +1083,263 @@ impl CliApp {
         Ok(())
     }
 
-    async fn exec_from_string(&self, sql: &str) -> Result<()> {
-        let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
-        let statements = DFParser::parse_sql_with_dialect(sql, &dialect)?;
+    async fn exec_from_string(&self, sql: &str, out: &mut dyn Write) -> Result<()> {
+        // DataFusion can't parse `CREATE EXTERNAL OBJECT STORE`, so it's run on its own
+        if parse_create_object_store(sql).is_some() {
+            return self
+                .app_execution
+                .execution_ctx()
+                .execute_sql_and_discard_results(sql)
+                .await
+                .map_err(|e| self.report_error(e.into(), None, Some(sql)));
+        }
+        let parse_start = std::time::Instant::now();
+        let mut statements = self
+            .app_execution
+            .execution_ctx()
+            .parse_sql(sql)
+            .map_err(|e| self.report_error(e.into(), None, Some(sql)))?;
+        let parsing = parse_start.elapsed();
+        let ctas = match &self.args.create_table {
+            Some(name) => {
+                let dialect = self
+                    .app_execution
+                    .execution_ctx()
+                    .dialect()
+                    .map_err(|e| self.report_error(e.into(), None, Some(sql)))?;
+                Some(wrap_in_ctas(&mut statements, name, dialect.as_ref())?)
+            }
+            None => None,
+        };
         let start = if self.args.time {
             Some(std::time::Instant::now())
         } else {
             None
         };
         for (i, statement) in statements.into_iter().enumerate() {
-            let stream = self
-                .app_execution
-                .execution_ctx()
-                .execute_statement(statement)
-                .await?;
-            if let Some(output_path) = &self.args.output {
-                self.output_stream(stream, output_path).await?;
-            } else if self.args.json {
-                self.print_json_stream(stream).await;
-            } else if let Some(start) = start {
-                self.exec_stream(stream).await;
-                let elapsed = start.elapsed();
-                println!("Query {i} executed in {:?}", elapsed);
+            // The SQL is only needed for recording the session or reporting errors as JSON
+            let sql =
+                (self.recorder.is_some() || self.json_errors()).then(|| statement.to_string());
+            // The statements are parsed together before the first one runs, so parsing is part
+            // of its breakdown
+            let parse = if i == 0 {
+                parsing
+            } else {
+                std::time::Duration::ZERO
+            };
+            let statement_start = std::time::Instant::now();
+            let result = self
+                .with_timeout(i, self.exec_statement(statement, i, start, parse, out))
+                .await;
+            if let (Some(recorder), Some(sql)) = (&self.recorder, &sql) {
+                let rows = result.as_ref().ok().copied().flatten();
+                recorder.record(sql.clone(), statement_start.elapsed(), rows, &result)?;
+            }
+            result.map_err(|e| self.report_error(e, Some(i), sql.as_deref()))?;
+        }
+        if let (Some(name), Some(ctas)) = (&self.args.create_table, ctas) {
+            writeln!(out, "Created table {name}")?;
+            if self.args.persist_ddl {
+                self.app_execution
+                    .execution_ctx()
+                    .save_table_ddl(&ctas)
+                    .map_err(|e| eyre!("Error saving DDL for table {name}: {e}"))?;
+                writeln!(out, "Saved DDL for table {name}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute a single statement and print or save its results, returning the number of rows
+    /// returned if they were counted. `parse` is how long parsing the statement took, which is
+    /// only used for `--time-breakdown`.
+    async fn exec_statement(
+        &self,
+        statement: Statement,
+        i: usize,
+        start: Option<std::time::Instant>,
+        parse: std::time::Duration,
+        out: &mut dyn Write,
+    ) -> Result<Option<usize>> {
+        if let Some(dir) = &self.args.save_plans {
+            self.save_plans(&statement, dir).await;
+        }
+        if self.args.time_breakdown {
+            self.exec_with_breakdown(statement, i, parse, out).await?;
+            return Ok(None);
+        }
+        if let (Some(output_path), false) = (&self.args.output, self.args.partition_by.is_empty()) {
+            self.write_partitioned(statement, output_path).await?;
+            return Ok(None);
+        }
+        let stream = self
+            .app_execution
+            .execution_ctx()
+            .execute_statement(statement)
+            .await?;
+        let rows = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = std::sync::Arc::clone(&rows);
+        let stream = stream.inspect(move |batch| {
+            if let Ok(batch) = batch {
+                counter.fetch_add(batch.num_rows(), std::sync::atomic::Ordering::Relaxed);
+            }
+        });
+        if let Some(output_path) = &self.args.output {
+            self.output_stream(stream, output_path).await?;
+        } else if self.args.json {
+            self.print_json_stream(stream, out).await?;
+        } else if let Some(start) = start {
+            self.exec_stream(stream, out).await?;
+            let elapsed = start.elapsed();
+            if self.json_metrics() {
+                let metrics = serde_json::json!({
+                    "query": i,
+                    "rows": rows.load(std::sync::atomic::Ordering::Relaxed),
+                    "elapsed_ms": duration_ms(elapsed),
+                });
+                self.write_metrics(metrics, out)?;
             } else {
-                self.print_any_stream(stream).await;
+                writeln!(out, "Query {i} executed in {:?}", elapsed)?;
             }
+        } else {
+            self.print_any_stream(stream, out).await?;
+        }
+        Ok(Some(rows.load(std::sync::atomic::Ordering::Relaxed)))
+    }
+
+    /// Save the plans of a statement for `--save-plans`. Plans that can't be encoded, such as
+    /// those that scan in-memory tables, are reported without failing the statement.
+    async fn save_plans(&self, statement: &Statement, dir: &Path) {
+        let n = self
+            .saved_plans
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let format = match self.args.plan_format {
+            PlanFileFormat::Proto => PlanFormat::Proto,
+            PlanFileFormat::Json => PlanFormat::Json,
+        };
+        let saved = self
+            .app_execution
+            .execution_ctx()
+            .save_statement_plans(statement.clone(), dir, &format!("query_{n}"), format)
+            .await;
+        match saved {
+            Ok(paths) => info!("Saved plans of query {n} to {:?}", paths),
+            Err(e) => eprintln!("Unable to save the plans of query {n}: {e}"),
+        }
+    }
+
+    /// Execute a plan saved with `--save-plans` and print or save its results
+    async fn run_saved_plan(&self, path: &Path) -> Result<()> {
+        info!("Executing saved plan: {:?}", path);
+        let stream = self
+            .app_execution
+            .execution_ctx()
+            .execute_saved_plan(path)
+            .await?;
+        if let Some(output_path) = &self.args.output {
+            self.output_stream(stream, output_path).await
+        } else {
+            self.print_stream(stream, &mut std::io::stdout()).await
+        }
+    }
+
+    /// Re-run the statements of a recorded session in the order they were recorded
+    async fn replay(&self, session: &Path) -> Result<()> {
+        let statements = record::read_session(session)?;
+        info!(
+            "Replaying {} statements from {:?}",
+            statements.len(),
+            session
+        );
+        for statement in statements {
+            println!("{}", statement.sql);
+            self.exec_from_string(&statement.sql, &mut std::io::stdout())
+                .await?;
         }
         Ok(())
     }
 
+    /// Execute a statement, timing each stage the same way benchmarks do, and print the
+    /// breakdown, starting with how long `parse` took to parse it. Results are only written if an
+    /// output path was provided.
+    async fn exec_with_breakdown(
+        &self,
+        statement: Statement,
+        i: usize,
+        parse: std::time::Duration,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let ctx = self.app_execution.session_ctx().clone();
+        let start = std::time::Instant::now();
+        let task = async move {
+            let state = ctx.state();
+            let logical_plan = state.statement_to_plan(statement).await?;
+            let logical_planning = start.elapsed();
+
+            // DDL and other statements are handled by the `SessionContext` rather than a
+            // physical plan so they only have an execution stage
+            let physical_start = std::time::Instant::now();
+            let (physical_planning, batches) = if matches!(
+                logical_plan,
+                LogicalPlan::Ddl(_) | LogicalPlan::Statement(_)
+            ) {
+                let df = ctx.execute_logical_plan(logical_plan).await?;
+                (std::time::Duration::ZERO, df.collect().await?)
+            } else {
+                let physical_plan = state.create_physical_plan(&logical_plan).await?;
+                let physical_planning = physical_start.elapsed();
+                let batches =
+                    datafusion::physical_plan::collect(physical_plan, ctx.task_ctx()).await?;
+                (physical_planning, batches)
+            };
+            Ok::<_, datafusion::error::DataFusionError>((
+                logical_planning,
+                physical_planning,
+                batches,
+            ))
+        };
+        let (logical_planning, physical_planning, batches) = self
+            .app_execution
+            .execution_ctx()
+            .spawn_cpu(task)
+            .await
+            .map_err(|e| eyre!(e))??;
+        let execution = start.elapsed() - logical_planning - physical_planning;
+
+        let output_start = std::time::Instant::now();
+        if let Some(output_path) = &self.args.output {
+            let stream = futures::stream::iter(
+                batches
+                    .into_iter()
+                    .map(Ok::<_, datafusion::error::DataFusionError>),
+            );
+            self.output_stream(stream, output_path).await?;
+        }
+        let output = output_start.elapsed();
+        let total = parse + start.elapsed();
+
+        if self.json_metrics() {
+            let metrics = serde_json::json!({
+                "query": i,
+                "parse_ms": duration_ms(parse),
+                "logical_planning_ms": duration_ms(logical_planning),
+                "physical_planning_ms": duration_ms(physical_planning),
+                "execution_ms": duration_ms(execution),
+                "output_ms": duration_ms(output),
+                "total_ms": duration_ms(total),
+            });
+            return self.write_metrics(metrics, out);
+        }
+        writeln!(out, "Query {i} timing:")?;
+        writeln!(out, "  Parsing:           {:?}", parse)?;
+        writeln!(out, "  Logical planning:  {:?}", logical_planning)?;
+        writeln!(out, "  Physical planning: {:?}", physical_planning)?;
+        writeln!(out, "  Execution:         {:?}", execution)?;
+        writeln!(out, "  Output:            {:?}", output)?;
+        writeln!(out, "  Total:             {:?}", total)?;
+        Ok(())
+    }
+
     async fn benchmark_from_string(&self, sql: &str) -> Result<LocalBenchmarkStats> {
         use std::sync::Arc;
 
@@ -607,7 +1389,323 @@ impl CliApp {
             .analyze_query(sql)
             .await?;
         stats.collect_stats();
-        println!("{}", stats);
+        self.print_stats(&stats, stats.to_json())
+    }
+
+    /// Parse and plan every statement in the provided files or commands without executing them,
+    /// reporting the outcome of each statement
+    async fn dry_run(&self) -> Result<()> {
+        if self.args.flightsql {
+            return Err(eyre!(
+                "The `dry-run` flag is not currently supported with FlightSQL"
+            ));
+        }
+        let sources: Vec<(String, String)> =
+            match (self.args.files.is_empty(), self.args.commands.is_empty()) {
+                (true, true) => return Err(eyre!("No files or commands provided to execute")),
+                (false, false) => {
+                    return Err(eyre!(
+                        "Cannot execute both files and commands at the same time"
+                    ))
+                }
+                (false, true) => self
+                    .args
+                    .files
+                    .iter()
+                    .map(|f| Ok((f.display().to_string(), self.read_sql_file(f)?)))
+                    .collect::<Result<Vec<_>>>()?,
+                (true, false) => self
+                    .args
+                    .commands
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| (format!("Command {i}"), c.clone()))
+                    .collect(),
+            };
+
+        info!("Validating: {:?}", sources);
+        let execution = self.app_execution.execution_ctx();
+        let mut failures = 0;
+        for (source, sql) in sources {
+            let statements = match execution.parse_sql(&sql) {
+                Ok(statements) => statements,
+                Err(e) => {
+                    println!("{source}: Error parsing SQL: {e}");
+                    failures += 1;
+                    continue;
+                }
+            };
+            for (i, statement) in statements.into_iter().enumerate() {
+                match self.plan_statement(statement).await {
+                    Ok(_) => println!("{source}, statement {i}: OK"),
+                    Err(e) => {
+                        println!("{source}, statement {i}: Error: {e}");
+                        failures += 1;
+                    }
+                }
+            }
+        }
+
+        if failures > 0 {
+            Err(eyre!("{failures} statement(s) failed validation"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Create the logical and physical plans for a statement without executing it.  DDL and
+    /// other statements that only have an effect when executed are only logically planned.
+    async fn plan_statement(&self, statement: Statement) -> Result<()> {
+        let execution = self.app_execution.execution_ctx();
+        let logical_plan = execution.statement_to_logical_plan(statement).await?;
+        if !matches!(
+            logical_plan,
+            LogicalPlan::Ddl(_) | LogicalPlan::Statement(_)
+        ) {
+            execution
+                .logical_plan_to_physical_plan(logical_plan)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Write the results of a statement to a hive partitioned directory using the `DataFrame`
+    /// writers. DDL and other statements that don't produce results are only executed.
+    async fn write_partitioned(&self, statement: Statement, path: &Path) -> Result<()> {
+        let ctx = self.app_execution.session_ctx().clone();
+        let path = path
+            .to_str()
+            .ok_or(eyre!("Output path must be valid UTF-8"))?
+            .to_string();
+        let partition_by = self.args.partition_by.clone();
+        let row_group_size = self.args.row_group_size.map(|size| size as usize);
+        let write_concurrency = usize::from(self.args.write_concurrency);
+        let task = async move {
+            let logical_plan = ctx.state().statement_to_plan(statement).await?;
+            let df = ctx.execute_logical_plan(logical_plan).await?;
+            if matches!(
+                df.logical_plan(),
+                LogicalPlan::Ddl(_) | LogicalPlan::Statement(_) | LogicalPlan::EmptyRelation(_)
+            ) {
+                df.collect().await?;
+                return Ok(());
+            }
+
+            let write_opts = DataFrameWriteOptions::new().with_partition_by(partition_by);
+            match FileFormat::from_path(&path) {
+                Some(FileFormat::Parquet) | None => {
+                    let mut options = ctx.state().default_table_options().parquet;
+                    if let Some(row_group_size) = row_group_size {
+                        options.global.max_row_group_size = row_group_size;
+                    }
+                    options.global.maximum_parallel_row_group_writers = write_concurrency;
+                    df.write_parquet(&path, write_opts, Some(options)).await?;
+                }
+                Some(FileFormat::Csv) => {
+                    df.write_csv(&path, write_opts, None).await?;
+                }
+                Some(FileFormat::Json) => {
+                    df.write_json(&path, write_opts, None).await?;
+                }
+                Some(FileFormat::Arrow) => {
+                    return Err(eyre!(
+                        "Only 'csv', 'parquet', and 'json' partitioned output is supported"
+                    ))
+                }
+            }
+            Ok(())
+        };
+        self.app_execution
+            .execution_ctx()
+            .spawn_cpu(task)
+            .await
+            .map_err(|e| eyre!(e))?
+    }
+
+    /// Print the schema of the provided file or registered table
+    async fn print_schema(&self, target: &str, as_json: bool) -> Result<()> {
+        let ctx = self.app_execution.session_ctx();
+        let df = if Path::new(target).exists() || target.contains("://") {
+            let extension = Path::new(target)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_ascii_lowercase());
+            match extension.as_deref() {
+                Some("csv") => ctx.read_csv(target, CsvReadOptions::new()).await?,
+                Some("parquet") => ctx.read_parquet(target, ParquetReadOptions::default()).await?,
+                Some(ext @ ("json" | "ndjson")) => {
+                    ctx.read_json(target, NdJsonReadOptions::default().file_extension(ext))
+                        .await?
+                }
+                _ => {
+                    return Err(eyre!(
+                        "Unable to infer the format of '{target}'. Only 'csv', 'parquet', 'json', and 'ndjson' files are supported"
+                    ))
+                }
+            }
+        } else {
+            ctx.table(target).await?
+        };
+
+        let batch = display::schema_to_batch(df.schema().as_arrow())?;
+        if as_json {
+            let mut writer = json::writer::LineDelimitedWriter::new(std::io::stdout());
+            writer.write(&batch)?;
+            writer.finish()?;
+        } else {
+            println!("{}", pretty_format_batches(&[batch])?);
+        }
+        Ok(())
+    }
+
+    /// Query the local catalogs the same way the FlightSQL server answers the equivalent
+    /// metadata requests
+    async fn exec_catalog_command(&self, command: &CatalogCommand) -> Result<()> {
+        let ctx = self.app_execution.session_ctx();
+        let df = match command {
+            CatalogCommand::ListSchemas {
+                catalog,
+                db_schema_filter_pattern,
+            } => {
+                // Schemas are listed from `schemata` rather than `tables` so that those without
+                // any tables are included
+                let mut df = ctx
+                    .sql("SELECT catalog_name, schema_name FROM information_schema.schemata")
+                    .await?;
+                if let Some(catalog) = catalog {
+                    df = df.filter(col("catalog_name").eq(lit(catalog)))?;
+                }
+                if let Some(schema_filter) = db_schema_filter_pattern {
+                    df = df.filter(col("schema_name").ilike(lit(format!("%{schema_filter}%"))))?;
+                }
+                df.sort(vec![
+                    col("catalog_name").sort(true, false),
+                    col("schema_name").sort(true, false),
+                ])?
+            }
+            CatalogCommand::ListTables {
+                catalog,
+                db_schema_filter_pattern,
+                table_name_filter_pattern,
+                table_types,
+            } => {
+                let mut df = ctx.sql("SELECT * FROM information_schema.tables").await?;
+                if let Some(catalog) = catalog {
+                    df = df.filter(col("table_catalog").eq(lit(catalog)))?;
+                }
+                if let Some(schema_filter) = db_schema_filter_pattern {
+                    df = df.filter(col("table_schema").ilike(lit(format!("%{schema_filter}%"))))?;
+                }
+                if let Some(table_filter) = table_name_filter_pattern {
+                    df = df.filter(col("table_name").ilike(lit(format!("%{table_filter}%"))))?;
+                }
+                if let Some(table_types) = table_types {
+                    let table_exprs = table_types.iter().map(lit).collect();
+                    df = df.filter(col("table_type").in_list(table_exprs, false))?;
+                }
+                df.sort(vec![
+                    col("table_catalog").sort(true, false),
+                    col("table_schema").sort(true, false),
+                    col("table_name").sort(true, false),
+                ])?
+            }
+            CatalogCommand::Describe { table } => {
+                let df = ctx.table(table.as_str()).await?;
+                let batch = display::schema_to_batch(df.schema().as_arrow())?;
+                ctx.read_batch(batch)?
+            }
+        };
+        let stream = df.execute_stream().await?;
+        self.print_stream(stream, &mut std::io::stdout()).await
+    }
+
+    /// Run the selected TPC-H queries against the generated TPC-H tables, benchmarking them if
+    /// `--bench` was provided
+    async fn run_tpch(&self, queries: &[usize]) -> Result<()> {
+        let ctx = self.app_execution.session_ctx();
+        // The generated tables are registered as `dft.tpch.<table>` so they are registered
+        // again under their own names for the queries to use
+        for table in tpch::TABLES {
+            let provider = ctx
+                .table_provider(TableReference::full("dft", "tpch", table))
+                .await
+                .map_err(|_| {
+                    eyre!(
+                        "TPC-H table '{table}' not found. Generate it with `dft tpch run --scale-factor <N>` or `dft generate-tpch`"
+                    )
+                })?;
+            ctx.register_table(table, provider)?;
+        }
+
+        let numbers = if queries.is_empty() {
+            (1..=22).collect()
+        } else {
+            queries.to_vec()
+        };
+        let queries = numbers
+            .into_iter()
+            .map(|number| Ok((number, tpch::query(number)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.args.bench {
+            // Queries are flattened to a single line so that they can be saved with `--save`
+            let commands: Vec<String> = queries
+                .iter()
+                .map(|(_, sql)| sql.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect();
+            return self.benchmark_commands(&commands).await;
+        }
+
+        let mut total = std::time::Duration::ZERO;
+        for (number, sql) in &queries {
+            let start = std::time::Instant::now();
+            let batches = ctx.sql(sql).await?.collect().await?;
+            let elapsed = start.elapsed();
+            total += elapsed;
+            let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+            println!("Query {number:>2}: {rows} rows in {elapsed:?}");
+        }
+        println!("Ran {} TPC-H queries in {total:?}", queries.len());
+        Ok(())
+    }
+
+    /// Execute each query and print the metrics of every operator in its execution plan
+    async fn analyze_operators(&self, files: &[PathBuf], commands: &[String]) -> Result<()> {
+        let queries = match (files.is_empty(), commands.is_empty()) {
+            (true, true) => return Err(eyre!("No files or commands provided to analyze")),
+            (false, false) => {
+                return Err(eyre!(
+                    "Cannot analyze both files and commands at the same time"
+                ))
+            }
+            (false, true) => files
+                .iter()
+                .map(|file| self.read_sql_file(file))
+                .collect::<Result<Vec<String>>>()?,
+            (true, false) => commands.to_vec(),
+        };
+        info!("Analyzing operators for queries: {:?}", queries);
+        for query in queries {
+            let stats = self
+                .app_execution
+                .execution_ctx()
+                .analyze_query(&query)
+                .await?;
+            if self.json_metrics() {
+                let metrics = serde_json::json!({
+                    "query": stats.query(),
+                    "operators": stats.operator_stats().map(|o| o.to_json()),
+                });
+                self.write_metrics(metrics, &mut std::io::stdout())?;
+                continue;
+            }
+            println!("========================= Query ===========================");
+            println!("{}", stats.query());
+            match stats.operator_stats() {
+                Some(operator_stats) => println!("{}", operator_stats),
+                None => println!("No operator metrics found"),
+            }
+        }
         Ok(())
     }
 
@@ -644,18 +1742,32 @@ impl CliApp {
                 sql,
                 self.args.benchmark_iterations,
                 self.args.concurrent,
+                self.args.prepared,
                 progress_reporter,
             )
             .await?;
         Ok(stats)
     }
 
+    /// Read SQL from a file, substituting environment variables if `--env-substitution` was
+    /// provided
+    fn read_sql_file(&self, file: &Path) -> Result<String> {
+        let sql = std::fs::read_to_string(file)?;
+        if self.args.env_substitution {
+            substitute_env_vars(&sql)
+                .map_err(|e| eyre!("Error substituting environment variables in {file:?}: {e}"))
+        } else {
+            Ok(sql)
+        }
+    }
+
     /// run and execute SQL statements and commands from a file, against a context
     /// with the given print options
     pub async fn exec_from_file(&self, file: &Path) -> color_eyre::Result<()> {
-        let string = std::fs::read_to_string(file)?;
+        let string = self.read_sql_file(file)?;
 
-        self.exec_from_string(&string).await?;
+        self.exec_from_string(&string, &mut std::io::stdout())
+            .await?;
 
         Ok(())
     }
@@ -663,11 +1775,12 @@ impl CliApp {
     /// executes a sql statement and prints the result to stdout
     pub async fn execute_and_print_sql(&self, sql: &str) -> color_eyre::Result<()> {
         let stream = self.app_execution.execution_ctx().execute_sql(sql).await?;
-        self.print_any_stream(stream).await;
+        self.print_any_stream(stream, &mut std::io::stdout())
+            .await?;
         Ok(())
     }
 
-    async fn exec_stream<S, E>(&self, mut stream: S)
+    async fn exec_stream<S, E>(&self, mut stream: S, out: &mut dyn Write) -> Result<()>
     where
         S: Stream<Item = Result<RecordBatch, E>> + Unpin,
         E: Error,
@@ -676,27 +1789,31 @@ impl CliApp {
             match maybe_batch {
                 Ok(_) => {}
                 Err(e) => {
-                    println!("Error executing SQL: {e}");
+                    self.write_output_error(format!("Error executing SQL: {e}"), out)?;
                     break;
                 }
             }
         }
+        Ok(())
     }
 
-    #[cfg(feature = "flightsql")]
-    async fn print_stream<S, E>(&self, stream: S)
+    async fn print_stream<S, E>(&self, stream: S, out: &mut dyn Write) -> Result<()>
     where
         S: Stream<Item = Result<RecordBatch, E>> + Unpin,
         E: Error,
     {
         if self.args.json {
-            self.print_json_stream(stream).await;
+            self.print_json_stream(stream, out).await
         } else {
-            self.print_any_stream(stream).await;
+            self.print_any_stream(stream, out).await
         }
     }
 
-    async fn collect_stream<S, E>(&self, mut stream: S) -> Option<Vec<RecordBatch>>
+    async fn collect_stream<S, E>(
+        &self,
+        mut stream: S,
+        out: &mut dyn Write,
+    ) -> Result<Option<Vec<RecordBatch>>>
     where
         S: Stream<Item = Result<RecordBatch, E>> + Unpin,
         E: Error,
@@ -706,116 +1823,243 @@ impl CliApp {
             match maybe_batch {
                 Ok(batch) => batches.push(batch),
                 Err(e) => {
-                    println!("Error executing SQL: {e}");
-                    return None;
+                    self.write_output_error(format!("Error executing SQL: {e}"), out)?;
+                    return Ok(None);
                 }
             }
         }
-        Some(batches)
+        Ok(Some(batches))
     }
 
-    async fn print_any_stream<S, E>(&self, stream: S)
+    async fn print_any_stream<S, E>(&self, stream: S, out: &mut dyn Write) -> Result<()>
     where
         S: Stream<Item = Result<RecordBatch, E>> + Unpin,
         E: Error,
     {
+        match self.args.format {
+            Some(OutputFormat::Csv) => return self.print_csv_stream(stream, out).await,
+            Some(OutputFormat::Ndjson) => return self.print_json_stream(stream, out).await,
+            Some(OutputFormat::Table) | None => {}
+        }
+        let mut limiter = RowLimiter::new(self.args.max_rows);
         if self.args.concat {
-            let Some(batches) = self.collect_stream(stream).await else {
-                return;
+            let Some(batches) = self.collect_stream(stream, out).await? else {
+                return Ok(());
             };
             if !batches.is_empty() {
                 let schema = batches[0].schema();
                 match datafusion::arrow::compute::concat_batches(&schema, &batches) {
-                    Ok(batch) => match pretty_format_batches(&[batch]) {
-                        Ok(d) => println!("{}", d),
-                        Err(e) => println!("Error formatting batch: {e}"),
-                    },
-                    Err(e) => println!("Error concatenating batches: {e}"),
+                    Ok(batch) => {
+                        if let Some(batch) = limiter.limit(batch) {
+                            self.print_batch(batch, out)?;
+                        }
+                    }
+                    Err(e) => {
+                        self.write_output_error(format!("Error concatenating batches: {e}"), out)?
+                    }
                 }
             }
         } else {
             let mut stream = stream;
             while let Some(maybe_batch) = stream.next().await {
                 match maybe_batch {
-                    Ok(batch) => match pretty_format_batches(&[batch]) {
-                        Ok(d) => println!("{}", d),
-                        Err(e) => println!("Error formatting batch: {e}"),
-                    },
-                    Err(e) => println!("Error executing SQL: {e}"),
+                    Ok(batch) => {
+                        if let Some(batch) = limiter.limit(batch) {
+                            self.print_batch(batch, out)?;
+                        }
+                    }
+                    Err(e) => self.write_output_error(format!("Error executing SQL: {e}"), out)?,
                 }
             }
         }
+        if let Some(message) = limiter.truncation_message() {
+            writeln!(out, "{message}")?;
+        }
+        Ok(())
     }
 
-    async fn print_json_stream<S, E>(&self, stream: S)
+    /// Writes rows as CSV as soon as each batch arrives, with the header only written for the
+    /// first batch, so results are never accumulated in memory
+    async fn print_csv_stream<S, E>(&self, mut stream: S, out: &mut dyn Write) -> Result<()>
     where
         S: Stream<Item = Result<RecordBatch, E>> + Unpin,
         E: Error,
     {
+        let mut limiter = RowLimiter::new(self.args.max_rows);
+        let mut writer = csv::writer::Writer::new(&mut *out);
+        let mut error = None;
+        while let Some(maybe_batch) = stream.next().await {
+            match maybe_batch {
+                Ok(batch) => {
+                    let Some(batch) = limiter.limit(batch) else {
+                        continue;
+                    };
+                    if let Err(e) = writer.write(&batch) {
+                        error = Some(format!("Error formatting batch as CSV: {e}"));
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error = Some(format!("Error executing SQL: {e}"));
+                    break;
+                }
+            }
+        }
+        drop(writer);
+        if let Some(error) = error {
+            self.write_output_error(error, out)?;
+        }
+        // Like JSON, the truncation message is written to stderr to keep stdout parseable
+        if let Some(message) = limiter.truncation_message() {
+            eprintln!("{message}");
+        }
+        Ok(())
+    }
+
+    /// Pretty prints a single batch, truncating wide values if `--max-col-width` was provided
+    fn print_batch(&self, batch: RecordBatch, out: &mut dyn Write) -> Result<()> {
+        let batch = match self.args.max_col_width {
+            Some(max_width) => match display::truncate_columns(&batch, max_width) {
+                Ok(batch) => batch,
+                Err(e) => {
+                    self.write_output_error(format!("Error truncating columns: {e}"), out)?;
+                    return Ok(());
+                }
+            },
+            None => batch,
+        };
+        match pretty_format_batches(&[batch]) {
+            Ok(d) => writeln!(out, "{}", d)?,
+            Err(e) => self.write_output_error(format!("Error formatting batch: {e}"), out)?,
+        }
+        Ok(())
+    }
+
+    async fn print_json_stream<S, E>(&self, stream: S, out: &mut dyn Write) -> Result<()>
+    where
+        S: Stream<Item = Result<RecordBatch, E>> + Unpin,
+        E: Error,
+    {
+        let mut limiter = RowLimiter::new(self.args.max_rows);
         if self.args.concat {
-            let Some(batches) = self.collect_stream(stream).await else {
-                return;
+            let Some(batches) = self.collect_stream(stream, out).await? else {
+                return Ok(());
             };
             if !batches.is_empty() {
                 let schema = batches[0].schema();
                 match datafusion::arrow::compute::concat_batches(&schema, &batches) {
                     Ok(batch) => {
-                        let mut writer = json::writer::LineDelimitedWriter::new(std::io::stdout());
-                        if let Err(e) = writer.write(&batch) {
-                            println!("Error formatting batch as JSON: {e}");
-                            return;
+                        let mut writer = json::writer::LineDelimitedWriter::new(&mut *out);
+                        if let Some(batch) = limiter.limit(batch) {
+                            if let Err(e) = writer.write(&batch) {
+                                drop(writer);
+                                self.write_output_error(
+                                    format!("Error formatting batch as JSON: {e}"),
+                                    out,
+                                )?;
+                                return Ok(());
+                            }
                         }
                         if let Err(e) = writer.finish() {
-                            println!("Error finishing JSON output: {e}");
+                            drop(writer);
+                            self.write_output_error(
+                                format!("Error finishing JSON output: {e}"),
+                                out,
+                            )?;
                         }
                     }
-                    Err(e) => println!("Error concatenating batches: {e}"),
+                    Err(e) => {
+                        self.write_output_error(format!("Error concatenating batches: {e}"), out)?
+                    }
                 }
             }
         } else {
             let mut stream = stream;
-            let mut writer = json::writer::LineDelimitedWriter::new(std::io::stdout());
+            let mut writer = json::writer::LineDelimitedWriter::new(&mut *out);
+            let mut error = None;
             while let Some(maybe_batch) = stream.next().await {
                 match maybe_batch {
                     Ok(batch) => {
+                        let Some(batch) = limiter.limit(batch) else {
+                            continue;
+                        };
                         if let Err(e) = writer.write(&batch) {
-                            println!("Error formatting batch as JSON: {e}");
-                            return;
+                            error = Some(format!("Error formatting batch as JSON: {e}"));
+                            break;
                         }
                     }
                     Err(e) => {
-                        println!("Error executing SQL: {e}");
-                        return;
+                        error = Some(format!("Error executing SQL: {e}"));
+                        break;
                     }
                 }
             }
-            if let Err(e) = writer.finish() {
-                println!("Error finishing JSON output: {e}");
+            if error.is_none() {
+                if let Err(e) = writer.finish() {
+                    error = Some(format!("Error finishing JSON output: {e}"));
+                }
+            }
+            drop(writer);
+            if let Some(error) = error {
+                self.write_output_error(error, out)?;
+                return Ok(());
             }
         }
+        if let Some(message) = limiter.truncation_message() {
+            eprintln!("{message}");
+        }
+        Ok(())
     }
 
+    /// Write the batches of `stream` to `path` as they're produced. Batches are encoded and
+    /// written on a blocking thread, with up to `--write-concurrency` of them queued, so that the
+    /// query keeps executing while earlier results are written.
     async fn output_stream<S, E>(&self, mut stream: S, path: &Path) -> Result<()>
     where
         S: Stream<Item = Result<RecordBatch, E>> + Unpin,
         E: Error,
     {
         // We get the schema from the first batch and use that for creating the writer
-        if let Some(Ok(first_batch)) = stream.next().await {
-            let schema = first_batch.schema();
-            let mut writer = path_to_writer(path, schema)?;
-            writer.write(&first_batch)?;
-
-            while let Some(maybe_batch) = stream.next().await {
-                match maybe_batch {
-                    Ok(batch) => writer.write(&batch)?,
-                    Err(e) => return Err(eyre!("Error executing SQL: {e}")),
+        let first_batch = match stream.next().await {
+            Some(Ok(batch)) => batch,
+            Some(Err(e)) => return Err(eyre!("Error executing SQL: {e}")),
+            None => return Ok(()),
+        };
+        let schema = first_batch.schema();
+        let mut writer = path_to_writer(
+            path,
+            schema,
+            self.args.compression,
+            self.args.row_group_size.map(|size| size as usize),
+        )?;
+        let (tx, mut rx) =
+            tokio::sync::mpsc::channel::<RecordBatch>(usize::from(self.args.write_concurrency));
+        let write_task = tokio::task::spawn_blocking(move || -> Result<AnyWriter> {
+            while let Some(batch) = rx.blocking_recv() {
+                writer.write(&batch)?;
+            }
+            Ok(writer)
+        });
+
+        let mut batch = Some(first_batch);
+        while let Some(next) = batch.take() {
+            // The writer only stops receiving when it failed, which is reported below
+            if tx.send(next).await.is_err() {
+                break;
+            }
+            match stream.next().await {
+                Some(Ok(next)) => batch = Some(next),
+                Some(Err(e)) => {
+                    drop(tx);
+                    let _ = write_task.await;
+                    return Err(eyre!("Error executing SQL: {e}"));
                 }
+                None => {}
             }
-            writer.close().await?;
         }
-
-        Ok(())
+        drop(tx);
+        let writer = write_task.await??;
+        writer.close().await
     }
 }
 
@@ -872,13 +2116,51 @@ impl VortexFileWriter {
     }
 }
 
+/// A file that results are written to, which is optionally compressed. The encoders are kept
+/// rather than boxed so that their streams can be finished explicitly when the writer is closed.
+enum OutputFile {
+    Plain(std::io::BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl OutputFile {
+    /// Write the trailer of a compressed stream and flush the file, returning any error rather
+    /// than discarding it as dropping the encoders does
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(mut w) => w.flush(),
+            OutputFile::Gzip(w) => w.finish()?.flush(),
+            OutputFile::Zstd(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Plain(w) => w.write(buf),
+            OutputFile::Gzip(w) => w.write(buf),
+            OutputFile::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(w) => w.flush(),
+            OutputFile::Gzip(w) => w.flush(),
+            OutputFile::Zstd(w) => w.flush(),
+        }
+    }
+}
+
 /// We use an Enum for this because of limitations with using trait objects and the `close` method
 /// on a writer taking `self` as an argument which requires a size for the trait object which is
 /// not known at compile time.
 #[allow(clippy::large_enum_variant)]
 enum AnyWriter {
-    Csv(csv::writer::Writer<File>),
-    Json(json::writer::LineDelimitedWriter<File>),
+    Csv(csv::writer::Writer<OutputFile>),
+    Json(json::writer::LineDelimitedWriter<OutputFile>),
     Parquet(ArrowWriter<File>),
     #[cfg(feature = "vortex")]
     Vortex(VortexFileWriter),
@@ -897,8 +2179,13 @@ impl AnyWriter {
 
     async fn close(self) -> Result<()> {
         match self {
-            AnyWriter::Csv(w) => Ok(w.close()?),
-            AnyWriter::Json(w) => Ok(w.close()?),
+            // Finished explicitly, rather than when the file is dropped, so that errors writing
+            // the last chunk or the compression trailer are reported
+            AnyWriter::Csv(w) => Ok(w.into_inner().finish()?),
+            AnyWriter::Json(mut w) => {
+                w.finish()?;
+                Ok(w.into_inner().finish()?)
+            }
             AnyWriter::Parquet(w) => {
                 w.close()?;
                 Ok(())
@@ -909,17 +2196,120 @@ impl AnyWriter {
     }
 }
 
-fn path_to_writer(path: &Path, schema: SchemaRef) -> Result<AnyWriter> {
-    if let Some(extension) = path.extension() {
+/// Replace `${VAR}` references with the value of the environment variable `VAR`
+fn substitute_env_vars(sql: &str) -> std::result::Result<String, String> {
+    let mut result = String::with_capacity(sql.len());
+    let mut rest = sql;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("Unterminated variable reference '{}'", &rest[start..]))?;
+        let name = &after[..end];
+        let value =
+            std::env::var(name).map_err(|_| format!("Environment variable '{name}' is not set"))?;
+        result.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Replace the last statement, which must be a query, with a `CREATE TABLE <name> AS` statement
+/// for it, parsed with `dialect`, and return the SQL of the new statement
+fn wrap_in_ctas(
+    statements: &mut VecDeque<Statement>,
+    name: &str,
+    dialect: &dyn Dialect,
+) -> Result<String> {
+    let Some(Statement::Statement(statement)) = statements.back() else {
+        return Err(eyre!(
+            "The last statement must be a query to create a table"
+        ));
+    };
+    let SQLStatement::Query(query) = statement.as_ref() else {
+        return Err(eyre!(
+            "The last statement must be a query to create a table"
+        ));
+    };
+    let ctas = format!("CREATE TABLE {name} AS {query}");
+    let mut parsed = DFParser::parse_sql_with_dialect(&ctas, dialect)?;
+    match (parsed.pop_front(), parsed.is_empty()) {
+        (Some(ctas_statement), true) => {
+            statements.pop_back();
+            statements.push_back(ctas_statement);
+            Ok(ctas)
+        }
+        _ => Err(eyre!("Invalid table name: {name}")),
+    }
+}
+
+/// Whether a FlightSQL error is likely to succeed if retried, which is the case when the server
+/// was unavailable, including connections that failed or were reset
+#[cfg(feature = "flightsql")]
+fn is_transient_flightsql_error(e: &ArrowError) -> bool {
+    flightsql_status_code(e) == Some(tonic::Code::Unavailable)
+}
+
+/// The code of the `tonic` status behind a FlightSQL error. The client converts statuses to
+/// `ArrowError::IpcError`s holding the status' debug representation, so the code is read from
+/// there, while errors from result streams keep the status itself.
+#[cfg(feature = "flightsql")]
+fn flightsql_status_code(e: &ArrowError) -> Option<tonic::Code> {
+    match e {
+        ArrowError::IpcError(message) => {
+            let code = message.strip_prefix("Status { code: ")?.split(',').next()?;
+            (0..=16)
+                .map(tonic::Code::from_i32)
+                .find(|c| format!("{c:?}") == code)
+        }
+        ArrowError::ExternalError(source) => {
+            if let Some(status) = source.downcast_ref::<tonic::Status>() {
+                return Some(status.code());
+            }
+            match source.downcast_ref::<arrow_flight::error::FlightError>() {
+                Some(arrow_flight::error::FlightError::Tonic(status)) => Some(status.code()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn path_to_writer(
+    path: &Path,
+    schema: SchemaRef,
+    compression: Option<OutputCompression>,
+    row_group_size: Option<usize>,
+) -> Result<AnyWriter> {
+    // A compression suffix, e.g. `results.csv.gz`, is ignored when inferring the file type
+    let inferred_compression = OutputCompression::from_path(path);
+    let format_path = match inferred_compression {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    };
+    let compression = compression.or(inferred_compression);
+    if let Some(extension) = format_path.extension() {
         if let Some(e) = extension.to_ascii_lowercase().to_str() {
+            if compression.is_some() && !matches!(e, "csv" | "json") {
+                return Err(eyre!("Only 'csv' and 'json' output can be compressed"));
+            }
             let file = std::fs::File::create(path)?;
             return match e {
-                "csv" => Ok(AnyWriter::Csv(csv::writer::Writer::new(file))),
-                "json" => Ok(AnyWriter::Json(json::writer::LineDelimitedWriter::new(
+                "csv" => Ok(AnyWriter::Csv(csv::writer::Writer::new(compressed_file(
                     file,
+                    compression,
+                )?))),
+                "json" => Ok(AnyWriter::Json(json::writer::LineDelimitedWriter::new(
+                    compressed_file(file, compression)?,
                 ))),
                 "parquet" => {
-                    let props = WriterProperties::default();
+                    let mut props = WriterProperties::builder();
+                    if let Some(row_group_size) = row_group_size {
+                        props = props.set_max_row_group_size(row_group_size);
+                    }
+                    let props = props.build();
                     let writer = ArrowWriter::try_new(file, schema, Some(props))?;
                     Ok(AnyWriter::Parquet(writer))
                 }
@@ -943,11 +2333,26 @@ fn path_to_writer(path: &Path, schema: SchemaRef) -> Result<AnyWriter> {
     Err(eyre!("Unable to parse extension"))
 }
 
+/// Wrap a file in an encoder for the given compression. The encoders write their trailers when
+/// the writer using them is closed, with [`OutputFile::finish`].
+fn compressed_file(file: File, compression: Option<OutputCompression>) -> Result<OutputFile> {
+    Ok(match compression {
+        Some(OutputCompression::Gzip) => OutputFile::Gzip(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        )),
+        Some(OutputCompression::Zstd) => OutputFile::Zstd(zstd::Encoder::new(file, 0)?),
+        None => OutputFile::Plain(std::io::BufWriter::new(file)),
+    })
+}
+
 pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
     let merged_exec_config = merge_configs(config.shared.clone(), config.cli.execution.clone());
-    let session_state_builder = DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
-        .with_extensions()
-        .await?;
+    let mut session_state_builder =
+        DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
+            .with_extensions()
+            .await?;
+    add_catalog_persistence(&mut session_state_builder, &config.db)?;
 
     // CLI mode: executing commands from files or CLI arguments
     let session_state = session_state_builder.build()?;
@@ -957,15 +2362,16 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         crate::APP_NAME,
         env!("CARGO_PKG_VERSION"),
     )?;
+    restore_system_tables(execution_ctx.system_tables(), &config.db)?;
+    let system_tables = execution_ctx.system_tables().clone();
     #[allow(unused_mut)]
     let mut app_execution = AppExecution::new(execution_ctx);
     #[cfg(feature = "flightsql")]
     {
         if cli.flightsql || matches!(cli.command, Some(Command::FlightSql { .. })) {
-            let auth = AuthConfig {
-                basic_auth: config.flightsql_client.auth.basic_auth,
-                bearer_token: config.flightsql_client.auth.bearer_token,
-            };
+            let auth = config.flightsql_client.auth.clone();
+            let mut tls = config.flightsql_client.tls.clone();
+            tls.insecure_skip_verify |= cli.insecure_skip_verify;
             let flightsql_cfg = FlightSQLConfig::new(
                 config.flightsql_client.connection_url,
                 config.flightsql_client.benchmark_iterations,
@@ -973,8 +2379,10 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
                 config.flightsql_client.headers.clone(),
                 config.flightsql_client.max_decoding_message_size,
                 config.flightsql_client.max_encoding_message_size,
+                tls,
             );
-            let flightsql_ctx = FlightSQLContext::new(flightsql_cfg);
+            let flightsql_ctx =
+                FlightSQLContext::new(flightsql_cfg).with_dialect(merged_exec_config.dialect);
 
             // Three-way header merge: config < file < CLI
             let mut all_headers = config.flightsql_client.headers.clone();
@@ -1014,7 +2422,9 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         }
     }
     register_db(app_execution.session_ctx(), &config.db).await?;
-    let app = CliApp::new(app_execution, cli.clone());
-    app.execute_files_or_commands().await?;
-    Ok(())
+    restore_catalog(&app_execution.execution_ctx().ddl_session_ctx(), &config.db).await?;
+    let app = CliApp::try_new(app_execution, cli.clone())?;
+    let result = app.execute_files_or_commands().await;
+    save_system_tables(&system_tables, &config.db)?;
+    result
 }
@@ -0,0 +1,92 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Recording executed statements with `--record` so they can be run again with `dft replay`
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+/// A single executed statement, written as one line of the session file
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordedStatement {
+    pub sql: String,
+    pub duration_ms: u128,
+    /// Number of rows returned, if they were counted
+    pub rows: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// Appends executed statements to a line delimited JSON session file
+pub struct SessionRecorder {
+    file: Mutex<File>,
+}
+
+impl SessionRecorder {
+    pub fn try_new(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| eyre!("Error creating session file '{}': {e}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record<T>(
+        &self,
+        sql: String,
+        duration: Duration,
+        rows: Option<usize>,
+        result: &Result<T>,
+    ) -> Result<()> {
+        let statement = RecordedStatement {
+            sql,
+            duration_ms: duration.as_millis(),
+            rows,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        let line = serde_json::to_string(&statement)?;
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| eyre!("Session file lock was poisoned"))?;
+        writeln!(file, "{line}")?;
+        Ok(())
+    }
+}
+
+/// Read the statements recorded in a session file in the order they were executed
+pub fn read_session(path: &Path) -> Result<Vec<RecordedStatement>> {
+    let file = File::open(path)
+        .map_err(|e| eyre!("Error opening session file '{}': {e}", path.display()))?;
+    let mut statements = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let statement = serde_json::from_str(&line)
+            .map_err(|e| eyre!("Invalid statement on line {} of session file: {e}", i + 1))?;
+        statements.push(statement);
+    }
+    Ok(statements)
+}
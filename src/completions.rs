@@ -0,0 +1,108 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Shell completion scripts for the `completions` subcommand
+
+use crate::{args::DftArgs, config::AppConfig, APP_NAME};
+use clap::{builder::PossibleValuesParser, CommandFactory};
+use clap_complete::Shell;
+use color_eyre::Result;
+use datafusion::sql::{
+    parser::{DFParser, Statement},
+    sqlparser::ast::Statement as SQLStatement,
+};
+use datafusion_app::{config::merge_configs, local::ddl_files};
+use log::info;
+use std::io::Write;
+
+/// Write the completion script for `shell` to `out`. If `tables` is true the names of the tables
+/// created in the DDL file are completed for arguments that take a table name.
+pub fn generate(config: &AppConfig, shell: Shell, tables: bool, out: &mut dyn Write) -> Result<()> {
+    let mut command = DftArgs::command();
+    if tables {
+        let names = ddl_table_names(config)?;
+        info!("Completing table names: {:?}", names);
+        command = with_table_names(command, names);
+    }
+    clap_complete::generate(shell, &mut command, APP_NAME, out);
+    Ok(())
+}
+
+/// Names of the tables created by the DDL file, or DDL directory, of the CLI's execution config
+fn ddl_table_names(config: &AppConfig) -> Result<Vec<String>> {
+    let execution = merge_configs(config.shared.clone(), config.cli.execution.clone());
+    let Some(ddl_path) = execution.ddl_path.filter(|path| path.exists()) else {
+        return Ok(Vec::new());
+    };
+    let mut names = Vec::new();
+    for file in ddl_files(&ddl_path)? {
+        names.extend(table_names(&std::fs::read_to_string(file)?)?);
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Names of the tables created by `CREATE TABLE` and `CREATE EXTERNAL TABLE` statements
+fn table_names(ddl: &str) -> datafusion::error::Result<Vec<String>> {
+    let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
+    let statements = DFParser::parse_sql_with_dialect(ddl, &dialect)?;
+    let mut names: Vec<String> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::CreateExternalTable(create) => Some(create.name.to_string()),
+            Statement::Statement(statement) => match statement.as_ref() {
+                SQLStatement::CreateTable(create) => Some(create.name.to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Complete table names for the arguments of subcommands that take one
+fn with_table_names(command: clap::Command, names: Vec<String>) -> clap::Command {
+    let tables = PossibleValuesParser::new(names);
+    command
+        .mut_subcommand("catalog", |catalog| {
+            catalog.mut_subcommand("describe", |describe| {
+                describe.mut_arg("table", |arg| arg.value_parser(tables.clone()))
+            })
+        })
+        .mut_subcommand("schema", |schema| {
+            schema.mut_arg("target", |arg| arg.value_parser(tables))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::table_names;
+
+    #[test]
+    fn test_table_names() {
+        let ddl = r#"
+CREATE EXTERNAL TABLE events STORED AS PARQUET LOCATION 's3://bucket/events/';
+CREATE TABLE users AS VALUES (1, 'a');
+CREATE VIEW recent AS SELECT * FROM events;
+CREATE TABLE events (id INT);
+"#;
+        assert_eq!(table_names(ddl).unwrap(), vec!["events", "users"]);
+    }
+}
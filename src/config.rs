@@ -17,23 +17,33 @@
 
 //! Configuration management handling
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(any(feature = "flightsql", feature = "http"))]
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-use datafusion_app::config::ExecutionConfig;
+#[cfg(feature = "azure")]
+use datafusion_app::config::AzureConfig;
+#[cfg(feature = "gcs")]
+use datafusion_app::config::GcsConfig;
+#[cfg(feature = "s3")]
+use datafusion_app::config::S3Config;
+use datafusion_app::config::{ExecutionConfig, ObjectStoreConfig};
 use directories::{ProjectDirs, UserDirs};
 use lazy_static::lazy_static;
 use log::{debug, error};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[cfg(any(feature = "flightsql", feature = "http"))]
 use datafusion_app::config::AuthConfig;
 #[cfg(feature = "flightsql")]
+use datafusion_app::config::FlightSQLClientTlsConfig;
+#[cfg(feature = "flightsql")]
 use std::collections::HashMap;
 use url::Url;
 
+use crate::args::DftArgs;
+
 lazy_static! {
     pub static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_uppercase().to_string();
     pub static ref DATA_FOLDER: Option<PathBuf> =
@@ -103,6 +113,103 @@ pub struct FlightSQLServerConfig {
     /// Defaults to tonic's default (4MB) when unset.
     #[serde(default)]
     pub max_encoding_message_size: Option<usize>,
+    /// Serve over TLS instead of plaintext when set
+    #[serde(default)]
+    pub tls: Option<ServerTlsConfig>,
+    /// Return an endpoint per output partition of a query's physical plan so that clients can
+    /// fetch large results in parallel
+    #[serde(default)]
+    pub partitioned_results: bool,
+    /// Seconds a client connection's session, and the settings it changed with `SET`, is kept
+    /// after its last request
+    #[serde(default = "default_session_idle_timeout_secs")]
+    pub session_idle_timeout_secs: u64,
+    /// Seconds a ticket returned by `GetFlightInfo` can be fetched after it was issued or last
+    /// fetched. The planned query is dropped once it expires.
+    #[serde(default = "default_ticket_ttl_secs")]
+    pub ticket_ttl_secs: u64,
+    /// Maximum number of statements executing at once. Unlimited when unset.
+    #[serde(default)]
+    pub max_concurrent_statements: Option<usize>,
+    /// Maximum number of statements waiting for `max_concurrent_statements` to allow them to
+    /// execute. Statements beyond this are rejected.
+    #[serde(default = "default_statement_queue_depth")]
+    pub statement_queue_depth: usize,
+    /// Seconds a statement waits in the queue before it is rejected
+    #[serde(default = "default_statement_queue_timeout_secs")]
+    pub statement_queue_timeout_secs: u64,
+    /// Maximum bytes of memory a single query may use. Unlimited when unset.
+    #[serde(default)]
+    pub query_memory_limit: Option<usize>,
+    /// Seconds in-flight requests may take to finish once the server receives SIGINT or SIGTERM
+    #[serde(default = "default_shutdown_drain_timeout_secs")]
+    pub shutdown_drain_timeout_secs: u64,
+    #[serde(default)]
+    pub result_limit: FlightSQLServerResultLimitConfig,
+    /// Seconds the tables returned by `GetTables` and `GetDbSchemas` are cached. DDL executed by
+    /// the server clears the cache. Zero disables the cache.
+    #[serde(default = "default_catalog_cache_ttl_secs")]
+    pub catalog_cache_ttl_secs: u64,
+    /// Seconds a session token issued by `Handshake` to a client using basic auth is valid
+    #[serde(default = "default_session_token_ttl_secs")]
+    pub session_token_ttl_secs: u64,
+    /// Limit on the rate of requests from each client. Unlimited when unset.
+    #[serde(default)]
+    pub rate_limit: Option<FlightSQLServerRateLimitConfig>,
+    /// Execute queries in `GetFlightInfo` and spill results that outgrow memory to disk, serving
+    /// them in chunks. Results are streamed as they're computed when unset.
+    #[serde(default)]
+    pub result_spill: Option<FlightSQLServerSpillConfig>,
+    /// Token clients must send in the `x-dft-admin-token` header to call administrative actions
+    /// such as `ReloadDdl`. The actions are disabled when unset.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+#[cfg(feature = "flightsql")]
+impl FlightSQLServerConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.max_concurrent_statements == Some(0) {
+            return Err(
+                "flightsql_server.max_concurrent_statements must be at least 1, leave it unset for no limit"
+                    .to_string(),
+            );
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            validate_rate_limit(
+                "flightsql_server.rate_limit",
+                rate_limit.requests_per_second,
+                rate_limit.burst,
+                rate_limit.max_concurrent_requests,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the limits of a `rate_limit` section, which would otherwise reject every request or
+/// panic when computing how long a client has to wait
+#[cfg(any(feature = "flightsql", feature = "http"))]
+fn validate_rate_limit(
+    section: &str,
+    requests_per_second: Option<f64>,
+    burst: u32,
+    max_concurrent_requests: Option<usize>,
+) -> Result<(), String> {
+    if requests_per_second.is_some_and(|rate| !(rate.is_finite() && rate > 0.0)) {
+        return Err(format!(
+            "{section}.requests_per_second must be greater than 0, leave it unset for no limit"
+        ));
+    }
+    if burst == 0 {
+        return Err(format!("{section}.burst must be at least 1"));
+    }
+    if max_concurrent_requests == Some(0) {
+        return Err(format!(
+            "{section}.max_concurrent_requests must be at least 1, leave it unset for no limit"
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(feature = "flightsql")]
@@ -115,10 +222,98 @@ impl Default for FlightSQLServerConfig {
             auth: default_auth_config(),
             max_decoding_message_size: None,
             max_encoding_message_size: None,
+            tls: None,
+            partitioned_results: false,
+            session_idle_timeout_secs: default_session_idle_timeout_secs(),
+            ticket_ttl_secs: default_ticket_ttl_secs(),
+            max_concurrent_statements: None,
+            statement_queue_depth: default_statement_queue_depth(),
+            statement_queue_timeout_secs: default_statement_queue_timeout_secs(),
+            query_memory_limit: None,
+            shutdown_drain_timeout_secs: default_shutdown_drain_timeout_secs(),
+            result_limit: FlightSQLServerResultLimitConfig::default(),
+            catalog_cache_ttl_secs: default_catalog_cache_ttl_secs(),
+            session_token_ttl_secs: default_session_token_ttl_secs(),
+            rate_limit: None,
+            result_spill: None,
+            admin_token: None,
+        }
+    }
+}
+
+/// Limits on the size of the results returned by a single `DoGet`
+#[cfg(feature = "flightsql")]
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FlightSQLServerResultLimitConfig {
+    /// Maximum number of rows returned. Unlimited when unset.
+    #[serde(default)]
+    pub rows: Option<usize>,
+    /// Maximum number of bytes of encoded Arrow data returned. Unlimited when unset.
+    #[serde(default)]
+    pub bytes: Option<usize>,
+    /// Fail queries whose results exceed a limit instead of truncating their results
+    #[serde(default)]
+    pub reject: bool,
+}
+
+/// Token bucket rate limit applied to each client of the server
+#[cfg(feature = "flightsql")]
+#[derive(Clone, Debug, Deserialize)]
+pub struct FlightSQLServerRateLimitConfig {
+    /// Requests per second a client may sustain. Unlimited when unset.
+    #[serde(default)]
+    pub requests_per_second: Option<f64>,
+    /// Requests a client may make at once after being idle
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+    /// Requests a client may have in progress at the same time, including the results being
+    /// streamed by `DoGet`. Unlimited when unset.
+    #[serde(default)]
+    pub max_concurrent_requests: Option<usize>,
+}
+
+/// Where, and when, the results of a query are spilled to disk
+#[cfg(feature = "flightsql")]
+#[derive(Clone, Debug, Deserialize)]
+pub struct FlightSQLServerSpillConfig {
+    /// Bytes of results kept in memory before they are spilled
+    #[serde(default = "default_spill_memory_threshold_bytes")]
+    pub memory_threshold_bytes: usize,
+    /// Bytes of results in each spilled chunk, which is fetched from its own endpoint
+    #[serde(default = "default_spill_chunk_bytes")]
+    pub chunk_bytes: usize,
+    /// Directory the results are spilled to. The system's temporary directory when unset.
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+}
+
+#[cfg(feature = "flightsql")]
+impl Default for FlightSQLServerSpillConfig {
+    fn default() -> Self {
+        Self {
+            memory_threshold_bytes: default_spill_memory_threshold_bytes(),
+            chunk_bytes: default_spill_chunk_bytes(),
+            directory: None,
         }
     }
 }
 
+#[cfg(any(feature = "flightsql", feature = "http"))]
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerTlsConfig {
+    /// PEM encoded certificate chain presented by the server
+    pub cert_path: PathBuf,
+    /// PEM encoded private key of the server certificate
+    pub key_path: PathBuf,
+    /// PEM bundle of the CAs trusted to sign client certificates. When set clients must present a
+    /// certificate signed by one of them (mutual TLS).
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+    /// Accept clients without a certificate while still verifying the ones that present one
+    #[serde(default)]
+    pub client_auth_optional: bool,
+}
+
 #[cfg(feature = "flightsql")]
 #[derive(Clone, Debug, Deserialize)]
 pub struct FlightSQLClientConfig {
@@ -140,6 +335,9 @@ pub struct FlightSQLClientConfig {
     /// Defaults to tonic's default (4MB) when unset.
     #[serde(default)]
     pub max_encoding_message_size: Option<usize>,
+    /// TLS settings used when `connection_url` is `https://`
+    #[serde(default)]
+    pub tls: FlightSQLClientTlsConfig,
 }
 
 #[cfg(feature = "flightsql")]
@@ -153,6 +351,7 @@ impl Default for FlightSQLClientConfig {
             headers_file: None,
             max_decoding_message_size: None,
             max_encoding_message_size: None,
+            tls: FlightSQLClientTlsConfig::default(),
         }
     }
 }
@@ -170,8 +369,48 @@ pub struct HttpServerConfig {
     pub auth: AuthConfig,
     #[serde(default = "default_timeout_seconds")]
     pub timeout_seconds: u64,
+    /// Largest timeout a request can set with the `x-dft-timeout-seconds` header, requests can
+    /// only shorten `timeout_seconds` when unset
+    #[serde(default)]
+    pub max_timeout_seconds: Option<u64>,
     #[serde(default = "default_result_limit")]
     pub result_limit: usize,
+    /// Largest result limit a request can set with the `x-dft-result-limit` header, requests can
+    /// only lower `result_limit` when unset
+    #[serde(default)]
+    pub max_result_limit: Option<usize>,
+    /// Largest file that can be uploaded to create a table
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: usize,
+    /// Compression of query results for clients that accept it
+    #[serde(default)]
+    pub compression: HttpCompressionConfig,
+    /// Limits on the requests of each client. Unlimited when unset.
+    #[serde(default)]
+    pub rate_limit: Option<HttpRateLimitConfig>,
+    /// Check that the FlightSQL server queries can be sent to answers in `/ready`, for servers
+    /// that rely on it
+    #[cfg(feature = "flightsql")]
+    #[serde(default)]
+    pub ready_checks_flightsql: bool,
+    /// Allow browsers on other origins to call the server. Cross-origin requests are blocked when
+    /// unset.
+    #[serde(default)]
+    pub cors: Option<HttpCorsConfig>,
+    /// Serve over TLS instead of plain HTTP
+    #[serde(default)]
+    pub tls: Option<ServerTlsConfig>,
+    /// Seconds a session, and the settings and prepared statements it holds, is kept after its
+    /// last request
+    #[serde(default = "default_http_session_idle_timeout_secs")]
+    pub session_idle_timeout_secs: u64,
+    /// Sessions that can be open at once. Starting another drops the least recently used one.
+    #[serde(default = "default_http_max_sessions")]
+    pub max_sessions: usize,
+    /// Start a session for requests that don't name one in the `x-dft-session` header, returning
+    /// its id in the response. Requests without a session otherwise share the server's session.
+    #[serde(default)]
+    pub auto_create_sessions: bool,
 }
 
 #[cfg(feature = "http")]
@@ -183,11 +422,103 @@ impl Default for HttpServerConfig {
             server_metrics_addr: default_server_metrics_addr(),
             auth: default_auth_config(),
             timeout_seconds: default_timeout_seconds(),
+            max_timeout_seconds: None,
             result_limit: default_result_limit(),
+            max_result_limit: None,
+            max_upload_bytes: default_max_upload_bytes(),
+            compression: HttpCompressionConfig::default(),
+            rate_limit: None,
+            #[cfg(feature = "flightsql")]
+            ready_checks_flightsql: false,
+            cors: None,
+            tls: None,
+            session_idle_timeout_secs: default_http_session_idle_timeout_secs(),
+            max_sessions: default_http_max_sessions(),
+            auto_create_sessions: false,
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl HttpServerConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.max_sessions == 0 {
+            return Err("http_server.max_sessions must be at least 1".to_string());
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            validate_rate_limit(
+                "http_server.rate_limit",
+                rate_limit.requests_per_second,
+                rate_limit.burst,
+                rate_limit.max_concurrent_requests,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The encodings the HTTP server can compress query results with, picked by the client's
+/// `Accept-Encoding` header
+#[cfg(feature = "http")]
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpCompressionConfig {
+    #[serde(default = "default_compression_enabled")]
+    pub gzip: bool,
+    #[serde(default = "default_compression_enabled")]
+    pub br: bool,
+    #[serde(default = "default_compression_enabled")]
+    pub zstd: bool,
+}
+
+#[cfg(feature = "http")]
+impl Default for HttpCompressionConfig {
+    fn default() -> Self {
+        Self {
+            gzip: default_compression_enabled(),
+            br: default_compression_enabled(),
+            zstd: default_compression_enabled(),
         }
     }
 }
 
+/// Limits applied to each client of the HTTP server, identified by the user of their basic
+/// credentials, or else their IP address
+#[cfg(feature = "http")]
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpRateLimitConfig {
+    /// Requests per second a client may sustain. Unlimited when unset.
+    #[serde(default)]
+    pub requests_per_second: Option<f64>,
+    /// Requests a client may make at once after being idle
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+    /// Requests a client may have in progress at the same time. Unlimited when unset.
+    #[serde(default)]
+    pub max_concurrent_requests: Option<usize>,
+}
+
+/// The cross-origin requests the HTTP server allows
+#[cfg(feature = "http")]
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HttpCorsConfig {
+    /// Origins that can make requests, such as `https://example.com`, or `*` for any origin
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Methods that can be used, `GET`, `POST` and `DELETE` when empty
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    /// Headers that can be sent, `accept`, `authorization`, `content-type`, `x-query-id`,
+    /// `x-dft-result-limit` and `x-dft-timeout-seconds` when empty
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    /// Allow requests with credentials, such as cookies. Can't be used with any origin.
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// How long browsers can cache the response to a preflight request
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct AppConfig {
     #[serde(default)]
@@ -210,6 +541,36 @@ pub struct AppConfig {
     pub db: DbConfig,
 }
 
+impl AppConfig {
+    /// Check the settings that would otherwise only fail, or misbehave, once they're used
+    pub fn validate(&self) -> Result<(), String> {
+        let executions = [
+            ("shared", &self.shared),
+            ("cli.execution", &self.cli.execution),
+            #[cfg(feature = "tui")]
+            ("tui.execution", &self.tui.execution),
+            #[cfg(feature = "flightsql")]
+            (
+                "flightsql_server.execution",
+                &self.flightsql_server.execution,
+            ),
+            #[cfg(feature = "http")]
+            ("http_server.execution", &self.http_server.execution),
+        ];
+        for (name, execution) in executions {
+            execution
+                .memory
+                .validate()
+                .map_err(|e| format!("{name}.{e}"))?;
+        }
+        #[cfg(feature = "flightsql")]
+        self.flightsql_server.validate()?;
+        #[cfg(feature = "http")]
+        self.http_server.validate()?;
+        Ok(())
+    }
+}
+
 fn default_execution_config() -> ExecutionConfig {
     ExecutionConfig::default()
 }
@@ -228,6 +589,17 @@ fn default_interaction_config() -> InteractionConfig {
 pub struct DbConfig {
     #[serde(default = "default_db_path")]
     pub path: Url,
+    /// Record the statements executed by the FlightSQL and HTTP servers in `system.query_log`
+    #[serde(default)]
+    pub query_log: bool,
+    /// Save the external tables, views and `--object-store` stores created in any app to
+    /// `catalog.json` in the database path and restore them at startup
+    #[serde(default)]
+    pub persist_catalog: bool,
+    /// Save the query history in `system.queries` to the database path on shutdown and load it
+    /// at startup
+    #[serde(default)]
+    pub persist_system_tables: bool,
 }
 
 impl Default for DbConfig {
@@ -239,6 +611,9 @@ impl Default for DbConfig {
 fn default_db_config() -> DbConfig {
     DbConfig {
         path: default_db_path(),
+        query_log: false,
+        persist_catalog: false,
+        persist_system_tables: false,
     }
 }
 
@@ -309,6 +684,56 @@ pub fn default_headers() -> HashMap<String, String> {
     HashMap::new()
 }
 
+#[cfg(feature = "flightsql")]
+fn default_session_idle_timeout_secs() -> u64 {
+    crate::server::flightsql::service::DEFAULT_SESSION_IDLE_TIMEOUT.as_secs()
+}
+
+#[cfg(feature = "flightsql")]
+fn default_spill_memory_threshold_bytes() -> usize {
+    256 * 1024 * 1024
+}
+
+#[cfg(feature = "flightsql")]
+fn default_spill_chunk_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+#[cfg(feature = "flightsql")]
+fn default_ticket_ttl_secs() -> u64 {
+    crate::server::flightsql::tickets::DEFAULT_TICKET_TTL.as_secs()
+}
+
+#[cfg(feature = "flightsql")]
+fn default_session_token_ttl_secs() -> u64 {
+    crate::server::flightsql::auth::DEFAULT_SESSION_TOKEN_TTL.as_secs()
+}
+
+#[cfg(any(feature = "flightsql", feature = "http"))]
+fn default_rate_limit_burst() -> u32 {
+    10
+}
+
+#[cfg(feature = "flightsql")]
+fn default_catalog_cache_ttl_secs() -> u64 {
+    crate::server::flightsql::service::DEFAULT_CATALOG_CACHE_TTL.as_secs()
+}
+
+#[cfg(feature = "flightsql")]
+fn default_statement_queue_depth() -> usize {
+    64
+}
+
+#[cfg(feature = "flightsql")]
+fn default_statement_queue_timeout_secs() -> u64 {
+    30
+}
+
+#[cfg(feature = "flightsql")]
+fn default_shutdown_drain_timeout_secs() -> u64 {
+    30
+}
+
 #[cfg(any(feature = "flightsql", feature = "http"))]
 fn default_server_metrics_addr() -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9000)
@@ -340,7 +765,38 @@ fn default_result_limit() -> usize {
     1000
 }
 
+#[cfg(feature = "http")]
+fn default_compression_enabled() -> bool {
+    true
+}
+
+#[cfg(feature = "http")]
+fn default_max_upload_bytes() -> usize {
+    100 * 1024 * 1024
+}
+
+#[cfg(feature = "http")]
+fn default_http_session_idle_timeout_secs() -> u64 {
+    crate::server::http::sessions::DEFAULT_SESSION_IDLE_TIMEOUT.as_secs()
+}
+
+#[cfg(feature = "http")]
+fn default_http_max_sessions() -> usize {
+    crate::server::http::sessions::DEFAULT_MAX_SESSIONS
+}
+
 pub fn create_config(config_path: PathBuf, overrides: &[(String, String)]) -> AppConfig {
+    // Profiles are the only source of errors so this can't fail without one
+    create_config_with_profile(config_path, None, overrides).unwrap_or_default()
+}
+
+/// Create the config, merging the tables of the selected `[profile.<name>]` over the defaults
+/// before applying any overrides
+pub fn create_config_with_profile(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    overrides: &[(String, String)],
+) -> Result<AppConfig, String> {
     let mut config_value = if config_path.exists() {
         debug!("Config exists");
         match std::fs::read_to_string(&config_path) {
@@ -358,24 +814,184 @@ pub fn create_config(config_path: PathBuf, overrides: &[(String, String)]) -> Ap
         toml::Value::Table(Default::default())
     };
 
-    for (key, value) in overrides {
-        if let Err(err) = apply_config_override(&mut config_value, key, value) {
-            error!("Error applying config override '{key}={value}': {err}");
-        }
-    }
+    apply_profile_and_overrides(&mut config_value, &config_path, profile, overrides)?;
 
     match config_value.try_into::<AppConfig>() {
         Ok(parsed_config) => {
             debug!("Parsed config: {:?}", parsed_config);
-            parsed_config
+            parsed_config.validate()?;
+            Ok(parsed_config)
         }
         Err(err) => {
             error!("Error parsing config after applying overrides: {:?}", err);
-            AppConfig::default()
+            Ok(AppConfig::default())
         }
     }
 }
 
+/// Like [`create_config_with_profile`] but fails, instead of falling back to the defaults, when
+/// the config file can't be read or parsed. Used to reload the config of a running server
+/// without losing its settings to a typo.
+pub fn try_create_config_with_profile(
+    config_path: PathBuf,
+    profile: Option<&str>,
+    overrides: &[(String, String)],
+) -> Result<AppConfig, String> {
+    let mut config_value = if config_path.exists() {
+        let config_contents = std::fs::read_to_string(&config_path)
+            .map_err(|e| format!("Error reading config {}: {e}", config_path.display()))?;
+        toml::from_str::<toml::Value>(&config_contents)
+            .map_err(|e| format!("Error parsing config {}: {e}", config_path.display()))?
+    } else {
+        toml::Value::Table(Default::default())
+    };
+    apply_profile_and_overrides(&mut config_value, &config_path, profile, overrides)?;
+    let config = config_value
+        .try_into::<AppConfig>()
+        .map_err(|e| format!("Error parsing config {}: {e}", config_path.display()))?;
+    config.validate()?;
+    Ok(config)
+}
+
+fn apply_profile_and_overrides(
+    config_value: &mut toml::Value,
+    config_path: &Path,
+    profile: Option<&str>,
+    overrides: &[(String, String)],
+) -> Result<(), String> {
+    let profiles = config_value
+        .as_table_mut()
+        .and_then(|table| table.remove("profile"));
+    if let Some(profile) = profile {
+        let profile_value = profiles
+            .and_then(|profiles| profiles.get(profile).cloned())
+            .ok_or_else(|| {
+                format!(
+                    "Profile '{profile}' not found in config {}",
+                    config_path.display()
+                )
+            })?;
+        debug!("Applying profile: {profile}");
+        merge_config_values(config_value, profile_value);
+    }
+
+    for (key, value) in overrides {
+        if let Err(err) = apply_config_override(config_value, key, value) {
+            error!("Error applying config override '{key}={value}': {err}");
+        }
+    }
+    Ok(())
+}
+
+/// An object store registered at startup with `--object-store`, along with the options its
+/// credentials are read with
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ObjectStoreArg {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcs_service_account: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure_storage_account: Option<String>,
+}
+
+impl ObjectStoreArg {
+    /// The stores passed with `--object-store`, which share the credential options of `args`
+    pub fn from_args(args: &DftArgs) -> Vec<Self> {
+        args.object_stores
+            .iter()
+            .map(|url| Self {
+                url: url.clone(),
+                aws_profile: args.aws_profile.clone(),
+                gcs_service_account: args.gcs_service_account.clone(),
+                azure_storage_account: args.azure_storage_account.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Add the object stores passed with `--object-store` to the shared execution config, and to any
+/// app specific execution config that overrides the shared object stores, so that they are
+/// registered with the `RuntimeEnv` the same way as stores from the config file
+#[cfg_attr(
+    not(any(feature = "s3", feature = "gcs", feature = "azure")),
+    allow(unused_mut)
+)]
+pub fn add_object_stores(config: &mut AppConfig, args: &[ObjectStoreArg]) -> Result<(), String> {
+    if args.is_empty() {
+        return Ok(());
+    }
+    let mut stores = ObjectStoreConfig::default();
+    for arg in args {
+        let url = &arg.url;
+        let parsed =
+            Url::parse(url).map_err(|e| format!("Invalid object store url '{url}': {e}"))?;
+        match parsed.scheme() {
+            #[cfg(feature = "s3")]
+            "s3" | "s3a" => {
+                let s3 = S3Config::from_url(url, arg.aws_profile.clone())
+                    .map_err(|e| e.to_string())?;
+                stores.s3.get_or_insert_with(Vec::new).push(s3);
+            }
+            #[cfg(feature = "gcs")]
+            "gs" => {
+                let gcs = GcsConfig::new(url.clone(), arg.gcs_service_account.clone());
+                stores.gcs.get_or_insert_with(Vec::new).push(gcs);
+            }
+            #[cfg(feature = "azure")]
+            "az" | "azure" | "abfs" | "abfss" => {
+                let azure = AzureConfig::new(url.clone(), arg.azure_storage_account.clone());
+                stores.azure.get_or_insert_with(Vec::new).push(azure);
+            }
+            _ => {
+                return Err(format!(
+                    "Unsupported object store url '{url}'. s3://, gs://, and az:// urls are supported when built with the `s3`, `gcs`, and `azure` features respectively"
+                ))
+            }
+        }
+    }
+
+    config
+        .shared
+        .object_store
+        .get_or_insert_with(ObjectStoreConfig::default)
+        .extend(stores.clone());
+    let app_executions = [
+        &mut config.cli.execution,
+        #[cfg(feature = "tui")]
+        &mut config.tui.execution,
+        #[cfg(feature = "flightsql")]
+        &mut config.flightsql_server.execution,
+        #[cfg(feature = "http")]
+        &mut config.http_server.execution,
+    ];
+    for execution in app_executions {
+        if let Some(object_store) = execution.object_store.as_mut() {
+            object_store.extend(stores.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Recursively merge `overlay` onto `base`. Tables are merged key by key and any other value in
+/// `overlay` replaces the value in `base`.
+fn merge_config_values(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_config_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
 /// Apply a single `section.key=value` override onto a parsed TOML document,
 /// creating intermediate tables as needed. Values are inferred as bool, int,
 /// float, or string (in that order).
@@ -422,3 +1038,57 @@ fn parse_override_value(raw: &str) -> toml::Value {
         toml::Value::String(raw.to_string())
     }
 }
+
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_http_rate_limit() {
+        let mut config = AppConfig::default();
+        for (rate_limit, expected) in [
+            (
+                (Some(0.0), 10, None),
+                "http_server.rate_limit.requests_per_second must be greater than 0",
+            ),
+            (
+                (Some(10.0), 0, None),
+                "http_server.rate_limit.burst must be at least 1",
+            ),
+            (
+                (None, 10, Some(0)),
+                "http_server.rate_limit.max_concurrent_requests must be at least 1",
+            ),
+        ] {
+            let (requests_per_second, burst, max_concurrent_requests) = rate_limit;
+            config.http_server.rate_limit = Some(HttpRateLimitConfig {
+                requests_per_second,
+                burst,
+                max_concurrent_requests,
+            });
+            assert!(config.validate().unwrap_err().starts_with(expected));
+        }
+
+        config.http_server.rate_limit = Some(HttpRateLimitConfig {
+            requests_per_second: Some(0.5),
+            burst: 1,
+            max_concurrent_requests: Some(1),
+        });
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_memory_limit_fraction() {
+        let mut config = AppConfig::default();
+        for limit_fraction in [0.0, -0.5, 1.5, f64::NAN] {
+            config.http_server.execution.memory.limit_fraction = limit_fraction;
+            assert!(config
+                .validate()
+                .unwrap_err()
+                .starts_with("http_server.execution.memory.limit_fraction must be greater than 0"));
+        }
+
+        config.http_server.execution.memory.limit_fraction = 1.0;
+        assert!(config.validate().is_ok());
+    }
+}
@@ -15,23 +15,61 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use color_eyre::{Report, Result};
 use datafusion::{
     catalog::{MemoryCatalogProvider, MemorySchemaProvider},
+    common::{SchemaReference, TableReference},
     datasource::{
         file_format::{csv::CsvFormat, json::JsonFormat, parquet::ParquetFormat, FileFormat},
         listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
     },
+    execution::SessionState,
+    logical_expr::{DdlStatement, LogicalPlan},
     prelude::SessionContext,
 };
-use log::{debug, info};
-use std::path::Path;
+use datafusion_app::{
+    ddl::DdlListener, extensions::DftSessionStateBuilder, secrets::is_secret_ref,
+    system::SystemTables,
+};
+use log::{debug, error, info, warn};
+use object_store::{ObjectStore, ObjectStoreExt};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use url::Url;
+#[cfg(feature = "http")]
+use {
+    futures::{Stream, StreamExt},
+    object_store::WriteMultipart,
+    uuid::Uuid,
+};
 #[cfg(feature = "vortex")]
 use {vortex_datafusion::VortexFormat, vortex_session::VortexSession};
 
-use crate::config::DbConfig;
+use crate::config::{DbConfig, ObjectStoreArg};
+
+/// Name of the file in the database path that the catalog is persisted to
+const CATALOG_FILE: &str = "catalog.json";
+/// Name of the directory in the database path that the `system` tables are persisted to
+const SYSTEM_TABLES_DIR: &str = "system/";
+/// Name of the directory in the database path that uploaded files are written to, before they're
+/// moved to their table
+#[cfg(feature = "http")]
+const UPLOADS_DIR: &str = "uploads/";
+/// Name of the file holding the data of an uploaded table, without its extension
+#[cfg(feature = "http")]
+const UPLOADED_FILE: &str = "data";
+/// Parts of an uploaded file that are written at a time
+#[cfg(feature = "http")]
+const MAX_CONCURRENT_PARTS: usize = 8;
 
 /// Detects the file format based on file extension
 fn detect_format(extension: &str) -> Result<(Arc<dyn FileFormat>, &'static str)> {
@@ -51,6 +89,192 @@ fn detect_format(extension: &str) -> Result<(Arc<dyn FileFormat>, &'static str)>
     }
 }
 
+/// The directory of the table `catalog.schema.table` in the database at `db_path`
+pub fn table_dir_url(db_path: &Url, catalog: &str, schema: &str, table: &str) -> Result<Url> {
+    Ok(db_path
+        .join("tables/")?
+        .join(&format!("{catalog}/"))?
+        .join(&format!("{schema}/"))?
+        .join(&format!("{table}/"))?)
+}
+
+/// A table reading the files with `extension` in `table_url`, with the schema inferred from them
+pub async fn listing_table(
+    ctx: &SessionContext,
+    table_url: ListingTableUrl,
+    extension: &str,
+) -> Result<Arc<ListingTable>> {
+    let (file_format, file_extension) = detect_format(extension)?;
+    let listing_options = ListingOptions::new(file_format).with_file_extension(file_extension);
+    // Resolve the schema
+    let resolved_schema = listing_options
+        .infer_schema(&ctx.state(), &table_url)
+        .await?;
+    let config = ListingTableConfig::new(table_url)
+        .with_listing_options(listing_options)
+        .with_schema(resolved_schema);
+    // Create a new TableProvider
+    Ok(Arc::new(ListingTable::try_new(config)?))
+}
+
+/// Whether files with `extension` can be read as a table
+#[cfg(feature = "http")]
+pub fn is_supported_format(extension: &str) -> bool {
+    detect_format(extension).is_ok()
+}
+
+/// Error creating a table that already exists, holding its name
+#[cfg(feature = "http")]
+#[derive(Debug)]
+pub struct TableExists(pub String);
+
+#[cfg(feature = "http")]
+impl std::fmt::Display for TableExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Table {} already exists", self.0)
+    }
+}
+
+#[cfg(feature = "http")]
+impl std::error::Error for TableExists {}
+
+/// Stores `data`, the contents of a file with `extension`, as the table `catalog.schema.table` in
+/// the database at `db_path` and registers it in `ctx`, so that it's also loaded by
+/// [`register_db`] on restart.
+///
+/// `data` is streamed to a file in the database's uploads directory, which is then moved to the
+/// table without replacing an existing file, so concurrent uploads of the same table can't
+/// replace each other's data. The files are removed if the upload fails or can't be read as a
+/// table.
+#[cfg(feature = "http")]
+pub async fn create_table_from_file<B, E>(
+    ctx: &SessionContext,
+    db_path: &Url,
+    catalog: &str,
+    schema: &str,
+    table: &str,
+    extension: &str,
+    data: impl Stream<Item = std::result::Result<B, E>>,
+) -> Result<Arc<ListingTable>>
+where
+    B: AsRef<[u8]>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let (_, file_extension) = detect_format(extension)?;
+    let schema_provider = ctx
+        .catalog(catalog)
+        .and_then(|c| c.schema(schema))
+        .ok_or(Report::msg(format!("missing schema {catalog}.{schema}")))?;
+    if schema_provider.table_exist(table) {
+        return Err(Report::new(TableExists(format!(
+            "{catalog}.{schema}.{table}"
+        ))));
+    }
+
+    let table_dir = table_dir_url(db_path, catalog, schema, table)?;
+    let file_url = table_dir.join(&format!("{UPLOADED_FILE}{file_extension}"))?;
+    let upload_url = db_path
+        .join(UPLOADS_DIR)?
+        .join(&format!("{}{file_extension}", Uuid::new_v4()))?;
+    let table_url = ListingTableUrl::parse(table_dir)?;
+    let store = ctx.runtime_env().object_store(table_url.object_store())?;
+    let file_path = object_store::path::Path::from_url_path(file_url.path())?;
+    let upload_path = object_store::path::Path::from_url_path(upload_url.path())?;
+
+    debug!("writing upload to {upload_url}");
+    if let Err(e) = write_upload(store.as_ref(), &upload_path, data).await {
+        remove_upload(store.as_ref(), &upload_path).await;
+        return Err(e);
+    }
+    debug!("moving upload to {file_url}");
+    if let Err(e) = move_upload(store.as_ref(), &upload_path, &file_path).await {
+        remove_upload(store.as_ref(), &upload_path).await;
+        return Err(match e {
+            object_store::Error::AlreadyExists { .. } => {
+                Report::new(TableExists(format!("{catalog}.{schema}.{table}")))
+            }
+            e => e.into(),
+        });
+    }
+
+    let provider = match listing_table(ctx, table_url, extension).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            store.delete(&file_path).await?;
+            return Err(e);
+        }
+    };
+    schema_provider.register_table(table.to_string(), Arc::clone(&provider) as _)?;
+    info!("registered uploaded table \"{catalog}.{schema}.{table}\"");
+    Ok(provider)
+}
+
+/// Write `data` to `path` as it's received, aborting the write if `data` fails
+#[cfg(feature = "http")]
+async fn write_upload<B, E>(
+    store: &dyn ObjectStore,
+    path: &object_store::path::Path,
+    data: impl Stream<Item = std::result::Result<B, E>>,
+) -> Result<()>
+where
+    B: AsRef<[u8]>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut data = std::pin::pin!(data);
+    let mut writer = WriteMultipart::new(store.put_multipart(path).await?);
+    let written: Result<()> = async {
+        while let Some(bytes) = data.next().await {
+            writer.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+            writer.write(bytes?.as_ref());
+        }
+        Ok(())
+    }
+    .await;
+    match written {
+        Ok(()) => {
+            writer.finish().await?;
+            Ok(())
+        }
+        Err(e) => {
+            if let Err(abort) = writer.abort().await {
+                warn!("Error aborting upload to {path}: {abort}");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Move the upload at `from` to `to`, failing with [`object_store::Error::AlreadyExists`] if `to`
+/// exists. Stores that can't rename without replacing the destination check that it doesn't
+/// exist first, which isn't atomic.
+#[cfg(feature = "http")]
+async fn move_upload(
+    store: &dyn ObjectStore,
+    from: &object_store::path::Path,
+    to: &object_store::path::Path,
+) -> object_store::Result<()> {
+    match store.rename_if_not_exists(from, to).await {
+        Err(object_store::Error::NotImplemented { .. }) => match store.head(to).await {
+            Ok(_) => Err(object_store::Error::AlreadyExists {
+                path: to.to_string(),
+                source: "The uploaded table's file exists".into(),
+            }),
+            Err(object_store::Error::NotFound { .. }) => store.rename(from, to).await,
+            Err(e) => Err(e),
+        },
+        result => result,
+    }
+}
+
+/// Remove the upload at `path`, which may not have been written
+#[cfg(feature = "http")]
+async fn remove_upload(store: &dyn ObjectStore, path: &object_store::path::Path) {
+    match store.delete(path).await {
+        Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+        Err(e) => warn!("Error removing upload {path}: {e}"),
+    }
+}
+
 pub async fn register_db(ctx: &SessionContext, db_config: &DbConfig) -> Result<()> {
     info!("registering tables to database");
     let tables_url = db_config.path.join("tables/")?;
@@ -103,10 +327,7 @@ pub async fn register_db(ctx: &SessionContext, db_config: &DbConfig) -> Result<(
                     .ok_or(Report::msg("missing table name"))?;
                 info!("...handling table \"{catalog_name}.{schema_name}.{table_name}\"");
 
-                let p = tables_url
-                    .join(&format!("{catalog_name}/"))?
-                    .join(&format!("{schema_name}/"))?
-                    .join(&format!("{table_name}/"))?;
+                let p = table_dir_url(&db_config.path, catalog_name, schema_name, table_name)?;
 
                 let table_url = ListingTableUrl::parse(p)?;
                 debug!("...table url: {table_url:?}");
@@ -127,19 +348,7 @@ pub async fn register_db(ctx: &SessionContext, db_config: &DbConfig) -> Result<(
                     )))?;
 
                 info!("...detected format: {extension}");
-                let (file_format, file_extension) = detect_format(extension)?;
-
-                let listing_options =
-                    ListingOptions::new(file_format).with_file_extension(file_extension);
-                // Resolve the schema
-                let resolved_schema = listing_options
-                    .infer_schema(&ctx.state(), &table_url)
-                    .await?;
-                let config = ListingTableConfig::new(table_url)
-                    .with_listing_options(listing_options)
-                    .with_schema(resolved_schema);
-                // Create a new TableProvider
-                let provider = Arc::new(ListingTable::try_new(config)?);
+                let provider = listing_table(ctx, table_url, extension).await?;
                 info!("...table registered");
                 schema_provider.register_table(table_name.to_string(), provider)?;
             }
@@ -149,6 +358,372 @@ pub async fn register_db(ctx: &SessionContext, db_config: &DbConfig) -> Result<(
     Ok(())
 }
 
+/// The parts of a workspace that are persisted to the database's `catalog.json` when
+/// `persist_catalog` is enabled
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PersistedCatalog {
+    /// Stores passed with `--object-store`
+    #[serde(default)]
+    pub object_stores: Vec<ObjectStoreArg>,
+    /// External tables and views in the order they were created, so that views are restored
+    /// after the tables they read
+    #[serde(default)]
+    pub relations: Vec<PersistedRelation>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RelationKind {
+    Table,
+    View,
+}
+
+/// A table or view along with the statement that created it
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PersistedRelation {
+    pub kind: RelationKind,
+    /// Fully qualified name, `catalog.schema.name`
+    pub name: String,
+    pub sql: String,
+}
+
+impl PersistedCatalog {
+    async fn read(store: &dyn ObjectStore, path: &object_store::path::Path) -> Result<Self> {
+        match store.get(path).await {
+            Ok(result) => Ok(serde_json::from_slice(&result.bytes().await?)?),
+            Err(object_store::Error::NotFound { .. }) => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Apply `plan`, a DDL statement executed in `state`, returning whether anything changed
+    fn apply(&mut self, state: &SessionState, plan: &LogicalPlan) -> bool {
+        let LogicalPlan::Ddl(ddl) = plan else {
+            return false;
+        };
+        let catalog_options = &state.config_options().catalog;
+        let resolve = |name: &TableReference| {
+            name.clone()
+                .resolve(
+                    &catalog_options.default_catalog,
+                    &catalog_options.default_schema,
+                )
+                .to_string()
+        };
+        let (kind, name, sql, replace) = match ddl {
+            DdlStatement::CreateExternalTable(create) => {
+                let name = resolve(&create.name);
+                // The catalog file is stored in plaintext, so credentials have to be secrets
+                if let Some(key) = plaintext_credential(&create.options) {
+                    warn!(
+                        "{name} isn't persisted because its '{key}' option isn't a `secret://` reference"
+                    );
+                    return !create.if_not_exists && self.remove(&name);
+                }
+                (
+                    RelationKind::Table,
+                    name,
+                    create.definition.clone(),
+                    !create.if_not_exists,
+                )
+            }
+            DdlStatement::CreateView(create) if !create.temporary => (
+                RelationKind::View,
+                resolve(&create.name),
+                create.definition.clone(),
+                true,
+            ),
+            DdlStatement::DropTable(drop) => return self.remove(&resolve(&drop.name)),
+            DdlStatement::DropView(drop) => return self.remove(&resolve(&drop.name)),
+            DdlStatement::DropCatalogSchema(drop) => {
+                let (catalog, schema) = match &drop.name {
+                    SchemaReference::Bare { schema } => {
+                        (catalog_options.default_catalog.as_str(), schema.as_ref())
+                    }
+                    SchemaReference::Full { catalog, schema } => {
+                        (catalog.as_ref(), schema.as_ref())
+                    }
+                };
+                let prefix = format!("{catalog}.{schema}.");
+                let before = self.relations.len();
+                self.relations.retain(|r| !r.name.starts_with(&prefix));
+                return self.relations.len() != before;
+            }
+            _ => return false,
+        };
+        let Some(sql) = sql else {
+            return false;
+        };
+        if self.relations.iter().any(|r| r.name == name) {
+            if !replace {
+                return false;
+            }
+            self.remove(&name);
+        }
+        self.relations.push(PersistedRelation { kind, name, sql });
+        true
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let before = self.relations.len();
+        self.relations.retain(|r| r.name != name);
+        self.relations.len() != before
+    }
+}
+
+/// The key of the first option in `options` that looks like a credential, such as
+/// `aws.secret_access_key`, and isn't a `secret://` reference
+fn plaintext_credential(options: &HashMap<String, String>) -> Option<&str> {
+    const CREDENTIAL_KEYS: [&str; 6] = [
+        "secret",
+        "password",
+        "token",
+        "access_key",
+        "account_key",
+        "credential",
+    ];
+    options
+        .iter()
+        .find(|(key, value)| {
+            let key = key.to_lowercase();
+            CREDENTIAL_KEYS.iter().any(|part| key.contains(part)) && !is_secret_ref(value)
+        })
+        .map(|(key, _)| key.as_str())
+}
+
+/// The url of the catalog file of the database at `db_config`
+fn catalog_url(db_config: &DbConfig) -> Result<Url> {
+    Ok(db_config.path.join(CATALOG_FILE)?)
+}
+
+/// The object store and path of the file at `url`
+fn store_and_path(
+    state: &SessionState,
+    url: &Url,
+) -> Result<(Arc<dyn ObjectStore>, object_store::path::Path)> {
+    let listing_url = ListingTableUrl::parse(url.as_str())?;
+    let store = state
+        .runtime_env()
+        .object_store(listing_url.object_store())?;
+    let path = object_store::path::Path::from_url_path(url.path())?;
+    Ok((store, path))
+}
+
+/// Saves the external tables and views created by a session to the database's catalog file
+#[derive(Debug)]
+pub struct CatalogStore {
+    catalog_url: Url,
+    /// Held while the file is read and written so that concurrent statements don't overwrite
+    /// each other's changes
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl CatalogStore {
+    pub fn try_new(db_config: &DbConfig) -> Result<Self> {
+        Ok(Self {
+            catalog_url: catalog_url(db_config)?,
+            lock: tokio::sync::Mutex::new(()),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl DdlListener for CatalogStore {
+    async fn on_ddl(&self, state: &SessionState, plan: &LogicalPlan) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let (store, path) = store_and_path(state, &self.catalog_url)?;
+        let mut catalog = PersistedCatalog::read(store.as_ref(), &path).await?;
+        if catalog.apply(state, plan) {
+            debug!("persisting catalog to {}", self.catalog_url);
+            // Written next to the catalog and renamed over it, so that a crash mid-write can't
+            // leave it truncated
+            static WRITES: AtomicU64 = AtomicU64::new(0);
+            let tmp = object_store::path::Path::from(format!(
+                "{path}.{}.{}.tmp",
+                std::process::id(),
+                WRITES.fetch_add(1, Ordering::Relaxed)
+            ));
+            store
+                .put(&tmp, serde_json::to_vec_pretty(&catalog)?.into())
+                .await?;
+            if let Err(e) = store.rename(&tmp, &path).await {
+                let _ = store.delete(&tmp).await;
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Save the external tables and views created with `builder`'s session to the catalog of the
+/// database at `db_config`, if `persist_catalog` is enabled
+pub fn add_catalog_persistence(
+    builder: &mut DftSessionStateBuilder,
+    db_config: &DbConfig,
+) -> Result<()> {
+    if db_config.persist_catalog {
+        builder.add_ddl_listener(Arc::new(CatalogStore::try_new(db_config)?));
+    }
+    Ok(())
+}
+
+/// Create the external tables and views persisted in the catalog of the database at
+/// `db_config`, if `persist_catalog` is enabled. Ones that already exist, for example because
+/// they are also in the DDL file, are skipped, and ones that can't be read or fail are logged
+/// and skipped.
+pub async fn restore_catalog(ctx: &SessionContext, db_config: &DbConfig) -> Result<()> {
+    if !db_config.persist_catalog {
+        return Ok(());
+    }
+    let state = ctx.state();
+    let (store, path) = store_and_path(&state, &catalog_url(db_config)?)?;
+    let relations = match read_relations(store.as_ref(), &path).await {
+        Ok(relations) => relations,
+        Err(e) => {
+            error!("Error reading the persisted catalog {path}: {e}");
+            return Ok(());
+        }
+    };
+    info!("restoring {} persisted relations", relations.len());
+    for relation in relations {
+        let relation: PersistedRelation = match serde_json::from_value(relation) {
+            Ok(relation) => relation,
+            Err(e) => {
+                error!("Error reading a persisted relation: {e}");
+                continue;
+            }
+        };
+        // The name was resolved when the relation was created, so it isn't parsed again in case
+        // it isn't lowercase
+        let mut parts = relation.name.splitn(3, '.');
+        let reference = match (parts.next(), parts.next(), parts.next()) {
+            (Some(catalog), Some(schema), Some(table)) => {
+                TableReference::full(catalog, schema, table)
+            }
+            _ => TableReference::from(relation.name.as_str()),
+        };
+        match ctx.table_exist(reference) {
+            Ok(false) => {}
+            Ok(true) => {
+                debug!("...{} already exists", relation.name);
+                continue;
+            }
+            Err(e) => {
+                error!("Error restoring {:?} {}: {e}", relation.kind, relation.name);
+                continue;
+            }
+        }
+        match ctx.sql(&relation.sql).await {
+            Ok(_) => info!("...restored {:?} {}", relation.kind, relation.name),
+            Err(e) => error!("Error restoring {:?} {}: {e}", relation.kind, relation.name),
+        }
+    }
+    Ok(())
+}
+
+/// The relations in the catalog file at `path`, each left as JSON so that one that can't be
+/// read, for example because it was written by another version, doesn't stop the others from
+/// being restored
+async fn read_relations(
+    store: &dyn ObjectStore,
+    path: &object_store::path::Path,
+) -> Result<Vec<serde_json::Value>> {
+    #[derive(Deserialize)]
+    struct Relations {
+        #[serde(default)]
+        relations: Vec<serde_json::Value>,
+    }
+    match store.get(path).await {
+        Ok(result) => Ok(serde_json::from_slice::<Relations>(&result.bytes().await?)?.relations),
+        Err(object_store::Error::NotFound { .. }) => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The directory the `system` tables are persisted to, if `persist_system_tables` is enabled.
+/// Only databases in a local directory can persist them.
+fn system_tables_dir(db_config: &DbConfig) -> Result<Option<PathBuf>> {
+    if !db_config.persist_system_tables {
+        return Ok(None);
+    }
+    match db_config.path.join(SYSTEM_TABLES_DIR)?.to_file_path() {
+        Ok(dir) => Ok(Some(dir)),
+        Err(_) => {
+            warn!("System tables are only persisted for databases in a local directory");
+            Ok(None)
+        }
+    }
+}
+
+/// Load the query history saved by the previous run into `tables`, if `persist_system_tables`
+/// is enabled. Histories that can't be read, for example because they were saved by an older
+/// version, are logged and skipped.
+pub fn restore_system_tables(tables: &SystemTables, db_config: &DbConfig) -> Result<()> {
+    if let Some(dir) = system_tables_dir(db_config)? {
+        match tables.load(&dir) {
+            Ok(loaded) => info!("restored {loaded} queries to system.queries"),
+            Err(e) => error!("Error restoring system tables from {}: {e}", dir.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Save the query history of `tables` to the database at `db_config`, if
+/// `persist_system_tables` is enabled
+pub fn save_system_tables(tables: &SystemTables, db_config: &DbConfig) -> Result<()> {
+    if let Some(dir) = system_tables_dir(db_config)? {
+        debug!("saving system tables to {}", dir.display());
+        tables.save(&dir)?;
+    }
+    Ok(())
+}
+
+/// Combine `args`, the stores passed with `--object-store`, with the stores persisted in the
+/// catalog of the database at `db_config`, saving any new ones. Only databases in a local
+/// directory can persist object stores, as the stores are needed to read the others.
+pub fn persist_object_stores(
+    db_config: &DbConfig,
+    args: Vec<ObjectStoreArg>,
+) -> Result<Vec<ObjectStoreArg>> {
+    if !db_config.persist_catalog {
+        return Ok(args);
+    }
+    let Ok(path) = catalog_url(db_config)?.to_file_path() else {
+        if !args.is_empty() {
+            warn!("Object stores are only persisted for databases in a local directory");
+        }
+        return Ok(args);
+    };
+    let mut catalog: PersistedCatalog = match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => PersistedCatalog::default(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut changed = false;
+    for arg in args {
+        match catalog.object_stores.iter_mut().find(|s| s.url == arg.url) {
+            Some(existing) if *existing == arg => {}
+            Some(existing) => {
+                *existing = arg;
+                changed = true;
+            }
+            None => {
+                catalog.object_stores.push(arg);
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        let parent = path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+        // Written to a temporary file that replaces the catalog, see `CatalogStore`
+        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+        tmp.write_all(&serde_json::to_vec_pretty(&catalog)?)?;
+        tmp.persist(&path)?;
+    }
+    Ok(catalog.object_stores)
+}
+
 #[cfg(test)]
 mod test {
     use datafusion::{
@@ -157,13 +732,60 @@ mod test {
         prelude::{SessionConfig, SessionContext},
     };
 
-    use crate::{config::DbConfig, db::register_db};
+    use crate::{
+        config::DbConfig,
+        db::{register_db, restore_catalog, PersistedCatalog},
+    };
 
     fn setup() -> SessionContext {
         let config = SessionConfig::default().with_information_schema(true);
         SessionContext::new_with_config(config)
     }
 
+    #[tokio::test]
+    async fn test_persisted_catalog_refuses_plaintext_credentials() {
+        let ctx = setup();
+        let state = ctx.state();
+        let mut catalog = PersistedCatalog::default();
+        let create = |secret: &str| {
+            format!("CREATE EXTERNAL TABLE t STORED AS PARQUET LOCATION 's3://bucket/t/' OPTIONS ('aws.secret_access_key' '{secret}')")
+        };
+
+        let plan = state
+            .create_logical_plan(&create("secret://aws"))
+            .await
+            .unwrap();
+        assert!(catalog.apply(&state, &plan));
+        assert_eq!(catalog.relations.len(), 1);
+
+        // Replacing the table with a plaintext credential drops it from the catalog instead
+        let plan = state.create_logical_plan(&create("hunter2")).await.unwrap();
+        assert!(catalog.apply(&state, &plan));
+        assert!(catalog.relations.is_empty());
+        assert!(!catalog.apply(&state, &plan));
+    }
+
+    #[tokio::test]
+    async fn test_restore_catalog_skips_bad_relations() {
+        let ctx = setup();
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = r#"{"relations": [
+            {"kind": "table", "name": "datafusion.public.missing_sql"},
+            {"kind": "view", "name": "datafusion.public.fails", "sql": "CREATE VIEW fails AS SELECT * FROM missing"},
+            {"kind": "view", "name": "datafusion.public.restored", "sql": "CREATE VIEW restored AS SELECT 1 AS a"}
+        ]}"#;
+        std::fs::write(dir.path().join("catalog.json"), catalog).unwrap();
+        let db_config = DbConfig {
+            path: url::Url::from_directory_path(dir.path()).unwrap(),
+            persist_catalog: true,
+            ..Default::default()
+        };
+
+        restore_catalog(&ctx, &db_config).await.unwrap();
+        assert!(ctx.table_exist("restored").unwrap());
+        assert!(!ctx.table_exist("fails").unwrap());
+    }
+
     #[tokio::test]
     async fn test_register_db_no_tables() {
         let ctx = setup();
@@ -171,7 +793,12 @@ mod test {
         let db_path = dir.path().join("db");
         let path = format!("file://{}/", db_path.to_str().unwrap());
         let db_url = url::Url::parse(&path).unwrap();
-        let config = DbConfig { path: db_url };
+        let config = DbConfig {
+            path: db_url,
+            query_log: false,
+            persist_catalog: false,
+            persist_system_tables: false,
+        };
 
         register_db(&ctx, &config).await.unwrap();
 
@@ -207,7 +834,12 @@ mod test {
         let db_path = dir.path().join("db");
         let path = format!("file://{}/", db_path.to_str().unwrap());
         let db_url = url::Url::parse(&path).unwrap();
-        let config = DbConfig { path: db_url };
+        let config = DbConfig {
+            path: db_url,
+            query_log: false,
+            persist_catalog: false,
+            persist_system_tables: false,
+        };
         let data_path = db_path.join("tables").join("dft").join("stuff").join("hi");
 
         let df = ctx.sql("SELECT 1").await.unwrap();
@@ -259,7 +891,12 @@ mod test {
         let db_path = dir.path().join("db");
         let path = format!("file://{}/", db_path.to_str().unwrap());
         let db_url = url::Url::parse(&path).unwrap();
-        let config = DbConfig { path: db_url };
+        let config = DbConfig {
+            path: db_url,
+            query_log: false,
+            persist_catalog: false,
+            persist_system_tables: false,
+        };
         let data_1_path = db_path.join("tables").join("dft").join("stuff").join("hi");
         let data_2_path = db_path.join("tables").join("dft").join("stuff").join("bye");
 
@@ -318,7 +955,12 @@ mod test {
         let db_path = dir.path().join("db");
         let path = format!("file://{}/", db_path.to_str().unwrap());
         let db_url = url::Url::parse(&path).unwrap();
-        let config = DbConfig { path: db_url };
+        let config = DbConfig {
+            path: db_url,
+            query_log: false,
+            persist_catalog: false,
+            persist_system_tables: false,
+        };
         let data_1_path = db_path.join("tables").join("dft").join("stuff").join("hi");
         let data_2_path = db_path
             .join("tables")
@@ -381,7 +1023,12 @@ mod test {
         let db_path = dir.path().join("db");
         let path = format!("file://{}/", db_path.to_str().unwrap());
         let db_url = url::Url::parse(&path).unwrap();
-        let config = DbConfig { path: db_url };
+        let config = DbConfig {
+            path: db_url,
+            query_log: false,
+            persist_catalog: false,
+            persist_system_tables: false,
+        };
         let data_1_path = db_path.join("tables").join("dft2").join("stuff").join("hi");
         let data_2_path = db_path
             .join("tables")
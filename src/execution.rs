@@ -17,11 +17,44 @@
 
 pub use datafusion_app::{collect_plan_io_stats, ExecutionStats};
 
+#[cfg(feature = "http")]
+use crate::server::jobs::QueryJobs;
+#[cfg(any(feature = "flightsql", feature = "http"))]
+use crate::server::query_log::QueryLog;
 use color_eyre::Result;
 use datafusion::prelude::*;
 #[cfg(feature = "flightsql")]
 use datafusion_app::flightsql::{FlightSQLClient, FlightSQLContext};
 use datafusion_app::{local::ExecutionContext, ExecOptions, ExecResult};
+#[cfg(feature = "http")]
+use url::Url;
+#[cfg(any(feature = "flightsql", feature = "http"))]
+use {
+    datafusion::error::DataFusionError,
+    std::sync::{Arc, PoisonError, RwLock},
+};
+
+/// The errors of the DDL statements last executed, shared by the clones so that reloading DDL
+/// in one server is seen by the readiness checks of another
+#[cfg(any(feature = "flightsql", feature = "http"))]
+#[derive(Clone, Debug, Default)]
+pub struct DdlErrors(Arc<RwLock<Option<Arc<[String]>>>>);
+
+#[cfg(any(feature = "flightsql", feature = "http"))]
+impl DdlErrors {
+    /// The errors, or `None` if DDL wasn't executed
+    pub fn get(&self) -> Option<Arc<[String]>> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set(&self, errors: &[DataFusionError]) {
+        let errors = errors.iter().map(|e| e.to_string()).collect();
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Some(errors);
+    }
+}
 
 /// Provides all core execution functionality for execution queries from either a local
 /// `SessionContext` or a remote `FlightSQL` service
@@ -30,6 +63,17 @@ pub struct AppExecution {
     local: ExecutionContext,
     #[cfg(feature = "flightsql")]
     flightsql: FlightSQLContext,
+    #[cfg(any(feature = "flightsql", feature = "http"))]
+    query_log: Option<QueryLog>,
+    /// Queries running in the background for the HTTP server, shared by all clones
+    #[cfg(feature = "http")]
+    query_jobs: QueryJobs,
+    /// Database that tables uploaded to the HTTP server are written to
+    #[cfg(feature = "http")]
+    db_path: Option<Url>,
+    /// Errors of the DDL statements last executed, shared by all clones
+    #[cfg(any(feature = "flightsql", feature = "http"))]
+    ddl_errors: DdlErrors,
 }
 
 impl AppExecution {
@@ -38,6 +82,14 @@ impl AppExecution {
             local,
             #[cfg(feature = "flightsql")]
             flightsql: FlightSQLContext::default(),
+            #[cfg(any(feature = "flightsql", feature = "http"))]
+            query_log: None,
+            #[cfg(feature = "http")]
+            query_jobs: QueryJobs::default(),
+            #[cfg(feature = "http")]
+            db_path: None,
+            #[cfg(any(feature = "flightsql", feature = "http"))]
+            ddl_errors: DdlErrors::default(),
         }
     }
 
@@ -49,6 +101,15 @@ impl AppExecution {
         self.local.session_ctx()
     }
 
+    /// Return a copy whose local context has its own copy of the session state, so that `SET`
+    /// and `PREPARE` statements, and the views created, in it don't apply to this execution
+    #[cfg(feature = "http")]
+    pub fn fork_session(&self) -> Self {
+        let mut forked = self.clone();
+        forked.local = self.local.fork_session_with_own_views();
+        forked
+    }
+
     #[cfg(feature = "flightsql")]
     pub fn flightsql_client(&self) -> &FlightSQLClient {
         self.flightsql.client()
@@ -64,6 +125,45 @@ impl AppExecution {
         self.flightsql = flightsql_ctx;
     }
 
+    /// The log that servers record executed statements in, if enabled
+    #[cfg(any(feature = "flightsql", feature = "http"))]
+    pub fn query_log(&self) -> Option<&QueryLog> {
+        self.query_log.as_ref()
+    }
+
+    #[cfg(any(feature = "flightsql", feature = "http"))]
+    pub fn with_query_log(&mut self, query_log: QueryLog) {
+        self.query_log = Some(query_log);
+    }
+
+    /// The queries submitted to the HTTP server's asynchronous query API
+    #[cfg(feature = "http")]
+    pub fn query_jobs(&self) -> &QueryJobs {
+        &self.query_jobs
+    }
+
+    /// The database path uploaded tables are stored in, uploads are rejected when unset
+    #[cfg(feature = "http")]
+    pub fn db_path(&self) -> Option<&Url> {
+        self.db_path.as_ref()
+    }
+
+    #[cfg(feature = "http")]
+    pub fn with_db_path(&mut self, db_path: Url) {
+        self.db_path = Some(db_path);
+    }
+
+    /// The errors of the DDL statements executed at startup, or when DDL was last reloaded
+    #[cfg(any(feature = "flightsql", feature = "http"))]
+    pub fn ddl_errors(&self) -> &DdlErrors {
+        &self.ddl_errors
+    }
+
+    #[cfg(any(feature = "flightsql", feature = "http"))]
+    pub fn with_ddl_errors(&mut self, errors: Vec<DataFusionError>) {
+        self.ddl_errors.set(&errors);
+    }
+
     pub async fn execute_sql_with_opts(&self, sql: &str, opts: ExecOptions) -> Result<ExecResult> {
         #[cfg(feature = "flightsql")]
         if opts.flightsql {
@@ -17,6 +17,7 @@
 
 pub mod args;
 pub mod cli;
+pub mod completions;
 pub mod config;
 pub mod db;
 pub mod execution;
@@ -25,6 +26,7 @@ pub mod server;
 #[cfg(feature = "tui")]
 pub mod telemetry;
 pub mod test_utils;
+pub mod tpcds;
 pub mod tpch;
 #[cfg(feature = "tui")]
 pub mod tui;
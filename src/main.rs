@@ -16,20 +16,36 @@
 // under the License.
 
 use clap::Parser;
-#[cfg(not(feature = "tui"))]
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use datafusion_dft::args::Command;
+use datafusion_app::config::merge_configs;
+use datafusion_dft::args::{Command, ErrorFormat, TpchCommand};
 #[cfg(any(feature = "flightsql", feature = "http"))]
 use datafusion_dft::server;
 #[cfg(feature = "tui")]
 use datafusion_dft::tui;
-use datafusion_dft::{args::DftArgs, cli, config::create_config, tpch};
+use datafusion_dft::{
+    args::DftArgs,
+    cli, completions,
+    config::{add_object_stores, create_config_with_profile, ObjectStoreArg},
+    db, tpcds, tpch,
+};
 #[cfg(feature = "http")]
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 fn main() -> Result<()> {
-    let cli = DftArgs::parse();
+    let mut cli = DftArgs::parse();
+    let error_format = cli.error_format;
+    let globs = match cli.expand_file_globs() {
+        Ok(globs) => globs,
+        Err(e) => return exit_with_error(eyre!(e), error_format),
+    };
+    for (pattern, files) in globs {
+        eprintln!("Resolved {} file(s) from '{pattern}':", files.len());
+        for file in files {
+            eprintln!("  {}", file.display());
+        }
+    }
 
     // With Runtimes configured correctly the main Tokio runtime should only be used for network
     // IO, in which a single thread should be sufficient.
@@ -41,7 +57,23 @@ fn main() -> Result<()> {
         .build()?;
 
     let entry_point = app_entry_point(cli);
-    runtime.block_on(entry_point)
+    match runtime.block_on(entry_point) {
+        Err(e) => exit_with_error(e, error_format),
+        result => result,
+    }
+}
+
+/// Return the error to be printed by `color_eyre`, or with `--error-format json` write it to
+/// stderr as JSON and exit
+fn exit_with_error(error: color_eyre::Report, error_format: ErrorFormat) -> Result<()> {
+    if error_format != ErrorFormat::Json {
+        return Err(error);
+    }
+    // Errors from statements have already been written with more context
+    if !error.is::<cli::ReportedError>() {
+        eprintln!("{}", cli::error_to_json(&error, None, None));
+    }
+    std::process::exit(1)
 }
 
 // TODO: FlightSQL should use tracing
@@ -50,21 +82,51 @@ fn should_init_env_logger(cli: &DftArgs) -> bool {
     if let Some(Command::ServeFlightSql { .. }) = cli.command {
         return true;
     }
+    #[cfg(all(feature = "flightsql", feature = "http"))]
+    if let Some(Command::Serve { .. }) = cli.command {
+        return true;
+    }
 
-    if let Some(Command::GenerateTpch { .. }) = cli.command {
+    if let Some(Command::GenerateTpch { .. } | Command::GenerateTpcds { .. }) = cli.command {
+        return true;
+    }
+    if is_cli_subcommand(cli) {
         return true;
     }
-    if !cli.files.is_empty() || !cli.commands.is_empty() {
+    if !cli.files.is_empty() || !cli.commands.is_empty() || cli.run_plan.is_some() {
         return true;
     }
     false
 }
 
+/// Subcommands that run against the local execution context through the CLI
+fn is_cli_subcommand(cli: &DftArgs) -> bool {
+    matches!(
+        cli.command,
+        Some(
+            Command::Analyze { .. }
+                | Command::Catalog { .. }
+                | Command::Schema { .. }
+                | Command::Convert { .. }
+                | Command::Cp { .. }
+                | Command::Tpch { .. }
+                | Command::Replay { .. }
+        )
+    )
+}
+
 async fn app_entry_point(cli: DftArgs) -> Result<()> {
     if should_init_env_logger(&cli) {
         env_logger::init();
     }
-    let cfg = create_config(cli.config_path(), cli.set.as_deref().unwrap_or_default());
+    let mut cfg = create_config_with_profile(
+        cli.config_path(),
+        cli.profile.as_deref(),
+        cli.set.as_deref().unwrap_or_default(),
+    )
+    .map_err(|e| eyre!(e))?;
+    let object_stores = db::persist_object_stores(&cfg.db, ObjectStoreArg::from_args(&cli))?;
+    add_object_stores(&mut cfg, &object_stores).map_err(|e| eyre!(e))?;
 
     // Start tokio metrics collection for IO runtime when running servers
     #[cfg(any(feature = "flightsql", feature = "http"))]
@@ -77,6 +139,8 @@ async fn app_entry_point(cli: DftArgs) -> Result<()> {
             Some(Command::ServeHttp { .. }) => true,
             #[cfg(feature = "flightsql")]
             Some(Command::ServeFlightSql { .. }) => true,
+            #[cfg(all(feature = "flightsql", feature = "http"))]
+            Some(Command::Serve { .. }) => true,
             _ => false,
         };
 
@@ -99,6 +163,50 @@ async fn app_entry_point(cli: DftArgs) -> Result<()> {
         return Ok(());
     }
 
+    if let Some(Command::Fmt {
+        files,
+        check,
+        write,
+        max_width,
+        keyword_case,
+    }) = &cli.command
+    {
+        let execution = merge_configs(cfg.shared.clone(), cfg.cli.execution.clone());
+        let options = cli::FormatOptions {
+            max_width: *max_width,
+            keyword_case: *keyword_case,
+            dialect: execution.dialect,
+        };
+        cli::format_files(files, *check, *write, options)?;
+        return Ok(());
+    }
+
+    if let Some(Command::Completions { shell, tables }) = &cli.command {
+        completions::generate(&cfg, *shell, *tables, &mut std::io::stdout())?;
+        return Ok(());
+    }
+
+    if let Some(Command::GenerateTpcds {
+        scale_factor,
+        toolkit,
+    }) = &cli.command
+    {
+        tpcds::generate(cfg.clone(), *scale_factor, toolkit.clone()).await?;
+        return Ok(());
+    }
+
+    if let Some(Command::Tpch {
+        command:
+            TpchCommand::Run {
+                scale_factor: Some(scale_factor),
+                format,
+                ..
+            },
+    }) = &cli.command
+    {
+        tpch::generate(cfg.clone(), *scale_factor, format.clone()).await?;
+    }
+
     #[cfg(feature = "flightsql")]
     {
         if matches!(cli.command, Some(Command::FlightSql { .. })) {
@@ -110,6 +218,12 @@ async fn app_entry_point(cli: DftArgs) -> Result<()> {
         }
     }
 
+    #[cfg(all(feature = "flightsql", feature = "http"))]
+    if let Some(Command::Serve { .. }) = cli.command {
+        server::serve::try_run(cli.clone(), cfg.clone()).await?;
+        return Ok(());
+    }
+
     #[cfg(feature = "http")]
     {
         if let Some(Command::ServeHttp { .. }) = cli.command {
@@ -130,7 +244,11 @@ async fn app_entry_point(cli: DftArgs) -> Result<()> {
         }
     }
 
-    if !cli.files.is_empty() || !cli.commands.is_empty() {
+    if !cli.files.is_empty()
+        || !cli.commands.is_empty()
+        || cli.run_plan.is_some()
+        || is_cli_subcommand(&cli)
+    {
         cli::try_run(cli, cfg).await?;
     } else {
         #[cfg(feature = "tui")]
@@ -0,0 +1,149 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Admission control for statements executed by the FlightSQL server
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tonic::Status;
+
+/// Limits how many statements execute at once. Statements over the limit wait in a queue of
+/// bounded depth for up to `queue_timeout` before they are rejected.
+#[derive(Clone, Debug)]
+pub struct AdmissionController {
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    queue_depth: usize,
+    queue_timeout: Duration,
+}
+
+impl AdmissionController {
+    pub fn new(max_concurrent: usize, queue_depth: usize, queue_timeout: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            queue_depth,
+            queue_timeout,
+        }
+    }
+
+    /// Wait for a statement to be allowed to execute. The statement may execute for as long as
+    /// the returned permit is held.
+    pub async fn admit(&self) -> Result<OwnedSemaphorePermit, Status> {
+        if let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() {
+            return Ok(permit);
+        }
+        // Leaves the queue when dropped, including when the request is cancelled while waiting
+        let queued = QueuedStatement::new(&self.queued);
+        if queued.position > self.queue_depth {
+            return Err(Status::resource_exhausted(
+                "Too many statements are queued, try again later",
+            ));
+        }
+        debug!("queueing statement, {} statements queued", queued.position);
+        let permit = tokio::time::timeout(
+            self.queue_timeout,
+            Arc::clone(&self.permits).acquire_owned(),
+        )
+        .await;
+        drop(queued);
+        match permit {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(Status::unavailable("The server is shutting down")),
+            Err(_) => Err(Status::resource_exhausted(format!(
+                "Statement was queued for longer than {:?}, try again later",
+                self.queue_timeout
+            ))),
+        }
+    }
+}
+
+/// A statement counted in the queue until it's dropped
+struct QueuedStatement<'a> {
+    queued: &'a AtomicUsize,
+    /// Number of statements queued, including this one, when it joined the queue
+    position: usize,
+}
+
+impl<'a> QueuedStatement<'a> {
+    fn new(queued: &'a AtomicUsize) -> Self {
+        let position = queued.fetch_add(1, Ordering::SeqCst) + 1;
+        metrics::gauge!("flightsql_statement_queue_depth").set(position as f64);
+        Self { queued, position }
+    }
+}
+
+impl Drop for QueuedStatement<'_> {
+    fn drop(&mut self) {
+        let queued = self.queued.fetch_sub(1, Ordering::SeqCst) - 1;
+        metrics::gauge!("flightsql_statement_queue_depth").set(queued as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[tokio::test]
+    async fn rejects_statements_over_queue_depth() {
+        let admission = AdmissionController::new(1, 0, Duration::from_secs(1));
+        let _permit = admission.admit().await.unwrap();
+        let err = admission.admit().await.unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn queued_statement_runs_when_permit_is_released() {
+        let admission = AdmissionController::new(1, 1, Duration::from_secs(5));
+        let permit = admission.admit().await.unwrap();
+        let queued = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.admit().await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(permit);
+        assert!(queued.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancelled_statement_leaves_queue() {
+        let admission = AdmissionController::new(1, 1, Duration::from_secs(5));
+        let _permit = admission.admit().await.unwrap();
+        let queued = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.admit().await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(admission.queued.load(Ordering::SeqCst), 1);
+        queued.abort();
+        assert!(queued.await.unwrap_err().is_cancelled());
+        assert_eq!(admission.queued.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn queued_statement_times_out() {
+        let admission = AdmissionController::new(1, 1, Duration::from_millis(50));
+        let _permit = admission.admit().await.unwrap();
+        let err = admission.admit().await.unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+    }
+}
@@ -0,0 +1,330 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Authentication of requests to the FlightSQL server, with the configured credentials or a
+//! session token exchanged for them with the `Handshake` RPC
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use log::debug;
+use tower_http::validate_request::ValidateRequest;
+use uuid::Uuid;
+
+use crate::server::rate_limit::basic_auth_user;
+use crate::server::{constant_time_eq, AuthenticatedUser};
+
+/// How long a session token issued by `Handshake` is valid by default
+pub const DEFAULT_SESSION_TOKEN_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// A session token issued by the server
+#[derive(Clone, Debug)]
+struct IssuedToken {
+    expires: Instant,
+    /// The user whose credentials the token was exchanged for
+    user: Option<String>,
+}
+
+/// The session tokens issued by the server and when they expire
+#[derive(Clone, Debug)]
+pub struct SessionTokens {
+    tokens: Arc<Mutex<HashMap<String, IssuedToken>>>,
+    ttl: Duration,
+}
+
+impl SessionTokens {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            tokens: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    /// Issue a new token for `user`, valid for the configured TTL
+    pub fn issue(&self, user: Option<String>) -> String {
+        let token = Uuid::new_v4().to_string();
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.retain(|_, issued| issued.expires > now);
+        tokens.insert(
+            token.clone(),
+            IssuedToken {
+                expires: now + self.ttl,
+                user,
+            },
+        );
+        token
+    }
+
+    fn get(&self, token: &str) -> Option<IssuedToken> {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens
+            .get(token)
+            .filter(|issued| issued.expires > Instant::now())
+            .cloned()
+    }
+
+    /// Whether `token` was issued by the server and hasn't expired
+    pub fn is_valid(&self, token: &str) -> bool {
+        self.get(token).is_some()
+    }
+
+    /// The user `token` was issued to, if it's valid
+    pub fn user(&self, token: &str) -> Option<String> {
+        self.get(token)?.user
+    }
+
+    /// Invalidate every token issued so far, so clients must handshake again
+    pub fn revoke_all(&self) {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// The `authorization` header clients must send, either basic credentials or a bearer token.
+/// Clones share the value so that it can be changed while the server is running.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    expected: Arc<RwLock<HeaderValue>>,
+}
+
+impl Credentials {
+    pub fn basic(username: &str, password: &str) -> Self {
+        Self {
+            expected: Arc::new(RwLock::new(basic_header(username, password))),
+        }
+    }
+
+    pub fn bearer(token: &str) -> Self {
+        Self {
+            expected: Arc::new(RwLock::new(bearer_header(token))),
+        }
+    }
+
+    /// Replace the credentials with basic credentials. If they changed, the session tokens
+    /// issued for the old credentials are revoked.
+    pub fn set_basic(&self, username: &str, password: &str, tokens: &SessionTokens) {
+        self.set(basic_header(username, password), tokens)
+    }
+
+    /// Replace the credentials with a bearer token. If it changed, the session tokens issued so
+    /// far are revoked.
+    pub fn set_bearer(&self, token: &str, tokens: &SessionTokens) {
+        self.set(bearer_header(token), tokens)
+    }
+
+    fn set(&self, value: HeaderValue, tokens: &SessionTokens) {
+        let mut expected = self.expected.write().unwrap_or_else(|e| e.into_inner());
+        if *expected != value {
+            *expected = value;
+            tokens.revoke_all();
+        }
+    }
+
+    fn matches(&self, value: &HeaderValue) -> bool {
+        let expected = self.expected.read().unwrap_or_else(|e| e.into_inner());
+        constant_time_eq(expected.as_bytes(), value.as_bytes())
+    }
+
+    /// Whether these are basic credentials rather than a bearer token
+    pub fn is_basic(&self) -> bool {
+        self.expected
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_bytes()
+            .starts_with(b"Basic ")
+    }
+}
+
+fn basic_header(username: &str, password: &str) -> HeaderValue {
+    let encoded = STANDARD.encode(format!("{username}:{password}"));
+    HeaderValue::from_str(&format!("Basic {encoded}")).expect("base64 is a valid header value")
+}
+
+fn bearer_header(token: &str) -> HeaderValue {
+    HeaderValue::from_str(&format!("Bearer {token}")).expect("token is a valid header value")
+}
+
+/// Accepts requests with the configured credentials or a session token issued for them by
+/// `Handshake`, so that clients only need to send their credentials once
+pub struct CredentialsOrSessionToken<ResBody> {
+    credentials: Credentials,
+    tokens: SessionTokens,
+    _ty: PhantomData<fn() -> ResBody>,
+}
+
+impl<ResBody> CredentialsOrSessionToken<ResBody> {
+    pub fn new(credentials: Credentials, tokens: SessionTokens) -> Self {
+        Self {
+            credentials,
+            tokens,
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<ResBody> Clone for CredentialsOrSessionToken<ResBody> {
+    fn clone(&self) -> Self {
+        Self {
+            credentials: self.credentials.clone(),
+            tokens: self.tokens.clone(),
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<B, ResBody> ValidateRequest<B> for CredentialsOrSessionToken<ResBody>
+where
+    ResBody: Default,
+{
+    type ResponseBody = ResBody;
+
+    fn validate(&mut self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        let Some(value) = request.headers().get(header::AUTHORIZATION).cloned() else {
+            return Err(self.unauthorized());
+        };
+        let user = if self.credentials.matches(&value) {
+            basic_auth_user(request.headers())
+        } else {
+            match value.to_str().ok().and_then(|v| v.strip_prefix("Bearer ")) {
+                Some(token) => match self.tokens.get(token) {
+                    Some(issued) => issued.user,
+                    None => {
+                        debug!("rejecting request with an unknown or expired session token");
+                        return Err(self.unauthorized());
+                    }
+                },
+                None => return Err(self.unauthorized()),
+            }
+        };
+        if let Some(user) = user {
+            request.extensions_mut().insert(AuthenticatedUser(user));
+        }
+        Ok(())
+    }
+}
+
+impl<ResBody: Default> CredentialsOrSessionToken<ResBody> {
+    fn unauthorized(&self) -> Response<ResBody> {
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = StatusCode::UNAUTHORIZED;
+        if self.credentials.is_basic() {
+            res.headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_expire() {
+        let tokens = SessionTokens::new(Duration::ZERO);
+        let token = tokens.issue(None);
+        assert!(!tokens.is_valid(&token));
+
+        let tokens = SessionTokens::new(Duration::from_secs(60));
+        let token = tokens.issue(Some("user".to_string()));
+        assert!(tokens.is_valid(&token));
+        assert_eq!(tokens.user(&token).as_deref(), Some("user"));
+        assert!(!tokens.is_valid("unknown"));
+    }
+
+    #[test]
+    fn accepts_basic_credentials_and_session_tokens() {
+        let tokens = SessionTokens::new(Duration::from_secs(60));
+        let token = tokens.issue(None);
+        let credentials = Credentials::basic("user", "pass");
+        let validator = CredentialsOrSessionToken::<()>::new(credentials, tokens);
+
+        let authorized = |value: &str| {
+            let mut request = Request::builder()
+                .header(header::AUTHORIZATION, value)
+                .body(())
+                .unwrap();
+            validator.clone().validate(&mut request).is_ok()
+        };
+        assert!(authorized("Basic dXNlcjpwYXNz"));
+        assert!(authorized(&format!("Bearer {token}")));
+        assert!(!authorized("Basic dXNlcjp3cm9uZw=="));
+        assert!(!authorized("Bearer unknown"));
+    }
+
+    #[test]
+    fn adds_the_authenticated_user_to_requests() {
+        let tokens = SessionTokens::new(Duration::from_secs(60));
+        let token = tokens.issue(Some("user".to_string()));
+        let credentials = Credentials::basic("user", "pass");
+        let validator = CredentialsOrSessionToken::<()>::new(credentials, tokens);
+
+        let user = |value: &str| {
+            let mut request = Request::builder()
+                .header(header::AUTHORIZATION, value)
+                .body(())
+                .unwrap();
+            validator.clone().validate(&mut request).unwrap();
+            request.extensions().get::<AuthenticatedUser>().cloned()
+        };
+        let expected = Some(AuthenticatedUser("user".to_string()));
+        assert_eq!(user("Basic dXNlcjpwYXNz"), expected);
+        assert_eq!(user(&format!("Bearer {token}")), expected);
+    }
+
+    #[test]
+    fn credentials_can_be_replaced() {
+        let credentials = Credentials::bearer("old");
+        let validator = CredentialsOrSessionToken::<()>::new(
+            credentials.clone(),
+            SessionTokens::new(Duration::ZERO),
+        );
+        let authorized = |value: &str| {
+            let mut request = Request::builder()
+                .header(header::AUTHORIZATION, value)
+                .body(())
+                .unwrap();
+            validator.clone().validate(&mut request).is_ok()
+        };
+        assert!(authorized("Bearer old"));
+
+        credentials.set_bearer("new", &SessionTokens::new(Duration::ZERO));
+        assert!(!authorized("Bearer old"));
+        assert!(authorized("Bearer new"));
+    }
+
+    #[test]
+    fn changing_credentials_revokes_session_tokens() {
+        let tokens = SessionTokens::new(Duration::from_secs(60));
+        let credentials = Credentials::basic("user", "pass");
+        let token = tokens.issue(Some("user".to_string()));
+
+        // Setting the same credentials again, as every config reload does, keeps the sessions
+        credentials.set_basic("user", "pass", &tokens);
+        assert!(tokens.is_valid(&token));
+
+        credentials.set_basic("user", "new", &tokens);
+        assert!(!tokens.is_valid(&token));
+    }
+}
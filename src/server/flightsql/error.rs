@@ -0,0 +1,138 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Structured details attached to the errors returned by the FlightSQL server so that clients can
+//! tell, for example, a typo in their SQL from a query that failed while executing
+
+use std::collections::HashMap;
+
+use color_eyre::Report;
+use datafusion::error::DataFusionError;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// Domain of the `ErrorInfo` detail attached to errors
+pub const ERROR_DOMAIN: &str = "dft.flightsql";
+
+/// The stage of a query that failed. Used as the error class when the error itself doesn't
+/// identify a more specific cause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryStage {
+    Plan,
+    Execution,
+}
+
+/// Converts an error from DataFusion into a `Status` with an `ErrorInfo` detail. The detail's
+/// reason is the error class (`PARSE_ERROR`, `PLAN_ERROR`, `NOT_IMPLEMENTED`,
+/// `RESOURCES_EXHAUSTED` or `EXECUTION_ERROR`) and its metadata holds the query id and, when
+/// known, the line and column of the SQL the error refers to.
+pub fn datafusion_error_to_status(
+    e: &DataFusionError,
+    stage: QueryStage,
+    query_id: Option<&str>,
+) -> Status {
+    let (code, reason) = match e.find_root() {
+        DataFusionError::SQL(..) => (Code::InvalidArgument, "PARSE_ERROR"),
+        DataFusionError::Plan(_) | DataFusionError::SchemaError(..) => {
+            (Code::InvalidArgument, "PLAN_ERROR")
+        }
+        DataFusionError::NotImplemented(_) => (Code::Unimplemented, "NOT_IMPLEMENTED"),
+        DataFusionError::ResourcesExhausted(_) => (Code::ResourceExhausted, "RESOURCES_EXHAUSTED"),
+        _ => match stage {
+            QueryStage::Plan => (Code::Internal, "PLAN_ERROR"),
+            QueryStage::Execution => (Code::Internal, "EXECUTION_ERROR"),
+        },
+    };
+    let mut metadata = HashMap::new();
+    if let Some(query_id) = query_id {
+        metadata.insert("query_id".to_string(), query_id.to_string());
+    }
+    if let Some((line, column)) = sql_position(e) {
+        metadata.insert("line".to_string(), line.to_string());
+        metadata.insert("column".to_string(), column.to_string());
+    }
+    Status::with_error_details(
+        code,
+        e.to_string(),
+        ErrorDetails::with_error_info(reason, ERROR_DOMAIN, metadata),
+    )
+}
+
+/// Like [`datafusion_error_to_status`] for the errors of `ExecutionContext`, which wrap the
+/// DataFusion error in a [`Report`]
+pub fn report_to_status(e: &Report, stage: QueryStage, query_id: Option<&str>) -> Status {
+    match e.downcast_ref::<DataFusionError>() {
+        Some(e) => datafusion_error_to_status(e, stage, query_id),
+        None => Status::internal(e.to_string()),
+    }
+}
+
+/// The type of error `status` represents, used to label the `flightsql_errors` metric: the
+/// reason of its `ErrorInfo` detail or otherwise its code
+pub fn error_type(status: &Status) -> String {
+    match status.get_details_error_info() {
+        Some(info) => info.reason,
+        None => format!("{:?}", status.code()),
+    }
+}
+
+/// The line and column of the SQL that an error refers to, taken from its diagnostic when spans
+/// are collected and otherwise from the position the SQL parser includes in its messages
+fn sql_position(e: &DataFusionError) -> Option<(u64, u64)> {
+    if let Some(span) = e.diagnostic().and_then(|diagnostic| diagnostic.span) {
+        return Some((span.start.line, span.start.column));
+    }
+    let message = e.to_string();
+    let (_, position) = message.rsplit_once("Line: ")?;
+    let (line, position) = position.split_once(", Column: ")?;
+    let column: String = position
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::sql::parser::DFParser;
+    use datafusion::sql::sqlparser::dialect::GenericDialect;
+
+    #[test]
+    fn parse_errors_include_position() {
+        let e = DFParser::parse_sql_with_dialect("SELEC 1", &GenericDialect {}).unwrap_err();
+        let status = datafusion_error_to_status(&e, QueryStage::Plan, Some("q1"));
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let info = status.get_details_error_info().unwrap();
+        assert_eq!(info.reason, "PARSE_ERROR");
+        assert_eq!(info.domain, ERROR_DOMAIN);
+        assert_eq!(info.metadata["query_id"], "q1");
+        assert_eq!(info.metadata["line"], "1");
+    }
+
+    #[test]
+    fn execution_errors_default_to_stage() {
+        let e = DataFusionError::Execution("boom".to_string());
+        let status = datafusion_error_to_status(&e, QueryStage::Execution, None);
+        assert_eq!(status.code(), Code::Internal);
+        let info = status.get_details_error_info().unwrap();
+        assert_eq!(info.reason, "EXECUTION_ERROR");
+        assert!(!info.metadata.contains_key("line"));
+        assert_eq!(error_type(&status), "EXECUTION_ERROR");
+        assert_eq!(error_type(&Status::cancelled("")), "Cancelled");
+    }
+}
@@ -15,45 +15,178 @@
 // specific language governing permissions and limitations
 // under the License.
 
+pub mod admission;
+pub mod auth;
+pub mod error;
+pub mod rate_limit;
+pub mod reflection;
 pub mod service;
+pub mod spill;
+pub mod tickets;
 
 use crate::args::{Command, DftArgs};
-use crate::config::AppConfig;
-use crate::db::register_db;
+use crate::config::{try_create_config_with_profile, AppConfig};
+use crate::db::{
+    add_catalog_persistence, register_db, restore_catalog, restore_system_tables,
+    save_system_tables,
+};
 use crate::execution::AppExecution;
+use crate::server::query_log::QueryLog;
+use crate::server::tls;
+use admission::AdmissionController;
+use auth::{Credentials, CredentialsOrSessionToken, SessionTokens};
 use color_eyre::{eyre::eyre, Result};
-use datafusion_app::config::merge_configs;
 use datafusion_app::extensions::DftSessionStateBuilder;
 use datafusion_app::local::ExecutionContext;
-use log::info;
+use futures::Stream;
+use log::{info, warn};
+use rate_limit::RateLimitLayer;
 use service::FlightSqlServiceImpl;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::time::Duration;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
+use tonic::transport::server::Connected;
 use tonic::transport::Server;
 #[cfg(feature = "flightsql")]
 use tower_http::validate_request::ValidateRequestHeaderLayer;
 
-use super::try_start_metrics_server;
+use super::{server_execution_config, shutdown_signal, try_start_metrics_server, MetricsServer};
 
 const DEFAULT_TIMEOUT_SECONDS: u64 = 60;
 
+/// How often the config file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long after their tickets expire spilled results left in the spill directory are removed
+/// at startup
+const SPILL_CLEANUP_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// Settings of a running server that can be changed without restarting it
+#[derive(Clone)]
+pub struct ReloadableSettings {
+    flightsql: FlightSqlServiceImpl,
+    credentials: Option<Credentials>,
+    /// Session tokens issued by `Handshake`, revoked when the credentials change
+    session_tokens: SessionTokens,
+    rate_limit: RateLimitLayer,
+}
+
+impl ReloadableSettings {
+    pub fn new(config: &AppConfig, flightsql: FlightSqlServiceImpl) -> Self {
+        let auth = &config.flightsql_server.auth;
+        let credentials = match (&auth.basic_auth, &auth.bearer_token) {
+            (Some(basic), None) => Some(Credentials::basic(&basic.username, &basic.password)),
+            (None, Some(token)) => Some(Credentials::bearer(token)),
+            _ => None,
+        };
+        Self {
+            flightsql,
+            credentials,
+            session_tokens: SessionTokens::new(Duration::from_secs(
+                config.flightsql_server.session_token_ttl_secs,
+            )),
+            rate_limit: RateLimitLayer::new(config.flightsql_server.rate_limit.as_ref()),
+        }
+    }
+
+    /// Apply the result limits, credentials, and rate limit of `config`. Connections and
+    /// in-flight requests are kept. Switching between auth types requires a restart.
+    pub fn apply(&self, config: &AppConfig) {
+        let server = &config.flightsql_server;
+        self.flightsql.set_result_limit(server.result_limit.clone());
+        self.rate_limit.set_config(server.rate_limit.as_ref());
+        match (
+            &self.credentials,
+            &server.auth.basic_auth,
+            &server.auth.bearer_token,
+        ) {
+            (Some(credentials), Some(basic), None) if credentials.is_basic() => {
+                credentials.set_basic(&basic.username, &basic.password, &self.session_tokens)
+            }
+            (Some(credentials), None, Some(token)) if !credentials.is_basic() => {
+                credentials.set_bearer(token, &self.session_tokens)
+            }
+            (None, None, None) => {}
+            _ => warn!("changing the auth type requires a restart, keeping the current auth"),
+        }
+    }
+}
+
+/// Where the config of the server was loaded from, so that it can be loaded again
+#[derive(Clone, Debug)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    pub profile: Option<String>,
+    pub overrides: Vec<(String, String)>,
+}
+
+impl ConfigSource {
+    fn load(&self) -> std::result::Result<AppConfig, String> {
+        try_create_config_with_profile(self.path.clone(), self.profile.as_deref(), &self.overrides)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
 #[allow(deprecated)]
 pub fn create_server_handle(
     config: &AppConfig,
-    flightsql: FlightSqlServiceImpl,
+    settings: &ReloadableSettings,
     listener: TcpListener,
     rx: oneshot::Receiver<()>,
     // shutdown_future: impl Future<Output = ()> + Send,
 ) -> Result<JoinHandle<std::result::Result<(), tonic::transport::Error>>> {
+    match &config.flightsql_server.tls {
+        Some(tls) => {
+            // gRPC requires HTTP/2
+            let acceptor = tls::try_create_acceptor(tls, &[b"h2"])?;
+            let incoming = tls::incoming(listener, acceptor);
+            spawn_server(config, settings, incoming, rx)
+        }
+        None => {
+            let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+            spawn_server(config, settings, incoming, rx)
+        }
+    }
+}
+
+/// Serve the FlightSQL service of `settings` on the connections from `incoming` until `rx`
+/// receives the shutdown signal
+#[allow(deprecated)]
+fn spawn_server<I, IO>(
+    config: &AppConfig,
+    settings: &ReloadableSettings,
+    incoming: I,
+    rx: oneshot::Receiver<()>,
+) -> Result<JoinHandle<std::result::Result<(), tonic::transport::Error>>>
+where
+    I: Stream<Item = std::io::Result<IO>> + Send + 'static,
+    IO: AsyncRead + AsyncWrite + Connected + Unpin + Send + 'static,
+    IO::ConnectInfo: Clone + Send + Sync + 'static,
+{
     let server_timeout = Duration::from_secs(DEFAULT_TIMEOUT_SECONDS);
     let mut server_builder = Server::builder().timeout(server_timeout);
     let shutdown_future = async move {
         rx.await.ok();
     };
 
+    // Clients using basic auth can exchange their credentials for a session token with
+    // `Handshake` and present it on later requests
+    let session_tokens = settings.session_tokens.clone();
+    let flightsql = settings.flightsql.clone();
+    let flightsql = if config.flightsql_server.auth.basic_auth.is_some() {
+        flightsql.with_session_tokens(session_tokens.clone())
+    } else {
+        flightsql
+    };
     let mut flight_service = flightsql.service();
     if let Some(size) = config.flightsql_server.max_decoding_message_size {
         flight_service = flight_service.max_decoding_message_size(size);
@@ -62,53 +195,46 @@ pub fn create_server_handle(
         flight_service = flight_service.max_encoding_message_size(size);
     }
 
+    let reflection_service = reflection::reflection_service()?;
+
+    // Throttling runs after authentication so that clients are limited by their identity
+    let rate_limit_layer = settings.rate_limit.clone();
+
     // TODO: onlu include TrailersLayer for testing
     if cfg!(feature = "flightsql") {
         match (
             &config.flightsql_server.auth.basic_auth,
             &config.flightsql_server.auth.bearer_token,
+            &settings.credentials,
         ) {
-            (Some(_), Some(_)) => Err(eyre!("Only one auth type can be used at a time")),
-            (Some(basic), None) => {
-                let basic_auth_layer =
-                    ValidateRequestHeaderLayer::basic(&basic.username, &basic.password);
-                let f = server_builder
-                    .layer(basic_auth_layer)
-                    .add_service(flight_service)
-                    .serve_with_incoming_shutdown(
-                        tokio_stream::wrappers::TcpListenerStream::new(listener),
-                        shutdown_future,
-                    );
-                Ok(tokio::task::spawn(f))
-            }
-            (None, Some(token)) => {
-                let bearer_auth_layer = ValidateRequestHeaderLayer::bearer(token);
+            (Some(_), Some(_), _) => Err(eyre!("Only one auth type can be used at a time")),
+            (_, _, Some(credentials)) => {
+                let auth_layer = ValidateRequestHeaderLayer::custom(
+                    CredentialsOrSessionToken::new(credentials.clone(), session_tokens),
+                );
                 let f = server_builder
-                    .layer(bearer_auth_layer)
+                    .layer(auth_layer)
+                    .layer(rate_limit_layer)
                     .add_service(flight_service)
-                    .serve_with_incoming_shutdown(
-                        tokio_stream::wrappers::TcpListenerStream::new(listener),
-                        shutdown_future,
-                    );
+                    .add_service(reflection_service)
+                    .serve_with_incoming_shutdown(incoming, shutdown_future);
                 Ok(tokio::task::spawn(f))
             }
-            (None, None) => {
+            (_, _, None) => {
                 let f = server_builder
+                    .layer(rate_limit_layer)
                     .add_service(flight_service)
-                    .serve_with_incoming_shutdown(
-                        tokio_stream::wrappers::TcpListenerStream::new(listener),
-                        shutdown_future,
-                    );
+                    .add_service(reflection_service)
+                    .serve_with_incoming_shutdown(incoming, shutdown_future);
                 Ok(tokio::task::spawn(f))
             }
         }
     } else {
         let f = server_builder
+            .layer(rate_limit_layer)
             .add_service(flight_service)
-            .serve_with_incoming_shutdown(
-                tokio_stream::wrappers::TcpListenerStream::new(listener),
-                shutdown_future,
-            );
+            .add_service(reflection_service)
+            .serve_with_incoming_shutdown(incoming, shutdown_future);
         Ok(tokio::task::spawn(f))
     }
 }
@@ -123,34 +249,112 @@ pub struct FlightSqlApp {
 
     /// handle for the server task
     handle: Option<JoinHandle<Result<(), tonic::transport::Error>>>,
+
+    /// Prometheus exporter, stopped once the server shuts down
+    metrics: Option<MetricsServer>,
+
+    /// How long in-flight requests may take to finish once shutdown starts
+    drain_timeout: Duration,
+
+    /// The service, used to reload the DDL on SIGHUP
+    flightsql: FlightSqlServiceImpl,
+
+    /// Settings updated when the config is reloaded
+    settings: ReloadableSettings,
+
+    /// Where the config was loaded from. The config isn't reloaded when unset.
+    config_source: Option<ConfigSource>,
 }
 
 impl FlightSqlApp {
-    /// create a new app for the flightsql server
+    /// create a new app for the flightsql server, serving metrics on `metrics_addr` unless they
+    /// are served by another app in the process
     pub async fn try_new(
         app_execution: AppExecution,
         config: &AppConfig,
         addr: SocketAddr,
-        metrics_addr: SocketAddr,
+        metrics_addr: Option<SocketAddr>,
     ) -> Result<Self> {
         info!("listening to FlightSQL on {addr}");
-        let flightsql = service::FlightSqlServiceImpl::new(app_execution);
+        let mut flightsql = service::FlightSqlServiceImpl::new(app_execution)
+            .with_partitioned_results(config.flightsql_server.partitioned_results)
+            .with_session_idle_timeout(Duration::from_secs(
+                config.flightsql_server.session_idle_timeout_secs,
+            ))
+            .with_catalog_cache_ttl(Duration::from_secs(
+                config.flightsql_server.catalog_cache_ttl_secs,
+            ))
+            .with_ticket_ttl(Duration::from_secs(config.flightsql_server.ticket_ttl_secs));
+        flightsql = flightsql
+            .with_result_limit(config.flightsql_server.result_limit.clone())
+            .with_result_spill(config.flightsql_server.result_spill.clone());
+        if let Some(spill) = &config.flightsql_server.result_spill {
+            // Results older than their tickets can't be fetched anymore, so they were left behind
+            // by a server that didn't shut down cleanly. Allow for streams that are still being
+            // sent by another server sharing the directory.
+            let older_than =
+                Duration::from_secs(config.flightsql_server.ticket_ttl_secs) + SPILL_CLEANUP_GRACE;
+            let removed = spill::remove_stale_spills(spill, older_than);
+            if removed > 0 {
+                info!("removed {removed} directories of spilled results left behind");
+            }
+        }
+        if let Some(limit) = config.flightsql_server.query_memory_limit {
+            flightsql = flightsql.with_query_memory_limit(limit);
+        }
+        if let Some(token) = &config.flightsql_server.admin_token {
+            flightsql = flightsql.with_admin_token(token.clone());
+        }
+        if let Some(max_concurrent) = config.flightsql_server.max_concurrent_statements {
+            flightsql = flightsql.with_admission_controller(AdmissionController::new(
+                max_concurrent,
+                config.flightsql_server.statement_queue_depth,
+                Duration::from_secs(config.flightsql_server.statement_queue_timeout_secs),
+            ));
+        }
         let listener = TcpListener::bind(addr).await.unwrap();
 
         // prepare the shutdown channel
         let (tx, rx) = tokio::sync::oneshot::channel();
-        let handle = create_server_handle(config, flightsql, listener, rx)?;
+        let settings = ReloadableSettings::new(config, flightsql.clone());
+        let handle = create_server_handle(config, &settings, listener, rx)?;
 
-        try_start_metrics_server(metrics_addr)?;
+        let metrics = metrics_addr.map(try_start_metrics_server).transpose()?;
 
         let app = Self {
             shutdown: Some(tx),
-            addr: metrics_addr,
+            addr,
             handle: Some(handle),
+            metrics,
+            drain_timeout: Duration::from_secs(config.flightsql_server.shutdown_drain_timeout_secs),
+            flightsql,
+            settings,
+            config_source: None,
         };
         Ok(app)
     }
 
+    /// Reload the config from `source` on SIGHUP and whenever the file changes
+    pub fn with_config_source(mut self, source: ConfigSource) -> Self {
+        self.config_source = Some(source);
+        self
+    }
+
+    /// Load the config again and apply the settings that can change while running. An invalid
+    /// config is logged and the current settings are kept.
+    fn reload_config(&self) {
+        let Some(source) = &self.config_source else {
+            return;
+        };
+        match source.load() {
+            Ok(config) => {
+                self.settings.apply(&config);
+                info!("reloaded config from {}", source.path.display());
+            }
+            Err(e) => warn!("failed to reload config, keeping the current settings: {e}"),
+        }
+    }
+
     /// Stops the server and waits for the server to shutdown
     pub async fn shutdown_and_wait(mut self) {
         if let Some(shutdown) = self.shutdown.take() {
@@ -162,28 +366,111 @@ impl FlightSqlApp {
                 .expect("task join error (panic?)")
                 .expect("Server Error found at shutdown");
         }
+        if let Some(metrics) = self.metrics.take() {
+            metrics.shutdown();
+        }
     }
 
-    pub async fn run(self) {
-        if let Some(handle) = self.handle {
-            handle
-                .await
-                .expect("Unable to run server task")
-                .expect("Server Error found at shutdown");
-        } else {
+    /// Runs the server until it receives SIGINT or SIGTERM. The server then stops accepting
+    /// requests and waits up to the drain timeout for in-flight requests, including the results
+    /// streamed by `DoGet`, to finish before shutting down. SIGHUP reloads the config and the
+    /// DDL file, and the config is also reloaded whenever its file changes.
+    pub async fn run(mut self) {
+        let Some(mut handle) = self.handle.take() else {
             panic!("Server task not found");
+        };
+        let mut config_modified = self.config_source.as_ref().and_then(|s| s.modified());
+        let mut config_poll = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        let mut reload_signal = ReloadSignal::new();
+        // Created once, like the reload signal, so SIGINT and SIGTERM received while the DDL is
+        // reloaded, or between passes of the loop, still shut the server down
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                res = &mut handle => {
+                    res.expect("Unable to run server task")
+                        .expect("Server Error found at shutdown");
+                    break;
+                }
+                _ = reload_signal.recv() => {
+                    self.reload_config();
+                    if let Err(e) = self.flightsql.reload_ddl().await {
+                        warn!("failed to reload DDL: {e}");
+                    }
+                    continue;
+                }
+                _ = config_poll.tick() => {
+                    let modified = self.config_source.as_ref().and_then(|s| s.modified());
+                    if modified != config_modified {
+                        config_modified = modified;
+                        self.reload_config();
+                    }
+                    continue;
+                }
+                _ = &mut shutdown => {}
+            }
+            info!(
+                "shutting down, waiting up to {:?} for in-flight requests",
+                self.drain_timeout
+            );
+            if let Some(shutdown) = self.shutdown.take() {
+                shutdown.send(()).ok();
+            }
+            match tokio::time::timeout(self.drain_timeout, &mut handle).await {
+                Ok(res) => {
+                    res.expect("Unable to run server task")
+                        .expect("Server Error found at shutdown");
+                    info!("in-flight requests finished");
+                }
+                Err(_) => {
+                    warn!("in-flight requests did not finish in time, shutting down");
+                    handle.abort();
+                }
+            }
+            break;
         }
+        if let Some(metrics) = self.metrics.take() {
+            metrics.shutdown();
+        }
+    }
+}
+
+/// Receives SIGHUP, which reloads the config and the DDL. The handler is installed once so that
+/// signals sent while a reload runs aren't missed. Never receives on platforms without SIGHUP.
+struct ReloadSignal {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl ReloadSignal {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .expect("failed to install signal handler"),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
     }
 }
 
 pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
-    let merged_exec_config = merge_configs(
+    let merged_exec_config = server_execution_config(
         config.shared.clone(),
         config.flightsql_server.execution.clone(),
     );
-    let session_state_builder = DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
-        .with_extensions()
-        .await?;
+    let mut session_state_builder =
+        DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
+            .with_extensions()
+            .await?;
+    add_catalog_persistence(&mut session_state_builder, &config.db)?;
     let session_state = session_state_builder.build()?;
     // FlightSQL Server mode: start a FlightSQL server
     let execution_ctx = ExecutionContext::try_new(
@@ -192,10 +479,14 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         crate::APP_NAME,
         env!("CARGO_PKG_VERSION"),
     )?;
+    restore_system_tables(execution_ctx.system_tables(), &config.db)?;
+    let system_tables = execution_ctx.system_tables().clone();
+    // Refreshes listing tables until the app exits
+    let _catalog_refresh = execution_ctx.start_catalog_refresh();
     if cli.run_ddl {
         execution_ctx.execute_ddl().await;
     }
-    let app_execution = AppExecution::new(execution_ctx);
+    let mut app_execution = AppExecution::new(execution_ctx);
 
     let (addr, metrics_addr) = if let Some(cmd) = cli.command.clone() {
         match cmd {
@@ -230,7 +521,64 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         )
     };
     register_db(app_execution.session_ctx(), &config.db).await?;
-    let app = FlightSqlApp::try_new(app_execution, &config, addr, metrics_addr).await?;
+    restore_catalog(&app_execution.execution_ctx().ddl_session_ctx(), &config.db).await?;
+    let query_log = if config.db.query_log {
+        let query_log = QueryLog::try_new(app_execution.session_ctx(), &config.db).await?;
+        app_execution.with_query_log(query_log.clone());
+        Some(query_log)
+    } else {
+        None
+    };
+    let config_source = ConfigSource {
+        path: cli.config_path(),
+        profile: cli.profile.clone(),
+        overrides: cli.set.clone().unwrap_or_default(),
+    };
+    let app = FlightSqlApp::try_new(app_execution, &config, addr, Some(metrics_addr))
+        .await?
+        .with_config_source(config_source);
     app.run().await;
+    save_system_tables(&system_tables, &config.db)?;
+    if let Some(query_log) = query_log {
+        query_log.flush().await?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use datafusion_app::config::BasicAuth;
+
+    use super::*;
+    use crate::config::FlightSQLServerRateLimitConfig;
+
+    fn config_with(password: &str, burst: u32) -> AppConfig {
+        let mut config = AppConfig::default();
+        config.flightsql_server.auth.basic_auth = Some(BasicAuth {
+            username: "user".to_string(),
+            password: password.to_string(),
+        });
+        config.flightsql_server.rate_limit = Some(FlightSQLServerRateLimitConfig {
+            requests_per_second: Some(1.0),
+            burst,
+            max_concurrent_requests: None,
+        });
+        config
+    }
+
+    #[test]
+    fn test_reload_settings() {
+        let config = config_with("pass", 1);
+        let flightsql = FlightSqlServiceImpl::new(AppExecution::new(ExecutionContext::test()));
+        let settings = ReloadableSettings::new(&config, flightsql);
+        let token = settings.session_tokens.issue(Some("user".to_string()));
+
+        // Reloading an unchanged config keeps the sessions
+        settings.apply(&config);
+        assert!(settings.session_tokens.is_valid(&token));
+
+        // New credentials revoke the sessions issued for the old ones
+        settings.apply(&config_with("new", 5));
+        assert!(!settings.session_tokens.is_valid(&token));
+    }
+}
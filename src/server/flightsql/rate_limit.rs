@@ -0,0 +1,272 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Per client rate and concurrency limits of requests to the FlightSQL server
+
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use http::{Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use log::debug;
+use metrics::counter;
+use pin_project_lite::pin_project;
+use prost::bytes::Bytes;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::Status;
+use tower::{Layer, Service};
+
+use crate::config::FlightSQLServerRateLimitConfig;
+use crate::server::rate_limit::{Buckets, InProgress, InProgressRequests};
+use crate::server::AuthenticatedUser;
+
+/// Rejects requests from clients that exceed their rate limit, or have too many requests in
+/// progress, with `RESOURCE_EXHAUSTED`. Clients are identified by the user the auth layer
+/// authenticated them as, or else their IP address. Requests are passed through untouched when
+/// no limit is configured.
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer {
+    limits: Arc<RwLock<Option<Limits>>>,
+}
+
+/// The limits of every client, replaced when the config is reloaded
+#[derive(Clone, Debug)]
+struct Limits {
+    buckets: Option<Buckets>,
+    in_progress: InProgressRequests,
+}
+
+impl RateLimitLayer {
+    pub fn new(config: Option<&FlightSQLServerRateLimitConfig>) -> Self {
+        Self {
+            limits: Arc::new(RwLock::new(config.map(limits))),
+        }
+    }
+
+    /// Replace the limit of the services created by this layer. Clients keep the tokens left in
+    /// their buckets and the requests they have in progress.
+    pub fn set_config(&self, config: Option<&FlightSQLServerRateLimitConfig>) {
+        let mut current = self.limits.write().unwrap_or_else(|e| e.into_inner());
+        *current = config.map(|config| match current.as_ref() {
+            Some(current) => current.reconfigure(config),
+            None => limits(config),
+        });
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limits: Arc::clone(&self.limits),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimit<S> {
+    inner: S,
+    limits: Arc<RwLock<Option<Limits>>>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RateLimit<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<tonic::body::Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let limits = self
+            .limits
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let mut in_progress = None;
+        if let Some(limits) = limits {
+            let client = client_identity(&request);
+            if let Some(buckets) = &limits.buckets {
+                if buckets.try_acquire(&client, Instant::now()).is_err() {
+                    return throttle(&client, "Rate limit exceeded");
+                }
+            }
+            match limits.in_progress.try_start(&client) {
+                Some(started) => in_progress = Some(started),
+                None => return throttle(&client, "Too many concurrent requests"),
+            }
+        }
+        // Use the inner service that was driven to readiness by `poll_ready`
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let response = inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            Ok(match in_progress {
+                Some(in_progress) => response.map(|body| {
+                    tonic::body::Body::new(InProgressBody {
+                        inner: body,
+                        in_progress: Some(in_progress),
+                    })
+                }),
+                None => response,
+            })
+        })
+    }
+}
+
+fn throttle<E: 'static>(
+    client: &str,
+    reason: &str,
+) -> BoxFuture<'static, Result<Response<tonic::body::Body>, E>> {
+    debug!("throttling request from {client}: {reason}");
+    counter!("flightsql_requests_throttled").increment(1);
+    let status = Status::resource_exhausted(format!("{reason}, try again later"));
+    Box::pin(async move { Ok(status.into_http()) })
+}
+
+pin_project! {
+    /// Response body that keeps its request counted as in progress until the response,
+    /// including results streamed by `DoGet`, has been sent or dropped
+    struct InProgressBody {
+        #[pin]
+        inner: tonic::body::Body,
+        in_progress: Option<InProgress>,
+    }
+}
+
+impl Body for InProgressBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+        if frame.is_none() {
+            this.in_progress.take();
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Limits {
+    /// These limits with the rate and maximums of `config`, sharing the state of every client
+    fn reconfigure(&self, config: &FlightSQLServerRateLimitConfig) -> Limits {
+        Limits {
+            buckets: config
+                .requests_per_second
+                .map(|requests_per_second| match &self.buckets {
+                    Some(buckets) => buckets.with_rate(requests_per_second, config.burst),
+                    None => Buckets::new(requests_per_second, config.burst),
+                }),
+            in_progress: self.in_progress.with_max(config.max_concurrent_requests),
+        }
+    }
+}
+
+fn limits(config: &FlightSQLServerRateLimitConfig) -> Limits {
+    Limits {
+        buckets: config
+            .requests_per_second
+            .map(|requests_per_second| Buckets::new(requests_per_second, config.burst)),
+        in_progress: InProgressRequests::new(config.max_concurrent_requests),
+    }
+}
+
+/// The identity that a client's requests are counted against: the user it was authenticated as,
+/// rather than the unvalidated credentials it sent, or else its IP address
+fn client_identity<B>(request: &Request<B>) -> String {
+    let extensions = request.extensions();
+    if let Some(AuthenticatedUser(user)) = extensions.get::<AuthenticatedUser>() {
+        return format!("user:{user}");
+    }
+    let addr = extensions
+        .get::<TcpConnectInfo>()
+        .and_then(|info| info.remote_addr())
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .and_then(|info| info.get_ref().remote_addr())
+        });
+    match addr {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header;
+
+    #[test]
+    fn identifies_clients_by_authenticated_user() {
+        let mut request = Request::builder().body(()).unwrap();
+        request
+            .extensions_mut()
+            .insert(AuthenticatedUser("user".to_string()));
+        assert_eq!(client_identity(&request), "user:user");
+
+        // Credentials the auth layer didn't validate are ignored
+        let request = Request::builder()
+            .header(header::AUTHORIZATION, "Basic dXNlcjpwYXNz")
+            .body(())
+            .unwrap();
+        assert_eq!(client_identity(&request), "unknown");
+    }
+
+    #[test]
+    fn keeps_client_state_when_reconfigured() {
+        let config = FlightSQLServerRateLimitConfig {
+            requests_per_second: Some(1.0),
+            burst: 1,
+            max_concurrent_requests: Some(1),
+        };
+        let layer = RateLimitLayer::new(Some(&config));
+        let limits = || layer.limits.read().unwrap().clone().unwrap();
+        let now = Instant::now();
+        assert!(limits().buckets.unwrap().try_acquire("a", now).is_ok());
+        let _in_progress = limits().in_progress.try_start("a").unwrap();
+
+        layer.set_config(Some(&FlightSQLServerRateLimitConfig { burst: 5, ..config }));
+        assert!(limits().buckets.unwrap().try_acquire("a", now).is_err());
+        assert!(limits().in_progress.try_start("a").is_none());
+        assert!(limits().in_progress.try_start("b").is_some());
+    }
+}
@@ -0,0 +1,48 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! gRPC server reflection for the Flight service, so that tools like `grpcurl` can discover and
+//! call the server's RPCs without its `.proto` files.
+//!
+//! `arrow-flight` doesn't ship the descriptors of its generated code, so the build script
+//! compiles `proto/Flight.proto` into the descriptor served here. FlightSQL commands are `Any`
+//! messages sent in the `cmd` of a `FlightDescriptor` or the body of an `Action`, so the service
+//! is fully described by it.
+
+use tonic_reflection::server::v1::{ServerReflection, ServerReflectionServer};
+
+/// Encoded `FileDescriptorSet` of `proto/Flight.proto` and the well-known types it imports
+const FLIGHT_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/flight_descriptor.bin"));
+
+/// The reflection service, describing the Flight service and itself
+pub fn reflection_service() -> color_eyre::Result<ServerReflectionServer<impl ServerReflection>> {
+    Ok(tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FLIGHT_DESCRIPTOR_SET)
+        .build_v1()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiled_descriptor_can_be_served() {
+        // Building the service decodes the descriptor and indexes its services and messages
+        assert!(reflection_service().is_ok());
+    }
+}
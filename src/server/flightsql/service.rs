@@ -15,41 +15,84 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::execution::AppExecution;
+use super::admission::AdmissionController;
+use super::auth::SessionTokens;
+use super::error::{datafusion_error_to_status, error_type, report_to_status, QueryStage};
+use super::spill::{materialize, MaterializedResult};
+use super::tickets::{TicketEntry, TicketStore, DEFAULT_TICKET_TTL};
+use crate::config::{FlightSQLServerResultLimitConfig, FlightSQLServerSpillConfig};
+use crate::execution::{AppExecution, DdlErrors};
+use crate::server::query_log::{PendingQueryLogEntry, QueryLog, QueryLogEntry};
+use crate::server::rate_limit::basic_auth_user;
+use crate::server::{constant_time_eq, AuthenticatedUser};
+use arrow_flight::decode::FlightRecordBatchStream;
 use arrow_flight::encode::FlightDataEncoderBuilder;
 use arrow_flight::error::FlightError;
 use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
-use arrow_flight::sql::server::FlightSqlService;
+use arrow_flight::sql::metadata::{SqlInfoData, SqlInfoDataBuilder};
+use arrow_flight::sql::server::{FlightSqlService, PeekableFlightDataStream};
 use arrow_flight::sql::{
     ActionClosePreparedStatementRequest, ActionCreatePreparedStatementRequest,
-    ActionCreatePreparedStatementResult, Any, CommandGetCatalogs, CommandGetDbSchemas,
+    ActionCreatePreparedStatementResult, Any, CommandGetCatalogs, CommandGetCrossReference,
+    CommandGetDbSchemas, CommandGetExportedKeys, CommandGetImportedKeys, CommandGetPrimaryKeys,
     CommandGetSqlInfo, CommandGetTableTypes, CommandGetTables, CommandGetXdbcTypeInfo,
-    CommandPreparedStatementQuery, CommandStatementQuery, SqlInfo, TicketStatementQuery,
+    CommandPreparedStatementQuery, CommandStatementQuery, DoPutPreparedStatementResult,
+    DoPutUpdateResult, ProstMessageExt, SqlInfo, SqlNullOrdering, SqlOuterJoinsSupportLevel,
+    SqlSupportedCaseSensitivity, SqlSupportedTransaction, SqlSupportedUnions, TicketStatementQuery,
 };
 use arrow_flight::{
-    Action, FlightDescriptor, FlightEndpoint, FlightInfo, IpcMessage, SchemaAsIpc, Ticket,
+    Action, ActionType, CancelFlightInfoRequest, CancelFlightInfoResult, CancelStatus, FlightData,
+    FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest, HandshakeResponse, IpcMessage,
+    SchemaAsIpc, Ticket,
 };
-use color_eyre::Result;
-use datafusion::arrow::datatypes::Schema;
+use color_eyre::{Report, Result};
+use datafusion::arrow::array::{Int32Builder, RecordBatch, StringBuilder, UInt64Array};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
 use datafusion::arrow::error::ArrowError;
 use datafusion::arrow::ipc::writer::IpcWriteOptions;
+use datafusion::common::Constraint;
+use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::datasource::MemTable;
+use datafusion::error::DataFusionError;
+use datafusion::execution::SendableRecordBatchStream;
 use datafusion::logical_expr::LogicalPlan;
-use datafusion::prelude::{col, lit};
-use datafusion::sql::parser::DFParser;
+use datafusion::prelude::{col, lit, DataFrame};
+use datafusion::scalar::ScalarValue;
+use datafusion::sql::sqlparser::keywords::ALL_KEYWORDS;
+use datafusion::sql::TableReference;
 use datafusion_app::local::ExecutionContext;
 use datafusion_app::observability::ObservabilityRequestDetails;
-use futures::{StreamExt, TryStreamExt};
+use datafusion_app::rewrite::QueryIdentity;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt, TryStreamExt};
 use jiff::Timestamp;
 use log::{debug, error, info};
 use metrics::{counter, histogram};
 use prost::bytes::Bytes;
 use prost::Message;
 use std::collections::HashMap;
+use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
-use tonic::{Code, Request, Response, Status};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Response, Status, Streaming};
 use uuid::Uuid;
 
+/// How long a client session is kept after its last request by default
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Type of the custom action that re-reads the DDL file, see [`FlightSqlServiceImpl::reload_ddl`]
+pub const RELOAD_DDL_ACTION: &str = "ReloadDdl";
+
+/// Header holding the admin token that administrative actions, such as [`RELOAD_DDL_ACTION`],
+/// require
+pub const ADMIN_TOKEN_HEADER: &str = "x-dft-admin-token";
+
+/// How long the tables returned by `GetTables` and `GetDbSchemas` are cached by default
+pub const DEFAULT_CATALOG_CACHE_TTL: Duration = Duration::from_secs(60);
+
 /// Prepared statement handle containing the logical plan and metadata
 #[derive(Clone)]
 pub struct PreparedStatementHandle {
@@ -57,26 +100,321 @@ pub struct PreparedStatementHandle {
     pub parameter_schema: Option<Arc<Schema>>,
     pub dataset_schema: Arc<Schema>,
     pub created_at: Timestamp,
+    /// Values bound to the statement's placeholders with `DoPut`, used by later executions
+    pub parameters: Option<Vec<ScalarValue>>,
+    /// The SQL the statement was created from
+    pub sql: String,
+}
+
+type HandshakeStream = Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>;
+
+/// The rows of `information_schema.tables`, which `GetTables` and `GetDbSchemas` are answered from
+#[derive(Clone)]
+struct CachedTables {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    created: Instant,
+}
+
+/// The session of a client connection, holding the configuration it changed with `SET`
+#[derive(Clone)]
+struct ClientSession {
+    execution: ExecutionContext,
+    last_used: Instant,
+}
+
+/// Counts a query in the `flightsql_queries_active` gauge for as long as it's alive
+struct ActiveQuery;
+
+impl ActiveQuery {
+    fn new() -> Self {
+        metrics::gauge!("flightsql_queries_active").increment(1.0);
+        Self
+    }
+}
+
+impl Drop for ActiveQuery {
+    fn drop(&mut self) {
+        metrics::gauge!("flightsql_queries_active").decrement(1.0);
+    }
 }
 
 #[derive(Clone)]
 pub struct FlightSqlServiceImpl {
-    requests: Arc<Mutex<HashMap<Uuid, LogicalPlan>>>,
+    /// Queries planned by `GetFlightInfo` that can be fetched with `DoGet`
+    tickets: TicketStore,
+    /// Sessions of client connections keyed by the client's address
+    sessions: Arc<Mutex<HashMap<String, ClientSession>>>,
+    /// How long a client session is kept after its last request
+    session_idle_timeout: Duration,
     prepared_statements: Arc<Mutex<HashMap<Uuid, PreparedStatementHandle>>>,
     execution: ExecutionContext,
+    /// Return an endpoint per output partition of a query's physical plan
+    partitioned_results: bool,
+    /// Limits the statements executing at once, unlimited when unset
+    admission: Option<AdmissionController>,
+    /// Bytes of memory each query may use, unlimited when unset
+    query_memory_limit: Option<usize>,
+    /// Records executed statements when enabled
+    query_log: Option<QueryLog>,
+    /// Limits on the size of each `DoGet` result, shared so that they can be reloaded
+    result_limit: Arc<RwLock<FlightSQLServerResultLimitConfig>>,
+    /// Execute queries in `GetFlightInfo`, spilling large results to disk, when set
+    result_spill: Option<FlightSQLServerSpillConfig>,
+    /// Tables of the catalogs, cached because BI tools request them on every connection
+    catalog_cache: Arc<Mutex<Option<CachedTables>>>,
+    /// How long `catalog_cache` is used before the tables are listed again, disabled when zero
+    catalog_cache_ttl: Duration,
+    /// Tokens issued by `Handshake`, when the server uses basic auth
+    session_tokens: Option<SessionTokens>,
+    /// Token required by administrative actions, which are disabled when unset
+    admin_token: Option<String>,
+    /// Errors of the DDL statements last executed, updated when DDL is reloaded
+    ddl_errors: DdlErrors,
 }
 
 impl FlightSqlServiceImpl {
     pub fn new(execution: AppExecution) -> Self {
-        let requests = HashMap::new();
         let prepared_statements = HashMap::new();
         Self {
             execution: execution.execution_ctx().clone(),
-            requests: Arc::new(Mutex::new(requests)),
+            tickets: TicketStore::new(DEFAULT_TICKET_TTL),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            session_idle_timeout: DEFAULT_SESSION_IDLE_TIMEOUT,
             prepared_statements: Arc::new(Mutex::new(prepared_statements)),
+            partitioned_results: false,
+            admission: None,
+            query_memory_limit: None,
+            query_log: execution.query_log().cloned(),
+            result_limit: Arc::new(RwLock::new(FlightSQLServerResultLimitConfig::default())),
+            result_spill: None,
+            catalog_cache: Arc::new(Mutex::new(None)),
+            catalog_cache_ttl: DEFAULT_CATALOG_CACHE_TTL,
+            session_tokens: None,
+            admin_token: None,
+            ddl_errors: execution.ddl_errors().clone(),
         }
     }
 
+    /// Return one endpoint per output partition of a query's physical plan from
+    /// `GetFlightInfo` so that clients can fetch the partitions in parallel
+    pub fn with_partitioned_results(mut self, partitioned_results: bool) -> Self {
+        self.partitioned_results = partitioned_results;
+        self
+    }
+
+    /// Drop client sessions, and the configuration they changed, after they have been idle for
+    /// `timeout`
+    pub fn with_session_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.session_idle_timeout = timeout;
+        self
+    }
+
+    /// Drop the queries planned by `GetFlightInfo` whose tickets aren't fetched within `ttl` of
+    /// being issued or last fetched
+    pub fn with_ticket_ttl(mut self, ttl: Duration) -> Self {
+        self.tickets = TicketStore::new(ttl);
+        self
+    }
+
+    /// Limit the statements executing at once with `admission`, queueing the rest
+    pub fn with_admission_controller(mut self, admission: AdmissionController) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// Fail queries that use more than `limit` bytes of memory, instead of letting a single query
+    /// exhaust the memory of the server
+    pub fn with_query_memory_limit(mut self, limit: usize) -> Self {
+        self.query_memory_limit = Some(limit);
+        self
+    }
+
+    /// The session of the client connection that sent `request`, created from the server's
+    /// session if this is the client's first request. Requests without a known peer address use
+    /// the server's session.
+    fn session<T>(&self, request: &Request<T>) -> Result<ExecutionContext, Status> {
+        let Some(addr) = request.remote_addr() else {
+            return Ok(self.execution.clone());
+        };
+        let mut guard = self
+            .sessions
+            .lock()
+            .map_err(|_| Status::internal("Failed to acquire lock on sessions"))?;
+        let now = Instant::now();
+        guard
+            .retain(|_, session| now.duration_since(session.last_used) < self.session_idle_timeout);
+        let session = guard.entry(addr.to_string()).or_insert_with(|| {
+            debug!("creating session for {addr}");
+            ClientSession {
+                execution: self.execution.fork_session(),
+                last_used: now,
+            }
+        });
+        session.last_used = now;
+        metrics::gauge!("flightsql_sessions_active").set(guard.len() as f64);
+        Ok(session.execution.clone())
+    }
+
+    /// Who sent `request`: the user that the auth layer validated its basic credentials, or the
+    /// session token issued for them, for. Its headers aren't trusted on their own.
+    fn identity<T>(&self, request: &Request<T>) -> QueryIdentity {
+        let user = request.extensions().get::<AuthenticatedUser>();
+        QueryIdentity {
+            user: user.map(|AuthenticatedUser(user)| user.clone()),
+        }
+    }
+
+    /// Truncate, or reject, results with more rows or bytes than `result_limit` allows so that a
+    /// single client can't saturate the network
+    pub fn with_result_limit(self, result_limit: FlightSQLServerResultLimitConfig) -> Self {
+        self.set_result_limit(result_limit);
+        self
+    }
+
+    /// Replace the result limits of this service and its clones. Results already being streamed
+    /// keep the limits they started with.
+    pub fn set_result_limit(&self, result_limit: FlightSQLServerResultLimitConfig) {
+        *self.result_limit.write().unwrap_or_else(|e| e.into_inner()) = result_limit;
+    }
+
+    /// Execute queries when `GetFlightInfo` is called and hold their results, spilling them to
+    /// disk in chunks that are each fetched from their own endpoint once they outgrow memory
+    pub fn with_result_spill(mut self, result_spill: Option<FlightSQLServerSpillConfig>) -> Self {
+        self.result_spill = result_spill;
+        self
+    }
+
+    /// Answer `GetTables` and `GetDbSchemas` from tables listed at most `ttl` ago. DDL executed by
+    /// the server clears the cache. A `ttl` of zero disables the cache.
+    pub fn with_catalog_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.catalog_cache_ttl = ttl;
+        self
+    }
+
+    /// Issue session tokens from `Handshake` that clients can present instead of their basic
+    /// credentials. The auth layer in front of the service validates both.
+    pub fn with_session_tokens(mut self, tokens: SessionTokens) -> Self {
+        self.session_tokens = Some(tokens);
+        self
+    }
+
+    /// Allow administrative actions, such as reloading the DDL, for requests that send `token`
+    /// in the [`ADMIN_TOKEN_HEADER`] header
+    pub fn with_admin_token(mut self, token: String) -> Self {
+        self.admin_token = Some(token);
+        self
+    }
+
+    /// Check that `request` sent the admin token
+    fn authorize_admin<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let Some(expected) = &self.admin_token else {
+            return Err(Status::permission_denied(
+                "Administrative actions are disabled, set flightsql_server.admin_token to enable them",
+            ));
+        };
+        let token = request
+            .metadata()
+            .get(ADMIN_TOKEN_HEADER)
+            .map(|value| value.as_bytes());
+        match token {
+            Some(token) if constant_time_eq(token, expected.as_bytes()) => Ok(()),
+            _ => Err(Status::permission_denied("Missing or invalid admin token")),
+        }
+    }
+
+    /// The rows of `information_schema.tables`, from the cache if it's fresh
+    async fn information_schema_tables(
+        &self,
+        execution: &ExecutionContext,
+    ) -> Result<DataFrame, Status> {
+        let ctx = execution.session_ctx();
+        if self.catalog_cache_ttl.is_zero() {
+            return ctx
+                .sql("SELECT * FROM information_schema.tables")
+                .await
+                .map_err(|e| Status::internal(e.to_string()));
+        }
+        let cached = self
+            .catalog_cache
+            .lock()
+            .map_err(|_| Status::internal("Failed to acquire lock on catalog cache"))?
+            .clone()
+            .filter(|cached| cached.created.elapsed() < self.catalog_cache_ttl);
+        let cached =
+            match cached {
+                Some(cached) => cached,
+                None => {
+                    debug!("listing tables for catalog cache");
+                    let df = ctx
+                        .sql("SELECT * FROM information_schema.tables")
+                        .await
+                        .map_err(|e| Status::internal(e.to_string()))?;
+                    let schema: SchemaRef = Arc::new(df.schema().as_arrow().clone());
+                    let batches = df
+                        .collect()
+                        .await
+                        .map_err(|e| Status::internal(e.to_string()))?;
+                    let cached = CachedTables {
+                        schema,
+                        batches,
+                        created: Instant::now(),
+                    };
+                    *self.catalog_cache.lock().map_err(|_| {
+                        Status::internal("Failed to acquire lock on catalog cache")
+                    })? = Some(cached.clone());
+                    cached
+                }
+            };
+        let table = MemTable::try_new(cached.schema, vec![cached.batches])
+            .map_err(|e| Status::internal(e.to_string()))?;
+        ctx.read_table(Arc::new(table))
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+
+    /// Re-read the DDL file and execute it in the server's session so that tables added to it are
+    /// registered, and tables and views whose definition changed are registered again, without
+    /// restarting the server. Statements that fail are logged and reported by readiness checks.
+    pub async fn reload_ddl(&self) -> Result<(), Status> {
+        info!("reloading DDL");
+        let errors = self.execution.reload_ddl().await;
+        self.ddl_errors.set(&errors);
+        self.invalidate_catalog_cache()
+    }
+
+    /// Clear the catalog cache after the tables of the catalogs changed
+    fn invalidate_catalog_cache(&self) -> Result<(), Status> {
+        debug!("invalidating catalog cache");
+        *self
+            .catalog_cache
+            .lock()
+            .map_err(|_| Status::internal("Failed to acquire lock on catalog cache"))? = None;
+        Ok(())
+    }
+
+    /// Start the query log entry of the statement request `id`, which is recorded once the
+    /// results of all its partitions have been streamed
+    fn log_statement(&self, id: Uuid, sql: String, client: Option<String>) -> Result<(), Status> {
+        if let Some(log) = &self.query_log {
+            let start = Timestamp::now();
+            let entry = QueryLogEntry {
+                query_id: Some(id.to_string()),
+                client,
+                protocol: "flightsql",
+                sql,
+                start,
+                end: start,
+                rows: 0,
+                status: Code::Ok as u16,
+                error: None,
+                request_id: None,
+            };
+            self.tickets
+                .set_logged(&id, PendingQueryLogEntry::new(log.clone(), entry))?;
+        }
+        Ok(())
+    }
+
     /// Return a [`FlightServiceServer`] that can be used with a
     /// [`Server`](tonic::transport::Server)
     pub fn service(&self) -> FlightServiceServer<Self> {
@@ -88,32 +426,121 @@ impl FlightSqlServiceImpl {
         &self,
         request_id: String,
     ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
-        match Uuid::from_str(&request_id) {
-            Ok(id) => {
+        match parse_statement_handle(&request_id) {
+            Ok((id, partition)) => {
                 info!("getting plan for id: {:?}", id);
-                // Limit the scope of the lock
-                let maybe_plan = {
-                    let guard = self
-                        .requests
-                        .lock()
-                        .map_err(|_| Status::internal("Failed to acquire lock on requests"))?;
-                    guard.get(&id).cloned()
+                let Some((ticket, ticket_stream)) = self.tickets.fetch(&id, partition)? else {
+                    return Err(Status::not_found(format!(
+                        "Ticket for query {id} not found, it may have expired"
+                    )));
                 };
-                if let Some(plan) = maybe_plan {
-                    let stream = self
-                        .execution
-                        .execute_logical_plan(plan)
-                        .await
-                        .map_err(|e| Status::internal(e.to_string()))?;
-                    let builder = FlightDataEncoderBuilder::new();
-                    let flight_data_stream = builder
-                        .build(stream.map_err(|e| FlightError::ExternalError(Box::new(e))))
-                        .map_err(|e| Status::internal(e.to_string()))
-                        .boxed();
-                    Ok(Response::new(flight_data_stream))
-                } else {
-                    Err(Status::internal("plan not found for id"))
+                let cancellation = ticket.cancellation.clone();
+                if cancellation.is_cancelled() {
+                    return Err(Status::cancelled(format!("Query {id} was cancelled")));
+                }
+                // Held until the result stream is dropped so that the statement counts
+                // against the concurrency limit for as long as it executes
+                let permit = match &self.admission {
+                    Some(admission) => Some(admission.admit().await?),
+                    None => None,
+                };
+                let execution = ticket.execution;
+                let pending_log = ticket.logged;
+                let active = ActiveQuery::new();
+                let stream = match (ticket.result, partition) {
+                    (Some(result), chunk) => result.stream(chunk).map_err(Report::from),
+                    (None, Some(partition)) => {
+                        let physical_plan = ticket.physical_plan.ok_or_else(|| {
+                            Status::invalid_argument(format!(
+                                "Query {id} does not have partitioned results"
+                            ))
+                        })?;
+                        execution
+                            .execute_partition(physical_plan, partition, &ticket.guardrails)
+                            .await
+                    }
+                    (None, None) => {
+                        let is_ddl = matches!(ticket.plan, LogicalPlan::Ddl(_));
+                        let stream = execution
+                            .execute_logical_plan_as(
+                                ticket.plan,
+                                ticket.sql.as_deref(),
+                                &ticket.identity,
+                            )
+                            .await;
+                        // DDL is executed eagerly so the catalogs have changed by now
+                        if is_ddl {
+                            self.invalidate_catalog_cache()?;
+                        }
+                        stream
+                    }
                 }
+                .map_err(|e| report_to_status(&e, QueryStage::Execution, Some(&request_id)));
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(status) => {
+                        if let Some(pending) = &pending_log {
+                            pending.fail(status.code() as u16, status.message().to_string());
+                        }
+                        return Err(status);
+                    }
+                };
+                let rows = pending_log.as_ref().map(|pending| pending.rows());
+                let result_limit = self
+                    .result_limit
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone();
+                let reject = result_limit.reject;
+                let stream =
+                    limit_rows(stream, result_limit.rows, reject).inspect_ok(move |batch| {
+                        counter!("flightsql_rows_streamed").increment(batch.num_rows() as u64);
+                        if let Some(rows) = &rows {
+                            rows.fetch_add(batch.num_rows() as u64, Ordering::Relaxed);
+                        }
+                    });
+                let builder = FlightDataEncoderBuilder::new();
+                // Stop polling the plan once the query is cancelled. Dropping the stream
+                // stops any tasks still executing it.
+                let cancelled = cancellation.clone();
+                let flight_data_stream = builder
+                    .build(stream.map_err(|e| FlightError::ExternalError(Box::new(e))))
+                    .map_err({
+                        let request_id = request_id.clone();
+                        move |e| flight_error_to_status(e, &request_id)
+                    });
+                let flight_data_stream =
+                    limit_bytes(flight_data_stream, result_limit.bytes, reject)
+                        .take_until(cancellation.cancelled_owned())
+                        .chain(futures::stream::once(async move {
+                            cancelled.is_cancelled().then(|| {
+                                Err(Status::cancelled(format!("Query {id} was cancelled")))
+                            })
+                        }))
+                        .filter_map(futures::future::ready)
+                        .map(move |data| {
+                            let _permit = &permit;
+                            let _active = &active;
+                            let _ticket_stream = &ticket_stream;
+                            match &data {
+                                Ok(data) => counter!("flightsql_bytes_streamed").increment(
+                                    (data.data_header.len() + data.data_body.len()) as u64,
+                                ),
+                                Err(status) => {
+                                    counter!("flightsql_errors", "type" => error_type(status))
+                                        .increment(1);
+                                    if let Some(pending) = &pending_log {
+                                        pending.fail(
+                                            status.code() as u16,
+                                            status.message().to_string(),
+                                        );
+                                    }
+                                }
+                            }
+                            data
+                        })
+                        .boxed();
+                Ok(Response::new(flight_data_stream))
             }
             Err(e) => {
                 error!("error decoding handle to uuid for {request_id}: {:?}", e);
@@ -124,6 +551,118 @@ impl FlightSqlServiceImpl {
         }
     }
 
+    /// Cancel every query that an endpoint of `info` refers to, returning whether any was found
+    fn cancel_flight_info(&self, info: &FlightInfo) -> Result<bool, Status> {
+        let mut found = false;
+        for endpoint in &info.endpoint {
+            let Some(ticket) = &endpoint.ticket else {
+                continue;
+            };
+            let request_id = request_id_from_ticket(ticket)
+                .map_err(|e| Status::invalid_argument(format!("Invalid ticket: {e}")))?;
+            let (id, _) = parse_statement_handle(&request_id)
+                .map_err(|e| Status::invalid_argument(format!("Invalid ticket: {e}")))?;
+            if let Some(ticket) = self.tickets.get(&id)? {
+                info!("cancelling query {id}");
+                ticket.cancellation.cancel();
+                found = true;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Resolve the table named by the descriptor of the first message of an exchange and write
+    /// the batches that follow to it, acknowledging each one. Like statements, the exchange holds
+    /// a slot of the admission controller and is recorded in the query log once it ends.
+    async fn do_exchange_ingest(
+        &self,
+        request: Request<Streaming<FlightData>>,
+        request_id: String,
+    ) -> Result<Response<<Self as FlightService>::DoExchangeStream>, Status> {
+        let ctx = self.session(&request)?.session_ctx().clone();
+        let client = request.remote_addr().map(|addr| addr.to_string());
+        let mut stream = request.into_inner();
+        let first = stream
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("No data sent to exchange"))?;
+        let table = first
+            .flight_descriptor
+            .as_ref()
+            .map(ingest_table)
+            .transpose()?
+            .ok_or_else(|| Status::invalid_argument("No table descriptor sent to exchange"))?;
+        // Fail before reading any batches when the table doesn't exist
+        ctx.table_provider(table.clone())
+            .await
+            .map_err(|e| Status::not_found(format!("Table {table} not found: {e}")))?;
+        debug!("ingesting into {table} for request_id: {request_id}");
+
+        let permit = match &self.admission {
+            Some(admission) => Some(admission.admit().await?),
+            None => None,
+        };
+        let pending_log = self.query_log.as_ref().map(|log| {
+            let start = Timestamp::now();
+            Arc::new(PendingQueryLogEntry::new(
+                log.clone(),
+                QueryLogEntry {
+                    query_id: None,
+                    client,
+                    protocol: "flightsql",
+                    // Ingested batches have no SQL, so the equivalent statement is logged
+                    sql: format!("INSERT INTO {} (DoExchange)", table.to_quoted_string()),
+                    start,
+                    end: start,
+                    rows: 0,
+                    status: Code::Ok as u16,
+                    error: None,
+                    request_id: Some(request_id.clone()),
+                },
+            ))
+        });
+        let rows = pending_log.as_ref().map(|pending| pending.rows());
+
+        let flight_data = futures::stream::once(async move { Ok(first) })
+            .chain(stream)
+            .map_err(FlightError::from);
+        let acks = FlightRecordBatchStream::new_from_flight_data(flight_data)
+            .map_err(|e| Status::invalid_argument(format!("Failed to decode batch: {e}")))
+            .and_then(move |batch| {
+                let ctx = ctx.clone();
+                let table = table.clone();
+                let request_id = request_id.clone();
+                let rows = rows.clone();
+                async move {
+                    let df = ctx.read_batch(batch).map_err(|e| {
+                        datafusion_error_to_status(&e, QueryStage::Plan, Some(&request_id))
+                    })?;
+                    let written = df
+                        .write_table(&table.to_quoted_string(), DataFrameWriteOptions::new())
+                        .await
+                        .map_err(|e| {
+                            datafusion_error_to_status(&e, QueryStage::Execution, Some(&request_id))
+                        })?;
+                    let record_count = written_row_count(&written);
+                    counter!("flightsql_rows_ingested").increment(record_count as u64);
+                    if let Some(rows) = &rows {
+                        rows.fetch_add(record_count as u64, Ordering::Relaxed);
+                    }
+                    let ack = DoPutUpdateResult { record_count };
+                    Ok(FlightData::new().with_app_metadata(ack.encode_to_vec()))
+                }
+            })
+            .map(move |ack| {
+                // Held until the exchange ends, when the entry is recorded
+                let _permit = &permit;
+                if let (Err(status), Some(pending)) = (&ack, &pending_log) {
+                    pending.fail(status.code() as u16, status.message().to_string());
+                }
+                ack
+            });
+        Ok(Response::new(acks.boxed()))
+    }
+
     async fn record_request(
         &self,
         start: Timestamp,
@@ -135,7 +674,10 @@ impl FlightSqlServiceImpl {
         let duration = Timestamp::now() - start;
         let grpc_code = match &response_err {
             None => Code::Ok,
-            Some(status) => status.code(),
+            Some(status) => {
+                counter!("flightsql_errors", "type" => error_type(status)).increment(1);
+                status.code()
+            }
         };
         let ctx = self.execution.session_ctx();
         let req = ObservabilityRequestDetails {
@@ -159,31 +701,130 @@ impl FlightSqlServiceImpl {
         histogram!(latency_metric).record(duration.get_milliseconds() as f64);
     }
 
+    /// Execute `logical_plan`, planned from `sql` and sent by `identity`, to completion, holding a
+    /// slot of the admission controller while it runs, and keep its results in memory or spilled
+    /// to disk
+    async fn materialize(
+        &self,
+        execution: &ExecutionContext,
+        logical_plan: LogicalPlan,
+        sql: Option<&str>,
+        identity: &QueryIdentity,
+        spill: &FlightSQLServerSpillConfig,
+        request_id: Uuid,
+    ) -> Result<MaterializedResult, Status> {
+        let request_id = request_id.to_string();
+        let _permit = match &self.admission {
+            Some(admission) => Some(admission.admit().await?),
+            None => None,
+        };
+        let stream = execution
+            .execute_logical_plan_as(logical_plan, sql, identity)
+            .await
+            .map_err(|e| report_to_status(&e, QueryStage::Execution, Some(&request_id)))?;
+        let result = materialize(stream, spill).await.map_err(|e| {
+            datafusion_error_to_status(&e, QueryStage::Execution, Some(&request_id))
+        })?;
+        if let MaterializedResult::Spilled { .. } = &result {
+            info!(
+                "spilled {} bytes of results of query {request_id} in {} chunks",
+                result.total_bytes(),
+                result.chunks()
+            );
+        }
+        Ok(result)
+    }
+
+    /// Plan the tickets of `logical_plan`, planned from `sql` if it was sent as SQL
     async fn create_flight_info_for_logical_plan(
         &self,
         logical_plan: LogicalPlan,
+        sql: Option<String>,
         request_id: Uuid,
-        _request: Request<FlightDescriptor>,
+        request: Request<FlightDescriptor>,
     ) -> Result<Response<FlightInfo>, Status> {
+        let identity = self.identity(&request);
+        let mut execution = self.session(&request)?;
+        // `SET` statements must run in the client's session for their changes to apply to it
+        if let Some(limit) = self.query_memory_limit {
+            if !matches!(logical_plan, LogicalPlan::Statement(_)) {
+                execution = execution
+                    .fork_with_memory_limit(limit)
+                    .map_err(|e| Status::internal(e.to_string()))?;
+            }
+        }
         let schema = logical_plan.schema();
+        let mut partitioned_plan = None;
+        let result = match &self.result_spill {
+            Some(spill) if returns_rows(&logical_plan) => Some(Arc::new(
+                self.materialize(
+                    &execution,
+                    logical_plan.clone(),
+                    sql.as_deref(),
+                    &identity,
+                    spill,
+                    request_id,
+                )
+                .await?,
+            )),
+            _ => None,
+        };
+        let partitions = if let Some(result) = &result {
+            result.chunks()
+        } else if self.partitioned_results {
+            let physical_plan = execution
+                .logical_plan_to_physical_plan(logical_plan.clone())
+                .await
+                .map_err(|e| {
+                    report_to_status(&e, QueryStage::Plan, Some(&request_id.to_string()))
+                })?;
+            let partitions = physical_plan
+                .properties()
+                .output_partitioning()
+                .partition_count();
+            if partitions > 1 {
+                partitioned_plan = Some(physical_plan);
+            }
+            partitions
+        } else {
+            1
+        };
         let ticket = TicketStatementQuery {
             statement_handle: request_id.to_string().into(),
         };
         debug!("created ticket handle: {:?}", ticket.statement_handle);
         let mut bytes: Vec<u8> = Vec::new();
         if ticket.encode(&mut bytes).is_ok() {
-            let info = FlightInfo::new()
+            let mut info = FlightInfo::new()
                 .try_with_schema(schema.as_arrow())
                 .unwrap()
-                .with_endpoint(FlightEndpoint::new().with_ticket(Ticket::new(bytes.clone())))
-                .with_descriptor(FlightDescriptor::new_cmd(bytes));
+                .with_descriptor(FlightDescriptor::new_cmd(bytes.clone()));
+            if let Some(result) = &result {
+                info = info
+                    .with_total_records(result.total_rows() as i64)
+                    .with_total_bytes(result.total_bytes() as i64);
+            }
+            if partitions > 1 {
+                for partition in 0..partitions {
+                    let ticket = TicketStatementQuery {
+                        statement_handle: format!("{request_id}/{partition}").into(),
+                    };
+                    info = info.with_endpoint(
+                        FlightEndpoint::new().with_ticket(Ticket::new(ticket.encode_to_vec())),
+                    );
+                }
+            } else {
+                info = info.with_endpoint(FlightEndpoint::new().with_ticket(Ticket::new(bytes)));
+            }
             debug!("created flight info: {:?}", info);
 
-            let mut guard = self
-                .requests
-                .lock()
-                .map_err(|_| Status::internal("failed to acquire lock on requests"))?;
-            guard.insert(request_id, logical_plan);
+            self.tickets.register(
+                request_id,
+                TicketEntry::new(logical_plan, partitioned_plan, execution)
+                    .with_query(sql, identity)
+                    .with_result(result)
+                    .with_partitions(partitions.max(1)),
+            )?;
 
             Ok(Response::new(info))
         } else {
@@ -192,6 +833,25 @@ impl FlightSqlServiceImpl {
         }
     }
 
+    /// Create the flight info for metadata the server computes itself rather than with a query
+    async fn create_flight_info_for_batch(
+        &self,
+        batch: RecordBatch,
+        request_id: Uuid,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let execution = self.session(&request)?;
+        let logical_plan = execution
+            .session_ctx()
+            .read_batch(batch)
+            .map_err(|e| {
+                datafusion_error_to_status(&e, QueryStage::Plan, Some(&request_id.to_string()))
+            })?
+            .into_unoptimized_plan();
+        self.create_flight_info_for_logical_plan(logical_plan, None, request_id, request)
+            .await
+    }
+
     async fn create_flight_info(
         &self,
         query: String,
@@ -202,25 +862,44 @@ impl FlightSqlServiceImpl {
             "creating flight info for request id {request_id} with query: {:?}",
             query
         );
-        let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
-        match DFParser::parse_sql_with_dialect(&query, &dialect) {
+        let execution = self.session(&request)?;
+        match execution.parse_sql(&query) {
             Ok(statements) => {
                 let statement = statements[0].clone();
                 let start = std::time::Instant::now();
 
-                let logical_plan = self
-                    .execution
+                let logical_plan = execution
                     .statement_to_logical_plan(statement)
                     .await
-                    .map_err(|e| Status::internal(e.to_string()))?;
+                    .map_err(|e| {
+                        report_to_status(&e, QueryStage::Plan, Some(&request_id.to_string()))
+                    })?;
+                let logical_plan = execution
+                    .rewrite_plan(logical_plan, &self.identity(&request))
+                    .map_err(|e| {
+                        datafusion_error_to_status(
+                            &e,
+                            QueryStage::Plan,
+                            Some(&request_id.to_string()),
+                        )
+                    })?;
 
                 debug!("logical planning took: {:?}", start.elapsed());
-                self.create_flight_info_for_logical_plan(logical_plan, request_id, request)
-                    .await
+                self.create_flight_info_for_logical_plan(
+                    logical_plan,
+                    Some(query),
+                    request_id,
+                    request,
+                )
+                .await
             }
             Err(e) => {
                 error!("error parsing SQL query: {:?}", e);
-                Err(Status::internal("error parsing SQL query"))
+                Err(datafusion_error_to_status(
+                    &e,
+                    QueryStage::Plan,
+                    Some(&request_id.to_string()),
+                ))
             }
         }
     }
@@ -248,6 +927,41 @@ impl FlightSqlServiceImpl {
 impl FlightSqlService for FlightSqlServiceImpl {
     type FlightService = FlightSqlServiceImpl;
 
+    async fn do_handshake(
+        &self,
+        request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<HandshakeStream>, Status> {
+        counter!("requests", "endpoint" => "do_handshake").increment(1);
+        // Without basic auth there are no credentials to exchange for a token
+        let Some(tokens) = &self.session_tokens else {
+            let output: HandshakeStream = Box::pin(futures::stream::once(async {
+                Ok(HandshakeResponse::default())
+            }));
+            return Ok(Response::new(output));
+        };
+        // The auth layer has already checked the credentials of the request. Only basic
+        // credentials are exchanged, so a session token can't be used to renew itself.
+        let headers = request.metadata().clone().into_headers();
+        let user = request.extensions().get::<AuthenticatedUser>();
+        let (Some(_), Some(AuthenticatedUser(user))) = (basic_auth_user(&headers), user) else {
+            return Err(Status::unauthenticated(
+                "Handshake requires basic credentials",
+            ));
+        };
+        let token = tokens.issue(Some(user.clone()));
+        info!("issued session token to {:?}", request.remote_addr());
+        let header = MetadataValue::try_from(format!("Bearer {token}"))
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let response = HandshakeResponse {
+            protocol_version: 0,
+            payload: token.into(),
+        };
+        let output: HandshakeStream = Box::pin(futures::stream::once(async { Ok(response) }));
+        let mut response = Response::new(output);
+        response.metadata_mut().insert("authorization", header);
+        Ok(response)
+    }
+
     async fn get_flight_info_catalogs(
         &self,
         _query: CommandGetCatalogs,
@@ -283,13 +997,33 @@ impl FlightSqlService for FlightSqlServiceImpl {
             catalog,
             db_schema_filter_pattern,
         } = command;
-        let query = match (catalog, db_schema_filter_pattern) {
-            (Some(catalog), Some(filter)) => format!("SELECT DISTINCT table_catalog, table_schema FROM information_schema.tables WHERE table_catalog = '{catalog}' AND table_schema ILIKE '%{filter}%' ORDER BY table_catalog, table_schema"),
-            (None, Some(filter)) => format!("SELECT DISTINCT table_catalog, table_schema FROM information_schema.tables WHERE table_schema ILIKE '%{filter}%' ORDER BY table_catalog, table_schema"),
-            (Some(catalog), None) => format!("SELECT DISTINCT table_catalog, table_schema FROM information_schema.tables WHERE table_catalog = '{catalog}' ORDER BY table_catalog, table_schema"),
-            (None, None) => "SELECT DISTINCT table_catalog, table_schema FROM information_schema.tables ORDER BY table_catalog, table_schema".to_string()
+        let mut df = self
+            .information_schema_tables(&self.session(&request)?)
+            .await?;
+        if let Some(catalog) = catalog {
+            df = df
+                .filter(col("table_catalog").eq(lit(catalog)))
+                .map_err(|e| Status::internal(e.to_string()))?;
         };
-        let res = self.create_flight_info(query, request_id, request).await;
+        if let Some(schema_filter) = db_schema_filter_pattern {
+            df = df
+                .filter(col("table_schema").ilike(lit(format!("%{schema_filter}%"))))
+                .map_err(|e| Status::internal(e.to_string()))?;
+        };
+        df = df
+            .select_columns(&["table_catalog", "table_schema"])
+            .and_then(|df| df.distinct())
+            .and_then(|df| {
+                df.sort(vec![
+                    col("table_catalog").sort(true, false),
+                    col("table_schema").sort(true, false),
+                ])
+            })
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let logical_plan = df.into_unoptimized_plan();
+        let res = self
+            .create_flight_info_for_logical_plan(logical_plan, None, request_id, request)
+            .await;
 
         // TODO: Move recording to after response is sent to not impact response latency
         self.record_request(
@@ -317,14 +1051,9 @@ impl FlightSqlService for FlightSqlServiceImpl {
             table_types,
             include_schema: _include_schema,
         } = command;
-        let base_query = "SELECT * FROM information_schema.tables";
-
         let mut df = self
-            .execution
-            .session_ctx()
-            .sql(base_query)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .information_schema_tables(&self.session(&request)?)
+            .await?;
 
         if let Some(catalog) = catalog {
             df = df
@@ -358,7 +1087,7 @@ impl FlightSqlService for FlightSqlServiceImpl {
 
         let request_id = uuid::Uuid::new_v4();
         let res = self
-            .create_flight_info_for_logical_plan(logical_plan, request_id, request)
+            .create_flight_info_for_logical_plan(logical_plan, None, request_id, request)
             .await;
 
         // TODO: Move recording to after response is sent to not impact response latency
@@ -397,30 +1126,143 @@ impl FlightSqlService for FlightSqlServiceImpl {
         res
     }
 
-    async fn get_flight_info_sql_info(
+    async fn get_flight_info_primary_keys(
         &self,
-        _query: CommandGetSqlInfo,
+        query: CommandGetPrimaryKeys,
         request: Request<FlightDescriptor>,
     ) -> Result<Response<FlightInfo>, Status> {
-        counter!("requests", "endpoint" => "get_flight_info_sql_info").increment(1);
+        counter!("requests", "endpoint" => "get_flight_info_primary_keys").increment(1);
         let start = Timestamp::now();
         let request_id = uuid::Uuid::new_v4();
+        debug!("getting primary keys of table: {}", query.table);
+        let res = async {
+            let keys = primary_keys(&self.session(&request)?, &query).await?;
+            self.create_flight_info_for_batch(keys, request_id, request)
+                .await
+        }
+        .await;
 
-        // For now, return basic server info via a simple SQL query
-        // TODO: Implement full SqlInfo support with DenseUnion schema
-        let query = format!(
-            "SELECT '{}' as server_name, '{}' as server_version, '57.0' as arrow_version, false as read_only",
-            "datafusion-dft",
-            env!("CARGO_PKG_VERSION")
-        );
+        // TODO: Move recording to after response is sent to not impact response latency
+        self.record_request(
+            start,
+            Some(request_id.to_string()),
+            res.as_ref().err(),
+            "/get_flight_info_primary_keys".to_string(),
+            "get_flight_info_primary_keys_latency_ms",
+        )
+        .await;
+        res
+    }
 
-        let res = self.create_flight_info(query, request_id, request).await;
+    async fn get_flight_info_exported_keys(
+        &self,
+        query: CommandGetExportedKeys,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        counter!("requests", "endpoint" => "get_flight_info_exported_keys").increment(1);
+        let start = Timestamp::now();
+        let request_id = uuid::Uuid::new_v4();
+        debug!("getting exported keys of table: {}", query.table);
+        // DataFusion has no foreign key constraints so no rows are returned
+        let keys = RecordBatch::new_empty(foreign_keys_schema());
+        let res = self
+            .create_flight_info_for_batch(keys, request_id, request)
+            .await;
 
         // TODO: Move recording to after response is sent to not impact response latency
         self.record_request(
             start,
             Some(request_id.to_string()),
             res.as_ref().err(),
+            "/get_flight_info_exported_keys".to_string(),
+            "get_flight_info_exported_keys_latency_ms",
+        )
+        .await;
+        res
+    }
+
+    async fn get_flight_info_imported_keys(
+        &self,
+        query: CommandGetImportedKeys,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        counter!("requests", "endpoint" => "get_flight_info_imported_keys").increment(1);
+        let start = Timestamp::now();
+        let request_id = uuid::Uuid::new_v4();
+        debug!("getting imported keys of table: {}", query.table);
+        // DataFusion has no foreign key constraints so no rows are returned
+        let keys = RecordBatch::new_empty(foreign_keys_schema());
+        let res = self
+            .create_flight_info_for_batch(keys, request_id, request)
+            .await;
+
+        // TODO: Move recording to after response is sent to not impact response latency
+        self.record_request(
+            start,
+            Some(request_id.to_string()),
+            res.as_ref().err(),
+            "/get_flight_info_imported_keys".to_string(),
+            "get_flight_info_imported_keys_latency_ms",
+        )
+        .await;
+        res
+    }
+
+    async fn get_flight_info_cross_reference(
+        &self,
+        query: CommandGetCrossReference,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        counter!("requests", "endpoint" => "get_flight_info_cross_reference").increment(1);
+        let start = Timestamp::now();
+        let request_id = uuid::Uuid::new_v4();
+        debug!(
+            "getting foreign keys of table {} referencing table {}",
+            query.fk_table, query.pk_table
+        );
+        // DataFusion has no foreign key constraints so no rows are returned
+        let keys = RecordBatch::new_empty(foreign_keys_schema());
+        let res = self
+            .create_flight_info_for_batch(keys, request_id, request)
+            .await;
+
+        // TODO: Move recording to after response is sent to not impact response latency
+        self.record_request(
+            start,
+            Some(request_id.to_string()),
+            res.as_ref().err(),
+            "/get_flight_info_cross_reference".to_string(),
+            "get_flight_info_cross_reference_latency_ms",
+        )
+        .await;
+        res
+    }
+
+    async fn get_flight_info_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        counter!("requests", "endpoint" => "get_flight_info_sql_info").increment(1);
+        let start = Timestamp::now();
+
+        // The ticket is the command itself so that `DoGet` is routed to `do_get_sql_info`
+        let res = sql_info_data().and_then(|data| {
+            let schema = query.clone().into_builder(&data).schema();
+            let ticket = Ticket::new(query.as_any().encode_to_vec());
+            let info = FlightInfo::new()
+                .try_with_schema(schema.as_ref())
+                .map_err(|e| Status::internal(e.to_string()))?
+                .with_endpoint(FlightEndpoint::new().with_ticket(ticket))
+                .with_descriptor(request.into_inner());
+            Ok(Response::new(info))
+        });
+
+        // TODO: Move recording to after response is sent to not impact response latency
+        self.record_request(
+            start,
+            None,
+            res.as_ref().err(),
             "/get_flight_info_sql_info".to_string(),
             "get_flight_info_sql_info_latency_ms",
         )
@@ -428,6 +1270,38 @@ impl FlightSqlService for FlightSqlServiceImpl {
         res
     }
 
+    async fn do_get_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        _request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        counter!("requests", "endpoint" => "do_get_sql_info").increment(1);
+        let start = Timestamp::now();
+        let res = sql_info_data().and_then(|data| {
+            let builder = query.into_builder(&data);
+            let schema = builder.schema();
+            let batch = builder
+                .build()
+                .map_err(|e| Status::internal(e.to_string()))?;
+            let stream = FlightDataEncoderBuilder::new()
+                .with_schema(schema)
+                .build(futures::stream::once(async { Ok(batch) }))
+                .map_err(|e| Status::internal(e.to_string()))
+                .boxed();
+            Ok(Response::new(stream))
+        });
+
+        self.record_request(
+            start,
+            None,
+            res.as_ref().err(),
+            "/do_get_sql_info".to_string(),
+            "do_get_sql_info_latency_ms",
+        )
+        .await;
+        res
+    }
+
     async fn get_flight_info_xdbc_type_info(
         &self,
         query: CommandGetXdbcTypeInfo,
@@ -492,9 +1366,13 @@ impl FlightSqlService for FlightSqlServiceImpl {
         let start = Timestamp::now();
         let CommandStatementQuery { query, .. } = query;
         let request_id = uuid::Uuid::new_v4();
+        let client = request.remote_addr().map(|addr| addr.to_string());
         let res = self
             .create_flight_info(query.clone(), request_id, request)
             .await;
+        if res.is_ok() {
+            self.log_statement(request_id, query, client)?;
+        }
 
         // TODO: Move recording to after response is sent to not impact response latency
         self.record_request(
@@ -563,7 +1441,7 @@ impl FlightSqlService for FlightSqlServiceImpl {
     async fn do_action_create_prepared_statement(
         &self,
         query: ActionCreatePreparedStatementRequest,
-        _request: Request<Action>,
+        request: Request<Action>,
     ) -> Result<ActionCreatePreparedStatementResult, Status> {
         counter!("requests", "endpoint" => "do_action_create_prepared_statement").increment(1);
         let start = Timestamp::now();
@@ -572,32 +1450,38 @@ impl FlightSqlService for FlightSqlServiceImpl {
         debug!("Creating prepared statement for SQL: {}", query.query);
 
         // Parse and create logical plan
-        let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
-        let statements = DFParser::parse_sql_with_dialect(&query.query, &dialect)
-            .map_err(|e| Status::internal(format!("Failed to parse SQL: {}", e)))?;
+        let request_id_str = request_id.to_string();
+        let execution = self.session(&request)?;
+        let statements = execution
+            .parse_sql(&query.query)
+            .map_err(|e| datafusion_error_to_status(&e, QueryStage::Plan, Some(&request_id_str)))?;
 
         if statements.is_empty() {
             return Err(Status::invalid_argument("No SQL statement provided"));
         }
 
         let statement = statements[0].clone();
-        let logical_plan = self
-            .execution
+        let logical_plan = execution
             .statement_to_logical_plan(statement)
             .await
-            .map_err(|e| Status::internal(format!("Failed to create logical plan: {}", e)))?;
+            .map_err(|e| report_to_status(&e, QueryStage::Plan, Some(&request_id_str)))?;
+        let logical_plan = execution
+            .rewrite_plan(logical_plan, &self.identity(&request))
+            .map_err(|e| datafusion_error_to_status(&e, QueryStage::Plan, Some(&request_id_str)))?;
 
         // Extract schemas
         let dataset_schema = logical_plan.schema().as_arrow().clone();
-        // For now, we don't support parameterized queries, so parameter_schema is None
-        let parameter_schema = None;
+        let parameter_schema = parameter_schema(&logical_plan)
+            .map_err(|e| Status::internal(format!("Failed to infer parameter types: {}", e)))?;
 
         // Store the prepared statement
         let handle = PreparedStatementHandle {
             plan: logical_plan,
-            parameter_schema,
+            parameter_schema: parameter_schema.clone().map(Arc::new),
             dataset_schema: Arc::new(dataset_schema.clone()),
             created_at: Timestamp::now(),
+            parameters: None,
+            sql: query.query.clone(),
         };
 
         {
@@ -619,11 +1503,24 @@ impl FlightSqlService for FlightSqlServiceImpl {
                 Status::internal(format!("Failed to serialize schema: {}", e))
             })?;
 
+        let parameter_schema_bytes = match parameter_schema {
+            Some(schema) => {
+                let IpcMessage(bytes) =
+                    SchemaAsIpc::new(&schema, &options)
+                        .try_into()
+                        .map_err(|e: ArrowError| {
+                            Status::internal(format!("Failed to serialize parameter schema: {}", e))
+                        })?;
+                bytes
+            }
+            None => Bytes::new(),
+        };
+
         // Build response
         let result = ActionCreatePreparedStatementResult {
             prepared_statement_handle: Bytes::from(request_id.as_bytes().to_vec()),
             dataset_schema: dataset_schema_bytes,
-            parameter_schema: Bytes::new(), // No parameters supported yet
+            parameter_schema: parameter_schema_bytes,
         };
 
         // Record metrics
@@ -746,11 +1643,27 @@ impl FlightSqlService for FlightSqlServiceImpl {
 
         // Create a new request ID for this execution
         let request_id = Uuid::new_v4();
+        let client = request.remote_addr().map(|addr| addr.to_string());
+        let sql = prepared_stmt.sql.clone();
+
+        // Substitute any bound parameters into the stored logical plan
+        let plan = match prepared_stmt.parameters {
+            Some(parameters) => prepared_stmt
+                .plan
+                .with_param_values(parameters)
+                .map_err(|e| {
+                    Status::invalid_argument(format!("Failed to bind parameters: {}", e))
+                })?,
+            None => prepared_stmt.plan,
+        };
 
         // Create FlightInfo from the stored logical plan
         let res = self
-            .create_flight_info_for_logical_plan(prepared_stmt.plan, request_id, request)
+            .create_flight_info_for_logical_plan(plan, Some(sql.clone()), request_id, request)
             .await;
+        if res.is_ok() {
+            self.log_statement(request_id, sql, client)?;
+        }
 
         // Record observability
         let duration = Timestamp::now() - start;
@@ -783,6 +1696,49 @@ impl FlightSqlService for FlightSqlServiceImpl {
         res
     }
 
+    async fn do_put_prepared_statement_query(
+        &self,
+        query: CommandPreparedStatementQuery,
+        request: Request<PeekableFlightDataStream>,
+    ) -> Result<DoPutPreparedStatementResult, Status> {
+        counter!("requests", "endpoint" => "do_put_prepared_statement_query").increment(1);
+        let start = Timestamp::now();
+
+        let handle_bytes = query.prepared_statement_handle.to_vec();
+        let handle_uuid = Uuid::from_slice(&handle_bytes).map_err(|e| {
+            Status::invalid_argument(format!("Invalid prepared statement handle: {}", e))
+        })?;
+
+        debug!("Binding parameters for prepared statement: {}", handle_uuid);
+
+        let batches: Vec<RecordBatch> = FlightRecordBatchStream::new_from_flight_data(
+            request.into_inner().map_err(FlightError::from),
+        )
+        .try_collect()
+        .await
+        .map_err(|e| Status::invalid_argument(format!("Failed to decode parameters: {}", e)))?;
+        let parameters = parameter_values(&batches)?;
+
+        {
+            let mut guard = self
+                .prepared_statements
+                .lock()
+                .map_err(|_| Status::internal("Failed to acquire lock on prepared statements"))?;
+            let handle = guard.get_mut(&handle_uuid).ok_or_else(|| {
+                Status::not_found(format!("Prepared statement not found: {}", handle_uuid))
+            })?;
+            handle.parameters = parameters;
+        }
+
+        let duration = Timestamp::now() - start;
+        histogram!("do_put_prepared_statement_query_latency_ms")
+            .record(duration.get_milliseconds() as f64);
+
+        Ok(DoPutPreparedStatementResult {
+            prepared_statement_handle: Some(query.prepared_statement_handle),
+        })
+    }
+
     async fn do_get_prepared_statement(
         &self,
         query: CommandPreparedStatementQuery,
@@ -839,11 +1795,502 @@ impl FlightSqlService for FlightSqlServiceImpl {
         res
     }
 
+    async fn do_action_cancel_flight_info(
+        &self,
+        query: CancelFlightInfoRequest,
+        _request: Request<Action>,
+    ) -> Result<CancelFlightInfoResult, Status> {
+        counter!("requests", "endpoint" => "do_action_cancel_flight_info").increment(1);
+        let start = Timestamp::now();
+        let info = query
+            .info
+            .ok_or_else(|| Status::invalid_argument("No FlightInfo provided to cancel"))?;
+        let res = self.cancel_flight_info(&info).map(|found| {
+            CancelFlightInfoResult::new(if found {
+                CancelStatus::Cancelled
+            } else {
+                CancelStatus::NotCancellable
+            })
+        });
+
+        self.record_request(
+            start,
+            None,
+            res.as_ref().err(),
+            "/do_action/cancel_flight_info".to_string(),
+            "do_action_cancel_flight_info_latency_ms",
+        )
+        .await;
+        res
+    }
+
+    // `CancelQuery` is deprecated in favor of `CancelFlightInfo` but older clients still use it
+    #[allow(deprecated)]
+    async fn do_action_cancel_query(
+        &self,
+        query: arrow_flight::sql::ActionCancelQueryRequest,
+        _request: Request<Action>,
+    ) -> Result<arrow_flight::sql::ActionCancelQueryResult, Status> {
+        use arrow_flight::sql::{ActionCancelQueryResult, CancelResult};
+
+        counter!("requests", "endpoint" => "do_action_cancel_query").increment(1);
+        let start = Timestamp::now();
+        let res = FlightInfo::decode(query.info)
+            .map_err(|e| Status::invalid_argument(format!("Invalid FlightInfo: {e}")))
+            .and_then(|info| self.cancel_flight_info(&info))
+            .map(|found| ActionCancelQueryResult {
+                result: if found {
+                    CancelResult::Cancelled
+                } else {
+                    CancelResult::NotCancellable
+                } as i32,
+            });
+
+        self.record_request(
+            start,
+            None,
+            res.as_ref().err(),
+            "/do_action/cancel_query".to_string(),
+            "do_action_cancel_query_latency_ms",
+        )
+        .await;
+        res
+    }
+
+    /// Stream batches into a table. The first message's descriptor is the path of the table, and
+    /// each batch written is acknowledged with a message whose app metadata is a
+    /// `DoPutUpdateResult` with the number of rows written.
+    async fn do_exchange_fallback(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<<Self as FlightService>::DoExchangeStream>, Status> {
+        counter!("requests", "endpoint" => "do_exchange").increment(1);
+        let start = Timestamp::now();
+        let request_id = Uuid::new_v4().to_string();
+        let res = self.do_exchange_ingest(request, request_id.clone()).await;
+        self.record_request(
+            start,
+            Some(request_id),
+            res.as_ref().err(),
+            "/do_exchange".to_string(),
+            "do_exchange_latency_ms",
+        )
+        .await;
+        res
+    }
+
+    async fn do_action_fallback(
+        &self,
+        request: Request<Action>,
+    ) -> Result<Response<<Self as FlightService>::DoActionStream>, Status> {
+        let action_type = &request.get_ref().r#type;
+        if action_type != RELOAD_DDL_ACTION {
+            return Err(Status::invalid_argument(format!(
+                "do_action: The defined request is invalid: {action_type:?}"
+            )));
+        }
+        counter!("requests", "endpoint" => "do_action_reload_ddl").increment(1);
+        let start = Timestamp::now();
+        let res = match self.authorize_admin(&request) {
+            Ok(()) => self.reload_ddl().await,
+            Err(status) => Err(status),
+        };
+        self.record_request(
+            start,
+            None,
+            res.as_ref().err(),
+            "/do_action/reload_ddl".to_string(),
+            "do_action_reload_ddl_latency_ms",
+        )
+        .await;
+        res?;
+        let output = futures::stream::once(async {
+            Ok(arrow_flight::Result {
+                body: Bytes::from_static(b"DDL reloaded"),
+            })
+        });
+        Ok(Response::new(output.boxed()))
+    }
+
+    async fn list_custom_actions(&self) -> Option<Vec<Result<ActionType, Status>>> {
+        Some(vec![Ok(ActionType {
+            r#type: RELOAD_DDL_ACTION.to_string(),
+            description: "Re-read the DDL file and register the tables it defines".to_string(),
+        })])
+    }
+
     async fn register_sql_info(&self, _id: i32, _result: &SqlInfo) {}
 }
 
+/// The table named by the descriptor of a `DoExchange`, either a path of up to three parts or a
+/// command with the table reference as text
+fn ingest_table(descriptor: &FlightDescriptor) -> Result<TableReference, Status> {
+    match descriptor.path.as_slice() {
+        [] if !descriptor.cmd.is_empty() => std::str::from_utf8(&descriptor.cmd)
+            .map(TableReference::from)
+            .map_err(|e| Status::invalid_argument(format!("Invalid table in descriptor: {e}"))),
+        [table] => Ok(TableReference::from(table.as_str())),
+        [schema, table] => Ok(TableReference::partial(schema.as_str(), table.as_str())),
+        [catalog, schema, table] => Ok(TableReference::full(
+            catalog.as_str(),
+            schema.as_str(),
+            table.as_str(),
+        )),
+        _ => Err(Status::invalid_argument(
+            "Descriptor must name the table to ingest into",
+        )),
+    }
+}
+
+/// The number of rows reported written by `DataFrame::write_table`
+fn written_row_count(batches: &[RecordBatch]) -> i64 {
+    batches
+        .iter()
+        .filter_map(|batch| {
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .map(|counts| counts.iter().flatten().sum::<u64>())
+        })
+        .sum::<u64>() as i64
+}
+
+/// Whether `plan` is a query whose results are worth holding for `DoGet`. DDL, DML and `SET`
+/// statements are executed when their tickets are fetched, as without spilling.
+fn returns_rows(plan: &LogicalPlan) -> bool {
+    !matches!(
+        plan,
+        LogicalPlan::Ddl(_)
+            | LogicalPlan::Dml(_)
+            | LogicalPlan::Copy(_)
+            | LogicalPlan::Statement(_)
+    )
+}
+
+/// Schema of the results of `GetPrimaryKeys`, with the nullability the FlightSQL spec requires
+fn primary_keys_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("catalog_name", DataType::Utf8, true),
+        Field::new("db_schema_name", DataType::Utf8, true),
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("column_name", DataType::Utf8, false),
+        Field::new("key_name", DataType::Utf8, true),
+        Field::new("key_sequence", DataType::Int32, false),
+    ]))
+}
+
+/// Schema of the results of `GetExportedKeys`, `GetImportedKeys` and `GetCrossReference`, with
+/// the nullability the FlightSQL spec requires
+fn foreign_keys_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("pk_catalog_name", DataType::Utf8, true),
+        Field::new("pk_db_schema_name", DataType::Utf8, true),
+        Field::new("pk_table_name", DataType::Utf8, false),
+        Field::new("pk_column_name", DataType::Utf8, false),
+        Field::new("fk_catalog_name", DataType::Utf8, true),
+        Field::new("fk_db_schema_name", DataType::Utf8, true),
+        Field::new("fk_table_name", DataType::Utf8, false),
+        Field::new("fk_column_name", DataType::Utf8, false),
+        Field::new("key_sequence", DataType::Int32, false),
+        Field::new("fk_key_name", DataType::Utf8, true),
+        Field::new("pk_key_name", DataType::Utf8, true),
+        Field::new("update_rule", DataType::UInt8, false),
+        Field::new("delete_rule", DataType::UInt8, false),
+    ]))
+}
+
+/// The primary key columns of the tables named in `query`, from the constraints of their
+/// `TableProvider`, such as the `PRIMARY KEY` of a `CREATE TABLE`
+async fn primary_keys(
+    execution: &ExecutionContext,
+    query: &CommandGetPrimaryKeys,
+) -> Result<RecordBatch, Status> {
+    let ctx = execution.session_ctx();
+    let mut catalog_names = StringBuilder::new();
+    let mut db_schema_names = StringBuilder::new();
+    let mut table_names = StringBuilder::new();
+    let mut column_names = StringBuilder::new();
+    let mut key_names = StringBuilder::new();
+    let mut key_sequences = Int32Builder::new();
+    for catalog_name in ctx.catalog_names() {
+        if query.catalog.as_ref().is_some_and(|c| *c != catalog_name) {
+            continue;
+        }
+        let Some(catalog) = ctx.catalog(&catalog_name) else {
+            continue;
+        };
+        for schema_name in catalog.schema_names() {
+            if query.db_schema.as_ref().is_some_and(|s| *s != schema_name) {
+                continue;
+            }
+            let Some(schema) = catalog.schema(&schema_name) else {
+                continue;
+            };
+            let table = schema
+                .table(&query.table)
+                .await
+                .map_err(|e| datafusion_error_to_status(&e, QueryStage::Plan, None))?;
+            let Some(table) = table else {
+                continue;
+            };
+            let Some(constraints) = table.constraints() else {
+                continue;
+            };
+            let table_schema = table.schema();
+            for constraint in constraints.iter() {
+                let Constraint::PrimaryKey(indices) = constraint else {
+                    continue;
+                };
+                for (sequence, index) in (1..).zip(indices) {
+                    catalog_names.append_value(&catalog_name);
+                    db_schema_names.append_value(&schema_name);
+                    table_names.append_value(&query.table);
+                    column_names.append_value(table_schema.field(*index).name());
+                    key_names.append_null();
+                    key_sequences.append_value(sequence);
+                }
+            }
+        }
+    }
+    RecordBatch::try_new(
+        primary_keys_schema(),
+        vec![
+            Arc::new(catalog_names.finish()),
+            Arc::new(db_schema_names.finish()),
+            Arc::new(table_names.finish()),
+            Arc::new(column_names.finish()),
+            Arc::new(key_names.finish()),
+            Arc::new(key_sequences.finish()),
+        ],
+    )
+    .map_err(|e| Status::internal(e.to_string()))
+}
+
+/// Version of Arrow the server is built with, reported by `GetSqlInfo`
+const ARROW_VERSION: &str = "58";
+
+/// The server's capabilities reported by `GetSqlInfo`, which drivers such as ADBC and JDBC probe
+/// when connecting
+fn sql_info_data() -> Result<SqlInfoData, Status> {
+    let mut builder = SqlInfoDataBuilder::new();
+    // Server
+    builder.append(SqlInfo::FlightSqlServerName, crate::APP_NAME);
+    builder.append(SqlInfo::FlightSqlServerVersion, env!("CARGO_PKG_VERSION"));
+    builder.append(SqlInfo::FlightSqlServerArrowVersion, ARROW_VERSION);
+    builder.append(SqlInfo::FlightSqlServerReadOnly, false);
+    builder.append(SqlInfo::FlightSqlServerSql, true);
+    builder.append(SqlInfo::FlightSqlServerSubstrait, false);
+    builder.append(
+        SqlInfo::FlightSqlServerTransaction,
+        SqlSupportedTransaction::None as i32,
+    );
+    builder.append(SqlInfo::FlightSqlServerCancel, true);
+    builder.append(SqlInfo::FlightSqlServerStatementTimeout, 0i32);
+    builder.append(SqlInfo::FlightSqlServerTransactionTimeout, 0i32);
+    // SQL syntax
+    builder.append(SqlInfo::SqlDdlCatalog, false);
+    builder.append(SqlInfo::SqlDdlSchema, true);
+    builder.append(SqlInfo::SqlDdlTable, true);
+    builder.append(
+        SqlInfo::SqlIdentifierCase,
+        SqlSupportedCaseSensitivity::SqlCaseSensitivityLowercase as i32,
+    );
+    builder.append(SqlInfo::SqlIdentifierQuoteChar, "\"");
+    builder.append(
+        SqlInfo::SqlQuotedIdentifierCase,
+        SqlSupportedCaseSensitivity::SqlCaseSensitivityCaseInsensitive as i32,
+    );
+    builder.append(SqlInfo::SqlAllTablesAreSelectable, true);
+    builder.append(
+        SqlInfo::SqlNullOrdering,
+        SqlNullOrdering::SqlNullsSortedHigh as i32,
+    );
+    let keywords: Vec<String> = ALL_KEYWORDS.iter().map(|k| k.to_string()).collect();
+    builder.append(SqlInfo::SqlKeywords, keywords);
+    builder.append(SqlInfo::SqlSearchStringEscape, "\\");
+    builder.append(SqlInfo::SqlExtraNameCharacters, "");
+    builder.append(SqlInfo::SqlSupportsColumnAliasing, true);
+    builder.append(SqlInfo::SqlNullPlusNullIsNull, true);
+    builder.append(SqlInfo::SqlSupportsTableCorrelationNames, true);
+    builder.append(SqlInfo::SqlSupportsDifferentTableCorrelationNames, true);
+    builder.append(SqlInfo::SqlSupportsExpressionsInOrderBy, true);
+    builder.append(SqlInfo::SqlSupportsOrderByUnrelated, true);
+    builder.append(SqlInfo::SqlSupportsLikeEscapeClause, true);
+    builder.append(SqlInfo::SqlSupportsNonNullableColumns, true);
+    builder.append(SqlInfo::SqlSupportsIntegrityEnhancementFacility, false);
+    builder.append(
+        SqlInfo::SqlOuterJoinsSupportLevel,
+        SqlOuterJoinsSupportLevel::SqlFullOuterJoins as i32,
+    );
+    builder.append(SqlInfo::SqlSchemaTerm, "schema");
+    builder.append(SqlInfo::SqlProcedureTerm, "");
+    builder.append(SqlInfo::SqlCatalogTerm, "catalog");
+    builder.append(SqlInfo::SqlCatalogAtStart, true);
+    builder.append(SqlInfo::SqlSelectForUpdateSupported, false);
+    builder.append(SqlInfo::SqlStoredProceduresSupported, false);
+    builder.append(SqlInfo::SqlCorrelatedSubqueriesSupported, true);
+    builder.append(
+        SqlInfo::SqlSupportedUnions,
+        (1 << SqlSupportedUnions::SqlUnion as i32) | (1 << SqlSupportedUnions::SqlUnionAll as i32),
+    );
+    builder.append(SqlInfo::SqlTransactionsSupported, false);
+    builder.append(SqlInfo::SqlBatchUpdatesSupported, false);
+    builder.append(SqlInfo::SqlSavepointsSupported, false);
+    builder.append(SqlInfo::SqlNamedParametersSupported, false);
+    builder.append(SqlInfo::SqlLocatorsUpdateCopy, false);
+    builder.append(SqlInfo::SqlStoredFunctionsUsingCallSyntaxSupported, false);
+    builder
+        .build()
+        .map_err(|e| Status::internal(format!("Failed to build SQL info: {e}")))
+}
+
+/// The schema of the placeholders in `plan`, with one field per placeholder in the order they are
+/// numbered, or `None` if the plan has no placeholders. Placeholders whose type could not be
+/// inferred are typed as `Null`.
+fn parameter_schema(plan: &LogicalPlan) -> datafusion::error::Result<Option<Schema>> {
+    let mut parameters: Vec<(String, Option<DataType>)> =
+        plan.get_parameter_types()?.into_iter().collect();
+    if parameters.is_empty() {
+        return Ok(None);
+    }
+    // Sort by length first so that `$2` comes before `$10`
+    parameters.sort_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)));
+    let fields: Vec<Field> = parameters
+        .into_iter()
+        .map(|(name, data_type)| Field::new(name, data_type.unwrap_or(DataType::Null), true))
+        .collect();
+    Ok(Some(Schema::new(fields)))
+}
+
+/// Convert the parameter batches sent with `DoPut` into the values of each placeholder. A single
+/// row binds one value per column and no rows clears any bound values.
+fn parameter_values(batches: &[RecordBatch]) -> Result<Option<Vec<ScalarValue>>, Status> {
+    let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    match rows {
+        0 => Ok(None),
+        1 => {
+            let batch = batches
+                .iter()
+                .find(|batch| batch.num_rows() == 1)
+                .expect("batch with one row");
+            let values = batch
+                .columns()
+                .iter()
+                .map(|column| ScalarValue::try_from_array(column, 0))
+                .collect::<datafusion::error::Result<Vec<_>>>()
+                .map_err(|e| Status::invalid_argument(format!("Invalid parameter: {}", e)))?;
+            Ok(Some(values))
+        }
+        _ => Err(Status::invalid_argument(format!(
+            "Only a single row of parameters is supported, got {rows}"
+        ))),
+    }
+}
+
+/// Stop `stream` after `limit` rows, slicing the batch that crosses the limit. With `reject` the
+/// stream fails instead once it has more than `limit` rows.
+fn limit_rows(
+    stream: SendableRecordBatchStream,
+    limit: Option<usize>,
+    reject: bool,
+) -> BoxStream<'static, datafusion::error::Result<RecordBatch>> {
+    let Some(limit) = limit else {
+        return stream.boxed();
+    };
+    // `None` once the limit is reached
+    stream
+        .scan(Some(0), move |returned: &mut Option<usize>, batch| {
+            let Some(rows) = *returned else {
+                return futures::future::ready(None);
+            };
+            let res = match batch {
+                Ok(batch) if rows + batch.num_rows() > limit => {
+                    *returned = None;
+                    if reject {
+                        Err(DataFusionError::ResourcesExhausted(format!(
+                            "Query returned more than {limit} rows, the server's result limit"
+                        )))
+                    } else {
+                        info!("truncating results to {limit} rows");
+                        Ok(batch.slice(0, limit - rows))
+                    }
+                }
+                Ok(batch) => {
+                    *returned = Some(rows + batch.num_rows());
+                    Ok(batch)
+                }
+                Err(e) => Err(e),
+            };
+            futures::future::ready(Some(res))
+        })
+        .boxed()
+}
+
+/// Stop `stream` before the message that would take it over `limit` bytes of encoded data. With
+/// `reject` the stream fails instead.
+fn limit_bytes(
+    stream: impl Stream<Item = Result<FlightData, Status>> + Send + 'static,
+    limit: Option<usize>,
+    reject: bool,
+) -> BoxStream<'static, Result<FlightData, Status>> {
+    let Some(limit) = limit else {
+        return stream.boxed();
+    };
+    // `None` once the limit is reached
+    stream
+        .scan(Some(0), move |sent: &mut Option<usize>, data| {
+            let Some(bytes) = *sent else {
+                return futures::future::ready(None);
+            };
+            let res = match data {
+                Ok(data) if bytes + data.data_header.len() + data.data_body.len() > limit => {
+                    *sent = None;
+                    if reject {
+                        Some(Err(Status::resource_exhausted(format!(
+                            "Query returned more than {limit} bytes, the server's result limit"
+                        ))))
+                    } else {
+                        info!("truncating results to {limit} bytes");
+                        None
+                    }
+                }
+                Ok(data) => {
+                    *sent = Some(bytes + data.data_header.len() + data.data_body.len());
+                    Some(Ok(data))
+                }
+                Err(e) => Some(Err(e)),
+            };
+            futures::future::ready(res)
+        })
+        .boxed()
+}
+
+/// Convert an error from a result stream into a `Status`, reporting queries that ran out of
+/// memory as `RESOURCE_EXHAUSTED` so clients can tell them apart from other failures
+fn flight_error_to_status(e: FlightError, query_id: &str) -> Status {
+    if let FlightError::ExternalError(e) = &e {
+        if let Some(e) = e.downcast_ref::<DataFusionError>() {
+            return datafusion_error_to_status(e, QueryStage::Execution, Some(query_id));
+        }
+    }
+    Status::internal(e.to_string())
+}
+
+/// Split a statement handle into the request id and, for the endpoints of a partitioned result,
+/// the output partition to execute. Handles are formatted as `<request_id>[/<partition>]`.
+fn parse_statement_handle(handle: &str) -> Result<(Uuid, Option<usize>)> {
+    match handle.split_once('/') {
+        Some((id, partition)) => Ok((Uuid::from_str(id)?, Some(partition.parse()?))),
+        None => Ok((Uuid::from_str(handle)?, None)),
+    }
+}
+
 fn try_request_id_from_request(request: Request<Ticket>) -> Result<String> {
-    let ticket = request.into_inner();
+    request_id_from_ticket(&request.into_inner())
+}
+
+fn request_id_from_ticket(ticket: &Ticket) -> Result<String> {
     let bytes = ticket.ticket.to_vec();
 
     let request_id = String::from_utf8(
@@ -0,0 +1,424 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Results materialized by `GetFlightInfo`, spilled to Arrow IPC files once they outgrow memory
+//! and served in chunks, one per endpoint
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::reader::StreamReader;
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::SendableRecordBatchStream;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use futures::{StreamExt, TryStreamExt};
+use log::{debug, warn};
+use metrics::counter;
+use tempfile::TempDir;
+
+use crate::config::FlightSQLServerSpillConfig;
+
+/// Prefix of the directories results are spilled to
+const SPILL_DIR_PREFIX: &str = "dft-results-";
+
+/// The results of a query, held in memory or spilled to files
+pub enum MaterializedResult {
+    Memory {
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+    },
+    Spilled {
+        schema: SchemaRef,
+        /// Removed with the files in it when the result is dropped
+        _dir: TempDir,
+        chunks: Vec<PathBuf>,
+        rows: usize,
+        bytes: usize,
+    },
+}
+
+impl MaterializedResult {
+    pub fn schema(&self) -> SchemaRef {
+        match self {
+            Self::Memory { schema, .. } | Self::Spilled { schema, .. } => SchemaRef::clone(schema),
+        }
+    }
+
+    /// Number of chunks the result is served in
+    pub fn chunks(&self) -> usize {
+        match self {
+            Self::Memory { .. } => 1,
+            Self::Spilled { chunks, .. } => chunks.len(),
+        }
+    }
+
+    pub fn total_rows(&self) -> usize {
+        match self {
+            Self::Memory { batches, .. } => batches.iter().map(|b| b.num_rows()).sum(),
+            Self::Spilled { rows, .. } => *rows,
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        match self {
+            Self::Memory { batches, .. } => batches.iter().map(|b| b.get_array_memory_size()).sum(),
+            Self::Spilled { bytes, .. } => *bytes,
+        }
+    }
+
+    /// Stream the batches of `chunk`, or of every chunk in order when unset
+    pub fn stream(self: Arc<Self>, chunk: Option<usize>) -> Result<SendableRecordBatchStream> {
+        match chunk {
+            Some(chunk) => self.stream_chunk(chunk),
+            None => {
+                let schema = self.schema();
+                let chunks = futures::stream::iter(0..self.chunks())
+                    .map(move |chunk| self.stream_chunk(chunk))
+                    .try_flatten();
+                Ok(Box::pin(RecordBatchStreamAdapter::new(schema, chunks)))
+            }
+        }
+    }
+
+    /// Stream the batches of `chunk`, reading spilled chunks from disk on a blocking thread
+    pub fn stream_chunk(&self, chunk: usize) -> Result<SendableRecordBatchStream> {
+        match self {
+            Self::Memory { schema, batches } if chunk == 0 => {
+                let batches = batches.clone();
+                Ok(Box::pin(RecordBatchStreamAdapter::new(
+                    SchemaRef::clone(schema),
+                    futures::stream::iter(batches.into_iter().map(Ok)),
+                )))
+            }
+            Self::Spilled { schema, chunks, .. } if chunk < chunks.len() => {
+                let path = chunks[chunk].clone();
+                let (tx, rx) = tokio::sync::mpsc::channel(2);
+                tokio::task::spawn_blocking(move || {
+                    let reader =
+                        match File::open(&path)
+                            .map_err(DataFusionError::from)
+                            .and_then(|file| {
+                                StreamReader::try_new(BufReader::new(file), None)
+                                    .map_err(DataFusionError::from)
+                            }) {
+                            Ok(reader) => reader,
+                            Err(e) => {
+                                let _ = tx.blocking_send(Err(e));
+                                return;
+                            }
+                        };
+                    for batch in reader {
+                        if tx
+                            .blocking_send(batch.map_err(DataFusionError::from))
+                            .is_err()
+                        {
+                            // The client stopped fetching
+                            return;
+                        }
+                    }
+                });
+                Ok(Box::pin(RecordBatchStreamAdapter::new(
+                    SchemaRef::clone(schema),
+                    tokio_stream::wrappers::ReceiverStream::new(rx),
+                )))
+            }
+            _ => Err(DataFusionError::Plan(format!(
+                "Result does not have chunk {chunk}"
+            ))),
+        }
+    }
+}
+
+/// Collect the results of `stream`, keeping them in memory until they take more than the memory
+/// threshold of `config` and then writing them to files of about `chunk_bytes` each
+pub async fn materialize(
+    mut stream: SendableRecordBatchStream,
+    config: &FlightSQLServerSpillConfig,
+) -> Result<MaterializedResult> {
+    let schema = stream.schema();
+    let mut batches = Vec::new();
+    let mut buffered = 0;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        buffered += batch.get_array_memory_size();
+        batches.push(batch);
+        if buffered > config.memory_threshold_bytes {
+            return spill(schema, batches, stream, config).await;
+        }
+    }
+    Ok(MaterializedResult::Memory { schema, batches })
+}
+
+/// Write the results to files on a blocking thread, with batches sent to it as they're computed
+async fn spill(
+    schema: SchemaRef,
+    buffered: Vec<RecordBatch>,
+    mut stream: SendableRecordBatchStream,
+    config: &FlightSQLServerSpillConfig,
+) -> Result<MaterializedResult> {
+    let directory = config.directory.clone();
+    let dir = tokio::task::spawn_blocking(move || {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(SPILL_DIR_PREFIX);
+        match directory {
+            Some(directory) => builder.tempdir_in(directory),
+            None => builder.tempdir(),
+        }
+    })
+    .await
+    .map_err(|e| DataFusionError::External(Box::new(e)))??;
+    debug!("spilling results to {}", dir.path().display());
+    let mut writer = ChunkWriter {
+        dir: dir.path().to_path_buf(),
+        schema: SchemaRef::clone(&schema),
+        chunk_bytes: config.chunk_bytes,
+        current: None,
+        chunks: Vec::new(),
+        rows: 0,
+        bytes: 0,
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<RecordBatch>(2);
+    let written = tokio::task::spawn_blocking(move || {
+        while let Some(batch) = rx.blocking_recv() {
+            writer.write(&batch)?;
+        }
+        writer.finish()
+    });
+    // Stops early when the writer failed, whose error is then returned
+    let sent: Result<()> = async {
+        for batch in buffered {
+            if tx.send(batch).await.is_err() {
+                return Ok(());
+            }
+        }
+        while let Some(batch) = stream.next().await {
+            if tx.send(batch?).await.is_err() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+    .await;
+    drop(tx);
+    // The writer is waited for even when the query failed, so the files are closed before the
+    // directory is removed
+    let written = written
+        .await
+        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+    sent?;
+    let (chunks, rows, bytes) = written?;
+    counter!("flightsql_results_spilled").increment(1);
+    counter!("flightsql_spilled_bytes").increment(bytes as u64);
+    Ok(MaterializedResult::Spilled {
+        schema,
+        _dir: dir,
+        chunks,
+        rows,
+        bytes,
+    })
+}
+
+/// Remove the directories of spilled results that weren't modified for `older_than`, which are
+/// left behind when a server doesn't shut down cleanly. Returns the number removed.
+pub fn remove_stale_spills(config: &FlightSQLServerSpillConfig, older_than: Duration) -> usize {
+    let directory = config.directory.clone().unwrap_or_else(std::env::temp_dir);
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(
+                "failed to list spilled results in {}: {e}",
+                directory.display()
+            );
+            return 0;
+        }
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_stale_spill(&path, now, older_than) {
+            match std::fs::remove_dir_all(&path) {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("failed to remove spilled results {}: {e}", path.display()),
+            }
+        }
+    }
+    removed
+}
+
+fn is_stale_spill(path: &Path, now: SystemTime, older_than: Duration) -> bool {
+    let is_spill = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(SPILL_DIR_PREFIX));
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return false;
+    };
+    is_spill
+        && metadata.is_dir()
+        && metadata
+            .modified()
+            .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() >= older_than)
+}
+
+/// Writes batches to a new file whenever the current one has `chunk_bytes` of data
+struct ChunkWriter {
+    dir: PathBuf,
+    schema: SchemaRef,
+    chunk_bytes: usize,
+    current: Option<(StreamWriter<BufWriter<File>>, usize)>,
+    chunks: Vec<PathBuf>,
+    rows: usize,
+    bytes: usize,
+}
+
+impl ChunkWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if self.current.is_none() {
+            let path = self.dir.join(format!("chunk-{}.arrow", self.chunks.len()));
+            let file = BufWriter::new(File::create(&path)?);
+            self.current = Some((StreamWriter::try_new(file, &self.schema)?, 0));
+            self.chunks.push(path);
+        }
+        let Some((writer, written)) = &mut self.current else {
+            unreachable!("a chunk is open");
+        };
+        writer.write(batch)?;
+        let size = batch.get_array_memory_size();
+        *written += size;
+        self.rows += batch.num_rows();
+        self.bytes += size;
+        if *written >= self.chunk_bytes {
+            self.finish_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn finish_chunk(&mut self) -> Result<()> {
+        if let Some((mut writer, _)) = self.current.take() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Vec<PathBuf>, usize, usize)> {
+        self.finish_chunk()?;
+        Ok((self.chunks, self.rows, self.bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    fn batches(count: usize) -> (SchemaRef, Vec<RecordBatch>) {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let batches = (0..count)
+            .map(|i| {
+                let values = Int64Array::from(vec![i as i64; 1024]);
+                RecordBatch::try_new(SchemaRef::clone(&schema), vec![Arc::new(values)]).unwrap()
+            })
+            .collect();
+        (schema, batches)
+    }
+
+    fn stream(schema: SchemaRef, batches: Vec<RecordBatch>) -> SendableRecordBatchStream {
+        Box::pin(RecordBatchStreamAdapter::new(
+            schema,
+            futures::stream::iter(batches.into_iter().map(Ok)),
+        ))
+    }
+
+    #[tokio::test]
+    async fn spills_results_over_the_threshold_in_chunks() {
+        let (schema, input) = batches(4);
+        let batch_size = input[0].get_array_memory_size();
+        let config = FlightSQLServerSpillConfig {
+            memory_threshold_bytes: batch_size,
+            chunk_bytes: batch_size * 2,
+            directory: None,
+        };
+        let result = materialize(stream(schema, input.clone()), &config)
+            .await
+            .unwrap();
+        assert_eq!(result.chunks(), 2);
+        assert_eq!(result.total_rows(), 4 * 1024);
+
+        let first: Vec<RecordBatch> = result.stream_chunk(0).unwrap().try_collect().await.unwrap();
+        assert_eq!(first, input[..2]);
+
+        let read: Vec<RecordBatch> = Arc::new(result)
+            .stream(None)
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(read, input);
+    }
+
+    #[tokio::test]
+    async fn spilled_files_are_removed_with_the_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let (schema, input) = batches(2);
+        let config = FlightSQLServerSpillConfig {
+            memory_threshold_bytes: 0,
+            chunk_bytes: 1,
+            directory: Some(dir.path().to_path_buf()),
+        };
+        let result = materialize(stream(schema, input), &config).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        drop(result);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn removes_stale_spills() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("dft-results-left-behind")).unwrap();
+        std::fs::create_dir(dir.path().join("other")).unwrap();
+        let config = FlightSQLServerSpillConfig {
+            directory: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        // Results that could still be fetched are kept
+        assert_eq!(remove_stale_spills(&config, Duration::from_secs(60)), 0);
+        assert_eq!(remove_stale_spills(&config, Duration::ZERO), 1);
+        let left: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(left, ["other"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_small_results_in_memory() {
+        let (schema, input) = batches(2);
+        let config = FlightSQLServerSpillConfig::default();
+        let result = materialize(stream(schema, input), &config).await.unwrap();
+        assert!(matches!(result, MaterializedResult::Memory { .. }));
+        assert_eq!(result.chunks(), 1);
+    }
+}
@@ -0,0 +1,247 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Registry of the queries planned by `GetFlightInfo` whose results are fetched with `DoGet`
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion_app::cancel::QueryHandle;
+use datafusion_app::guardrails::QueryGuardrails;
+use datafusion_app::local::ExecutionContext;
+use datafusion_app::rewrite::QueryIdentity;
+use log::debug;
+use metrics::{counter, gauge};
+use tonic::Status;
+use uuid::Uuid;
+
+use super::spill::MaterializedResult;
+use crate::server::query_log::PendingQueryLogEntry;
+
+/// How long a ticket can be fetched after it was issued or last fetched by default
+pub const DEFAULT_TICKET_TTL: Duration = Duration::from_secs(300);
+
+/// A query planned by `GetFlightInfo`, executed when its tickets are fetched with `DoGet`
+#[derive(Clone)]
+pub struct TicketEntry {
+    pub plan: LogicalPlan,
+    /// SQL the plan was planned from, unset for the metadata queries the server plans itself
+    pub sql: Option<String>,
+    /// Who sent the query
+    pub identity: QueryIdentity,
+    /// Physical plan of a query whose partitions are returned as separate endpoints
+    pub physical_plan: Option<Arc<dyn ExecutionPlan>>,
+    /// Counts the results of every partition of the query against the guardrails
+    pub guardrails: QueryGuardrails,
+    /// The session the query was planned in, which it's executed with
+    pub execution: ExecutionContext,
+    /// Cancelled by `CancelFlightInfo`
+    pub cancellation: QueryHandle,
+    /// Query log entry shared by the result streams of the query, when it's logged. The store
+    /// releases it once every partition has been fetched, so it's recorded when the last of
+    /// their streams is dropped.
+    pub logged: Option<Arc<PendingQueryLogEntry>>,
+    /// Results of a query executed by `GetFlightInfo`, which are served instead of executing it
+    pub result: Option<Arc<MaterializedResult>>,
+    /// Number of endpoints the results are returned from
+    pub partitions: usize,
+}
+
+impl TicketEntry {
+    pub fn new(
+        plan: LogicalPlan,
+        physical_plan: Option<Arc<dyn ExecutionPlan>>,
+        execution: ExecutionContext,
+    ) -> Self {
+        Self {
+            plan,
+            sql: None,
+            identity: QueryIdentity::default(),
+            physical_plan,
+            guardrails: execution.guardrails().start_query(),
+            execution,
+            cancellation: QueryHandle::new(),
+            logged: None,
+            result: None,
+            partitions: 1,
+        }
+    }
+
+    pub fn with_query(mut self, sql: Option<String>, identity: QueryIdentity) -> Self {
+        self.sql = sql;
+        self.identity = identity;
+        self
+    }
+
+    pub fn with_result(mut self, result: Option<Arc<MaterializedResult>>) -> Self {
+        self.result = result;
+        self
+    }
+
+    pub fn with_partitions(mut self, partitions: usize) -> Self {
+        self.partitions = partitions;
+        self
+    }
+}
+
+struct RegisteredTicket {
+    entry: TicketEntry,
+    last_used: Instant,
+    /// Partitions whose results have been fetched
+    fetched: HashSet<usize>,
+    /// Cloned by the open result streams of the query
+    streams: Arc<()>,
+}
+
+/// Keeps a ticket registered while a stream of its results is open
+pub struct TicketStream {
+    _streams: Arc<()>,
+}
+
+/// The queries that can be fetched with `DoGet`. Tickets that haven't been fetched within the TTL
+/// of when they were issued, or last fetched, are dropped along with their plans and sessions,
+/// unless a stream of their results is still open.
+#[derive(Clone)]
+pub struct TicketStore {
+    tickets: Arc<Mutex<HashMap<Uuid, RegisteredTicket>>>,
+    ttl: Duration,
+}
+
+impl TicketStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            tickets: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<Uuid, RegisteredTicket>>, Status> {
+        self.tickets
+            .lock()
+            .map_err(|_| Status::internal("Failed to acquire lock on tickets"))
+    }
+
+    /// Register the query of the ticket `id`
+    pub fn register(&self, id: Uuid, entry: TicketEntry) -> Result<(), Status> {
+        let now = Instant::now();
+        let mut guard = self.lock()?;
+        self.collect(&mut guard, now);
+        guard.insert(
+            id,
+            RegisteredTicket {
+                entry,
+                last_used: now,
+                fetched: HashSet::new(),
+                streams: Arc::new(()),
+            },
+        );
+        gauge!("flightsql_tickets_active").set(guard.len() as f64);
+        Ok(())
+    }
+
+    /// The query of the ticket `id`, with a guard to hold for as long as the results of
+    /// `partition` are streamed
+    pub fn fetch(
+        &self,
+        id: &Uuid,
+        partition: Option<usize>,
+    ) -> Result<Option<(TicketEntry, TicketStream)>, Status> {
+        let now = Instant::now();
+        let mut guard = self.lock()?;
+        self.collect(&mut guard, now);
+        gauge!("flightsql_tickets_active").set(guard.len() as f64);
+        Ok(guard.get_mut(id).map(|ticket| {
+            ticket.last_used = now;
+            let stream = TicketStream {
+                _streams: Arc::clone(&ticket.streams),
+            };
+            let entry = ticket.entry.clone();
+            ticket.fetched.insert(partition.unwrap_or(0));
+            // Fetching a partition again isn't logged as another query
+            if ticket.fetched.len() >= ticket.entry.partitions {
+                ticket.entry.logged = None;
+            }
+            (entry, stream)
+        }))
+    }
+
+    /// The query of the ticket `id`, without counting as a fetch
+    pub fn get(&self, id: &Uuid) -> Result<Option<TicketEntry>, Status> {
+        Ok(self.lock()?.get(id).map(|ticket| ticket.entry.clone()))
+    }
+
+    /// Record the query of the ticket `id` in the query log once its results are fetched
+    pub fn set_logged(&self, id: &Uuid, logged: PendingQueryLogEntry) -> Result<(), Status> {
+        if let Some(ticket) = self.lock()?.get_mut(id) {
+            ticket.entry.logged = Some(Arc::new(logged));
+        }
+        Ok(())
+    }
+
+    /// Drop the tickets that expired by `now` and have no open result streams
+    fn collect(&self, tickets: &mut HashMap<Uuid, RegisteredTicket>, now: Instant) {
+        let before = tickets.len();
+        tickets.retain(|_, ticket| {
+            Arc::strong_count(&ticket.streams) > 1
+                || now.duration_since(ticket.last_used) < self.ttl
+        });
+        let expired = before - tickets.len();
+        if expired > 0 {
+            debug!("dropped {expired} expired tickets");
+            counter!("flightsql_tickets_expired").increment(expired as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> TicketEntry {
+        let execution = ExecutionContext::test();
+        TicketEntry::new(
+            LogicalPlan::EmptyRelation(datafusion::logical_expr::EmptyRelation {
+                produce_one_row: false,
+                schema: Arc::new(datafusion::common::DFSchema::empty()),
+            }),
+            None,
+            execution,
+        )
+    }
+
+    #[test]
+    fn expires_tickets_that_are_not_fetched() {
+        let store = TicketStore::new(Duration::ZERO);
+        let fetched = Uuid::new_v4();
+        store.register(fetched, entry()).unwrap();
+        let (_, stream) = store.fetch(&fetched, None).unwrap().unwrap();
+
+        // Registering collects the expired tickets, except those being streamed
+        let abandoned = Uuid::new_v4();
+        store.register(abandoned, entry()).unwrap();
+        store.register(Uuid::new_v4(), entry()).unwrap();
+        assert!(store.get(&abandoned).unwrap().is_none());
+        assert!(store.get(&fetched).unwrap().is_some());
+
+        drop(stream);
+        store.register(Uuid::new_v4(), entry()).unwrap();
+        assert!(store.get(&fetched).unwrap().is_none());
+    }
+}
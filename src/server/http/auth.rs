@@ -0,0 +1,186 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Authentication of requests to the HTTP server with the configured basic credentials or bearer
+//! token
+
+use std::convert::Infallible;
+
+use axum::{async_trait, body::Body, extract::FromRequestParts};
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use color_eyre::{eyre::eyre, Result};
+use datafusion_app::config::AuthConfig;
+use http::{header, request::Parts, HeaderValue, Request, Response, StatusCode};
+use tower_http::validate_request::ValidateRequest;
+
+use crate::server::{constant_time_eq, AuthenticatedUser};
+
+/// Accepts requests whose `authorization` header holds the configured credentials, adding the
+/// [`AuthenticatedUser`] of basic credentials to them
+#[derive(Clone, Debug)]
+pub struct RequireCredentials {
+    /// Every request is rejected when unset
+    expected: Option<HeaderValue>,
+    /// The user of basic credentials
+    user: Option<String>,
+    basic: bool,
+}
+
+impl RequireCredentials {
+    /// The credentials required by `auth`, or `None` if requests don't need to be authenticated.
+    /// Basic credentials are used if both kinds are configured, which the server rejects at
+    /// startup. Fails if the credentials can't be sent in a header.
+    pub fn try_from_config(auth: &AuthConfig) -> Result<Option<Self>> {
+        match (&auth.basic_auth, &auth.bearer_token) {
+            (Some(basic), _) => {
+                let encoded = STANDARD.encode(format!("{}:{}", basic.username, basic.password));
+                Ok(Some(Self {
+                    expected: Some(HeaderValue::from_str(&format!("Basic {encoded}"))?),
+                    user: Some(basic.username.clone()),
+                    basic: true,
+                }))
+            }
+            (None, Some(token)) => {
+                let expected = HeaderValue::from_str(&format!("Bearer {token}"))
+                    .map_err(|_| eyre!("The bearer token must only contain visible ASCII"))?;
+                Ok(Some(Self {
+                    expected: Some(expected),
+                    user: None,
+                    basic: false,
+                }))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Rejects every request, for when the configured credentials are invalid so that the
+    /// server doesn't accept requests without them
+    pub fn reject_all() -> Self {
+        Self {
+            expected: None,
+            user: None,
+            basic: false,
+        }
+    }
+}
+
+/// The user that authentication validated for a request, from its [`AuthenticatedUser`]
+/// extension. Unset when the server doesn't use basic auth.
+#[derive(Clone, Debug, Default)]
+pub struct RequestUser(pub Option<String>);
+
+impl RequestUser {
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestUser {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user = parts.extensions.get::<AuthenticatedUser>();
+        Ok(Self(user.map(|AuthenticatedUser(user)| user.clone())))
+    }
+}
+
+impl<B> ValidateRequest<B> for RequireCredentials {
+    type ResponseBody = Body;
+
+    fn validate(&mut self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        let authorized = match (request.headers().get(header::AUTHORIZATION), &self.expected) {
+            (Some(value), Some(expected)) => {
+                constant_time_eq(value.as_bytes(), expected.as_bytes())
+            }
+            _ => false,
+        };
+        if authorized {
+            if let Some(user) = &self.user {
+                request
+                    .extensions_mut()
+                    .insert(AuthenticatedUser(user.clone()));
+            }
+            return Ok(());
+        }
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::UNAUTHORIZED;
+        if self.basic {
+            res.headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+        }
+        Err(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion_app::config::BasicAuth;
+
+    use super::*;
+
+    fn validate(credentials: &RequireCredentials, value: Option<&str>) -> Option<Request<()>> {
+        let mut request = Request::builder();
+        if let Some(value) = value {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        let mut request = request.body(()).unwrap();
+        credentials
+            .clone()
+            .validate(&mut request)
+            .is_ok()
+            .then_some(request)
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        let auth = AuthConfig {
+            basic_auth: Some(BasicAuth {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            }),
+            ..Default::default()
+        };
+        let credentials = RequireCredentials::try_from_config(&auth).unwrap().unwrap();
+        let request = validate(&credentials, Some("Basic dXNlcjpwYXNz")).unwrap();
+        assert_eq!(
+            request.extensions().get::<AuthenticatedUser>(),
+            Some(&AuthenticatedUser("user".to_string()))
+        );
+        assert!(validate(&credentials, Some("Basic dXNlcjp3cm9uZw==")).is_none());
+        assert!(validate(&credentials, None).is_none());
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let auth = AuthConfig {
+            bearer_token: Some("token".to_string()),
+            ..Default::default()
+        };
+        let credentials = RequireCredentials::try_from_config(&auth).unwrap().unwrap();
+        let request = validate(&credentials, Some("Bearer token")).unwrap();
+        assert!(request.extensions().get::<AuthenticatedUser>().is_none());
+        assert!(validate(&credentials, Some("Bearer other")).is_none());
+
+        let auth = AuthConfig {
+            bearer_token: Some("bad\ntoken".to_string()),
+            ..Default::default()
+        };
+        assert!(RequireCredentials::try_from_config(&auth).is_err());
+        assert!(validate(&RequireCredentials::reject_all(), Some("Bearer token")).is_none());
+    }
+}
@@ -0,0 +1,43 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Compression of the query results returned by the HTTP server
+
+use tower_http::compression::{
+    predicate::{And, DefaultPredicate, NotForContentType, Predicate},
+    CompressionLayer,
+};
+
+use crate::config::HttpCompressionConfig;
+
+use super::format::ResultFormat;
+
+pub type ResultCompressionLayer = CompressionLayer<And<DefaultPredicate, NotForContentType>>;
+
+/// Builds the layer compressing results with the encodings enabled in `config` that the client
+/// accepts. Streamed `ndjson` results are left uncompressed so that rows reach the client as
+/// they're produced rather than once the encoder's buffer fills.
+pub fn compression_layer(config: &HttpCompressionConfig) -> ResultCompressionLayer {
+    let predicate =
+        DefaultPredicate::new().and(NotForContentType::new(ResultFormat::NdJson.content_type()));
+    CompressionLayer::new()
+        .gzip(config.gzip)
+        .br(config.br)
+        .zstd(config.zstd)
+        .no_deflate()
+        .compress_when(predicate)
+}
@@ -0,0 +1,110 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cross-origin resource sharing, so that browser frontends on other origins can call the server
+
+use std::time::Duration;
+
+use color_eyre::{eyre::eyre, Result};
+use http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::config::HttpCorsConfig;
+
+use super::{
+    overrides::{RESULT_LIMIT_HEADER, TIMEOUT_SECONDS_HEADER},
+    request_id::REQUEST_ID_HEADER,
+    sessions::SESSION_HEADER,
+    statements::QUERY_ID_HEADER,
+};
+
+/// Builds the layer answering preflight requests and adding CORS headers to responses for
+/// `config`, failing if it isn't valid
+pub fn try_cors_layer(config: &HttpCorsConfig) -> Result<CorsLayer> {
+    let any_origin = config.allowed_origins.iter().any(|origin| origin == "*");
+    if any_origin && config.allow_credentials {
+        return Err(eyre!(
+            "CORS credentials can't be allowed for any origin, list the allowed origins instead"
+        ));
+    }
+    let origins = if any_origin {
+        AllowOrigin::any()
+    } else {
+        let origins = config
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .map_err(|e| eyre!("invalid CORS origin '{origin}': {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = if config.allowed_methods.is_empty() {
+        vec![Method::GET, Method::POST, Method::DELETE]
+    } else {
+        config
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .map_err(|e| eyre!("invalid CORS method '{method}': {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let headers = if config.allowed_headers.is_empty() {
+        vec![
+            header::ACCEPT,
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(QUERY_ID_HEADER),
+            HeaderName::from_static(RESULT_LIMIT_HEADER),
+            HeaderName::from_static(TIMEOUT_SECONDS_HEADER),
+            HeaderName::from_static(SESSION_HEADER),
+            HeaderName::from_static(REQUEST_ID_HEADER),
+        ]
+    } else {
+        config
+            .allowed_headers
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|e| eyre!("invalid CORS header '{name}': {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.allow_credentials)
+        // Let scripts read the headers clients need to follow pages, manage queries and report issues
+        .expose_headers([
+            HeaderName::from_static("x-next-offset"),
+            HeaderName::from_static(QUERY_ID_HEADER),
+            HeaderName::from_static(SESSION_HEADER),
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            header::LOCATION,
+        ]);
+    if let Some(secs) = config.max_age_secs {
+        layer = layer.max_age(Duration::from_secs(secs));
+    }
+    Ok(layer)
+}
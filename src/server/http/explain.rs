@@ -0,0 +1,120 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Plans of statements described as trees of operators, for clients that visualize them
+
+use std::sync::Arc;
+
+use datafusion::{
+    arrow::datatypes::Schema,
+    error::Result,
+    logical_expr::LogicalPlan,
+    physical_plan::{displayable, ExecutionPlan},
+    prelude::SessionContext,
+};
+use datafusion_app::rewrite::{rewrite_plan, QueryIdentity};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlanColumn {
+    name: String,
+    data_type: String,
+    nullable: bool,
+}
+
+/// An operator of a plan and the operators it reads from
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlanNode {
+    /// The kind of operator, such as `Projection` or `FilterExec`
+    operator: String,
+    /// The operator as it's shown by `EXPLAIN`
+    details: String,
+    /// The columns the operator produces
+    schema: Vec<PlanColumn>,
+    #[schema(no_recursion)]
+    children: Vec<PlanNode>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExplainedPlans {
+    logical_plan: PlanNode,
+    optimized_logical_plan: PlanNode,
+    physical_plan: PlanNode,
+}
+
+/// Plans `sql` as sent by `identity` without executing it, returning the plans it goes through
+pub async fn explain(
+    ctx: &SessionContext,
+    sql: &str,
+    identity: &QueryIdentity,
+) -> Result<ExplainedPlans> {
+    let state = ctx.state();
+    let logical_plan = state.create_logical_plan(sql).await?;
+    let logical_plan = rewrite_plan(&state, logical_plan, identity)?;
+    let optimized_logical_plan = state.optimize(&logical_plan)?;
+    // Rather than `create_physical_plan`, which would optimize the logical plan again
+    let physical_plan = state
+        .query_planner()
+        .create_physical_plan(&optimized_logical_plan, &state)
+        .await?;
+    Ok(ExplainedPlans {
+        logical_plan: logical_node(&logical_plan),
+        optimized_logical_plan: logical_node(&optimized_logical_plan),
+        physical_plan: physical_node(&physical_plan),
+    })
+}
+
+fn columns(schema: &Schema) -> Vec<PlanColumn> {
+    schema
+        .fields()
+        .iter()
+        .map(|f| PlanColumn {
+            name: f.name().clone(),
+            data_type: f.data_type().to_string(),
+            nullable: f.is_nullable(),
+        })
+        .collect()
+}
+
+fn logical_node(plan: &LogicalPlan) -> PlanNode {
+    let details = plan.display().to_string();
+    // Logical operators are displayed as `Name: details`
+    let operator = details
+        .split_once(':')
+        .map_or(details.as_str(), |(operator, _)| operator)
+        .to_string();
+    PlanNode {
+        operator,
+        details,
+        schema: columns(plan.schema().as_arrow()),
+        children: plan.inputs().into_iter().map(logical_node).collect(),
+    }
+}
+
+fn physical_node(plan: &Arc<dyn ExecutionPlan>) -> PlanNode {
+    PlanNode {
+        operator: plan.name().to_string(),
+        details: displayable(plan.as_ref())
+            .one_line()
+            .to_string()
+            .trim_end()
+            .to_string(),
+        schema: columns(&plan.schema()),
+        children: plan.children().into_iter().map(physical_node).collect(),
+    }
+}
@@ -0,0 +1,258 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Encodings the HTTP server can return query results in
+
+use std::sync::Arc;
+
+use datafusion::arrow::{
+    array::RecordBatch,
+    csv,
+    datatypes::SchemaRef,
+    error::ArrowError,
+    ipc::writer::StreamWriter,
+    json::{ArrayWriter, LineDelimitedWriter},
+};
+use http::{header::ACCEPT, HeaderMap};
+
+/// Format of a query result, picked from the `format` query param or the `Accept` header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResultFormat {
+    /// A single JSON array of row objects
+    #[default]
+    Json,
+    /// Newline delimited JSON, streamed as batches are produced
+    NdJson,
+    /// CSV with a header row
+    Csv,
+    /// Arrow IPC stream
+    Arrow,
+}
+
+impl ResultFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ResultFormat::Json => "application/json",
+            ResultFormat::NdJson => "application/x-ndjson",
+            ResultFormat::Csv => "text/csv",
+            ResultFormat::Arrow => "application/vnd.apache.arrow.stream",
+        }
+    }
+
+    fn from_param(param: &str) -> Option<Self> {
+        match param.to_lowercase().as_str() {
+            "json" => Some(ResultFormat::Json),
+            "ndjson" => Some(ResultFormat::NdJson),
+            "csv" => Some(ResultFormat::Csv),
+            "arrow" => Some(ResultFormat::Arrow),
+            _ => None,
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        [
+            ResultFormat::Json,
+            ResultFormat::NdJson,
+            ResultFormat::Csv,
+            ResultFormat::Arrow,
+        ]
+        .into_iter()
+        .find(|format| format.content_type().eq_ignore_ascii_case(media_type))
+    }
+
+    /// Uses the `format` param when given, otherwise the supported media type in the `Accept`
+    /// header with the highest quality, the first one listed among equals. Media types with a
+    /// quality of 0 are never picked. Falls back to JSON when the header names nothing we
+    /// support (such as `*/*`).
+    pub fn negotiate(param: Option<&str>, headers: &HeaderMap) -> Result<Self, String> {
+        if let Some(param) = param {
+            return Self::from_param(param).ok_or_else(|| {
+                format!("Unsupported format '{param}', expected one of json, ndjson, csv, or arrow")
+            });
+        }
+        let mut best: Option<(Self, f32)> = None;
+        for media_range in headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let mut parts = media_range.split(';');
+            let Some(format) = parts
+                .next()
+                .and_then(|media_type| Self::from_media_type(media_type.trim()))
+            else {
+                continue;
+            };
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((format, quality));
+            }
+        }
+        Ok(best.map(|(format, _)| format).unwrap_or_default())
+    }
+}
+
+/// Writes batches into an in memory buffer in a [`ResultFormat`]
+pub enum BatchWriter {
+    Json(ArrayWriter<Vec<u8>>),
+    NdJson(LineDelimitedWriter<Vec<u8>>),
+    Csv(csv::Writer<Vec<u8>>),
+    Arrow(StreamWriter<Vec<u8>>),
+}
+
+impl BatchWriter {
+    pub fn try_new(format: ResultFormat, schema: &SchemaRef) -> Result<Self, ArrowError> {
+        let writer = match format {
+            ResultFormat::Json => BatchWriter::Json(ArrayWriter::new(Vec::new())),
+            ResultFormat::NdJson => BatchWriter::NdJson(LineDelimitedWriter::new(Vec::new())),
+            ResultFormat::Csv => {
+                // The header is written up front so that empty results still name their columns
+                let mut writer = csv::Writer::new(Vec::new());
+                writer.write(&RecordBatch::new_empty(Arc::clone(schema)))?;
+                BatchWriter::Csv(writer)
+            }
+            // The schema is written up front so that empty results can still be read
+            ResultFormat::Arrow => BatchWriter::Arrow(StreamWriter::try_new(Vec::new(), schema)?),
+        };
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), ArrowError> {
+        match self {
+            BatchWriter::Json(writer) => writer.write(batch),
+            BatchWriter::NdJson(writer) => writer.write(batch),
+            BatchWriter::Csv(writer) => writer.write(batch),
+            BatchWriter::Arrow(writer) => writer.write(batch),
+        }
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, ArrowError> {
+        match self {
+            BatchWriter::Json(mut writer) => {
+                writer.finish()?;
+                Ok(writer.into_inner())
+            }
+            BatchWriter::NdJson(mut writer) => {
+                writer.finish()?;
+                Ok(writer.into_inner())
+            }
+            BatchWriter::Csv(writer) => Ok(writer.into_inner()),
+            BatchWriter::Arrow(mut writer) => {
+                writer.finish()?;
+                writer.into_inner()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use datafusion::arrow::{
+        array::{Int64Array, RecordBatch, StringArray},
+        datatypes::{DataType, Field, Schema},
+    };
+    use http::{header::ACCEPT, HeaderMap, HeaderValue};
+
+    use super::{BatchWriter, ResultFormat};
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let none = HeaderMap::new();
+        assert_eq!(
+            ResultFormat::negotiate(None, &none).unwrap(),
+            ResultFormat::Json
+        );
+        assert_eq!(
+            ResultFormat::negotiate(None, &accept("*/*")).unwrap(),
+            ResultFormat::Json
+        );
+        assert_eq!(
+            ResultFormat::negotiate(None, &accept("text/html, text/csv;q=0.9")).unwrap(),
+            ResultFormat::Csv
+        );
+        assert_eq!(
+            ResultFormat::negotiate(None, &accept("application/vnd.apache.arrow.stream")).unwrap(),
+            ResultFormat::Arrow
+        );
+        // The query param takes precedence over the header
+        assert_eq!(
+            ResultFormat::negotiate(Some("ndjson"), &accept("text/csv")).unwrap(),
+            ResultFormat::NdJson
+        );
+        assert!(ResultFormat::negotiate(Some("parquet"), &none).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_quality() {
+        assert_eq!(
+            ResultFormat::negotiate(None, &accept("application/json;q=0.5, text/csv")).unwrap(),
+            ResultFormat::Csv
+        );
+        assert_eq!(
+            ResultFormat::negotiate(
+                None,
+                &accept("text/csv; q=0.8, application/vnd.apache.arrow.stream; q=0.9")
+            )
+            .unwrap(),
+            ResultFormat::Arrow
+        );
+        // The first of equally preferred types wins
+        assert_eq!(
+            ResultFormat::negotiate(None, &accept("application/x-ndjson, text/csv")).unwrap(),
+            ResultFormat::NdJson
+        );
+        // Types with a quality of 0 are not acceptable
+        assert_eq!(
+            ResultFormat::negotiate(None, &accept("text/csv;q=0")).unwrap(),
+            ResultFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_empty_csv_has_header() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let writer = BatchWriter::try_new(ResultFormat::Csv, &schema).unwrap();
+        assert_eq!(writer.finish().unwrap(), b"a,b\n");
+
+        let mut writer = BatchWriter::try_new(ResultFormat::Csv, &schema).unwrap();
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["x"])),
+            ],
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        assert_eq!(writer.finish().unwrap(), b"a,b\n1,x\n");
+    }
+}
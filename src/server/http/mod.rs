@@ -15,60 +15,56 @@
 // specific language governing permissions and limitations
 // under the License.
 
+mod auth;
+mod compression;
+mod cors;
+mod explain;
+mod format;
+mod overrides;
+mod params;
+mod rate_limit;
+mod ready;
+mod request_id;
+mod request_metrics;
 mod router;
+pub mod sessions;
+mod statements;
 mod tpch;
 
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 use crate::{
     args::{Command, DftArgs},
     config::AppConfig,
-    db::register_db,
+    db::{
+        add_catalog_persistence, register_db, restore_catalog, restore_system_tables,
+        save_system_tables,
+    },
     execution::AppExecution,
+    server::{query_log::QueryLog, tls},
 };
 use axum::Router;
-use color_eyre::Result;
-use datafusion_app::{
-    config::merge_configs, extensions::DftSessionStateBuilder, local::ExecutionContext,
+use color_eyre::{eyre::eyre, Result};
+use datafusion_app::{extensions::DftSessionStateBuilder, local::ExecutionContext};
+use futures::StreamExt;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
 };
 use router::create_router;
-use tokio::{net::TcpListener, signal};
-use tracing::{debug, info};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, info, warn};
 #[cfg(feature = "flightsql")]
 use {
-    datafusion_app::{
-        config::{AuthConfig, FlightSQLConfig},
-        flightsql::FlightSQLContext,
-    },
+    datafusion_app::{config::FlightSQLConfig, flightsql::FlightSQLContext},
     tracing::error,
 };
 
-use super::try_start_metrics_server;
-
-/// From https://github.com/tokio-rs/axum/blob/main/examples/graceful-shutdown/src/main.rs
-async fn shutdown_signal() {
-    let ctrl_c = async {
-        signal::ctrl_c()
-            .await
-            .expect("failed to install Ctrl+C handler");
-    };
-
-    #[cfg(unix)]
-    let terminate = async {
-        signal::unix::signal(signal::unix::SignalKind::terminate())
-            .expect("failed to install signal handler")
-            .recv()
-            .await;
-    };
-
-    #[cfg(not(unix))]
-    let terminate = std::future::pending::<()>();
-
-    tokio::select! {
-        _ = ctrl_c => {},
-        _ = terminate => {},
-    }
-}
+use super::{server_execution_config, shutdown_signal, try_start_metrics_server, MetricsServer};
 
 /// Creates and manages a running FlightSqlServer with a background task
 pub struct HttpApp {
@@ -77,47 +73,147 @@ pub struct HttpApp {
 
     /// handle for the server task
     router: Router,
+
+    /// Prometheus exporter, stopped once the server shuts down
+    metrics: Option<MetricsServer>,
+
+    /// Performs the TLS handshake for new connections when serving over TLS
+    tls: Option<TlsAcceptor>,
 }
 
 impl HttpApp {
-    /// Create a new HTTP server app
+    /// Create a new HTTP server app, serving metrics on `metrics_addr` unless they are served by
+    /// another app in the process
     pub async fn try_new(
         execution: AppExecution,
         config: AppConfig,
         addr: SocketAddr,
-        metrics_addr: SocketAddr,
+        metrics_addr: Option<SocketAddr>,
     ) -> Result<Self> {
-        info!("listening to HTTP on {addr}");
+        let auth = &config.http_server.auth;
+        if auth.basic_auth.is_some() && auth.bearer_token.is_some() {
+            return Err(eyre!("Only one auth type can be used at a time"));
+        }
+        auth::RequireCredentials::try_from_config(auth)?;
+        if let Some(cors) = &config.http_server.cors {
+            cors::try_cors_layer(cors)?;
+        }
+        // Browsers only use HTTP/2 when it's negotiated during the handshake
+        let tls = config
+            .http_server
+            .tls
+            .as_ref()
+            .map(|tls| tls::try_create_acceptor(tls, &[b"h2", b"http/1.1"]))
+            .transpose()?;
+        if tls.is_some() {
+            info!("listening to HTTPS on {addr}");
+        } else {
+            info!("listening to HTTP on {addr}");
+        }
         let listener = TcpListener::bind(addr).await.unwrap();
+        execution.query_jobs().start_sweeping();
         let router = create_router(execution, config.http_server);
 
-        try_start_metrics_server(metrics_addr)?;
+        let metrics = metrics_addr.map(try_start_metrics_server).transpose()?;
 
-        let app = Self { listener, router };
+        let app = Self {
+            listener,
+            router,
+            metrics,
+            tls,
+        };
         Ok(app)
     }
 
     pub async fn run(self) {
-        match axum::serve(self.listener, self.router)
-            .with_graceful_shutdown(shutdown_signal())
-            .await
-        {
-            Ok(_) => {
+        match self.tls {
+            Some(acceptor) => {
+                serve_tls(self.listener, acceptor, self.router, shutdown_signal()).await;
                 info!("Shutting down app")
             }
-            Err(_) => {
-                panic!("Error serving HTTP app")
+            None => {
+                let service = self
+                    .router
+                    .into_make_service_with_connect_info::<SocketAddr>();
+                match axum::serve(self.listener, service)
+                    .with_graceful_shutdown(shutdown_signal())
+                    .await
+                {
+                    Ok(_) => {
+                        info!("Shutting down app")
+                    }
+                    Err(_) => {
+                        panic!("Error serving HTTP app")
+                    }
+                }
             }
         }
+        if let Some(metrics) = self.metrics {
+            metrics.shutdown();
+        }
     }
 }
 
+/// Serve `router` on the connections from `listener` that complete the TLS handshake until
+/// `shutdown` completes, then stop accepting connections and wait for the open ones to finish
+/// their requests
+async fn serve_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    router: Router,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut make_service = router.into_make_service_with_connect_info::<SocketAddr>();
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    let mut incoming = tls::incoming(listener, acceptor);
+    tokio::pin!(shutdown);
+    loop {
+        let stream = tokio::select! {
+            stream = incoming.next() => match stream {
+                Some(Ok(stream)) => stream,
+                Some(Err(e)) => {
+                    warn!("Error accepting connection: {e}");
+                    continue;
+                }
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        };
+        let peer = match stream.get_ref().0.peer_addr() {
+            Ok(peer) => peer,
+            Err(e) => {
+                warn!("Error reading peer address: {e}");
+                continue;
+            }
+        };
+        let service = match make_service.call(peer).await {
+            Ok(service) => service,
+            Err(infallible) => match infallible {},
+        };
+        let conn = builder
+            .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+            .into_owned();
+        let conn = graceful.watch(conn);
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                debug!("Error serving connection from {peer}: {e}");
+            }
+        });
+    }
+    // Closes the listener
+    drop(incoming);
+    graceful.shutdown().await;
+}
+
 pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
     let merged_exec_config =
-        merge_configs(config.shared.clone(), config.http_server.execution.clone());
-    let session_state_builder = DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
-        .with_extensions()
-        .await?;
+        server_execution_config(config.shared.clone(), config.http_server.execution.clone());
+    let mut session_state_builder =
+        DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
+            .with_extensions()
+            .await?;
+    add_catalog_persistence(&mut session_state_builder, &config.db)?;
     let session_state = session_state_builder.build()?;
     let execution_ctx = ExecutionContext::try_new(
         &merged_exec_config,
@@ -125,42 +221,21 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         crate::APP_NAME,
         env!("CARGO_PKG_VERSION"),
     )?;
-    if cli.run_ddl {
-        execution_ctx.execute_ddl().await;
-    }
-
-    #[allow(unused_mut)]
+    restore_system_tables(execution_ctx.system_tables(), &config.db)?;
+    let system_tables = execution_ctx.system_tables().clone();
+    // Refreshes listing tables until the app exits
+    let _catalog_refresh = execution_ctx.start_catalog_refresh();
+    let ddl_errors = if cli.run_ddl {
+        Some(execution_ctx.execute_ddl().await)
+    } else {
+        None
+    };
     let mut app_execution = AppExecution::new(execution_ctx);
-    #[cfg(feature = "flightsql")]
-    {
-        info!("Setting up FlightSQLContext");
-        let auth = AuthConfig {
-            basic_auth: config.flightsql_client.auth.basic_auth.clone(),
-            bearer_token: config.flightsql_client.auth.bearer_token.clone(),
-        };
-        let flightsql_cfg = FlightSQLConfig::new(
-            config.flightsql_client.connection_url.clone(),
-            config.flightsql_client.benchmark_iterations,
-            auth,
-            config.flightsql_client.headers.clone(),
-            config.flightsql_client.max_decoding_message_size,
-            config.flightsql_client.max_encoding_message_size,
-        );
-
-        let flightsql_context = FlightSQLContext::new(flightsql_cfg.clone());
-        // TODO - Consider adding flag to allow startup even if FlightSQL initiation fails
-        if let Err(e) = flightsql_context
-            .create_client(
-                Some(flightsql_cfg.connection_url),
-                Some(flightsql_cfg.headers),
-            )
-            .await
-        {
-            error!("{}", e.to_string())
-        } else {
-            app_execution.with_flightsql_ctx(flightsql_context);
-        }
+    if let Some(errors) = ddl_errors {
+        app_execution.with_ddl_errors(errors);
     }
+    #[cfg(feature = "flightsql")]
+    connect_flightsql_client(&mut app_execution, &config).await;
     debug!("Created AppExecution: {app_execution:?}");
     let (addr, metrics_addr) = if let Some(cmd) = cli.command.clone() {
         match cmd {
@@ -195,8 +270,124 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         )
     };
     register_db(app_execution.session_ctx(), &config.db).await?;
-    let app = HttpApp::try_new(app_execution, config.clone(), addr, metrics_addr).await?;
+    restore_catalog(&app_execution.execution_ctx().ddl_session_ctx(), &config.db).await?;
+    app_execution.with_db_path(config.db.path.clone());
+    let query_log = if config.db.query_log {
+        let query_log = QueryLog::try_new(app_execution.session_ctx(), &config.db).await?;
+        app_execution.with_query_log(query_log.clone());
+        Some(query_log)
+    } else {
+        None
+    };
+    let app = HttpApp::try_new(app_execution, config.clone(), addr, Some(metrics_addr)).await?;
     app.run().await;
+    save_system_tables(&system_tables, &config.db)?;
+    if let Some(query_log) = query_log {
+        query_log.flush().await?;
+    }
 
     Ok(())
 }
+
+/// Connect the FlightSQL client used by requests that run on a FlightSQL server. The server
+/// starts without it when the connection fails.
+#[cfg(feature = "flightsql")]
+pub(crate) async fn connect_flightsql_client(app_execution: &mut AppExecution, config: &AppConfig) {
+    info!("Setting up FlightSQLContext");
+    let auth = config.flightsql_client.auth.clone();
+    let flightsql_cfg = FlightSQLConfig::new(
+        config.flightsql_client.connection_url.clone(),
+        config.flightsql_client.benchmark_iterations,
+        auth,
+        config.flightsql_client.headers.clone(),
+        config.flightsql_client.max_decoding_message_size,
+        config.flightsql_client.max_encoding_message_size,
+        config.flightsql_client.tls.clone(),
+    );
+
+    let flightsql_context = FlightSQLContext::new(flightsql_cfg.clone());
+    // TODO - Consider adding flag to allow startup even if FlightSQL initiation fails
+    if let Err(e) = flightsql_context
+        .create_client(
+            Some(flightsql_cfg.connection_url),
+            Some(flightsql_cfg.headers),
+        )
+        .await
+    {
+        error!("{}", e.to_string())
+    } else {
+        app_execution.with_flightsql_ctx(flightsql_context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use datafusion_app::config::ExecutionConfig;
+    use rustls::pki_types::ServerName;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::config::HttpServerConfig;
+    use crate::server::tls::tests::{connector, self_signed_config};
+
+    #[tokio::test]
+    async fn test_serve_https() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tls_config, cert) = self_signed_config(dir.path());
+        let acceptor = tls::try_create_acceptor(&tls_config, &[b"h2", b"http/1.1"]).unwrap();
+        let config = ExecutionConfig::default();
+        let state = DftSessionStateBuilder::try_new(None)
+            .unwrap()
+            .build()
+            .unwrap();
+        let local =
+            ExecutionContext::try_new(&config, state, crate::APP_NAME, env!("CARGO_PKG_VERSION"))
+                .unwrap();
+        let router = create_router(AppExecution::new(local), HttpServerConfig::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_tls(listener, acceptor, router, async move {
+            let _ = shutdown_rx.await;
+        }));
+
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector(cert, &[b"http/1.1"])
+            .connect(server_name, tcp)
+            .await
+            .unwrap();
+        stream
+            .write_all(b"GET /ready HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        let mut buf = [0; 1024];
+        while !response.windows(2).any(|w| w == b"\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before the response");
+            response.extend_from_slice(&buf[..n]);
+        }
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        drop(stream);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+        let mut closed = false;
+        for _ in 0..50 {
+            if TcpStream::connect(addr).await.is_err() {
+                closed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            closed,
+            "listener still accepting connections after shutdown"
+        );
+    }
+}
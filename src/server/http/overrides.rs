@@ -0,0 +1,111 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Headers that let a request override the server's configured result limit and timeout, up to
+//! the configured maxima
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{HeaderMap, StatusCode};
+
+use crate::config::HttpServerConfig;
+
+/// Header holding the most rows the request returns
+pub const RESULT_LIMIT_HEADER: &str = "x-dft-result-limit";
+
+/// Header holding how long the server can take to respond to the request
+pub const TIMEOUT_SECONDS_HEADER: &str = "x-dft-timeout-seconds";
+
+/// Reads a header overriding a configured value, which must be a positive integer no larger than
+/// `max`
+fn header_override(headers: &HeaderMap, name: &str, max: u64) -> Result<Option<u64>, Response> {
+    let Some(value) = headers.get(name) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("{name} must be a positive integer"),
+            )
+                .into_response()
+        })?;
+    if value > max {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{name} can be at most {max}"),
+        )
+            .into_response());
+    }
+    Ok(Some(value))
+}
+
+/// The result limit of a request, the configured `result_limit` unless the request sets a
+/// [`RESULT_LIMIT_HEADER`] of up to `max_result_limit`
+pub fn result_limit(headers: &HeaderMap, config: &HttpServerConfig) -> Result<usize, Response> {
+    let max = config.max_result_limit.unwrap_or(config.result_limit);
+    let limit = header_override(headers, RESULT_LIMIT_HEADER, max as u64)?;
+    Ok(limit.map_or(config.result_limit, |limit| limit as usize))
+}
+
+/// The bounds of the requests' timeouts
+#[derive(Clone, Copy, Debug)]
+pub struct TimeoutLimits {
+    default: Duration,
+    max_seconds: u64,
+}
+
+impl TimeoutLimits {
+    pub fn new(config: &HttpServerConfig) -> Self {
+        Self {
+            default: Duration::from_secs(config.timeout_seconds),
+            max_seconds: config.max_timeout_seconds.unwrap_or(config.timeout_seconds),
+        }
+    }
+
+    fn timeout(&self, headers: &HeaderMap) -> Result<Duration, Response> {
+        let seconds = header_override(headers, TIMEOUT_SECONDS_HEADER, self.max_seconds)?;
+        Ok(seconds.map_or(self.default, Duration::from_secs))
+    }
+}
+
+/// Responds with a `408 Request Timeout` when the response isn't ready within the request's
+/// timeout. Graceful shutdown waits for outstanding requests to complete, so this also keeps
+/// requests from hanging it forever.
+pub async fn enforce_timeout(
+    State(limits): State<TimeoutLimits>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let timeout = match limits.timeout(req.headers()) {
+        Ok(timeout) => timeout,
+        Err(res) => return res,
+    };
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(res) => res,
+        Err(_) => StatusCode::REQUEST_TIMEOUT.into_response(),
+    }
+}
@@ -0,0 +1,126 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Values bound to the placeholders of statements sent to the HTTP server
+
+use std::collections::HashMap;
+
+use datafusion::{common::ParamValues, scalar::ScalarValue};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The `params` of a request, a list for `$1`, `$2`, ... placeholders or an object for `$name`
+/// placeholders
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SqlParams {
+    Positional(Vec<Value>),
+    Named(HashMap<String, Value>),
+}
+
+impl SqlParams {
+    pub fn try_into_param_values(self) -> Result<ParamValues, String> {
+        match self {
+            Self::Positional(values) => {
+                let scalars = values
+                    .iter()
+                    .map(json_to_scalar)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(ParamValues::from(scalars))
+            }
+            Self::Named(values) => {
+                let scalars = values
+                    .iter()
+                    .map(|(name, value)| {
+                        // Allow naming the placeholder as written in the SQL
+                        let name = name.strip_prefix('$').unwrap_or(name).to_string();
+                        json_to_scalar(value).map(|scalar| (name, scalar))
+                    })
+                    .collect::<Result<HashMap<_, _>, _>>()?;
+                Ok(ParamValues::from(scalars))
+            }
+        }
+    }
+}
+
+/// Converts a JSON value to the literal it's bound as, which DataFusion casts to the type the
+/// placeholder is used as
+fn json_to_scalar(value: &Value) -> Result<ScalarValue, String> {
+    match value {
+        Value::Null => Ok(ScalarValue::Null),
+        Value::Bool(b) => Ok(ScalarValue::Boolean(Some(*b))),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(ScalarValue::Int64(Some(i)))
+            } else if let Some(u) = n.as_u64() {
+                Ok(ScalarValue::UInt64(Some(u)))
+            } else {
+                Ok(ScalarValue::Float64(n.as_f64()))
+            }
+        }
+        Value::String(s) => Ok(ScalarValue::Utf8(Some(s.clone()))),
+        Value::Array(_) | Value::Object(_) => Err(format!(
+            "Unsupported parameter {value}, parameters must be null, a boolean, a number or a string"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use datafusion::scalar::ScalarValue;
+    use serde_json::json;
+
+    use super::{json_to_scalar, SqlParams};
+
+    #[test]
+    fn test_json_to_scalar() {
+        assert_eq!(json_to_scalar(&json!(null)).unwrap(), ScalarValue::Null);
+        assert_eq!(
+            json_to_scalar(&json!(true)).unwrap(),
+            ScalarValue::Boolean(Some(true))
+        );
+        assert_eq!(
+            json_to_scalar(&json!(-1)).unwrap(),
+            ScalarValue::Int64(Some(-1))
+        );
+        assert_eq!(
+            json_to_scalar(&json!(u64::MAX)).unwrap(),
+            ScalarValue::UInt64(Some(u64::MAX))
+        );
+        assert_eq!(
+            json_to_scalar(&json!(2.5)).unwrap(),
+            ScalarValue::Float64(Some(2.5))
+        );
+        assert_eq!(
+            json_to_scalar(&json!("a")).unwrap(),
+            ScalarValue::Utf8(Some("a".to_string()))
+        );
+        assert!(json_to_scalar(&json!([1])).is_err());
+    }
+
+    #[test]
+    fn test_sql_params() {
+        let positional: SqlParams = serde_json::from_value(json!([1, "a"])).unwrap();
+        assert!(matches!(positional, SqlParams::Positional(_)));
+        let named: SqlParams = serde_json::from_value(json!({"$id": 1, "name": "a"})).unwrap();
+        assert!(matches!(named, SqlParams::Named(_)));
+        assert!(named.try_into_param_values().is_ok());
+
+        let nested: SqlParams = serde_json::from_value(json!([{"a": 1}])).unwrap();
+        assert!(nested.try_into_param_values().is_err());
+    }
+}
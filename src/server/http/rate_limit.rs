@@ -0,0 +1,99 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Per client rate and concurrency limits of requests to the HTTP server
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header::RETRY_AFTER, StatusCode};
+use log::debug;
+use metrics::counter;
+
+use crate::{
+    config::HttpRateLimitConfig,
+    server::{
+        rate_limit::{Buckets, InProgressRequests},
+        AuthenticatedUser,
+    },
+};
+
+/// Rejects requests from clients that exceed their rate limit or have too many requests in
+/// progress with a `429 Too Many Requests`, telling them when to retry with a `Retry-After`
+/// header
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    buckets: Option<Buckets>,
+    in_progress: InProgressRequests,
+}
+
+impl RateLimiter {
+    pub fn new(config: &HttpRateLimitConfig) -> Self {
+        Self {
+            buckets: config
+                .requests_per_second
+                .map(|requests_per_second| Buckets::new(requests_per_second, config.burst)),
+            in_progress: InProgressRequests::new(config.max_concurrent_requests),
+        }
+    }
+}
+
+fn too_many_requests(client: &str, retry_after_secs: u64, reason: &str) -> Response {
+    debug!("throttling request from {client}: {reason}");
+    counter!("http_requests_throttled").increment(1);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_after_secs.to_string())],
+        format!("{reason}, try again later"),
+    )
+        .into_response()
+}
+
+pub async fn enforce_rate_limit(
+    State(limiter): State<RateLimiter>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let client = client_identity(&req);
+    if let Some(buckets) = &limiter.buckets {
+        if let Err(wait) = buckets.try_acquire(&client, Instant::now()) {
+            let retry_after = wait.as_secs_f64().ceil() as u64;
+            return too_many_requests(&client, retry_after.max(1), "Rate limit exceeded");
+        }
+    }
+    let Some(_in_progress) = limiter.in_progress.try_start(&client) else {
+        return too_many_requests(&client, 1, "Too many concurrent requests");
+    };
+    next.run(req).await
+}
+
+/// The identity that a client's requests are counted against: the user that authentication
+/// validated, or else the client's IP address
+fn client_identity(req: &Request) -> String {
+    if let Some(AuthenticatedUser(user)) = req.extensions().get::<AuthenticatedUser>() {
+        return format!("user:{user}");
+    }
+    match req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
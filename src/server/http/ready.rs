@@ -0,0 +1,214 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Readiness checks, telling orchestrators whether the server can serve queries
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use datafusion::datasource::listing::ListingTableUrl;
+use log::warn;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::{config::HttpServerConfig, execution::AppExecution};
+
+/// How long a check can take before it fails, so that an unreachable dependency doesn't hang
+/// the request
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the result of the checks is reused, so that frequent probes don't run them on every
+/// request
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    /// The check doesn't apply to this server
+    Skipped,
+}
+
+/// The result of a check. Why a check failed is logged rather than returned, since the probe
+/// doesn't need credentials.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct CheckResult {
+    status: CheckStatus,
+}
+
+impl CheckResult {
+    fn skipped() -> Self {
+        Self {
+            status: CheckStatus::Skipped,
+        }
+    }
+
+    fn from_result(check: &str, result: Result<()>) -> Self {
+        let status = match result {
+            Ok(()) => CheckStatus::Ok,
+            Err(e) => {
+                warn!("Readiness check {check} failed: {e}");
+                CheckStatus::Failed
+            }
+        };
+        Self { status }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    /// Whether no check failed
+    ready: bool,
+    checks: BTreeMap<&'static str, CheckResult>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+}
+
+/// The result of the last checks, shared by the clones so that concurrent probes wait for the
+/// same checks
+#[derive(Clone, Debug, Default)]
+pub struct ReadinessCache(Arc<Mutex<Option<(Instant, Readiness)>>>);
+
+impl ReadinessCache {
+    /// The result of the checks run within the last [`CACHE_TTL`], or else of running them
+    pub async fn check(&self, execution: &AppExecution, config: &HttpServerConfig) -> Readiness {
+        let mut cached = self.0.lock().await;
+        if let Some((checked, readiness)) = cached.as_ref() {
+            if checked.elapsed() < CACHE_TTL {
+                return readiness.clone();
+            }
+        }
+        let readiness = check_readiness(execution, config).await;
+        *cached = Some((Instant::now(), readiness.clone()));
+        readiness
+    }
+}
+
+/// Runs the checks that apply to the server:
+///
+/// - `ddl`: the DDL executed at startup, or when it was last reloaded, didn't fail
+/// - `session`: the server's session can plan and execute a query
+/// - `catalog`: the default catalog and schema are registered
+/// - `db`: the object store holding the database can be listed
+/// - `flightsql`: the FlightSQL server that queries can be sent to answers, when
+///   `ready_checks_flightsql` is set
+async fn check_readiness(execution: &AppExecution, config: &HttpServerConfig) -> Readiness {
+    let mut checks = BTreeMap::new();
+    checks.insert("ddl", check_ddl(execution));
+    checks.insert(
+        "session",
+        CheckResult::from_result("session", with_timeout(check_session(execution)).await),
+    );
+    checks.insert(
+        "catalog",
+        CheckResult::from_result("catalog", check_catalog(execution)),
+    );
+    let db = match execution.db_path() {
+        Some(db_path) => {
+            CheckResult::from_result("db", with_timeout(check_db(execution, db_path)).await)
+        }
+        None => CheckResult::skipped(),
+    };
+    checks.insert("db", db);
+    #[cfg(feature = "flightsql")]
+    let flightsql = if config.ready_checks_flightsql {
+        CheckResult::from_result("flightsql", with_timeout(check_flightsql(execution)).await)
+    } else {
+        CheckResult::skipped()
+    };
+    #[cfg(not(feature = "flightsql"))]
+    let flightsql = {
+        let _ = config;
+        CheckResult::skipped()
+    };
+    checks.insert("flightsql", flightsql);
+
+    let ready = checks
+        .values()
+        .all(|check| check.status != CheckStatus::Failed);
+    Readiness { ready, checks }
+}
+
+async fn with_timeout(check: impl Future<Output = Result<()>>) -> Result<()> {
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .map_err(|_| eyre!("Timed out after {}s", CHECK_TIMEOUT.as_secs()))?
+}
+
+fn check_ddl(execution: &AppExecution) -> CheckResult {
+    let result = match execution.ddl_errors().get() {
+        None => return CheckResult::skipped(),
+        Some(errors) if errors.is_empty() => Ok(()),
+        Some(errors) => Err(eyre!(
+            "{} DDL statement(s) failed: {}",
+            errors.len(),
+            errors.join("; ")
+        )),
+    };
+    CheckResult::from_result("ddl", result)
+}
+
+async fn check_session(execution: &AppExecution) -> Result<()> {
+    execution
+        .session_ctx()
+        .sql("SELECT 1")
+        .await?
+        .collect()
+        .await?;
+    Ok(())
+}
+
+fn check_catalog(execution: &AppExecution) -> Result<()> {
+    let ctx = execution.session_ctx();
+    let options = ctx.state().config_options().catalog.clone();
+    ctx.catalog(&options.default_catalog)
+        .and_then(|catalog| catalog.schema(&options.default_schema))
+        .ok_or_else(|| {
+            eyre!(
+                "The default schema {}.{} isn't registered",
+                options.default_catalog,
+                options.default_schema
+            )
+        })?;
+    Ok(())
+}
+async fn check_db(execution: &AppExecution, db_path: &url::Url) -> Result<()> {
+    let url = ListingTableUrl::parse(db_path)?;
+    let store = execution
+        .session_ctx()
+        .runtime_env()
+        .object_store(url.object_store())?;
+    let path = object_store::path::Path::from_url_path(db_path.path())?;
+    store.list_with_delimiter(Some(&path)).await?;
+    Ok(())
+}
+
+#[cfg(feature = "flightsql")]
+async fn check_flightsql(execution: &AppExecution) -> Result<()> {
+    execution.flightsql_ctx().get_catalogs_flight_info().await?;
+    Ok(())
+}
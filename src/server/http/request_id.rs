@@ -0,0 +1,67 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Ids of the requests served by the HTTP server, so that issues reported by clients can be
+//! found in the server's logs
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use http::{HeaderMap, HeaderValue};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+/// Header with the id of a request. Clients can choose the id of their requests by sending it,
+/// otherwise one is generated, and it's sent back with every response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest id accepted from clients, longer ones are replaced by a generated id
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Takes the id of the request from its header or generates one, and serves the request in a
+/// span with that id so that everything logged for it, including by the parse, plan and execute
+/// spans of its query, can be correlated
+pub async fn propagate_request_id(mut req: Request, next: Next) -> Response {
+    let id = valid_request_id(req.headers()).unwrap_or_else(|| Uuid::new_v4().to_string());
+    // Replaces an invalid id, so that handlers can read the id from the request's headers
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        req.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path()
+    );
+    let mut res = next.run(req).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res
+}
+
+/// The id in `headers`, if it's printable ASCII of at most `MAX_REQUEST_ID_LEN` bytes
+fn valid_request_id(headers: &HeaderMap) -> Option<String> {
+    let id = headers.get(REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+/// The id of a request that went through `propagate_request_id`
+pub fn request_id(headers: &HeaderMap) -> Option<String> {
+    valid_request_id(headers)
+}
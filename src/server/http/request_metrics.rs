@@ -0,0 +1,77 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Prometheus metrics for the requests served by the HTTP server
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, gauge, histogram, Gauge};
+
+/// Counts a request as in flight until it's dropped, which also covers requests whose client
+/// disconnected or that timed out
+struct InFlight(Gauge);
+
+impl InFlight {
+    fn new(gauge: Gauge) -> Self {
+        gauge.increment(1.0);
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.decrement(1.0);
+    }
+}
+
+/// Records the count, latency and number in flight of requests by route and method. Streamed
+/// responses are counted as finished once their headers are sent.
+pub async fn track_metrics(req: Request, next: Next) -> Response {
+    // Label by route rather than path so that path params don't create a series per value
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+    let in_flight = InFlight::new(gauge!(
+        "http_requests_in_flight",
+        "route" => route.clone(),
+        "method" => method.clone()
+    ));
+    let start = Instant::now();
+
+    let res = next.run(req).await;
+
+    drop(in_flight);
+    let status = res.status().as_u16().to_string();
+    histogram!("http_request_latency_ms", "route" => route.clone(), "method" => method.clone())
+        .record(start.elapsed().as_secs_f64() * 1000.0);
+    counter!("http_requests", "route" => route, "method" => method, "status" => status)
+        .increment(1);
+    res
+}
+
+/// Records rows of results returned by a route
+pub fn record_rows_returned(route: &str, rows: u64) {
+    counter!("http_rows_returned", "route" => route.to_string()).increment(rows);
+}
@@ -15,85 +15,411 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{io::Cursor, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
 
 use axum::{
-    body::Body,
-    extract::{Json, OriginalUri, Path, Query, State},
-    response::{IntoResponse, Response},
-    routing::{get, post},
+    body::{Body, Bytes},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Json, MatchedPath, Multipart, OriginalUri, Path, Query,
+        State,
+    },
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
     Router,
 };
-use datafusion::{arrow::json::ArrayWriter, execution::SendableRecordBatchStream};
-use datafusion_app::{observability::ObservabilityRequestDetails, ExecOptions, ExecResult};
-use http::{HeaderValue, StatusCode};
+use datafusion::{
+    arrow::{
+        array::RecordBatch,
+        datatypes::Schema,
+        error::ArrowError,
+        json::{ArrayWriter, LineDelimitedWriter},
+    },
+    datasource::TableProvider,
+    error::DataFusionError,
+    execution::SendableRecordBatchStream,
+};
+use datafusion_app::{
+    observability::ObservabilityRequestDetails, rewrite::QueryIdentity, ExecOptions, ExecResult,
+};
+use futures::TryStreamExt;
+use http::{header::LOCATION, HeaderMap, HeaderValue, StatusCode};
 use jiff::Timestamp;
 use log::error;
-use serde::{Deserialize, Serialize};
-use tokio_stream::StreamExt;
-use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
-use tracing::debug;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio_stream::{Stream, StreamExt};
+use tower_http::{trace::TraceLayer, validate_request::ValidateRequestHeaderLayer};
+use tracing::{debug, Instrument, Span};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
 
-use crate::{config::HttpServerConfig, execution::AppExecution};
+use crate::{
+    config::HttpServerConfig,
+    db::{create_table_from_file, is_supported_format, TableExists},
+    execution::AppExecution,
+    server::{jobs::JobInfo, query_log::QueryLogEntry},
+};
 
-use super::tpch;
+use super::{
+    auth::{RequestUser, RequireCredentials},
+    compression::compression_layer,
+    cors::try_cors_layer,
+    explain::{explain, ExplainedPlans},
+    format::{BatchWriter, ResultFormat},
+    overrides::{enforce_timeout, result_limit, TimeoutLimits},
+    params::SqlParams,
+    rate_limit::{enforce_rate_limit, RateLimiter},
+    ready::{Readiness, ReadinessCache},
+    request_id::{propagate_request_id, request_id},
+    request_metrics::{record_rows_returned, track_metrics},
+    sessions::{Sessions, SESSION_HEADER},
+    statements::{cancelled_status, RunningStatement, RunningStatements, QUERY_ID_HEADER},
+    tpch,
+};
 
 #[derive(Debug)]
 struct ExecRequest {
     path: String,
+    /// The route that matched the request, such as `/table/:catalog/:schema/:table`
+    route: String,
     sql: String,
+    /// Address of the client, when the server is run with connection info
+    client: Option<String>,
+    /// Id of the statement, which is generated when the client doesn't choose one
+    query_id: Option<String>,
+    /// Who sent the statement, for the plan rewriters of the session
+    identity: QueryIdentity,
+    /// Id of the HTTP request, for correlating the statement with the server's logs
+    request_id: Option<String>,
 }
 
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "dft HTTP API"),
+    paths(
+        post_sql_handler,
+        cancel_sql_handler,
+        explain_handler,
+        create_session_handler,
+        delete_session_handler,
+        ready_handler,
+        post_query_handler,
+        get_query_handler,
+        get_query_results_handler,
+        delete_query_handler,
+        get_catalogs_handler,
+        get_schemas_handler,
+        get_tables_handler,
+        get_table_schema_handler,
+        upload_table_handler,
+        get_table_handler,
+        get_table_arrow_schema_handler,
+        get_tpch_query_handler,
+    )
+)]
+struct ApiDoc;
+
+const DOCS_HTML: &str = include_str!("docs.html");
+
+#[cfg(feature = "http-ui")]
+const UI_HTML: &str = include_str!("ui.html");
+
 #[derive(Clone)]
 struct ExecutionState {
     execution: AppExecution,
     config: HttpServerConfig,
+    statements: RunningStatements,
+    sessions: Sessions,
+    /// Result of the last readiness checks
+    readiness: ReadinessCache,
 }
 
 impl ExecutionState {
     pub fn new(execution: AppExecution, config: HttpServerConfig) -> Self {
-        Self { execution, config }
+        let sessions = Sessions::new(
+            Duration::from_secs(config.session_idle_timeout_secs),
+            config.max_sessions,
+        );
+        Self {
+            execution,
+            config,
+            statements: RunningStatements::default(),
+            sessions,
+            readiness: ReadinessCache::default(),
+        }
+    }
+
+    /// The state to execute a request in: the session named by its `x-dft-session` header, a
+    /// new session when `auto_create_sessions` is set and it names none, or else the server's
+    /// session. The id of a new session is returned so that it can be sent to the client.
+    /// Sessions can only be used by the user that started them.
+    fn for_session(
+        &self,
+        headers: &HeaderMap,
+        user: &RequestUser,
+    ) -> Result<(Self, Option<String>), Response> {
+        let (execution, created) = match headers.get(SESSION_HEADER).map(|id| id.to_str()) {
+            Some(Ok(id)) => match self.sessions.get(id, user.name()) {
+                Some(execution) => (execution, None),
+                None => {
+                    return Err(
+                        (StatusCode::NOT_FOUND, format!("Session {id} not found")).into_response()
+                    )
+                }
+            },
+            Some(Err(_)) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid {SESSION_HEADER} header"),
+                )
+                    .into_response())
+            }
+            None if self.config.auto_create_sessions => {
+                let (id, execution) = self.sessions.create(&self.execution, user.name());
+                (execution, Some(id))
+            }
+            None => return Ok((self.clone(), None)),
+        };
+        let state = Self {
+            execution,
+            ..self.clone()
+        };
+        Ok((state, created))
     }
 }
 
 pub fn create_router(execution: AppExecution, config: HttpServerConfig) -> Router {
     let state = ExecutionState::new(execution, config);
-    Router::new()
+    let compression = compression_layer(&state.config.compression);
+    let mut router = Router::new()
         .route(
             "/",
             get(|State(_): State<ExecutionState>| async { "Hello, from DFT!" }),
         )
+        .route("/docs", get(|| async { Html(DOCS_HTML) }))
+        .route(
+            "/docs/openapi.json",
+            get(|| async { Json(ApiDoc::openapi()) }),
+        )
+        .route("/sql", post(post_sql_handler).layer(compression.clone()))
+        .route("/sql/:id/cancel", post(cancel_sql_handler))
+        .route("/explain", post(explain_handler))
+        .route("/sessions", post(create_session_handler))
+        .route("/sessions/:id", delete(delete_session_handler))
+        .route("/ws", get(ws_handler))
+        .route("/queries", post(post_query_handler))
+        .route(
+            "/queries/:id",
+            get(get_query_handler).delete(delete_query_handler),
+        )
+        .route(
+            "/queries/:id/results",
+            get(get_query_results_handler).layer(compression.clone()),
+        )
+        .route("/catalogs", get(get_catalogs_handler))
+        .route("/catalogs/:catalog/schemas", get(get_schemas_handler))
+        .route(
+            "/catalogs/:catalog/schemas/:schema/tables",
+            get(get_tables_handler),
+        )
+        .route(
+            "/tables/:name",
+            post(upload_table_handler).layer(DefaultBodyLimit::max(state.config.max_upload_bytes)),
+        )
+        .route(
+            "/tables/:catalog/:schema/:table/schema",
+            get(get_table_schema_handler),
+        )
+        .route("/tpch/:number", get(get_tpch_query_handler))
+        .route(
+            "/table/:catalog/:schema/:table",
+            get(get_table_handler).layer(compression),
+        )
+        .route(
+            "/table/:catalog/:schema/:table/schema",
+            get(get_table_arrow_schema_handler),
+        );
+    // Only applies to the routes added so far, so health checks aren't throttled. Added before
+    // authentication so that it runs after it, counting requests against the validated user.
+    if let Some(rate_limit) = &state.config.rate_limit {
+        router = router.route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(rate_limit),
+            enforce_rate_limit,
+        ));
+    }
+    // Health checks don't need credentials either
+    match RequireCredentials::try_from_config(&state.config.auth) {
+        Ok(Some(credentials)) => {
+            router = router.route_layer(ValidateRequestHeaderLayer::custom(credentials));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("Rejecting every request, invalid auth config: {e}");
+            router = router.route_layer(ValidateRequestHeaderLayer::custom(
+                RequireCredentials::reject_all(),
+            ));
+        }
+    }
+    router = router
         .route(
             "/health-check",
             get(|State(_): State<ExecutionState>| async { "Healthy" }),
         )
-        .route("/sql", post(post_sql_handler))
-        .route("/catalog", get(get_catalog_handler))
-        .route("/tpch/:number", get(get_tpch_query_handler))
-        .route("/table/:catalog/:schema/:table", get(get_table_handler))
+        .route("/ready", get(ready_handler));
+    // The page holds no data, it authenticates its own requests with the token it's given
+    #[cfg(feature = "http-ui")]
+    {
+        router = router.route("/ui", get(|| async { Html(UI_HTML) }));
+    }
+    // Outside of authentication, since browsers don't send credentials with preflight requests
+    if let Some(cors) = &state.config.cors {
+        match try_cors_layer(cors) {
+            Ok(layer) => router = router.layer(layer),
+            Err(e) => error!("Not allowing cross-origin requests, invalid CORS config: {e}"),
+        }
+    }
+    router
         .layer((
+            // Outside of tracing so that its span is the parent of the request's other spans
+            middleware::from_fn(propagate_request_id),
             TraceLayer::new_for_http(),
-            // Graceful shutdown will wait for outstanding requests to complete. Add a timeout so
-            // requests don't hang forever.
-            TimeoutLayer::with_status_code(
-                http::StatusCode::REQUEST_TIMEOUT,
-                Duration::from_secs(state.config.timeout_seconds),
-            ),
+            middleware::from_fn_with_state(TimeoutLimits::new(&state.config), enforce_timeout),
         ))
+        // Outermost so that requests cut off by the timeout are also recorded
+        .layer(middleware::from_fn(track_metrics))
         .with_state(state)
 }
 
-#[derive(Deserialize)]
+/// Whether the server can serve queries, with the result of each check. Responds with a
+/// `503 Service Unavailable` when a check failed.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "The server is ready", body = Readiness),
+        (status = 503, description = "A check failed", body = Readiness),
+    )
+)]
+async fn ready_handler(state: State<ExecutionState>) -> Response {
+    let readiness = state.readiness.check(&state.execution, &state.config).await;
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
 struct PostSqlBody {
+    /// The statement to execute
     sql: String,
+    /// Execute the statement on the configured FlightSQL server instead of locally
     #[serde(default)]
     flightsql: bool,
+    /// Values for the statement's placeholders, a list for `$1`, `$2`, ... or an object for
+    /// `$name` placeholders. Only supported for local execution.
+    #[schema(value_type = Option<Object>)]
+    params: Option<SqlParams>,
+}
+
+impl PostSqlBody {
+    /// Options to execute the statement with its parameters bound
+    fn exec_options(&mut self, limit: Option<usize>) -> Result<ExecOptions, Response> {
+        let opts = ExecOptions::new(limit, self.flightsql);
+        match self.params.take() {
+            Some(params) => match params.try_into_param_values() {
+                Ok(params) => Ok(opts.with_params(params)),
+                Err(e) => Err((StatusCode::BAD_REQUEST, e).into_response()),
+            },
+            None => Ok(opts),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PostSqlQueryParams {
+    /// One of `json`, `ndjson`, `csv` or `arrow`, takes precedence over the `Accept` header
+    format: Option<String>,
+    /// Rows per page, capped at the configured result limit
+    limit: Option<usize>,
+    /// Rows to skip before the page
+    offset: Option<usize>,
+}
+
+/// Header naming the offset of the next page, only set when more rows follow the current page
+const NEXT_OFFSET_HEADER: &str = "x-next-offset";
+
+/// A page of results requested with the `limit` and `offset` query params
+#[derive(Clone, Copy, Debug)]
+struct Page {
+    offset: usize,
+    limit: usize,
+}
+
+impl Page {
+    /// Pages default to, and are capped at, the configured result limit
+    fn try_new(
+        limit: Option<usize>,
+        offset: Option<usize>,
+        result_limit: usize,
+    ) -> Result<Self, String> {
+        let limit = limit.unwrap_or(result_limit).min(result_limit);
+        if limit == 0 {
+            return Err("limit must be greater than zero".to_string());
+        }
+        Ok(Self {
+            offset: offset.unwrap_or_default(),
+            limit,
+        })
+    }
+
+    /// Restricts the query to the page. With `lookahead` one extra row is fetched so that we can
+    /// tell whether another page follows.
+    fn apply(&self, opts: ExecOptions, lookahead: bool) -> ExecOptions {
+        let limit = if lookahead {
+            self.limit + 1
+        } else {
+            self.limit
+        };
+        ExecOptions {
+            limit: Some(limit),
+            offset: self.offset,
+            ..opts
+        }
+    }
+
+    fn next_offset(&self) -> usize {
+        self.offset + self.limit
+    }
 }
 
+/// Execute a statement, returning its results
+#[utoipa::path(
+    post,
+    path = "/sql",
+    request_body = PostSqlBody,
+    params(PostSqlQueryParams),
+    responses(
+        (status = 200, description = "Results in the negotiated format, with an `x-next-offset` header when another page follows"),
+        (status = 400, description = "The statement or parameters are invalid", body = String),
+    )
+)]
 async fn post_sql_handler(
     state: State<ExecutionState>,
     OriginalUri(uri): OriginalUri,
-    Json(body): Json<PostSqlBody>,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Query(query): Query<PostSqlQueryParams>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Json(mut body): Json<PostSqlBody>,
 ) -> Response {
     if body.flightsql && !cfg!(feature = "flightsql") {
         return (
@@ -102,307 +428,2867 @@ async fn post_sql_handler(
         )
             .into_response();
     }
+    let format = match ResultFormat::negotiate(query.format.as_deref(), &headers) {
+        Ok(format) => format,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let result_limit = match result_limit(&headers, &state.config) {
+        Ok(result_limit) => result_limit,
+        Err(res) => return res,
+    };
+    let page = match Page::try_new(query.limit, query.offset, result_limit) {
+        Ok(page) => page,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let query_id = match requested_query_id(&headers) {
+        Ok(query_id) => query_id,
+        Err(res) => return res,
+    };
     let req = ExecRequest {
         path: uri.path().to_string(),
+        route: route.as_str().to_string(),
         sql: body.sql.to_string(),
+        client: client_addr(connect_info),
+        query_id,
+        identity: request_identity(&user),
+        request_id: request_id(&headers),
     };
-    let opts = ExecOptions::new(Some(state.config.result_limit), body.flightsql);
-    create_response(&state, req, opts).await
+    let opts = match body.exec_options(None) {
+        Ok(opts) => opts,
+        Err(res) => return res,
+    };
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let mut res = create_response(&State(session), req, opts, format, Some(page)).await;
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
+    }
+    res
 }
 
-#[derive(Deserialize)]
-struct GetCatalogQueryParams {
-    #[serde(default)]
-    flightsql: bool,
+fn insert_session_id(res: &mut Response, id: &str) {
+    if let Ok(value) = HeaderValue::from_str(id) {
+        res.headers_mut().insert(SESSION_HEADER, value);
+    }
 }
 
-async fn get_catalog_handler(
+#[derive(Serialize, ToSchema)]
+struct CreatedSession {
+    /// Sent in the `x-dft-session` header to execute requests in the session
+    id: String,
+}
+
+/// Start a session, which keeps the settings changed with `SET` and the statements prepared
+/// with `PREPARE` for the requests that name it in their `x-dft-session` header
+#[utoipa::path(
+    post,
+    path = "/sessions",
+    responses((status = 201, description = "The session was started", body = CreatedSession))
+)]
+async fn create_session_handler(state: State<ExecutionState>, user: RequestUser) -> Response {
+    let (id, _) = state.sessions.create(&state.execution, user.name());
+    let mut res = (StatusCode::CREATED, Json(CreatedSession { id: id.clone() })).into_response();
+    insert_session_id(&mut res, &id);
+    res
+}
+
+/// End a session, dropping its settings and prepared statements
+#[utoipa::path(
+    delete,
+    path = "/sessions/{id}",
+    params(("id" = String, Path)),
+    responses(
+        (status = 204, description = "The session was ended"),
+        (status = 404, description = "There is no such session", body = String),
+    )
+)]
+async fn delete_session_handler(
     state: State<ExecutionState>,
-    OriginalUri(uri): OriginalUri,
-    Query(query): Query<GetCatalogQueryParams>,
+    user: RequestUser,
+    Path(id): Path<String>,
 ) -> Response {
-    let opts = ExecOptions::new(None, query.flightsql);
-    if opts.flightsql && !cfg!(feature = "flightsql") {
-        return (
+    if state.sessions.remove(&id, user.name()) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, format!("Session {id} not found")).into_response()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ExplainBody {
+    /// The statement to plan
+    sql: String,
+}
+
+/// Plan a statement without executing it, describing its logical, optimized logical and
+/// physical plans as trees of operators
+#[utoipa::path(
+    post,
+    path = "/explain",
+    request_body = ExplainBody,
+    responses(
+        (status = 200, body = ExplainedPlans),
+        (status = 400, description = "The statement can't be planned", body = String),
+    )
+)]
+async fn explain_handler(
+    state: State<ExecutionState>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Json(body): Json<ExplainBody>,
+) -> Response {
+    let (session, _) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let identity = request_identity(&user);
+    match explain(session.execution.session_ctx(), &body.sql, &identity).await {
+        Ok(plans) => Json(plans).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// The id the client chose for its statement, if any
+fn requested_query_id(headers: &HeaderMap) -> Result<Option<String>, Response> {
+    match headers.get(QUERY_ID_HEADER).map(|id| id.to_str()) {
+        Some(Ok(id)) if !id.is_empty() => Ok(Some(id.to_string())),
+        Some(_) => Err((
             StatusCode::BAD_REQUEST,
-            "FlightSQL is not enabled on this server",
+            format!("Invalid {QUERY_ID_HEADER} header"),
         )
-            .into_response();
+            .into_response()),
+        None => Ok(None),
     }
-    let sql = "SHOW TABLES".to_string();
-    let req = ExecRequest {
-        path: uri.path().to_string(),
-        sql,
-    };
-    create_response(&state, req, opts).await
 }
 
-#[derive(Deserialize, Serialize)]
-struct GetTablePathParams {
-    catalog: String,
-    schema: String,
-    table: String,
+#[derive(Serialize, ToSchema)]
+struct CancelledStatement {
+    /// Whether the statement was running, and is now cancelled
+    cancelled: bool,
+}
+
+/// Cancel a statement executed by `/sql` or `/table/...`, by the id in its `x-query-id` header.
+/// Only statements started by the same user can be cancelled.
+#[utoipa::path(
+    post,
+    path = "/sql/{id}/cancel",
+    params(("id" = String, Path)),
+    responses((status = 200, body = CancelledStatement))
+)]
+async fn cancel_sql_handler(
+    state: State<ExecutionState>,
+    Path(id): Path<String>,
+    user: RequestUser,
+) -> Response {
+    let identity = request_identity(&user);
+    let cancelled = state.statements.cancel(identity.user.as_deref(), &id);
+    Json(CancelledStatement { cancelled }).into_response()
 }
 
 #[derive(Deserialize)]
-struct GetTableQueryParams {
+struct WsQuery {
+    sql: String,
     #[serde(default)]
     flightsql: bool,
+    #[serde(default)]
+    format: WsFormat,
 }
 
-async fn get_table_handler(
-    state: State<ExecutionState>,
-    Path(path): Path<GetTablePathParams>,
-    Query(query): Query<GetTableQueryParams>,
-    OriginalUri(uri): OriginalUri,
-) -> Response {
-    let GetTablePathParams {
-        catalog,
-        schema,
-        table,
-    } = path;
-    let sql = format!(
-        "SELECT * FROM \"{catalog}\".\"{schema}\".\"{table}\" LIMIT {}",
-        state.config.result_limit
-    );
-    let req = ExecRequest {
-        path: uri.path().to_string(),
-        sql,
-    };
-    let opts = ExecOptions::new(Some(state.config.result_limit), query.flightsql);
-    create_response(&state, req, opts).await
+/// Encoding of the batches sent over a WebSocket
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum WsFormat {
+    /// Text messages holding `{"type": "batch", "rows": [...]}`
+    #[default]
+    Json,
+    /// Binary messages each holding an Arrow IPC stream with a single batch
+    Arrow,
 }
 
-#[derive(Deserialize, Serialize)]
-struct GetTpchPathParams {
-    number: usize,
+/// Text messages sent over a WebSocket, other than JSON batches
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum WsMessage {
+    /// Sent when a query starts, its id can be used to cancel it with `/sql/{id}/cancel`
+    Start { query_id: String },
+    /// Sent once all of the results of a query have been sent
+    Summary {
+        query_id: String,
+        rows: u64,
+        batches: u64,
+        duration_ms: i64,
+    },
+    Error {
+        query_id: Option<String>,
+        message: String,
+    },
 }
 
-async fn get_tpch_query_handler(
+impl WsMessage {
+    fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).expect("messages serialize to JSON"))
+    }
+}
+
+/// Upgrades to a WebSocket that executes the queries sent as `{ sql, flightsql?, format? }` text
+/// messages, one at a time, sending back each batch of results as it's produced
+async fn ws_handler(
     state: State<ExecutionState>,
-    Path(path): Path<GetTpchPathParams>,
+    ws: WebSocketUpgrade,
     OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
 ) -> Response {
-    if let Some(sql) = tpch::sql_for_tpch_query(path.number) {
-        let req = ExecRequest {
-            path: uri.path().to_string(),
-            sql: sql.to_string(),
-        };
-        let opts = ExecOptions::new(None, false);
-        create_response(&state, req, opts).await
-    } else {
-        (StatusCode::BAD_REQUEST, "Unknown TPC-H query number").into_response()
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let path = uri.path().to_string();
+    let route = route.as_str().to_string();
+    let client = client_addr(connect_info);
+    let identity = request_identity(&user);
+    let request_id = request_id(&headers);
+    // The socket outlives the request, keep its span so that queries sent over it are correlated
+    let span = Span::current();
+    let mut res = ws.on_upgrade(move |socket| {
+        handle_ws(session, socket, path, route, client, identity, request_id).instrument(span)
+    });
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
     }
+    res
 }
 
-async fn response_for_sql(
-    State(state): &State<ExecutionState>,
-    sql: String,
-    opts: ExecOptions,
-) -> (Response, ResponseDetails) {
-    debug!("Executing sql: {sql}");
-    match state.execution.execute_sql_with_opts(&sql, opts).await {
-        Ok(ExecResult::RecordBatchStream(stream)) => batch_stream_to_response(stream).await,
-        Ok(_) => {
-            let res = (
-                StatusCode::BAD_REQUEST,
-                "Execution failed: unknown result type".to_string(),
-            )
-                .into_response();
-            (res, error_response_details())
-        }
-
-        Err(e) => {
-            let res = (StatusCode::BAD_REQUEST, format!("{}", e)).into_response();
-            (res, error_response_details())
+async fn handle_ws(
+    state: ExecutionState,
+    mut socket: WebSocket,
+    path: String,
+    route: String,
+    client: Option<String>,
+    identity: QueryIdentity,
+    request_id: Option<String>,
+) {
+    // Queries sent while another one is streaming, which are executed once it's finished
+    let mut pending = VecDeque::new();
+    loop {
+        let message = match pending.pop_front() {
+            Some(message) => message,
+            None => match socket.recv().await {
+                Some(Ok(message)) => message,
+                _ => break,
+            },
+        };
+        let query = match message {
+            Message::Text(text) => serde_json::from_str::<WsQuery>(&text),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let result = match query {
+            Ok(query) => {
+                let req = ExecRequest {
+                    path: path.clone(),
+                    route: route.clone(),
+                    sql: query.sql.clone(),
+                    client: client.clone(),
+                    query_id: Some(Uuid::new_v4().to_string()),
+                    identity: identity.clone(),
+                    request_id: request_id.clone(),
+                };
+                stream_ws_query(&state, &mut socket, &mut pending, req, query).await
+            }
+            Err(e) => {
+                let error = WsMessage::Error {
+                    query_id: None,
+                    message: format!("Invalid query message: {e}"),
+                };
+                socket.send(error.to_message()).await
+            }
+        };
+        if let Err(e) = result {
+            debug!("Closing WebSocket: {e}");
+            break;
         }
     }
 }
 
-struct ResponseDetails {
-    rows: u64,
-}
+/// Executes the query, sending its results over `socket`. Fails if the socket is closed, which
+/// cancels the query. Messages received while the results are sent are added to `pending`.
+async fn stream_ws_query(
+    state: &ExecutionState,
+    socket: &mut WebSocket,
+    pending: &mut VecDeque<Message>,
+    req: ExecRequest,
+    query: WsQuery,
+) -> Result<(), axum::Error> {
+    let query_id = req.query_id.clone().unwrap_or_default();
+    let start = Timestamp::now();
+    let Some(statement) = state
+        .statements
+        .register(req.identity.user.as_deref(), &query_id)
+    else {
+        let error = WsMessage::Error {
+            query_id: Some(query_id.clone()),
+            message: format!("Query {query_id} is already running"),
+        };
+        return socket.send(error.to_message()).await;
+    };
+    socket
+        .send(
+            WsMessage::Start {
+                query_id: query_id.clone(),
+            }
+            .to_message(),
+        )
+        .await?;
 
-fn error_response_details() -> ResponseDetails {
-    ResponseDetails { rows: 0 }
-}
+    let executed = if query.flightsql && !cfg!(feature = "flightsql") {
+        Err("FlightSQL is not enabled on this server".to_string())
+    } else {
+        let opts = ExecOptions::new(Some(state.config.result_limit), query.flightsql)
+            .with_identity(req.identity.clone());
+        match state.execution.execute_sql_with_opts(&req.sql, opts).await {
+            Ok(ExecResult::RecordBatchStream(stream)) => Ok(stream),
+            Ok(_) => Err("Execution failed: unknown result type".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    let mut stream = match executed {
+        Ok(stream) => statement.cancellation.wrap_stream(stream),
+        Err(message) => {
+            let details = error_response_details(&message);
+            record_response(state, req, start, StatusCode::BAD_REQUEST.as_u16(), details).await;
+            let error = WsMessage::Error {
+                query_id: Some(query_id),
+                message,
+            };
+            return socket.send(error.to_message()).await;
+        }
+    };
 
-async fn batch_stream_to_response(
-    batch_stream: SendableRecordBatchStream,
-) -> (Response, ResponseDetails) {
-    let mut buf: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-    let mut writer = ArrayWriter::new(&mut buf);
-    let mut batch_stream = batch_stream;
-    let mut rows: usize = 0;
-    while let Some(maybe_batch) = batch_stream.next().await {
-        match maybe_batch {
-            Ok(batch) => {
-                if let Err(e) = writer.write(&batch) {
-                    error!("Error serializing result batches: {}", e);
-                    return (
-                        (StatusCode::INTERNAL_SERVER_ERROR, "Serialization error").into_response(),
-                        error_response_details(),
-                    );
+    let mut rows = 0;
+    let mut batches = 0;
+    let mut error = None;
+    let mut sent = Ok(());
+    loop {
+        // The socket is read while waiting for batches, so that closing it stops the query
+        let maybe_batch = tokio::select! {
+            maybe_batch = stream.next() => maybe_batch,
+            message = socket.recv() => {
+                match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                        statement.cancellation.cancel();
+                        sent = Err(axum::Error::new("The WebSocket was closed"));
+                        break;
+                    }
+                    Some(Ok(message)) => pending.push_back(message),
+                }
+                continue;
+            }
+        };
+        let Some(maybe_batch) = maybe_batch else {
+            break;
+        };
+        let encoded = maybe_batch.map_err(|e| e.to_string()).and_then(|batch| {
+            rows += batch.num_rows() as u64;
+            batches += 1;
+            encode_ws_batch(&batch, query.format).map_err(|e| e.to_string())
+        });
+        match encoded {
+            Ok(message) => {
+                sent = socket.send(message).await;
+                if sent.is_err() {
+                    break;
                 }
-                rows += batch.num_rows()
             }
             Err(e) => {
-                error!("Error executing query: {}", e);
-                // TODO: Use more appropriate errors, like 404 for table that doesnt exist
-                return (
-                    (StatusCode::INTERNAL_SERVER_ERROR, "Query execution error").into_response(),
-                    error_response_details(),
-                );
+                error = Some(e);
+                break;
             }
         }
     }
+    // Stop executing the query, even if the socket was closed part way through
+    drop(stream);
 
-    if let Err(e) = writer.finish() {
-        error!("Error finalizing JSON writer: {}", e);
-        return (
-            (StatusCode::INTERNAL_SERVER_ERROR, "Finalization error").into_response(),
-            error_response_details(),
-        );
-    }
+    let status = if statement.cancellation.is_cancelled() {
+        cancelled_status()
+    } else if error.is_some() || sent.is_err() {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    };
+    let details = ResponseDetails {
+        rows,
+        error: error.clone(),
+    };
+    record_response(state, req, start, status.as_u16(), details).await;
+    sent?;
+    let message = match error {
+        Some(message) => WsMessage::Error {
+            query_id: Some(query_id),
+            message,
+        },
+        None => WsMessage::Summary {
+            query_id,
+            rows,
+            batches,
+            duration_ms: (Timestamp::now() - start).get_milliseconds(),
+        },
+    };
+    socket.send(message.to_message()).await
+}
 
-    match String::from_utf8(buf.into_inner()) {
-        Ok(json) => {
-            let mut res = Response::new(Body::new(json));
-            res.headers_mut()
-                .insert("content-type", HeaderValue::from_static("application/json"));
-            let details = ResponseDetails { rows: rows as u64 };
-            (res, details)
+fn encode_ws_batch(batch: &RecordBatch, format: WsFormat) -> Result<Message, ArrowError> {
+    match format {
+        WsFormat::Json => {
+            let mut writer = BatchWriter::try_new(ResultFormat::Json, &batch.schema())?;
+            writer.write(batch)?;
+            let rows = String::from_utf8(writer.finish()?)
+                .map_err(|e| ArrowError::JsonError(e.to_string()))?;
+            Ok(Message::Text(format!(
+                r#"{{"type":"batch","rows":{rows}}}"#
+            )))
+        }
+        WsFormat::Arrow => {
+            let mut writer = BatchWriter::try_new(ResultFormat::Arrow, &batch.schema())?;
+            writer.write(batch)?;
+            Ok(Message::Binary(writer.finish()?))
         }
-        Err(_) => (
-            (StatusCode::INTERNAL_SERVER_ERROR, "UTF-8 conversion error").into_response(),
-            error_response_details(),
-        ),
     }
 }
 
-async fn create_response(
-    state: &State<ExecutionState>,
-    req: ExecRequest,
-    opts: ExecOptions,
+#[derive(Serialize, ToSchema)]
+struct SubmittedQuery {
+    id: String,
+}
+
+/// Starts executing the query in the background so that it isn't bound by the request timeout
+#[utoipa::path(
+    post,
+    path = "/queries",
+    request_body = PostSqlBody,
+    responses(
+        (status = 202, description = "The query was started", body = SubmittedQuery),
+        (status = 429, description = "Too many queries are running", body = String),
+    )
+)]
+async fn post_query_handler(
+    state: State<ExecutionState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Json(mut body): Json<PostSqlBody>,
 ) -> Response {
-    let start = Timestamp::now();
-    let (res, details) = response_for_sql(state, req.sql.clone(), opts).await;
-    let elapsed = Timestamp::now() - start;
-    let req = ObservabilityRequestDetails {
-        request_id: None,
-        path: req.path,
-        sql: Some(req.sql),
-        start_ms: start.as_millisecond(),
-        duration_ms: elapsed.get_milliseconds(),
-        rows: Some(details.rows),
-        status: res.status().as_u16(),
+    if body.flightsql && !cfg!(feature = "flightsql") {
+        return (
+            StatusCode::BAD_REQUEST,
+            "FlightSQL is not enabled on this server",
+        )
+            .into_response();
+    }
+    let result_limit = match result_limit(&headers, &state.config) {
+        Ok(result_limit) => result_limit,
+        Err(res) => return res,
     };
-    let obs = state.execution.execution_ctx().observability();
-    if let Err(e) = obs
-        .try_record_request(state.execution.session_ctx(), req)
-        .await
-    {
-        error!("Error recording request: {}", e)
+    let opts = match body.exec_options(Some(result_limit)) {
+        Ok(opts) => opts,
+        Err(res) => return res,
+    };
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let id = match state.execution.query_jobs().submit(
+        session.execution,
+        body.sql,
+        opts.with_identity(request_identity(&user)),
+        client_addr(connect_info),
+        request_id(&headers),
+    ) {
+        Ok(id) => id,
+        Err(e) => return (StatusCode::TOO_MANY_REQUESTS, e.to_string()).into_response(),
+    };
+    let mut res = (
+        StatusCode::ACCEPTED,
+        [(LOCATION, format!("/queries/{id}"))],
+        Json(SubmittedQuery { id }),
+    )
+        .into_response();
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
     }
     res
 }
 
-#[cfg(test)]
-mod test {
-    use axum::body::Body;
-    use datafusion_app::{
-        config::ExecutionConfig, extensions::DftSessionStateBuilder, local::ExecutionContext,
-    };
-    use http::{Request, StatusCode};
+#[utoipa::path(
+    get,
+    path = "/queries/{id}",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, description = "The state of the query", body = JobInfo),
+        (status = 404, description = "There is no such query", body = String),
+    )
+)]
+async fn get_query_handler(state: State<ExecutionState>, Path(id): Path<String>) -> Response {
+    match state.execution.query_jobs().status(&id) {
+        Some(info) => Json(info).into_response(),
+        None => query_not_found(&id),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GetQueryResultsQueryParams {
+    /// One of `json`, `ndjson`, `csv` or `arrow`, takes precedence over the `Accept` header
+    format: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/queries/{id}/results",
+    params(("id" = String, Path), GetQueryResultsQueryParams),
+    responses(
+        (status = 200, description = "Results in the negotiated format"),
+        (status = 404, description = "There is no such query", body = String),
+        (status = 409, description = "The query is running or didn't succeed", body = String),
+    )
+)]
+async fn get_query_results_handler(
+    state: State<ExecutionState>,
+    Path(id): Path<String>,
+    Query(query): Query<GetQueryResultsQueryParams>,
+    headers: HeaderMap,
+) -> Response {
+    let format = match ResultFormat::negotiate(query.format.as_deref(), &headers) {
+        Ok(format) => format,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let results = match state.execution.query_jobs().results(&id) {
+        Some(Ok(results)) => results,
+        Some(Err(info)) => {
+            let mut message = format!("Query {id} is {}", info.status);
+            if let Some(error) = info.error {
+                message.push_str(&format!(": {error}"));
+            }
+            return (StatusCode::CONFLICT, message).into_response();
+        }
+        None => return query_not_found(&id),
+    };
+    let encoded = BatchWriter::try_new(format, &results.schema).and_then(|mut writer| {
+        for batch in &results.batches {
+            writer.write(batch)?;
+        }
+        writer.finish()
+    });
+    match encoded {
+        Ok(buf) => {
+            let mut res = Response::new(Body::from(buf));
+            res.headers_mut().insert(
+                "content-type",
+                HeaderValue::from_static(format.content_type()),
+            );
+            res
+        }
+        Err(e) => {
+            error!("Error serializing results of query {id}: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Serialization error").into_response()
+        }
+    }
+}
+
+/// Cancels the query if it's running, otherwise drops its results
+#[utoipa::path(
+    delete,
+    path = "/queries/{id}",
+    params(("id" = String, Path)),
+    responses(
+        (status = 204, description = "The query was cancelled or its results dropped"),
+        (status = 404, description = "There is no such query", body = String),
+    )
+)]
+async fn delete_query_handler(state: State<ExecutionState>, Path(id): Path<String>) -> Response {
+    if state.execution.query_jobs().cancel(&id) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        query_not_found(&id)
+    }
+}
+
+fn query_not_found(id: &str) -> Response {
+    (StatusCode::NOT_FOUND, format!("Query {id} not found")).into_response()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CatalogQueryParams {
+    /// Describe the catalog of the configured FlightSQL server
+    #[serde(default)]
+    flightsql: bool,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+struct CatalogEntry {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+struct SchemaEntry {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+struct TableEntry {
+    name: String,
+    table_type: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+struct ColumnEntry {
+    name: String,
+    data_type: String,
+    nullable: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TableSchemaResponse {
+    catalog: String,
+    schema: String,
+    table: String,
+    columns: Vec<ColumnEntry>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/catalogs",
+    params(CatalogQueryParams),
+    responses((status = 200, body = Vec<CatalogEntry>))
+)]
+async fn get_catalogs_handler(
+    state: State<ExecutionState>,
+    OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Query(query): Query<CatalogQueryParams>,
+) -> Response {
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let req = ExecRequest {
+        path: uri.path().to_string(),
+        route: route.as_str().to_string(),
+        sql: "SELECT DISTINCT catalog_name AS name FROM information_schema.schemata ORDER BY name"
+            .to_string(),
+        client: client_addr(connect_info),
+        query_id: None,
+        identity: request_identity(&user),
+        request_id: request_id(&headers),
+    };
+    let mut res = match information_schema_rows::<CatalogEntry>(
+        &State(session),
+        req,
+        query.flightsql,
+    )
+    .await
+    {
+        Ok(catalogs) => Json(catalogs).into_response(),
+        Err(res) => res,
+    };
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
+    }
+    res
+}
+
+#[utoipa::path(
+    get,
+    path = "/catalogs/{catalog}/schemas",
+    params(("catalog" = String, Path), CatalogQueryParams),
+    responses(
+        (status = 200, body = Vec<SchemaEntry>),
+        (status = 404, description = "There is no such catalog", body = String),
+    )
+)]
+async fn get_schemas_handler(
+    state: State<ExecutionState>,
+    Path(catalog): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Query(query): Query<CatalogQueryParams>,
+) -> Response {
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let sql = format!(
+        "SELECT schema_name AS name FROM information_schema.schemata WHERE catalog_name = {} ORDER BY name",
+        sql_literal(&catalog)
+    );
+    let req = ExecRequest {
+        path: uri.path().to_string(),
+        route: route.as_str().to_string(),
+        sql,
+        client: client_addr(connect_info),
+        query_id: None,
+        identity: request_identity(&user),
+        request_id: request_id(&headers),
+    };
+    let mut res =
+        match information_schema_rows::<SchemaEntry>(&State(session), req, query.flightsql).await {
+            // Every catalog has at least `information_schema`
+            Ok(schemas) if schemas.is_empty() => (
+                StatusCode::NOT_FOUND,
+                format!("Catalog {catalog} not found"),
+            )
+                .into_response(),
+            Ok(schemas) => Json(schemas).into_response(),
+            Err(res) => res,
+        };
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
+    }
+    res
+}
+
+#[utoipa::path(
+    get,
+    path = "/catalogs/{catalog}/schemas/{schema}/tables",
+    params(("catalog" = String, Path), ("schema" = String, Path), CatalogQueryParams),
+    responses((status = 200, body = Vec<TableEntry>))
+)]
+async fn get_tables_handler(
+    state: State<ExecutionState>,
+    Path((catalog, schema)): Path<(String, String)>,
+    OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Query(query): Query<CatalogQueryParams>,
+) -> Response {
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let sql = format!(
+        "SELECT table_name AS name, table_type FROM information_schema.tables WHERE table_catalog = {} AND table_schema = {} ORDER BY name",
+        sql_literal(&catalog),
+        sql_literal(&schema)
+    );
+    let req = ExecRequest {
+        path: uri.path().to_string(),
+        route: route.as_str().to_string(),
+        sql,
+        client: client_addr(connect_info),
+        query_id: None,
+        identity: request_identity(&user),
+        request_id: request_id(&headers),
+    };
+    let mut res =
+        match information_schema_rows::<TableEntry>(&State(session), req, query.flightsql).await {
+            Ok(tables) => Json(tables).into_response(),
+            Err(res) => res,
+        };
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
+    }
+    res
+}
+
+#[utoipa::path(
+    get,
+    path = "/tables/{catalog}/{schema}/{table}/schema",
+    params(
+        ("catalog" = String, Path),
+        ("schema" = String, Path),
+        ("table" = String, Path),
+        CatalogQueryParams,
+    ),
+    responses(
+        (status = 200, body = TableSchemaResponse),
+        (status = 404, description = "There is no such table", body = String),
+    )
+)]
+async fn get_table_schema_handler(
+    state: State<ExecutionState>,
+    Path(path): Path<GetTablePathParams>,
+    OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
+    Query(query): Query<CatalogQueryParams>,
+) -> Response {
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let GetTablePathParams {
+        catalog,
+        schema,
+        table,
+    } = path;
+    let sql = format!(
+        "SELECT column_name AS name, data_type, is_nullable = 'YES' AS nullable FROM information_schema.columns WHERE table_catalog = {} AND table_schema = {} AND table_name = {} ORDER BY ordinal_position",
+        sql_literal(&catalog),
+        sql_literal(&schema),
+        sql_literal(&table)
+    );
+    let req = ExecRequest {
+        path: uri.path().to_string(),
+        route: route.as_str().to_string(),
+        sql,
+        client: client_addr(connect_info),
+        query_id: None,
+        identity: request_identity(&user),
+        request_id: request_id(&headers),
+    };
+    let mut res =
+        match information_schema_rows::<ColumnEntry>(&State(session), req, query.flightsql).await {
+            Ok(columns) if columns.is_empty() => (
+                StatusCode::NOT_FOUND,
+                format!("Table {catalog}.{schema}.{table} not found"),
+            )
+                .into_response(),
+            Ok(columns) => Json(TableSchemaResponse {
+                catalog,
+                schema,
+                table,
+                columns,
+            })
+            .into_response(),
+            Err(res) => res,
+        };
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
+    }
+    res
+}
+
+#[derive(Serialize, ToSchema)]
+struct ArrowField {
+    name: String,
+    /// The Arrow data type, such as `Int64` or `Timestamp(Nanosecond, None)`
+    data_type: String,
+    nullable: bool,
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ArrowSchemaResponse {
+    fields: Vec<ArrowField>,
+    metadata: HashMap<String, String>,
+}
+
+impl From<&Schema> for ArrowSchemaResponse {
+    fn from(schema: &Schema) -> Self {
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|f| ArrowField {
+                name: f.name().clone(),
+                data_type: f.data_type().to_string(),
+                nullable: f.is_nullable(),
+                metadata: f.metadata().clone(),
+            })
+            .collect();
+        Self {
+            fields,
+            metadata: schema.metadata().clone(),
+        }
+    }
+}
+
+/// The Arrow schema of a table, read from its provider without running a query
+#[utoipa::path(
+    get,
+    path = "/table/{catalog}/{schema}/{table}/schema",
+    params(("catalog" = String, Path), ("schema" = String, Path), ("table" = String, Path)),
+    responses(
+        (status = 200, body = ArrowSchemaResponse),
+        (status = 404, description = "There is no such table", body = String),
+    )
+)]
+async fn get_table_arrow_schema_handler(
+    state: State<ExecutionState>,
+    Path(path): Path<GetTablePathParams>,
+    user: RequestUser,
+    headers: HeaderMap,
+) -> Response {
+    let (session, _) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let GetTablePathParams {
+        catalog,
+        schema,
+        table,
+    } = path;
+    let schema_provider = session
+        .execution
+        .session_ctx()
+        .catalog(&catalog)
+        .and_then(|c| c.schema(&schema));
+    let provider = match schema_provider {
+        Some(schema_provider) => schema_provider.table(&table).await,
+        None => Ok(None),
+    };
+    match provider {
+        Ok(Some(provider)) => {
+            Json(ArrowSchemaResponse::from(provider.schema().as_ref())).into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            format!("Table {catalog}.{schema}.{table} not found"),
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UploadTableQueryParams {
+    /// One of `csv`, `json` or `parquet`, detected from the extension of the file name when unset
+    format: Option<String>,
+}
+
+/// Create a table in the default catalog and schema from an uploaded file
+#[utoipa::path(
+    post,
+    path = "/tables/{name}",
+    params(("name" = String, Path), UploadTableQueryParams),
+    request_body(
+        content_type = "multipart/form-data",
+        description = "The table's data as a CSV, JSON or Parquet file in a `file` field",
+    ),
+    responses(
+        (status = 201, description = "The table was created", body = TableSchemaResponse),
+        (status = 400, description = "The upload is missing or can't be read as a table", body = String),
+        (status = 409, description = "The table already exists", body = String),
+    )
+)]
+async fn upload_table_handler(
+    state: State<ExecutionState>,
+    Path(name): Path<String>,
+    Query(query): Query<UploadTableQueryParams>,
+    mut multipart: Multipart,
+) -> Response {
+    let Some(db_path) = state.execution.db_path() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Uploads need a database path to store tables in",
+        )
+            .into_response();
+    };
+    // The name is used as a directory of the database
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Table names can only contain letters, digits and underscores",
+        )
+            .into_response();
+    }
+    let ctx = state.execution.session_ctx();
+    let catalog_options = ctx.state().config_options().catalog.clone();
+    let (catalog, schema) = (
+        catalog_options.default_catalog,
+        catalog_options.default_schema,
+    );
+    let exists = ctx
+        .catalog(&catalog)
+        .and_then(|c| c.schema(&schema))
+        .is_some_and(|s| s.table_exist(&name));
+    if exists {
+        return (
+            StatusCode::CONFLICT,
+            format!("Table {catalog}.{schema}.{name} already exists"),
+        )
+            .into_response();
+    }
+
+    let field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return (StatusCode::BAD_REQUEST, "Missing the `file` field").into_response()
+            }
+            Err(e) => return (e.status(), e.body_text()).into_response(),
+        }
+    };
+    let extension = query.format.clone().or_else(|| {
+        field
+            .file_name()
+            .and_then(|f| std::path::Path::new(f).extension())
+            .and_then(|e| e.to_str())
+            .map(str::to_string)
+    });
+    let Some(extension) = extension.filter(|e| is_supported_format(e)) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Unsupported file format, upload a `.csv`, `.json` or `.parquet` file or set the `format` param",
+        )
+            .into_response();
+    };
+
+    // The file is streamed to the database rather than buffered in memory
+    match create_table_from_file(ctx, db_path, &catalog, &schema, &name, &extension, field).await {
+        Ok(table) => {
+            let columns = table
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| ColumnEntry {
+                    name: f.name().clone(),
+                    data_type: f.data_type().to_string(),
+                    nullable: f.is_nullable(),
+                })
+                .collect();
+            let location = format!("/tables/{catalog}/{schema}/{name}/schema");
+            (
+                StatusCode::CREATED,
+                [(LOCATION, location)],
+                Json(TableSchemaResponse {
+                    catalog,
+                    schema,
+                    table: name,
+                    columns,
+                }),
+            )
+                .into_response()
+        }
+        // Another upload of the table finished first
+        Err(e) if e.downcast_ref::<TableExists>().is_some() => {
+            (StatusCode::CONFLICT, e.to_string()).into_response()
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to create table: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+/// Quotes a path param to be compared against in an `information_schema` query
+fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Runs a query against `information_schema`, deserializing each row into a `T`. The request is
+/// recorded like any other and failures are returned as the response to send.
+async fn information_schema_rows<T: DeserializeOwned>(
+    state: &State<ExecutionState>,
+    req: ExecRequest,
+    flightsql: bool,
+) -> Result<Vec<T>, Response> {
+    if flightsql && !cfg!(feature = "flightsql") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "FlightSQL is not enabled on this server",
+        )
+            .into_response());
+    }
+    let start = Timestamp::now();
+    debug!("Executing sql: {}", req.sql);
+    let opts = ExecOptions::new(None, flightsql).with_identity(req.identity.clone());
+    let result = match state.execution.execute_sql_with_opts(&req.sql, opts).await {
+        Ok(ExecResult::RecordBatchStream(stream)) => decode_rows::<T>(stream).await.map_err(|e| {
+            error!("Error reading information_schema: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e)
+        }),
+        Ok(_) => Err((
+            StatusCode::BAD_REQUEST,
+            "Execution failed: unknown result type".to_string(),
+        )),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    };
+    match result {
+        Ok(rows) => {
+            let details = ResponseDetails {
+                rows: rows.len() as u64,
+                error: None,
+            };
+            record_response(state, req, start, StatusCode::OK.as_u16(), details).await;
+            Ok(rows)
+        }
+        Err((status, e)) => {
+            let details = error_response_details(&e);
+            record_response(state, req, start, status.as_u16(), details).await;
+            Err((status, e).into_response())
+        }
+    }
+}
+
+async fn decode_rows<T: DeserializeOwned>(
+    batch_stream: SendableRecordBatchStream,
+) -> Result<Vec<T>, String> {
+    let batches: Vec<RecordBatch> = batch_stream
+        .try_collect()
+        .await
+        .map_err(|e| e.to_string())?;
+    if batches.iter().all(|batch| batch.num_rows() == 0) {
+        return Ok(Vec::new());
+    }
+    let mut writer = ArrayWriter::new(Vec::new());
+    for batch in &batches {
+        writer.write(batch).map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())?;
+    serde_json::from_slice(&writer.into_inner()).map_err(|e| e.to_string())
+}
+
+#[derive(Deserialize, Serialize)]
+struct GetTablePathParams {
+    catalog: String,
+    schema: String,
+    table: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GetTableQueryParams {
+    /// Read the table from the configured FlightSQL server
+    #[serde(default)]
+    flightsql: bool,
+    /// One of `json`, `ndjson`, `csv` or `arrow`, takes precedence over the `Accept` header
+    format: Option<String>,
+    /// Rows per page, capped at the configured result limit
+    limit: Option<usize>,
+    /// Rows to skip before the page
+    offset: Option<usize>,
+}
+
+/// Read the rows of a table
+#[utoipa::path(
+    get,
+    path = "/table/{catalog}/{schema}/{table}",
+    params(
+        ("catalog" = String, Path),
+        ("schema" = String, Path),
+        ("table" = String, Path),
+        GetTableQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Results in the negotiated format, with an `x-next-offset` header when another page follows"),
+        (status = 400, description = "The table or parameters are invalid", body = String),
+    )
+)]
+async fn get_table_handler(
+    state: State<ExecutionState>,
+    Path(path): Path<GetTablePathParams>,
+    Query(query): Query<GetTableQueryParams>,
+    OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: RequestUser,
+    headers: HeaderMap,
+) -> Response {
+    let format = match ResultFormat::negotiate(query.format.as_deref(), &headers) {
+        Ok(format) => format,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let result_limit = match result_limit(&headers, &state.config) {
+        Ok(result_limit) => result_limit,
+        Err(res) => return res,
+    };
+    let page = match Page::try_new(query.limit, query.offset, result_limit) {
+        Ok(page) => page,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let GetTablePathParams {
+        catalog,
+        schema,
+        table,
+    } = path;
+    let query_id = match requested_query_id(&headers) {
+        Ok(query_id) => query_id,
+        Err(res) => return res,
+    };
+    let sql = format!("SELECT * FROM \"{catalog}\".\"{schema}\".\"{table}\"");
+    let req = ExecRequest {
+        path: uri.path().to_string(),
+        route: route.as_str().to_string(),
+        sql,
+        client: client_addr(connect_info),
+        query_id,
+        identity: request_identity(&user),
+        request_id: request_id(&headers),
+    };
+    let opts = ExecOptions::new(None, query.flightsql);
+    let (session, created) = match state.for_session(&headers, &user) {
+        Ok(session) => session,
+        Err(res) => return res,
+    };
+    let mut res = create_response(&State(session), req, opts, format, Some(page)).await;
+    if let Some(id) = created {
+        insert_session_id(&mut res, &id);
+    }
+    res
+}
+
+#[derive(Deserialize, Serialize)]
+struct GetTpchPathParams {
+    number: usize,
+}
+
+/// Execute a TPC-H query against the generated TPC-H tables
+#[utoipa::path(
+    get,
+    path = "/tpch/{number}",
+    params(("number" = usize, Path, description = "Number of the TPC-H query, from 1 to 22")),
+    responses(
+        (status = 200, description = "Results as JSON"),
+        (status = 400, description = "Unknown TPC-H query number", body = String),
+    )
+)]
+async fn get_tpch_query_handler(
+    state: State<ExecutionState>,
+    Path(path): Path<GetTpchPathParams>,
+    OriginalUri(uri): OriginalUri,
+    route: MatchedPath,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    user: RequestUser,
+) -> Response {
+    if let Some(sql) = tpch::sql_for_tpch_query(path.number) {
+        let req = ExecRequest {
+            path: uri.path().to_string(),
+            route: route.as_str().to_string(),
+            sql: sql.to_string(),
+            client: client_addr(connect_info),
+            query_id: None,
+            identity: request_identity(&user),
+            request_id: request_id(&headers),
+        };
+        let opts = ExecOptions::new(None, false);
+        create_response(&state, req, opts, ResultFormat::Json, None).await
+    } else {
+        (StatusCode::BAD_REQUEST, "Unknown TPC-H query number").into_response()
+    }
+}
+
+async fn response_for_sql(
+    State(state): &State<ExecutionState>,
+    sql: String,
+    opts: ExecOptions,
+    format: ResultFormat,
+    page: Option<Page>,
+) -> (Response, ResponseDetails) {
+    debug!("Executing sql: {sql}");
+    match state.execution.execute_sql_with_opts(&sql, opts).await {
+        Ok(ExecResult::RecordBatchStream(stream)) => {
+            batch_stream_to_response(stream, format, page).await
+        }
+        Ok(_) => {
+            let error = "Execution failed: unknown result type";
+            let res = (StatusCode::BAD_REQUEST, error).into_response();
+            (res, error_response_details(error))
+        }
+
+        Err(e) => {
+            let res = (StatusCode::BAD_REQUEST, format!("{}", e)).into_response();
+            (res, error_response_details(e))
+        }
+    }
+}
+
+struct ResponseDetails {
+    rows: u64,
+    error: Option<String>,
+}
+
+fn error_response_details(error: impl ToString) -> ResponseDetails {
+    ResponseDetails {
+        rows: 0,
+        error: Some(error.to_string()),
+    }
+}
+
+fn client_addr(connect_info: Option<ConnectInfo<SocketAddr>>) -> Option<String> {
+    connect_info.map(|ConnectInfo(addr)| addr.to_string())
+}
+
+/// Who sent the request: the user that authentication validated, if any. Credentials in its
+/// headers that the server didn't check aren't trusted.
+fn request_identity(user: &RequestUser) -> QueryIdentity {
+    QueryIdentity {
+        user: user.0.clone(),
+    }
+}
+
+async fn batch_stream_to_response(
+    batch_stream: SendableRecordBatchStream,
+    format: ResultFormat,
+    page: Option<Page>,
+) -> (Response, ResponseDetails) {
+    let mut writer = match BatchWriter::try_new(format, &batch_stream.schema()) {
+        Ok(writer) => writer,
+        Err(e) => {
+            error!("Error creating result writer: {}", e);
+            return (
+                (StatusCode::INTERNAL_SERVER_ERROR, "Serialization error").into_response(),
+                error_response_details(e),
+            );
+        }
+    };
+    let mut batch_stream = batch_stream;
+    let mut rows: usize = 0;
+    let mut has_next_page = false;
+    while let Some(maybe_batch) = batch_stream.next().await {
+        match maybe_batch {
+            Ok(mut batch) => {
+                // The stream includes one row past the page to tell if there is another page
+                if let Some(page) = page {
+                    let remaining = page.limit - rows;
+                    if batch.num_rows() > remaining {
+                        batch = batch.slice(0, remaining);
+                        has_next_page = true;
+                    }
+                }
+                if let Err(e) = writer.write(&batch) {
+                    error!("Error serializing result batches: {}", e);
+                    return (
+                        (StatusCode::INTERNAL_SERVER_ERROR, "Serialization error").into_response(),
+                        error_response_details(e),
+                    );
+                }
+                rows += batch.num_rows();
+                if has_next_page {
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("Error executing query: {}", e);
+                // TODO: Use more appropriate errors, like 404 for table that doesnt exist
+                return (
+                    (StatusCode::INTERNAL_SERVER_ERROR, "Query execution error").into_response(),
+                    error_response_details(e),
+                );
+            }
+        }
+    }
+
+    match writer.finish() {
+        Ok(buf) => {
+            let mut res = Response::new(Body::from(buf));
+            res.headers_mut().insert(
+                "content-type",
+                HeaderValue::from_static(format.content_type()),
+            );
+            if let Some(page) = page.filter(|_| has_next_page) {
+                res.headers_mut()
+                    .insert(NEXT_OFFSET_HEADER, HeaderValue::from(page.next_offset()));
+            }
+            let details = ResponseDetails {
+                rows: rows as u64,
+                error: None,
+            };
+            (res, details)
+        }
+        Err(e) => {
+            error!("Error finalizing result writer: {}", e);
+            (
+                (StatusCode::INTERNAL_SERVER_ERROR, "Finalization error").into_response(),
+                error_response_details(e),
+            )
+        }
+    }
+}
+
+async fn create_response(
+    state: &State<ExecutionState>,
+    mut req: ExecRequest,
+    opts: ExecOptions,
+    format: ResultFormat,
+    page: Option<Page>,
+) -> Response {
+    let opts = opts.with_identity(req.identity.clone());
+    let query_id = req
+        .query_id
+        .get_or_insert_with(|| Uuid::new_v4().to_string())
+        .clone();
+    let Some(statement) = state
+        .statements
+        .register(req.identity.user.as_deref(), &query_id)
+    else {
+        return (
+            StatusCode::CONFLICT,
+            format!("Query {query_id} is already running"),
+        )
+            .into_response();
+    };
+    if format == ResultFormat::NdJson {
+        // Headers are sent before the stream so there is no next page header, clients instead
+        // stop once they get a short page
+        let opts = match page {
+            Some(page) => page.apply(opts, false),
+            None => opts,
+        };
+        return create_streaming_response(state, req, opts, statement).await;
+    }
+    let opts = match page {
+        Some(page) => page.apply(opts, true),
+        None => opts,
+    };
+    let start = Timestamp::now();
+    // Dropping the response future when cancelled drops the result stream, stopping execution
+    let (mut res, details) = statement
+        .cancellation
+        .until_cancelled(response_for_sql(state, req.sql.clone(), opts, format, page))
+        .await
+        .unwrap_or_else(|| cancelled_response(&query_id));
+    insert_query_id(&mut res, &query_id);
+    record_response(state, req, start, res.status().as_u16(), details).await;
+    res
+}
+
+fn cancelled_response(query_id: &str) -> (Response, ResponseDetails) {
+    let error = format!("Query {query_id} was cancelled");
+    let res = (cancelled_status(), error.clone()).into_response();
+    (res, error_response_details(error))
+}
+
+fn insert_query_id(res: &mut Response, query_id: &str) {
+    if let Ok(value) = HeaderValue::from_str(query_id) {
+        res.headers_mut().insert(QUERY_ID_HEADER, value);
+    }
+}
+
+/// Executes the request and streams the results back as newline delimited JSON, writing each
+/// batch as it is produced. The body is only polled as fast as the client reads it, so at most a
+/// batch is buffered on the server regardless of the result size.
+async fn create_streaming_response(
+    state: &State<ExecutionState>,
+    req: ExecRequest,
+    opts: ExecOptions,
+    statement: RunningStatement,
+) -> Response {
+    let start = Timestamp::now();
+    let query_id = req.query_id.clone().unwrap_or_default();
+    debug!("Executing sql: {}", req.sql);
+    let executed = statement
+        .cancellation
+        .until_cancelled(state.execution.execute_sql_with_opts(&req.sql, opts))
+        .await;
+    let Some(executed) = executed else {
+        let (mut res, details) = cancelled_response(&query_id);
+        insert_query_id(&mut res, &query_id);
+        record_response(state, req, start, res.status().as_u16(), details).await;
+        return res;
+    };
+    let (mut res, details) = match executed {
+        Ok(ExecResult::RecordBatchStream(stream)) => {
+            let stream = statement.cancellation.wrap_stream(stream);
+            let log = StreamedRequest {
+                state: ExecutionState::clone(state),
+                req: Some(req),
+                start,
+                rows: 0,
+                error: None,
+                statement,
+            };
+            let mut res = Response::new(Body::from_stream(ndjson_stream(stream, log)));
+            res.headers_mut().insert(
+                "content-type",
+                HeaderValue::from_static(ResultFormat::NdJson.content_type()),
+            );
+            insert_query_id(&mut res, &query_id);
+            return res;
+        }
+        Ok(_) => {
+            let error = "Execution failed: unknown result type";
+            let res = (StatusCode::BAD_REQUEST, error).into_response();
+            (res, error_response_details(error))
+        }
+        Err(e) => {
+            let res = (StatusCode::BAD_REQUEST, format!("{}", e)).into_response();
+            (res, error_response_details(e))
+        }
+    };
+    insert_query_id(&mut res, &query_id);
+    record_response(state, req, start, res.status().as_u16(), details).await;
+    res
+}
+
+fn ndjson_stream(
+    batch_stream: SendableRecordBatchStream,
+    mut log: StreamedRequest,
+) -> impl Stream<Item = Result<Bytes, DataFusionError>> + Send {
+    batch_stream.map(move |maybe_batch| {
+        let batch = maybe_batch.inspect_err(|e| {
+            error!("Error executing query: {}", e);
+            log.error = Some(e.to_string());
+        })?;
+        let mut writer = LineDelimitedWriter::new(Vec::new());
+        writer
+            .write(&batch)
+            .and_then(|_| writer.finish())
+            .inspect_err(|e| {
+                error!("Error serializing result batches: {}", e);
+                log.error = Some(e.to_string());
+            })?;
+        let buf = writer.into_inner();
+        log.rows += batch.num_rows() as u64;
+        Ok(Bytes::from(buf))
+    })
+}
+
+/// Records a streamed request once its body is finished or dropped. The status has already been
+/// sent by then, so a failure part way through the stream is logged as a server error and the
+/// response is cut short.
+struct StreamedRequest {
+    state: ExecutionState,
+    req: Option<ExecRequest>,
+    start: Timestamp,
+    rows: u64,
+    error: Option<String>,
+    /// Keeps the statement registered, so that it can be cancelled, until the stream is finished
+    statement: RunningStatement,
+}
+
+impl Drop for StreamedRequest {
+    fn drop(&mut self) {
+        let Some(req) = self.req.take() else {
+            return;
+        };
+        let state = self.state.clone();
+        let start = self.start;
+        let status = if self.statement.cancellation.is_cancelled() {
+            cancelled_status()
+        } else if self.error.is_some() {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        };
+        let details = ResponseDetails {
+            rows: self.rows,
+            error: self.error.take(),
+        };
+        tokio::spawn(async move {
+            record_response(&state, req, start, status.as_u16(), details).await;
+        });
+    }
+}
+
+async fn record_response(
+    state: &ExecutionState,
+    req: ExecRequest,
+    start: Timestamp,
+    status: u16,
+    details: ResponseDetails,
+) {
+    let end = Timestamp::now();
+    let elapsed = end - start;
+    record_rows_returned(&req.route, details.rows);
+    if let Some(query_log) = state.execution.query_log() {
+        query_log.record(QueryLogEntry {
+            query_id: None,
+            client: req.client,
+            protocol: "http",
+            sql: req.sql.clone(),
+            start,
+            end,
+            rows: details.rows,
+            status,
+            error: details.error,
+            request_id: req.request_id.clone(),
+        });
+    }
+    let req = ObservabilityRequestDetails {
+        request_id: req.request_id,
+        path: req.path,
+        sql: Some(req.sql),
+        start_ms: start.as_millisecond(),
+        duration_ms: elapsed.get_milliseconds(),
+        rows: Some(details.rows),
+        status,
+    };
+    let obs = state.execution.execution_ctx().observability();
+    if let Err(e) = obs
+        .try_record_request(state.execution.session_ctx(), req)
+        .await
+    {
+        error!("Error recording request: {}", e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use axum::{body::Body, extract::ConnectInfo};
+    use datafusion::{
+        arrow::{array::AsArray, datatypes::UInt16Type, ipc::reader::StreamReader},
+        logical_expr::{col, lit, LogicalPlan},
+        sql::TableReference,
+    };
+    use datafusion_app::{
+        config::{BasicAuth, ExecutionConfig},
+        extensions::DftSessionStateBuilder,
+        local::ExecutionContext,
+        rewrite::{filter_table, PlanRewriter, QueryIdentity},
+    };
+    use futures::{SinkExt, StreamExt};
+    use http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tokio_tungstenite::tungstenite::{self, Message as WsClientMessage};
+    use url::Url;
+
+    use crate::{
+        config::{HttpCorsConfig, HttpRateLimitConfig, HttpServerConfig},
+        execution::AppExecution,
+        server::http::{
+            request_id::REQUEST_ID_HEADER, router::create_router, sessions::SESSION_HEADER,
+        },
+    };
+    use tower::ServiceExt;
+
+    fn setup() -> (AppExecution, HttpServerConfig) {
+        let config = ExecutionConfig::default();
+        let state = DftSessionStateBuilder::try_new(None)
+            .unwrap()
+            .build()
+            .unwrap();
+        let local =
+            ExecutionContext::try_new(&config, state, crate::APP_NAME, env!("CARGO_PKG_VERSION"))
+                .unwrap();
+        let execution = AppExecution::new(local);
+
+        let http_config = HttpServerConfig::default();
+        (execution, http_config)
+    }
+
+    #[tokio::test]
+    async fn test_ready() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+        let req = Request::builder()
+            .uri("/ready")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let readiness: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(readiness["ready"], true);
+        assert_eq!(readiness["checks"]["ddl"]["status"], "skipped");
+
+        let (mut execution, http_config) = setup();
+        let dir = tempfile::tempdir().unwrap();
+        execution.with_db_path(Url::from_directory_path(dir.path()).unwrap());
+        let ddl_error = execution
+            .session_ctx()
+            .sql("CREATE TABLE t AS SELECT * FROM missing")
+            .await
+            .unwrap_err();
+        execution.with_ddl_errors(vec![ddl_error]);
+        let router = create_router(execution, http_config);
+        let req = Request::builder()
+            .uri("/ready")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let readiness: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(readiness["ready"], false);
+        assert_eq!(readiness["checks"]["ddl"]["status"], "failed");
+        assert_eq!(readiness["checks"]["db"]["status"], "ok");
+        assert_eq!(readiness["checks"]["session"]["status"], "ok");
+        assert_eq!(readiness["checks"]["catalog"]["status"], "ok");
+        // Errors are logged rather than returned to unauthenticated probes
+        assert!(!String::from_utf8_lossy(&body).contains("missing"));
+    }
+
+    #[cfg(feature = "http-ui")]
+    #[tokio::test]
+    async fn test_ui() {
+        let (execution, mut http_config) = setup();
+        http_config.auth.bearer_token = Some("secret".to_string());
+        let router = create_router(execution, http_config);
+        let req = Request::builder().uri("/ui").body(Body::empty()).unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let (execution, mut http_config) = setup();
+        http_config.auth.bearer_token = Some("secret".to_string());
+        let router = create_router(execution, http_config);
+
+        // Sent back with errors, including those from before the request reaches a handler
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header(REQUEST_ID_HEADER, "client-id-1")
+            .header("Content-Type", "application/json")
+            .body(Body::from("{\"sql\": \"SELECT 1\"}"))
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[REQUEST_ID_HEADER], "client-id-1");
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header(REQUEST_ID_HEADER, "client-id-2")
+            .header("Authorization", "Bearer secret")
+            .header("Content-Type", "application/json")
+            .body(Body::from("{\"sql\": \"SELECT * FROM missing\"}"))
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers()[REQUEST_ID_HEADER], "client-id-2");
+
+        // Generated when the client doesn't send one, or sends one that isn't valid
+        for id in [None, Some(""), Some("has spaces")] {
+            let mut builder = Request::builder().uri("/health-check");
+            if let Some(id) = id {
+                builder = builder.header(REQUEST_ID_HEADER, id);
+            }
+            let res = router
+                .clone()
+                .oneshot(builder.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let generated = res.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+            assert!(uuid::Uuid::parse_str(generated).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_catalogs() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/catalogs")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let catalogs: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(catalogs
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({"name": "datafusion"})));
+    }
+
+    #[tokio::test]
+    async fn test_get_schemas() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/catalogs/datafusion/schemas")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let schemas: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(schemas
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({"name": "information_schema"})));
+
+        let req = Request::builder()
+            .uri("/catalogs/missing/schemas")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_tables() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/catalogs/datafusion/schemas/information_schema/tables")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let tables: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(tables
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({"name": "df_settings", "table_type": "VIEW"})));
+    }
+
+    #[tokio::test]
+    async fn test_get_table_schema() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/tables/datafusion/information_schema/df_settings/schema")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let schema: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(schema["table"], "df_settings");
+        assert_eq!(schema["columns"][0]["name"], "name");
+
+        let req = Request::builder()
+            .uri("/tables/datafusion/information_schema/df_setting/schema")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_upload_table() {
+        let (mut execution, http_config) = setup();
+        let dir = tempfile::tempdir().unwrap();
+        execution.with_db_path(Url::from_directory_path(dir.path()).unwrap());
+        let router = create_router(execution, http_config);
+
+        let boundary = "dft-upload";
+        let body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"people.csv\"\r\nContent-Type: text/csv\r\n\r\nname,age\nalice,30\nbob,40\n\r\n--{boundary}--\r\n"
+        );
+        let upload = || {
+            Request::builder()
+                .method("POST")
+                .uri("/tables/people")
+                .header(
+                    "Content-Type",
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(Body::from(body.clone()))
+                .unwrap()
+        };
+        let res = router.clone().oneshot(upload()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let schema: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(schema["table"], "people");
+        assert_eq!(schema["columns"][1]["name"], "age");
+        let table_dir = dir.path().join("tables/datafusion/public/people");
+        assert_eq!(std::fs::read_dir(table_dir).unwrap().count(), 1);
+        // The upload was moved to the table
+        let uploads = dir.path().join("uploads");
+        assert_eq!(std::fs::read_dir(uploads).unwrap().count(), 0);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                "{\"sql\": \"SELECT sum(age) AS total FROM people\"}",
+            ))
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let rows: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(rows[0]["total"], 70);
+
+        let res = router.oneshot(upload()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_get_table() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/table/datafusion/information_schema/df_settings")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_get_table_arrow_schema() {
+        let (execution, http_config) = setup();
+        execution
+            .session_ctx()
+            .sql("CREATE TABLE t (a BIGINT NOT NULL, b VARCHAR)")
+            .await
+            .unwrap();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/table/datafusion/public/t/schema")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let schema: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(schema["fields"][0]["name"], "a");
+        assert_eq!(schema["fields"][0]["data_type"], "Int64");
+        assert_eq!(schema["fields"][0]["nullable"], false);
+        assert_eq!(schema["fields"][1]["nullable"], true);
+
+        let req = Request::builder()
+            .uri("/table/datafusion/public/missing/schema")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let request = || {
+            Request::builder()
+                .uri("/table/datafusion/information_schema/df_settings")
+                .header("Accept-Encoding", "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+        let res = router.oneshot(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("content-encoding").unwrap(), "gzip");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(body.as_ref())
+            .read_to_string(&mut json)
+            .unwrap();
+        let rows: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(!rows.as_array().unwrap().is_empty());
+
+        let (execution, mut http_config) = setup();
+        http_config.compression.gzip = false;
+        let router = create_router(execution, http_config);
+        let res = router.oneshot(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_nonexistent_table() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/table/datafusion/information_schema/df_setting")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // Not sure why this isnt working
+    // #[tokio::test]
+    // async fn test_correct_when_flightsql_not_enabled() {
+    //     let (execution, http_config) = setup();
+    //     let router = create_router(execution, http_config);
+    //
+    //     let req = Request::builder()
+    //         .uri("/catalogs?flightsql=true")
+    //         .body(Body::empty())
+    //         .unwrap();
+    //     let res = router.oneshot(req).await.unwrap();
+    //     assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    //     let body = res.into_body().collect().await.unwrap().to_bytes();
+    //     assert_eq!(body, "FlightSQL is not enabled on this server".as_bytes())
+    // }
+
+    #[tokio::test]
+    async fn test_post_sql() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .body(Body::from("{\"sql\": \"SELECT 1\"}"))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_post_sql_ndjson() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/x-ndjson")
+            .body(Body::from(
+                "{\"sql\": \"SELECT * FROM (VALUES (1), (2), (3)) AS t(a)\"}",
+            ))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_post_sql_params() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+        let request = |body: &'static str| {
+            Request::builder()
+                .method("POST")
+                .uri("/sql")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let res = router
+            .clone()
+            .oneshot(request(
+                r#"{"sql": "SELECT $1 + 1 AS a, $2 AS b", "params": [41, "it's"]}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"[{"a":42,"b":"it's"}]"#.as_bytes());
+
+        let res = router
+            .clone()
+            .oneshot(request(
+                r#"{"sql": "SELECT $name AS name", "params": {"name": "dft"}}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"[{"name":"dft"}]"#.as_bytes());
+
+        let res = router
+            .oneshot(request(r#"{"sql": "SELECT $1", "params": [[1]]}"#))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+        let request = |sql: &str, session: Option<&str>| {
+            let mut builder = Request::builder()
+                .method("POST")
+                .uri("/sql")
+                .header("Content-Type", "application/json");
+            if let Some(session) = session {
+                builder = builder.header(SESSION_HEADER, session);
+            }
+            builder
+                .body(Body::from(format!(r#"{{"sql": "{sql}"}}"#)))
+                .unwrap()
+        };
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sessions")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let id = res.headers()[SESSION_HEADER].to_str().unwrap().to_string();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["id"], id.as_str());
+
+        let prepare = request("PREPARE plus_one(INT) AS SELECT $1 + 1 AS a", Some(&id));
+        let res = router.clone().oneshot(prepare).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let execute = request("EXECUTE plus_one(41)", Some(&id));
+        let res = router.clone().oneshot(execute).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"[{"a":42}]"#.as_bytes());
+
+        // The statement was only prepared in the session
+        let execute = request("EXECUTE plus_one(41)", None);
+        let res = router.clone().oneshot(execute).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // Views are also kept to the session, and `/table` is executed in it
+        let create_view = request("CREATE VIEW session_view AS VALUES (1)", Some(&id));
+        let res = router.clone().oneshot(create_view).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let select = request("SELECT * FROM session_view", None);
+        let res = router.clone().oneshot(select).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let req = Request::builder()
+            .uri("/table/datafusion/public/session_view")
+            .header(SESSION_HEADER, &id)
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::builder()
+            .method("DELETE")
+            .uri(format!("/sessions/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let execute = request("EXECUTE plus_one(41)", Some(&id));
+        let res = router.oneshot(execute).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_auto_create_sessions() {
+        let (execution, mut http_config) = setup();
+        http_config.auto_create_sessions = true;
+        let router = create_router(execution, http_config);
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"sql": "SELECT 1"}"#))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().contains_key(SESSION_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_explain() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/explain")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                "{\"sql\": \"SELECT a + 1 AS b FROM (VALUES (1), (2)) AS t(a) WHERE a > 1\"}",
+            ))
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let plans: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let logical = &plans["logical_plan"];
+        assert_eq!(logical["operator"], "Projection");
+        assert_eq!(logical["schema"][0]["name"], "b");
+        assert_eq!(logical["children"][0]["operator"], "Filter");
+        assert!(!plans["optimized_logical_plan"]["children"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+        let physical = &plans["physical_plan"];
+        assert_eq!(physical["schema"][0]["name"], "b");
+        assert!(physical["operator"].as_str().unwrap().ends_with("Exec"));
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/explain")
+            .header("Content-Type", "application/json")
+            .body(Body::from("{\"sql\": \"SELECT * FROM missing\"}"))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_post_sql_csv() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql?format=csv")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                "{\"sql\": \"SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(x, y)\"}",
+            ))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("content-type").unwrap(), "text/csv");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "x,y\n1,a\n2,b\n".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_get_table_arrow() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/table/datafusion/information_schema/df_settings")
+            .header("Accept", "application/vnd.apache.arrow.stream")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let reader = StreamReader::try_new(Cursor::new(body), None).unwrap();
+        assert_eq!(reader.schema().field(0).name(), "name");
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert!(rows > 0);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_format() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql?format=parquet")
+            .header("Content-Type", "application/json")
+            .body(Body::from("{\"sql\": \"SELECT 1\"}"))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
 
-    use crate::{
-        config::HttpServerConfig, execution::AppExecution, server::http::router::create_router,
-    };
-    use tower::ServiceExt;
+    #[tokio::test]
+    async fn test_post_sql_pagination() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+        let sql = "{\"sql\": \"SELECT * FROM (VALUES (1), (2), (3), (4), (5)) AS t(a)\"}";
 
-    fn setup() -> (AppExecution, HttpServerConfig) {
-        let config = ExecutionConfig::default();
-        let state = DftSessionStateBuilder::try_new(None)
-            .unwrap()
-            .build()
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql?limit=2&offset=2")
+            .header("Content-Type", "application/json")
+            .body(Body::from(sql))
             .unwrap();
-        let local =
-            ExecutionContext::try_new(&config, state, crate::APP_NAME, env!("CARGO_PKG_VERSION"))
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-next-offset").unwrap(), "4");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "[{\"a\":3},{\"a\":4}]".as_bytes());
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql?limit=2&offset=4")
+            .header("Content-Type", "application/json")
+            .body(Body::from(sql))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get("x-next-offset").is_none());
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "[{\"a\":5}]".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_request_overrides() {
+        let (execution, mut http_config) = setup();
+        http_config.result_limit = 3;
+        http_config.max_result_limit = Some(4);
+        let router = create_router(execution, http_config);
+        let sql = "{\"sql\": \"SELECT * FROM (VALUES (1), (2), (3), (4), (5)) AS t(a)\"}";
+        let request = |name: &str, value: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/sql")
+                .header("Content-Type", "application/json")
+                .header(name, value)
+                .body(Body::from(sql))
+                .unwrap()
+        };
+
+        let res = router
+            .clone()
+            .oneshot(request("x-dft-result-limit", "4"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("x-next-offset").unwrap(), "4");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "[{\"a\":1},{\"a\":2},{\"a\":3},{\"a\":4}]".as_bytes());
+
+        let res = router
+            .clone()
+            .oneshot(request("x-dft-result-limit", "5"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = router
+            .clone()
+            .oneshot(request("x-dft-timeout-seconds", "5"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Without a configured maximum the timeout can only be shortened
+        let res = router
+            .clone()
+            .oneshot(request("x-dft-timeout-seconds", "11"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = router
+            .oneshot(request("x-dft-timeout-seconds", "0"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let (execution, mut http_config) = setup();
+        http_config.rate_limit = Some(HttpRateLimitConfig {
+            requests_per_second: Some(0.5),
+            burst: 2,
+            max_concurrent_requests: None,
+        });
+        let router = create_router(execution, http_config);
+        let request = |uri: &str, ip: &str| {
+            Request::builder()
+                .uri(uri)
+                .extension(ConnectInfo(SocketAddr::new(ip.parse().unwrap(), 1234)))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            let res = router
+                .clone()
+                .oneshot(request("/", "10.0.0.1"))
+                .await
                 .unwrap();
-        let execution = AppExecution::new(local);
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        let res = router
+            .clone()
+            .oneshot(request("/", "10.0.0.1"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get("retry-after").unwrap(), "2");
+        // Other clients have their own limit
+        let res = router
+            .clone()
+            .oneshot(request("/", "10.0.0.2"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // Health checks aren't limited
+        let res = router
+            .oneshot(request("/health-check", "10.0.0.1"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
 
-        let http_config = HttpServerConfig::default();
-        (execution, http_config)
+    #[tokio::test]
+    async fn test_rate_limit_by_user() {
+        let (execution, mut http_config) = setup();
+        http_config.auth.basic_auth = Some(BasicAuth {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        });
+        http_config.rate_limit = Some(HttpRateLimitConfig {
+            requests_per_second: Some(0.5),
+            burst: 1,
+            max_concurrent_requests: None,
+        });
+        let router = create_router(execution, http_config);
+        let request = |credentials: &str, ip: &str| {
+            Request::builder()
+                .uri("/")
+                .header("Authorization", format!("Basic {credentials}"))
+                .extension(ConnectInfo(SocketAddr::new(ip.parse().unwrap(), 1234)))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Requests that fail authentication aren't counted against the user they claim
+        let res = router
+            .clone()
+            .oneshot(request("dXNlcjp3cm9uZw==", "10.0.0.1"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = router
+            .clone()
+            .oneshot(request("dXNlcjpwYXNz", "10.0.0.1"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // The user is limited from any address
+        let res = router
+            .oneshot(request("dXNlcjpwYXNz", "10.0.0.2"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrency_limit() {
+        let (execution, mut http_config) = setup();
+        http_config.rate_limit = Some(HttpRateLimitConfig {
+            requests_per_second: None,
+            burst: 10,
+            max_concurrent_requests: Some(1),
+        });
+        let router = create_router(execution, http_config);
+        let request = |sql: &'static str| {
+            Request::builder()
+                .method("POST")
+                .uri("/sql")
+                .header("Content-Type", "application/json")
+                .body(Body::from(sql))
+                .unwrap()
+        };
+
+        let running = tokio::spawn(router.clone().oneshot(request(
+            "{\"sql\": \"SELECT sum(value) FROM generate_series(1, 1000000000000)\"}",
+        )));
+        let mut throttled = false;
+        for _ in 0..100 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            let res = router
+                .clone()
+                .oneshot(request("{\"sql\": \"SELECT 1\"}"))
+                .await
+                .unwrap();
+            if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                assert_eq!(res.headers().get("retry-after").unwrap(), "1");
+                throttled = true;
+                break;
+            }
+        }
+        assert!(throttled);
+        running.abort();
     }
 
     #[tokio::test]
-    async fn test_get_catalog() {
+    async fn test_async_query() {
         let (execution, http_config) = setup();
         let router = create_router(execution, http_config);
 
         let req = Request::builder()
-            .uri("/catalog")
+            .method("POST")
+            .uri("/queries")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                "{\"sql\": \"SELECT * FROM (VALUES (1), (2)) AS t(a)\"}",
+            ))
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let submitted: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = submitted["id"].as_str().unwrap().to_string();
+
+        let mut status = String::new();
+        for _ in 0..100 {
+            let req = Request::builder()
+                .uri(format!("/queries/{id}"))
+                .body(Body::empty())
+                .unwrap();
+            let res = router.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            status = info["status"].as_str().unwrap().to_string();
+            if status != "running" {
+                assert_eq!(info["rows"], 2);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(status, "succeeded");
+
+        let req = Request::builder()
+            .uri(format!("/queries/{id}/results"))
             .body(Body::empty())
             .unwrap();
-        let res = router.oneshot(req).await.unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
         assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "[{\"a\":1},{\"a\":2}]".as_bytes());
+
+        let req = Request::builder()
+            .method("DELETE")
+            .uri(format!("/queries/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = Request::builder()
+            .uri(format!("/queries/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_get_table() {
+    async fn test_openapi_document() {
         let (execution, http_config) = setup();
         let router = create_router(execution, http_config);
 
         let req = Request::builder()
-            .uri("/table/datafusion/information_schema/df_settings")
+            .uri("/docs/openapi.json")
             .body(Body::empty())
             .unwrap();
         let res = router.oneshot(req).await.unwrap();
         assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let doc: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(doc["paths"]["/sql"]["post"].is_object());
+        assert!(doc["paths"]["/queries/{id}"]["delete"].is_object());
+        assert!(doc["components"]["schemas"]["JobInfo"].is_object());
     }
 
     #[tokio::test]
-    async fn test_get_nonexistent_table() {
-        let (execution, http_config) = setup();
+    async fn test_bearer_auth() {
+        let (execution, mut http_config) = setup();
+        http_config.auth.bearer_token = Some("MyToken".to_string());
         let router = create_router(execution, http_config);
 
+        let sql = || Body::from("{\"sql\": \"SELECT 1\"}");
         let req = Request::builder()
-            .uri("/table/datafusion/information_schema/df_setting")
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .body(sql())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .header("Authorization", "Bearer MyToken")
+            .body(sql())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::builder()
+            .uri("/health-check")
             .body(Body::empty())
             .unwrap();
         let res = router.oneshot(req).await.unwrap();
-        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.status(), StatusCode::OK);
     }
 
-    // Not sure why this isnt working
-    // #[tokio::test]
-    // async fn test_correct_when_flightsql_not_enabled() {
-    //     let (execution, http_config) = setup();
-    //     let router = create_router(execution, http_config);
-    //
-    //     let req = Request::builder()
-    //         .uri("/catalog?flightsql=true")
-    //         .body(Body::empty())
-    //         .unwrap();
-    //     let res = router.oneshot(req).await.unwrap();
-    //     assert_eq!(res.status(), StatusCode::BAD_REQUEST);
-    //     let body = res.into_body().collect().await.unwrap().to_bytes();
-    //     assert_eq!(body, "FlightSQL is not enabled on this server".as_bytes())
-    // }
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let (execution, mut http_config) = setup();
+        http_config.auth.basic_auth = Some(BasicAuth {
+            username: "User".to_string(),
+            password: "Pass".to_string(),
+        });
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .uri("/catalogs")
+            .header("Authorization", "Basic VXNlcjpXcm9uZw==")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers().get("www-authenticate").unwrap(), "Basic");
+
+        let req = Request::builder()
+            .uri("/catalogs")
+            .header("Authorization", "Basic VXNlcjpQYXNz")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    /// Only lets users read the rows of `owned` they own
+    #[derive(Debug)]
+    struct OwnerFilter;
+
+    impl PlanRewriter for OwnerFilter {
+        fn rewrite(
+            &self,
+            plan: LogicalPlan,
+            identity: &QueryIdentity,
+        ) -> datafusion::error::Result<LogicalPlan> {
+            let user = identity.user.clone().unwrap_or_default();
+            filter_table(
+                plan,
+                &TableReference::bare("owned"),
+                col("owner").eq(lit(user)),
+            )
+        }
+    }
 
     #[tokio::test]
-    async fn test_post_sql() {
-        let (execution, http_config) = setup();
+    async fn test_plan_rewriter() {
+        let mut builder = DftSessionStateBuilder::try_new(None).unwrap();
+        builder.add_plan_rewriter(Arc::new(OwnerFilter));
+        let state = builder.build().unwrap();
+        let local = ExecutionContext::try_new(
+            &ExecutionConfig::default(),
+            state,
+            crate::APP_NAME,
+            env!("CARGO_PKG_VERSION"),
+        )
+        .unwrap();
+        local
+            .session_ctx()
+            .sql("CREATE TABLE owned AS SELECT * FROM (VALUES ('User', 1), ('Other', 2), ('User', 3)) AS t(owner, v)")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let mut http_config = HttpServerConfig::default();
+        http_config.auth.basic_auth = Some(BasicAuth {
+            username: "User".to_string(),
+            password: "Pass".to_string(),
+        });
+        let execution = AppExecution::new(local);
+        let router = create_router(execution.clone(), http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Authorization", "Basic VXNlcjpQYXNz")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"sql": "SELECT sum(v) AS v FROM owned"}"#))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"[{"v":4}]"#.as_bytes());
+
+        // Credentials that the server doesn't check don't make a client that user
+        let router = create_router(execution, HttpServerConfig::default());
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Authorization", "Basic VXNlcjpQYXNz")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"sql": "SELECT count(*) AS n FROM owned"}"#))
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"[{"n":0}]"#.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let (execution, mut http_config) = setup();
+        http_config.auth.bearer_token = Some("MyToken".to_string());
+        http_config.cors = Some(HttpCorsConfig {
+            allowed_origins: vec!["https://example.com".to_string()],
+            ..Default::default()
+        });
         let router = create_router(execution, http_config);
 
+        // Preflight requests are answered without credentials
+        let req = Request::builder()
+            .method("OPTIONS")
+            .uri("/sql")
+            .header("Origin", "https://example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .header(
+                "Access-Control-Request-Headers",
+                "authorization,content-type",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("access-control-allow-origin").unwrap(),
+            "https://example.com"
+        );
+
         let req = Request::builder()
             .method("POST")
             .uri("/sql")
+            .header("Origin", "https://example.com")
             .header("Content-Type", "application/json")
+            .header("Authorization", "Bearer MyToken")
             .body(Body::from("{\"sql\": \"SELECT 1\"}"))
             .unwrap();
-        let res = router.oneshot(req).await.unwrap();
+        let res = router.clone().oneshot(req).await.unwrap();
         assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("access-control-allow-origin").unwrap(),
+            "https://example.com"
+        );
+
+        let req = Request::builder()
+            .uri("/health-check")
+            .header("Origin", "https://other.com")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancel_sql() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql")
+            .header("Content-Type", "application/json")
+            .header("x-query-id", "long-query")
+            .body(Body::from(
+                "{\"sql\": \"SELECT sum(value) FROM generate_series(1, 1000000000000)\"}",
+            ))
+            .unwrap();
+        let running = tokio::spawn(router.clone().oneshot(req));
+
+        let mut cancelled = false;
+        for _ in 0..100 {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/sql/long-query/cancel")
+                .body(Body::empty())
+                .unwrap();
+            let res = router.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            if body["cancelled"] == true {
+                cancelled = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(cancelled);
+
+        let res = running.await.unwrap().unwrap();
+        assert_eq!(res.status().as_u16(), 499);
+        assert_eq!(res.headers().get("x-query-id").unwrap(), "long-query");
+
+        // The statement is no longer running
+        let req = Request::builder()
+            .method("POST")
+            .uri("/sql/long-query/cancel")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{\"cancelled\":false}".as_bytes());
+    }
+
+    async fn next_json<S>(ws: &mut S) -> serde_json::Value
+    where
+        S: futures::Stream<Item = Result<WsClientMessage, tungstenite::Error>> + Unpin,
+    {
+        let message = ws.next().await.unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_websocket() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution, http_config);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+            .await
+            .unwrap();
+        ws.send(WsClientMessage::text(
+            r#"{"sql": "SELECT * FROM (VALUES (1), (2)) AS t(a)"}"#,
+        ))
+        .await
+        .unwrap();
+
+        let start = next_json(&mut ws).await;
+        assert_eq!(start["type"], "start");
+        let batch = next_json(&mut ws).await;
+        assert_eq!(batch["type"], "batch");
+        assert_eq!(batch["rows"], serde_json::json!([{"a": 1}, {"a": 2}]));
+        let summary = next_json(&mut ws).await;
+        assert_eq!(summary["type"], "summary");
+        assert_eq!(summary["rows"], 2);
+        assert_eq!(summary["query_id"], start["query_id"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_closing_websocket_cancels_query() {
+        let (execution, http_config) = setup();
+        let router = create_router(execution.clone(), http_config);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+            .await
+            .unwrap();
+        ws.send(WsClientMessage::text(
+            r#"{"sql": "SELECT sum(value) FROM generate_series(1, 1000000000000)"}"#,
+        ))
+        .await
+        .unwrap();
+        let start = next_json(&mut ws).await;
+        assert_eq!(start["type"], "start");
+        ws.close(None).await.unwrap();
+
+        // The query is only recorded once it stops
+        let mut status = None;
+        for _ in 0..100 {
+            let batches = execution
+                .execution_ctx()
+                .session_ctx()
+                .sql("SELECT status FROM dft.observability.requests WHERE path = '/ws'")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+            if let Some(batch) = batches.iter().find(|b| b.num_rows() > 0) {
+                status = Some(batch.column(0).as_primitive::<UInt16Type>().value(0));
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(status, Some(499));
     }
 
     #[tokio::test]
@@ -476,12 +3362,12 @@ mod flightsql_test {
     }
 
     #[tokio::test]
-    async fn test_get_catalog() {
+    async fn test_get_catalogs() {
         let (execution, http_config) = setup().await;
         let router = create_router(execution, http_config);
 
         let req = Request::builder()
-            .uri("/catalog?flightsql=true")
+            .uri("/catalogs?flightsql=true")
             .body(Body::empty())
             .unwrap();
         let res = router.oneshot(req).await.unwrap();
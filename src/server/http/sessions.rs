@@ -0,0 +1,177 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client sessions, which keep the settings changed with `SET` and the statements prepared with
+//! `PREPARE` across a client's requests
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use uuid::Uuid;
+
+use crate::execution::AppExecution;
+
+/// Header holding the id of the session a request is executed in
+pub const SESSION_HEADER: &str = "x-dft-session";
+
+/// How long a session is kept after its last request by default
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// How many sessions can be open at once by default
+pub const DEFAULT_MAX_SESSIONS: usize = 1000;
+
+#[derive(Debug)]
+struct Session {
+    execution: AppExecution,
+    /// The user that started the session, who is the only one that can use it
+    owner: Option<String>,
+    last_used: Instant,
+}
+
+/// The open sessions by their id
+#[derive(Clone, Debug)]
+pub struct Sessions {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+}
+
+impl Sessions {
+    /// Sessions are dropped once they haven't been used for `idle_timeout`, and the least
+    /// recently used session is dropped to start another once there are `max_sessions`
+    pub fn new(idle_timeout: Duration, max_sessions: usize) -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            idle_timeout,
+            max_sessions,
+        }
+    }
+
+    /// Lock the sessions, dropping the ones that have been idle for too long
+    fn lock(&self, now: Instant) -> MutexGuard<'_, HashMap<String, Session>> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        sessions.retain(|_, session| now.duration_since(session.last_used) < self.idle_timeout);
+        sessions
+    }
+
+    /// Start a session for `owner` with its own copy of the settings of `execution`, and its own
+    /// views, returning its id and execution
+    pub fn create(&self, execution: &AppExecution, owner: Option<&str>) -> (String, AppExecution) {
+        let id = Uuid::new_v4().to_string();
+        let execution = execution.fork_session();
+        let now = Instant::now();
+        let mut sessions = self.lock(now);
+        while sessions.len() >= self.max_sessions {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(id, _)| id.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            debug!("dropping least recently used session {oldest}");
+            sessions.remove(&oldest);
+        }
+        sessions.insert(
+            id.clone(),
+            Session {
+                execution: execution.clone(),
+                owner: owner.map(str::to_string),
+                last_used: now,
+            },
+        );
+        metrics::gauge!("http_sessions_active").set(sessions.len() as f64);
+        debug!("created session {id}");
+        (id, execution)
+    }
+
+    /// The execution of session `id`, keeping the session open for another idle timeout, or
+    /// `None` if there is no such session, it expired or it was started by another user
+    pub fn get(&self, id: &str, user: Option<&str>) -> Option<AppExecution> {
+        let now = Instant::now();
+        let mut sessions = self.lock(now);
+        metrics::gauge!("http_sessions_active").set(sessions.len() as f64);
+        let session = sessions
+            .get_mut(id)
+            .filter(|session| session.owner.as_deref() == user)?;
+        session.last_used = now;
+        Some(session.execution.clone())
+    }
+
+    /// End session `id` if it was started by `user`, returning whether it was open
+    pub fn remove(&self, id: &str, user: Option<&str>) -> bool {
+        let mut sessions = self.lock(Instant::now());
+        let owned = sessions
+            .get(id)
+            .is_some_and(|session| session.owner.as_deref() == user);
+        if owned {
+            sessions.remove(id);
+        }
+        metrics::gauge!("http_sessions_active").set(sessions.len() as f64);
+        owned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion_app::{extensions::DftSessionStateBuilder, local::ExecutionContext};
+
+    use super::*;
+
+    fn execution() -> AppExecution {
+        let state = DftSessionStateBuilder::try_new(None)
+            .unwrap()
+            .build()
+            .unwrap();
+        let local = ExecutionContext::try_new(
+            &Default::default(),
+            state,
+            crate::APP_NAME,
+            env!("CARGO_PKG_VERSION"),
+        )
+        .unwrap();
+        AppExecution::new(local)
+    }
+
+    #[test]
+    fn test_sessions_belong_to_their_owner() {
+        let sessions = Sessions::new(DEFAULT_SESSION_IDLE_TIMEOUT, 10);
+        let (id, _) = sessions.create(&execution(), Some("alice"));
+        assert!(sessions.get(&id, Some("bob")).is_none());
+        assert!(sessions.get(&id, None).is_none());
+        assert!(sessions.get(&id, Some("alice")).is_some());
+        assert!(!sessions.remove(&id, Some("bob")));
+        assert!(sessions.remove(&id, Some("alice")));
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        let sessions = Sessions::new(DEFAULT_SESSION_IDLE_TIMEOUT, 2);
+        let execution = execution();
+        let (first, _) = sessions.create(&execution, None);
+        let (second, _) = sessions.create(&execution, None);
+        // Using the first session makes the second the least recently used
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(sessions.get(&first, None).is_some());
+        let (third, _) = sessions.create(&execution, None);
+        assert!(sessions.get(&first, None).is_some());
+        assert!(sessions.get(&second, None).is_none());
+        assert!(sessions.get(&third, None).is_some());
+    }
+}
@@ -0,0 +1,118 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Statements being executed by the HTTP server, tracked by id so that they can be cancelled
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use datafusion_app::cancel::QueryHandle;
+use http::StatusCode;
+
+/// Header holding the id of a statement. Clients can set it on a request to choose the id, which
+/// lets them cancel statements whose response only arrives once they're finished. Ids are scoped
+/// to the user that sent the request, so clients can only cancel their own statements.
+pub const QUERY_ID_HEADER: &str = "x-query-id";
+
+/// Status of requests whose statement was cancelled, as used by nginx for requests closed by the
+/// client
+pub fn cancelled_status() -> StatusCode {
+    StatusCode::from_u16(499).expect("499 is a valid status code")
+}
+
+/// A statement's id, along with the user that started it, if the request was authenticated
+type StatementKey = (Option<String>, String);
+
+/// The statements currently being executed by their user and id
+#[derive(Clone, Debug, Default)]
+pub struct RunningStatements {
+    statements: Arc<Mutex<HashMap<StatementKey, QueryHandle>>>,
+}
+
+impl RunningStatements {
+    fn lock(&self) -> MutexGuard<'_, HashMap<StatementKey, QueryHandle>> {
+        self.statements
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Register the statement `id` started by `user`, or `None` if `user` already has a statement
+    /// with that id running
+    pub fn register(&self, user: Option<&str>, id: &str) -> Option<RunningStatement> {
+        let key = (user.map(str::to_string), id.to_string());
+        let mut statements = self.lock();
+        if statements.contains_key(&key) {
+            return None;
+        }
+        let cancellation = QueryHandle::new();
+        statements.insert(key.clone(), cancellation.clone());
+        Some(RunningStatement {
+            key,
+            statements: self.clone(),
+            cancellation,
+        })
+    }
+
+    /// Cancel the statement `id` started by `user`, returning whether it was running
+    pub fn cancel(&self, user: Option<&str>, id: &str) -> bool {
+        let key = (user.map(str::to_string), id.to_string());
+        match self.lock().get(&key) {
+            Some(cancellation) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A registered statement, unregistered when dropped
+pub struct RunningStatement {
+    key: StatementKey,
+    statements: RunningStatements,
+    pub cancellation: QueryHandle,
+}
+
+impl Drop for RunningStatement {
+    fn drop(&mut self) {
+        self.statements.lock().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements_are_scoped_to_their_user() {
+        let statements = RunningStatements::default();
+        let alice = statements.register(Some("alice"), "q").unwrap();
+        assert!(statements.register(Some("alice"), "q").is_none());
+        let bob = statements.register(Some("bob"), "q").unwrap();
+
+        assert!(!statements.cancel(None, "q"));
+        assert!(!statements.cancel(Some("mallory"), "q"));
+        assert!(!alice.cancellation.is_cancelled());
+
+        assert!(statements.cancel(Some("alice"), "q"));
+        assert!(alice.cancellation.is_cancelled());
+        assert!(!bob.cancellation.is_cancelled());
+
+        drop(alice);
+        assert!(!statements.cancel(Some("alice"), "q"));
+    }
+}
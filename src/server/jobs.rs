@@ -0,0 +1,443 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Registry of the queries submitted to the HTTP server's asynchronous query API, which run in the
+//! background so that they outlive the request that started them
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use datafusion::arrow::{array::RecordBatch, datatypes::SchemaRef};
+use datafusion_app::{ExecOptions, ExecResult};
+use jiff::Timestamp;
+use log::debug;
+use serde::Serialize;
+use tokio::task::AbortHandle;
+use tokio_stream::StreamExt;
+use tracing::{Instrument, Span};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{execution::AppExecution, server::query_log::QueryLogEntry};
+
+/// How long a finished job, and its results, are kept before being dropped
+pub const DEFAULT_JOB_TTL: Duration = Duration::from_secs(3600);
+
+/// How many jobs, running or finished, are kept at once by default
+pub const DEFAULT_MAX_JOBS: usize = 1000;
+
+/// How often jobs past their TTL are dropped, so their results don't stay in memory until the
+/// next request for a job
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{status}")
+    }
+}
+
+/// The state of a job, as reported by its status endpoint
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct JobInfo {
+    pub id: String,
+    pub sql: String,
+    pub status: JobStatus,
+    /// Rows produced so far
+    pub rows: u64,
+    pub submitted_at: String,
+    pub finished_at: Option<String>,
+    pub elapsed_ms: i64,
+    pub error: Option<String>,
+}
+
+/// Results of a job that succeeded
+pub struct JobResults {
+    pub schema: SchemaRef,
+    pub batches: Vec<RecordBatch>,
+}
+
+#[derive(Debug)]
+struct Job {
+    sql: String,
+    status: JobStatus,
+    rows: u64,
+    submitted: Timestamp,
+    finished: Option<(Timestamp, Instant)>,
+    error: Option<String>,
+    schema: Option<SchemaRef>,
+    batches: Vec<RecordBatch>,
+    /// Aborts the task executing the job
+    task: Option<AbortHandle>,
+}
+
+impl Job {
+    fn info(&self, id: &str) -> JobInfo {
+        let end = self
+            .finished
+            .map(|(end, _)| end)
+            .unwrap_or_else(Timestamp::now);
+        JobInfo {
+            id: id.to_string(),
+            sql: self.sql.clone(),
+            status: self.status,
+            rows: self.rows,
+            submitted_at: self.submitted.to_string(),
+            finished_at: self.finished.map(|(end, _)| end.to_string()),
+            elapsed_ms: (end - self.submitted).get_milliseconds(),
+            error: self.error.clone(),
+        }
+    }
+
+    fn finish(&mut self, status: JobStatus, error: Option<String>) {
+        self.status = status;
+        self.error = error;
+        self.finished = Some((Timestamp::now(), Instant::now()));
+        self.task = None;
+    }
+}
+
+/// Queries executing in the background, along with the results of those that finished. Finished
+/// jobs are dropped once they have been deleted or the TTL has passed since they finished, and
+/// the oldest finished job is dropped to make room when `max_jobs` are kept.
+#[derive(Clone, Debug)]
+pub struct QueryJobs {
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+    ttl: Duration,
+    max_jobs: usize,
+}
+
+impl Default for QueryJobs {
+    fn default() -> Self {
+        Self::new(DEFAULT_JOB_TTL, DEFAULT_MAX_JOBS)
+    }
+}
+
+/// A job was submitted while the maximum number of jobs were running
+#[derive(Debug)]
+pub struct TooManyJobs(pub usize);
+
+impl std::fmt::Display for TooManyJobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} queries are already running, try again later", self.0)
+    }
+}
+
+impl QueryJobs {
+    pub fn new(ttl: Duration, max_jobs: usize) -> Self {
+        Self {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            max_jobs,
+        }
+    }
+
+    /// Drop expired jobs every [`SWEEP_INTERVAL`] until every clone of the jobs is dropped
+    pub fn start_sweeping(&self) {
+        let jobs = Arc::downgrade(&self.jobs);
+        let (ttl, max_jobs) = (self.ttl, self.max_jobs);
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(SWEEP_INTERVAL);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes immediately, before any job is submitted
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                let Some(jobs) = Weak::upgrade(&jobs) else {
+                    break;
+                };
+                let store = QueryJobs {
+                    jobs,
+                    ttl,
+                    max_jobs,
+                };
+                store.collect(&mut store.lock(), Instant::now());
+            }
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Job>> {
+        // Jobs are only updated by single assignments so they are still consistent after a panic
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Start executing `sql` in the background, returning the id of its job. Fails when the
+    /// maximum number of jobs are running.
+    pub fn submit(
+        &self,
+        execution: AppExecution,
+        sql: String,
+        opts: ExecOptions,
+        client: Option<String>,
+        request_id: Option<String>,
+    ) -> Result<String, TooManyJobs> {
+        let id = Uuid::new_v4().to_string();
+        let mut jobs = self.lock();
+        self.collect(&mut jobs, Instant::now());
+        if jobs.len() >= self.max_jobs {
+            // Make room by dropping the results of the job that finished first
+            let oldest = jobs
+                .iter()
+                .filter_map(|(id, job)| job.finished.map(|(_, finished)| (finished, id)))
+                .min()
+                .map(|(_, id)| id.clone());
+            match oldest {
+                Some(oldest) => {
+                    debug!("dropping query job {oldest} to make room for {id}");
+                    jobs.remove(&oldest);
+                }
+                None => return Err(TooManyJobs(jobs.len())),
+            }
+        }
+        jobs.insert(
+            id.clone(),
+            Job {
+                sql: sql.clone(),
+                status: JobStatus::Running,
+                rows: 0,
+                submitted: Timestamp::now(),
+                finished: None,
+                error: None,
+                schema: None,
+                batches: Vec::new(),
+                task: None,
+            },
+        );
+        let store = self.clone();
+        let job_id = id.clone();
+        // Keep the span of the request so that the job's logs can be correlated with it
+        let task = tokio::spawn(
+            async move {
+                store
+                    .run(&job_id, execution, sql, opts, client, request_id)
+                    .await;
+            }
+            .instrument(Span::current()),
+        );
+        // The lock is still held so the job can't finish before its task is set
+        if let Some(job) = jobs.get_mut(&id) {
+            job.task = Some(task.abort_handle());
+        }
+        Ok(id)
+    }
+
+    async fn run(
+        &self,
+        id: &str,
+        execution: AppExecution,
+        sql: String,
+        opts: ExecOptions,
+        client: Option<String>,
+        request_id: Option<String>,
+    ) {
+        debug!("Executing job {id}: {sql}");
+        let start = Timestamp::now();
+        let result = match execution.execute_sql_with_opts(&sql, opts).await {
+            Ok(ExecResult::RecordBatchStream(mut stream)) => {
+                self.update(id, |job| job.schema = Some(stream.schema()));
+                let mut result = Ok(());
+                while let Some(maybe_batch) = stream.next().await {
+                    match maybe_batch {
+                        Ok(batch) => self.update(id, |job| {
+                            job.rows += batch.num_rows() as u64;
+                            job.batches.push(batch);
+                        }),
+                        Err(e) => {
+                            result = Err(e.to_string());
+                            break;
+                        }
+                    }
+                }
+                result
+            }
+            Ok(_) => Err("Execution failed: unknown result type".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        let mut rows = 0;
+        self.update(id, |job| {
+            rows = job.rows;
+            match &result {
+                Ok(()) => job.finish(JobStatus::Succeeded, None),
+                Err(e) => job.finish(JobStatus::Failed, Some(e.clone())),
+            }
+        });
+        if let Some(query_log) = execution.query_log() {
+            query_log.record(QueryLogEntry {
+                query_id: Some(id.to_string()),
+                client,
+                protocol: "http",
+                sql,
+                start,
+                end: Timestamp::now(),
+                rows,
+                status: if result.is_ok() { 200 } else { 500 },
+                error: result.err(),
+                request_id,
+            });
+        }
+    }
+
+    /// Apply `f` to the job `id`, unless it has been cancelled
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.lock().get_mut(id) {
+            if job.status == JobStatus::Running {
+                f(job)
+            }
+        }
+    }
+
+    /// The state of the job `id`
+    pub fn status(&self, id: &str) -> Option<JobInfo> {
+        let mut jobs = self.lock();
+        self.collect(&mut jobs, Instant::now());
+        jobs.get(id).map(|job| job.info(id))
+    }
+
+    /// The results of the job `id` if it succeeded, otherwise its state
+    pub fn results(&self, id: &str) -> Option<Result<JobResults, JobInfo>> {
+        let mut jobs = self.lock();
+        self.collect(&mut jobs, Instant::now());
+        let job = jobs.get(id)?;
+        match (&job.status, &job.schema) {
+            (JobStatus::Succeeded, Some(schema)) => Some(Ok(JobResults {
+                schema: SchemaRef::clone(schema),
+                batches: job.batches.clone(),
+            })),
+            _ => Some(Err(job.info(id))),
+        }
+    }
+
+    /// Cancel the job `id` if it's running, otherwise drop it along with its results. Returns
+    /// `false` if there is no such job.
+    pub fn cancel(&self, id: &str) -> bool {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get_mut(id) else {
+            return false;
+        };
+        if let Some(task) = job.task.take() {
+            debug!("Cancelling job {id}");
+            task.abort();
+            job.batches.clear();
+            job.finish(JobStatus::Cancelled, None);
+        } else {
+            jobs.remove(id);
+        }
+        true
+    }
+
+    /// Drop the jobs that finished more than the TTL before `now`
+    fn collect(&self, jobs: &mut HashMap<String, Job>, now: Instant) {
+        let before = jobs.len();
+        jobs.retain(|_, job| match job.finished {
+            Some((_, finished)) => now.duration_since(finished) < self.ttl,
+            None => true,
+        });
+        let expired = before - jobs.len();
+        if expired > 0 {
+            debug!("dropped {expired} expired query jobs");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion_app::local::ExecutionContext;
+
+    use super::*;
+
+    fn job(finished: Option<Instant>) -> Job {
+        Job {
+            sql: "SELECT 1".to_string(),
+            status: if finished.is_some() {
+                JobStatus::Succeeded
+            } else {
+                JobStatus::Running
+            },
+            rows: 0,
+            submitted: Timestamp::now(),
+            finished: finished.map(|finished| (Timestamp::now(), finished)),
+            error: None,
+            schema: None,
+            batches: Vec::new(),
+            task: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_jobs() {
+        let jobs = QueryJobs::new(DEFAULT_JOB_TTL, 2);
+        let execution = AppExecution::new(ExecutionContext::test());
+        let now = Instant::now();
+        jobs.lock().insert("running".to_string(), job(None));
+        jobs.lock().insert("finished".to_string(), job(Some(now)));
+
+        // The finished job is dropped to make room
+        let id = jobs
+            .submit(
+                execution.clone(),
+                "SELECT 1".to_string(),
+                ExecOptions::new(None, false),
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(jobs.status("finished").is_none());
+        assert!(jobs.status(&id).is_some());
+
+        // Until every job kept is running
+        jobs.lock().get_mut(&id).unwrap().finished = None;
+        jobs.lock().get_mut(&id).unwrap().status = JobStatus::Running;
+        assert!(jobs
+            .submit(
+                execution,
+                "SELECT 1".to_string(),
+                ExecOptions::new(None, false),
+                None,
+                None,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_expired_jobs_are_dropped() {
+        let jobs = QueryJobs::new(Duration::from_secs(60), DEFAULT_MAX_JOBS);
+        let now = Instant::now();
+        jobs.lock().insert("old".to_string(), job(Some(now)));
+        jobs.lock().insert("running".to_string(), job(None));
+        jobs.collect(&mut jobs.lock(), now + Duration::from_secs(61));
+        assert_eq!(
+            jobs.lock().keys().cloned().collect::<Vec<_>>(),
+            ["running".to_string()]
+        );
+    }
+}
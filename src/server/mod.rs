@@ -16,20 +16,122 @@
 // under the License.
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
-use color_eyre::Result;
-use log::info;
-use metrics::{describe_counter, describe_histogram};
+use color_eyre::{eyre::eyre, Result};
+use datafusion_app::config::{merge_configs, ExecutionConfig};
+use log::{error, info};
+use metrics::{describe_counter, describe_gauge, describe_histogram};
 use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+use tokio::{signal, task::JoinHandle};
 
 #[cfg(feature = "flightsql")]
 pub mod flightsql;
 #[cfg(feature = "http")]
 pub mod http;
+#[cfg(feature = "http")]
+pub mod jobs;
+pub mod query_log;
+pub mod rate_limit;
+#[cfg(all(feature = "flightsql", feature = "http"))]
+pub mod serve;
+#[cfg(any(feature = "flightsql", feature = "http"))]
+pub mod tls;
 
 fn describe_metrics() {
     describe_counter!("requests", "Incoming requests by FlightSQL endpoint");
 
+    describe_counter!(
+        "tls_handshakes_rejected",
+        "Connections to the FlightSQL or HTTP server dropped because the TLS handshake failed, including clients without a trusted certificate"
+    );
+
+    describe_gauge!(
+        "flightsql_queries_active",
+        "Queries whose results are being streamed by the FlightSQL server"
+    );
+
+    describe_counter!(
+        "flightsql_rows_streamed",
+        "Rows of query results streamed by the FlightSQL server"
+    );
+
+    describe_counter!(
+        "flightsql_bytes_streamed",
+        "Bytes of Arrow IPC data streamed by the FlightSQL server"
+    );
+
+    describe_counter!(
+        "flightsql_rows_ingested",
+        "Rows written to tables by FlightSQL DoExchange ingestion"
+    );
+
+    describe_counter!(
+        "flightsql_errors",
+        "Errors returned by the FlightSQL server by type, the error class for query errors and the gRPC code otherwise"
+    );
+
+    describe_counter!(
+        "flightsql_requests_throttled",
+        "Requests to the FlightSQL server rejected by its per client rate limit"
+    );
+
+    describe_counter!(
+        "flightsql_results_spilled",
+        "Query results the FlightSQL server spilled to disk because they outgrew memory"
+    );
+
+    describe_counter!(
+        "flightsql_spilled_bytes",
+        "Bytes of query results the FlightSQL server spilled to disk"
+    );
+
+    describe_gauge!(
+        "flightsql_tickets_active",
+        "Queries planned by the FlightSQL server whose tickets can be fetched"
+    );
+
+    describe_counter!(
+        "flightsql_tickets_expired",
+        "Tickets dropped by the FlightSQL server because they weren't fetched in time"
+    );
+
+    describe_gauge!(
+        "flightsql_sessions_active",
+        "Client connections with a FlightSQL session"
+    );
+
+    describe_gauge!(
+        "flightsql_statement_queue_depth",
+        "Statements waiting for the FlightSQL server's concurrency limit to allow them to execute"
+    );
+
+    #[cfg(feature = "http")]
+    {
+        describe_counter!(
+            "http_requests",
+            "Requests served by the HTTP server by route, method and status"
+        );
+        describe_histogram!(
+            "http_request_latency_ms",
+            metrics::Unit::Milliseconds,
+            "Time for the HTTP server to respond by route and method, up to the headers of streamed responses"
+        );
+        describe_gauge!(
+            "http_requests_in_flight",
+            "Requests being handled by the HTTP server by route and method"
+        );
+        describe_counter!(
+            "http_rows_returned",
+            "Rows of query results returned by the HTTP server by route"
+        );
+        describe_counter!(
+            "http_requests_throttled",
+            "Requests to the HTTP server rejected by its per client rate or concurrency limits"
+        );
+        describe_gauge!("http_sessions_active", "Open HTTP client sessions");
+    }
+
     describe_histogram!(
         "get_flight_info_latency_ms",
         metrics::Unit::Milliseconds,
@@ -109,10 +211,76 @@ fn describe_metrics() {
     }
 }
 
-pub fn try_start_metrics_server(metrics_addr: SocketAddr) -> Result<()> {
+/// Completes when the process receives Ctrl+C (SIGINT) or SIGTERM
+///
+/// From https://github.com/tokio-rs/axum/blob/main/examples/graceful-shutdown/src/main.rs
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// The user a request was authenticated as. The auth layer adds it to the extensions of the
+/// requests whose credentials, or session token, it validated so that later layers and handlers
+/// don't have to trust the headers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthenticatedUser(pub String);
+
+/// The execution config of a server, `shared` merged with the server's `execution`, with the
+/// defaults of servers for the settings their clients shouldn't have unless they're allowed
+pub fn server_execution_config(
+    shared: ExecutionConfig,
+    execution: ExecutionConfig,
+) -> ExecutionConfig {
+    let mut config = merge_configs(shared, execution);
+    config.object_store_ddl.get_or_insert(false);
+    config
+}
+
+/// Whether `a` and `b` are equal, taking the same time wherever they first differ so that
+/// comparing secrets doesn't reveal how much of them a guess got right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The Prometheus exporter started by [`try_start_metrics_server`]
+pub struct MetricsServer {
+    handle: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Stop serving metrics
+    pub fn shutdown(self) {
+        info!("shutting down metrics server");
+        self.handle.abort();
+    }
+}
+
+/// How often histograms of the metrics recorder are cleaned up
+const METRICS_UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn try_start_metrics_server(metrics_addr: SocketAddr) -> Result<MetricsServer> {
     let builder = PrometheusBuilder::new();
     info!("listening to metrics on {metrics_addr}");
-    builder
+    let (recorder, exporter) = builder
         .with_http_listener(metrics_addr)
         .set_buckets_for_metric(
             Matcher::Suffix("latency_ms".to_string()),
@@ -121,9 +289,29 @@ pub fn try_start_metrics_server(metrics_addr: SocketAddr) -> Result<()> {
                 10000.0, 20000.0,
             ],
         )?
-        .install()
-        .expect("failed to install metrics recorder/exporter");
+        .build()?;
+    let recorder_handle = recorder.handle();
+    metrics::set_global_recorder(recorder)
+        .map_err(|e| eyre!("failed to install metrics recorder: {e}"))?;
 
     describe_metrics();
-    Ok(())
+
+    // Run the exporter on a task, rather than on the thread `install` starts, so that it can be
+    // stopped when the server shuts down
+    let handle = tokio::spawn(async move {
+        let mut exporter = exporter;
+        let mut upkeep = tokio::time::interval(METRICS_UPKEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                res = &mut exporter => {
+                    if let Err(e) = res {
+                        error!("metrics exporter failed: {e}");
+                    }
+                    break;
+                }
+                _ = upkeep.tick() => recorder_handle.run_upkeep(),
+            }
+        }
+    });
+    Ok(MetricsServer { handle })
 }
@@ -0,0 +1,367 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Persistent log of the statements executed by the servers, queryable as `system.query_log`
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use color_eyre::{eyre::eyre, Result};
+use datafusion::{
+    arrow::{
+        array::{
+            ArrayRef, RecordBatch, StringArray, TimestampMillisecondArray, UInt16Array, UInt64Array,
+        },
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    },
+    catalog::{MemorySchemaProvider, SchemaProvider, TableProvider},
+    datasource::{
+        file_format::parquet::ParquetFormat,
+        listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
+    },
+    logical_expr::dml::InsertOp,
+    physical_plan::collect,
+    prelude::SessionContext,
+};
+use jiff::Timestamp;
+use log::{debug, error};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::config::DbConfig;
+
+pub const QUERY_LOG_SCHEMA_NAME: &str = "system";
+pub const QUERY_LOG_TABLE_NAME: &str = "query_log";
+
+/// How often the buffered entries are written to the query log
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of buffered entries that are written without waiting for the next interval
+const MAX_BUFFERED_ENTRIES: usize = 1000;
+
+/// A statement executed by one of the servers
+#[derive(Clone, Debug)]
+pub struct QueryLogEntry {
+    pub query_id: Option<String>,
+    /// Identity of the client that executed the statement, such as its address
+    pub client: Option<String>,
+    /// The server that executed the statement, `flightsql` or `http`
+    pub protocol: &'static str,
+    pub sql: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub rows: u64,
+    /// gRPC code for FlightSQL and HTTP status for HTTP
+    pub status: u16,
+    pub error: Option<String>,
+    /// Id of the HTTP request that executed the statement, from its `x-request-id` header
+    pub request_id: Option<String>,
+}
+
+/// Appends [`QueryLogEntry`]s to the `system.query_log` table, which is stored as parquet files
+/// under the `tables` directory of the configured [`DbConfig`] path so that it persists across
+/// restarts. Entries are buffered and written together every [`FLUSH_INTERVAL`], or once
+/// [`MAX_BUFFERED_ENTRIES`] are waiting, so that each statement doesn't write its own file.
+#[derive(Clone, Debug)]
+pub struct QueryLog {
+    ctx: SessionContext,
+    table: Arc<dyn TableProvider>,
+    buffer: Arc<Mutex<Vec<QueryLogEntry>>>,
+    /// Held while entries are written so that flushes append in the order they were taken
+    writing: Arc<AsyncMutex<()>>,
+}
+
+impl QueryLog {
+    /// Register the `system.query_log` table in the default catalog of `ctx`
+    pub async fn try_new(ctx: &SessionContext, db_config: &DbConfig) -> Result<Self> {
+        let catalog_name = ctx.state().config_options().catalog.default_catalog.clone();
+        let table_url = db_config
+            .path
+            .join("tables/")?
+            .join(&format!("{catalog_name}/"))?
+            .join(&format!("{QUERY_LOG_SCHEMA_NAME}/"))?
+            .join(&format!("{QUERY_LOG_TABLE_NAME}/"))?;
+        debug!("query log url: {table_url}");
+
+        let catalog = ctx
+            .catalog(&catalog_name)
+            .ok_or(eyre!("missing default catalog {catalog_name}"))?;
+        let schema = match catalog.schema(QUERY_LOG_SCHEMA_NAME) {
+            Some(schema) => schema,
+            None => {
+                let schema: Arc<dyn SchemaProvider> = Arc::new(MemorySchemaProvider::new());
+                catalog.register_schema(QUERY_LOG_SCHEMA_NAME, Arc::clone(&schema))?;
+                schema
+            }
+        };
+
+        let listing_options =
+            ListingOptions::new(Arc::new(ParquetFormat::new())).with_file_extension(".parquet");
+        let config = ListingTableConfig::new(ListingTableUrl::parse(table_url)?)
+            .with_listing_options(listing_options)
+            .with_schema(query_log_schema());
+        let table: Arc<dyn TableProvider> = Arc::new(ListingTable::try_new(config)?);
+        // Replace the table `register_db` created from the files written by previous runs
+        schema.deregister_table(QUERY_LOG_TABLE_NAME)?;
+        schema.register_table(QUERY_LOG_TABLE_NAME.to_string(), Arc::clone(&table))?;
+
+        let log = Self {
+            ctx: ctx.clone(),
+            table,
+            buffer: Arc::new(Mutex::new(Vec::new())),
+            writing: Arc::new(AsyncMutex::new(())),
+        };
+        log.start_flushing();
+        Ok(log)
+    }
+
+    /// Flush the buffered entries every [`FLUSH_INTERVAL`] until every clone of the log is
+    /// dropped
+    fn start_flushing(&self) {
+        let buffer = Arc::downgrade(&self.buffer);
+        let ctx = self.ctx.clone();
+        let table = Arc::clone(&self.table);
+        let writing = Arc::clone(&self.writing);
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(FLUSH_INTERVAL);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes immediately, before anything is recorded
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                let Some(buffer) = Weak::upgrade(&buffer) else {
+                    break;
+                };
+                let log = QueryLog {
+                    ctx: ctx.clone(),
+                    table: Arc::clone(&table),
+                    buffer,
+                    writing: Arc::clone(&writing),
+                };
+                if let Err(e) = log.flush().await {
+                    error!("Error flushing query log: {e}")
+                }
+            }
+        });
+    }
+
+    /// Write the buffered entries to the query log. Called on shutdown so that the entries
+    /// recorded since the last interval aren't lost.
+    pub async fn flush(&self) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let entries = std::mem::take(&mut *self.lock_buffer());
+        if entries.is_empty() {
+            return Ok(());
+        }
+        debug!("writing {} entries to the query log", entries.len());
+        let batch = entries_to_batch(entries)?;
+        let input = self.ctx.read_batch(batch)?.create_physical_plan().await?;
+        let state = self.ctx.state();
+        let insert = self
+            .table
+            .insert_into(&state, input, InsertOp::Append)
+            .await?;
+        collect(insert, self.ctx.task_ctx()).await?;
+        Ok(())
+    }
+
+    /// Buffer `entry` to be appended to the query log with the next flush, so that writing it
+    /// doesn't delay the response
+    pub fn record(&self, entry: QueryLogEntry) {
+        let buffered = {
+            let mut buffer = self.lock_buffer();
+            buffer.push(entry);
+            buffer.len()
+        };
+        if buffered < MAX_BUFFERED_ENTRIES {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let log = self.clone();
+        runtime.spawn(async move {
+            if let Err(e) = log.flush().await {
+                error!("Error flushing query log: {e}")
+            }
+        });
+    }
+
+    fn lock_buffer(&self) -> std::sync::MutexGuard<'_, Vec<QueryLogEntry>> {
+        self.buffer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A statement whose results are still being streamed to the client. It is recorded in the
+/// query log, with the rows counted so far, when dropped. The result streams of the partitions
+/// of a statement share one entry, so it is recorded once.
+#[derive(Debug)]
+pub struct PendingQueryLogEntry {
+    log: QueryLog,
+    entry: Mutex<QueryLogEntry>,
+    rows: Arc<AtomicU64>,
+}
+
+impl PendingQueryLogEntry {
+    pub fn new(log: QueryLog, entry: QueryLogEntry) -> Self {
+        Self {
+            log,
+            entry: Mutex::new(entry),
+            rows: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Counter of the rows returned for the statement
+    pub fn rows(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.rows)
+    }
+
+    /// Record that the statement failed
+    pub fn fail(&self, status: u16, error: String) {
+        let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        entry.status = status;
+        entry.error = Some(error);
+    }
+}
+
+impl Drop for PendingQueryLogEntry {
+    fn drop(&mut self) {
+        let mut entry = self
+            .entry
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        entry.end = Timestamp::now();
+        entry.rows = self.rows.load(Ordering::Relaxed);
+        self.log.record(entry);
+    }
+}
+
+fn query_log_schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("query_id", DataType::Utf8, true),
+        Field::new("client", DataType::Utf8, true),
+        Field::new("protocol", DataType::Utf8, false),
+        Field::new("sql", DataType::Utf8, false),
+        Field::new("start", timestamp.clone(), false),
+        Field::new("end", timestamp, false),
+        Field::new("rows", DataType::UInt64, false),
+        Field::new("status", DataType::UInt16, false),
+        Field::new("error", DataType::Utf8, true),
+        // Last, and nullable, so that files written before it was added can still be read
+        Field::new("request_id", DataType::Utf8, true),
+    ]))
+}
+
+fn entries_to_batch(entries: Vec<QueryLogEntry>) -> Result<RecordBatch> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter(
+            entries.iter().map(|e| e.query_id.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            entries.iter().map(|e| e.client.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            entries.iter().map(|e| e.protocol),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            entries.iter().map(|e| e.sql.as_str()),
+        )),
+        Arc::new(
+            TimestampMillisecondArray::from_iter_values(
+                entries.iter().map(|e| e.start.as_millisecond()),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(
+            TimestampMillisecondArray::from_iter_values(
+                entries.iter().map(|e| e.end.as_millisecond()),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(UInt64Array::from_iter_values(
+            entries.iter().map(|e| e.rows),
+        )),
+        Arc::new(UInt16Array::from_iter_values(
+            entries.iter().map(|e| e.status),
+        )),
+        Arc::new(StringArray::from_iter(
+            entries.iter().map(|e| e.error.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            entries.iter().map(|e| e.request_id.as_deref()),
+        )),
+    ];
+    Ok(RecordBatch::try_new(query_log_schema(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(sql: &str) -> QueryLogEntry {
+        let now = Timestamp::now();
+        QueryLogEntry {
+            query_id: None,
+            client: None,
+            protocol: "http",
+            sql: sql.to_string(),
+            start: now,
+            end: now,
+            rows: 1,
+            status: 200,
+            error: None,
+            request_id: None,
+        }
+    }
+
+    async fn logged(ctx: &SessionContext) -> usize {
+        ctx.sql("SELECT * FROM system.query_log")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_written_when_flushed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = format!("file://{}/", dir.path().to_str().unwrap());
+        let db_config = DbConfig {
+            path: url::Url::parse(&path).unwrap(),
+            query_log: true,
+            persist_catalog: false,
+            persist_system_tables: false,
+        };
+        let ctx = SessionContext::new();
+        let log = QueryLog::try_new(&ctx, &db_config).await.unwrap();
+
+        log.record(entry("SELECT 1"));
+        log.record(entry("SELECT 2"));
+        assert_eq!(logged(&ctx).await, 0);
+
+        log.flush().await.unwrap();
+        assert_eq!(logged(&ctx).await, 2);
+        // Nothing is left to write
+        log.flush().await.unwrap();
+        assert_eq!(logged(&ctx).await, 2);
+    }
+}
@@ -0,0 +1,217 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Token bucket rate limiting shared by the FlightSQL and HTTP servers
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use http::{header, HeaderMap};
+
+/// Buckets that are full are dropped once there are more than this many clients
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// Token bucket of a single client
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets of the clients of a server, refilled at `requests_per_second` up to `burst`
+#[derive(Clone, Debug)]
+pub struct Buckets {
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    requests_per_second: f64,
+    burst: f64,
+}
+
+impl Buckets {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            requests_per_second,
+            burst: burst as f64,
+        }
+    }
+
+    /// The same buckets with a new rate and burst, so clients keep the tokens they have left.
+    /// Buckets holding more than the new burst are capped at it the next time they're used.
+    pub fn with_rate(&self, requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            buckets: Arc::clone(&self.buckets),
+            requests_per_second,
+            burst: burst as f64,
+        }
+    }
+
+    /// Take a token from the bucket of `client`. When it's empty, returns how long until a token
+    /// is available.
+    pub fn try_acquire(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            let (requests_per_second, burst) = (self.requests_per_second, self.burst);
+            buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * requests_per_second < burst
+            });
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.requests_per_second,
+            ))
+        }
+    }
+}
+
+/// The requests each client of a server has in progress, limited to `max` per client
+#[derive(Clone, Debug)]
+pub struct InProgressRequests {
+    counts: Arc<Mutex<HashMap<String, usize>>>,
+    max: Option<usize>,
+}
+
+impl InProgressRequests {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            counts: Arc::default(),
+            max,
+        }
+    }
+
+    /// The same requests in progress with a new maximum, which applies to the requests started
+    /// from then on
+    pub fn with_max(&self, max: Option<usize>) -> Self {
+        Self {
+            counts: Arc::clone(&self.counts),
+            max,
+        }
+    }
+
+    /// Count a request of `client` as in progress until the returned guard is dropped, unless it
+    /// already has the maximum number in progress
+    pub fn try_start(&self, client: &str) -> Option<InProgress> {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let count = counts.get(client).copied().unwrap_or(0);
+        if self.max.is_some_and(|max| count >= max) {
+            return None;
+        }
+        counts.insert(client.to_string(), count + 1);
+        Some(InProgress {
+            counts: Arc::clone(&self.counts),
+            client: client.to_string(),
+        })
+    }
+}
+
+/// A request counted against its client's concurrency limit. Clients without requests in
+/// progress are forgotten.
+#[derive(Debug)]
+pub struct InProgress {
+    counts: Arc<Mutex<HashMap<String, usize>>>,
+    client: String,
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.client);
+            }
+        }
+    }
+}
+
+/// The user of the basic credentials in the `authorization` header, if the request sent any
+pub fn basic_auth_user(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())?
+        .strip_prefix("Basic ")
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(user, _)| user.to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refills_at_configured_rate() {
+        let buckets = Buckets::new(1.0, 2);
+        let now = Instant::now();
+        assert!(buckets.try_acquire("a", now).is_ok());
+        assert!(buckets.try_acquire("a", now).is_ok());
+        assert_eq!(buckets.try_acquire("a", now), Err(Duration::from_secs(1)));
+        // Other clients have their own bucket
+        assert!(buckets.try_acquire("b", now).is_ok());
+        assert!(buckets
+            .try_acquire("a", now + Duration::from_secs(1))
+            .is_ok());
+        assert!(buckets
+            .try_acquire("a", now + Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_buckets_when_rate_changes() {
+        let buckets = Buckets::new(1.0, 2);
+        let now = Instant::now();
+        assert!(buckets.try_acquire("a", now).is_ok());
+        assert!(buckets.try_acquire("a", now).is_ok());
+
+        let buckets = buckets.with_rate(1.0, 10);
+        assert!(buckets.try_acquire("a", now).is_err());
+        assert!(buckets.try_acquire("b", now).is_ok());
+    }
+
+    #[test]
+    fn limits_requests_in_progress() {
+        let in_progress = InProgressRequests::new(Some(1));
+        let first = in_progress.try_start("a").unwrap();
+        assert!(in_progress.try_start("a").is_none());
+        assert!(in_progress.try_start("b").is_some());
+        drop(first);
+        assert!(in_progress.try_start("a").is_some());
+        // Clients are forgotten once their requests finish
+        assert!(in_progress.counts.lock().unwrap().is_empty());
+
+        // Requests in progress still count after the maximum changes
+        let _first = in_progress.try_start("a").unwrap();
+        let in_progress = in_progress.with_max(Some(1));
+        assert!(in_progress.try_start("a").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Serve FlightSQL and HTTP from one process, sharing a single execution context so that both
+//! see the same catalog
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use color_eyre::Result;
+use datafusion_app::extensions::DftSessionStateBuilder;
+use datafusion_app::local::ExecutionContext;
+use log::info;
+
+use super::flightsql::{ConfigSource, FlightSqlApp};
+use super::http::{connect_flightsql_client, HttpApp};
+use super::query_log::QueryLog;
+use super::{server_execution_config, try_start_metrics_server};
+use crate::args::{Command, DftArgs};
+use crate::config::AppConfig;
+use crate::db::{
+    add_catalog_persistence, register_db, restore_catalog, restore_system_tables,
+    save_system_tables,
+};
+use crate::execution::AppExecution;
+
+pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
+    let (flightsql_addr, http_addr, metrics_addr) = match &cli.command {
+        Some(Command::Serve {
+            flightsql_addr,
+            http_addr,
+            metrics_addr,
+            ..
+        }) => (*flightsql_addr, *http_addr, *metrics_addr),
+        _ => (None, None, None),
+    };
+    let flightsql_addr = flightsql_addr
+        .unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 50051));
+    let http_addr =
+        http_addr.unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080));
+    let metrics_addr = metrics_addr.unwrap_or(config.flightsql_server.server_metrics_addr);
+
+    // Both servers execute queries with the FlightSQL server's execution config
+    let merged_exec_config = server_execution_config(
+        config.shared.clone(),
+        config.flightsql_server.execution.clone(),
+    );
+    let mut session_state_builder =
+        DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
+            .with_extensions()
+            .await?;
+    add_catalog_persistence(&mut session_state_builder, &config.db)?;
+    let session_state = session_state_builder.build()?;
+    let execution_ctx = ExecutionContext::try_new(
+        &merged_exec_config,
+        session_state,
+        crate::APP_NAME,
+        env!("CARGO_PKG_VERSION"),
+    )?;
+    restore_system_tables(execution_ctx.system_tables(), &config.db)?;
+    let system_tables = execution_ctx.system_tables().clone();
+    // Refreshes listing tables until the app exits
+    let _catalog_refresh = execution_ctx.start_catalog_refresh();
+    let ddl_errors = if cli.run_ddl {
+        Some(execution_ctx.execute_ddl().await)
+    } else {
+        None
+    };
+    let mut app_execution = AppExecution::new(execution_ctx);
+    if let Some(errors) = ddl_errors {
+        app_execution.with_ddl_errors(errors);
+    }
+    register_db(app_execution.session_ctx(), &config.db).await?;
+    restore_catalog(&app_execution.execution_ctx().ddl_session_ctx(), &config.db).await?;
+    app_execution.with_db_path(config.db.path.clone());
+    let query_log = if config.db.query_log {
+        let query_log = QueryLog::try_new(app_execution.session_ctx(), &config.db).await?;
+        app_execution.with_query_log(query_log.clone());
+        Some(query_log)
+    } else {
+        None
+    };
+
+    let metrics = try_start_metrics_server(metrics_addr)?;
+    let config_source = ConfigSource {
+        path: cli.config_path(),
+        profile: cli.profile.clone(),
+        overrides: cli.set.clone().unwrap_or_default(),
+    };
+    let flightsql = FlightSqlApp::try_new(app_execution.clone(), &config, flightsql_addr, None)
+        .await?
+        .with_config_source(config_source);
+    // Connected once the FlightSQL server is listening, in case the client points at it
+    connect_flightsql_client(&mut app_execution, &config).await;
+    let db_config = config.db.clone();
+    let http = HttpApp::try_new(app_execution, config, http_addr, None).await?;
+
+    info!("serving FlightSQL on {flightsql_addr} and HTTP on {http_addr}");
+    // Each server shuts down on SIGINT or SIGTERM
+    tokio::join!(flightsql.run(), http.run());
+    metrics.shutdown();
+    save_system_tables(&system_tables, &db_config)?;
+    if let Some(query_log) = query_log {
+        query_log.flush().await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,242 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! TLS, and optionally mutual TLS, for the FlightSQL and HTTP servers
+
+use crate::config::ServerTlsConfig;
+use color_eyre::{eyre::eyre, Result};
+use log::warn;
+use metrics::counter;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// How long a client has to complete the TLS handshake before the connection is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long accepting connections is paused after the first of consecutive accept errors, which
+/// doubles with each error up to [`MAX_ACCEPT_BACKOFF`]
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Create the acceptor performing the TLS handshake for new connections, negotiating one of
+/// `alpn_protocols`. When a client CA is configured clients are required to present a
+/// certificate signed by it.
+pub fn try_create_acceptor(
+    config: &ServerTlsConfig,
+    alpn_protocols: &[&[u8]],
+) -> Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+    let builder = match &config.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca_path)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.client_auth_optional {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|e| eyre!("Error reading TLS key '{}': {e}", config.key_path.display()))?;
+    let mut server_config = builder.with_single_cert(load_certs(&config.cert_path)?, key)?;
+    server_config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| eyre!("Error reading certificates '{}': {e}", path.display()))?;
+    if certs.is_empty() {
+        return Err(eyre!("No certificates found in '{}'", path.display()));
+    }
+    Ok(certs)
+}
+
+/// Accept connections from `listener` and perform the TLS handshake for each of them
+/// concurrently, yielding the connections that complete it. Failed handshakes, including clients
+/// rejected for not presenting a trusted certificate, are counted in the
+/// `tls_handshakes_rejected` metric. The listener is closed as soon as the stream is dropped, so
+/// no connections are accepted once the server shuts down.
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> ReceiverStream<std::io::Result<TlsStream<TcpStream>>> {
+    let (tx, rx) = mpsc::channel(128);
+    tokio::spawn(async move {
+        let mut backoff = MIN_ACCEPT_BACKOFF;
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = tx.closed() => break,
+            };
+            let (stream, peer) = match accepted {
+                Ok(conn) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    conn
+                }
+                Err(e) => {
+                    // Errors such as running out of file descriptors persist for a while, so
+                    // retrying immediately would spin
+                    warn!("Error accepting connection, retrying in {backoff:?}: {e}");
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff) => {}
+                        _ = tx.closed() => break,
+                    }
+                    backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => {
+                        counter!("tls_handshakes_rejected").increment(1);
+                        warn!("TLS handshake with {peer} failed: {e}");
+                    }
+                    Err(_) => {
+                        counter!("tls_handshakes_rejected").increment(1);
+                        warn!("TLS handshake with {peer} timed out");
+                    }
+                }
+            });
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use rustls::pki_types::ServerName;
+    use rustls::ClientConfig;
+    use tokio_rustls::TlsConnector;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    /// A self signed certificate for `localhost` written to `dir`, along with its key
+    pub(crate) fn self_signed_config(dir: &Path) -> (ServerTlsConfig, CertificateDer<'static>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+        let config = ServerTlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+            client_auth_optional: false,
+        };
+        (config, cert.cert.der().clone())
+    }
+
+    /// A connector that only trusts `cert` and negotiates `alpn_protocols`
+    pub(crate) fn connector(
+        cert: CertificateDer<'static>,
+        alpn_protocols: &[&[u8]],
+    ) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+        TlsConnector::from(Arc::new(config))
+    }
+
+    #[tokio::test]
+    async fn test_self_signed_handshake() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, cert) = self_signed_config(dir.path());
+        let acceptor = try_create_acceptor(&config, &[b"h2"]).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut incoming = incoming(listener, acceptor);
+
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let client = connector(cert, &[b"h2"])
+            .connect(server_name, tcp)
+            .await
+            .unwrap();
+        assert_eq!(client.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+        let server = incoming.next().await.unwrap().unwrap();
+        assert_eq!(server.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        // A client that doesn't trust the certificate is rejected
+        let other_dir = tempfile::tempdir().unwrap();
+        let (_, other_cert) = self_signed_config(other_dir.path());
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        assert!(connector(other_cert, &[b"h2"])
+            .connect(server_name, tcp)
+            .await
+            .is_err());
+
+        // Once the stream is dropped the listener is closed
+        drop(incoming);
+        let mut closed = false;
+        for _ in 0..50 {
+            if TcpStream::connect(addr).await.is_err() {
+                closed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            closed,
+            "listener still accepting connections after shutdown"
+        );
+    }
+
+    #[test]
+    fn test_missing_certificate() {
+        let config = ServerTlsConfig {
+            cert_path: PathBuf::from("missing_cert.pem"),
+            key_path: PathBuf::from("missing_key.pem"),
+            client_ca_path: None,
+            client_auth_optional: false,
+        };
+        assert!(try_create_acceptor(&config, &[b"h2"]).is_err());
+    }
+}
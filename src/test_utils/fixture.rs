@@ -35,6 +35,7 @@ use prost::Message;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::net::TcpListener;
@@ -47,6 +48,8 @@ use uuid::Uuid;
 pub struct TestFlightSqlServiceImpl {
     requests: Arc<Mutex<HashMap<Uuid, LogicalPlan>>>,
     context: SessionContext,
+    /// Number of statements still to be failed as if the server was unavailable
+    transient_failures: Arc<AtomicUsize>,
 }
 
 impl TestFlightSqlServiceImpl {
@@ -56,9 +59,16 @@ impl TestFlightSqlServiceImpl {
         Self {
             context,
             requests: Arc::new(Mutex::new(requests)),
+            transient_failures: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    /// Fail the first `failures` statements with `UNAVAILABLE`, for testing client retries
+    pub fn with_transient_failures(self, failures: usize) -> Self {
+        self.transient_failures.store(failures, Ordering::SeqCst);
+        self
+    }
+
     /// Return an [`FlightServiceServer`] that can be used with a
     /// [`Server`](tonic::transport::Server)
     pub fn service(&self) -> FlightServiceServer<Self> {
@@ -83,6 +93,12 @@ impl FlightSqlService for TestFlightSqlServiceImpl {
         _request: Request<FlightDescriptor>,
     ) -> Result<Response<FlightInfo>, Status> {
         let CommandStatementQuery { query, .. } = query;
+        let failed = self
+            .transient_failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if failed.is_ok() {
+            return Err(Status::unavailable("Server temporarily unavailable"));
+        }
         let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
         match DFParser::parse_sql_with_dialect(&query, &dialect) {
             Ok(statements) => {
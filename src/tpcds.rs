@@ -0,0 +1,232 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Generation of TPC-DS data and queries with the official TPC-DS toolkit
+//!
+//! Unlike TPC-H there is no Rust generator for TPC-DS, so the `dsdgen` and `dsqgen` binaries
+//! built from the toolkit are used to generate the raw data and queries, which are then
+//! converted into tables in the configured DB path.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::AppConfig;
+use color_eyre::{eyre, Result};
+use datafusion::{
+    dataframe::DataFrameWriteOptions,
+    datasource::listing::ListingTableUrl,
+    prelude::{CsvReadOptions, SessionContext},
+    sql::parser::{DFParser, Statement},
+};
+use datafusion_app::{
+    config::merge_configs, extensions::DftSessionStateBuilder, local::ExecutionContext,
+};
+use log::info;
+use object_store::ObjectStoreExt;
+use url::Url;
+
+/// Run a binary from the toolkit's `tools` directory, which it must be run from to find its
+/// supporting files
+async fn run_tool(tools_dir: &Path, tool: &str, args: &[&str]) -> Result<()> {
+    let binary = tools_dir.join(tool);
+    if !binary.exists() {
+        return Err(eyre::Report::msg(format!(
+            "{tool} not found in {}. Build the TPC-DS toolkit with `make` in its tools directory",
+            tools_dir.display()
+        )));
+    }
+    info!("...running {tool} {}", args.join(" "));
+    let output = tokio::process::Command::new(&binary)
+        .args(args)
+        .current_dir(tools_dir)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(eyre::Report::msg(format!(
+            "{tool} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(())
+}
+
+/// Parse the toolkit's DDL (`tpcds.sql`) into the name and `CREATE TABLE` statement of each table
+fn table_definitions(tools_dir: &Path) -> Result<Vec<(String, Statement)>> {
+    let ddl = std::fs::read_to_string(tools_dir.join("tpcds.sql"))?;
+    let dialect = datafusion::sql::sqlparser::dialect::GenericDialect {};
+    let statements = DFParser::parse_sql_with_dialect(&ddl, &dialect)?;
+    let mut tables = Vec::new();
+    for statement in statements {
+        if let Statement::Statement(s) = &statement {
+            if let datafusion::sql::sqlparser::ast::Statement::CreateTable(create) = s.as_ref() {
+                tables.push((create.name.to_string(), statement));
+            }
+        }
+    }
+    Ok(tables)
+}
+
+/// Split the output of `dsqgen`, which writes every query to a single file, into the number of
+/// the template each query was generated from and its SQL
+fn split_queries(generated: &str) -> Vec<(String, String)> {
+    let mut queries = Vec::new();
+    for part in generated.split("-- start query").skip(1) {
+        // Each query begins with `-- start query 1 in stream 0 using template query96.tpl`
+        let (header, sql) = part.split_once('\n').unwrap_or((part, ""));
+        let template = header
+            .rsplit("template ")
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_end_matches(".tpl")
+            .to_string();
+        let sql = sql.split("-- end query").next().unwrap_or_default().trim();
+        queries.push((template, format!("{sql}\n")));
+    }
+    queries
+}
+
+pub async fn generate(config: AppConfig, scale_factor: f64, toolkit: PathBuf) -> Result<()> {
+    let tools_dir = toolkit.join("tools");
+    let merged_exec_config = merge_configs(config.shared.clone(), config.cli.execution.clone());
+    let session_state_builder = DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
+        .with_extensions()
+        .await?;
+    let session_state = session_state_builder.build()?;
+    let execution_ctx = ExecutionContext::try_new(
+        &merged_exec_config,
+        session_state,
+        crate::APP_NAME,
+        env!("CARGO_PKG_VERSION"),
+    )?;
+    let ctx = execution_ctx.session_ctx();
+
+    // `/` suffix is used so that the final path part is interpretted as a directory
+    let tpcds_dir: Url = config
+        .db
+        .path
+        .join("tables/")?
+        .join("dft/")?
+        .join("tpcds/")?;
+    let raw_dir = tempfile::tempdir()?;
+    let raw_path = raw_dir.path().to_str().ok_or(eyre::Report::msg(
+        "Temporary directory path must be valid UTF-8",
+    ))?;
+
+    info!("generating TPC-DS data");
+    let scale = scale_factor.to_string();
+    run_tool(
+        &tools_dir,
+        "dsdgen",
+        &[
+            "-scale",
+            &scale,
+            "-dir",
+            raw_path,
+            "-terminate",
+            "n",
+            "-force",
+            "y",
+        ],
+    )
+    .await?;
+
+    // The raw data doesn't have headers so the schemas come from the toolkit's DDL, which is
+    // run in a separate context to avoid registering empty tables in the configured one
+    let ddl_ctx = SessionContext::new();
+    for (table, statement) in table_definitions(&tools_dir)? {
+        let data_file = raw_dir.path().join(format!("{table}.dat"));
+        if !data_file.exists() {
+            info!("...no data generated for {table}, skipping");
+            continue;
+        }
+        info!("...converting {table}");
+        let plan = ddl_ctx.state().statement_to_plan(statement).await?;
+        ddl_ctx.execute_logical_plan(plan).await?.collect().await?;
+        let schema = ddl_ctx
+            .table(table.as_str())
+            .await?
+            .schema()
+            .as_arrow()
+            .clone();
+        let options = CsvReadOptions::new()
+            .has_header(false)
+            .delimiter(b'|')
+            .file_extension(".dat")
+            .schema(&schema);
+        let data_file = data_file
+            .to_str()
+            .ok_or(eyre::Report::msg("Data file path must be valid UTF-8"))?;
+        let df = ctx.read_csv(data_file, options).await?;
+        let file_url = tpcds_dir.join(&format!("{table}/"))?.join("data.parquet")?;
+        df.write_parquet(file_url.as_str(), DataFrameWriteOptions::new(), None)
+            .await?;
+    }
+
+    info!("generating TPC-DS queries");
+    let query_dir = tempfile::tempdir()?;
+    let query_path = query_dir.path().to_str().ok_or(eyre::Report::msg(
+        "Temporary directory path must be valid UTF-8",
+    ))?;
+    run_tool(
+        &tools_dir,
+        "dsqgen",
+        &[
+            "-directory",
+            "../query_templates",
+            "-input",
+            "../query_templates/templates.lst",
+            "-dialect",
+            "netezza",
+            "-scale",
+            &scale,
+            "-output_dir",
+            query_path,
+        ],
+    )
+    .await?;
+    let generated = std::fs::read_to_string(query_dir.path().join("query_0.sql"))?;
+    let queries_url = config.db.path.join("queries/")?.join("tpcds/")?;
+    let store_url = ListingTableUrl::parse(queries_url.clone())?;
+    let store = ctx.runtime_env().object_store(store_url.object_store())?;
+    for (template, sql) in split_queries(&generated) {
+        let file_url = queries_url.join(&format!("{template}.sql"))?;
+        let file_path = object_store::path::Path::from_url_path(file_url.path())?;
+        store.put(&file_path, sql.into_bytes().into()).await?;
+    }
+
+    println!("TPC-DS dataset saved to: {}", tpcds_dir);
+    println!("TPC-DS queries saved to: {}", queries_url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_queries() {
+        let generated = "-- start query 1 in stream 0 using template query96.tpl\nselect 1;\n\n-- end query 1 in stream 0 using template query96.tpl\n-- start query 2 in stream 0 using template query7.tpl\nselect 2;\n-- end query 2 in stream 0 using template query7.tpl\n";
+        let queries = split_queries(generated);
+        assert_eq!(
+            queries,
+            vec![
+                ("query96".to_string(), "select 1;\n".to_string()),
+                ("query7".to_string(), "select 2;\n".to_string()),
+            ]
+        );
+    }
+}
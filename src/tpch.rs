@@ -45,6 +45,49 @@ use {
     vortex_session::VortexSession,
 };
 
+/// Names of the generated TPC-H tables
+pub const TABLES: [&str; 8] = [
+    "customer", "orders", "lineitem", "nation", "part", "partsupp", "region", "supplier",
+];
+
+/// The 22 TPC-H queries, written against the unqualified table names. Query 15 uses a CTE
+/// instead of creating and dropping a view so that every query is a single statement.
+const QUERIES: [&str; 22] = [
+    include_str!("tpch/queries/q1.sql"),
+    include_str!("tpch/queries/q2.sql"),
+    include_str!("tpch/queries/q3.sql"),
+    include_str!("tpch/queries/q4.sql"),
+    include_str!("tpch/queries/q5.sql"),
+    include_str!("tpch/queries/q6.sql"),
+    include_str!("tpch/queries/q7.sql"),
+    include_str!("tpch/queries/q8.sql"),
+    include_str!("tpch/queries/q9.sql"),
+    include_str!("tpch/queries/q10.sql"),
+    include_str!("tpch/queries/q11.sql"),
+    include_str!("tpch/queries/q12.sql"),
+    include_str!("tpch/queries/q13.sql"),
+    include_str!("tpch/queries/q14.sql"),
+    include_str!("tpch/queries/q15.sql"),
+    include_str!("tpch/queries/q16.sql"),
+    include_str!("tpch/queries/q17.sql"),
+    include_str!("tpch/queries/q18.sql"),
+    include_str!("tpch/queries/q19.sql"),
+    include_str!("tpch/queries/q20.sql"),
+    include_str!("tpch/queries/q21.sql"),
+    include_str!("tpch/queries/q22.sql"),
+];
+
+/// Returns the SQL for a TPC-H query by its number (1 through 22)
+pub fn query(number: usize) -> Result<&'static str> {
+    number
+        .checked_sub(1)
+        .and_then(|i| QUERIES.get(i))
+        .copied()
+        .ok_or(eyre::Report::msg(format!(
+            "TPC-H query {number} does not exist, queries are numbered 1 through 22"
+        )))
+}
+
 enum GeneratorType {
     Customer,
     Order,
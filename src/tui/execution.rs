@@ -21,14 +21,17 @@ use crate::execution::AppExecution;
 use crate::tui::AppEvent;
 use color_eyre::eyre::Result;
 use datafusion::arrow::array::RecordBatch;
+use datafusion::error::DataFusionError;
 use datafusion::execution::context::SessionContext;
 use datafusion::execution::SendableRecordBatchStream;
-use datafusion::physical_plan::execute_stream;
+use datafusion_app::cancel::QueryHandle;
+use datafusion_app::stats::ExecutionStats;
+use datafusion_app::ExecOptions;
 use futures::StreamExt;
 use log::{error, info};
 #[cfg(feature = "flightsql")]
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
@@ -100,6 +103,14 @@ impl ExecutionResultsBatch {
     }
 }
 
+/// The metrics of a query that was run to completion with [`TuiExecution::analyze_sql`]
+#[derive(Clone, Debug)]
+pub struct AnalyzeResults {
+    pub query: String,
+    pub stats: ExecutionStats,
+    pub duration: Duration,
+}
+
 /// Handles executing queries for the TUI application, formatting results
 /// and sending them to the UI.
 ///
@@ -107,6 +118,8 @@ impl ExecutionResultsBatch {
 pub struct TuiExecution {
     inner: Arc<AppExecution>,
     result_stream: Arc<Mutex<Option<SendableRecordBatchStream>>>,
+    /// Cancels the query whose results are displayed, until they have all been fetched
+    query_handle: std::sync::Mutex<Option<QueryHandle>>,
     /// StreamMao of FlightSQL streams that could be coming from multiple endpoints / tickets.
     /// Often times there is only one but we need to be able to handle multiple.  We should test
     /// this at some point as well.
@@ -120,6 +133,7 @@ impl TuiExecution {
         Self {
             inner,
             result_stream: Arc::new(Mutex::new(None)),
+            query_handle: std::sync::Mutex::new(None),
             #[cfg(feature = "flightsql")]
             flightsql_result_stream: Arc::new(Mutex::new(None)),
         }
@@ -129,6 +143,27 @@ impl TuiExecution {
         self.inner.session_ctx()
     }
 
+    fn set_query_handle(&self, handle: QueryHandle) {
+        let mut query_handle = self
+            .query_handle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *query_handle = Some(handle);
+    }
+
+    /// Cancel the query whose results are displayed, if it's still running. Fetching its next
+    /// batch fails once it's cancelled.
+    pub fn cancel_query(&self) {
+        let query_handle = self
+            .query_handle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(handle) = query_handle.as_ref() {
+            info!("Cancelling query");
+            handle.cancel();
+        }
+    }
+
     pub async fn set_result_stream(&self, stream: SendableRecordBatchStream) {
         let mut s = self.result_stream.lock().await;
         *s = Some(stream)
@@ -182,49 +217,48 @@ impl TuiExecution {
             if i == statement_count - 1 {
                 info!("Executing last query and display results");
                 sender.send(AppEvent::NewExecution)?;
-                match self.inner.execution_ctx().create_physical_plan(&sql).await {
-                    Ok(plan) => match execute_stream(plan, self.inner.session_ctx().task_ctx()) {
-                        Ok(stream) => {
-                            self.set_result_stream(stream).await;
-                            let mut stream = self.result_stream.lock().await;
-                            if let Some(s) = stream.as_mut() {
-                                if let Some(b) = s.next().await {
-                                    match b {
-                                        Ok(b) => {
-                                            let duration = start.elapsed();
-                                            let results = ExecutionResultsBatch {
-                                                query: sql.to_string(),
-                                                batch: b,
-                                                duration,
-                                            };
-                                            sender.send(AppEvent::ExecutionResultsNextBatch(
-                                                results,
-                                            ))?;
-                                        }
-                                        Err(e) => {
-                                            error!("Error getting RecordBatch: {:?}", e);
-                                        }
+                let query = self
+                    .inner
+                    .execution_ctx()
+                    .execute_sql_cancellable(&sql, ExecOptions::new(None, false));
+                self.set_query_handle(query.handle());
+                match query.await {
+                    Ok(stream) => {
+                        self.set_result_stream(stream).await;
+                        let mut stream = self.result_stream.lock().await;
+                        if let Some(s) = stream.as_mut() {
+                            if let Some(b) = s.next().await {
+                                match b {
+                                    Ok(b) => {
+                                        let duration = start.elapsed();
+                                        let results = ExecutionResultsBatch {
+                                            query: sql.to_string(),
+                                            batch: b,
+                                            duration,
+                                        };
+                                        sender
+                                            .send(AppEvent::ExecutionResultsNextBatch(results))?;
+                                    }
+                                    Err(e) => {
+                                        error!("Error getting RecordBatch: {:?}", e);
+                                        // Shown so that cancelling the query is acknowledged
+                                        let e = ExecutionError {
+                                            query: sql.to_string(),
+                                            error: e.to_string(),
+                                            duration: start.elapsed(),
+                                        };
+                                        sender.send(AppEvent::ExecutionResultsError(e))?;
                                     }
                                 }
                             }
                         }
-                        Err(stream_err) => {
-                            error!("Error executing stream: {:?}", stream_err);
-                            let elapsed = start.elapsed();
-                            let e = ExecutionError {
-                                query: sql.to_string(),
-                                error: stream_err.to_string(),
-                                duration: elapsed,
-                            };
-                            sender.send(AppEvent::ExecutionResultsError(e))?;
-                        }
-                    },
-                    Err(plan_err) => {
-                        error!("Error creating physical plan: {:?}", plan_err);
+                    }
+                    Err(exec_err) => {
+                        error!("Error executing query: {:?}", exec_err);
                         let elapsed = start.elapsed();
                         let e = ExecutionError {
                             query: sql.to_string(),
-                            error: plan_err.to_string(),
+                            error: exec_err.to_string(),
                             duration: elapsed,
                         };
                         sender.send(AppEvent::ExecutionResultsError(e))?;
@@ -250,6 +284,38 @@ impl TuiExecution {
         Ok(())
     }
 
+    /// Run `sql` to completion, collecting the metrics of every operator of its plan, and send
+    /// them as [`AppEvent::AnalyzeResults`]
+    pub async fn analyze_sql(
+        self: Arc<Self>,
+        sql: String,
+        sender: UnboundedSender<AppEvent>,
+    ) -> Result<()> {
+        info!("Analyzing query: {sql}");
+        let start = std::time::Instant::now();
+        match self.inner.execution_ctx().analyze_query(&sql).await {
+            Ok(mut stats) => {
+                stats.collect_stats();
+                let results = AnalyzeResults {
+                    query: sql,
+                    stats,
+                    duration: start.elapsed(),
+                };
+                sender.send(AppEvent::AnalyzeResults(results))?;
+            }
+            Err(analyze_err) => {
+                error!("Error analyzing query: {:?}", analyze_err);
+                let e = ExecutionError {
+                    query: sql,
+                    error: analyze_err.to_string(),
+                    duration: start.elapsed(),
+                };
+                sender.send(AppEvent::ExecutionResultsError(e))?;
+            }
+        }
+        Ok(())
+    }
+
     #[cfg(feature = "flightsql")]
     pub async fn run_flightsqls(
         self: Arc<Self>,
@@ -449,7 +515,12 @@ impl TuiExecution {
         self.inner.execution_ctx().load_ddl()
     }
 
-    pub fn save_ddl(&self, ddl: String) {
+    pub fn save_ddl(&self, ddl: String) -> color_eyre::Result<()> {
         self.inner.execution_ctx().save_ddl(ddl)
     }
+
+    /// Execute the configured DDL, returning an error for each statement that failed
+    pub async fn execute_ddl(&self) -> Vec<DataFusionError> {
+        self.inner.execution_ctx().execute_ddl().await
+    }
 }
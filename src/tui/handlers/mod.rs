@@ -24,11 +24,13 @@ use log::{error, info, trace};
 use ratatui::crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers};
 use tui_logger::TuiWidgetEvent;
 
+use crate::tui::execution::ExecutionError;
 #[cfg(feature = "flightsql")]
 use crate::tui::state::tabs::flightsql::FlightSQLConnectionStatus;
 use crate::tui::state::tabs::history::Context;
 use crate::tui::ExecutionResultsBatch;
 use std::sync::Arc;
+use std::time::Duration;
 
 use super::App;
 use crate::tui::ui::SelectedTab;
@@ -154,45 +156,29 @@ pub fn app_event_handler(app: &mut App, event: AppEvent) -> Result<()> {
     trace!("Tui::Event: {:?}", event);
     let now = std::time::Instant::now();
     match event {
-        AppEvent::ExecuteDDL(ddl) => {
-            let queries: Vec<String> = ddl
-                .split(';')
-                .filter_map(|s| {
-                    if s.is_empty() || s == "\n" {
-                        return None;
-                    }
-                    Some(s.to_string())
-                })
-                .collect();
-            let ctx = app.execution.session_ctx().clone();
+        AppEvent::ExecuteDDL => {
+            let execution = Arc::clone(&app.execution);
             let _event_tx = app.event_tx.clone();
             let handle = tokio::spawn(async move {
-                let mut error = false;
-                for q in queries {
-                    info!("Executing DDL: {:?}", q);
-                    match ctx.sql(&q).await {
-                        Ok(df) => {
-                            if df.collect().await.is_ok() {
-                                info!("Successful DDL");
-                            }
-                        }
-                        Err(e) => {
-                            error!("Error executing DDL {:?}: {:?}", q, e);
-                            error = true;
-                        }
-                    }
-                }
-                if error {
-                    if let Err(e) = _event_tx.send(AppEvent::DDLError) {
-                        error!("Error sending DDLError message: {e}");
-                    }
-                } else if let Err(e) = _event_tx.send(AppEvent::DDLSuccess) {
-                    error!("Error sending DDLSuccess message: {e}");
+                let errors = execution.execute_ddl().await;
+                let event = if errors.is_empty() {
+                    info!("Successful DDL");
+                    AppEvent::DDLSuccess
+                } else {
+                    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                    AppEvent::DDLError(errors.join("\n"))
+                };
+                if let Err(e) = _event_tx.send(event) {
+                    error!("Error sending DDL result message: {e}");
                 }
             });
             app.ddl_task = Some(handle);
         }
-        AppEvent::DDLError => app.state.sql_tab.set_ddl_error(true),
+        AppEvent::DDLError(errors) => {
+            app.state.sql_tab.set_ddl_error(true);
+            let error = ExecutionError::new("DDL".to_string(), errors, Duration::ZERO);
+            app.state.sql_tab.set_execution_error(error);
+        }
         AppEvent::DDLSuccess => app.state.sql_tab.set_ddl_error(false),
         AppEvent::NewExecution => {
             app.state.sql_tab.reset_execution_results();
@@ -214,6 +200,18 @@ pub fn app_event_handler(app: &mut App, event: AppEvent) -> Result<()> {
             app.state.history_tab.add_to_history(history_query);
             app.state.history_tab.refresh_history_table_state();
         }
+        AppEvent::AnalyzeResults(r) => {
+            let history_query =
+                HistoryQuery::new(Context::Local, r.query, r.duration, Some(r.stats), None);
+            app.state.history_tab.add_to_history(history_query);
+            app.state.history_tab.refresh_history_table_state();
+            // Show the metrics of the analyzed query in the history tab
+            if let Some(table_state) = app.state.history_tab.history_table_state() {
+                let selected = app.state.history_tab.history().len() - 1;
+                table_state.borrow_mut().select(Some(selected));
+            }
+            app.state.tabs.selected = SelectedTab::History;
+        }
         AppEvent::ExecutionResultsNextBatch(r) => {
             let ExecutionResultsBatch {
                 query,
@@ -16,12 +16,16 @@
 // under the License.
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use log::{error, info};
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 use super::App;
-use crate::tui::{handlers::tab_navigation_handler, state::tabs::sql::SQLTabMode, AppEvent};
+use crate::tui::{
+    execution::ExecutionError, handlers::tab_navigation_handler, state::tabs::sql::SQLTabMode,
+    AppEvent,
+};
 
 pub fn normal_mode_handler(app: &mut App, key: KeyEvent) {
     match (key.code, key.modifiers) {
@@ -47,13 +51,28 @@ pub fn normal_mode_handler(app: &mut App, key: KeyEvent) {
             }
             app.state.sql_tab.edit();
         }
+        (KeyCode::Char('x'), KeyModifiers::NONE) => app.execution.cancel_query(),
+        (KeyCode::Char('a'), KeyModifiers::NONE) => {
+            if *app.state.sql_tab.mode() == SQLTabMode::Normal {
+                let sql = app.state.sql_tab.sql();
+                let _event_tx = app.event_tx().clone();
+                let execution = Arc::clone(&app.execution);
+                tokio::spawn(execution.analyze_sql(sql, _event_tx));
+            }
+        }
         (KeyCode::Char('d'), KeyModifiers::NONE) => app.state.sql_tab.set_mode(SQLTabMode::DDL),
         (KeyCode::Char('n'), KeyModifiers::NONE) => app.state.sql_tab.set_mode(SQLTabMode::Normal),
         (KeyCode::Char('s'), KeyModifiers::NONE) => {
             if *app.state.sql_tab.mode() == SQLTabMode::DDL {
                 let textarea = app.state.sql_tab.active_editor_cloned();
                 let ddl = textarea.lines().join("\n");
-                app.execution.save_ddl(ddl)
+                if let Err(e) = app.execution.save_ddl(ddl) {
+                    error!("{e}");
+                    // Show why the edits weren't saved, e.g. because the DDL is a directory
+                    let error =
+                        ExecutionError::new("Save DDL".to_string(), e.to_string(), Duration::ZERO);
+                    app.state.sql_tab.set_execution_error(error);
+                }
             }
         }
         (KeyCode::Down, KeyModifiers::NONE) => {
@@ -83,10 +102,9 @@ pub fn normal_mode_handler(app: &mut App, key: KeyEvent) {
             }
             SQLTabMode::DDL => {
                 let _event_tx = app.event_tx().clone();
-                // TODO: Probably want this to load from Editor instead of the file so that
+                // TODO: Probably want this to execute the Editor instead of the file so that
                 // it is the latest content.
-                let ddl = app.execution.load_ddl().unwrap_or_default();
-                if let Err(e) = _event_tx.send(AppEvent::ExecuteDDL(ddl)) {
+                if let Err(e) = _event_tx.send(AppEvent::ExecuteDDL) {
                     error!("Error sending ExecuteDDL event: {:?}", e);
                 }
             }
@@ -145,10 +163,9 @@ pub fn editable_handler(app: &mut App, key: KeyEvent) {
                 }
                 SQLTabMode::DDL => {
                     let _event_tx = app.event_tx().clone();
-                    // TODO: Probably want this to load from Editor instead of the file so that
+                    // TODO: Probably want this to execute the Editor instead of the file so that
                     // it is the latest content.
-                    let ddl = app.execution.load_ddl().unwrap_or_default();
-                    if let Err(e) = _event_tx.send(AppEvent::ExecuteDDL(ddl)) {
+                    if let Err(e) = _event_tx.send(AppEvent::ExecuteDDL) {
                         error!("Error sending ExecuteDDL event: {:?}", e);
                     }
                 }
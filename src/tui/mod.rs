@@ -44,13 +44,15 @@ use tokio::task::JoinHandle;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
 
-use self::execution::{ExecutionError, ExecutionResultsBatch, TuiExecution};
+use self::execution::{AnalyzeResults, ExecutionError, ExecutionResultsBatch, TuiExecution};
 use self::handlers::{app_event_handler, crossterm_event_handler};
 use crate::config::AppConfig;
-use crate::db::register_db;
+use crate::db::{
+    add_catalog_persistence, register_db, restore_catalog, restore_system_tables,
+    save_system_tables,
+};
 use crate::telemetry;
 use crate::{args::DftArgs, execution::AppExecution};
-use datafusion_app::sql_utils::clean_sql;
 
 #[derive(Debug)]
 pub enum AppEvent {
@@ -66,8 +68,9 @@ pub enum AppEvent {
     Mouse(event::MouseEvent),
     Resize(u16, u16),
     // DDL
-    ExecuteDDL(String),
-    DDLError,
+    ExecuteDDL,
+    /// Errors from executing the DDL, one per line
+    DDLError(String),
     DDLSuccess,
     // Query Execution
     NewExecution,
@@ -75,6 +78,7 @@ pub enum AppEvent {
     ExecutionResultsNextPage,
     ExecutionResultsPreviousPage,
     ExecutionResultsError(ExecutionError),
+    AnalyzeResults(AnalyzeResults),
     // FlightSQL
     #[cfg(feature = "flightsql")]
     FlightSQLEstablishConnection,
@@ -274,9 +278,9 @@ impl<'app> App<'app> {
         let ddl = self.execution.load_ddl().unwrap_or_default();
         info!("Loaded DDL: {:?}", ddl);
         if !ddl.is_empty() {
-            self.state.sql_tab.add_ddl_to_editor(ddl.clone());
+            self.state.sql_tab.add_ddl_to_editor(ddl);
         }
-        let _ = self.event_tx().send(AppEvent::ExecuteDDL(clean_sql(ddl)));
+        let _ = self.event_tx().send(AppEvent::ExecuteDDL);
     }
 
     #[cfg(feature = "flightsql")]
@@ -370,9 +374,11 @@ impl App<'_> {
 
 pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
     let merged_exec_config = merge_configs(config.shared.clone(), config.tui.execution.clone());
-    let session_state_builder = DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
-        .with_extensions()
-        .await?;
+    let mut session_state_builder =
+        DftSessionStateBuilder::try_new(Some(merged_exec_config.clone()))?
+            .with_extensions()
+            .await?;
+    add_catalog_persistence(&mut session_state_builder, &config.db)?;
     let session_state = session_state_builder.build()?;
 
     // TUI mode: running the TUI
@@ -384,6 +390,10 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
         crate::APP_NAME,
         env!("CARGO_PKG_VERSION"),
     )?;
+    restore_system_tables(execution_ctx.system_tables(), &config.db)?;
+    let system_tables = execution_ctx.system_tables().clone();
+    // Refreshes listing tables until the app exits
+    let _catalog_refresh = execution_ctx.start_catalog_refresh();
     #[allow(unused_mut)]
     let mut app_execution = AppExecution::new(execution_ctx);
 
@@ -414,6 +424,8 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
             }
         }
 
+        let mut tls = config.flightsql_client.tls.clone();
+        tls.insecure_skip_verify |= cli.insecure_skip_verify;
         let flightsql_config = FlightSQLConfig::new(
             config.flightsql_client.connection_url.clone(),
             config.flightsql_client.benchmark_iterations,
@@ -421,12 +433,17 @@ pub async fn try_run(cli: DftArgs, config: AppConfig) -> Result<()> {
             all_headers,
             config.flightsql_client.max_decoding_message_size,
             config.flightsql_client.max_encoding_message_size,
+            tls,
+        );
+        app_execution.with_flightsql_ctx(
+            FlightSQLContext::new(flightsql_config).with_dialect(merged_exec_config.dialect),
         );
-        app_execution.with_flightsql_ctx(FlightSQLContext::new(flightsql_config));
     }
 
     register_db(app_execution.session_ctx(), &config.db).await?;
+    restore_catalog(&app_execution.execution_ctx().ddl_session_ctx(), &config.db).await?;
     let app = App::new(state, cli, app_execution);
-    app.run_app().await?;
-    Ok(())
+    let result = app.run_app().await;
+    save_system_tables(&system_tables, &config.db)?;
+    result
 }
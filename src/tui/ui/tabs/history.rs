@@ -43,7 +43,12 @@ pub fn render_query(area: Rect, buf: &mut Buffer, app: &App) {
             info!("Selected: {}", selected);
             if let Some(selected_query) = app.state.history_tab.history().get(selected) {
                 info!("Selected Query: {:?}", selected_query);
-                let query = Paragraph::new(selected_query.sql().as_str()).block(block);
+                // Analyzed queries show the metrics of their plan along with the query
+                let text = match selected_query.execution_stats() {
+                    Some(stats) => stats.to_string(),
+                    None => selected_query.sql().clone(),
+                };
+                let query = Paragraph::new(text).block(block);
                 query.render(area, buf);
             } else {
                 info!("Rendering placeholder because no selected_query");
@@ -85,14 +90,15 @@ pub fn render_query_history(area: Rect, buf: &mut Buffer, app: &App) {
                         Cell::from(q.context().as_str()),
                         Cell::from(q.sql().as_str()),
                         Cell::from(q.execution_time().as_millis().to_string()),
-                        // Not sure showing scanned_bytes is useful anymore in the context of
-                        // paginated queries.  Hard coding to zero for now but this will need to be
-                        // revisted.  One option I have is removing these type of stats from the
-                        // query history table (so we only show execution time) and then
-                        // _anything_ ExecutionPlan statistics related is shown in the lower pane
-                        // and their is a `analyze` mode that runs the query to completion and
-                        // collects all stats to show in a table next to the query.
-                        Cell::from(0.to_string()),
+                        // Paginated queries aren't run to completion so only analyzed queries
+                        // know how many bytes they scanned
+                        Cell::from(
+                            q.execution_stats()
+                                .as_ref()
+                                .and_then(|stats| stats.bytes_scanned())
+                                .unwrap_or_default()
+                                .to_string(),
+                        ),
                     ])
                 })
                 .collect();
@@ -130,6 +130,7 @@ pub fn render_sql_help(area: Rect, buf: &mut Buffer, app: &App) {
                     "'d' for DDL mode",
                     "'q' to exit app",
                     "'Enter' to run query",
+                    "'a' to analyze query",
                 ]
             }
         }
@@ -18,6 +18,7 @@
 //! Tests for the CLI (e.g. run from files)
 
 use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
 use std::{io::Read, path::PathBuf};
 
 use super::{assert_output_contains, contains_str, sql_in_file};
@@ -312,6 +313,39 @@ fn test_multiple_sql_in_multiple_args2() {
     assert.stdout(contains_str(expected));
 }
 
+#[test]
+fn test_system_queries() {
+    let expected = r##"
++--------------+-----------+------+
+| sql          | status    | rows |
++--------------+-----------+------+
+| SELECT 1 + 2 | succeeded | 1    |
++--------------+-----------+------+
+    "##;
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 + 2")
+        .arg("SELECT sql, status, rows FROM system.queries")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_set_applies_to_following_statements() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SET datafusion.execution.batch_size = 1")
+        .arg("SHOW datafusion.execution.batch_size")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("| datafusion.execution.batch_size | 1     |"));
+}
+
 #[test]
 fn test_time_command() {
     let expected = r##"executed in"##;
@@ -346,6 +380,250 @@ SELECT 1 + 1;
     assert.code(0).stdout(contains_str(expected_err));
 }
 
+#[test]
+fn test_time_breakdown() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 + 2")
+        .arg("--time")
+        .arg("--time-breakdown")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Query 0 timing:"))
+        .stdout(contains_str("Parsing:"))
+        .stdout(contains_str("Physical planning:"))
+        .stdout(contains_str("Output:"));
+}
+
+#[test]
+fn test_time_breakdown_metrics_format_json() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("CREATE TABLE t AS SELECT 1; SELECT * FROM t")
+        .arg("--time")
+        .arg("--time-breakdown")
+        .arg("--metrics-format")
+        .arg("json")
+        .assert()
+        .success();
+
+    let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let metrics: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(metrics.len(), 2);
+    assert!(metrics[0]["parse_ms"].as_f64().unwrap() > 0.0);
+    // DDL is executed by the session rather than planned, so it's timed as execution
+    assert_eq!(metrics[0]["physical_planning_ms"], 0.0);
+    assert_eq!(metrics[1]["query"], 1);
+    assert_eq!(metrics[1]["parse_ms"], 0.0);
+}
+
+#[test]
+fn test_time_metrics_format_json() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 + 2")
+        .arg("--time")
+        .arg("--metrics-format")
+        .arg("json")
+        .assert()
+        .success();
+
+    let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let metrics: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+    assert_eq!(metrics["query"], 0);
+    assert_eq!(metrics["rows"], 1);
+    assert!(metrics["elapsed_ms"].is_number());
+}
+
+#[test]
+fn test_metrics_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("metrics.ndjson");
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("-c")
+        .arg("SELECT 2")
+        .arg("--time")
+        .arg("--metrics-format")
+        .arg("json")
+        .arg("--metrics-output")
+        .arg(&path)
+        .assert()
+        .success();
+
+    assert.stdout(predicates::str::is_empty());
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().count(), 2);
+}
+
+#[test]
+fn test_metrics_output_requires_json() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--time")
+        .arg("--metrics-output")
+        .arg("metrics.ndjson")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str(
+        "The `metrics-output` flag can only be used with `--metrics-format json`",
+    ));
+}
+
+#[test]
+fn test_time_breakdown_requires_time() {
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 + 2")
+        .arg("--time-breakdown")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_timeout() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT sum(value) FROM generate_series(1, 100000000000)")
+        .arg("--timeout")
+        .arg("1")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str("Query 0 timed out after 1s"));
+}
+
+#[test]
+fn test_unsupported_object_store_url() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--object-store")
+        .arg("ftp://my_bucket")
+        .arg("-c")
+        .arg("SELECT 1")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str(
+        "Unsupported object store url 'ftp://my_bucket'",
+    ));
+}
+
+#[test]
+fn test_error_format_json() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1; SELECT * FROM missing_table")
+        .arg("--error-format")
+        .arg("json")
+        .assert()
+        .failure();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let error: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert_eq!(error["kind"], "planning");
+    assert_eq!(error["statement"], 1);
+    assert_eq!(error["sql"], "SELECT * FROM missing_table");
+    assert!(error["message"]
+        .as_str()
+        .unwrap()
+        .contains("missing_table' not found"));
+}
+
+#[test]
+fn test_error_format_json_parse_error() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELEC 1")
+        .arg("--error-format")
+        .arg("json")
+        .assert()
+        .failure();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let error: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert_eq!(error["kind"], "parse");
+    assert_eq!(error["statement"], serde_json::Value::Null);
+    assert_eq!(error["sql"], "SELEC 1");
+}
+
+#[test]
+fn test_error_format_json_execution_error() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT column1 / 0 FROM (VALUES (1))")
+        .arg("--error-format")
+        .arg("json")
+        .assert()
+        .failure()
+        .stdout(contains_str("Error").not());
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let error: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert_eq!(error["kind"], "execution");
+    assert!(error["message"]
+        .as_str()
+        .unwrap()
+        .contains("Divide by zero"));
+}
+
+#[test]
+fn test_env_substitution() {
+    let file = sql_in_file("SELECT ${DFT_TEST_ENV_SUBSTITUTION} AS value");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--env-substitution")
+        .env("DFT_TEST_ENV_SUBSTITUTION", "42")
+        .assert()
+        .success();
+
+    let expected = r#"
++-------+
+| value |
++-------+
+| 42    |
++-------+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_env_substitution_missing_variable() {
+    let file = sql_in_file("SELECT ${DFT_TEST_ENV_SUBSTITUTION_MISSING}");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--env-substitution")
+        .env_remove("DFT_TEST_ENV_SUBSTITUTION_MISSING")
+        .assert()
+        .failure()
+        .stderr(contains_str(
+            "Environment variable 'DFT_TEST_ENV_SUBSTITUTION_MISSING' is not set",
+        ));
+}
+
 #[test]
 fn test_write_file() {
     let temp_dir = tempfile::tempdir().unwrap();
@@ -458,6 +736,86 @@ fn test_output_json() {
     assert_eq!(buffer, expected);
 }
 
+#[test]
+fn test_output_csv_gzip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.csv.gz");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("-o")
+        .arg(path.clone())
+        .assert()
+        .success();
+
+    let file = std::fs::File::open(path).unwrap();
+    let mut buffer = String::new();
+    flate2::read::GzDecoder::new(file)
+        .read_to_string(&mut buffer)
+        .unwrap();
+
+    let expected = "Int64(1)\n1\n";
+    assert_eq!(buffer, expected);
+}
+
+#[test]
+fn test_output_json_zstd_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.json");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("-o")
+        .arg(path.clone())
+        .arg("--compression")
+        .arg("zstd")
+        .assert()
+        .success();
+
+    let file = std::fs::File::open(path).unwrap();
+    let buffer = String::from_utf8(zstd::decode_all(file).unwrap()).unwrap();
+
+    let expected = "{\"Int64(1)\":1}\n";
+    assert_eq!(buffer, expected);
+}
+
+#[test]
+fn test_output_compressed_many_batches() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv_path = dir.path().join("test.csv.gz");
+    let json_path = dir.path().join("test.json.zst");
+
+    for path in [&csv_path, &json_path] {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT * FROM range(100000)")
+            .arg("-o")
+            .arg(path)
+            .assert()
+            .success();
+    }
+
+    let mut csv = String::new();
+    flate2::read::GzDecoder::new(std::fs::File::open(csv_path).unwrap())
+        .read_to_string(&mut csv)
+        .unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 100001);
+    assert_eq!(lines[0], "value");
+
+    let json =
+        String::from_utf8(zstd::decode_all(std::fs::File::open(json_path).unwrap()).unwrap())
+            .unwrap();
+    let lines: Vec<&str> = json.lines().collect();
+    assert_eq!(lines.len(), 100000);
+    assert!(lines.iter().all(|line| line.starts_with("{\"value\":")));
+}
+
 #[test]
 fn test_output_parquet() {
     let dir = tempfile::tempdir().unwrap();
@@ -493,68 +851,196 @@ fn test_output_parquet() {
 }
 
 #[test]
-fn test_json_output() {
-    let assert = Command::cargo_bin("dft")
+fn test_output_parquet_row_group_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.parquet");
+
+    Command::cargo_bin("dft")
         .unwrap()
         .arg("-c")
-        .arg("SELECT 1 AS id, 'hello' AS name")
-        .arg("-j")
+        .arg("SELECT * FROM generate_series(1, 100)")
+        .arg("-o")
+        .arg(path.clone())
+        .arg("--row-group-size")
+        .arg("10")
+        .arg("--write-concurrency")
+        .arg("1")
         .assert()
         .success();
 
-    assert.stdout(contains_str(r#"{"id":1,"name":"hello"}"#));
+    let read_sql = format!(
+        "SELECT count(DISTINCT row_group_id) AS row_groups, sum(row_group_num_rows) AS num_rows FROM parquet_metadata('{}')",
+        path.to_str().unwrap()
+    );
+    let expected = r#"
++------------+----------+
+| row_groups | num_rows |
++------------+----------+
+| 10         | 100      |
++------------+----------+"#;
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg(read_sql)
+        .assert()
+        .success()
+        .stdout(contains_str(expected));
 }
 
 #[test]
-fn test_json_output_multiple_rows() {
-    let assert = Command::cargo_bin("dft")
+fn test_row_group_size_must_be_positive() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.parquet");
+
+    Command::cargo_bin("dft")
         .unwrap()
         .arg("-c")
-        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, val)")
-        .arg("-j")
+        .arg("SELECT 1")
+        .arg("-o")
+        .arg(path)
+        .arg("--row-group-size")
+        .arg("0")
         .assert()
-        .success();
-
-    assert
-        .stdout(contains_str(r#"{"id":1,"val":"a"}"#))
-        .stdout(contains_str(r#"{"id":2,"val":"b"}"#));
+        .failure();
 }
 
 #[test]
-fn test_concat_output() {
-    let assert = Command::cargo_bin("dft")
+fn test_write_concurrency_requires_output() {
+    Command::cargo_bin("dft")
         .unwrap()
         .arg("-c")
-        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, val)")
-        .arg("-C")
+        .arg("SELECT 1")
+        .arg("--write-concurrency")
+        .arg("4")
         .assert()
-        .success();
-
-    // With concat the result is a single table with all rows
-    let expected = r#"
-+----+-----+
-| id | val |
-+----+-----+
-| 1  | a   |
-| 2  | b   |
-+----+-----+"#;
-    assert.stdout(contains_str(expected));
+        .failure();
 }
 
 #[test]
-fn test_json_and_concat_output() {
-    let assert = Command::cargo_bin("dft")
+fn test_output_partitioned_parquet() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out");
+
+    let sql = "SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, name)".to_string();
+    Command::cargo_bin("dft")
         .unwrap()
         .arg("-c")
-        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, val)")
-        .arg("-j")
-        .arg("-C")
+        .arg(sql)
+        .arg("-o")
+        .arg(format!("{}/", path.to_str().unwrap()))
+        .arg("--partition-by")
+        .arg("name")
         .assert()
         .success();
 
-    assert
-        .stdout(contains_str(r#"{"id":1,"val":"a"}"#))
-        .stdout(contains_str(r#"{"id":2,"val":"b"}"#));
+    assert!(path.join("name=a").is_dir());
+    assert!(path.join("name=b").is_dir());
+}
+
+#[test]
+fn test_partition_by_without_output() {
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--partition-by")
+        .arg("a")
+        .assert()
+        .failure()
+        .stderr(contains_str("can only be used with `output`"));
+}
+
+#[test]
+fn test_csv_format() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, name)")
+        .arg("--format")
+        .arg("csv")
+        .assert()
+        .success();
+
+    assert.stdout("id,name\n1,a\n2,b\n");
+}
+
+#[test]
+fn test_ndjson_format() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 AS id")
+        .arg("--format")
+        .arg("ndjson")
+        .assert()
+        .success();
+
+    assert.stdout("{\"id\":1}\n");
+}
+
+#[test]
+fn test_json_output() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 AS id, 'hello' AS name")
+        .arg("-j")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str(r#"{"id":1,"name":"hello"}"#));
+}
+
+#[test]
+fn test_json_output_multiple_rows() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, val)")
+        .arg("-j")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str(r#"{"id":1,"val":"a"}"#))
+        .stdout(contains_str(r#"{"id":2,"val":"b"}"#));
+}
+
+#[test]
+fn test_concat_output() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, val)")
+        .arg("-C")
+        .assert()
+        .success();
+
+    // With concat the result is a single table with all rows
+    let expected = r#"
++----+-----+
+| id | val |
++----+-----+
+| 1  | a   |
+| 2  | b   |
++----+-----+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_json_and_concat_output() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, val)")
+        .arg("-j")
+        .arg("-C")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str(r#"{"id":1,"val":"a"}"#))
+        .stdout(contains_str(r#"{"id":2,"val":"b"}"#));
 }
 
 #[test]
@@ -591,3 +1077,796 @@ fn test_output_vortex() {
 
     assert.stdout(contains_str(expected));
 }
+
+#[test]
+fn test_max_rows() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1), (2), (3), (4)) AS t(id)")
+        .arg("--max-rows")
+        .arg("2")
+        .assert()
+        .success();
+
+    let expected = r#"
++----+
+| id |
++----+
+| 1  |
+| 2  |
++----+
+... 2 more rows"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_max_rows_not_reached() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1), (2)) AS t(id)")
+        .arg("--max-rows")
+        .arg("5")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("more rows").not());
+}
+
+#[test]
+fn test_max_rows_json() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM (VALUES (1), (2), (3)) AS t(id)")
+        .arg("-j")
+        .arg("--max-rows")
+        .arg("1")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str(r#"{"id":1}"#))
+        .stdout(contains_str(r#"{"id":2}"#).not())
+        .stderr(contains_str("... 2 more rows"));
+}
+
+#[test]
+fn test_max_col_width() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 'abcdefghijkl' AS val")
+        .arg("--max-col-width")
+        .arg("8")
+        .assert()
+        .success();
+
+    let expected = r#"
++----------+
+| val      |
++----------+
+| abcde... |
++----------+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_analyze_subcommand() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("analyze")
+        .arg("-c")
+        .arg("SELECT id FROM (VALUES (1), (2), (3)) AS t(id) WHERE id > 1")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Operator Summary"))
+        .stdout(contains_str("FilterExec"))
+        .stdout(contains_str("Output Rows"))
+        .stdout(contains_str("Bytes Scanned"))
+        .stdout(contains_str("Total: 0 spills"));
+}
+
+#[test]
+fn test_analyze_subcommand_without_query() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("analyze")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str("No files or commands provided to analyze"));
+}
+
+#[test]
+fn test_schema_subcommand_csv() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.csv");
+    std::fs::write(&path, "id,name\n1,a\n2,b\n").unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("schema")
+        .arg(&path)
+        .assert()
+        .success();
+
+    let expected = r#"
++------+-----------+----------+
+| name | data_type | nullable |
++------+-----------+----------+
+| id   | Int64     | true     |
+| name | Utf8      | true     |
++------+-----------+----------+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_schema_subcommand_json_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.csv");
+    std::fs::write(&path, "id,name\n1,a\n2,b\n").unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("schema")
+        .arg(&path)
+        .arg("--json")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str(
+            r#"{"name":"id","data_type":"Int64","nullable":true}"#,
+        ))
+        .stdout(contains_str(
+            r#"{"name":"name","data_type":"Utf8","nullable":true}"#,
+        ));
+}
+
+#[test]
+fn test_schema_subcommand_missing_table() {
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("schema")
+        .arg("not_a_table")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_create_table() {
+    let file = sql_in_file(
+        r#"
+CREATE TABLE source AS VALUES (1), (2);
+SELECT column1 * 10 AS x FROM source;
+    "#,
+    );
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--create-table")
+        .arg("staged")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("Created table staged"));
+}
+
+#[test]
+fn test_create_table_requires_query() {
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("CREATE TABLE source AS VALUES (1)")
+        .arg("--create-table")
+        .arg("staged")
+        .assert()
+        .failure()
+        .stderr(contains_str("The last statement must be a query"));
+}
+
+#[test]
+fn test_record_and_replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let session = dir.path().join("session.jsonl");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("CREATE TABLE t AS VALUES (1), (2); SELECT count(*) AS n FROM t")
+        .arg("--record")
+        .arg(&session)
+        .assert()
+        .success();
+
+    let recorded = std::fs::read_to_string(&session).unwrap();
+    let lines: Vec<&str> = recorded.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].contains(r#""rows":1"#));
+    assert!(lines[1].contains(r#""error":null"#));
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("replay")
+        .arg(&session)
+        .assert()
+        .success();
+
+    let expected = r#"
++---+
+| n |
++---+
+| 2 |
++---+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_record_failed_statement() {
+    let dir = tempfile::tempdir().unwrap();
+    let session = dir.path().join("session.jsonl");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM missing_table")
+        .arg("--record")
+        .arg(&session)
+        .assert()
+        .failure();
+
+    let recorded = std::fs::read_to_string(&session).unwrap();
+    assert!(recorded.contains("missing_table"));
+    assert!(recorded.contains(r#""rows":null"#));
+}
+
+#[test]
+fn test_catalog_list_schemas() {
+    // A schema without any tables is still listed
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--ddl-sql")
+        .arg("CREATE SCHEMA empty_schema;")
+        .arg("catalog")
+        .arg("list-schemas")
+        .arg("--db-schema-filter-pattern")
+        .arg("empty")
+        .assert()
+        .success();
+
+    let expected = r#"
++--------------+--------------+
+| catalog_name | schema_name  |
++--------------+--------------+
+| datafusion   | empty_schema |
++--------------+--------------+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_catalog_list_tables_and_describe_after_ddl() {
+    let ddl = "CREATE TABLE t AS VALUES (1, 'a');";
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--ddl-sql")
+        .arg(ddl)
+        .arg("catalog")
+        .arg("list-tables")
+        .arg("--table-name-filter-pattern")
+        .arg("t")
+        .arg("--table-types")
+        .arg("BASE TABLE")
+        .assert()
+        .success();
+    assert.stdout(contains_str(
+        "| datafusion    | public       | t          | BASE TABLE |",
+    ));
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--ddl-sql")
+        .arg(ddl)
+        .arg("catalog")
+        .arg("describe")
+        .arg("t")
+        .assert()
+        .success();
+    assert
+        .stdout(contains_str("| column1 | Int64     | true     |"))
+        .stdout(contains_str("| column2 | Utf8     "));
+}
+
+#[test]
+fn test_convert_csv_to_parquet() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("test.csv");
+    let output = dir.path().join("test.parquet");
+    std::fs::write(&input, "id,name\n1,a\n2,b\n").unwrap();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("convert")
+        .arg(&input)
+        .arg(&output)
+        .arg("--cast")
+        .arg("id=INT")
+        .arg("--compression")
+        .arg("zstd(3)")
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("schema")
+        .arg(&output)
+        .assert()
+        .success();
+
+    let expected = r#"
++------+-----------+----------+
+| name | data_type | nullable |
++------+-----------+----------+
+| id   | Int32     | true     |
+| name | Utf8      | true     |
++------+-----------+----------+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_convert_partition_by() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("test.csv");
+    let output = dir.path().join("out");
+    std::fs::write(&input, "id,name\n1,a\n2,b\n").unwrap();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("convert")
+        .arg(&input)
+        .arg(&output)
+        .arg("--output-format")
+        .arg("parquet")
+        .arg("--partition-by")
+        .arg("name")
+        .assert()
+        .success();
+
+    assert!(output.join("name=a").is_dir());
+    assert!(output.join("name=b").is_dir());
+}
+
+#[test]
+fn test_convert_unknown_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("test.csv");
+    std::fs::write(&input, "id,name\n1,a\n").unwrap();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("convert")
+        .arg(&input)
+        .arg(dir.path().join("test.unknown"))
+        .assert()
+        .failure()
+        .stderr(contains_str("Unable to infer the format"));
+}
+
+#[test]
+fn test_cp_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(src.join("nested")).unwrap();
+    std::fs::write(src.join("a.csv"), "id\n1\n").unwrap();
+    std::fs::write(src.join("nested").join("b.csv"), "id\n2\n").unwrap();
+    let dst = dir.path().join("dst");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("cp")
+        .arg(&src)
+        .arg(format!("{}/", dst.display()))
+        .assert()
+        .success()
+        .stdout(contains_str("Copied 2 file(s) (10 bytes)"));
+
+    assert_eq!(
+        std::fs::read_to_string(dst.join("a.csv")).unwrap(),
+        "id\n1\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dst.join("nested").join("b.csv")).unwrap(),
+        "id\n2\n"
+    );
+}
+
+#[test]
+fn test_cp_with_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("test.csv");
+    let dst = dir.path().join("test.parquet");
+    std::fs::write(&src, "id,name\n1,a\n").unwrap();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("cp")
+        .arg(&src)
+        .arg(&dst)
+        .arg("--format")
+        .arg("parquet")
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg(format!("SELECT name FROM '{}'", dst.display()))
+        .assert()
+        .success();
+    assert.stdout(contains_str("| a    |"));
+}
+
+#[test]
+fn test_dry_run() {
+    let file = sql_in_file(
+        r#"
+CREATE TABLE foo AS VALUES (1);
+SELECT 1 + 1;
+    "#,
+    );
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--dry-run")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("statement 0: OK"))
+        .stdout(contains_str("statement 1: OK"))
+        .stdout(contains_str("| 2 ").not());
+}
+
+#[test]
+fn test_dry_run_reports_planning_errors() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1; SELECT * FROM missing_table")
+        .arg("--dry-run")
+        .assert()
+        .failure();
+
+    assert
+        .stdout(contains_str("Command 0, statement 0: OK"))
+        .stdout(contains_str("Command 0, statement 1: Error"))
+        .stderr(contains_str("1 statement(s) failed validation"));
+}
+
+#[test]
+fn test_ddl_sql() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--ddl-sql")
+        .arg("CREATE TABLE foo AS VALUES (1), (2)")
+        .arg("--ddl-sql")
+        .arg("CREATE VIEW bar AS SELECT column1 * 10 AS x FROM foo")
+        .arg("-c")
+        .arg("SELECT sum(x) AS total FROM bar")
+        .assert()
+        .success();
+
+    let expected = r#"
++-------+
+| total |
++-------+
+| 30    |
++-------+"#;
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_ddl_sql_error() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--ddl-sql")
+        .arg("CREATE VIEW bar AS SELECT * FROM missing_table")
+        .arg("-c")
+        .arg("SELECT 1")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str("Error executing DDL"));
+}
+
+#[test]
+fn test_jobs_commands() {
+    let expected = r##"
++---------------------+
+| Int64(1) + Int64(2) |
++---------------------+
+| 3                   |
++---------------------+
++---------------------+
+| Int64(3) + Int64(5) |
++---------------------+
+| 8                   |
++---------------------+
+    "##;
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1 + 2")
+        .arg("SELECT 3 + 5")
+        .arg("--jobs")
+        .arg("2")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_jobs_files() {
+    let expected = r##"
++---------------------+
+| Int64(1) + Int64(2) |
++---------------------+
+| 3                   |
++---------------------+
++----------+
+| Int64(1) |
++----------+
+| 1        |
++----------+
++----------+
+| Int64(2) |
++----------+
+| 2        |
++----------+
+    "##;
+
+    let file1 = sql_in_file("SELECT 1 + 2");
+    let file2 = sql_in_file("SELECT 1;\nselect 2;");
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file1.path())
+        .arg("-f")
+        .arg(file2.path())
+        .arg("--jobs")
+        .arg("2")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_jobs_zero() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--jobs")
+        .arg("0")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str("The number of jobs must be greater than zero"));
+}
+
+#[test]
+fn test_jobs_with_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1; SELECT 2")
+        .arg("--jobs")
+        .arg("2")
+        .arg("-o")
+        .arg(dir.path().join("results.csv"))
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str(
+        "The `jobs` flag can not be used with `output`",
+    ));
+}
+
+#[test]
+fn test_completions() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("completions")
+        .arg("bash")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("_dft()"))
+        .stdout(contains_str("--metrics-format"))
+        .stdout(contains_str("catalog"));
+}
+
+#[test]
+fn test_files_glob() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("b.sql"), "SELECT 2").unwrap();
+    std::fs::write(dir.path().join("a.sql"), "SELECT 1").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "not sql").unwrap();
+
+    let pattern = dir.path().join("*.sql");
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(&pattern)
+        .assert()
+        .success();
+
+    let expected = r##"
++----------+
+| Int64(1) |
++----------+
+| 1        |
++----------+
++----------+
+| Int64(2) |
++----------+
+| 2        |
++----------+
+    "##;
+    let manifest = format!(
+        "Resolved 2 file(s) from '{}':\n  {}\n  {}",
+        pattern.display(),
+        dir.path().join("a.sql").display(),
+        dir.path().join("b.sql").display()
+    );
+    assert
+        .stdout(contains_str(expected))
+        .stderr(contains_str(&manifest));
+}
+
+#[test]
+fn test_files_glob_no_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    let pattern = dir.path().join("*.sql");
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(&pattern)
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str("No files match"));
+}
+
+#[test]
+fn test_fmt() {
+    let file = sql_in_file("select a,b from t where a>1");
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("fmt")
+        .arg(file.path())
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("SELECT"))
+        .stdout(contains_str("a > 1;"))
+        .stdout(contains_str("select").not());
+}
+
+#[test]
+fn test_fmt_check_and_write() {
+    let file = sql_in_file("select 1;select 2");
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("fmt")
+        .arg("--check")
+        .arg(file.path())
+        .assert()
+        .failure();
+    assert
+        .stdout(contains_str("Would reformat"))
+        .stderr(contains_str("1 file(s) would be reformatted"));
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("fmt")
+        .arg("--write")
+        .arg(file.path())
+        .assert()
+        .success();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("fmt")
+        .arg("--check")
+        .arg(file.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_fmt_write_refuses_comments() {
+    let sql = "-- totals\nselect 1";
+    let file = sql_in_file(sql);
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("fmt")
+        .arg("--write")
+        .arg(file.path())
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str("comments would be dropped"));
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), sql);
+}
+
+#[test]
+fn test_save_and_run_plans() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let csv = temp_dir.path().join("values.csv");
+    std::fs::write(&csv, "a,b\n1,2\n3,4\n").unwrap();
+    let plans = temp_dir.path().join("plans");
+    let sql = format!("SELECT sum(a) AS total FROM '{}'", csv.display());
+
+    let expected = "
++-------+
+| total |
++-------+
+| 4     |
++-------+";
+
+    for (format, extension) in [("proto", "pb"), ("json", "json")] {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg(&sql)
+            .arg("--save-plans")
+            .arg(&plans)
+            .arg("--plan-format")
+            .arg(format)
+            .assert()
+            .success()
+            .stdout(contains_str(expected));
+
+        for kind in ["logical", "physical"] {
+            let plan = plans.join(format!("query_0.{kind}.{extension}"));
+            assert!(plan.exists(), "{} wasn't saved", plan.display());
+
+            // The file is queried by its path in the plan so it doesn't need to be registered
+            Command::cargo_bin("dft")
+                .unwrap()
+                .arg("--run-plan")
+                .arg(&plan)
+                .assert()
+                .success()
+                .stdout(contains_str(expected));
+        }
+    }
+}
+
+#[test]
+fn test_run_plan_with_unknown_kind() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let plan = temp_dir.path().join("plan.pb");
+    std::fs::write(&plan, b"").unwrap();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--run-plan")
+        .arg(&plan)
+        .assert()
+        .failure()
+        .stderr(contains_str("is a logical or physical plan"));
+}
@@ -18,6 +18,7 @@
 //! Tests for the CLI (e.g. run from files)
 
 use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
 
 use super::{contains_str, sql_in_file};
 
@@ -40,6 +41,56 @@ SELECT 1
     assert.stdout(contains_str(expected));
 }
 
+#[test]
+fn test_bench_command_metrics_format_json() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--metrics-format")
+        .arg("json")
+        .assert()
+        .success();
+
+    let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+    assert_eq!(stats["query"], "SELECT 1");
+    assert_eq!(stats["runs"], 10);
+    assert_eq!(stats["mode"], "serial");
+    assert!(stats["execution"]["median_ms"].is_number());
+}
+
+#[test]
+fn test_bench_command_memory_and_spills() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM range(100000) ORDER BY value DESC")
+        .arg("--bench")
+        .arg("-n")
+        .arg("3")
+        .arg("--metrics-format")
+        .arg("json")
+        .assert()
+        .success();
+
+    let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+    assert!(stats["peak_memory_bytes"]["max"].as_u64().unwrap() > 0);
+    assert!(stats["spill_count"]["median"].is_number());
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .assert()
+        .success()
+        .stdout(contains_str("Peak Memory (bytes)"))
+        .stdout(contains_str("Spills"));
+}
+
 #[test]
 fn test_bench_files() {
     let file = sql_in_file(r#"SELECT 1 + 1;"#);
@@ -289,3 +340,253 @@ fn test_bench_serial_vs_concurrent_csv_compatibility() {
     assert!(lines[1].ends_with("serial"));
     assert!(lines[2].contains("concurrent"));
 }
+
+#[test]
+fn test_bench_command_with_compare() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--save")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .arg("--regression-threshold")
+        .arg("1000")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Baseline Comparison (median)"))
+        .stdout(contains_str("Execution"));
+}
+
+#[test]
+fn test_bench_command_with_compare_improvement() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+    std::fs::write(
+        &baseline,
+        "SELECT 1,10,1000,1000,1000,1000,10.00,1000,1000,1000,1000,10.00,8000,8000,8000,8000,80.00,10000,10000,10000,10000,100.00,serial\n",
+    )
+    .unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("Baseline Comparison (median)"));
+}
+
+#[test]
+fn test_bench_command_with_save_records_memory_and_spills() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file = temp_dir.path().join("results.csv");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--save")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .success();
+
+    let contents = std::fs::read_to_string(&file).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert!(lines[0].ends_with(",peak_memory_median,spill_count_median,concurrency_mode"));
+    let fields: Vec<&str> = lines[1].split(',').collect();
+    assert_eq!(fields.len(), 25);
+    assert!(fields[22].parse::<usize>().is_ok());
+    assert_eq!(fields[23], "0");
+}
+
+#[test]
+fn test_bench_command_with_compare_memory_and_spills() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+    std::fs::write(
+        &baseline,
+        "SELECT 1,10,1000,1000,1000,1000,10.00,1000,1000,1000,1000,10.00,8000,8000,8000,8000,80.00,10000,10000,10000,10000,100.00,1000000000,0,serial\n",
+    )
+    .unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Peak Memory (bytes)"))
+        .stdout(contains_str("Spills"));
+}
+
+#[test]
+fn test_bench_command_with_compare_memory_regression() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+    std::fs::write(
+        &baseline,
+        "query,runs,...,peak_memory_median,spill_count_median,concurrency_mode\n\
+         SELECT * FROM range(100000) ORDER BY value DESC,10,1000,1000,1000,1000,10.00,1000,1000,1000,1000,10.00,8000,8000,8000,8000,80.00,10000,10000,10000,10000,100.00,1,0,serial\n",
+    )
+    .unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT * FROM range(100000) ORDER BY value DESC")
+        .arg("--bench")
+        .arg("-n")
+        .arg("3")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .failure();
+
+    assert.stdout(contains_str("Peak Memory (bytes) regressed by"));
+}
+
+#[test]
+fn test_bench_command_with_compare_without_memory_columns() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+    std::fs::write(
+        &baseline,
+        "SELECT 1, 2,10,1000,1000,1000,1000,10.00,1000,1000,1000,1000,10.00,8000,8000,8000,8000,80.00,10000,10000,10000,10000,100.00,serial\n",
+    )
+    .unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1, 2")
+        .arg("--bench")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Baseline Comparison (median)"))
+        .stdout(contains_str("No baseline found").not());
+}
+
+#[test]
+fn test_bench_command_with_compare_missing_baseline() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+    std::fs::write(
+        &baseline,
+        "SELECT 2,10,0,0,0,0,0.00,0,0,0,0,0.00,0,0,0,0,0.00,0,0,0,0,100.00,serial\n",
+    )
+    .unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--bench")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("No baseline found for query: SELECT 1"));
+}
+
+#[test]
+fn test_bench_files_with_save() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let results = temp_dir.path().join("results.csv");
+    let file = sql_in_file("SELECT 1 + 1;");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--bench")
+        .arg("--save")
+        .arg(results.to_str().unwrap())
+        .assert()
+        .success();
+
+    let contents = std::fs::read_to_string(results).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("query,runs"));
+    assert!(lines[1].starts_with("SELECT 1 + 1;,10,"));
+}
+
+#[test]
+fn test_bench_files_with_compare_multi_line_query() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let baseline = temp_dir.path().join("baseline.csv");
+    let file = sql_in_file("SELECT 1,\n  2");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--bench")
+        .arg("--save")
+        .arg(baseline.to_str().unwrap())
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-f")
+        .arg(file.path())
+        .arg("--bench")
+        .arg("--compare")
+        .arg(baseline.to_str().unwrap())
+        .arg("--regression-threshold")
+        .arg("1000")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Baseline Comparison (median)"))
+        .stdout(contains_str("No baseline found").not());
+}
+
+#[test]
+fn test_compare_without_bench() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--compare")
+        .arg("baseline.csv")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str(
+        "The `compare` flag can only be used when benchmarking local queries",
+    ));
+}
@@ -19,6 +19,7 @@
 
 use crate::config::TestConfigBuilder;
 use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
 use std::io::Write;
 
 use super::contains_str;
@@ -88,6 +89,108 @@ fn test_custom_config_multiple_ddl() {
     assert.stdout(contains_str(expected));
 }
 
+#[test]
+fn test_custom_config_ddl_directory() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let ddl_dir = tempdir.path().join("ddl");
+    std::fs::create_dir(&ddl_dir).unwrap();
+    // Written out of order to check that files are executed in order of their names
+    std::fs::write(
+        ddl_dir.join("02_derived.sql"),
+        "CREATE TABLE y AS SELECT column1 + 1 AS column1 FROM x",
+    )
+    .unwrap();
+    std::fs::write(
+        ddl_dir.join("03_invalid.sql"),
+        "CREATE TABLE z AS SELECT * FROM missing;",
+    )
+    .unwrap();
+    std::fs::write(ddl_dir.join("01_base.sql"), "CREATE TABLE x AS VALUES (1);").unwrap();
+    std::fs::write(ddl_dir.join("notes.txt"), "not sql").unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_ddl_path("cli", ddl_dir);
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("--run-ddl")
+        .arg("-c")
+        .arg("SELECT * FROM y")
+        .assert()
+        .success();
+
+    let expected = r#"
++---------+
+| column1 |
++---------+
+| 2       |
++---------+"#;
+
+    assert
+        .stdout(contains_str(expected))
+        .stderr(contains_str("03_invalid.sql"));
+}
+
+#[test]
+fn test_create_table_persist_ddl() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let ddl_path = tempdir.path().join("my_ddl.sql");
+    std::fs::write(
+        &ddl_path,
+        "-- Staged tables\nCREATE TABLE x AS VALUES (1);\n",
+    )
+    .unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_ddl_path("cli", ddl_path.clone());
+    let config = config_builder.build("my_config.toml");
+
+    for value in [2, 3] {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(&config.path)
+            .arg("-c")
+            .arg(format!("SELECT {value} AS v"))
+            .arg("--create-table")
+            .arg("staged")
+            .arg("--persist-ddl")
+            .assert()
+            .success()
+            .stdout(contains_str("Saved DDL for table staged"));
+    }
+
+    // Saving the table again replaces its previous DDL
+    let ddl = std::fs::read_to_string(&ddl_path).unwrap();
+    assert_eq!(
+        ddl,
+        "-- Staged tables\nCREATE TABLE x AS VALUES (1);\nCREATE TABLE staged AS SELECT 3 AS v;\n"
+    );
+}
+
+#[test]
+fn test_create_table_persist_ddl_error() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_ddl_path("cli", tempdir.path().to_path_buf());
+    let config = config_builder.build("my_config.toml");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("-c")
+        .arg("SELECT 1 AS v")
+        .arg("--create-table")
+        .arg("staged")
+        .arg("--persist-ddl")
+        .assert()
+        .failure()
+        .stdout(contains_str("Saved DDL").not())
+        .stderr(contains_str("is a directory"));
+}
+
 #[test]
 fn test_custom_config_benchmark_iterations() {
     let mut config_builder = TestConfigBuilder::default();
@@ -108,3 +211,328 @@ fn test_custom_config_benchmark_iterations() {
 
     assert.stdout(contains_str(expected));
 }
+
+#[test]
+fn test_config_profile() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let default_ddl_path = tempdir.path().join("default_ddl.sql");
+    std::fs::write(&default_ddl_path, "CREATE TABLE x AS VALUES (1)").unwrap();
+    let staging_ddl_path = tempdir.path().join("staging_ddl.sql");
+    std::fs::write(&staging_ddl_path, "CREATE TABLE x AS VALUES (2)").unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_ddl_path("cli", default_ddl_path);
+    config_builder.with_ddl_path("profile.staging.cli", staging_ddl_path);
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("--profile")
+        .arg("staging")
+        .arg("--run-ddl")
+        .arg("-c")
+        .arg("SELECT * FROM x")
+        .assert()
+        .success();
+
+    let expected = r#"
++---------+
+| column1 |
++---------+
+| 2       |
++---------+"#;
+
+    assert.stdout(contains_str(expected));
+}
+
+#[test]
+fn test_config_missing_profile() {
+    let config = TestConfigBuilder::default().build("my_config.toml");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("--profile")
+        .arg("missing")
+        .arg("-c")
+        .arg("SELECT 1")
+        .assert()
+        .failure()
+        .stderr(contains_str("Profile 'missing' not found"));
+}
+
+#[test]
+fn test_config_dialect() {
+    let run = |dialect: &str| {
+        let mut config_builder = TestConfigBuilder::default();
+        config_builder.with_dialect("cli", dialect);
+        let config = config_builder.build("my_config.toml");
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(config.path)
+            .arg("-c")
+            .arg("SELECT 1 AS `quoted`")
+            .assert()
+    };
+
+    // Backticks quote identifiers in MySQL but not in ANSI SQL
+    run("mysql").success().stdout(contains_str("quoted"));
+    run("ansi").failure();
+}
+
+#[test]
+fn test_config_memory_limit() {
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_memory_limit("cli", 1024 * 1024);
+    let config = config_builder.build("my_config.toml");
+
+    // The build side of a hash join can't spill
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("-c")
+        .arg("SELECT count(*) FROM generate_series(1, 1000000) a JOIN generate_series(1, 1000000) b ON a.value = b.value")
+        .assert()
+        .failure()
+        .stderr(contains_str("Resources exhausted"));
+}
+
+#[test]
+fn test_config_spill_directories() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let spill_dir = tempdir.path().join("spill");
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder
+        .with_memory_limit("cli", 64 * 1024 * 1024)
+        .with_spill_directory("cli", spill_dir.clone());
+    let config = config_builder.build("my_config.toml");
+
+    // The window's sort needs more memory than the limit, so it spills
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("--analyze")
+        .arg("-c")
+        .arg("SELECT max(rn) FROM (SELECT row_number() OVER (ORDER BY value DESC) AS rn FROM generate_series(1, 20000000))")
+        .assert()
+        .success()
+        .stdout(contains_str("Spill Summary"));
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    // The counts are on the line after the summary's column names
+    let spills: usize = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("Spills"))
+        .nth(1)
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|count| count.parse().ok())
+        .expect("spill count");
+    assert!(spills > 0, "{stdout}");
+    assert!(spill_dir.is_dir());
+}
+
+#[test]
+fn test_config_dedicated_executor() {
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_dedicated_executor("cli", 2);
+    let config = config_builder.build("my_config.toml");
+
+    // The executor's threads are named after it, and log the streams they poll
+    Command::cargo_bin("dft")
+        .unwrap()
+        .env("RUST_LOG", "datafusion_app::executor::dedicated=debug")
+        .arg("--config")
+        .arg(config.path)
+        .arg("-c")
+        .arg("SELECT count(*) FROM generate_series(1, 100000) a JOIN generate_series(1, 100) b ON a.value = b.value")
+        .assert()
+        .success()
+        .stdout(contains_str("100"))
+        .stderr(contains_str("polling stream on thread cpu_runtime"));
+}
+
+#[test]
+fn test_config_persisted_catalog() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let db_path = tempdir.path().join("db");
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_persisted_catalog(&format!("file://{}/", db_path.to_str().unwrap()));
+    let config = config_builder.build("my_config.toml");
+    let run = |sql: &str| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(&config.path)
+            .arg("-c")
+            .arg(sql)
+            .assert()
+    };
+
+    let csv = concat!(env!("CARGO_MANIFEST_DIR"), "/data/aggregate_test_100.csv");
+    run(&format!(
+        "CREATE EXTERNAL TABLE persisted STORED AS CSV LOCATION '{csv}' OPTIONS ('format.has_header' 'true')"
+    ))
+    .success();
+    run("CREATE VIEW persisted_view AS SELECT count(*) AS n FROM persisted").success();
+
+    // Both are restored in the next run
+    run("SELECT n FROM persisted_view")
+        .success()
+        .stdout(contains_str("100"));
+    let catalog = std::fs::read_to_string(db_path.join("catalog.json")).unwrap();
+    assert!(catalog.contains("persisted_view"));
+
+    run("DROP VIEW persisted_view").success();
+    run("SELECT n FROM persisted_view").failure();
+    run("SELECT count(*) FROM persisted").success();
+}
+
+#[test]
+fn test_persisted_system_tables() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let db_path = tempdir.path().join("db");
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_persisted_system_tables(&format!("file://{}/", db_path.to_str().unwrap()));
+    let config = config_builder.build("my_config.toml");
+    let run = |sql: &str| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(&config.path)
+            .arg("-c")
+            .arg(sql)
+            .assert()
+    };
+
+    run("SELECT 'from the first run'").success();
+    assert!(db_path.join("system/queries.arrow").exists());
+
+    // The query history of the first run is loaded by the next one
+    run("SELECT sql FROM system.queries")
+        .success()
+        .stdout(contains_str("SELECT 'from the first run'"));
+}
+
+#[test]
+fn test_secrets_in_ddl_options() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let ddl_path = tempdir.path().join("my_ddl.sql");
+    let csv = concat!(env!("CARGO_MANIFEST_DIR"), "/data/aggregate_test_100.csv");
+    let ddl = format!(
+        "CREATE EXTERNAL TABLE with_secret STORED AS CSV LOCATION '{csv}' OPTIONS ('format.has_header' 'secret://has_header');\n\
+         CREATE EXTERNAL TABLE with_undefined_secret STORED AS CSV LOCATION '{csv}' OPTIONS ('format.has_header' 'secret://undefined')"
+    );
+    std::fs::write(&ddl_path, ddl).unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_env_secret("has_header", "DFT_TEST_HAS_HEADER");
+    config_builder.with_ddl_path("cli", ddl_path);
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .env("DFT_TEST_HAS_HEADER", "true")
+        .arg("--config")
+        .arg(config.path)
+        .arg("--run-ddl")
+        .arg("-c")
+        .arg("SELECT count(*) FROM with_secret")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("| 100 "))
+        .stderr(contains_str("Secret 'undefined' isn't defined"));
+}
+
+#[test]
+fn test_secrets_in_queries_require_query_secrets() {
+    let csv = concat!(env!("CARGO_MANIFEST_DIR"), "/data/aggregate_test_100.csv");
+    let sql = format!(
+        "CREATE EXTERNAL TABLE with_secret STORED AS CSV LOCATION '{csv}' OPTIONS ('format.has_header' 'secret://has_header'); SELECT count(*) FROM with_secret"
+    );
+    let run = |mut config_builder: TestConfigBuilder| {
+        config_builder.with_env_secret("has_header", "DFT_TEST_HAS_HEADER");
+        let config = config_builder.build("my_config.toml");
+        Command::cargo_bin("dft")
+            .unwrap()
+            .env("DFT_TEST_HAS_HEADER", "true")
+            .arg("--config")
+            .arg(config.path)
+            .arg("-c")
+            .arg(&sql)
+            .assert()
+    };
+
+    run(TestConfigBuilder::default())
+        .failure()
+        .stderr(contains_str("`query_secrets`"));
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_query_secrets("cli");
+    run(config_builder).success().stdout(contains_str("| 100 "));
+}
+
+#[test]
+fn test_completions_with_ddl_table_names() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let ddl_path = tempdir.path().join("my_ddl.sql");
+    let mut file = std::fs::File::create(ddl_path.clone()).unwrap();
+    let ddl = "CREATE TABLE completion_table_one AS VALUES (1);\nCREATE TABLE completion_table_two AS VALUES (2);";
+    file.write_all(ddl.as_bytes()).unwrap();
+    file.flush().unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_ddl_path("cli", ddl_path);
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("completions")
+        .arg("bash")
+        .arg("--tables")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("completion_table_one completion_table_two"));
+}
+
+#[test]
+fn test_guardrails() {
+    let cases = [
+        (
+            "max_rows",
+            5,
+            "SELECT * FROM range(10)",
+            "guardrails.max_rows",
+        ),
+        (
+            "max_bytes",
+            1,
+            "SELECT * FROM range(10)",
+            "guardrails.max_bytes",
+        ),
+        ("max_rows", 10, "SELECT * FROM range(10)", ""),
+    ];
+    for (name, value, sql, error) in cases {
+        let mut config_builder = TestConfigBuilder::default();
+        config_builder.with_guardrail("cli", name, value);
+        let config = config_builder.build("my_config.toml");
+
+        let assert = Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(config.path)
+            .arg("-c")
+            .arg(sql)
+            .assert();
+        if error.is_empty() {
+            assert.success();
+        } else {
+            assert.failure().stderr(contains_str(error));
+        }
+    }
+}
@@ -19,6 +19,8 @@
 
 use crate::config::TestConfigBuilder;
 use assert_cmd::Command;
+
+use super::contains_str;
 use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore};
 
 #[test]
@@ -52,6 +54,68 @@ fn test_custom_config() {
     })
 }
 
+#[test]
+fn test_tpch_run() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let db_path = tempdir.path().join("db/");
+    std::fs::create_dir_all(&db_path).unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_db_path(&format!("file://{}", db_path.to_str().unwrap()));
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("tpch")
+        .arg("run")
+        .arg("--scale-factor")
+        .arg("0.01")
+        .arg("--query")
+        .arg("1,6")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("Query  1: 4 rows in"))
+        .stdout(contains_str("Query  6: 1 rows in"))
+        .stdout(contains_str("Ran 2 TPC-H queries in"));
+}
+
+#[test]
+fn test_tpch_run_without_data() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let db_path = tempdir.path().join("db/");
+    std::fs::create_dir_all(&db_path).unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_db_path(&format!("file://{}", db_path.to_str().unwrap()));
+    let config = config_builder.build("my_config.toml");
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("tpch")
+        .arg("run")
+        .assert()
+        .failure()
+        .stderr(contains_str("TPC-H table 'customer' not found"));
+}
+
+#[test]
+fn test_generate_tpcds_without_toolkit() {
+    let tempdir = tempfile::tempdir().unwrap();
+
+    Command::cargo_bin("dft")
+        .unwrap()
+        .arg("generate-tpcds")
+        .arg("--toolkit")
+        .arg(tempdir.path())
+        .assert()
+        .failure()
+        .stderr(contains_str("dsdgen not found"));
+}
+
 #[tokio::test]
 async fn test_custom_config_with_s3() {
     let mut config_builder = TestConfigBuilder::default();
@@ -222,6 +222,53 @@ impl TestConfigBuilder {
         self
     }
 
+    pub fn with_dialect(&mut self, app: &str, dialect: &str) -> &mut Self {
+        self.config_text
+            .push_str(&format!("[{app}.execution]\ndialect = '{dialect}'\n"));
+        self
+    }
+
+    pub fn with_memory_limit(&mut self, app: &str, limit_bytes: usize) -> &mut Self {
+        self.config_text.push_str(&format!(
+            "[{app}.execution.memory]\nlimit_bytes = {limit_bytes}\n"
+        ));
+        self
+    }
+
+    pub fn with_guardrail(&mut self, app: &str, name: &str, value: u64) -> &mut Self {
+        self.config_text
+            .push_str(&format!("[{app}.execution.guardrails]\n{name} = {value}\n"));
+        self
+    }
+
+    pub fn with_env_secret(&mut self, name: &str, env_var: &str) -> &mut Self {
+        self.config_text.push_str(&format!(
+            "[shared.secrets]\n{name} = {{ env = '{env_var}' }}\n"
+        ));
+        self
+    }
+
+    pub fn with_query_secrets(&mut self, app: &str) -> &mut Self {
+        self.config_text
+            .push_str(&format!("[{app}.execution]\nquery_secrets = true\n"));
+        self
+    }
+
+    pub fn with_spill_directory(&mut self, app: &str, dir: PathBuf) -> &mut Self {
+        self.config_text.push_str(&format!(
+            "[{app}.execution.spill]\ndirectories = ['{}']\n",
+            dir.display()
+        ));
+        self
+    }
+
+    pub fn with_dedicated_executor(&mut self, app: &str, threads: usize) -> &mut Self {
+        self.config_text.push_str(&format!(
+            "[{app}.execution]\ndedicated_executor_enabled = true\ndedicated_executor_threads = {threads}\n"
+        ));
+        self
+    }
+
     pub fn with_benchmark_iterations(&mut self, app: &str, iterations: u64) -> &mut Self {
         self.config_text.push_str(&format!(
             "[{app}.execution]\nbenchmark_iterations = {}\n",
@@ -239,6 +286,30 @@ impl TestConfigBuilder {
         self
     }
 
+    /// Set a `[flightsql_server]` setting to a TOML `value`
+    #[cfg(feature = "flightsql")]
+    pub fn with_flightsql_server_setting(&mut self, key: &str, value: &str) -> &mut Self {
+        self.config_text
+            .push_str(&format!("[flightsql_server]\n{key} = {value}\n"));
+        self
+    }
+
+    #[cfg(feature = "flightsql")]
+    pub fn with_client_basic_auth(
+        &mut self,
+        username: &str,
+        password: &str,
+        handshake: bool,
+    ) -> &mut Self {
+        self.config_text.push_str("[flightsql_client.auth]\n");
+        self.config_text.push_str(&format!(
+            "basic_auth = {{ username = '{username}', password = '{password}' }}\n"
+        ));
+        self.config_text
+            .push_str(&format!("handshake = {handshake}\n"));
+        self
+    }
+
     #[cfg(feature = "huggingface")]
     pub fn with_huggingface(
         &mut self,
@@ -314,6 +385,26 @@ impl TestConfigBuilder {
         self
     }
 
+    pub fn with_udf(
+        &mut self,
+        name: &str,
+        kind: &str,
+        path: &str,
+        input_types: &[&str],
+        return_type: &str,
+    ) -> &mut Self {
+        let input_types: Vec<String> = input_types.iter().map(|t| format!("\"{t}\"")).collect();
+        self.config_text.push_str("[[shared.udf]]\n");
+        self.config_text.push_str(&format!("name = \"{name}\"\n"));
+        self.config_text.push_str(&format!("kind = \"{kind}\"\n"));
+        self.config_text.push_str(&format!("path = \"{path}\"\n"));
+        self.config_text.push_str(&format!(
+            "signature = {{ input_types = [{}], return_type = \"{return_type}\" }}\n",
+            input_types.join(", ")
+        ));
+        self
+    }
+
     #[cfg(feature = "flightsql")]
     pub fn with_client_auth(
         &mut self,
@@ -386,4 +477,18 @@ impl TestConfigBuilder {
         self.config_text.push_str(&format!("path = \"{path}\"\n"));
         self
     }
+
+    pub fn with_persisted_catalog(&mut self, path: &str) -> &mut Self {
+        self.config_text.push_str("[db]\n");
+        self.config_text.push_str(&format!("path = \"{path}\"\n"));
+        self.config_text.push_str("persist_catalog = true\n");
+        self
+    }
+
+    pub fn with_persisted_system_tables(&mut self, path: &str) -> &mut Self {
+        self.config_text.push_str("[db]\n");
+        self.config_text.push_str(&format!("path = \"{path}\"\n"));
+        self.config_text.push_str("persist_system_tables = true\n");
+        self
+    }
 }
@@ -0,0 +1,45 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Tests for the Azure Blob Storage extension. They only register stores, so they don't need
+//! access to Azure.
+
+use datafusion::execution::object_store::ObjectStoreUrl;
+use datafusion_app::config::ObjectStoreConfig;
+use datafusion_dft::config::{add_object_stores, AppConfig, ObjectStoreArg};
+
+use crate::extension_cases::TestExecution;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_azure_object_store_is_registered() {
+    let mut config = AppConfig::default();
+    config.cli.execution.object_store = Some(ObjectStoreConfig::default());
+    let stores = [ObjectStoreArg {
+        url: "az://dft-test-container".to_string(),
+        aws_profile: None,
+        gcs_service_account: None,
+        azure_storage_account: Some("dfttestaccount".to_string()),
+    }];
+    add_object_stores(&mut config, &stores).unwrap();
+
+    let execution = TestExecution::new_with_config(config).await;
+    let runtime_env = execution.runtime_env();
+    let registered = ObjectStoreUrl::parse("az://dft-test-container").unwrap();
+    assert!(runtime_env.object_store(registered).is_ok());
+    let unregistered = ObjectStoreUrl::parse("az://other-container").unwrap();
+    assert!(runtime_env.object_store(unregistered).is_err());
+}
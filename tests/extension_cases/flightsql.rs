@@ -290,6 +290,32 @@ SELECT 1
     fixture.shutdown_and_wait().await;
 }
 
+#[tokio::test]
+pub async fn test_bench_command_prepared() {
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+    let assert = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT 1")
+            .arg("--bench")
+            .arg("--flightsql")
+            .arg("--prepared")
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    assert
+        .stdout(contains_str("Benchmark Stats (10 runs, serial, prepared)"))
+        .stdout(contains_str("Create Prepared Statement"));
+    fixture.shutdown_and_wait().await;
+}
+
 #[tokio::test]
 pub async fn test_bench_files() {
     let test_server = TestFlightSqlServiceImpl::new();
@@ -533,6 +559,92 @@ pub async fn test_execute_custom_port() {
     fixture.shutdown_and_wait().await;
 }
 
+#[tokio::test]
+pub async fn test_execute_with_retries() {
+    let test_server = TestFlightSqlServiceImpl::new().with_transient_failures(2);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT 1 + 2;")
+            .arg("--flightsql")
+            .arg("--retries")
+            .arg("3")
+            .arg("--retry-backoff-ms")
+            .arg("10")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let expected = r##"
++---------------------+
+| Int64(1) + Int64(2) |
++---------------------+
+| 3                   |
++---------------------+
+    "##;
+    assert.stdout(contains_str(expected));
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_execute_retries_exhausted() {
+    let test_server = TestFlightSqlServiceImpl::new().with_transient_failures(2);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT 1 + 2;")
+            .arg("--flightsql")
+            .arg("--retries")
+            .arg("1")
+            .arg("--retry-backoff-ms")
+            .arg("10")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .failure()
+    })
+    .await
+    .unwrap();
+
+    assert.stderr(contains_str("Server temporarily unavailable"));
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_execute_does_not_retry_other_errors() {
+    let test_server = TestFlightSqlServiceImpl::new();
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT * FROM missing_table")
+            .arg("--flightsql")
+            .arg("--retries")
+            .arg("3")
+            .arg("--retry-backoff-ms")
+            .arg("2000")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .failure()
+    })
+    .await
+    .unwrap();
+
+    // Retrying with the backoff would have taken longer than the timeout
+    assert.stderr(contains_str("missing_table"));
+    fixture.shutdown_and_wait().await;
+}
+
 #[tokio::test]
 pub async fn test_output_csv() {
     let dir = tempfile::tempdir().unwrap();
@@ -737,6 +849,7 @@ async fn test_get_schemas_no_filter() {
 | table_catalog | table_schema       |
 +---------------+--------------------+
 | datafusion    | information_schema |
+| datafusion    | system             |
 | test          | information_schema |
 | test          | meta               |
 +---------------+--------------------+
@@ -873,25 +986,28 @@ async fn test_get_tables_no_filter() {
     .unwrap();
 
     let expected = r#"
-+---------------+--------------------+-------------+------------+
-| table_catalog | table_schema       | table_name  | table_type |
-+---------------+--------------------+-------------+------------+
-| datafusion    | information_schema | columns     | VIEW       |
-| datafusion    | information_schema | df_settings | VIEW       |
-| datafusion    | information_schema | parameters  | VIEW       |
-| datafusion    | information_schema | routines    | VIEW       |
-| datafusion    | information_schema | schemata    | VIEW       |
-| datafusion    | information_schema | tables      | VIEW       |
-| datafusion    | information_schema | views       | VIEW       |
-| test          | information_schema | columns     | VIEW       |
-| test          | information_schema | df_settings | VIEW       |
-| test          | information_schema | parameters  | VIEW       |
-| test          | information_schema | routines    | VIEW       |
-| test          | information_schema | schemata    | VIEW       |
-| test          | information_schema | tables      | VIEW       |
-| test          | information_schema | views       | VIEW       |
-| test          | meta               | versions    | BASE TABLE |
-+---------------+--------------------+-------------+------------+
++---------------+--------------------+-----------------+------------+
+| table_catalog | table_schema       | table_name      | table_type |
++---------------+--------------------+-----------------+------------+
+| datafusion    | information_schema | columns         | VIEW       |
+| datafusion    | information_schema | df_settings     | VIEW       |
+| datafusion    | information_schema | parameters      | VIEW       |
+| datafusion    | information_schema | routines        | VIEW       |
+| datafusion    | information_schema | schemata        | VIEW       |
+| datafusion    | information_schema | tables          | VIEW       |
+| datafusion    | information_schema | views           | VIEW       |
+| datafusion    | system             | object_stores   | BASE TABLE |
+| datafusion    | system             | queries         | BASE TABLE |
+| datafusion    | system             | running_queries | BASE TABLE |
+| test          | information_schema | columns         | VIEW       |
+| test          | information_schema | df_settings     | VIEW       |
+| test          | information_schema | parameters      | VIEW       |
+| test          | information_schema | routines        | VIEW       |
+| test          | information_schema | schemata        | VIEW       |
+| test          | information_schema | tables          | VIEW       |
+| test          | information_schema | views           | VIEW       |
+| test          | meta               | versions        | BASE TABLE |
++---------------+--------------------+-----------------+------------+
 "#;
 
     assert.stdout(contains_str(expected));
@@ -1089,6 +1205,178 @@ async fn test_get_table_types() {
     fixture.shutdown_and_wait().await;
 }
 
+#[tokio::test]
+async fn test_get_primary_keys() {
+    let ctx = ExecutionContext::test();
+    ctx.session_ctx()
+        .sql("CREATE TABLE foo (a INT, b INT, c INT, PRIMARY KEY (b, a))")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("get-primary-keys")
+            .arg("--table")
+            .arg("foo")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    let keys: Vec<&str> = output
+        .lines()
+        .filter(|line| line.contains("| foo "))
+        .collect();
+    assert_eq!(keys.len(), 2, "{output}");
+    assert!(keys[0].ends_with("| foo        | b           |          | 1            |"));
+    assert!(keys[1].ends_with("| foo        | a           |          | 2            |"));
+
+    // Tables without a primary key have no rows
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("get-primary-keys")
+            .arg("--table")
+            .arg("missing")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!output.contains("| missing"), "{output}");
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_prepared_query() {
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("prepared-query")
+            .arg("--sql")
+            .arg("SELECT name FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, name) WHERE id = $1")
+            .arg("--param")
+            .arg("2")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let expected = r##"
++------+
+| name |
++------+
+| b    |
++------+
+    "##;
+    assert.stdout(contains_str(expected));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_get_cross_reference() {
+    use arrow_flight::sql::{client::FlightSqlServiceClient, CommandGetCrossReference};
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("get-cross-reference")
+            .arg("--pk-table")
+            .arg("customers")
+            .arg("--fk-table")
+            .arg("orders")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+    // DataFusion has no foreign key constraints so no keys are printed
+    let output = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(!output.contains("customers"), "{output}");
+
+    // The empty result still has the schema the FlightSQL spec defines
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+    let flight_info = client
+        .get_cross_reference(CommandGetCrossReference {
+            pk_catalog: None,
+            pk_db_schema: None,
+            pk_table: "customers".to_string(),
+            fk_catalog: None,
+            fk_db_schema: None,
+            fk_table: "orders".to_string(),
+        })
+        .await
+        .unwrap();
+    let schema = flight_info.clone().try_decode_schema().unwrap();
+    let columns: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(
+        columns,
+        [
+            "pk_catalog_name",
+            "pk_db_schema_name",
+            "pk_table_name",
+            "pk_column_name",
+            "fk_catalog_name",
+            "fk_db_schema_name",
+            "fk_table_name",
+            "fk_column_name",
+            "key_sequence",
+            "fk_key_name",
+            "pk_key_name",
+            "update_rule",
+            "delete_rule",
+        ]
+    );
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+    let batches: Vec<_> = client
+        .do_get(ticket)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+
+    fixture.shutdown_and_wait().await;
+}
+
 #[tokio::test]
 async fn test_get_sql_info() {
     let ctx = ExecutionContext::test();
@@ -1115,8 +1403,12 @@ async fn test_get_sql_info() {
         "Should contain server name"
     );
     assert!(
-        output.contains("server_name"),
-        "Should contain server_name column"
+        output.contains("info_name"),
+        "Should contain info_name column"
+    );
+    assert!(
+        output.contains(env!("CARGO_PKG_VERSION")),
+        "Should contain server version"
     );
 
     fixture.shutdown_and_wait().await;
@@ -1359,46 +1651,243 @@ pub async fn test_prepared_statement_complex_query() {
 }
 
 #[tokio::test]
-pub async fn test_execute_with_headers_file() {
-    let test_server = TestFlightSqlServiceImpl::new();
+pub async fn test_prepared_statement_with_parameters() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::{Int64Array, RecordBatch};
+    use datafusion::arrow::datatypes::DataType;
+    use futures::TryStreamExt;
+    use std::sync::Arc;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
     let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
 
-    // Create a temporary headers file
-    let mut headers_file = tempfile::NamedTempFile::new().unwrap();
-    use std::io::Write;
-    writeln!(headers_file, "# Test headers file").unwrap();
-    writeln!(headers_file, "x-test-header: test-value").unwrap();
-    writeln!(headers_file, "database: test_db").unwrap();
-    headers_file.flush().unwrap();
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
 
-    let headers_path = headers_file.path().to_path_buf();
-    let assert = tokio::task::spawn_blocking(move || {
-        Command::cargo_bin("dft")
-            .unwrap()
-            .arg("-c")
-            .arg("SELECT 1 + 2;")
-            .arg("--flightsql")
-            .arg("--headers-file")
-            .arg(headers_path)
-            .timeout(Duration::from_secs(5))
-            .assert()
-            .success()
-    })
-    .await
-    .unwrap();
+    let sql = "SELECT x FROM (VALUES (1), (2), (3)) AS t(x) WHERE x > $1";
+    let mut prepared_stmt = client
+        .prepare(sql.to_string(), None)
+        .await
+        .expect("Failed to create prepared statement");
 
-    let expected = r##"
-+---------------------+
-| Int64(1) + Int64(2) |
-+---------------------+
-| 3                   |
-+---------------------+
-    "##;
-    assert.stdout(contains_str(expected));
-    fixture.shutdown_and_wait().await;
-}
+    let parameter_schema = prepared_stmt
+        .parameter_schema()
+        .expect("Failed to get parameter schema")
+        .clone();
+    assert_eq!(parameter_schema.fields().len(), 1);
+    assert_eq!(parameter_schema.field(0).name(), "$1");
+    assert_eq!(parameter_schema.field(0).data_type(), &DataType::Int64);
+
+    let parameters = RecordBatch::try_new(
+        Arc::new(parameter_schema),
+        vec![Arc::new(Int64Array::from(vec![1]))],
+    )
+    .unwrap();
+    prepared_stmt
+        .set_parameters(parameters)
+        .expect("Failed to set parameters");
 
-#[tokio::test]
+    let flight_info = prepared_stmt
+        .execute()
+        .await
+        .expect("Failed to execute prepared statement");
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+    let batches: Vec<RecordBatch> = client
+        .do_get(ticket)
+        .await
+        .expect("Failed to get results")
+        .try_collect()
+        .await
+        .expect("Failed to read results");
+    let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    assert_eq!(rows, 2);
+
+    prepared_stmt
+        .close()
+        .await
+        .expect("Failed to close prepared statement");
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_cancel_flight_info() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use arrow_flight::{CancelFlightInfoRequest, CancelStatus};
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let flight_info = client
+        .execute("SELECT 1".to_string(), None)
+        .await
+        .expect("Failed to get flight info");
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+
+    let result = client
+        .cancel_flight_info(CancelFlightInfoRequest::new(flight_info))
+        .await
+        .expect("Failed to cancel query");
+    assert_eq!(result.status, CancelStatus::Cancelled as i32);
+
+    let Err(err) = client.do_get(ticket).await else {
+        panic!("Cancelled query should not be executed");
+    };
+    assert!(err.to_string().contains("was cancelled"));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_partitioned_results() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::RecordBatch;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec).with_partitioned_results(true);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let flight_info = client
+        .execute("SELECT 1 AS x UNION ALL SELECT 2 AS x".to_string(), None)
+        .await
+        .expect("Failed to get flight info");
+    assert_eq!(flight_info.endpoint.len(), 2);
+
+    let mut rows = 0;
+    for endpoint in flight_info.endpoint {
+        let batches: Vec<RecordBatch> = client
+            .do_get(endpoint.ticket.unwrap())
+            .await
+            .expect("Failed to get partition")
+            .try_collect()
+            .await
+            .expect("Failed to read partition");
+        rows += batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+    }
+    assert_eq!(rows, 2);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_set_is_scoped_to_connection() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::RecordBatch;
+    use datafusion::arrow::util::pretty::pretty_format_batches;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    async fn query(client: &mut FlightSqlServiceClient<Channel>, sql: &str) -> String {
+        let flight_info = client
+            .execute(sql.to_string(), None)
+            .await
+            .expect("Failed to get flight info");
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        for endpoint in flight_info.endpoint {
+            let mut stream_batches: Vec<RecordBatch> = client
+                .do_get(endpoint.ticket.unwrap())
+                .await
+                .expect("Failed to execute")
+                .try_collect()
+                .await
+                .expect("Failed to read results");
+            batches.append(&mut stream_batches);
+        }
+        pretty_format_batches(&batches).unwrap().to_string()
+    }
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let mut client_a = FlightSqlServiceClient::new(
+        Channel::from_static("http://127.0.0.1:50051")
+            .connect()
+            .await
+            .expect("Failed to connect to test server"),
+    );
+    let mut client_b = FlightSqlServiceClient::new(
+        Channel::from_static("http://127.0.0.1:50051")
+            .connect()
+            .await
+            .expect("Failed to connect to test server"),
+    );
+
+    let setting = "SELECT value FROM information_schema.df_settings WHERE name = 'datafusion.execution.batch_size'";
+    query(&mut client_a, "SET datafusion.execution.batch_size = 1234").await;
+
+    assert!(query(&mut client_a, setting).await.contains("1234"));
+    assert!(!query(&mut client_b, setting).await.contains("1234"));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_execute_with_headers_file() {
+    let test_server = TestFlightSqlServiceImpl::new();
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    // Create a temporary headers file
+    let mut headers_file = tempfile::NamedTempFile::new().unwrap();
+    use std::io::Write;
+    writeln!(headers_file, "# Test headers file").unwrap();
+    writeln!(headers_file, "x-test-header: test-value").unwrap();
+    writeln!(headers_file, "database: test_db").unwrap();
+    headers_file.flush().unwrap();
+
+    let headers_path = headers_file.path().to_path_buf();
+    let assert = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT 1 + 2;")
+            .arg("--flightsql")
+            .arg("--headers-file")
+            .arg(headers_path)
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let expected = r##"
++---------------------+
+| Int64(1) + Int64(2) |
++---------------------+
+| 3                   |
++---------------------+
+    "##;
+    assert.stdout(contains_str(expected));
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
 pub async fn test_headers_file_with_curl_format() {
     let test_server = TestFlightSqlServiceImpl::new();
     let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
@@ -1589,3 +2078,798 @@ async fn test_headers_file_precedence() {
     assert.stdout(contains_str(expected));
     fixture.shutdown_and_wait().await;
 }
+
+#[tokio::test]
+pub async fn test_query_memory_limit() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::RecordBatch;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec).with_query_memory_limit(1024);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    // The build side of a hash join can't spill so it fails once the pool is exhausted
+    let flight_info = client
+        .execute(
+            "SELECT a.value FROM generate_series(1, 100000) a JOIN generate_series(1, 100000) b ON a.value = b.value"
+                .to_string(),
+            None,
+        )
+        .await
+        .expect("Failed to get flight info");
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+    let result = match client.do_get(ticket).await {
+        Ok(stream) => stream.try_collect::<Vec<RecordBatch>>().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let Err(err) = result else {
+        panic!("Query should exceed the memory limit");
+    };
+    assert!(
+        err.to_string().contains("Resources exhausted"),
+        "unexpected error: {err}"
+    );
+
+    // Small queries still succeed
+    let flight_info = client
+        .execute("SELECT 1".to_string(), None)
+        .await
+        .expect("Failed to get flight info");
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+    let batches: Vec<RecordBatch> = client
+        .do_get(ticket)
+        .await
+        .expect("Failed to execute query")
+        .try_collect()
+        .await
+        .expect("Failed to collect results");
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_query_log() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::RecordBatch;
+    use datafusion_dft::config::DbConfig;
+    use datafusion_dft::server::query_log::QueryLog;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = format!("file://{}/", dir.path().to_str().unwrap());
+    let db_config = DbConfig {
+        path: url::Url::parse(&path).unwrap(),
+        query_log: true,
+        persist_catalog: false,
+        persist_system_tables: false,
+    };
+    let ctx = ExecutionContext::test();
+    let mut exec = AppExecution::new(ctx);
+    let query_log = QueryLog::try_new(exec.session_ctx(), &db_config)
+        .await
+        .unwrap();
+    exec.with_query_log(query_log.clone());
+    let session_ctx = exec.session_ctx().clone();
+    let test_server = FlightSqlServiceImpl::new(exec).with_partitioned_results(true);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let flight_info = client
+        .execute("SELECT 1 AS x UNION ALL SELECT 2 AS x".to_string(), None)
+        .await
+        .expect("Failed to get flight info");
+    assert_eq!(flight_info.endpoint.len(), 2);
+    for endpoint in flight_info.endpoint {
+        let _: Vec<RecordBatch> = client
+            .do_get(endpoint.ticket.unwrap())
+            .await
+            .expect("Failed to execute query")
+            .try_collect()
+            .await
+            .expect("Failed to collect results");
+    }
+
+    // The entry is buffered once the results of both partitions are sent
+    let mut logged = Vec::new();
+    for _ in 0..50 {
+        query_log.flush().await.unwrap();
+        logged = session_ctx
+            .sql("SELECT sql, rows, protocol FROM system.query_log")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        if logged.iter().map(|b| b.num_rows()).sum::<usize>() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let expected = [
+        "+---------------------------------------+------+-----------+",
+        "| sql                                   | rows | protocol  |",
+        "+---------------------------------------+------+-----------+",
+        "| SELECT 1 AS x UNION ALL SELECT 2 AS x | 2    | flightsql |",
+        "+---------------------------------------+------+-----------+",
+    ];
+    datafusion::assert_batches_eq!(expected, &logged);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_result_row_limit() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::RecordBatch;
+    use datafusion_dft::config::FlightSQLServerResultLimitConfig;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server =
+        FlightSqlServiceImpl::new(exec).with_result_limit(FlightSQLServerResultLimitConfig {
+            rows: Some(5),
+            bytes: None,
+            reject: false,
+        });
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let flight_info = client
+        .execute("SELECT * FROM generate_series(1, 100)".to_string(), None)
+        .await
+        .expect("Failed to get flight info");
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+    let batches: Vec<RecordBatch> = client
+        .do_get(ticket)
+        .await
+        .expect("Failed to execute query")
+        .try_collect()
+        .await
+        .expect("Failed to collect results");
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_result_row_limit_reject() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use datafusion::arrow::array::RecordBatch;
+    use datafusion_dft::config::FlightSQLServerResultLimitConfig;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server =
+        FlightSqlServiceImpl::new(exec).with_result_limit(FlightSQLServerResultLimitConfig {
+            rows: Some(5),
+            bytes: None,
+            reject: true,
+        });
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let flight_info = client
+        .execute("SELECT * FROM generate_series(1, 100)".to_string(), None)
+        .await
+        .expect("Failed to get flight info");
+    let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+    let result = match client.do_get(ticket).await {
+        Ok(stream) => stream.try_collect::<Vec<RecordBatch>>().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let Err(err) = result else {
+        panic!("Query should exceed the result limit");
+    };
+    assert!(
+        err.to_string().contains("more than 5 rows"),
+        "unexpected error: {err}"
+    );
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_catalog_cache_invalidated_by_ddl() {
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use arrow_flight::sql::CommandGetTables;
+    use arrow_flight::FlightInfo;
+    use datafusion::arrow::array::RecordBatch;
+    use futures::TryStreamExt;
+    use tonic::transport::Channel;
+
+    async fn fetch_rows(
+        client: &mut FlightSqlServiceClient<Channel>,
+        flight_info: FlightInfo,
+    ) -> usize {
+        let ticket = flight_info.endpoint[0].ticket.clone().unwrap();
+        let batches: Vec<RecordBatch> = client
+            .do_get(ticket)
+            .await
+            .expect("Failed to execute query")
+            .try_collect()
+            .await
+            .expect("Failed to collect results");
+        batches.iter().map(|b| b.num_rows()).sum()
+    }
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let get_tables = CommandGetTables {
+        table_name_filter_pattern: Some("cache_test".to_string()),
+        ..Default::default()
+    };
+    let flight_info = client.get_tables(get_tables.clone()).await.unwrap();
+    assert_eq!(fetch_rows(&mut client, flight_info).await, 0);
+
+    let flight_info = client
+        .execute("CREATE TABLE cache_test AS VALUES (1)".to_string(), None)
+        .await
+        .unwrap();
+    fetch_rows(&mut client, flight_info).await;
+
+    let flight_info = client.get_tables(get_tables).await.unwrap();
+    assert_eq!(fetch_rows(&mut client, flight_info).await, 1);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_handshake_issues_session_token() {
+    use arrow_flight::flight_service_client::FlightServiceClient;
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use arrow_flight::HandshakeRequest;
+    use datafusion_dft::server::flightsql::auth::SessionTokens;
+    use tonic::transport::Channel;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let tokens = SessionTokens::new(Duration::from_secs(60));
+    let test_server = FlightSqlServiceImpl::new(exec).with_session_tokens(tokens.clone());
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightSqlServiceClient::new(channel);
+
+    let payload = client
+        .handshake("user", "pass")
+        .await
+        .expect("Failed to handshake");
+    let token = String::from_utf8(payload.to_vec()).unwrap();
+    assert!(tokens.is_valid(&token));
+    assert_eq!(client.token(), Some(&token));
+
+    // A session token can't be exchanged for another one
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut flight_client = FlightServiceClient::new(channel);
+    let mut request =
+        tonic::Request::new(futures::stream::once(async { HandshakeRequest::default() }));
+    request
+        .metadata_mut()
+        .insert("authorization", format!("Bearer {token}").parse().unwrap());
+    let status = flight_client.handshake(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_reload_ddl_action() {
+    use arrow_flight::flight_service_client::FlightServiceClient;
+    use arrow_flight::Action;
+    use datafusion_app::config::ExecutionConfig;
+    use datafusion_app::extensions::DftSessionStateBuilder;
+    use datafusion_dft::server::flightsql::service::{ADMIN_TOKEN_HEADER, RELOAD_DDL_ACTION};
+    use tonic::transport::Channel;
+
+    let dir = tempfile::tempdir().unwrap();
+    let ddl_path = dir.path().join("ddl.sql");
+    let config = ExecutionConfig {
+        ddl_path: Some(ddl_path.clone()),
+        ..Default::default()
+    };
+    let session_state = DftSessionStateBuilder::try_new(Some(config.clone()))
+        .unwrap()
+        .build()
+        .unwrap();
+    let ctx = ExecutionContext::try_new(&config, session_state, "dft", env!("CARGO_PKG_VERSION"))
+        .unwrap();
+    let session_ctx = ctx.session_ctx().clone();
+    let exec = AppExecution::new(ctx);
+    let ddl_errors = exec.ddl_errors().clone();
+    let test_server = FlightSqlServiceImpl::new(exec).with_admin_token("admin".to_string());
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    // The view is added to the DDL file after the server started
+    std::fs::write(&ddl_path, "CREATE VIEW reloaded AS VALUES (1);").unwrap();
+    assert!(!session_ctx.table_exist("reloaded").unwrap());
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightServiceClient::new(channel);
+    let reload = |token: Option<&str>| {
+        let mut request = tonic::Request::new(Action::new(RELOAD_DDL_ACTION, ""));
+        if let Some(token) = token {
+            request
+                .metadata_mut()
+                .insert(ADMIN_TOKEN_HEADER, token.parse().unwrap());
+        }
+        request
+    };
+    // Only requests with the admin token can reload the DDL
+    let status = client.do_action(reload(None)).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    let status = client.do_action(reload(Some("wrong"))).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    assert!(!session_ctx.table_exist("reloaded").unwrap());
+
+    client
+        .do_action(reload(Some("admin")))
+        .await
+        .expect("Failed to reload DDL");
+    assert!(session_ctx.table_exist("reloaded").unwrap());
+
+    // A view whose definition changed is registered again
+    std::fs::write(&ddl_path, "CREATE VIEW reloaded AS VALUES (1), (2);").unwrap();
+    client
+        .do_action(reload(Some("admin")))
+        .await
+        .expect("Failed to reload DDL");
+    let expected = [
+        "+----------+",
+        "| count(*) |",
+        "+----------+",
+        "| 2        |",
+        "+----------+",
+    ];
+    let batches = session_ctx
+        .sql("SELECT count(*) FROM reloaded")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    datafusion::assert_batches_eq!(expected, &batches);
+    assert_eq!(ddl_errors.get().unwrap().len(), 0);
+
+    // Statements that fail are reported to readiness checks
+    std::fs::write(&ddl_path, "CREATE VIEW broken AS SELECT * FROM missing;").unwrap();
+    client
+        .do_action(reload(Some("admin")))
+        .await
+        .expect("Failed to reload DDL");
+    assert_eq!(ddl_errors.get().unwrap().len(), 1);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_parse_error_details() {
+    use arrow_flight::flight_service_client::FlightServiceClient;
+    use arrow_flight::sql::{CommandStatementQuery, ProstMessageExt};
+    use arrow_flight::FlightDescriptor;
+    use datafusion_dft::server::flightsql::error::ERROR_DOMAIN;
+    use prost::Message;
+    use tonic::transport::Channel;
+    use tonic_types::StatusExt;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let channel = Channel::from_static("http://127.0.0.1:50051")
+        .connect()
+        .await
+        .expect("Failed to connect to test server");
+    let mut client = FlightServiceClient::new(channel);
+    let command = CommandStatementQuery {
+        query: "SELECT 1;\nSELEC 2".to_string(),
+        transaction_id: None,
+    };
+    let descriptor = FlightDescriptor::new_cmd(command.as_any().encode_to_vec());
+    let Err(status) = client.get_flight_info(descriptor).await else {
+        panic!("Expected invalid SQL to fail");
+    };
+
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    let info = status.get_details_error_info().expect("Missing error info");
+    assert_eq!(info.reason, "PARSE_ERROR");
+    assert_eq!(info.domain, ERROR_DOMAIN);
+    assert_eq!(info.metadata["line"], "2");
+    assert!(info.metadata.contains_key("query_id"));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+pub async fn test_client_handshake_auth() {
+    use datafusion_dft::server::flightsql::auth::SessionTokens;
+
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    let tokens = SessionTokens::new(Duration::from_secs(60));
+    let test_server = FlightSqlServiceImpl::new(exec).with_session_tokens(tokens);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_client_basic_auth("user", "pass", true);
+    let config = config_builder.build("my_config.toml");
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("-c")
+            .arg("SELECT 1 + 2;")
+            .arg("--flightsql")
+            .arg("--config")
+            .arg(config.path)
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let expected = r##"
++---------------------+
+| Int64(1) + Int64(2) |
++---------------------+
+| 3                   |
++---------------------+
+    "##;
+    assert.stdout(contains_str(expected));
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_ingest() {
+    let ctx = ExecutionContext::test();
+    ctx.session_ctx()
+        .sql("CREATE TABLE events (id BIGINT, name VARCHAR)")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let exec = AppExecution::new(ctx.clone());
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("ingest")
+            .arg("--table")
+            .arg("events")
+            .arg("--sql")
+            .arg("SELECT CAST(column1 AS BIGINT) AS id, CAST(column2 AS VARCHAR) AS name FROM (VALUES (1, 'a'), (2, 'b'))")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+    assert.stdout(contains_str("Ingested 2 rows into events"));
+
+    let batches = ctx
+        .session_ctx()
+        .sql("SELECT count(*) AS count FROM events")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let expected = r##"
++-------+
+| count |
++-------+
+| 2     |
++-------+
+"##;
+    assert_eq!(
+        datafusion::arrow::util::pretty::pretty_format_batches(&batches)
+            .unwrap()
+            .to_string(),
+        expected.trim()
+    );
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_ingest_is_logged() {
+    use datafusion_dft::config::DbConfig;
+    use datafusion_dft::server::query_log::QueryLog;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = format!("file://{}/", dir.path().to_str().unwrap());
+    let db_config = DbConfig {
+        path: url::Url::parse(&path).unwrap(),
+        query_log: true,
+        persist_catalog: false,
+        persist_system_tables: false,
+    };
+    let ctx = ExecutionContext::test();
+    ctx.session_ctx()
+        .sql("CREATE TABLE events (id BIGINT)")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let mut exec = AppExecution::new(ctx.clone());
+    let query_log = QueryLog::try_new(exec.session_ctx(), &db_config)
+        .await
+        .unwrap();
+    exec.with_query_log(query_log.clone());
+    let test_server = FlightSqlServiceImpl::new(exec);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("ingest")
+            .arg("--table")
+            .arg("events")
+            .arg("--sql")
+            .arg("SELECT CAST(column1 AS BIGINT) AS id FROM (VALUES (1), (2))")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    // The entry is buffered once the exchange ends
+    let mut logged = Vec::new();
+    for _ in 0..50 {
+        query_log.flush().await.unwrap();
+        logged = ctx
+            .session_ctx()
+            .sql("SELECT sql, rows, protocol FROM system.query_log")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        if logged.iter().map(|b| b.num_rows()).sum::<usize>() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let expected = [
+        "+---------------------------------+------+-----------+",
+        "| sql                             | rows | protocol  |",
+        "+---------------------------------+------+-----------+",
+        "| INSERT INTO events (DoExchange) | 2    | flightsql |",
+        "+---------------------------------+------+-----------+",
+    ];
+    datafusion::assert_batches_eq!(expected, &logged);
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_ingest_is_admitted() {
+    use datafusion_dft::server::flightsql::admission::AdmissionController;
+
+    let ctx = ExecutionContext::test();
+    ctx.session_ctx()
+        .sql("CREATE TABLE events (id BIGINT)")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let exec = AppExecution::new(ctx);
+    // No statements can execute, and none can wait for a slot
+    let admission = AdmissionController::new(0, 0, Duration::from_millis(10));
+    let test_server = FlightSqlServiceImpl::new(exec).with_admission_controller(admission);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("ingest")
+            .arg("--table")
+            .arg("events")
+            .arg("--sql")
+            .arg("SELECT CAST(1 AS BIGINT) AS id")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .failure()
+    })
+    .await
+    .unwrap();
+    assert.stderr(contains_str("Too many statements are queued"));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_expired_ticket() {
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    // Tickets expire as soon as they're issued
+    let test_server = FlightSqlServiceImpl::new(exec).with_ticket_ttl(Duration::ZERO);
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("statement-query")
+            .arg("--sql")
+            .arg("SELECT 1")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .failure()
+    })
+    .await
+    .unwrap();
+    assert.stderr(contains_str("not found, it may have expired"));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[tokio::test]
+async fn test_spilled_results() {
+    let ctx = ExecutionContext::test();
+    let exec = AppExecution::new(ctx);
+    // Spill every result, with each batch in its own chunk
+    let spill = datafusion_dft::config::FlightSQLServerSpillConfig {
+        memory_threshold_bytes: 0,
+        chunk_bytes: 1,
+        directory: None,
+    };
+    let test_server = FlightSqlServiceImpl::new(exec).with_result_spill(Some(spill));
+    let fixture = TestFixture::new(test_server.service(), "127.0.0.1:50051").await;
+
+    let assert = tokio::task::spawn_blocking(|| {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("flightsql")
+            .arg("statement-query")
+            .arg("--sql")
+            .arg("SELECT count(*) AS count FROM generate_series(1, 100000)")
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let expected = r##"
++--------+
+| count  |
++--------+
+| 100000 |
++--------+
+"##;
+    assert.stdout(contains_str(expected));
+
+    fixture.shutdown_and_wait().await;
+}
+
+#[test]
+fn test_invalid_max_concurrent_statements() {
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_flightsql_server_setting("max_concurrent_statements", "0");
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("-c")
+        .arg("SELECT 1")
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str(
+        "flightsql_server.max_concurrent_statements must be at least 1",
+    ));
+}
+
+#[test]
+fn test_invalid_rate_limit() {
+    for (rate_limit, expected) in [
+        (
+            "{ requests_per_second = 0 }",
+            "flightsql_server.rate_limit.requests_per_second must be greater than 0",
+        ),
+        (
+            "{ requests_per_second = 10, burst = 0 }",
+            "flightsql_server.rate_limit.burst must be at least 1",
+        ),
+        (
+            "{ max_concurrent_requests = 0 }",
+            "flightsql_server.rate_limit.max_concurrent_requests must be at least 1",
+        ),
+    ] {
+        let mut config_builder = TestConfigBuilder::default();
+        config_builder.with_flightsql_server_setting("rate_limit", rate_limit);
+        let config = config_builder.build("my_config.toml");
+
+        let assert = Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(config.path)
+            .arg("-c")
+            .arg("SELECT 1")
+            .assert()
+            .failure();
+
+        assert.stderr(contains_str(expected));
+    }
+}
+
+#[test]
+fn test_record_requires_local_queries() {
+    let dir = tempfile::tempdir().unwrap();
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("-c")
+        .arg("SELECT 1")
+        .arg("--flightsql")
+        .arg("--record")
+        .arg(dir.path().join("session.jsonl"))
+        .assert()
+        .failure();
+
+    assert.stderr(contains_str(
+        "The `record` flag can only be used for local queries",
+    ));
+}
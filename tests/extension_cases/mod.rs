@@ -21,6 +21,8 @@
 mod auth_basic;
 #[cfg(feature = "flightsql")]
 mod auth_bearer;
+#[cfg(feature = "azure")]
+mod azure;
 #[cfg(feature = "clickhouse")]
 mod clickhouse;
 #[cfg(feature = "deltalake")]
@@ -29,6 +31,8 @@ mod deltalake;
 mod flightsql;
 #[cfg(feature = "functions-json")]
 mod functions_json;
+#[cfg(feature = "gcs")]
+mod gcs;
 #[cfg(feature = "huggingface")]
 mod huggingface;
 #[cfg(feature = "mongodb")]
@@ -83,6 +87,11 @@ impl TestExecution {
         Self { execution }
     }
 
+    /// The `RuntimeEnv` queries are run with, to check the registered object stores
+    pub fn runtime_env(&self) -> std::sync::Arc<datafusion::execution::runtime_env::RuntimeEnv> {
+        self.execution.session_ctx().runtime_env()
+    }
+
     /// Run the setup SQL query, discarding the result
     pub async fn with_setup(self, sql: &str) -> Self {
         debug!("Running setup query: {sql}");
@@ -176,3 +176,86 @@ async fn test_s3_credential_chain_with_static_override() {
 
     assert.stdout(contains_str(expected));
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_s3_object_store_flag() {
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .env("AWS_ACCESS_KEY_ID", "LSIAQAAAAAAVNCBMPNSG")
+        .env(
+            "AWS_SECRET_ACCESS_KEY",
+            "5555555555555555555555555555555555555555",
+        )
+        .env("AWS_ENDPOINT", "http://localhost:9000")
+        .env("AWS_ALLOW_HTTP", "true")
+        .arg("--object-store")
+        .arg("s3://test")
+        .arg("-c")
+        .arg("SELECT c1 FROM 's3://test/aggregate_test_100.csv' LIMIT 1")
+        .assert()
+        .success();
+
+    let expected = r#"
++----+
+| c1 |
++----+
+| c  |
++----+
+"#;
+
+    assert.stdout(contains_str(expected));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_s3_object_store_flag_with_aws_profile() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let credentials_path = tempdir.path().join("credentials");
+    std::fs::write(
+        &credentials_path,
+        "[minio]\naws_access_key_id = LSIAQAAAAAAVNCBMPNSG\naws_secret_access_key = 5555555555555555555555555555555555555555\n",
+    )
+    .unwrap();
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .env("AWS_SHARED_CREDENTIALS_FILE", &credentials_path)
+        .env("AWS_ENDPOINT", "http://localhost:9000")
+        .env("AWS_ALLOW_HTTP", "true")
+        .arg("--object-store")
+        .arg("s3://test")
+        .arg("--aws-profile")
+        .arg("minio")
+        .arg("-c")
+        .arg("SELECT c1 FROM 's3://test/aggregate_test_100.csv' LIMIT 1")
+        .assert()
+        .success();
+
+    assert.stdout(contains_str("| c  |"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_s3_create_object_store() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let ddl_path = tempdir.path().join("my_ddl.sql");
+    let ddl = "CREATE EXTERNAL OBJECT STORE s3 LOCATION 's3://test' OPTIONS ('aws.endpoint' 'http://localhost:9000', 'aws.access_key_id' 'LSIAQAAAAAAVNCBMPNSG', 'aws.secret_access_key' '5555555555555555555555555555555555555555', 'aws.allow_http' 'true');\nCREATE EXTERNAL TABLE a STORED AS CSV LOCATION 's3://test/aggregate_test_100.csv';";
+    std::fs::write(&ddl_path, ddl).unwrap();
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_ddl_path("cli", ddl_path);
+    let config = config_builder.build("my_config.toml");
+
+    let assert = Command::cargo_bin("dft")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path)
+        .arg("--run-ddl")
+        .arg("-c")
+        .arg("SELECT c1 FROM a LIMIT 1")
+        .arg("-c")
+        .arg("SELECT url, kind FROM system.object_stores")
+        .assert()
+        .success();
+
+    assert
+        .stdout(contains_str("| c  |"))
+        .stdout(contains_str("| s3://test | s3   |"));
+}
@@ -53,3 +53,66 @@ async fn test_custom_config_benchmark_iterations() {
 
     assert.stdout(contains_str(expected));
 }
+
+#[tokio::test]
+async fn test_udf_declared_in_config() {
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_udf(
+        "wasm_add",
+        "wasm",
+        "crates/datafusion-udfs-wasm/test-wasm/wasm_examples.wasm",
+        &["Int64", "Int64"],
+        "Int64",
+    );
+    let config = config_builder.build("my_config.toml");
+
+    let assert = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(config.path)
+            .arg("-c")
+            .arg("SELECT wasm_add(1, 2)")
+            .assert()
+            .success()
+    })
+    .await
+    .unwrap();
+
+    let expected = "
++-----------------------------+
+| wasm_add(Int64(1),Int64(2)) |
++-----------------------------+
+| 3                           |
++-----------------------------+";
+
+    assert.stdout(contains_str(expected));
+}
+
+#[tokio::test]
+async fn test_udf_declared_in_config_with_missing_module() {
+    let mut config_builder = TestConfigBuilder::default();
+    config_builder.with_udf(
+        "wasm_add",
+        "wasm",
+        "does/not/exist.wasm",
+        &["Int64", "Int64"],
+        "Int64",
+    );
+    let config = config_builder.build("my_config.toml");
+
+    let assert = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("dft")
+            .unwrap()
+            .arg("--config")
+            .arg(config.path)
+            .arg("-c")
+            .arg("SELECT 1")
+            .assert()
+            .failure()
+    })
+    .await
+    .unwrap();
+
+    assert.stderr(contains_str("Error creating function wasm_add"));
+}
@@ -17,17 +17,16 @@
 
 use datafusion::assert_batches_eq;
 use datafusion_dft::tui::AppEvent;
+use ratatui::crossterm::event;
 
 use crate::tui_cases::TestApp;
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_create_table_ddl() {
-    let mut test_app = TestApp::new().await;
-
     let ddl = "CREATE TABLE foo AS VALUES (1);";
-    test_app
-        .handle_app_event(AppEvent::ExecuteDDL(ddl.to_string()))
-        .unwrap();
+    let mut test_app = TestApp::with_ddl(ddl).await;
+
+    test_app.handle_app_event(AppEvent::ExecuteDDL).unwrap();
     test_app.wait_for_ddl().await;
 
     let sql = "SELECT * FROM foo;";
@@ -45,14 +44,12 @@ async fn test_create_table_ddl() {
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_create_table_in_new_schema() {
-    let mut test_app = TestApp::new().await;
-
     let create_schema = "CREATE SCHEMA foo;";
     let create_table = "CREATE TABLE foo.bar AS VALUES (1);";
     let combined = [create_schema, create_table].join(";");
-    test_app
-        .handle_app_event(AppEvent::ExecuteDDL(combined))
-        .unwrap();
+    let mut test_app = TestApp::with_ddl(&combined).await;
+
+    test_app.handle_app_event(AppEvent::ExecuteDDL).unwrap();
     test_app.wait_for_ddl().await;
 
     let sql = "SELECT * FROM foo.bar;";
@@ -70,15 +67,13 @@ async fn test_create_table_in_new_schema() {
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_create_table_in_new_catalog() {
-    let mut test_app = TestApp::new().await;
-
     let create_catalog = "CREATE DATABASE foo;";
     let create_schema = "CREATE SCHEMA foo.bar;";
     let create_table = "CREATE TABLE foo.bar.baz AS VALUES (1);";
     let combined = [create_catalog, create_schema, create_table].join(";");
-    test_app
-        .handle_app_event(AppEvent::ExecuteDDL(combined))
-        .unwrap();
+    let mut test_app = TestApp::with_ddl(&combined).await;
+
+    test_app.handle_app_event(AppEvent::ExecuteDDL).unwrap();
     test_app.wait_for_ddl().await;
 
     let sql = "SELECT * FROM foo.bar.baz;";
@@ -99,8 +94,50 @@ async fn test_create_table_in_new_catalog() {
 async fn test_create_table_ddl_error() {
     let mut test_app = TestApp::new().await;
 
-    test_app.handle_app_event(AppEvent::DDLError).unwrap();
+    test_app
+        .handle_app_event(AppEvent::DDLError("Table 'foo' doesn't exist".to_string()))
+        .unwrap();
+
+    let state = test_app.state();
+    assert!(state.sql_tab.ddl_error());
+    let error = state.sql_tab.execution_error().as_ref().unwrap();
+    assert_eq!(error.error(), "Table 'foo' doesn't exist");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_ddl_error_names_the_file() {
+    let mut test_app = TestApp::with_ddl_dir(&[
+        ("01_good.sql", "CREATE TABLE foo AS VALUES (1);"),
+        ("02_bad.sql", "SELECT * FROM missing;"),
+    ])
+    .await;
+
+    test_app.handle_app_event(AppEvent::ExecuteDDL).unwrap();
+    test_app.wait_for_ddl().await;
+    test_app.handle_ddl_events().unwrap();
+
+    let state = test_app.state();
+    assert!(state.sql_tab.ddl_error());
+    let error = state.sql_tab.execution_error().as_ref().unwrap();
+    assert!(error.error().contains("02_bad.sql"), "{}", error.error());
+    assert!(!error.error().contains("01_good.sql"), "{}", error.error());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_saving_ddl_directory_shows_error() {
+    let mut test_app =
+        TestApp::with_ddl_dir(&[("01_tables.sql", "CREATE TABLE foo AS VALUES (1);")]).await;
+
+    for key in ['d', 's'] {
+        let key = event::KeyEvent::new(event::KeyCode::Char(key), event::KeyModifiers::NONE);
+        test_app.handle_app_event(AppEvent::Key(key)).unwrap();
+    }
 
     let state = test_app.state();
-    assert!(state.sql_tab.ddl_error())
+    let error = state.sql_tab.execution_error().as_ref().unwrap();
+    assert!(
+        error.error().contains("is a directory"),
+        "{}",
+        error.error()
+    );
 }
@@ -56,8 +56,34 @@ struct TestApp<'app> {
 impl TestApp<'_> {
     /// Create a new [`TestApp`] instance configured with a temporary directory
     async fn new() -> Self {
+        Self::build(None, &[]).await
+    }
+
+    /// Create a new [`TestApp`] instance whose DDL file, in the temporary directory, contains
+    /// `ddl`
+    async fn with_ddl(ddl: &str) -> Self {
+        Self::build(Some("ddl.sql"), &[("ddl.sql", ddl)]).await
+    }
+
+    /// Create a new [`TestApp`] instance whose DDL is a directory, in the temporary directory,
+    /// of the given files and their contents
+    async fn with_ddl_dir(files: &[(&str, &str)]) -> Self {
+        Self::build(Some(""), files).await
+    }
+
+    /// Write `ddl_files` to a `ddl` directory in the temporary directory and, if `ddl_path` is
+    /// set, configure the DDL path relative to it
+    async fn build(ddl_path: Option<&str>, ddl_files: &[(&str, &str)]) -> Self {
         let config_path = tempdir().unwrap();
-        let config = create_config(config_path.path().to_path_buf(), &[]);
+        let mut config = create_config(config_path.path().to_path_buf(), &[]);
+        let ddl_dir = config_path.path().join("ddl");
+        std::fs::create_dir_all(&ddl_dir).unwrap();
+        for (name, contents) in ddl_files {
+            std::fs::write(ddl_dir.join(name), contents).unwrap();
+        }
+        if let Some(ddl_path) = ddl_path {
+            config.tui.execution.ddl_path = Some(ddl_dir.join(ddl_path));
+        }
         let state = AppState::new(config);
         let session_state =
             DftSessionStateBuilder::try_new(Some(state.config.tui.execution.clone()))
@@ -97,6 +123,16 @@ impl TestApp<'_> {
         }
     }
 
+    /// Handle the DDL results the app sent to itself, ignoring its other events
+    pub fn handle_ddl_events(&mut self) -> color_eyre::Result<()> {
+        while let Ok(event) = self.app.event_rx().try_recv() {
+            if matches!(event, AppEvent::DDLError(_) | AppEvent::DDLSuccess) {
+                self.handle_app_event(event)?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn execute_sql(&self, sql: &str) -> Result<Vec<RecordBatch>> {
         let ctx = self.app.execution().session_ctx().clone();
         ctx.sql(sql).await.unwrap().collect().await